@@ -0,0 +1,98 @@
+//! WAPP ABI
+//!
+//! Status codes returned by the host's imports, shared by the host, which
+//! returns them, and the SDK, which turns them into [`Error`]s. The imports of
+//! the `wapps3` module return [`OK`] or one of the negative codes below;
+//! imports returning a count or an id return it instead of [`OK`]. Older
+//! revisions of the module report every failure as -1, which is
+//! [`OUT_OF_BOUNDS`].
+
+#![no_std]
+
+use core::fmt;
+
+/// The call succeeded
+pub const OK: i32 = 0;
+/// A pointer, length or id lies outside the guest's memory, or the call
+/// would exceed one of the host's limits
+pub const OUT_OF_BOUNDS: i32 = -1;
+/// A width, height or count is out of the range the call accepts
+pub const INVALID_DIMENSIONS: i32 = -2;
+/// The user or the package's manifest does not allow the call
+pub const PERMISSION_DENIED: i32 = -3;
+/// The host does not support the request, such as an unknown pixel format
+/// or text that is not UTF-8
+pub const UNSUPPORTED: i32 = -4;
+
+/// Why the host rejected a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Error {
+    OutOfBounds,
+    InvalidDimensions,
+    PermissionDenied,
+    Unsupported,
+}
+
+impl Error {
+    /// The status code reporting this error
+    pub const fn code(self) -> i32 {
+        match self {
+            Error::OutOfBounds => OUT_OF_BOUNDS,
+            Error::InvalidDimensions => INVALID_DIMENSIONS,
+            Error::PermissionDenied => PERMISSION_DENIED,
+            Error::Unsupported => UNSUPPORTED,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::OutOfBounds => "out of bounds",
+            Error::InvalidDimensions => "invalid dimensions",
+            Error::PermissionDenied => "permission denied",
+            Error::Unsupported => "unsupported",
+        })
+    }
+}
+
+/// The status code reporting `result`
+pub const fn status(result: Result<(), Error>) -> i32 {
+    match result {
+        Ok(()) => OK,
+        Err(error) => error.code(),
+    }
+}
+
+/// The value of a non-negative `status`, or its error
+///
+/// Codes added by newer hosts are reported as [`Error::Unsupported`].
+pub const fn check(status: i32) -> Result<i32, Error> {
+    match status {
+        0.. => Ok(status),
+        OUT_OF_BOUNDS => Err(Error::OutOfBounds),
+        INVALID_DIMENSIONS => Err(Error::InvalidDimensions),
+        PERMISSION_DENIED => Err(Error::PermissionDenied),
+        _ => Err(Error::Unsupported),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statuses_round_trip() {
+        for error in [
+            Error::OutOfBounds,
+            Error::InvalidDimensions,
+            Error::PermissionDenied,
+            Error::Unsupported,
+        ] {
+            assert_eq!(check(status(Err(error))), Err(error));
+        }
+        assert_eq!(check(status(Ok(()))), Ok(OK));
+        assert_eq!(check(7), Ok(7));
+        assert_eq!(check(-100), Err(Error::Unsupported));
+    }
+}
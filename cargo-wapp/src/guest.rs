@@ -0,0 +1,206 @@
+//! Guest Crates
+//!
+//! Finds the crate to package in `cargo metadata` output and builds the
+//! package manifest from its `Cargo.toml`. The `[package.metadata.wapp]`
+//! table holds manifest fields as `pack` reads them, such as `capabilities`
+//! or `config`, plus `icon` and `assets` paths relative to the crate; `name`,
+//! `description`, `version`, `author`, `license` and `homepage` default to
+//! the package's own.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// A crate to build into a package
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuestCrate {
+    /// Package name, naming the outputs
+    pub name: String,
+    /// The crate's `Cargo.toml`
+    pub manifest_path: PathBuf,
+    /// File name of the module cargo builds
+    pub module_name: String,
+    /// Directory cargo builds into
+    pub target_dir: PathBuf,
+    /// Package manifest
+    pub manifest: Map<String, Value>,
+    /// PNG icon to bundle, if any
+    pub icon: Option<PathBuf>,
+    /// Directory of assets to bundle, if any
+    pub assets: Option<PathBuf>,
+}
+
+/// The crate named `package` in `metadata`, the output of `cargo metadata
+/// --no-deps`, or else the workspace's only crate or the one containing `cwd`
+pub fn select(metadata: &Value, package: Option<&str>, cwd: &Path) -> Result<GuestCrate> {
+    let packages = metadata["packages"]
+        .as_array()
+        .context("cargo metadata listed no packages")?;
+    let manifest_dir = |package: &Value| {
+        package["manifest_path"]
+            .as_str()
+            .and_then(|path| Path::new(path).parent())
+            .map(Path::to_path_buf)
+    };
+    let selected = match package {
+        Some(name) => packages
+            .iter()
+            .find(|package| package["name"] == name)
+            .with_context(|| format!("No package named {:?} in the workspace", name))?,
+        None if packages.len() == 1 => &packages[0],
+        // The innermost crate containing the current directory
+        None => packages
+            .iter()
+            .filter(|package| manifest_dir(package).is_some_and(|dir| cwd.starts_with(dir)))
+            .max_by_key(|package| manifest_dir(package).map_or(0, |dir| dir.as_os_str().len()))
+            .context("The workspace has several packages; choose one with --package")?,
+    };
+
+    let name = string(selected, "name")?;
+    let dir = manifest_dir(selected).context("cargo metadata gave no manifest path")?;
+    let mut manifest = match &selected["metadata"]["wapp"] {
+        Value::Null => Map::new(),
+        Value::Object(table) => table.clone(),
+        _ => bail!("[package.metadata.wapp] of {} is not a table", name),
+    };
+    let path = |value: Option<Value>, key: &str| match value {
+        None => Ok(None),
+        Some(Value::String(path)) => Ok(Some(dir.join(path))),
+        Some(_) => bail!("[package.metadata.wapp] {} is not a path", key),
+    };
+    let icon = path(manifest.remove("icon"), "icon")?;
+    let assets = path(manifest.remove("assets"), "assets")?;
+
+    // Authors are written `Name <email>`; packages show the name
+    let author = selected["authors"][0]
+        .as_str()
+        .map(|author| author.split(" <").next().unwrap_or(author).to_string());
+    let defaults = [
+        ("name", Some(name.clone())),
+        (
+            "description",
+            selected["description"].as_str().map(String::from),
+        ),
+        ("version", selected["version"].as_str().map(String::from)),
+        ("author", author),
+        ("license", selected["license"].as_str().map(String::from)),
+        ("homepage", selected["homepage"].as_str().map(String::from)),
+    ];
+    for (key, value) in defaults {
+        if let Some(value) = value {
+            manifest.entry(key).or_insert(Value::String(value));
+        }
+    }
+
+    Ok(GuestCrate {
+        module_name: module_name(selected, &name)?,
+        manifest_path: PathBuf::from(string(selected, "manifest_path")?),
+        target_dir: PathBuf::from(
+            metadata["target_directory"]
+                .as_str()
+                .context("cargo metadata gave no target directory")?,
+        ),
+        name,
+        manifest,
+        icon,
+        assets,
+    })
+}
+
+/// File name of the module built from `package`'s `cdylib`, or else its
+/// binary
+fn module_name(package: &Value, name: &str) -> Result<String> {
+    let targets = package["targets"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let of_kind = |kind: &str| {
+        targets.iter().find(|target| {
+            target["kind"]
+                .as_array()
+                .is_some_and(|kinds| kinds.iter().any(|k| k == kind))
+        })
+    };
+    if let Some(lib) = of_kind("cdylib") {
+        // Cargo names library files after the crate, with underscores
+        return Ok(format!("{}.wasm", string(lib, "name")?.replace('-', "_")));
+    }
+    match of_kind("bin") {
+        Some(bin) => Ok(format!("{}.wasm", string(bin, "name")?)),
+        None => bail!(
+            "{} has no cdylib or bin target to build a module from",
+            name
+        ),
+    }
+}
+
+/// The string field `key` of `value`
+fn string(value: &Value, key: &str) -> Result<String> {
+    value[key]
+        .as_str()
+        .map(String::from)
+        .with_context(|| format!("cargo metadata gave no {}", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_manifest_comes_from_cargo_metadata() {
+        let metadata = json!({
+            "packages": [
+                {
+                    "name": "game-of-life",
+                    "version": "0.2.0",
+                    "description": "Conway's Game of Life",
+                    "authors": ["Ada <ada@example.com>"],
+                    "license": "MIT",
+                    "homepage": null,
+                    "manifest_path": "/work/games/life/Cargo.toml",
+                    "targets": [{ "name": "game-of-life", "kind": ["cdylib"] }],
+                    "metadata": {
+                        "wapp": {
+                            "name": "Game of Life",
+                            "icon": "icon.png",
+                            "capabilities": ["storage"]
+                        }
+                    }
+                },
+                {
+                    "name": "tools",
+                    "version": "0.1.0",
+                    "manifest_path": "/work/tools/Cargo.toml",
+                    "targets": [{ "name": "tools", "kind": ["bin"] }],
+                    "metadata": null
+                }
+            ],
+            "target_directory": "/work/target"
+        });
+
+        let guest = select(&metadata, None, Path::new("/work/games/life/src")).unwrap();
+        assert_eq!(guest.name, "game-of-life");
+        assert_eq!(guest.module_name, "game_of_life.wasm");
+        assert_eq!(guest.target_dir, PathBuf::from("/work/target"));
+        assert_eq!(guest.icon, Some(PathBuf::from("/work/games/life/icon.png")));
+        assert_eq!(guest.assets, None);
+        assert_eq!(
+            Value::Object(guest.manifest),
+            json!({
+                "name": "Game of Life",
+                "description": "Conway's Game of Life",
+                "version": "0.2.0",
+                "author": "Ada",
+                "license": "MIT",
+                "capabilities": ["storage"]
+            })
+        );
+
+        let tools = select(&metadata, Some("tools"), Path::new("/work")).unwrap();
+        assert_eq!(tools.module_name, "tools.wasm");
+        assert_eq!(tools.manifest["name"], "tools");
+        assert!(select(&metadata, None, Path::new("/work")).is_err());
+        assert!(select(&metadata, Some("missing"), Path::new("/work")).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! cargo-wapp - Building Guest Crates into WAPP Packages
+//!
+//! `cargo wapp build` turns a guest crate into a ready-to-run package in one
+//! step: it builds the crate for `wasm32-wasip1`, optimizes the module with
+//! `wasm-opt` when it is installed, strips the custom sections the host never
+//! reads, writes the package manifest from `Cargo.toml` and packs it all with
+//! the host's `pack` command. `cargo wapp run` then opens the package in the
+//! host.
+
+mod guest;
+mod strip;
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use serde_json::Value;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    /// Build and run WAPP guest crates
+    #[command(subcommand)]
+    Wapp(WappCommand),
+}
+
+#[derive(Subcommand)]
+enum WappCommand {
+    /// Build the crate into a .wapp package
+    Build(BuildArgs),
+    /// Build the crate, then run its package in the host
+    Run {
+        #[command(flatten)]
+        build: BuildArgs,
+        /// Arguments for the host, after `--`
+        #[arg(last = true)]
+        host_args: Vec<String>,
+    },
+}
+
+#[derive(Args)]
+struct BuildArgs {
+    /// Path to the guest crate's Cargo.toml
+    #[arg(long, value_name = "PATH")]
+    manifest_path: Option<PathBuf>,
+    /// Package to build, in workspaces of several crates
+    #[arg(short, long, value_name = "NAME")]
+    package: Option<String>,
+    /// Build the dev profile, keeping the module as built
+    #[arg(long)]
+    debug: bool,
+    /// Target to build for
+    #[arg(long, default_value = "wasm32-wasip1")]
+    target: String,
+    /// Skip `wasm-opt`, even when installed
+    #[arg(long)]
+    no_opt: bool,
+    /// Module compression: none, zstd or deflate
+    #[arg(long, default_value = "zstd")]
+    codec: String,
+    /// Output package path [default: wapp/NAME.wapp in the target directory]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Host binary packing and running packages
+    #[arg(long, env = "WAPPS_HOST", default_value = "wapps-host")]
+    host: PathBuf,
+}
+
+fn main() -> Result<()> {
+    let Cargo::Wapp(command) = Cargo::parse();
+    match command {
+        WappCommand::Build(args) => {
+            build(&args)?;
+        }
+        WappCommand::Run {
+            build: args,
+            host_args,
+        } => {
+            let package = build(&args)?;
+            status("Running", &package.display().to_string());
+            let exit = Command::new(&args.host)
+                .arg(&package)
+                .args(host_args)
+                .status()
+                .with_context(|| host_error(&args.host))?;
+            process::exit(exit.code().unwrap_or(1));
+        }
+    }
+    Ok(())
+}
+
+/// Build the crate into a package, returning its path
+fn build(args: &BuildArgs) -> Result<PathBuf> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let mut metadata = Command::new(&cargo);
+    metadata.args(["metadata", "--no-deps", "--format-version", "1"]);
+    if let Some(path) = &args.manifest_path {
+        metadata.arg("--manifest-path").arg(path);
+    }
+    let output = metadata.output().context("Failed to run cargo metadata")?;
+    if !output.status.success() {
+        bail!(
+            "cargo metadata failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let metadata: Value =
+        serde_json::from_slice(&output.stdout).context("Invalid cargo metadata output")?;
+    let guest = guest::select(&metadata, args.package.as_deref(), &env::current_dir()?)?;
+
+    let mut build = Command::new(&cargo);
+    build
+        .arg("build")
+        .arg("--manifest-path")
+        .arg(&guest.manifest_path)
+        .args(["--target", &args.target]);
+    if !args.debug {
+        build.arg("--release");
+    }
+    run(&mut build, "cargo build")?;
+
+    let profile = if args.debug { "debug" } else { "release" };
+    let built = guest
+        .target_dir
+        .join(&args.target)
+        .join(profile)
+        .join(&guest.module_name);
+    let out_dir = guest.target_dir.join("wapp");
+    fs::create_dir_all(&out_dir)
+        .with_context(|| format!("Failed to create {}", out_dir.display()))?;
+
+    let module = if args.debug {
+        built
+    } else {
+        let module = out_dir.join(format!("{}.wasm", guest.name));
+        if args.no_opt || !optimize(&built, &module)? {
+            fs::copy(&built, &module)
+                .with_context(|| format!("Failed to copy {}", built.display()))?;
+        }
+        let bytes = fs::read(&module)?;
+        let stripped = strip::strip(&bytes)
+            .with_context(|| format!("Failed to strip {}", module.display()))?;
+        fs::write(&module, &stripped)?;
+        status(
+            "Stripped",
+            &format!(
+                "{} ({} to {} bytes)",
+                guest.name,
+                bytes.len(),
+                stripped.len()
+            ),
+        );
+        module
+    };
+
+    let manifest = out_dir.join(format!("{}.json", guest.name));
+    fs::write(
+        &manifest,
+        serde_json::to_vec_pretty(&Value::Object(guest.manifest))?,
+    )?;
+    let package = args
+        .output
+        .clone()
+        .unwrap_or_else(|| out_dir.join(format!("{}.wapp", guest.name)));
+    let mut pack = Command::new(&args.host);
+    pack.arg("pack")
+        .arg(&module)
+        .arg("--manifest")
+        .arg(&manifest)
+        .arg("--output")
+        .arg(&package)
+        .args(["--codec", &args.codec]);
+    if let Some(icon) = &guest.icon {
+        pack.arg("--icon").arg(icon);
+    }
+    if let Some(assets) = &guest.assets {
+        pack.arg("--assets").arg(assets);
+    }
+    match pack.status() {
+        Ok(exit) if exit.success() => {}
+        Ok(_) => bail!("{} pack failed", args.host.display()),
+        Err(e) => return Err(e).with_context(|| host_error(&args.host)),
+    }
+    status("Packed", &package.display().to_string());
+    Ok(package)
+}
+
+/// Optimize `input` for size into `output` with `wasm-opt`, returning
+/// whether it is installed
+fn optimize(input: &Path, output: &Path) -> Result<bool> {
+    let exit = Command::new("wasm-opt")
+        .arg("-Oz")
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .status();
+    match exit {
+        Ok(exit) if exit.success() => {
+            status("Optimized", &output.display().to_string());
+            Ok(true)
+        }
+        Ok(_) => bail!("wasm-opt failed on {}", input.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            status(
+                "Skipping",
+                "wasm-opt, which is not installed (it comes with binaryen)",
+            );
+            Ok(false)
+        }
+        Err(e) => Err(e).context("Failed to run wasm-opt"),
+    }
+}
+
+/// Run `command`, failing unless it succeeds
+fn run(command: &mut Command, name: &str) -> Result<()> {
+    let exit = command
+        .status()
+        .with_context(|| format!("Failed to run {}", name))?;
+    if !exit.success() {
+        bail!("{} failed", name);
+    }
+    Ok(())
+}
+
+fn host_error(host: &Path) -> String {
+    format!(
+        "Failed to run {}; install the host or point --host at it",
+        host.display()
+    )
+}
+
+/// Print a status line the way cargo does
+fn status(verb: &str, message: &str) {
+    eprintln!("{:>12} {}", verb, message);
+}
@@ -0,0 +1,93 @@
+//! Module Stripping
+//!
+//! Release builds still carry custom sections the host never reads: DWARF
+//! debug info, the `producers` section naming the toolchain and the
+//! `target_features` section. Dropping them shrinks packages, often by more
+//! than half. The `name` section is kept, so that crash reports can still
+//! name the guest functions in their backtraces.
+
+use anyhow::{bail, Context, Result};
+
+/// Magic number and version starting every WebAssembly module
+const HEADER: [u8; 8] = *b"\0asm\x01\0\0\0";
+/// Id of custom sections
+const CUSTOM: u8 = 0;
+/// Custom section kept in stripped modules
+const KEPT: &[u8] = b"name";
+
+/// `module` without its custom sections besides `name`
+pub fn strip(module: &[u8]) -> Result<Vec<u8>> {
+    if !module.starts_with(&HEADER) {
+        bail!("Not a WebAssembly module");
+    }
+    let mut stripped = HEADER.to_vec();
+    let mut offset = HEADER.len();
+    while offset < module.len() {
+        let start = offset;
+        let id = module[offset];
+        let (size, read) =
+            read_u32(&module[offset + 1..]).context("Truncated WebAssembly section")?;
+        let payload = offset + 1 + read;
+        let end = payload
+            .checked_add(size as usize)
+            .filter(|&end| end <= module.len())
+            .context("Truncated WebAssembly section")?;
+        offset = end;
+        if id == CUSTOM {
+            let (length, read) =
+                read_u32(&module[payload..end]).context("Truncated custom section name")?;
+            let name = module.get(payload + read..payload + read + length as usize);
+            if name != Some(KEPT) {
+                continue;
+            }
+        }
+        stripped.extend_from_slice(&module[start..end]);
+    }
+    Ok(stripped)
+}
+
+/// Read an unsigned LEB128 number, returning it and the bytes it took
+fn read_u32(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (index, &byte) in bytes.iter().enumerate().take(5) {
+        value |= ((byte & 0x7f) as u32) << (index * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![name.len() as u8];
+        payload.extend_from_slice(name.as_bytes());
+        payload.extend_from_slice(data);
+        let mut section = vec![CUSTOM, payload.len() as u8];
+        section.extend(payload);
+        section
+    }
+
+    #[test]
+    fn test_custom_sections_besides_names_are_dropped() {
+        // A type section declaring `() -> ()`
+        let types = [1, 4, 1, 0x60, 0, 0];
+        let mut module = HEADER.to_vec();
+        module.extend(custom("producers", b"rustc"));
+        module.extend(types);
+        module.extend(custom(".debug_info", &[0xaa; 100]));
+        module.extend(custom("name", &[0, 1]));
+
+        let mut expected = HEADER.to_vec();
+        expected.extend(types);
+        expected.extend(custom("name", &[0, 1]));
+        assert_eq!(strip(&module).unwrap(), expected);
+
+        assert_eq!(read_u32(&[0xe5, 0x8e, 0x26]), Some((624_485, 3)));
+        assert!(strip(&module[..module.len() - 1]).is_err());
+        assert!(strip(b"WAPP").is_err());
+    }
+}
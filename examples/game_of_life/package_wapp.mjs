@@ -18,7 +18,10 @@ const DEFAULT_DESC = "A simple WAPP for demonstration purposes.";
 // Main execution
 function main() {
     // 1. Setup paths
-    const outputName = process.argv[2] || 'game_of_life.wapp';
+    // --simd builds the SIMD stepping path, which needs the SIMD proposal
+    const args = process.argv.slice(2);
+    const simd = args.includes('--simd');
+    const outputName = args.find((arg) => arg !== '--simd') || 'game_of_life.wapp';
     const scriptDir = __dirname;
     const projectRoot = path.resolve(scriptDir, '../..');
     const wasmPath = path.join(scriptDir, 'target/wasm32-wasip1/release/game_of_life.wasm');
@@ -27,7 +30,11 @@ function main() {
     // 2. Build WASM
     console.log('Building WASM module...');
     try {
-        execSync('cargo build --target wasm32-wasip1 --release', { cwd: scriptDir, stdio: 'inherit' });
+        const env = { ...process.env };
+        if (simd) {
+            env.RUSTFLAGS = `${env.RUSTFLAGS || ''} -C target-feature=+simd128`.trim();
+        }
+        execSync('cargo build --target wasm32-wasip1 --release', { cwd: scriptDir, stdio: 'inherit', env });
     } catch (e) {
         console.error('Build failed.');
         process.exit(1);
@@ -0,0 +1,2208 @@
+//! App Instances
+//!
+//! Bundles everything needed to run one WAPP: its window, its WASM runtime
+//! (with its own store) and the input events queued for its next update.
+//!
+//! A single app's updates run on a dedicated guest thread, and when several
+//! apps run at once, their updates are executed in parallel on a worker pool.
+//! Only the frame handoff (texture upload and present) happens on the main
+//! thread, since SDL rendering is not thread-safe.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, error, info, trace, warn};
+use sdl2::controller::Button;
+use sdl2::pixels::Color;
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::audio::{AudioInput, AudioOutput};
+use crate::capabilities::Capability;
+use crate::color_filter::{ColorFilter, Deficiency};
+use crate::console_overlay::ConsoleOverlay;
+use crate::crash_report::{Crash, CrashReporter};
+use crate::crash_screen::CrashScreen;
+use crate::display::{CursorSettings, ScalingMode};
+use crate::display_adjust::{Adjustment, Control, DisplayAdjuster};
+use crate::documents;
+use crate::events::{GuestEvent, TimedEvent};
+use crate::frame_diff::FrameDiff;
+use crate::frame_hash::{hash_frame, FrameHashLog};
+use crate::frame_limits::FrameLimits;
+use crate::frame_pacing;
+use crate::graphics::{host_time, parse_color, unpack_color, FrameSink, Graphics, GraphicsContext};
+use crate::guest_config;
+use crate::guest_thread::{self, GuestThread};
+use crate::host_interface::{CaptureRequest, GamepadRequest, HostInterface, OverlayUpdate};
+use crate::hot_reload::{self, FileWatcher, WatchOptions};
+use crate::input_map::InputMap;
+use crate::inspector::PixelInspector;
+use crate::latency::{LatencyMarker, LatencyProbe, LatencyReport};
+use crate::loader::{self, Assets};
+use crate::locale;
+use crate::perf::PerformanceMonitor;
+use crate::permissions::{self, Access, Permission};
+use crate::png;
+use crate::post_filter::{PostFilter, PostProcessor};
+use crate::presenter::VsyncMode;
+use crate::profile::{ProfileTrack, Profiler};
+use crate::rating::ParentalGate;
+use crate::recording::Session;
+use crate::runtime::WasmRuntime;
+use crate::save_state::{self, SaveState, SnapshotRequest};
+use crate::scores::ScoreKey;
+use crate::screenshot;
+use crate::signing::Keyring;
+use crate::stats::{SessionStats, SessionSummary};
+use crate::storage::{self, AppStorage};
+use crate::supervisor::RestartPolicy;
+use crate::suspend;
+use crate::theme::Theme;
+use crate::timing_overlay::TimingOverlay;
+use crate::update_rate::UpdateRate;
+use crate::usage::{UsageSnapshot, UsageTracker};
+use crate::video::VideoRecorder;
+use crate::virtual_controls::{self, VirtualControls};
+use crate::wasi_policy::{ClockPolicy, WasiPolicy};
+use crate::wasm_features::DisabledFeatures;
+use crate::worker_pool::WorkerPool;
+
+/// Longest the host blocks waiting for input under `--power-save`, so that
+/// network messages, reloads and menu actions are still picked up
+pub const MAX_IDLE_WAIT: Duration = Duration::from_millis(250);
+
+/// Minimum seconds between screen description queries with `--describe`
+const DESCRIBE_INTERVAL: f64 = 1.0;
+
+/// Host settings shared by every app instance
+#[derive(Clone, Default)]
+pub struct AppOptions {
+    /// When presents wait for the display's refresh (waiting only suits a
+    /// single window)
+    pub vsync: VsyncMode,
+    /// Present each frame as soon as it is uploaded, with `--low-latency`
+    pub low_latency: bool,
+    /// Show resource usage in the window title
+    pub show_usage: bool,
+    /// Allow guests to launch other packages via `wapps::launch`
+    pub allow_launch: bool,
+    /// Preopen a private directory into WASI for packages declaring the
+    /// `filesystem` capability
+    pub allow_storage: bool,
+    /// Start with the frame diff debug view enabled
+    pub frame_diff: bool,
+    /// Limit on the content rating of packages, if any
+    pub parental_gate: Option<ParentalGate>,
+    /// Keys trusted to sign packages
+    pub keyring: Keyring,
+    /// Locale for package strings (`None` = from the environment)
+    pub locale: Option<String>,
+    /// Theme the guest reads via `wapps::get_prefers_dark`
+    pub theme: Theme,
+    /// Print the guest's screen description to stdout whenever it changes
+    pub describe: bool,
+    /// Start with a color vision deficiency simulation enabled
+    pub color_filter: Option<Deficiency>,
+    /// Brightness, contrast and gamma of presented frames
+    pub display_adjustment: Adjustment,
+    /// Post-processing filter upscaling presented frames, if any
+    pub filter: Option<PostFilter>,
+    /// Color behind the frame, overriding the package's
+    pub clear_color: Option<Color>,
+    /// How frames are initially scaled into their window
+    pub scaling: ScalingMode,
+    /// Start in borderless fullscreen
+    pub fullscreen: bool,
+    /// Stay fullscreen, refusing guest requests to leave it
+    pub kiosk: bool,
+    /// Session whose clock and random values the guest records or replays
+    pub session: Option<Session>,
+    /// Where to write a hash of every guest frame, if anywhere
+    pub frame_hashes: Option<FrameHashLog>,
+    /// Link stubs for imports this host does not provide instead of failing
+    pub allow_unknown_imports: bool,
+    /// Clock precision overriding what packages ask for
+    pub clock: Option<ClockPolicy>,
+    /// Random seed overriding what packages ask for
+    pub random_seed: Option<u64>,
+    /// Most bytes of linear memory each guest may use
+    pub max_memory: Option<usize>,
+    /// How long a call into the guest may run before it is interrupted
+    pub frame_budget: Option<Duration>,
+    /// Largest frames a guest may present, and how many per update
+    pub frame_limits: FrameLimits,
+    /// Marker input to measure event-to-photon latency with, if any
+    pub measure_latency: Option<LatencyMarker>,
+    /// Where to write a report when the guest crashes, if anywhere
+    pub crash_reports: Option<CrashReporter>,
+    /// Reload the module when its file changes, with `--watch`
+    pub watch: Option<WatchOptions>,
+    /// Run without audio or guest display adjustments, denying every
+    /// permission, with `--safe-mode`
+    pub safe_mode: bool,
+    /// Save a screenshot once the guest has presented this many frames
+    pub screenshot_after: Option<u64>,
+    /// Record the app's frames to this file from launch
+    pub record_video: Option<PathBuf>,
+    /// Where frame spans are recorded, with `--profile`
+    pub profiler: Option<Profiler>,
+    /// Run updates on a dedicated guest thread, keeping the window responsive
+    /// while they run; off for sessions, which need an update per frame
+    pub guest_thread: bool,
+    /// Stop updating while the window is unfocused, with `--pause-on-blur`
+    pub pause_on_blur: bool,
+    /// Only update when the guest has events, a due timer or a redraw
+    /// request, with `--power-save`
+    pub power_save: bool,
+    /// Update with the constant dt of `--fixed-dt`, ignoring the frame rates
+    /// guests ask for
+    pub fixed_dt: bool,
+    /// Show the guest's log lines over its frames from launch, with `--console`
+    pub console: bool,
+    /// Quit the host when a guest crashes instead of showing the crash
+    /// screen, with `--on-crash exit`
+    pub exit_on_crash: bool,
+    /// Count guest calls to each import for the session statistics
+    pub count_import_calls: bool,
+    /// Update apps less often while their updates take longer than a frame,
+    /// with `--degrade`
+    pub degrade: bool,
+    /// Run modules using a shared memory, and the threads they start, with
+    /// `--enable-threads`
+    pub enable_threads: bool,
+    /// WebAssembly proposals disabled for every app, with `--disable-feature`
+    pub disabled_features: DisabledFeatures,
+    /// Settings overriding the packages' `config`, with `--guest-arg`
+    pub guest_config: Vec<(String, String)>,
+    /// File the app is suspended to on exit and resumed from on launch, with
+    /// `--session`
+    pub suspend_file: Option<PathBuf>,
+    /// Key and controller button remapping, with `--input-map`
+    pub input_map: Option<InputMap>,
+    /// Draw a D-pad and buttons pressing keys over the frames, with
+    /// `--virtual-controls`
+    pub virtual_controls: bool,
+}
+
+/// A running WAPP with its own window and runtime
+pub struct AppInstance {
+    /// Display name (metadata name or file stem)
+    name: String,
+    /// Package id its data is kept under, see `WappPackage::id`
+    id: String,
+    /// Path of the loaded .wapp file
+    path: PathBuf,
+    /// Host settings this app was started with
+    options: AppOptions,
+    /// WASM module bytes, kept to reinstantiate the guest after a crash
+    wasm_bytes: Vec<u8>,
+    /// The packaged module precompiled by wasmtime, if trusted
+    precompiled: Option<Vec<u8>>,
+    /// WASI arguments passed to the guest (`argv[0]` is the app name)
+    guest_args: Vec<String>,
+    /// Packaged version, readable by the guest
+    version: String,
+    /// Localized package strings readable by the guest
+    strings: HashMap<String, String>,
+    /// Startup settings readable by the guest
+    config: HashMap<String, String>,
+    /// Bundled package assets readable by the guest
+    assets: Arc<Assets>,
+    /// Clock and random policy for the guest's WASI context
+    wasi_policy: WasiPolicy,
+    /// WebAssembly proposals the guest's engine runs without
+    disabled_features: DisabledFeatures,
+    /// Permissions the user granted the package
+    access: Access,
+    /// Guest runtime; temporarily moved out while a worker updates it,
+    /// and absent while a crashed guest waits to be restarted
+    runtime: Option<WasmRuntime>,
+    /// Window the guest renders into
+    graphics: Graphics,
+    /// Device the guest's audio plays on, if audio is available
+    audio: Option<AudioOutput>,
+    /// Microphone the guest captures from, while it does
+    microphone: Option<AudioInput>,
+    /// Events received since the last update
+    pending_events: Vec<TimedEvent>,
+    /// When the oldest input among the pending events was polled
+    pending_input: Option<Instant>,
+    /// When the oldest input delivered to an update whose frame is not on
+    /// screen yet was polled, for the timing overlay's input latency
+    delivered_input: Option<Instant>,
+    /// Key and controller button remapping for this app
+    input_map: Option<InputMap>,
+    /// Number of frames received from the guest, numbering `--frame-hashes` lines
+    frames_received: u64,
+    /// Number of frames presented, passed to `on_present`
+    frames_presented: u64,
+    /// Time of a present not yet reported to the guest, in microseconds
+    unreported_present: Option<u64>,
+    /// Frame rate, guest time and memory tracking
+    usage: UsageTracker,
+    /// Dropped frame tracking behind `on_performance_warning`
+    performance: PerformanceMonitor,
+    /// Update rate behind `on_perf_hint`, with `--degrade`
+    update_rate: Option<UpdateRate>,
+    /// Totals for the whole session, reported by `--stats`
+    stats: SessionStats,
+    /// Input latency measurement, with `--measure-latency`
+    latency: Option<LatencyProbe>,
+    /// Frame diff debug view, when enabled
+    frame_diff: Option<FrameDiff>,
+    /// Color vision deficiency simulation, when enabled
+    color_filter: Option<ColorFilter>,
+    /// Post-processing filter upscaling frames, when enabled
+    post_filter: Option<PostProcessor>,
+    /// Brightness, contrast and gamma applied before presenting
+    display_adjust: DisplayAdjuster,
+    /// Pixel inspector debug view, when enabled
+    inspector: Option<PixelInspector>,
+    /// Frame timing debug view, when enabled
+    timing: Option<TimingOverlay>,
+    /// Guest log lines view, when enabled
+    console: Option<ConsoleOverlay>,
+    /// On-screen D-pad and buttons, when enabled
+    virtual_controls: Option<VirtualControls>,
+    /// Last cursor position inside the window
+    cursor: Option<(i32, i32)>,
+    /// Window title set by the guest, shown instead of the package name
+    title: Option<String>,
+    /// How the guest wants the mouse cursor over its window
+    cursor_settings: CursorSettings,
+    /// Pixels per window unit last reported to the guest
+    scale: f32,
+    /// Last screen description printed, and when the guest was last asked
+    description: Option<String>,
+    last_described: Option<Instant>,
+    /// Whether the app's window has keyboard focus
+    focused: bool,
+    /// Whether the app's window was last reported to the guest as visible
+    visible: bool,
+    /// Minimum seconds between updates while unfocused (`None` = never throttle)
+    background_interval: Option<f64>,
+    /// Time elapsed since the last update that has not yet been passed to the guest
+    deferred_dt: f64,
+    /// Number of times the guest has been restarted after a crash
+    restarts: u32,
+    /// When a crashed guest should be reinstantiated
+    restart_at: Option<Instant>,
+    /// Error shown in the window of a crashed guest that is not restarted
+    /// automatically, until the user restarts it or quits
+    crash_screen: Option<CrashScreen>,
+    /// File reloaded when it changes, with `--watch`
+    watcher: Option<FileWatcher>,
+    /// Video being recorded, with F11 or `--record-video`
+    video: Option<VideoRecorder>,
+    /// Track of the app's frame spans, with `--profile`
+    profile: Option<ProfileTrack>,
+    /// Thread running the guest's updates, holding the runtime while one runs
+    guest_thread: Option<GuestThread<(WasmRuntime, Result<()>)>>,
+}
+
+impl AppInstance {
+    /// Load a WAPP file and open a window for it
+    ///
+    /// `args` are passed to the guest as WASI arguments after the app name.
+    pub fn load(
+        context: &GraphicsContext,
+        wapp_path: &Path,
+        args: Vec<String>,
+        options: &AppOptions,
+    ) -> Result<Self> {
+        // Load and validate the WAPP file
+        let package = loader::load_package(wapp_path)
+            .with_context(|| format!("Failed to load WAPP file: {:?}", wapp_path))?;
+        options
+            .keyring
+            .check(&package.metadata.name, package.signer.as_ref())?;
+        let icon = package.icon().map(png::decode_rgba);
+        let mut wasm_bytes = package.module().data.clone();
+        // Precompiled modules are native code, only run when a trusted key
+        // vouches for them
+        let mut precompiled = package.precompiled().map(<[u8]>::to_vec);
+        if precompiled.is_some() && !options.keyring.trusts(package.signer.as_ref()) {
+            info!("Ignoring the precompiled module of a package not signed by a trusted key");
+            precompiled = None;
+        }
+        let assets = Arc::new(package.assets());
+        let id = package.id(wapp_path);
+        let metadata = package.metadata;
+
+        // Develop against a build output instead of the packaged module
+        let watched_module = options.watch.as_ref().and_then(|w| w.module.as_deref());
+        if let Some(module) = watched_module {
+            wasm_bytes = hot_reload::read_module(module, &options.keyring)?;
+            precompiled = None;
+        }
+
+        let locale = options.locale.clone().or_else(locale::user_locale);
+        let localized = locale::localize(&metadata, locale.as_deref());
+
+        info!(
+            "WAPP loaded successfully ({} bytes of WASM). Name: {:?}",
+            wasm_bytes.len(),
+            localized.name
+        );
+
+        if !localized.description.is_empty() {
+            info!("Description: {}", localized.description);
+        }
+
+        // Determine window title
+        let name = if !localized.name.is_empty() {
+            localized.name
+        } else {
+            wapp_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("WAPPS")
+                .to_string()
+        };
+
+        if let Some(gate) = &options.parental_gate {
+            gate.check(&name, metadata.age_rating)?;
+        }
+
+        let mut requested = permissions::requested(&wasm_bytes);
+        if options.allow_launch {
+            requested.remove(&Permission::Launch);
+        }
+        // Undeclared storage imports are denied anyway, so there is nothing to ask
+        if let Some(declared) = &metadata.capabilities {
+            if !declared.contains(&Capability::Storage) {
+                requested.remove(&Permission::Storage);
+            }
+        }
+        let package_name = permissions::package_name(&metadata.name, wapp_path);
+        let mut access = if options.safe_mode {
+            permissions::deny_all(&id, &package_name, &requested)
+        } else {
+            permissions::resolve(&id, &package_name, &requested, !options.kiosk)
+        };
+        access.capabilities = metadata.capabilities;
+        access.allowed_hosts = metadata.allowed_hosts;
+
+        // Initialize graphics
+        let mut graphics = context
+            .create_window(&name, 800, 600, options.vsync)
+            .context("Failed to initialize graphics")?;
+        let package_color = metadata.clear_color.as_deref().map(parse_color);
+        let clear_color = match options.clear_color {
+            Some(color) => Some(color),
+            None => package_color
+                .transpose()
+                .map_err(|e| anyhow!("Invalid clear_color in manifest: {}", e))?,
+        };
+        if let Some(color) = clear_color {
+            graphics.set_clear_color(color);
+        }
+        match icon {
+            Some(Ok((width, height, mut pixels))) => graphics.set_icon(width, height, &mut pixels),
+            Some(Err(e)) => warn!("Ignoring invalid package icon: {:#}", e),
+            None => {}
+        }
+        graphics.set_scaling_mode(options.scaling);
+        if options.fullscreen || options.kiosk {
+            graphics.set_fullscreen(true)?;
+        }
+
+        // Safe mode leaves the audio subsystem uninitialized
+        let audio = match (!options.safe_mode).then(|| context.audio()) {
+            None => None,
+            Some(Ok(subsystem)) => Some(AudioOutput::new(subsystem)),
+            Some(Err(e)) => {
+                warn!("Audio unavailable for {:?}: {:#}", name, e);
+                None
+            }
+        };
+
+        let wasi_policy = WasiPolicy::resolve(&metadata.wasi, options.clock, options.random_seed);
+        if wasi_policy != WasiPolicy::default() {
+            info!("WASI policy: {:?}", wasi_policy);
+        }
+        let disabled_features = options.disabled_features.with(&metadata.disabled_features);
+        let config = guest_config::resolve(&metadata.config, &options.guest_config);
+
+        // Initialize WASM runtime with host interface
+        let guest_args: Vec<String> = std::iter::once(name.clone()).chain(args).collect();
+        let mut runtime = instantiate(
+            &wasm_bytes,
+            precompiled.as_deref(),
+            &guest_args,
+            &name,
+            &id,
+            &metadata.version,
+            &localized.strings,
+            &config,
+            &assets,
+            &wasi_policy,
+            &disabled_features,
+            &access,
+            options,
+        )
+        .context("Failed to initialize WASM runtime")?;
+        let resumed = options
+            .suspend_file
+            .as_deref()
+            .and_then(|path| suspend::load(path, xxh3_64(&wasm_bytes)))
+            .filter(|state| suspend::confirm(&name, state, !options.kiosk));
+        init_guest(&mut runtime, &graphics, options)?;
+        if let Some(state) = &resumed {
+            runtime
+                .restore_state(state)
+                .with_context(|| format!("Failed to resume {:?}", name))?;
+            info!("Resumed {:?}", name);
+        }
+        let profile = options
+            .profiler
+            .as_ref()
+            .map(|profiler| profiler.track(&name));
+
+        let input_map = InputMap::for_app(options.input_map.as_ref(), &id);
+        let mut app = Self {
+            name,
+            id,
+            path: wapp_path.to_path_buf(),
+            options: options.clone(),
+            wasm_bytes,
+            precompiled,
+            guest_args,
+            version: metadata.version,
+            strings: localized.strings,
+            config,
+            assets,
+            wasi_policy,
+            disabled_features,
+            access,
+            runtime: Some(runtime),
+            graphics,
+            audio,
+            microphone: None,
+            pending_events: Vec::new(),
+            input_map,
+            frames_received: 0,
+            frames_presented: 0,
+            unreported_present: None,
+            usage: UsageTracker::new(),
+            performance: PerformanceMonitor::new(),
+            update_rate: options.degrade.then(UpdateRate::new),
+            stats: SessionStats::new(),
+            pending_input: None,
+            delivered_input: None,
+            latency: options
+                .measure_latency
+                .map(|marker| LatencyProbe::new(marker, Instant::now())),
+            frame_diff: options.frame_diff.then(FrameDiff::new),
+            color_filter: options.color_filter.map(ColorFilter::new),
+            post_filter: options.filter.map(PostProcessor::new),
+            display_adjust: DisplayAdjuster::new(options.display_adjustment),
+            inspector: None,
+            timing: None,
+            console: options.console.then(ConsoleOverlay::default),
+            virtual_controls: options.virtual_controls.then(VirtualControls::default),
+            cursor: None,
+            cursor_settings: CursorSettings::default(),
+            scale: 1.0,
+            title: None,
+            description: None,
+            last_described: None,
+            focused: false,
+            visible: true,
+            background_interval: None,
+            deferred_dt: 0.0,
+            restarts: 0,
+            restart_at: None,
+            crash_screen: None,
+            watcher: options
+                .watch
+                .as_ref()
+                .map(|_| FileWatcher::new(watched_module.unwrap_or(wapp_path), Instant::now())),
+            video: options.record_video.clone().map(VideoRecorder::new),
+            profile,
+            guest_thread: None,
+        };
+        // The resumed guest's memory describes the viewport it was suspended in
+        if resumed.is_some() {
+            let viewport = app.graphics.viewport();
+            app.pending_events.push(TimedEvent {
+                event: GuestEvent::Resize {
+                    width: viewport.width() as i32,
+                    height: viewport.height() as i32,
+                },
+                time: host_time().as_secs_f64(),
+            });
+        }
+        Ok(app)
+    }
+
+    /// Display name of the app
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Latest resource usage sample
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    pub fn usage(&self) -> Option<UsageSnapshot> {
+        self.usage.latest()
+    }
+
+    /// Totals for the session so far
+    pub fn session_summary(&self) -> SessionSummary {
+        let import_calls = self
+            .runtime
+            .as_ref()
+            .and_then(WasmRuntime::import_calls)
+            .unwrap_or_default();
+        self.stats.summary(&self.name, import_calls)
+    }
+
+    /// Input latency measured so far, with `--measure-latency`
+    pub fn latency_report(&self) -> Option<LatencyReport> {
+        Some(self.latency.as_ref()?.report(&self.name))
+    }
+
+    /// Path of the loaded .wapp file
+    #[cfg(feature = "menu")]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// SDL window ID of the app's window
+    pub fn window_id(&self) -> u32 {
+        self.graphics.window_id()
+    }
+
+    /// Time between refreshes of the display showing the app's window, if
+    /// known
+    pub fn refresh_period(&self) -> Option<Duration> {
+        match self.graphics.refresh_rate() {
+            0 => None,
+            rate => Some(Duration::from_secs_f64(1.0 / rate as f64)),
+        }
+    }
+
+    /// Bring the app's window to the front
+    pub fn raise_window(&mut self) {
+        self.graphics.raise();
+    }
+
+    /// Record a focus change of the app's window
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        if focused {
+            apply_cursor(&mut self.graphics, self.cursor_settings, self.options.kiosk);
+        }
+    }
+
+    /// Ask the guest, through `on_close_request`, whether its window may close
+    ///
+    /// Guests can veto closing to save their state first. Crashed guests,
+    /// guests still busy updating and guests failing to answer never keep
+    /// their window open.
+    pub fn request_close(&mut self) -> bool {
+        if let Err(e) = self.wait_for_guest(guest_thread::FRAME_WAIT) {
+            warn!("{:?} crashed while closing: {:#}", self.name, e);
+            return true;
+        }
+        let Some(runtime) = self.runtime.as_mut() else {
+            return true;
+        };
+        match runtime.call_on_close_request() {
+            Ok(true) => true,
+            Ok(false) => {
+                info!("{:?} kept its window open", self.name);
+                false
+            }
+            Err(e) => {
+                warn!("{:?} failed to answer closing: {:#}", self.name, e);
+                true
+            }
+        }
+    }
+
+    /// Let the guest clean up through `shutdown` before the app goes away
+    ///
+    /// The guest is not called again afterwards. Crashed guests are skipped.
+    pub fn shutdown(&mut self) {
+        if let Err(e) = self.leave_guest_thread() {
+            warn!("{:?} crashed while closing: {:#}", self.name, e);
+            self.discard_suspended();
+            return;
+        }
+        let Some(mut runtime) = self.runtime.take() else {
+            self.discard_suspended();
+            return;
+        };
+        if let Some(path) = &self.options.suspend_file {
+            let state = runtime.capture_state(xxh3_64(&self.wasm_bytes));
+            if let Err(e) = state.save(path) {
+                warn!("Failed to suspend {:?}: {:#}", self.name, e);
+            }
+        }
+        if let Err(e) = runtime.call_shutdown() {
+            warn!("{:?} failed to shut down: {:#}", self.name, e);
+        }
+    }
+
+    /// Limit the update rate while the window is unfocused, like browsers do
+    /// for background tabs. `None` or a non-positive rate disables throttling.
+    pub fn set_background_fps(&mut self, fps: Option<f64>) {
+        self.background_interval = fps.filter(|fps| *fps > 0.0).map(|fps| 1.0 / fps);
+    }
+
+    /// Accumulate `dt` and return the delta to pass to `update`, or `None`
+    /// if the app is throttled or paused in the background this frame
+    fn take_update_dt(&mut self, dt: f64) -> Option<f64> {
+        // Paused guests still get the update delivering their blur event, and
+        // the time spent paused is never passed to them
+        if self.options.pause_on_blur
+            && !self.focused
+            && !self
+                .pending_events
+                .iter()
+                .any(|event| event.event == GuestEvent::Blur)
+        {
+            self.deferred_dt = 0.0;
+            return None;
+        }
+        self.deferred_dt += dt;
+        if let Some(interval) = self.background_interval {
+            if !self.focused && self.deferred_dt < interval {
+                return None;
+            }
+        }
+        // Guests that asked for their own frame rate wait for their interval,
+        // getting the time since their last update clamped after stalls
+        if let Some(interval) = self
+            .runtime
+            .as_ref()
+            .and_then(WasmRuntime::frame_interval)
+            .filter(|_| !self.options.fixed_dt)
+        {
+            match frame_pacing::requested_dt(self.deferred_dt, interval) {
+                None => return None,
+                // Idle time under --power-save is owed to the guest's timers
+                Some(dt) if !self.options.power_save => self.deferred_dt = dt,
+                Some(_) => {}
+            }
+        }
+        // Idle guests accumulate the time until something wakes them
+        if self.options.power_save
+            && self.pending_events.is_empty()
+            && !self
+                .runtime
+                .as_ref()
+                .is_some_and(|runtime| runtime.wants_frame(self.deferred_dt))
+        {
+            return None;
+        }
+        // Degraded guests skip frames, getting their time with the next update
+        if self.update_rate.as_mut().is_some_and(|rate| !rate.tick()) {
+            return None;
+        }
+        let dt = std::mem::take(&mut self.deferred_dt);
+        if let Some(video) = &mut self.video {
+            video.advance(dt);
+        }
+        self.stats.record_update(dt);
+        Some(dt)
+    }
+
+    /// Take the rumble and LED changes the guest asked for since the last call
+    pub fn take_gamepad_requests(&mut self) -> Vec<GamepadRequest> {
+        match self.runtime.as_mut() {
+            Some(runtime) => runtime.take_gamepad_requests(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Take the packages this app asked to launch, resolved to file paths
+    ///
+    /// Targets are either paths or package names; both are looked up relative
+    /// to the directory of this app's package. Unresolvable targets are logged
+    /// and dropped.
+    pub fn take_launch_requests(&mut self) -> Vec<PathBuf> {
+        let Some(runtime) = self.runtime.as_mut() else {
+            return Vec::new();
+        };
+        let base_dir = self.path.parent().unwrap_or(Path::new("."));
+
+        runtime
+            .take_launch_requests()
+            .into_iter()
+            .filter_map(|target| {
+                let resolved = resolve_launch_target(base_dir, &target);
+                if resolved.is_none() {
+                    warn!("{:?} requested unknown package {:?}", self.name, target);
+                }
+                resolved
+            })
+            .collect()
+    }
+
+    /// Take the messages this app sent on the bus since the last call, as
+    /// (topic, data)
+    pub fn take_bus_messages(&mut self) -> Vec<(String, Vec<u8>)> {
+        match self.runtime.as_mut() {
+            Some(runtime) => runtime.take_bus_messages(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Queue a message another app sent on the bus, if the guest subscribed
+    /// to its `topic`
+    fn receive_bus_message(&mut self, topic: &str, data: &[u8], time: f64) {
+        let subscribed = self
+            .runtime
+            .as_ref()
+            .is_some_and(|runtime| runtime.bus_subscribed(topic));
+        if subscribed {
+            self.pending_events.push(TimedEvent {
+                event: GuestEvent::BusMessage {
+                    topic: topic.to_string(),
+                    data: data.to_vec(),
+                },
+                time,
+            });
+        }
+    }
+
+    /// Queue an event for delivery before the next update
+    ///
+    /// Window sizes are reported as the viewport's, which is letterboxed when
+    /// the guest requires an aspect ratio, and pointer and touch positions in
+    /// frame pixels, whatever the scaling, zoom, orientation or display
+    /// density.
+    pub fn push_event(&mut self, mut event: TimedEvent) {
+        if event.event.is_input() {
+            self.pending_input.get_or_insert_with(Instant::now);
+        }
+        let graphics = &self.graphics;
+        event.event = match event.event {
+            GuestEvent::PointerMove { x, y, xrel, yrel } => {
+                let (x, y) = graphics.window_to_layout(x, y);
+                let (xrel, yrel) = graphics.delta_to_layout(xrel, yrel);
+                GuestEvent::PointerMove { x, y, xrel, yrel }
+            }
+            other => other
+                .map_position(|x, y| graphics.window_to_layout(x, y))
+                .map_touch(|x, y| graphics.touch_to_layout(x, y)),
+        };
+        if let Some(controls) = &mut self.virtual_controls {
+            if let Some(buttons) = controls.handle(&event.event, self.graphics.window_size()) {
+                for (button, pressed) in buttons {
+                    self.press_virtual_button(button, pressed, event.time);
+                }
+                return;
+            }
+        }
+        let graphics = &self.graphics;
+        event.event = match event.event {
+            GuestEvent::Resize { .. } => {
+                let viewport = graphics.viewport();
+                GuestEvent::Resize {
+                    width: viewport.width() as i32,
+                    height: viewport.height() as i32,
+                }
+            }
+            // Minimizing then hiding a window only hides it once
+            GuestEvent::Visibility { visible } if visible == self.visible => return,
+            GuestEvent::Visibility { visible } => {
+                self.visible = visible;
+                GuestEvent::Visibility { visible }
+            }
+            key @ (GuestEvent::KeyDown { .. } | GuestEvent::KeyUp { .. }) => {
+                match &self.input_map {
+                    Some(map) => map.map_event(key),
+                    None => key,
+                }
+            }
+            other => other
+                .map_position(|x, y| graphics.window_to_guest(x, y))
+                .map_touch(|x, y| graphics.touch_to_guest(x, y)),
+        };
+        self.pending_events.push(event);
+    }
+
+    /// Deliver a controller button press or release as the key the app's
+    /// input map assigns to it, if any
+    pub fn press_button(&mut self, button: Button, pressed: bool, time: f64) {
+        let Some(map) = &self.input_map else {
+            return;
+        };
+        if let Some(event) = map.button_event(button, pressed) {
+            self.pending_input.get_or_insert_with(Instant::now);
+            self.pending_events.push(TimedEvent { event, time });
+        }
+    }
+
+    /// Deliver a press or release of a virtual control as the key the app's
+    /// input map assigns to its button, or else its default key
+    fn press_virtual_button(&mut self, button: Button, pressed: bool, time: f64) {
+        let event = self
+            .input_map
+            .as_ref()
+            .and_then(|map| map.button_event(button, pressed))
+            .unwrap_or_else(|| virtual_controls::key_event(button, pressed));
+        self.pending_events.push(TimedEvent { event, time });
+    }
+
+    /// Deliver a file dropped on the window to the guest's `on_file_dropped`,
+    /// if it exports one; the file is read now, and only its name is passed
+    pub fn drop_file(&mut self, path: &Path, time: f64) {
+        // A guest running on its thread is asked when the event arrives
+        let wanted = self
+            .runtime
+            .as_ref()
+            .is_none_or(WasmRuntime::wants_dropped_files);
+        if !wanted {
+            debug!("{}: ignoring dropped file {:?}", self.name, path);
+            return;
+        }
+        match documents::read(path) {
+            Ok(data) => self.pending_events.push(TimedEvent {
+                event: GuestEvent::FileDropped {
+                    name: documents::file_name(path),
+                    data,
+                },
+                time,
+            }),
+            Err(e) => warn!(
+                "{}: failed to read dropped file {:?}: {}",
+                self.name, path, e
+            ),
+        }
+    }
+
+    /// Take the queued events to deliver them to an update
+    fn take_events(&mut self) -> Vec<TimedEvent> {
+        if let Some(polled) = self.pending_input.take() {
+            self.delivered_input.get_or_insert(polled);
+        }
+        std::mem::take(&mut self.pending_events)
+    }
+
+    /// Events queued for delivery before the next update
+    pub fn pending_events(&self) -> &[TimedEvent] {
+        &self.pending_events
+    }
+
+    /// Replace the queued events (used when replaying a recorded session)
+    pub fn set_pending_events(&mut self, events: Vec<TimedEvent>) {
+        self.pending_events = events;
+    }
+
+    /// Handle a guest crash according to the restart policy
+    ///
+    /// Without a policy, or once the restart limit is reached, the window
+    /// shows the error until the user restarts the app or quits; recorded and
+    /// replayed sessions return the error instead, so the host exits.
+    /// Otherwise the runtime is dropped and a restart is scheduled after an
+    /// exponential backoff.
+    pub fn handle_crash(
+        &mut self,
+        error: anyhow::Error,
+        policy: Option<&RestartPolicy>,
+    ) -> Result<()> {
+        let error = error.context(format!("App {:?} crashed", self.name));
+        if let Some(reporter) = &self.options.crash_reports {
+            self.write_crash_report(reporter, &error);
+        }
+        // A crashed guest can no longer release a captured mouse
+        self.cursor_settings = CursorSettings::default();
+        if self.focused {
+            apply_cursor(&mut self.graphics, self.cursor_settings, self.options.kiosk);
+        }
+        let Some(policy) = policy.filter(|policy| policy.allows(self.restarts)) else {
+            // A restart would make the session diverge from its recording
+            if self.options.session.is_some() || self.options.exit_on_crash {
+                return Err(error);
+            }
+            error!("{:#}", error);
+            self.runtime = None;
+            self.microphone = None;
+            self.pending_events.clear();
+            self.unreported_present = None;
+            self.crash_screen = Some(CrashScreen::new(error));
+            return Ok(());
+        };
+
+        let delay = policy.backoff(self.restarts);
+        self.restarts += 1;
+        warn!("{:#}", error);
+        warn!(
+            "Restarting {:?} in {:.1}s (restart {} of {})",
+            self.name,
+            delay.as_secs_f64(),
+            self.restarts,
+            policy.limit_description()
+        );
+
+        self.runtime = None;
+        self.microphone = None;
+        self.pending_events.clear();
+        self.unreported_present = None;
+        self.restart_at = Some(Instant::now() + delay);
+        Ok(())
+    }
+
+    /// Whether the window shows a crash screen
+    pub fn is_crashed(&self) -> bool {
+        self.crash_screen.is_some()
+    }
+
+    /// Dismiss the crash screen and reinstantiate the guest before its next update
+    pub fn restart_after_crash(&mut self) {
+        if self.crash_screen.take().is_some() {
+            info!("Restarting {:?} at the user's request", self.name);
+            self.graphics.set_overlay(Vec::new());
+            self.pending_events.clear();
+            self.restart_at = Some(Instant::now());
+        }
+    }
+
+    /// Take the error shown on the crash screen, to quit with
+    pub fn take_crash(&mut self) -> Option<anyhow::Error> {
+        self.crash_screen.take().map(CrashScreen::into_error)
+    }
+
+    /// Swap in a new build of the module once the watched file changes
+    ///
+    /// Builds that fail to load are logged and the running guest is kept; an
+    /// error is only returned when the new guest fails in `on_reload`.
+    pub fn poll_reload(&mut self) -> Result<()> {
+        // The running guest is swapped out once its update finishes
+        if self.is_updating() {
+            return Ok(());
+        }
+        let Some(watcher) = self.watcher.as_mut() else {
+            return Ok(());
+        };
+        if !watcher.poll(Instant::now()) {
+            return Ok(());
+        }
+        info!(
+            "{} changed, reloading {:?}",
+            watcher.path().display(),
+            self.name
+        );
+        let wasm_bytes = match hot_reload::read_module(watcher.path(), &self.options.keyring) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Keeping the running version: {:#}", e);
+                return Ok(());
+            }
+        };
+        let mut runtime = match instantiate(
+            &wasm_bytes,
+            None,
+            &self.guest_args,
+            &self.name,
+            &self.id,
+            &self.version,
+            &self.strings,
+            &self.config,
+            &self.assets,
+            &self.wasi_policy,
+            &self.disabled_features,
+            &self.access,
+            &self.options,
+        ) {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                warn!("Keeping the running version: {:#}", e);
+                return Ok(());
+            }
+        };
+
+        let keep_memory = self.options.watch.as_ref().is_some_and(|w| w.keep_memory);
+        if let Some(previous) = self.runtime.as_ref().filter(|_| keep_memory) {
+            if let Err(e) = runtime.restore_memory(&previous.memory_data()) {
+                warn!("Starting {:?} from a fresh memory: {:#}", self.name, e);
+            }
+        }
+        if let Err(e) = init_guest(&mut runtime, &self.graphics, &self.options) {
+            warn!("Keeping the running version: {:#}", e);
+            return Ok(());
+        }
+
+        // The new build replaces a crashed one too
+        self.wasm_bytes = wasm_bytes;
+        self.precompiled = None;
+        self.runtime = Some(runtime);
+        // The new instance starts its own capture and HUD if it wants them
+        self.microphone = None;
+        self.graphics.update_hud(None)?;
+        self.restart_at = None;
+        self.restarts = 0;
+        self.deferred_dt = 0.0;
+        // Fresh guests learn the display scale and update rate again
+        self.scale = 1.0;
+        self.update_rate = self.options.degrade.then(UpdateRate::new);
+        self.unreported_present = None;
+        if self.crash_screen.take().is_some() {
+            self.graphics.set_overlay(Vec::new());
+        }
+        let viewport = self.graphics.viewport();
+        self.pending_events.push(TimedEvent {
+            event: GuestEvent::Resize {
+                width: viewport.width() as i32,
+                height: viewport.height() as i32,
+            },
+            time: host_time().as_secs_f64(),
+        });
+
+        let runtime = self
+            .runtime
+            .as_mut()
+            .context("App runtime is unavailable")?;
+        runtime.call_on_reload()
+    }
+
+    /// Remove the suspend file of a crashed guest, so it starts over next time
+    fn discard_suspended(&self) {
+        let Some(path) = &self.options.suspend_file else {
+            return;
+        };
+        if path.exists() {
+            match fs::remove_file(path) {
+                Ok(()) => info!("Discarded the suspended state of {:?}", self.name),
+                Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Save the guest's state to its state file, replacing the previous one
+    pub fn save_state(&mut self) {
+        let Some(path) = state_path(&self.id, &self.options) else {
+            warn!("Save states are unavailable for {:?}", self.name);
+            return;
+        };
+        let Some(runtime) = self.runtime.as_mut() else {
+            warn!("{:?} is not running; nothing to save", self.name);
+            return;
+        };
+        let state = runtime.capture_state(xxh3_64(&self.wasm_bytes));
+        if let Err(e) = state.save(&path) {
+            warn!("Failed to save the state of {:?}: {:#}", self.name, e);
+        }
+    }
+
+    /// Restore the guest's state from its state file
+    pub fn restore_state(&mut self) {
+        let Some(path) = state_path(&self.id, &self.options) else {
+            warn!("Save states are unavailable for {:?}", self.name);
+            return;
+        };
+        let Some(runtime) = self.runtime.as_mut() else {
+            warn!(
+                "{:?} is not running; restart it before restoring",
+                self.name
+            );
+            return;
+        };
+        let state = match SaveState::load(&path) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to restore {:?}: {:#}", self.name, e);
+                return;
+            }
+        };
+        if state.module_hash != xxh3_64(&self.wasm_bytes) {
+            warn!(
+                "Not restoring {:?}: its state was saved by a different build",
+                self.name
+            );
+            return;
+        }
+        match runtime.restore_state(&state) {
+            Ok(()) => info!("Restored {:?} from {}", self.name, path.display()),
+            Err(e) => warn!("Failed to restore {:?}: {:#}", self.name, e),
+        }
+    }
+
+    /// Save the frame the guest last presented as a screenshot
+    pub fn screenshot(&mut self) {
+        let frame = self.runtime.as_mut().and_then(WasmRuntime::capture_frame);
+        match frame {
+            Some((width, height, pixels)) => save_screenshot(&self.name, width, height, &pixels),
+            None => warn!("{:?} has no frame to capture", self.name),
+        }
+    }
+
+    /// Start recording a video of the app, or stop and save the current one
+    pub fn toggle_video(&mut self) {
+        if let Some(video) = self.video.take() {
+            finish_video(&self.name, video);
+            return;
+        }
+        let Some(path) = VideoRecorder::default_path(&self.name) else {
+            warn!(
+                "No user data directory to save videos of {:?} to",
+                self.name
+            );
+            return;
+        };
+        info!("Recording {:?} to {}", self.name, path.display());
+        self.video = Some(VideoRecorder::new(path));
+    }
+
+    /// Record a snapshot of guest memory into `session` after `frame` frames,
+    /// or check it against the recorded one when replaying
+    pub fn snapshot_memory(&self, session: &Session, frame: usize) {
+        if let Some(runtime) = &self.runtime {
+            session.snapshot(frame, &runtime.memory_data());
+        }
+    }
+
+    /// Write a crash report for `error`, logging where it went
+    fn write_crash_report(&self, reporter: &CrashReporter, error: &anyhow::Error) {
+        let memory = self
+            .runtime
+            .as_ref()
+            .filter(|_| reporter.include_memory())
+            .map(WasmRuntime::memory_snapshot);
+        let crash = Crash {
+            app: &self.name,
+            path: &self.path,
+            version: &self.version,
+            error,
+            session: self.options.session.as_ref(),
+            memory,
+        };
+        match reporter.write(&crash) {
+            Ok(path) => info!("Crash report written to {}", path.display()),
+            Err(e) => warn!("Failed to write crash report: {:#}", e),
+        }
+    }
+
+    /// Reinstantiate a crashed guest once its restart backoff has elapsed
+    ///
+    /// Returns whether a runtime is available to update this frame.
+    fn ensure_runtime(&mut self) -> Result<bool> {
+        if self.runtime.is_some() {
+            return Ok(true);
+        }
+        match self.restart_at {
+            Some(at) if Instant::now() >= at => {}
+            _ => return Ok(false),
+        }
+
+        self.restart_at = None;
+        self.deferred_dt = 0.0;
+        self.scale = 1.0;
+        self.update_rate = self.options.degrade.then(UpdateRate::new);
+        info!("Restarting {:?}", self.name);
+
+        let mut runtime = instantiate(
+            &self.wasm_bytes,
+            self.precompiled.as_deref(),
+            &self.guest_args,
+            &self.name,
+            &self.id,
+            &self.version,
+            &self.strings,
+            &self.config,
+            &self.assets,
+            &self.wasi_policy,
+            &self.disabled_features,
+            &self.access,
+            &self.options,
+        )
+        .context("Failed to reinstantiate WASM runtime")?;
+        init_guest(&mut runtime, &self.graphics, &self.options)?;
+        self.runtime = Some(runtime);
+        self.microphone = None;
+        self.graphics.update_hud(None)?;
+        Ok(true)
+    }
+
+    /// Deliver queued events and run the guest update, on the guest thread if
+    /// enabled or else on the current thread
+    pub fn update(&mut self, dt: f64) -> Result<()> {
+        if self.options.guest_thread {
+            return self.update_on_guest_thread(dt);
+        }
+        if !self.ensure_runtime()? {
+            return Ok(());
+        }
+        let Some(dt) = self.take_update_dt(dt) else {
+            return Ok(());
+        };
+        let events = self.take_events();
+        let runtime = self
+            .runtime
+            .as_mut()
+            .context("App runtime is unavailable")?;
+
+        let start = Instant::now();
+        let result = runtime.run_frame(&events, dt);
+        self.record_update(start, start.elapsed());
+
+        result
+    }
+
+    /// Start an update on the guest thread unless one is still running, then
+    /// wait up to a frame for it
+    fn update_on_guest_thread(&mut self, dt: f64) -> Result<()> {
+        if self.is_updating() {
+            // Events and dt wait for the next update
+            self.deferred_dt += dt;
+        } else {
+            if !self.ensure_runtime()? {
+                return Ok(());
+            }
+            let Some(dt) = self.take_update_dt(dt) else {
+                return Ok(());
+            };
+            if self.guest_thread.is_none() {
+                self.guest_thread = Some(GuestThread::spawn()?);
+            }
+            let events = self.take_events();
+            let mut runtime = self.runtime.take().context("App runtime is unavailable")?;
+            let thread = self
+                .guest_thread
+                .as_mut()
+                .context("Guest thread is unavailable")?;
+            thread.dispatch(move || {
+                let result = runtime.run_frame(&events, dt);
+                (runtime, result)
+            })?;
+        }
+        self.wait_for_guest(guest_thread::FRAME_WAIT)
+    }
+
+    /// Take the runtime back once the update on the guest thread finishes,
+    /// waiting up to `timeout`, and return the update's result
+    fn wait_for_guest(&mut self, timeout: Duration) -> Result<()> {
+        let Some(thread) = &mut self.guest_thread else {
+            return Ok(());
+        };
+        match thread.wait(timeout) {
+            None => Ok(()),
+            Some(Ok(finished)) => {
+                let (runtime, result) = finished.output;
+                self.runtime = Some(runtime);
+                self.record_update(finished.start, finished.elapsed);
+                result
+            }
+            Some(Err(e)) => {
+                // A new thread is spawned for the restarted guest
+                self.guest_thread = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// How long the host may block waiting for input before this app needs
+    /// an update under `--power-save`, or `None` if it needs one now
+    pub fn idle_wait(&self) -> Option<Duration> {
+        if !self.options.power_save || !self.pending_events.is_empty() || self.is_updating() {
+            return None;
+        }
+        let runtime = self.runtime.as_ref()?;
+        if runtime.wants_frame(self.deferred_dt) {
+            return None;
+        }
+        Some(runtime.next_wake().map_or(Duration::MAX, |next| {
+            Duration::from_secs_f64((next - self.deferred_dt).max(0.0))
+        }))
+    }
+
+    /// Whether an update is running on the guest thread
+    fn is_updating(&self) -> bool {
+        self.guest_thread.as_ref().is_some_and(GuestThread::is_busy)
+    }
+
+    /// Finish the update running on the guest thread and run further updates
+    /// through `update_all`'s worker pool
+    fn leave_guest_thread(&mut self) -> Result<()> {
+        self.options.guest_thread = false;
+        let result = self.wait_for_guest(Duration::MAX);
+        self.guest_thread = None;
+        result
+    }
+
+    /// Account for a `run_frame` call that started at `start` and took `elapsed`
+    fn record_update(&mut self, start: Instant, elapsed: Duration) {
+        self.usage.record_guest_time(elapsed);
+        self.stats.record_guest_time(elapsed);
+        if let Some(timing) = &mut self.timing {
+            timing.record_update(elapsed);
+        }
+        if let Some(rate) = &mut self.update_rate {
+            let frame = match self.graphics.refresh_rate() {
+                0 => Duration::from_secs_f64(1.0 / 60.0),
+                hz => Duration::from_secs_f64(1.0 / hz as f64),
+            };
+            if let Some(level) = rate.record_update(elapsed, frame) {
+                match level {
+                    0 => info!("{}: updating every frame again", self.name),
+                    _ => info!("{}: updating every {} frames", self.name, 1 << level),
+                }
+                self.pending_events.push(TimedEvent {
+                    event: GuestEvent::PerfHint {
+                        level: level as i32,
+                    },
+                    time: host_time().as_secs_f64(),
+                });
+            }
+        }
+        if let (Some(profile), Some(runtime)) = (&self.profile, &self.runtime) {
+            let dispatch = runtime.dispatch_time();
+            profile.span("event dispatch", start, dispatch);
+            profile.span(
+                "guest update",
+                start + dispatch,
+                elapsed.saturating_sub(dispatch),
+            );
+        }
+    }
+
+    /// Upload the latest guest frame (if any) and present it
+    pub fn present(&mut self) -> Result<()> {
+        // Save states requested during the update, between calls into the guest
+        match self
+            .runtime
+            .as_mut()
+            .and_then(WasmRuntime::take_snapshot_request)
+        {
+            Some(SnapshotRequest::Save) => self.save_state(),
+            Some(SnapshotRequest::Restore) => self.restore_state(),
+            None => {}
+        }
+        #[cfg(feature = "dialogs")]
+        if let Some(request) = self
+            .runtime
+            .as_mut()
+            .and_then(WasmRuntime::take_file_request)
+        {
+            let event = crate::file_dialog::show(request, &self.name);
+            self.pending_events.push(TimedEvent {
+                event,
+                time: host_time().as_secs_f64(),
+            });
+        }
+
+        let graphics = &mut self.graphics;
+        let Some(runtime) = self.runtime.as_mut() else {
+            // Keep showing the last frame while a crashed guest awaits restart,
+            // or the error if it waits for the user
+            if let Some(screen) = &self.crash_screen {
+                let overlay = screen.overlay(graphics.window_size());
+                graphics.set_overlay(overlay);
+            }
+            graphics.present()?;
+            return Ok(());
+        };
+
+        // Get the latest frame from the host interface and update graphics
+        // Uses zero-copy borrow pattern to avoid allocating a new Vec each frame
+        if let Some(adjustment) = runtime.take_display_adjustment() {
+            if self.options.safe_mode {
+                debug!("{}: ignoring display adjustment in safe mode", self.name);
+            } else {
+                debug!("{}: display adjustment {}", self.name, adjustment);
+                self.display_adjust.set_app(adjustment);
+            }
+        }
+        let frame_diff = &mut self.frame_diff;
+        let display_adjust = &mut self.display_adjust;
+        let color_filter = &mut self.color_filter;
+        let post_filter = &mut self.post_filter;
+        let inspector = &mut self.inspector;
+        let timing = &mut self.timing;
+        let frames_received = &mut self.frames_received;
+        let (name, frame_hashes) = (&self.name, &self.options.frame_hashes);
+        let screenshot_after = self.options.screenshot_after;
+        let video = &mut self.video;
+        let mut video_result = Ok(());
+        let mut upload = None;
+        let measure_latency = self.latency.is_some();
+        let mut new_frame_hash = None;
+        let copy_start = Instant::now();
+        let frame = runtime.with_frame_data(|width, height, pixels| {
+            let (width, height) = (width as u32, height as u32);
+            if measure_latency {
+                new_frame_hash = Some(hash_frame(width, height, pixels));
+            }
+            if let Some(log) = frame_hashes {
+                log.write(name, *frames_received, width, height, pixels)?;
+            }
+            *frames_received += 1;
+            if screenshot_after == Some(*frames_received) {
+                save_screenshot(name, width, height, pixels);
+            }
+            if let Some(video) = video {
+                video_result = video.push_frame(width, height, pixels);
+            }
+            if let Some(inspector) = inspector {
+                inspector.capture(width, height, pixels);
+            }
+            let pixels = match frame_diff {
+                Some(diff) => diff.apply(width, height, pixels),
+                None => pixels,
+            };
+            let pixels = display_adjust.apply(pixels);
+            let pixels = match color_filter {
+                Some(filter) => filter.apply(pixels),
+                None => pixels,
+            };
+            let (scale, pixels) = match post_filter {
+                Some(filter) => filter.apply(width, height, pixels),
+                None => (1, pixels),
+            };
+            let start = Instant::now();
+            let result = graphics.update_texture_scaled(width, height, scale, pixels);
+            let elapsed = start.elapsed();
+            if let Some(timing) = timing {
+                timing.record_upload(width, height, elapsed);
+            }
+            upload = Some((start, elapsed));
+            result
+        });
+        if let (Some(profile), Some(_)) = (&self.profile, &frame) {
+            // The copy span ends where the upload starts
+            match upload {
+                Some((start, elapsed)) => {
+                    profile.span("frame copy", copy_start, start - copy_start);
+                    profile.span("texture upload", start, elapsed);
+                }
+                None => profile.span("frame copy", copy_start, copy_start.elapsed()),
+            }
+        }
+        if let Some(result) = frame {
+            result?;
+        }
+        match runtime.take_overlay() {
+            Some(OverlayUpdate::Set {
+                width,
+                height,
+                pixels,
+            }) => self.graphics.update_hud(Some((width, height, &pixels)))?,
+            Some(OverlayUpdate::Remove) => self.graphics.update_hud(None)?,
+            None => {}
+        }
+        if let Err(e) = video_result {
+            warn!("Stopped recording {:?}: {:#}", self.name, e);
+            if let Some(video) = self.video.take() {
+                finish_video(&self.name, video);
+            }
+        }
+
+        let log_lines = runtime.take_log_lines();
+        if let Some(console) = &mut self.console {
+            console.extend(log_lines);
+        }
+        if self.inspector.is_some()
+            || self.timing.is_some()
+            || self.console.is_some()
+            || self.virtual_controls.is_some()
+        {
+            let mut overlay = self
+                .inspector
+                .as_ref()
+                .zip(self.cursor)
+                .and_then(|(inspector, cursor)| {
+                    let target = self.graphics.window_to_frame(cursor.0, cursor.1)?;
+                    Some(inspector.overlay(cursor, target, self.graphics.window_size()))
+                })
+                .unwrap_or_default();
+            if let Some(timing) = &self.timing {
+                overlay.extend(timing.overlay());
+            }
+            if let Some(console) = &self.console {
+                overlay.extend(console.overlay(self.graphics.window_size()));
+            }
+            if let Some(controls) = &self.virtual_controls {
+                overlay.extend(controls.overlay(self.graphics.window_size()));
+            }
+            self.graphics.set_overlay(overlay);
+        }
+
+        // Under --low-latency the frame is shown as soon as it is uploaded;
+        // the window changes below apply from the next one
+        let mut presented = None;
+        if self.options.low_latency {
+            presented = Some(render(&mut self.graphics, self.profile.as_ref())?);
+        }
+
+        // Apply layout constraints and fullscreen changes requested during
+        // the update, and report the resulting viewport like a resize
+        let mut resized = false;
+        if let Some(constraints) = runtime.take_constraints() {
+            debug!("{}: display constraints {:?}", self.name, constraints);
+            self.graphics.set_constraints(constraints);
+            resized = true;
+        }
+        if let Some(size) = runtime.take_window_size() {
+            debug!("{}: window size {:?}", self.name, size);
+            self.graphics.set_window_size(size);
+            resized = true;
+        }
+        if let Some(fullscreen) = runtime.take_fullscreen_request() {
+            debug!("{}: fullscreen {}", self.name, fullscreen);
+            match self.graphics.set_fullscreen(fullscreen) {
+                Ok(()) => resized = true,
+                Err(e) => warn!("{}: {:#}", self.name, e),
+            }
+        }
+        if resized {
+            let viewport = self.graphics.viewport();
+            self.pending_events.push(TimedEvent {
+                event: GuestEvent::Resize {
+                    width: viewport.width() as i32,
+                    height: viewport.height() as i32,
+                },
+                time: host_time().as_secs_f64(),
+            });
+        }
+        // Moving the window to another display may change its refresh rate
+        if self.options.session.is_none() {
+            runtime.set_refresh_rate(self.graphics.refresh_rate());
+        }
+        // Moving the window to a display of another density changes its scale
+        let scale = self.graphics.scale_factor();
+        if scale != self.scale {
+            let (width, height) = self.graphics.window_size();
+            let (drawable_width, drawable_height) = self.graphics.drawable_size();
+            debug!(
+                "{}: {}x{} window drawn at {}x{} pixels, scale {}",
+                self.name, width, height, drawable_width, drawable_height, scale
+            );
+            self.scale = scale;
+            self.pending_events.push(TimedEvent {
+                event: GuestEvent::ScaleChanged { scale },
+                time: host_time().as_secs_f64(),
+            });
+        }
+
+        if let Some(rgba) = runtime.take_clear_color() {
+            self.graphics.set_clear_color(unpack_color(rgba));
+        }
+        if let Some(scaling) = runtime.take_scaling_mode() {
+            self.graphics.set_scaling_mode(scaling);
+        }
+        let title = runtime.take_window_title();
+        if let Some(cursor) = runtime.take_cursor() {
+            debug!("{}: cursor {:?}", self.name, cursor);
+            self.cursor_settings = cursor;
+            if self.focused {
+                apply_cursor(&mut self.graphics, cursor, self.options.kiosk);
+            }
+        }
+
+        // Hand off the audio pushed during the update
+        if let Some(audio) = &mut self.audio {
+            if let Some((format, samples)) = runtime.take_audio() {
+                if let Err(e) = audio.queue(format, &samples) {
+                    warn!("{}: {:#}", self.name, e);
+                }
+            }
+            runtime.set_audio_device_frames(audio.queued_frames());
+            match runtime.take_capture_request() {
+                Some(CaptureRequest::Start(format)) => {
+                    self.microphone = None;
+                    if let Some(captured) = runtime.captured_audio() {
+                        match audio.open_capture(format, captured) {
+                            Ok(microphone) => self.microphone = Some(microphone),
+                            Err(e) => warn!("{}: {:#}", self.name, e),
+                        }
+                    }
+                }
+                Some(CaptureRequest::Stop) if self.microphone.take().is_some() => {
+                    info!("{}: closed microphone", self.name);
+                }
+                Some(CaptureRequest::Stop) | None => {}
+            }
+        }
+
+        let presented = match presented {
+            Some(presented) => presented,
+            None => render(&mut self.graphics, self.profile.as_ref())?,
+        };
+        if presented && upload.is_some() {
+            if let Some(polled) = self.delivered_input.take() {
+                let latency = polled.elapsed();
+                trace!("{}: input latency {:?}", self.name, latency);
+                if let Some(timing) = &mut self.timing {
+                    timing.record_input_latency(latency);
+                }
+            }
+        }
+        if presented && runtime.wants_present_time() {
+            let live = || host_time().as_micros() as u64;
+            self.unreported_present = Some(match &self.options.session {
+                Some(session) => session.present_time(live),
+                None => live(),
+            });
+        }
+
+        // Time the latency marker once its response is on screen, then
+        // queue the next one when due
+        if let Some(latency) = &mut self.latency {
+            let now = Instant::now();
+            if let Some(hash) = new_frame_hash {
+                latency.record_frame(hash, now);
+            }
+            if let Some(marker) = latency.poll(now) {
+                let viewport = self.graphics.viewport();
+                let time = host_time().as_secs_f64();
+                for event in marker.events(viewport.width() as i32, viewport.height() as i32) {
+                    self.pending_events.push(TimedEvent { event, time });
+                }
+            }
+        }
+
+        self.usage.record_frame();
+        if let Some(timing) = &mut self.timing {
+            timing.record_present(Instant::now());
+        }
+        let heap_bytes = runtime.heap_bytes();
+        self.stats.record_frame(runtime.memory_size(), heap_bytes);
+        if let Some(level) = self.performance.record_frame(Instant::now()) {
+            info!("{}: performance warning level {}", self.name, level);
+            self.pending_events.push(TimedEvent {
+                event: GuestEvent::PerformanceWarning {
+                    level: level as i32,
+                },
+                time: host_time().as_secs_f64(),
+            });
+        }
+        if let Some((current_pages, limit_pages)) = runtime.take_memory_pressure() {
+            info!(
+                "{}: memory pressure, {} of {} pages",
+                self.name, current_pages, limit_pages
+            );
+            self.pending_events.push(TimedEvent {
+                event: GuestEvent::MemoryPressure {
+                    current_pages: current_pages as i32,
+                    limit_pages: limit_pages as i32,
+                },
+                time: host_time().as_secs_f64(),
+            });
+        }
+        for (handle, data) in runtime.take_ws_messages() {
+            self.pending_events.push(TimedEvent {
+                event: GuestEvent::WsMessage { handle, data },
+                time: host_time().as_secs_f64(),
+            });
+        }
+        if let Some(snapshot) = self.usage.sample(runtime.memory_size(), heap_bytes) {
+            debug!("{}: {}", self.name, snapshot);
+            if let Some(diff) = &self.frame_diff {
+                debug!("{}: {} pixels changed", self.name, diff.changed_pixels());
+            }
+            if self.options.show_usage || self.frame_diff.is_some() {
+                self.refresh_title();
+            }
+        }
+        if let Some(title) = title {
+            debug!("{}: window title {:?}", self.name, title);
+            self.title = Some(title);
+            self.refresh_title();
+        }
+
+        Ok(())
+    }
+
+    /// Tell the guest when its last frame was presented, via `on_present`
+    ///
+    /// Kept separate from [`present`](Self::present) so that a guest failing
+    /// in `on_present` is handled like a crash in `update`.
+    pub fn report_present(&mut self) -> Result<()> {
+        let Some(present_time) = self.unreported_present.take() else {
+            return Ok(());
+        };
+        let frame_index = self.frames_presented;
+        self.frames_presented += 1;
+        match self.runtime.as_mut() {
+            Some(runtime) => runtime.call_on_present(frame_index, present_time),
+            None => Ok(()),
+        }
+    }
+
+    /// Toggle the frame diff debug view
+    pub fn toggle_frame_diff(&mut self) {
+        self.frame_diff = match self.frame_diff {
+            Some(_) => None,
+            None => Some(FrameDiff::new()),
+        };
+        info!(
+            "Frame diff view {} for {:?}",
+            if self.frame_diff.is_some() {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            self.name
+        );
+        self.refresh_title();
+    }
+
+    /// Switch to the next color vision deficiency simulation, or back to none
+    ///
+    /// Takes effect from the next frame the guest presents.
+    pub fn cycle_color_filter(&mut self) {
+        let next = Deficiency::cycle(self.color_filter.as_ref().map(ColorFilter::deficiency));
+        self.color_filter = next.map(ColorFilter::new);
+        match next {
+            Some(deficiency) => info!("Simulating {} for {:?}", deficiency, self.name),
+            None => info!("Color vision simulation disabled for {:?}", self.name),
+        }
+        self.refresh_title();
+    }
+
+    /// Move one of the viewer's display adjustments by `steps` hotkey presses
+    ///
+    /// Takes effect from the next frame the guest presents.
+    pub fn adjust_display(&mut self, control: Control, steps: i32) {
+        let adjustment = self.display_adjust.viewer().nudge(control, steps);
+        self.display_adjust.set_viewer(adjustment);
+        info!("Display {} for {:?}", adjustment, self.name);
+        self.refresh_title();
+    }
+
+    /// Undo the viewer's display adjustments
+    #[cfg(feature = "menu")]
+    pub fn reset_display_adjustment(&mut self) {
+        self.display_adjust.set_viewer(Adjustment::default());
+        info!("Display adjustment reset for {:?}", self.name);
+        self.refresh_title();
+    }
+
+    /// Toggle the pixel inspector debug view
+    ///
+    /// The inspector only sees frames presented after it is enabled.
+    pub fn toggle_inspector(&mut self) {
+        self.inspector = match self.inspector {
+            Some(_) => {
+                self.graphics.set_overlay(Vec::new());
+                None
+            }
+            None => Some(PixelInspector::new()),
+        };
+        info!(
+            "Pixel inspector {} for {:?}",
+            if self.inspector.is_some() {
+                "enabled"
+            } else {
+                "disabled"
+            },
+            self.name
+        );
+    }
+
+    /// Toggle the frame timing debug view
+    pub fn toggle_timing_overlay(&mut self) {
+        self.timing = match self.timing {
+            Some(_) => {
+                self.graphics.set_overlay(Vec::new());
+                None
+            }
+            None => Some(TimingOverlay::new(Instant::now())),
+        };
+    }
+
+    /// Toggle the guest log lines view
+    ///
+    /// Only lines logged after it is enabled are shown.
+    #[cfg(feature = "menu")]
+    pub fn toggle_console(&mut self) {
+        self.console = match self.console {
+            Some(_) => {
+                self.graphics.set_overlay(Vec::new());
+                None
+            }
+            None => Some(ConsoleOverlay::default()),
+        };
+    }
+
+    /// Zoom the debug view around window coordinate (`x`, `y`)
+    pub fn zoom_view(&mut self, x: i32, y: i32, steps: i32) {
+        let (x, y) = self.graphics.window_to_layout(x, y);
+        self.graphics.zoom_at(x, y, steps);
+    }
+
+    /// Zoom the debug view around the center of the window
+    #[cfg(feature = "menu")]
+    pub fn zoom_view_centered(&mut self, steps: i32) {
+        let (width, height) = self.graphics.window_size();
+        self.graphics
+            .zoom_at(width as i32 / 2, height as i32 / 2, steps);
+    }
+
+    /// Resize the window to a multiple of the frame size
+    #[cfg(feature = "menu")]
+    pub fn scale_window(&mut self, factor: u32) {
+        self.graphics.set_scale(factor);
+    }
+
+    /// The app's window, e.g. to attach a native menu bar
+    #[cfg(feature = "menu")]
+    pub fn window(&self) -> &sdl2::video::Window {
+        self.graphics.window()
+    }
+
+    /// Whether the app's window has keyboard focus
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Pan the zoomed debug view by a distance in window pixels
+    pub fn pan_view(&mut self, dx: i32, dy: i32) {
+        let (dx, dy) = self.graphics.delta_to_layout(dx, dy);
+        self.graphics.pan_by(dx, dy);
+    }
+
+    /// Whether the debug view is magnified
+    pub fn is_view_zoomed(&self) -> bool {
+        self.graphics.is_zoomed()
+    }
+
+    /// Track the cursor position inside the window (`None` once it leaves)
+    pub fn set_cursor(&mut self, cursor: Option<(i32, i32)>) {
+        self.cursor = cursor.map(|(x, y)| self.graphics.window_to_layout(x, y));
+    }
+
+    /// Print the guest's description of its current screen, if it provides one
+    pub fn print_description(&mut self) -> Result<()> {
+        let Some(runtime) = self.runtime.as_mut() else {
+            return Ok(());
+        };
+        match runtime.describe()? {
+            Some(description) => {
+                println!("[{}] {}", self.name, description);
+                self.description = Some(description);
+            }
+            None => info!("{:?} does not export on_describe", self.name),
+        }
+        Ok(())
+    }
+
+    /// With `--describe`, print the screen description when it changes
+    ///
+    /// The guest is asked at most once per second.
+    pub fn poll_description(&mut self) -> Result<()> {
+        if !self.options.describe
+            || self
+                .last_described
+                .is_some_and(|at| at.elapsed().as_secs_f64() < DESCRIBE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_described = Some(Instant::now());
+
+        let Some(runtime) = self.runtime.as_mut() else {
+            return Ok(());
+        };
+        if let Some(description) = runtime.describe()? {
+            if self.description.as_ref() != Some(&description) {
+                println!("[{}] {}", self.name, description);
+                self.description = Some(description);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild the window title from the guest's title, else the app name,
+    /// and any enabled debug readouts
+    fn refresh_title(&mut self) {
+        let mut title = self.title.clone().unwrap_or_else(|| self.name.clone());
+        if self.options.show_usage {
+            if let Some(snapshot) = self.usage.latest() {
+                title.push_str(&format!(" | {}", snapshot));
+            }
+        }
+        if let Some(filter) = &self.color_filter {
+            title.push_str(&format!(" | {}", filter.deficiency()));
+        }
+        let adjustment = self.display_adjust.viewer();
+        if !adjustment.is_identity() {
+            title.push_str(&format!(" | {}", adjustment));
+        }
+        if let Some(diff) = &self.frame_diff {
+            title.push_str(&format!(
+                " | {} px changed ({:.1}%)",
+                diff.changed_pixels(),
+                diff.changed_percent()
+            ));
+        }
+        self.graphics.set_title(&title);
+    }
+}
+
+/// Create a runtime for a guest module with the host interface configured from `options`
+///
+/// `name` and `version` are the packaged values the guest can read back;
+/// `id` is the package id its data is kept under.
+#[allow(clippy::too_many_arguments)]
+fn instantiate(
+    wasm_bytes: &[u8],
+    precompiled: Option<&[u8]>,
+    args: &[String],
+    name: &str,
+    id: &str,
+    version: &str,
+    strings: &HashMap<String, String>,
+    config: &HashMap<String, String>,
+    assets: &Arc<Assets>,
+    wasi_policy: &WasiPolicy,
+    disabled_features: &DisabledFeatures,
+    access: &Access,
+    options: &AppOptions,
+) -> Result<WasmRuntime> {
+    let mut host_interface = HostInterface::new();
+    host_interface.set_app_info(name.to_string(), version.to_string());
+    host_interface.set_package_id(id.to_string());
+    // Sessions replayed elsewhere must see the same preferences
+    if options.session.is_none() {
+        let locale = options.locale.clone().or_else(locale::user_locale);
+        host_interface.set_user_preferences(
+            locale.as_deref().map(locale::tag).unwrap_or_default(),
+            options.theme.is_dark(),
+        );
+    }
+    host_interface
+        .set_launch_allowed(options.allow_launch || access.granted.contains(&Permission::Launch));
+    host_interface.set_bus_allowed(access.granted.contains(&Permission::Messaging));
+    host_interface.set_capabilities(access.capabilities.clone());
+    host_interface.set_first_use(access.on_first_use.clone());
+    host_interface.set_count_import_calls(options.count_import_calls);
+    host_interface.set_threads_enabled(options.enable_threads);
+    host_interface.set_disabled_features(disabled_features.clone());
+    host_interface.set_frame_limits(options.frame_limits);
+    host_interface.set_fullscreen_locked(options.kiosk);
+    host_interface.set_strings(strings.clone());
+    host_interface.set_config(config.clone());
+    host_interface.set_assets(assets.clone());
+    // Recorded and replayed sessions start from empty storage so they match
+    if options.session.is_some() || !access.granted.contains(&Permission::Storage) {
+        host_interface.set_storage(AppStorage::in_memory());
+    } else {
+        host_interface.set_storage(AppStorage::open(id));
+        host_interface.set_score_key(ScoreKey::load_or_create());
+    }
+    host_interface.set_state_path(state_path(id, options));
+    // Sessions could not reproduce network responses
+    if options.session.is_none() {
+        host_interface.set_allowed_hosts(access.allowed_hosts.clone());
+    }
+    // Kiosks have no one to pick files, and sessions could not replay them
+    host_interface.set_file_dialogs_allowed(
+        cfg!(feature = "dialogs")
+            && options.session.is_none()
+            && !options.kiosk
+            && !options.safe_mode,
+    );
+    // Only apps declaring the capability get files, and sessions start from
+    // nothing on disk so they replay alike
+    let declares_files = access
+        .capabilities
+        .as_ref()
+        .is_some_and(|declared| declared.contains(&Capability::Filesystem));
+    if options.allow_storage && declares_files && options.session.is_none() && !options.safe_mode {
+        host_interface.set_files_dir(storage::files_dir(id));
+    }
+    let mut runtime = WasmRuntime::new(
+        wasm_bytes,
+        precompiled,
+        host_interface,
+        args,
+        options.session.as_ref(),
+        wasi_policy,
+        options.allow_unknown_imports,
+        options.max_memory,
+    )?;
+    runtime.set_frame_budget(options.frame_budget);
+    Ok(runtime)
+}
+
+/// Call the guest's `init` with the size of its viewport, once
+fn init_guest(runtime: &mut WasmRuntime, graphics: &Graphics, options: &AppOptions) -> Result<()> {
+    // Sessions replayed elsewhere must see the same display
+    if options.session.is_none() {
+        runtime.set_refresh_rate(graphics.refresh_rate());
+    }
+    let viewport = graphics.viewport();
+    runtime
+        .call_init(viewport.width() as i32, viewport.height() as i32)
+        .context("Guest failed to initialize")
+}
+
+/// Save a screenshot of the app named `name`, logging where it went
+fn save_screenshot(name: &str, width: u32, height: u32, pixels: &[u8]) {
+    match screenshot::save(name, width, height, pixels) {
+        Ok(path) => info!("Saved a screenshot of {:?} to {}", name, path.display()),
+        Err(e) => warn!("Failed to save a screenshot of {:?}: {:#}", name, e),
+    }
+}
+
+/// Finish `video` of the app named `name`, logging where it went
+fn finish_video(name: &str, video: VideoRecorder) {
+    let path = video.path().to_path_buf();
+    match video.finish() {
+        Ok(0) => info!("Stopped recording {:?}: no frames", name),
+        Ok(frames) => info!(
+            "Saved {} frames of {:?} to {}",
+            frames,
+            name,
+            path.display()
+        ),
+        Err(e) => warn!("Failed to save video {}: {:#}", path.display(), e),
+    }
+}
+
+/// Present the window's latest frame, profiling the present
+fn render(graphics: &mut Graphics, profile: Option<&ProfileTrack>) -> Result<bool> {
+    let start = Instant::now();
+    let presented = graphics.present()?;
+    if let Some(profile) = profile {
+        profile.span("present", start, start.elapsed());
+    }
+    Ok(presented)
+}
+
+/// Show a guest's cursor `settings` over its window; kiosks keep the cursor hidden
+fn apply_cursor(graphics: &mut Graphics, mut settings: CursorSettings, kiosk: bool) {
+    settings.hidden |= kiosk;
+    graphics.apply_cursor(settings);
+}
+
+/// File the package with id `id` saves its state to, or `None` when save
+/// states are unavailable: restoring one would make recorded and replayed
+/// sessions diverge, and safe mode writes nothing
+fn state_path(id: &str, options: &AppOptions) -> Option<PathBuf> {
+    if options.session.is_some() || options.safe_mode {
+        return None;
+    }
+    save_state::state_path(id)
+}
+
+/// How long the host may block waiting for input before any of `apps` needs
+/// an update, at most `MAX_IDLE_WAIT`, or `None` if one needs it now
+pub fn idle_wait(apps: &[AppInstance]) -> Option<Duration> {
+    apps.iter()
+        .try_fold(MAX_IDLE_WAIT, |wait, app| Some(wait.min(app.idle_wait()?)))
+}
+
+/// Deliver the messages each app sent on the bus to the other apps
+/// subscribed to their topic, in the order they were sent
+pub fn relay_bus_messages(apps: &mut [AppInstance]) {
+    let time = host_time().as_secs_f64();
+    for sender in 0..apps.len() {
+        for (topic, data) in apps[sender].take_bus_messages() {
+            debug!(
+                "{}: bus message on {:?} ({} bytes)",
+                apps[sender].name,
+                topic,
+                data.len()
+            );
+            for (index, app) in apps.iter_mut().enumerate() {
+                if index != sender {
+                    app.receive_bus_message(&topic, &data, time);
+                }
+            }
+        }
+    }
+}
+
+/// Update every app for one frame, returning the apps whose guest failed
+///
+/// With a worker pool, each runtime is moved to a worker together with its
+/// queued events and moved back once its update completes; this call blocks
+/// until all apps have finished so frames can be handed off together.
+/// Without a pool, apps are updated sequentially, each on its guest thread
+/// when enabled or else on the calling thread.
+/// Apps throttled in the background are skipped until their interval elapses,
+/// and idle apps under `--power-save` until something wakes them.
+pub fn update_all(
+    apps: &mut [AppInstance],
+    pool: Option<&WorkerPool>,
+    dt: f64,
+) -> Vec<(usize, anyhow::Error)> {
+    let mut failures = Vec::new();
+
+    let Some(pool) = pool else {
+        for (index, app) in apps.iter_mut().enumerate() {
+            if let Err(e) = app.update(dt) {
+                failures.push((index, e));
+            }
+        }
+        return failures;
+    };
+
+    let (tx, rx) = mpsc::channel();
+
+    for (index, app) in apps.iter_mut().enumerate() {
+        // An app that ran alone finishes its update before joining the pool
+        if app.guest_thread.is_some() {
+            if let Err(e) = app.leave_guest_thread() {
+                failures.push((index, e));
+                continue;
+            }
+        }
+        // Restarts reinstantiate on the main thread before the update is dispatched
+        match app.ensure_runtime() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                failures.push((index, e));
+                continue;
+            }
+        }
+        // Throttled apps keep their events queued until their next update
+        let Some(dt) = app.take_update_dt(dt) else {
+            continue;
+        };
+        let Some(mut runtime) = app.runtime.take() else {
+            continue;
+        };
+        let events = app.take_events();
+        let tx = tx.clone();
+
+        pool.execute(move || {
+            let start = Instant::now();
+            let update = panic::catch_unwind(AssertUnwindSafe(|| runtime.run_frame(&events, dt)));
+            // A runtime that panicked mid-update is dropped, as it may be
+            // left inconsistent; the app restarts or shows the crash
+            let (runtime, result) = match update {
+                Ok(result) => (Some(runtime), result),
+                Err(_) => (None, Err(anyhow!("Worker thread panicked during update"))),
+            };
+            let _ = tx.send((index, runtime, result, start, start.elapsed()));
+        });
+    }
+
+    // Only the jobs hold senders now, so the loop ends once all have reported
+    drop(tx);
+
+    for (index, runtime, result, start, elapsed) in rx {
+        let app = &mut apps[index];
+        app.runtime = runtime;
+        app.record_update(start, elapsed);
+        if let Err(e) = result {
+            failures.push((index, e));
+        }
+    }
+
+    failures
+}
+
+/// Resolve a `wapps::launch` target to an existing package file
+///
+/// `target` may be a path (absolute, or relative to `base_dir`) or a package
+/// name, in which case `<base_dir>/<name>.wapp` is tried.
+fn resolve_launch_target(base_dir: &Path, target: &str) -> Option<PathBuf> {
+    let path = base_dir.join(target);
+    if path.is_file() {
+        return Some(path);
+    }
+    let named = base_dir.join(format!("{}.wapp", target));
+    named.is_file().then_some(named)
+}
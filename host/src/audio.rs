@@ -0,0 +1,311 @@
+//! Audio Output and Capture
+//!
+//! Guests push interleaved `f32` samples with `wapps::push_audio` and pace
+//! their mixing with `wapps::get_audio_queued_frames`. Guests may run on worker
+//! threads while SDL audio devices belong to the main thread, so pushed samples
+//! wait in the host interface until the app hands them to its device once per
+//! frame, like frames are handed to its window.
+//!
+//! Microphone capture goes the other way: `wapps::audio_capture_start` asks
+//! the app to open a capture device, whose thread fills a buffer shared with
+//! the host interface, and `wapps::audio_capture_read` drains it. Capturing
+//! needs the `microphone` permission, asked on first use.
+
+#[cfg(feature = "window")]
+use anyhow::{anyhow, Result};
+use log::debug;
+#[cfg(feature = "window")]
+use log::{info, warn};
+#[cfg(feature = "window")]
+use sdl2::{
+    audio::{AudioCallback, AudioDevice, AudioQueue, AudioSpecDesired},
+    AudioSubsystem,
+};
+use std::collections::VecDeque;
+#[cfg(feature = "window")]
+use std::sync::{Arc, Mutex};
+
+/// Supported sample rates, in Hz
+const SAMPLE_RATES: std::ops::RangeInclusive<u32> = 8_000..=192_000;
+
+/// Most audio buffered per app, in seconds; samples pushed beyond it are dropped
+const MAX_BUFFERED_SECONDS: u32 = 2;
+
+/// Most microphone audio kept until the guest reads it, in seconds; older
+/// samples are dropped
+const MAX_CAPTURED_SECONDS: u32 = 1;
+
+/// Channel count and sample rate of a guest's audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub channels: u8,
+    pub sample_rate: u32,
+}
+
+impl AudioFormat {
+    /// Validate the format passed to `wapps::push_audio` (mono or stereo)
+    pub fn new(channels: i32, sample_rate: i32) -> Option<Self> {
+        let channels = u8::try_from(channels)
+            .ok()
+            .filter(|c| (1..=2).contains(c))?;
+        let sample_rate = u32::try_from(sample_rate)
+            .ok()
+            .filter(|rate| SAMPLE_RATES.contains(rate))?;
+        Some(Self {
+            channels,
+            sample_rate,
+        })
+    }
+
+    /// Most frames buffered at once in this format
+    fn max_buffered_frames(self) -> u32 {
+        self.sample_rate * MAX_BUFFERED_SECONDS
+    }
+}
+
+/// Samples pushed by a guest since they were last handed to the device
+#[derive(Debug, Default)]
+pub struct PendingAudio {
+    format: Option<AudioFormat>,
+    samples: Vec<f32>,
+}
+
+impl PendingAudio {
+    /// Buffer interleaved samples, discarding older ones in a different format
+    pub fn push(&mut self, format: AudioFormat, samples: &[f32]) {
+        if self.format != Some(format) {
+            self.samples.clear();
+            self.format = Some(format);
+        }
+        let room = format.max_buffered_frames().saturating_sub(self.frames()) as usize
+            * format.channels as usize;
+        if samples.len() > room {
+            debug!(
+                "Audio buffer full, dropping {} samples",
+                samples.len() - room
+            );
+        }
+        self.samples
+            .extend_from_slice(&samples[..samples.len().min(room)]);
+    }
+
+    /// Number of buffered frames
+    pub fn frames(&self) -> u32 {
+        match self.format {
+            Some(format) => (self.samples.len() / format.channels as usize) as u32,
+            None => 0,
+        }
+    }
+
+    /// Take the buffered samples, if any
+    pub fn take(&mut self) -> Option<(AudioFormat, Vec<f32>)> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some((self.format?, std::mem::take(&mut self.samples)))
+    }
+}
+
+/// Microphone samples captured for a guest and not read yet, filled by the
+/// capture device's thread
+#[derive(Debug, Default)]
+pub struct CapturedAudio {
+    /// Format being captured, if capturing
+    format: Option<AudioFormat>,
+    samples: VecDeque<f32>,
+}
+
+impl CapturedAudio {
+    /// Start over capturing in `format`, or stop with `None`
+    pub fn reset(&mut self, format: Option<AudioFormat>) {
+        self.format = format;
+        self.samples.clear();
+    }
+
+    /// Keep interleaved samples the device captured, dropping the oldest
+    /// past `MAX_CAPTURED_SECONDS`
+    pub fn push(&mut self, samples: &[f32]) {
+        let Some(format) = self.format else {
+            return;
+        };
+        self.samples.extend(samples);
+        let max = (format.sample_rate * MAX_CAPTURED_SECONDS) as usize * format.channels as usize;
+        let excess = self.samples.len().saturating_sub(max);
+        self.samples.drain(..excess);
+    }
+
+    /// Channels of the samples being captured
+    pub fn channels(&self) -> usize {
+        self.format.map_or(1, |format| format.channels as usize)
+    }
+
+    /// Take the oldest `max_frames` frames, or as many as were captured
+    pub fn read(&mut self, max_frames: usize) -> Vec<f32> {
+        let Some(format) = self.format else {
+            return Vec::new();
+        };
+        let channels = format.channels as usize;
+        let count = max_frames.saturating_mul(channels).min(self.samples.len());
+        self.samples.drain(..count - count % channels).collect()
+    }
+}
+
+/// An app's audio device, opened when the guest first pushes samples
+#[cfg(feature = "window")]
+pub struct AudioOutput {
+    subsystem: AudioSubsystem,
+    device: Option<(AudioFormat, AudioQueue<f32>)>,
+}
+
+#[cfg(feature = "window")]
+impl AudioOutput {
+    pub fn new(subsystem: AudioSubsystem) -> Self {
+        Self {
+            subsystem,
+            device: None,
+        }
+    }
+
+    /// Queue samples for playback, reopening the device if the format changed
+    ///
+    /// Samples that would exceed the buffering limit are dropped.
+    pub fn queue(&mut self, format: AudioFormat, samples: &[f32]) -> Result<()> {
+        if self.device.as_ref().map(|(current, _)| *current) != Some(format) {
+            self.device = None;
+            let desired = AudioSpecDesired {
+                freq: Some(format.sample_rate as i32),
+                channels: Some(format.channels),
+                samples: None,
+            };
+            let queue = self
+                .subsystem
+                .open_queue::<f32, _>(None, &desired)
+                .map_err(|e| anyhow!("Failed to open audio device: {}", e))?;
+            queue.resume();
+            info!(
+                "Opened audio device: {} channel(s) at {} Hz",
+                format.channels, format.sample_rate
+            );
+            self.device = Some((format, queue));
+        }
+
+        let Some((_, queue)) = &self.device else {
+            return Ok(());
+        };
+        let room = format
+            .max_buffered_frames()
+            .saturating_sub(self.queued_frames()) as usize
+            * format.channels as usize;
+        if samples.len() > room {
+            warn!(
+                "Audio device queue full, dropping {} samples",
+                samples.len() - room
+            );
+        }
+        queue
+            .queue_audio(&samples[..samples.len().min(room)])
+            .map_err(|e| anyhow!("Failed to queue audio: {}", e))
+    }
+
+    /// Open the default microphone, capturing `format` into `captured`
+    pub fn open_capture(
+        &self,
+        format: AudioFormat,
+        captured: Arc<Mutex<CapturedAudio>>,
+    ) -> Result<AudioInput> {
+        let desired = AudioSpecDesired {
+            freq: Some(format.sample_rate as i32),
+            channels: Some(format.channels),
+            samples: None,
+        };
+        let device = self
+            .subsystem
+            .open_capture(None, &desired, |_| Capture(captured))
+            .map_err(|e| anyhow!("Failed to open microphone: {}", e))?;
+        device.resume();
+        info!(
+            "Opened microphone: {} channel(s) at {} Hz",
+            format.channels, format.sample_rate
+        );
+        Ok(AudioInput { _device: device })
+    }
+
+    /// Frames queued on the device and not yet played
+    pub fn queued_frames(&self) -> u32 {
+        match &self.device {
+            Some((format, queue)) => {
+                queue.size() / (std::mem::size_of::<f32>() as u32 * format.channels as u32)
+            }
+            None => 0,
+        }
+    }
+}
+
+/// A microphone opened for an app, capturing until dropped
+#[cfg(feature = "window")]
+pub struct AudioInput {
+    _device: AudioDevice<Capture>,
+}
+
+/// Capture device callback, run on SDL's audio thread
+#[cfg(feature = "window")]
+struct Capture(Arc<Mutex<CapturedAudio>>);
+
+#[cfg(feature = "window")]
+impl AudioCallback for Capture {
+    type Channel = f32;
+
+    fn callback(&mut self, samples: &mut [f32]) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_format_validation() {
+        assert!(AudioFormat::new(2, 48_000).is_some());
+        assert!(AudioFormat::new(0, 48_000).is_none());
+        assert!(AudioFormat::new(6, 48_000).is_none());
+        assert!(AudioFormat::new(1, 100).is_none());
+    }
+
+    #[test]
+    fn test_pending_audio_is_bounded() {
+        let format = AudioFormat::new(2, 8_000).unwrap();
+        let mut pending = PendingAudio::default();
+        pending.push(format, &[0.5; 8]);
+        assert_eq!(pending.frames(), 4);
+
+        // A format change discards samples that would play at the wrong rate
+        let mono = AudioFormat::new(1, 8_000).unwrap();
+        pending.push(mono, &[0.25; 20_000]);
+        assert_eq!(pending.frames(), mono.max_buffered_frames());
+
+        let (taken_format, samples) = pending.take().unwrap();
+        assert_eq!(taken_format, mono);
+        assert_eq!(samples.len(), 16_000);
+        assert!(pending.take().is_none());
+    }
+
+    #[test]
+    fn test_captured_audio_is_read_in_whole_frames() {
+        let mut captured = CapturedAudio::default();
+        captured.push(&[0.5; 4]);
+        assert!(captured.read(10).is_empty());
+
+        captured.reset(AudioFormat::new(2, 8_000));
+        captured.push(&[0.5; 6]);
+        assert_eq!(captured.read(2), vec![0.5; 4]);
+        assert_eq!(captured.read(10).len(), 2);
+
+        // Only the latest second is kept
+        captured.push(&[0.25; 20_000]);
+        assert_eq!(captured.read(usize::MAX).len(), 16_000);
+    }
+}
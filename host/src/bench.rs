@@ -0,0 +1,186 @@
+//! `wapps bench` Command
+//!
+//! Runs a package headlessly as fast as it goes and reports how long its
+//! `update` calls and the copies of its frames took (min, mean, p95 and max)
+//! and how much its memory grew, for guest authors optimizing their render
+//! loops. Like `wapps test`, guests get a fixed `dt`, a disabled clock and a
+//! fixed random seed, so runs of two builds compare. The report is printed as
+//! a table, or as JSON with `--json`.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::host_interface::HostInterface;
+use crate::loader;
+use crate::memory_limit;
+use crate::runtime::WasmRuntime;
+use crate::storage::AppStorage;
+use crate::wasi_policy::{ClockPolicy, WasiPolicy};
+use crate::watchdog;
+
+/// Time step passed to `update` for each frame
+const FRAME_DT: f64 = 1.0 / 60.0;
+
+/// Arguments of `wapps bench`
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// Package to benchmark
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// Number of frames to run
+    #[arg(long, value_name = "N", default_value_t = 1000)]
+    frames: u32,
+
+    /// Print the report as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+/// Distribution of the time a step took over the run, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct Summary {
+    min: f64,
+    mean: f64,
+    p95: f64,
+    max: f64,
+}
+
+impl Summary {
+    fn new(samples: &[Duration]) -> Self {
+        let mut ms: Vec<f64> = samples.iter().map(|d| d.as_nanos() as f64 / 1e6).collect();
+        ms.sort_by(f64::total_cmp);
+        let p95 = (ms.len() * 95 / 100).min(ms.len().saturating_sub(1));
+        Self {
+            min: ms.first().copied().unwrap_or(0.0),
+            mean: ms.iter().sum::<f64>() / ms.len().max(1) as f64,
+            p95: ms.get(p95).copied().unwrap_or(0.0),
+            max: ms.last().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+/// Results of a run
+#[derive(Debug, Serialize)]
+struct Report {
+    app: String,
+    frames: u32,
+    update: Summary,
+    frame_copy: Summary,
+    /// Linear memory before the first frame and after the last, in bytes
+    memory_start: usize,
+    memory_end: usize,
+    /// Bytes the guest's allocator had in use at the end, if it reports them
+    heap_bytes: Option<u64>,
+}
+
+/// Run `wapps bench`
+pub fn run(args: &BenchArgs) -> Result<()> {
+    let (wasm_bytes, metadata) = loader::load_wapp(&args.file)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", args.file))?;
+    let mut host_interface = HostInterface::new();
+    host_interface.set_app_info(metadata.name.clone(), metadata.version.clone());
+    host_interface.set_strings(metadata.strings.clone());
+    host_interface.set_config(metadata.config.clone());
+    // Benchmarks must not touch the app's saved data
+    host_interface.set_storage(AppStorage::in_memory());
+    host_interface.set_capabilities(metadata.capabilities);
+    let policy = WasiPolicy {
+        clock: ClockPolicy::Disabled,
+        random_seed: Some(0),
+    };
+    let mut runtime = WasmRuntime::new(
+        &wasm_bytes,
+        None,
+        host_interface,
+        std::slice::from_ref(&metadata.name),
+        None,
+        &policy,
+        false,
+        Some(memory_limit::DEFAULT_MAX_MEMORY),
+    )
+    .context("Failed to initialize WASM runtime")?;
+    runtime.set_frame_budget(Some(watchdog::DEFAULT_FRAME_BUDGET));
+
+    let memory_start = runtime.memory_size();
+    let mut updates = Vec::with_capacity(args.frames as usize);
+    let mut copies = Vec::with_capacity(args.frames as usize);
+    let mut frame = Vec::new();
+    for index in 0..args.frames {
+        let start = Instant::now();
+        runtime
+            .run_frame(&[], FRAME_DT)
+            .with_context(|| format!("Guest failed at frame {}", index))?;
+        updates.push(start.elapsed());
+
+        // Copy the frame out as the windowed host does before uploading it
+        let start = Instant::now();
+        runtime.with_frame_data(|_, _, pixels| {
+            frame.clear();
+            frame.extend_from_slice(pixels);
+        });
+        copies.push(start.elapsed());
+    }
+
+    let report = Report {
+        app: metadata.name,
+        frames: args.frames,
+        update: Summary::new(&updates),
+        frame_copy: Summary::new(&copies),
+        memory_start,
+        memory_end: runtime.memory_size(),
+        heap_bytes: runtime.heap_bytes(),
+    };
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", table(&report));
+    }
+    Ok(())
+}
+
+/// The report as a table for the terminal
+fn table(report: &Report) -> String {
+    let row = |name: &str, summary: &Summary| {
+        format!(
+            "{:<12}{:>10.3}{:>10.3}{:>10.3}{:>10.3}\n",
+            name, summary.min, summary.mean, summary.p95, summary.max
+        )
+    };
+    let mut out = format!("{}: {} frames\n", report.app, report.frames);
+    out.push_str(&format!(
+        "{:<12}{:>10}{:>10}{:>10}{:>10}\n",
+        "ms", "min", "mean", "p95", "max"
+    ));
+    out.push_str(&row("update", &report.update));
+    out.push_str(&row("frame copy", &report.frame_copy));
+    out.push_str(&format!(
+        "memory      {} -> {} bytes ({:+})\n",
+        report.memory_start,
+        report.memory_end,
+        report.memory_end as i64 - report.memory_start as i64
+    ));
+    if let Some(heap) = report.heap_bytes {
+        out.push_str(&format!("heap        {} bytes in use\n", heap));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summaries_sort_samples() {
+        let samples: Vec<_> = (1..=20).rev().map(Duration::from_millis).collect();
+        let summary = Summary::new(&samples);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.mean, 10.5);
+        assert_eq!(summary.p95, 20.0);
+        assert_eq!(summary.max, 20.0);
+        assert_eq!(Summary::new(&[]).mean, 0.0);
+    }
+}
@@ -0,0 +1,462 @@
+//! `wapps bindgen` Command
+//!
+//! The host/guest contract is specified once, in `wit/wapps.wit`, and
+//! embedded in the host. This command prints guest glue generated from it:
+//! import declarations and export prototypes for Rust, a C header, or
+//! AssemblyScript declarations. The ABI is core WebAssembly, so only the
+//! subset of WIT the definition uses is understood: one interface of host
+//! functions and one world exporting the guest callbacks, with flat numeric
+//! types.
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+/// The WIT definition of the host/guest contract
+pub const WIT: &str = include_str!("../../wit/wapps.wit");
+
+/// Core WebAssembly module the host imports live in
+const IMPORT_MODULE: &str = "wapps";
+
+/// Language to generate glue for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Language {
+    /// `extern "C"` imports and `#[no_mangle]` export prototypes
+    Rust,
+    /// A header declaring imports and exports with clang attributes
+    C,
+    /// `@external` declarations
+    Assemblyscript,
+    /// The WIT definition itself
+    Wit,
+}
+
+/// Arguments of `wapps bindgen`
+#[derive(Args, Debug)]
+pub struct BindgenArgs {
+    /// Language to generate glue for
+    #[arg(long, value_name = "LANGUAGE")]
+    lang: Language,
+
+    /// Write the glue to this file instead of stdout
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// Run `wapps bindgen`
+pub fn run(args: &BindgenArgs) -> Result<()> {
+    let glue = match args.lang {
+        Language::Wit => WIT.to_string(),
+        lang => generate(&parse(WIT)?, lang),
+    };
+    match &args.output {
+        Some(path) => fs::write(path, glue)
+            .with_context(|| format!("Could not write bindings: {}", path.display())),
+        None => {
+            print!("{}", glue);
+            Ok(())
+        }
+    }
+}
+
+/// Numeric types of the core ABI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WitType {
+    S32,
+    S64,
+    F32,
+    F64,
+}
+
+impl WitType {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "s32" => WitType::S32,
+            "s64" => WitType::S64,
+            "f32" => WitType::F32,
+            "f64" => WitType::F64,
+            other => bail!("unsupported type {:?}; the core ABI is numeric only", other),
+        })
+    }
+
+    /// Core WebAssembly type, which AssemblyScript also uses
+    pub fn core(self) -> &'static str {
+        match self {
+            WitType::S32 => "i32",
+            WitType::S64 => "i64",
+            WitType::F32 => "f32",
+            WitType::F64 => "f64",
+        }
+    }
+
+    fn c(self) -> &'static str {
+        match self {
+            WitType::S32 => "int32_t",
+            WitType::S64 => "int64_t",
+            WitType::F32 => "float",
+            WitType::F64 => "double",
+        }
+    }
+}
+
+/// A function of the contract
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Core name, in snake_case
+    pub name: String,
+    pub docs: Vec<String>,
+    pub params: Vec<(String, WitType)>,
+    pub result: Option<WitType>,
+}
+
+impl Function {
+    /// Core signature, e.g. `(i32, i32) -> ()`, as `wapps validate` prints them
+    #[cfg(test)]
+    pub fn signature(&self) -> String {
+        let params: Vec<_> = self.params.iter().map(|(_, ty)| ty.core()).collect();
+        format!(
+            "({}) -> ({})",
+            params.join(", "),
+            self.result.map_or("", WitType::core)
+        )
+    }
+}
+
+/// Functions of the contract
+#[derive(Debug, Default)]
+pub struct Contract {
+    /// Functions guests import from the `wapps` module
+    pub imports: Vec<Function>,
+    /// Functions the host calls if guests export them
+    pub exports: Vec<Function>,
+}
+
+/// Parse the subset of WIT used by the contract
+pub fn parse(wit: &str) -> Result<Contract> {
+    let mut contract = Contract::default();
+    let mut docs = Vec::new();
+    let mut statement = String::new();
+    // Whether we are in the interface (imports) or the world (exports)
+    let mut section: Option<&str> = None;
+
+    for (number, line) in wit.lines().enumerate() {
+        let line = line.trim();
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.trim().to_string());
+            continue;
+        }
+        let line = line.split("//").next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        statement.push_str(line);
+        statement.push(' ');
+        let statement_text = statement.trim().to_string();
+
+        let context = || format!("wapps.wit line {}", number + 1);
+        if let Some(header) = statement_text.strip_suffix('{') {
+            section = match header.split_whitespace().next() {
+                Some("interface") => Some("interface"),
+                Some("world") => Some("world"),
+                _ => bail!("{}: unexpected block {:?}", context(), header),
+            };
+        } else if statement_text == "}" {
+            section = None;
+        } else if let Some(statement_text) = statement_text.strip_suffix(';') {
+            match section {
+                None if statement_text.starts_with("package ") => {}
+                Some("world") if statement_text.starts_with("import ") => {}
+                Some("world") => {
+                    let Some(function) = statement_text.strip_prefix("export ") else {
+                        bail!("{}: expected an export", context());
+                    };
+                    let function = parse_function(function, &docs).with_context(context)?;
+                    contract.exports.push(function);
+                }
+                Some(_) => {
+                    let function = parse_function(statement_text, &docs).with_context(context)?;
+                    contract.imports.push(function);
+                }
+                None => bail!("{}: unexpected {:?}", context(), statement_text),
+            }
+        } else {
+            // A statement continued on the next line
+            continue;
+        }
+        statement.clear();
+        docs.clear();
+    }
+    if !statement.trim().is_empty() || section.is_some() {
+        bail!("wapps.wit ends in the middle of a definition");
+    }
+    Ok(contract)
+}
+
+/// Parse `name: func(param: type, ...) -> type`
+fn parse_function(text: &str, docs: &[String]) -> Result<Function> {
+    let (name, signature) = text.split_once(':').context("expected `name: func(...)`")?;
+    let signature = signature
+        .trim()
+        .strip_prefix("func(")
+        .context("expected `func(`")?;
+    let (params, result) = signature.split_once(')').context("expected `)`")?;
+    let params = params
+        .split(',')
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, ty) = param.split_once(':').context("expected `name: type`")?;
+            Ok((snake_case(name.trim()), WitType::parse(ty.trim())?))
+        })
+        .collect::<Result<_>>()?;
+    let result = match result.trim() {
+        "" => None,
+        result => Some(WitType::parse(
+            result.strip_prefix("->").context("expected `->`")?.trim(),
+        )?),
+    };
+    Ok(Function {
+        name: snake_case(name.trim()),
+        docs: docs.to_vec(),
+        params,
+        result,
+    })
+}
+
+fn snake_case(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+/// Generate glue for `lang` (other than WIT)
+fn generate(contract: &Contract, lang: Language) -> String {
+    let mut out = String::new();
+    match lang {
+        Language::Rust => generate_rust(contract, &mut out),
+        Language::C => generate_c(contract, &mut out),
+        Language::Assemblyscript => generate_assemblyscript(contract, &mut out),
+        Language::Wit => out.push_str(WIT),
+    }
+    out
+}
+
+fn generate_rust(contract: &Contract, out: &mut String) {
+    let params = |function: &Function| {
+        let params: Vec<_> = function
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty.core()))
+            .collect();
+        params.join(", ")
+    };
+    let result = |function: &Function| {
+        function
+            .result
+            .map(|ty| format!(" -> {}", ty.core()))
+            .unwrap_or_default()
+    };
+
+    out.push_str("// Generated by `wapps bindgen --lang rust` from wapps.wit\n\n");
+    let _ = writeln!(out, "#[link(wasm_import_module = \"{}\")]", IMPORT_MODULE);
+    out.push_str("extern \"C\" {\n");
+    for function in &contract.imports {
+        for doc in &function.docs {
+            let _ = writeln!(out, "    /// {}", doc);
+        }
+        let _ = writeln!(
+            out,
+            "    pub fn {}({}){};",
+            function.name,
+            params(function),
+            result(function)
+        );
+    }
+    out.push_str("}\n");
+
+    out.push_str("\n// Exports the host calls; only `update` is required:\n");
+    for function in &contract.exports {
+        out.push_str("//\n");
+        for doc in &function.docs {
+            let _ = writeln!(out, "// /// {}", doc);
+        }
+        out.push_str("// #[no_mangle]\n");
+        let _ = writeln!(
+            out,
+            "// pub extern \"C\" fn {}({}){} {}",
+            function.name,
+            params(function),
+            result(function),
+            if function.result.is_some() {
+                "{ todo!() }"
+            } else {
+                "{}"
+            }
+        );
+    }
+}
+
+fn generate_c(contract: &Contract, out: &mut String) {
+    let declaration = |function: &Function| {
+        let params: Vec<_> = function
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{} {}", ty.c(), name))
+            .collect();
+        format!(
+            "{} {}({})",
+            function.result.map_or("void", WitType::c),
+            function.name,
+            if params.is_empty() {
+                "void".to_string()
+            } else {
+                params.join(", ")
+            }
+        )
+    };
+    let docs = |out: &mut String, function: &Function| {
+        for doc in &function.docs {
+            let _ = writeln!(out, "// {}", doc);
+        }
+    };
+
+    out.push_str("/* Generated by `wapps bindgen --lang c` from wapps.wit */\n\n");
+    out.push_str("#ifndef WAPPS_H\n#define WAPPS_H\n\n#include <stdint.h>\n\n");
+    let _ = writeln!(
+        out,
+        "#define WAPPS_IMPORT(name) __attribute__((import_module(\"{}\"), import_name(#name)))",
+        IMPORT_MODULE
+    );
+    out.push_str("#define WAPPS_EXPORT(name) __attribute__((export_name(#name)))\n");
+
+    out.push_str("\n/* Host imports */\n");
+    for function in &contract.imports {
+        out.push('\n');
+        docs(out, function);
+        let _ = writeln!(
+            out,
+            "WAPPS_IMPORT({}) {};",
+            function.name,
+            declaration(function)
+        );
+    }
+
+    out.push_str("\n/* Exports the host calls; define `update` and any others you need */\n");
+    for function in &contract.exports {
+        out.push('\n');
+        docs(out, function);
+        let _ = writeln!(
+            out,
+            "WAPPS_EXPORT({}) {};",
+            function.name,
+            declaration(function)
+        );
+    }
+    out.push_str("\n#endif\n");
+}
+
+fn generate_assemblyscript(contract: &Contract, out: &mut String) {
+    let signature = |function: &Function| {
+        let params: Vec<_> = function
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty.core()))
+            .collect();
+        format!(
+            "{}({}): {}",
+            function.name,
+            params.join(", "),
+            function.result.map_or("void", WitType::core)
+        )
+    };
+
+    out.push_str("// Generated by `wapps bindgen --lang assemblyscript` from wapps.wit\n");
+    for function in &contract.imports {
+        out.push('\n');
+        for doc in &function.docs {
+            let _ = writeln!(out, "// {}", doc);
+        }
+        let _ = writeln!(
+            out,
+            "@external(\"{}\", \"{}\")\nexport declare function {};",
+            IMPORT_MODULE,
+            function.name,
+            signature(function)
+        );
+    }
+
+    out.push_str("\n// Exports the host calls; export `update` and any others you need\n");
+    out.push_str("// from your entry file, along with `memory`:\n");
+    for function in &contract.exports {
+        out.push_str("//\n");
+        for doc in &function.docs {
+            let _ = writeln!(out, "// // {}", doc);
+        }
+        let _ = writeln!(out, "// export function {} {{}}", signature(function));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{runtime, validate};
+    use wasmtime::{Engine, Module};
+
+    #[test]
+    fn test_wit_matches_the_host() {
+        let contract = parse(WIT).unwrap();
+
+        // A module importing every function of the contract links
+        let mut wat = String::from("(module\n");
+        for function in &contract.imports {
+            let params: Vec<_> = function.params.iter().map(|(_, ty)| ty.core()).collect();
+            let _ = writeln!(
+                wat,
+                "(import \"wapps\" \"{}\" (func (param {}) (result {})))",
+                function.name,
+                params.join(" "),
+                function.result.map_or("", WitType::core)
+            );
+        }
+        wat.push_str("(memory (export \"memory\") 1)\n(func (export \"update\") (param f64)))");
+        let engine = Engine::default();
+        let module = Module::new(&engine, &wat).unwrap();
+        let linker = runtime::create_linker(&engine).unwrap();
+        assert!(runtime::missing_imports(&engine, &linker, &module).is_empty());
+        linker.instantiate_pre(&module).unwrap();
+
+        // ...and the contract lists every function the host provides
+        let documented: Vec<_> = contract.imports.iter().map(|f| f.name.as_str()).collect();
+        let undocumented: Vec<_> = runtime::provided_imports(&engine, &linker, "wapps")
+            .into_iter()
+            .filter(|name| !documented.contains(&name.as_str()))
+            .collect();
+        assert_eq!(undocumented, Vec::<String>::new());
+
+        // The host calls every export with the documented signature
+        let (update, optional) = contract.exports.split_first().unwrap();
+        assert_eq!(
+            (update.name.as_str(), update.signature()),
+            ("update", "(f64) -> ()".into())
+        );
+        let optional: Vec<_> = optional
+            .iter()
+            .map(|function| (function.name.as_str(), function.signature()))
+            .collect();
+        let expected: Vec<_> = validate::OPTIONAL_EXPORTS
+            .iter()
+            .map(|(name, signature)| (*name, signature.to_string()))
+            .collect();
+        assert_eq!(optional, expected);
+
+        let c = generate(&contract, Language::C);
+        assert!(c.contains(
+            "WAPPS_IMPORT(update_frame) void update_frame(int32_t width, int32_t height, \
+             int32_t pixels_ptr);"
+        ));
+        assert!(c.contains("WAPPS_EXPORT(on_idle) void on_idle(void);"));
+        assert!(generate(&contract, Language::Assemblyscript)
+            .contains("export declare function event_time(): f64;"));
+        assert!(parse("interface host {\n    f: func(s: string);\n}\n").is_err());
+    }
+}
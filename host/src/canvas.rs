@@ -0,0 +1,147 @@
+//! Canvas Drawing
+//!
+//! Simple apps on slow interpreters, or written in languages without a
+//! graphics library, can draw on the host-held frame instead of pushing a
+//! whole framebuffer every frame: `wapps::clear` fills it with one color,
+//! `wapps::fill_rect` blends a rectangle over it and `wapps::blit` blends RGBA
+//! pixels from guest memory, copied when the call is made. These are queued
+//! with image and text draws, drawn in call order over the frame (or a canvas
+//! started with `wapps::clear_canvas`), and clipped to it.
+
+use crate::layers::blend_pixel;
+
+/// Most bytes of blitted pixels queued per frame
+pub const MAX_BLIT_BYTES: usize = 64 * 1024 * 1024;
+
+/// A queued `wapps::clear`, `wapps::fill_rect` or `wapps::blit` call; colors
+/// are packed as `0xRRGGBBAA`
+#[derive(Debug, Clone, PartialEq)]
+pub enum CanvasDraw {
+    /// Replace every pixel with `rgba`
+    Clear { rgba: u32 },
+    /// Blend `rgba` over a `width` x `height` rectangle at (`x`, `y`)
+    Fill {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        rgba: u32,
+    },
+    /// Blend `width` x `height` RGBA `pixels` with their top-left corner at
+    /// (`x`, `y`)
+    Blit {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+}
+
+/// Draw `draw` into the `width` x `height` RGBA `target`, clipped to it
+pub fn draw(target: &mut [u8], width: u32, height: u32, draw: &CanvasDraw) {
+    match draw {
+        CanvasDraw::Clear { rgba } => {
+            let color = rgba.to_be_bytes();
+            for pixel in target.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+        CanvasDraw::Fill {
+            x,
+            y,
+            width: w,
+            height: h,
+            rgba,
+        } => {
+            let color = rgba.to_be_bytes();
+            let (xs, ys) = clip(*x, *y, *w, *h, width, height);
+            for ty in ys {
+                for tx in xs.clone() {
+                    let i = (ty * width as usize + tx) * 4;
+                    blend_pixel(&mut target[i..i + 4], &color, 1.0);
+                }
+            }
+        }
+        CanvasDraw::Blit {
+            x,
+            y,
+            width: w,
+            height: h,
+            pixels,
+        } => {
+            let (xs, ys) = clip(*x, *y, *w, *h, width, height);
+            for ty in ys {
+                let sy = (ty as i64 - *y as i64) as usize;
+                for tx in xs.clone() {
+                    let sx = (tx as i64 - *x as i64) as usize;
+                    let src = (sy * *w as usize + sx) * 4;
+                    let dst = (ty * width as usize + tx) * 4;
+                    blend_pixel(&mut target[dst..dst + 4], &pixels[src..src + 4], 1.0);
+                }
+            }
+        }
+    }
+}
+
+/// Target columns and rows a `w` x `h` rectangle at (`x`, `y`) covers in a
+/// `width` x `height` target
+fn clip(
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+    width: u32,
+    height: u32,
+) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+    let span = |start: i32, len: u32, max: u32| {
+        let end = (start as i64 + len as i64).clamp(0, max as i64) as usize;
+        (start.max(0) as usize).min(end)..end
+    };
+    (span(x, w, width), span(y, h, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn test_draws_are_clipped() {
+        let mut target = vec![0u8; 3 * 3 * 4];
+        let pixel = |target: &[u8], x: usize, y: usize| target[(y * 3 + x) * 4..][..4].to_vec();
+
+        draw(&mut target, 3, 3, &CanvasDraw::Clear { rgba: 0x0000ffff });
+        assert!(target.chunks(4).all(|pixel| pixel == BLUE));
+
+        // Hangs off the top-left corner
+        let fill = CanvasDraw::Fill {
+            x: -1,
+            y: -1,
+            width: 3,
+            height: 2,
+            rgba: 0xff0000ff,
+        };
+        draw(&mut target, 3, 3, &fill);
+        assert_eq!(pixel(&target, 1, 0), RED);
+        assert_eq!(pixel(&target, 2, 0), BLUE);
+        assert_eq!(pixel(&target, 0, 1), BLUE);
+
+        // Hangs off the bottom-right corner; its transparent pixel is skipped
+        let blit = CanvasDraw::Blit {
+            x: 2,
+            y: 1,
+            width: 2,
+            height: 2,
+            pixels: [RED, RED, [0; 4], RED].concat(),
+        };
+        draw(&mut target, 3, 3, &blit);
+        assert_eq!(pixel(&target, 2, 1), RED);
+        assert_eq!(pixel(&target, 2, 2), BLUE);
+
+        assert_eq!(clip(5, 0, 2, 2, 3, 3).0, 3..3);
+        assert_eq!(clip(-5, 0, 2, 2, 3, 3).0, 0..0);
+    }
+}
@@ -0,0 +1,141 @@
+//! Capability Manifest
+//!
+//! A package header may list the host capabilities its app needs under
+//! `"capabilities"`, e.g. `["audio", "storage"]`. Host imports belonging to a
+//! capability that is not listed are not linked: the runtime links stubs in
+//! their place that deny every call, logging the first one, so an app only
+//! reaches what its manifest declares. Packages without the field predate
+//! manifests and keep every import. `filesystem` gates no import: it only
+//! lets the user give the app a private WASI directory with
+//! `--allow-storage`. `clipboard` is reserved for a host API that does not
+//! exist yet, so declaring it grants nothing.
+
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+use crate::permissions;
+use crate::runtime;
+
+/// A group of host imports an app declares in its manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// Play sound via `wapps::push_audio`
+    Audio,
+    /// Keep settings and high scores between runs
+    Storage,
+    /// WASI sockets, `wapps::http_fetch` and `wapps::ws_connect`
+    Network,
+    /// Open and save documents the user picks in a host file dialog
+    Files,
+    /// A private directory preopened into WASI, when the user allows it
+    Filesystem,
+    /// Reserved for a clipboard API
+    Clipboard,
+    /// Controller rumble and LEDs via `wapps::gamepad_rumble` and
+    /// `wapps::gamepad_set_led`
+    Gamepad,
+    /// Record audio via `wapps::audio_capture_start`
+    Microphone,
+}
+
+impl Capability {
+    /// Capability an import belongs to, if it is gated at all
+    pub fn from_import(module: &str, name: &str) -> Option<Self> {
+        match (runtime::unversioned(module), name) {
+            ("wapps", "push_audio" | "get_audio_queued_frames") => Some(Capability::Audio),
+            ("wapps", "storage_get" | "storage_set" | "score_submit" | "score_list") => {
+                Some(Capability::Storage)
+            }
+            ("wapps", "http_fetch" | "http_poll" | "http_read" | "http_close") => {
+                Some(Capability::Network)
+            }
+            ("wapps", "ws_connect" | "ws_send" | "ws_status" | "ws_close") => {
+                Some(Capability::Network)
+            }
+            ("wasi_snapshot_preview1", name) if name.starts_with("sock_") => {
+                Some(Capability::Network)
+            }
+            ("wapps", "request_open_file" | "request_save_file") => Some(Capability::Files),
+            ("wapps", "gamepad_rumble" | "gamepad_set_led") => Some(Capability::Gamepad),
+            ("wapps", "audio_capture_start" | "audio_capture_read" | "audio_capture_stop") => {
+                Some(Capability::Microphone)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Audio => "audio",
+            Capability::Storage => "storage",
+            Capability::Network => "network",
+            Capability::Files => "files",
+            Capability::Filesystem => "filesystem",
+            Capability::Clipboard => "clipboard",
+            Capability::Gamepad => "gamepad",
+            Capability::Microphone => "microphone",
+        }
+    }
+}
+
+/// Imports of `wasm`, as `module::name`, whose capability is not `declared`
+pub fn undeclared(wasm: &[u8], declared: &BTreeSet<Capability>) -> Vec<(String, Capability)> {
+    permissions::imports(wasm)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(module, name)| {
+            let capability = Capability::from_import(&module, &name)?;
+            (!declared.contains(&capability)).then(|| (format!("{}::{}", module, name), capability))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_imports_map_to_declared_capabilities() {
+        let declared: BTreeSet<Capability> =
+            serde_json::from_str(r#"["audio", "gamepad"]"#).unwrap();
+        assert!(declared.contains(&Capability::Audio));
+        assert!(serde_json::from_str::<BTreeSet<Capability>>(r#"["camera"]"#).is_err());
+        let filesystem: BTreeSet<Capability> = serde_json::from_str(r#"["filesystem"]"#).unwrap();
+        assert!(filesystem.contains(&Capability::Filesystem));
+        assert_eq!(
+            Capability::from_import("wasi_snapshot_preview1", "path_open"),
+            None
+        );
+
+        assert_eq!(
+            Capability::from_import("wapps", "storage_set"),
+            Some(Capability::Storage)
+        );
+        assert_eq!(
+            Capability::from_import("wasi_snapshot_preview1", "sock_recv"),
+            Some(Capability::Network)
+        );
+        assert_eq!(
+            Capability::from_import("wapps", "gamepad_rumble"),
+            Some(Capability::Gamepad)
+        );
+        assert_eq!(Capability::from_import("wapps", "update_frame"), None);
+        assert_eq!(
+            Capability::from_import("wapps2", "push_audio"),
+            Some(Capability::Audio)
+        );
+
+        // wapps::push_audio then wapps::storage_get
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        let mut section = vec![2];
+        section.extend_from_slice(b"\x05wapps\x0apush_audio\x00\x00");
+        section.extend_from_slice(b"\x05wapps\x0bstorage_get\x00\x00");
+        wasm.extend_from_slice(&[2, section.len() as u8]);
+        wasm.extend_from_slice(&section);
+        assert_eq!(
+            undeclared(&wasm, &declared),
+            vec![("wapps::storage_get".to_string(), Capability::Storage)]
+        );
+    }
+}
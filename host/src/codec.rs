@@ -0,0 +1,189 @@
+//! Compression Codecs
+//!
+//! Every section of a package records the id of the codec it was stored with.
+//! The loader looks codecs up in a registry, so new algorithms only need a new
+//! id and a [`Codec`] implementation rather than another format version.
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+
+use crate::deflate;
+
+/// zstd level used by the packer; fixed so output stays reproducible
+const ZSTD_LEVEL: i32 = 19;
+
+/// A compression algorithm identified by a stable id in the package format
+pub trait Codec: Send + Sync {
+    /// Id stored in section headers; never reuse a retired id
+    fn id(&self) -> u8;
+    fn name(&self) -> &'static str;
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>>;
+    /// Decompress `data`, which expands to `raw_len` bytes; `raw_len` comes
+    /// from the package, so fail rather than inflate past it
+    fn decompress(&self, data: &[u8], raw_len: usize) -> Result<Vec<u8>>;
+}
+
+/// Codecs selectable when packing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CodecKind {
+    None,
+    Zstd,
+    Deflate,
+}
+
+impl CodecKind {
+    pub fn id(self) -> u8 {
+        match self {
+            CodecKind::None => NONE_ID,
+            CodecKind::Zstd => ZSTD_ID,
+            CodecKind::Deflate => DEFLATE_ID,
+        }
+    }
+}
+
+const NONE_ID: u8 = 0;
+const ZSTD_ID: u8 = 1;
+const DEFLATE_ID: u8 = 2;
+
+struct NoCompression;
+
+impl Codec for NoCompression {
+    fn id(&self) -> u8 {
+        NONE_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    fn decompress(&self, data: &[u8], _raw_len: usize) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+struct Zstd;
+
+impl Codec for Zstd {
+    fn id(&self) -> u8 {
+        ZSTD_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "zstd"
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        zstd::bulk::compress(data, ZSTD_LEVEL).context("zstd compression failed")
+    }
+
+    fn decompress(&self, data: &[u8], raw_len: usize) -> Result<Vec<u8>> {
+        zstd::bulk::decompress(data, raw_len).context("zstd decompression failed")
+    }
+}
+
+struct Deflate;
+
+impl Codec for Deflate {
+    fn id(&self) -> u8 {
+        DEFLATE_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "deflate"
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(deflate::compress(data))
+    }
+
+    fn decompress(&self, data: &[u8], raw_len: usize) -> Result<Vec<u8>> {
+        deflate::decompress(data, raw_len)
+    }
+}
+
+/// Codecs known to this host, looked up by id
+pub struct CodecRegistry {
+    codecs: Vec<Box<dyn Codec>>,
+}
+
+impl CodecRegistry {
+    /// Registry with every built-in codec
+    pub fn new() -> Self {
+        let mut registry = Self { codecs: Vec::new() };
+        registry.register(Box::new(NoCompression));
+        registry.register(Box::new(Zstd));
+        registry.register(Box::new(Deflate));
+        registry
+    }
+
+    /// Add a codec, replacing any codec with the same id
+    pub fn register(&mut self, codec: Box<dyn Codec>) {
+        self.codecs.retain(|existing| existing.id() != codec.id());
+        self.codecs.push(codec);
+    }
+
+    pub fn get(&self, id: u8) -> Result<&dyn Codec> {
+        match self.codecs.iter().find(|codec| codec.id() == id) {
+            Some(codec) => Ok(codec.as_ref()),
+            None => bail!(
+                "Unsupported compression codec id {}. This host supports: {}",
+                id,
+                self.names().join(", ")
+            ),
+        }
+    }
+
+    /// Names of the registered codecs
+    pub fn names(&self) -> Vec<&'static str> {
+        self.codecs.iter().map(|codec| codec.name()).collect()
+    }
+
+    /// Decompress a section stored with codec `id`, checking its size
+    pub fn decompress(&self, id: u8, data: &[u8], raw_len: usize) -> Result<Vec<u8>> {
+        let codec = self.get(id)?;
+        let raw = codec
+            .decompress(data, raw_len)
+            .with_context(|| format!("Failed to decompress {} section", codec.name()))?;
+        if raw.len() != raw_len {
+            bail!(
+                "Decompressed {} section is {} bytes, expected {}",
+                codec.name(),
+                raw.len(),
+                raw_len
+            );
+        }
+        Ok(raw)
+    }
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_codec_round_trips() {
+        let registry = CodecRegistry::new();
+        let data = b"pixel pixel pixel pixel package".repeat(50);
+        for kind in [CodecKind::None, CodecKind::Zstd, CodecKind::Deflate] {
+            let codec = registry.get(kind.id()).unwrap();
+            let compressed = codec.compress(&data).unwrap();
+            assert_eq!(
+                registry
+                    .decompress(kind.id(), &compressed, data.len())
+                    .unwrap(),
+                data
+            );
+        }
+        assert!(registry.get(200).is_err());
+    }
+}
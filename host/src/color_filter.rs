@@ -0,0 +1,166 @@
+//! Color Vision Deficiency Simulation
+//!
+//! Host-side filters that approximate how a frame looks to viewers with
+//! protanopia, deuteranopia or tritanopia, so app authors can check their
+//! palettes for accessibility. Uses the Machado et al. (2009) matrices at full
+//! severity, applied in linear RGB.
+
+use clap::ValueEnum;
+use std::fmt;
+
+/// Resolution of the linear-to-sRGB lookup table
+const LINEAR_STEPS: usize = 4096;
+
+/// A simulated color vision deficiency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Deficiency {
+    /// No red cones
+    Protanopia,
+    /// No green cones
+    Deuteranopia,
+    /// No blue cones
+    Tritanopia,
+}
+
+impl Deficiency {
+    /// The filter after `current` when cycling through them at runtime
+    pub fn cycle(current: Option<Self>) -> Option<Self> {
+        match current {
+            None => Some(Deficiency::Protanopia),
+            Some(Deficiency::Protanopia) => Some(Deficiency::Deuteranopia),
+            Some(Deficiency::Deuteranopia) => Some(Deficiency::Tritanopia),
+            Some(Deficiency::Tritanopia) => None,
+        }
+    }
+
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Deficiency::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Deficiency::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Deficiency::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Deficiency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Deficiency::Protanopia => "Protanopia",
+            Deficiency::Deuteranopia => "Deuteranopia",
+            Deficiency::Tritanopia => "Tritanopia",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Applies a color vision deficiency simulation to RGBA frames
+pub struct ColorFilter {
+    deficiency: Deficiency,
+    matrix: [[f32; 3]; 3],
+    /// sRGB byte to linear intensity
+    to_linear: [f32; 256],
+    /// Linear intensity (quantized) to sRGB byte
+    to_srgb: Vec<u8>,
+    /// Reusable output buffer
+    output: Vec<u8>,
+}
+
+impl ColorFilter {
+    pub fn new(deficiency: Deficiency) -> Self {
+        let mut to_linear = [0.0; 256];
+        for (i, value) in to_linear.iter_mut().enumerate() {
+            *value = srgb_to_linear(i as f32 / 255.0);
+        }
+        let to_srgb = (0..LINEAR_STEPS)
+            .map(|i| {
+                let linear = i as f32 / (LINEAR_STEPS - 1) as f32;
+                (linear_to_srgb(linear) * 255.0).round() as u8
+            })
+            .collect();
+
+        Self {
+            deficiency,
+            matrix: deficiency.matrix(),
+            to_linear,
+            to_srgb,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn deficiency(&self) -> Deficiency {
+        self.deficiency
+    }
+
+    /// Return the simulated version of an RGBA frame
+    pub fn apply(&mut self, pixels: &[u8]) -> &[u8] {
+        self.output.resize(pixels.len(), 0);
+        for (pixel, out) in pixels.chunks_exact(4).zip(self.output.chunks_exact_mut(4)) {
+            let rgb = [
+                self.to_linear[pixel[0] as usize],
+                self.to_linear[pixel[1] as usize],
+                self.to_linear[pixel[2] as usize],
+            ];
+            for (channel, row) in self.matrix.iter().enumerate() {
+                let linear = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+                let index = (linear.clamp(0.0, 1.0) * (LINEAR_STEPS - 1) as f32).round();
+                out[channel] = self.to_srgb[index as usize];
+            }
+            out[3] = pixel[3];
+        }
+        &self.output
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grays_are_preserved() {
+        let mut filter = ColorFilter::new(Deficiency::Deuteranopia);
+        let pixels = [0, 0, 0, 255, 128, 128, 128, 10, 255, 255, 255, 255];
+        let output = filter.apply(&pixels).to_vec();
+        for (actual, expected) in output.iter().zip(pixels) {
+            assert!(actual.abs_diff(expected) <= 1, "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn test_protanopia_confuses_red_and_green() {
+        let mut filter = ColorFilter::new(Deficiency::Protanopia);
+        // Both primaries collapse to shades of yellow
+        for primary in [[255, 0, 0, 255], [0, 255, 0, 255]] {
+            let output = filter.apply(&primary).to_vec();
+            assert!(output[0].abs_diff(output[1]) < 32, "{:?}", output);
+            assert!(output[2] < 8, "{:?}", output);
+        }
+    }
+}
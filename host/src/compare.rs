@@ -0,0 +1,205 @@
+//! `wapps compare` Command
+//!
+//! Runs two packages, typically two builds of the same app, headlessly and in
+//! lockstep with identical inputs, and reports the first frame where their
+//! output diverges, to check that an optimization did not change behavior.
+//! Both guests get the same `dt` every frame, a disabled clock and the same
+//! random seed; with `--replay`, they instead both replay one recorded
+//! session, events and clock readings included.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::frame_diff::FrameDiff;
+use crate::frame_hash::hash_frame;
+use crate::host_interface::HostInterface;
+use crate::loader;
+use crate::memory_limit;
+use crate::png;
+use crate::recording::Session;
+use crate::runtime::WasmRuntime;
+use crate::wasi_policy::{ClockPolicy, WasiPolicy};
+
+/// Time step passed to `update` when not replaying a session
+const FRAME_DT: f64 = 1.0 / 60.0;
+
+/// Frames compared when not replaying a session
+const DEFAULT_FRAMES: usize = 600;
+
+/// Arguments of `wapps compare`
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Reference package
+    #[arg(value_name = "A")]
+    a: PathBuf,
+
+    /// Package to check against the reference
+    #[arg(value_name = "B")]
+    b: PathBuf,
+
+    /// Number of frames to compare (defaults to 600, or the whole session
+    /// with --replay)
+    #[arg(long, value_name = "N")]
+    frames: Option<usize>,
+
+    /// Feed both packages the inputs of a session recorded with --record
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Random seed given to both packages when not replaying
+    #[arg(long, value_name = "SEED", default_value_t = 0)]
+    random_seed: u64,
+
+    /// Write both frames and a highlight of their differences as PNG files
+    /// into DIR when the outputs diverge
+    #[arg(long, value_name = "DIR")]
+    diff_out: Option<PathBuf>,
+}
+
+/// One of the two packages being compared
+struct Side {
+    path: PathBuf,
+    runtime: WasmRuntime,
+    /// This side's copy of the replayed session, consumed at its own pace
+    session: Option<Session>,
+}
+
+impl Side {
+    fn load(path: &Path, args: &CompareArgs) -> Result<Self> {
+        let (wasm_bytes, metadata) = loader::load_wapp(path)
+            .with_context(|| format!("Failed to load WAPP file: {:?}", path))?;
+        let session = args.replay.as_deref().map(Session::replay).transpose()?;
+        let policy = match session {
+            Some(_) => WasiPolicy::resolve(&metadata.wasi, None, None),
+            None => WasiPolicy {
+                clock: ClockPolicy::Disabled,
+                random_seed: Some(args.random_seed),
+            },
+        };
+
+        let mut host_interface = HostInterface::new();
+        host_interface.set_app_info(metadata.name.clone(), metadata.version.clone());
+        host_interface.set_strings(metadata.strings.clone());
+        host_interface.set_config(metadata.config.clone());
+        let runtime = WasmRuntime::new(
+            &wasm_bytes,
+            None,
+            host_interface,
+            &[metadata.name],
+            session.as_ref(),
+            &policy,
+            false,
+            Some(memory_limit::DEFAULT_MAX_MEMORY),
+        )
+        .with_context(|| format!("Failed to initialize WASM runtime for {:?}", path))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            runtime,
+            session,
+        })
+    }
+
+    /// Hash of the latest frame, if the guest presented one
+    fn frame_hash(&mut self) -> Option<u64> {
+        self.runtime.with_frame_data(|width, height, pixels| {
+            hash_frame(width as u32, height as u32, pixels)
+        })
+    }
+
+    /// Copy of the latest frame
+    fn frame(&mut self) -> Option<(u32, u32, Vec<u8>)> {
+        self.runtime
+            .with_frame_data(|width, height, pixels| (width as u32, height as u32, pixels.to_vec()))
+    }
+}
+
+/// Run `wapps compare`
+pub fn run(args: &CompareArgs) -> Result<()> {
+    let mut a = Side::load(&args.a, args)?;
+    let mut b = Side::load(&args.b, args)?;
+    let limit = args.frames.unwrap_or(match args.replay {
+        Some(_) => usize::MAX,
+        None => DEFAULT_FRAMES,
+    });
+
+    let mut frame = 0;
+    while frame < limit {
+        let (events, dt) = match (&a.session, &b.session) {
+            (Some(session_a), Some(session_b)) => {
+                let (Some(record), Some(_)) = (session_a.next_frame(), session_b.next_frame())
+                else {
+                    break;
+                };
+                (record.events, record.dt)
+            }
+            _ => (Vec::new(), FRAME_DT),
+        };
+
+        let result_a = a.runtime.run_frame(&events, dt);
+        let result_b = b.runtime.run_frame(&events, dt);
+        match (result_a, result_b) {
+            (Ok(()), Ok(())) => {}
+            (Err(e), Ok(())) => bail!("Only {:?} failed at frame {}: {:#}", a.path, frame, e),
+            (Ok(()), Err(e)) => bail!("Only {:?} failed at frame {}: {:#}", b.path, frame, e),
+            (Err(e), Err(_)) => {
+                println!("Both packages failed at frame {}: {:#}", frame, e);
+                return Ok(());
+            }
+        }
+
+        if a.frame_hash() != b.frame_hash() {
+            println!("Outputs diverge at frame {}", frame);
+            if let Some(dir) = &args.diff_out {
+                write_diff(dir, &mut a, &mut b)?;
+            }
+            bail!("{:?} and {:?} diverge at frame {}", a.path, b.path, frame);
+        }
+        frame += 1;
+    }
+
+    println!("Identical output for {} frames", frame);
+    Ok(())
+}
+
+/// Save both diverging frames and, when their sizes match, B's frame with
+/// the pixels that differ from A's highlighted
+fn write_diff(dir: &Path, a: &mut Side, b: &mut Side) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+    let write = |name: &str, width: u32, height: u32, pixels: &[u8]| -> Result<()> {
+        let path = dir.join(name);
+        fs::write(&path, png::encode_rgba(width, height, pixels))
+            .with_context(|| format!("Could not write {}", path.display()))?;
+        info!("Wrote {}", path.display());
+        Ok(())
+    };
+
+    let frame_a = a.frame();
+    let frame_b = b.frame();
+    if let Some((width, height, pixels)) = &frame_a {
+        write("a.png", *width, *height, pixels)?;
+    }
+    if let Some((width, height, pixels)) = &frame_b {
+        write("b.png", *width, *height, pixels)?;
+    }
+    if let (Some((wa, ha, pa)), Some((wb, hb, pb))) = (&frame_a, &frame_b) {
+        if (wa, ha) == (wb, hb) {
+            let mut diff = FrameDiff::new();
+            diff.apply(*wa, *ha, pa);
+            let highlighted = diff.apply(*wb, *hb, pb);
+            write("diff.png", *wb, *hb, highlighted)?;
+            println!(
+                "{} pixels differ ({:.1}%)",
+                diff.changed_pixels(),
+                diff.changed_percent()
+            );
+        } else {
+            println!("Frame sizes differ: {}x{} and {}x{}", wa, ha, wb, hb);
+        }
+    }
+    Ok(())
+}
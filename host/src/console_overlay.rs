@@ -0,0 +1,95 @@
+//! Console Overlay
+//!
+//! Debug view enabled with `--console` or from the Debug menu that shows
+//! the latest lines the app logged via `wapps::log` along the bottom of the
+//! window, colored by level, so guest logs can be followed without a
+//! terminal. Lines are kept whatever `RUST_LOG` filters out of the logger.
+
+use log::Level;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::collections::VecDeque;
+
+use crate::font;
+use crate::inspector::OverlayRect;
+
+/// Lines shown, the oldest scrolling off the top
+const MAX_LINES: usize = 8;
+/// On-screen size of each font pixel
+const TEXT_SCALE: i32 = 2;
+/// Margin around the text, inside the panel and between it and the window
+const PADDING: i32 = 4;
+
+const BACKGROUND: Color = Color::RGB(0, 0, 0);
+
+/// Recent guest log lines drawn over an app's frames
+#[derive(Default)]
+pub struct ConsoleOverlay {
+    lines: VecDeque<(Level, String)>,
+}
+
+impl ConsoleOverlay {
+    /// Add lines the guest logged, oldest first
+    pub fn extend(&mut self, lines: impl IntoIterator<Item = (Level, String)>) {
+        for line in lines {
+            if self.lines.len() == MAX_LINES {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line);
+        }
+    }
+
+    /// Rectangles drawing the lines across the bottom of a window of
+    /// `window_size`, cut to its width; nothing until a line is logged
+    pub fn overlay(&self, window_size: (u32, u32)) -> Vec<OverlayRect> {
+        if self.lines.is_empty() {
+            return Vec::new();
+        }
+        let (window_width, window_height) = (window_size.0 as i32, window_size.1 as i32);
+        let line_height = font::line_height(TEXT_SCALE);
+        let columns = ((window_width - PADDING * 4) / font::advance(TEXT_SCALE)).max(0) as usize;
+        let width = (window_width - PADDING * 2).max(1);
+        let height = self.lines.len() as i32 * line_height + PADDING * 2;
+        let panel_top = window_height - PADDING - height;
+        let mut rects = vec![(
+            Rect::new(PADDING, panel_top, width as u32, height as u32),
+            BACKGROUND,
+        )];
+        for (index, (level, line)) in self.lines.iter().enumerate() {
+            let text: String = line.chars().take(columns).collect();
+            let top = panel_top + PADDING + index as i32 * line_height;
+            let color = color(*level);
+            font::draw_text(&mut rects, &text, PADDING * 2, top, TEXT_SCALE, color);
+        }
+        rects
+    }
+}
+
+fn color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::RGB(255, 96, 96),
+        Level::Warn => Color::RGB(255, 208, 64),
+        Level::Info => Color::RGB(224, 224, 224),
+        Level::Debug | Level::Trace => Color::RGB(128, 160, 255),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latest_lines_are_kept_at_the_bottom() {
+        let mut console = ConsoleOverlay::default();
+        assert!(console.overlay((320, 240)).is_empty());
+
+        console.extend((0..10).map(|i| (Level::Info, format!("LINE {}", i))));
+        assert_eq!(console.lines.len(), MAX_LINES);
+        assert_eq!(console.lines[0].1, "LINE 2");
+
+        let overlay = console.overlay((320, 240));
+        let (panel, background) = overlay[0];
+        assert_eq!(background, BACKGROUND);
+        assert_eq!(panel.bottom(), 240 - PADDING);
+    }
+}
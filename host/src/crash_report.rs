@@ -0,0 +1,256 @@
+//! Crash Reports
+//!
+//! With `--crash-reports DIR`, every guest crash writes a report directory
+//! under DIR and a zip of it that users can attach to bug reports: the error
+//! with the guest backtrace, the host's latest log lines, host and package
+//! details, the session log when one is being recorded or replayed (so the
+//! crash can be reproduced with `--replay`) and, with `--crash-report-memory`,
+//! a snapshot of the guest's linear memory. Nothing is sent anywhere.
+
+use anyhow::{Context, Result};
+use log::{Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::deflate;
+use crate::png::crc32;
+use crate::recording::Session;
+
+/// Log lines kept for crash reports
+const LOG_TAIL_LINES: usize = 500;
+
+/// Latest log lines, oldest first
+static LOG_TAIL: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Logger keeping the latest lines for crash reports before passing them on
+pub struct TailLogger {
+    inner: env_logger::Logger,
+}
+
+impl TailLogger {
+    /// Install `inner` as the global logger, wrapped to keep its latest lines
+    pub fn init(inner: env_logger::Logger) {
+        let max_level = inner.filter();
+        if log::set_boxed_logger(Box::new(Self { inner })).is_ok() {
+            log::set_max_level(max_level);
+        }
+    }
+}
+
+impl Log for TailLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        if let Ok(mut tail) = LOG_TAIL.lock() {
+            if tail.len() == LOG_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(format!(
+                "{} {:<5} {}] {}",
+                unix_time_millis(),
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Where crash reports go and what they include
+#[derive(Debug, Clone)]
+pub struct CrashReporter {
+    dir: PathBuf,
+    include_memory: bool,
+}
+
+/// What is known about a crashed guest
+pub struct Crash<'a> {
+    pub app: &'a str,
+    pub path: &'a Path,
+    pub version: &'a str,
+    pub error: &'a anyhow::Error,
+    pub session: Option<&'a Session>,
+    /// Guest linear memory, if still available
+    pub memory: Option<Vec<u8>>,
+}
+
+impl CrashReporter {
+    pub fn new(dir: PathBuf, include_memory: bool) -> Self {
+        Self {
+            dir,
+            include_memory,
+        }
+    }
+
+    /// Whether reports include a snapshot of guest memory
+    pub fn include_memory(&self) -> bool {
+        self.include_memory
+    }
+
+    /// Write a report directory for `crash` and zip it, returning the zip's path
+    pub fn write(&self, crash: &Crash) -> Result<PathBuf> {
+        let safe: String = crash
+            .app
+            .chars()
+            .take(32)
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let dir = self.dir.join(format!("{}-{}", safe, unix_time_millis()));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+
+        let write = |name: &str, data: &[u8]| {
+            let path = dir.join(name);
+            fs::write(&path, data).with_context(|| format!("Could not write {}", path.display()))
+        };
+        // The debug format includes the error's causes and the guest backtrace
+        write("error.txt", format!("{:?}\n", crash.error).as_bytes())?;
+        let log: Vec<String> = LOG_TAIL
+            .lock()
+            .map(|tail| tail.iter().cloned().collect())
+            .unwrap_or_default();
+        write("log.txt", (log.join("\n") + "\n").as_bytes())?;
+        write("host.txt", host_info(crash).as_bytes())?;
+        if let Some(session) = crash.session {
+            session.save(&dir.join("session.json"))?;
+        }
+        if let Some(memory) = crash.memory.as_deref().filter(|_| self.include_memory) {
+            write("memory.bin", memory)?;
+        }
+
+        let mut files = Vec::new();
+        let mut entries: Vec<_> = fs::read_dir(&dir)
+            .with_context(|| format!("Could not read directory: {}", dir.display()))?
+            .collect::<std::io::Result<_>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            files.push((name, fs::read(entry.path())?));
+        }
+        let zip_path = dir.with_extension("zip");
+        fs::write(&zip_path, zip(&files)?)
+            .with_context(|| format!("Could not write {}", zip_path.display()))?;
+        Ok(zip_path)
+    }
+}
+
+/// Host version, platform, command line and package details
+fn host_info(crash: &Crash) -> String {
+    let args: Vec<String> = std::env::args().collect();
+    format!(
+        "wapps {}\nplatform: {} {}\ncommand: {}\napp: {}\nversion: {}\npackage: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        args.join(" "),
+        crash.app,
+        crash.version,
+        crash.path.display()
+    )
+}
+
+fn unix_time_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+/// Build a zip archive of `(name, contents)` files, each deflated
+fn zip(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    // 1980-01-01 00:00, the earliest DOS date, keeps archives reproducible
+    const DOS_TIME: u16 = 0;
+    const DOS_DATE: u16 = (1 << 5) | 1;
+    const DEFLATED: u16 = 8;
+    const VERSION: u16 = 20;
+
+    let u32_len = |len: usize| u32::try_from(len).context("File too large for a zip archive");
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, data) in files {
+        let compressed = deflate::compress(data);
+        let offset = u32_len(archive.len())?;
+        let mut fields = Vec::new();
+        for value in [VERSION, 0, DEFLATED, DOS_TIME, DOS_DATE] {
+            fields.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [
+            crc32(data),
+            u32_len(compressed.len())?,
+            u32_len(data.len())?,
+        ] {
+            fields.extend_from_slice(&value.to_le_bytes());
+        }
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+
+        archive.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        archive.extend_from_slice(&fields);
+        archive.extend_from_slice(name.as_bytes());
+        archive.extend_from_slice(&compressed);
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&VERSION.to_le_bytes());
+        directory.extend_from_slice(&fields);
+        // Comment length, disk number, internal and external attributes
+        directory.extend_from_slice(&[0; 2 + 2 + 2 + 4]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = u32_len(archive.len())?;
+    archive.extend_from_slice(&directory);
+    archive.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    archive.extend_from_slice(&[0; 4]);
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    archive.extend_from_slice(&u32_len(directory.len())?.to_le_bytes());
+    archive.extend_from_slice(&directory_offset.to_le_bytes());
+    archive.extend_from_slice(&0u16.to_le_bytes());
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip_entries_can_be_read_back() {
+        let files = vec![
+            ("error.txt".to_string(), b"unreachable".to_vec()),
+            ("log.txt".to_string(), b"line\n".repeat(100)),
+        ];
+        let archive = zip(&files).unwrap();
+
+        // The end record points at the central directory
+        let end = &archive[archive.len() - 22..];
+        assert_eq!(end[..4], 0x0605_4b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+        let directory = u32::from_le_bytes(end[16..20].try_into().unwrap()) as usize;
+        assert_eq!(
+            archive[directory..directory + 4],
+            0x0201_4b50u32.to_le_bytes()
+        );
+
+        // The first entry holds its name and deflated contents
+        let read_u32 = |at: usize| u32::from_le_bytes(archive[at..at + 4].try_into().unwrap());
+        let (crc, stored) = (read_u32(14), read_u32(18) as usize);
+        assert_eq!(&archive[30..39], b"error.txt");
+        let data = deflate::decompress(&archive[39..39 + stored], 11).unwrap();
+        assert_eq!(data, b"unreachable");
+        assert_eq!(crc, crc32(b"unreachable"));
+    }
+}
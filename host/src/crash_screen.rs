@@ -0,0 +1,131 @@
+//! Crash Screen
+//!
+//! When a guest traps with `--on-crash overlay`, or `--on-crash restart` gives
+//! up on it, its window shows the error and the guest's call stack instead of
+//! the host exiting. R restarts the app; Q or Escape quits, failing with the
+//! error as `--on-crash exit` does.
+//! Function names come from the module's name section, when the toolchain
+//! kept it.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use wasmtime::WasmBacktrace;
+
+use crate::font;
+use crate::inspector::OverlayRect;
+
+/// On-screen size of each font pixel
+const TEXT_SCALE: i32 = 2;
+/// Margin around the text
+const PADDING: i32 = 8;
+
+const BACKGROUND: Color = Color::RGB(40, 0, 0);
+const TITLE_COLOR: Color = Color::RGB(255, 96, 96);
+const TEXT_COLOR: Color = Color::RGB(230, 230, 230);
+const HINT: &str = "R: restart   Q: quit";
+
+/// The error a crashed app stopped with, as shown in its window
+pub struct CrashScreen {
+    error: anyhow::Error,
+    lines: Vec<String>,
+}
+
+impl CrashScreen {
+    pub fn new(error: anyhow::Error) -> Self {
+        let lines = describe(&error);
+        Self { error, lines }
+    }
+
+    pub fn into_error(self) -> anyhow::Error {
+        self.error
+    }
+
+    /// Rectangles covering a window of `window_size` with the error
+    pub fn overlay(&self, window_size: (u32, u32)) -> Vec<OverlayRect> {
+        let (width, height) = (window_size.0 as i32, window_size.1 as i32);
+        let mut rects = vec![(Rect::new(0, 0, window_size.0, window_size.1), BACKGROUND)];
+        let line_height = font::line_height(TEXT_SCALE);
+        let columns = ((width - PADDING * 2) / font::advance(TEXT_SCALE)).max(1) as usize;
+
+        // The hint stays at the bottom however long the backtrace is
+        let hint_top = height - PADDING - line_height;
+        font::draw_text(&mut rects, HINT, PADDING, hint_top, TEXT_SCALE, TITLE_COLOR);
+
+        let mut top = PADDING;
+        for (index, line) in self.lines.iter().enumerate() {
+            let color = if index == 0 { TITLE_COLOR } else { TEXT_COLOR };
+            let chars: Vec<char> = line.chars().collect();
+            for row in chars.chunks(columns).map(String::from_iter) {
+                if top + line_height * 2 > hint_top {
+                    return rects;
+                }
+                font::draw_text(&mut rects, &row, PADDING, top, TEXT_SCALE, color);
+                top += line_height;
+            }
+            if chars.is_empty() {
+                top += line_height;
+            }
+        }
+        rects
+    }
+}
+
+/// Lines describing `error`: its messages, outermost first, then the guest's
+/// call stack
+fn describe(error: &anyhow::Error) -> Vec<String> {
+    let backtrace = error.downcast_ref::<WasmBacktrace>();
+    let backtrace_message = backtrace.map(ToString::to_string);
+    let mut lines: Vec<String> = error
+        .chain()
+        .map(ToString::to_string)
+        .filter(|message| Some(message) != backtrace_message.as_ref())
+        .flat_map(|message| message.lines().map(str::to_string).collect::<Vec<_>>())
+        .collect();
+
+    if let Some(backtrace) = backtrace {
+        lines.push(String::new());
+        lines.push("Backtrace:".to_string());
+        for (index, frame) in backtrace.frames().iter().enumerate() {
+            let name = match frame.func_name() {
+                Some(name) => name.to_string(),
+                None => format!("function {}", frame.func_index()),
+            };
+            lines.push(format!("{:>3}: {}", index, name));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    #[test]
+    fn test_traps_show_messages_and_backtrace() {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (func $explode unreachable)
+                (func (export "update") (param f64) call $explode))"#,
+        )
+        .unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let update = instance
+            .get_typed_func::<f64, ()>(&mut store, "update")
+            .unwrap();
+        let error = update.call(&mut store, 0.0).unwrap_err();
+
+        let screen = CrashScreen::new(error.context("App \"demo\" crashed"));
+        assert_eq!(screen.lines[0], "App \"demo\" crashed");
+        assert!(screen.lines[1].contains("unreachable"));
+        let backtrace = screen.lines.iter().position(|l| l == "Backtrace:").unwrap();
+        assert_eq!(screen.lines[backtrace + 1], "  0: explode");
+
+        assert_eq!(screen.overlay((320, 240))[0].1, BACKGROUND);
+        // Short windows cut the text, not the hint
+        assert!(screen.overlay((320, 40)).len() < screen.overlay((320, 240)).len());
+    }
+}
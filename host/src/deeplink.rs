@@ -0,0 +1,236 @@
+//! Deep Links
+//!
+//! Handles `wapps://` URLs so web pages can launch an installed WAPP with
+//! context. The URL names a package installed with `wapps install` and its
+//! query parameters are passed to the guest as WASI arguments:
+//! `wapps://life?seed=42` runs the installed `life` package, which sees
+//! `argv = ["<app name>", "seed=42"]`. Any page can open these links, so
+//! they never name a file: paths are rejected rather than run.
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::path::{Path, PathBuf};
+
+use crate::download;
+use crate::registry;
+
+/// URL scheme handled by the host
+pub const SCHEME: &str = "wapps://";
+
+/// A parsed `wapps://` URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeepLink {
+    /// Name of an installed package
+    pub target: String,
+    /// Decoded query parameters, in order
+    pub params: Vec<(String, String)>,
+}
+
+impl DeepLink {
+    /// Parse a `wapps://` URL
+    pub fn parse(url: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix(SCHEME) else {
+            bail!("Not a {} URL: {}", SCHEME, url);
+        };
+
+        // Fragments are never meaningful to the host
+        let rest = rest.split('#').next().unwrap_or("");
+        let (target, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let target = percent_decode(target.trim_end_matches('/'))
+            .with_context(|| format!("Invalid package in URL: {}", url))?;
+        if target.is_empty() {
+            bail!("URL does not name a package: {}", url);
+        }
+        registry::check_name(&target)
+            .with_context(|| format!("Deep links name an installed package: {}", url))?;
+
+        let params = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Ok((percent_decode(key)?, percent_decode(value)?))
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Invalid query in URL: {}", url))?;
+
+        Ok(Self { target, params })
+    }
+
+    /// Resolve the installed package this link refers to
+    pub fn resolve(&self) -> Result<PathBuf> {
+        registry::installed_path(&self.target)
+    }
+
+    /// Query parameters formatted as `key=value` guest arguments
+    pub fn guest_args(&self) -> Vec<String> {
+        self.params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect()
+    }
+}
+
+/// Turn a FILE argument into a package path and guest arguments
+///
+/// HTTP(S) URLs are downloaded, see [`download`]. Plain paths are returned
+/// unchanged with no arguments.
+pub fn resolve_argument(arg: &Path) -> Result<(PathBuf, Vec<String>)> {
+    match arg.to_str() {
+        Some(url) if url.starts_with(SCHEME) => {
+            let link = DeepLink::parse(url)?;
+            info!("Opening deep link to {:?}", link.target);
+            Ok((link.resolve()?, link.guest_args()))
+        }
+        Some(url) if download::is_url(url) => Ok((download::fetch(url)?, Vec::new())),
+        _ => Ok((arg.to_path_buf(), Vec::new())),
+    }
+}
+
+/// Register this executable as the handler for `wapps://` URLs
+#[cfg(target_os = "linux")]
+pub fn register_url_scheme() -> Result<()> {
+    use std::process::Command;
+
+    let exe = std::env::current_exe().context("Could not locate the wapps executable")?;
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .context("Neither XDG_DATA_HOME nor HOME is set")?;
+    let applications = data_home.join("applications");
+    std::fs::create_dir_all(&applications)
+        .with_context(|| format!("Could not create {}", applications.display()))?;
+
+    let desktop_file = applications.join("wapps-url-handler.desktop");
+    let entry = format!(
+        "[Desktop Entry]\n\
+        Type=Application\n\
+        Name=WAPPS\n\
+        Exec=\"{}\" %u\n\
+        MimeType=x-scheme-handler/wapps;\n\
+        NoDisplay=true\n",
+        exe.display()
+    );
+    std::fs::write(&desktop_file, entry)
+        .with_context(|| format!("Could not write {}", desktop_file.display()))?;
+
+    let status = Command::new("xdg-mime")
+        .args([
+            "default",
+            "wapps-url-handler.desktop",
+            "x-scheme-handler/wapps",
+        ])
+        .status()
+        .context("Failed to run xdg-mime")?;
+    if !status.success() {
+        bail!("xdg-mime exited with {}", status);
+    }
+
+    info!("Registered {} URLs with {}", SCHEME, desktop_file.display());
+    Ok(())
+}
+
+/// Register this executable as the handler for `wapps://` URLs
+#[cfg(target_os = "windows")]
+pub fn register_url_scheme() -> Result<()> {
+    use std::process::Command;
+
+    let exe = std::env::current_exe().context("Could not locate the wapps executable")?;
+    let command = format!("\"{}\" \"%1\"", exe.display());
+    let key = r"HKCU\Software\Classes\wapps";
+    let command_key = format!(r"{}\shell\open\command", key);
+
+    let entries: [&[&str]; 3] = [
+        &["add", key, "/ve", "/d", "URL:WAPPS", "/f"],
+        &["add", key, "/v", "URL Protocol", "/d", "", "/f"],
+        &["add", &command_key, "/ve", "/d", &command, "/f"],
+    ];
+    for args in entries {
+        let status = Command::new("reg")
+            .args(args)
+            .status()
+            .context("Failed to run reg")?;
+        if !status.success() {
+            bail!("reg exited with {}", status);
+        }
+    }
+
+    info!("Registered {} URLs in {}", SCHEME, key);
+    Ok(())
+}
+
+/// Register this executable as the handler for `wapps://` URLs
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn register_url_scheme() -> Result<()> {
+    bail!(
+        "Registering the {} scheme is not supported on this platform; \
+        declare it in the application bundle instead",
+        SCHEME
+    )
+}
+
+/// Decode `%XX` escapes and `+` (as space) in a URL component
+fn percent_decode(input: &str) -> Result<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = input
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .with_context(|| format!("Invalid escape at position {}", i))?;
+                decoded.push(hex);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).context("Decoded URL component is not valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_name_and_params() {
+        let link = DeepLink::parse("wapps://life?seed=42&mode=hard+mode").unwrap();
+        assert_eq!(link.target, "life");
+        assert_eq!(link.guest_args(), vec!["seed=42", "mode=hard mode"]);
+    }
+
+    #[test]
+    fn test_parse_rejects_paths() {
+        let link = DeepLink::parse("wapps://life/").unwrap();
+        assert_eq!(link.target, "life");
+        assert!(link.params.is_empty());
+
+        for url in [
+            "wapps:///home/me/life.wapp",
+            "wapps://../life",
+            "wapps://apps/life",
+            "wapps://..%2Flife",
+            "wapps://apps%5Clife",
+            "wapps://C:%5Capps%5Clife.wapp",
+            "wapps://life.wapp",
+        ] {
+            assert!(DeepLink::parse(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_urls() {
+        assert!(DeepLink::parse("https://life").is_err());
+        assert!(DeepLink::parse("wapps://?seed=1").is_err());
+        assert!(DeepLink::parse("wapps://life?seed=%4").is_err());
+    }
+}
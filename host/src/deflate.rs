@@ -0,0 +1,441 @@
+//! Raw DEFLATE (RFC 1951)
+//!
+//! Self-contained encoder and decoder for the `deflate` package codec. Browsers
+//! can inflate these sections natively (`DecompressionStream("deflate-raw")`),
+//! which makes deflate the codec of choice for packages served to the web host.
+//! The encoder emits a single fixed-Huffman block with hash-chain LZ77 matching;
+//! the decoder accepts any valid stream, including dynamic and stored blocks.
+
+use anyhow::{bail, Context, Result};
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// Match candidates examined per position; bounds compression time
+const MAX_CHAIN: usize = 64;
+/// Most bytes reserved up front; the expected size comes from untrusted headers
+const MAX_PREALLOCATION: usize = 16 << 20;
+
+// ============================================================================
+// Decoder
+// ============================================================================
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32> {
+        while self.count < n {
+            let Some(&byte) = self.data.get(self.pos) else {
+                bail!("Unexpected end of deflate stream");
+            };
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u64 << n) - 1) as u32;
+        self.buffer = if n == 32 { 0 } else { self.buffer >> n };
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Discard bits up to the next byte boundary
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time
+struct Huffman {
+    /// Number of codes of each length
+    counts: [u16; 16],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("Invalid Huffman code in deflate stream")
+    }
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let Some(&previous) = lengths.last() else {
+                    bail!("Deflate length repeat with no previous length");
+                };
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        bail!("Deflate code lengths overflow the table");
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+/// Decompress a raw deflate stream, failing as soon as it inflates past
+/// `max_len` bytes
+pub fn decompress(data: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::with_capacity(max_len.min(MAX_PREALLOCATION));
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data
+                    .get(reader.pos..reader.pos + 4)
+                    .context("Unexpected end of deflate stream")?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let nlen = u16::from_le_bytes([header[2], header[3]]) as usize;
+                if len != !nlen & 0xFFFF {
+                    bail!("Corrupted stored deflate block");
+                }
+                let start = reader.pos + 4;
+                let block = data
+                    .get(start..start + len)
+                    .context("Unexpected end of deflate stream")?;
+                if output.len() + len > max_len {
+                    bail!("Deflate stream inflates past {} bytes", max_len);
+                }
+                output.extend_from_slice(block);
+                reader.pos = start + len;
+            }
+            block_type @ (1 | 2) => {
+                let (literals, distances) = if block_type == 1 {
+                    fixed_tables()?
+                } else {
+                    dynamic_tables(&mut reader)?
+                };
+                inflate_block(&mut reader, &literals, &distances, &mut output, max_len)?;
+            }
+            _ => bail!("Invalid deflate block type"),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    literals: &Huffman,
+    distances: &Huffman,
+    output: &mut Vec<u8>,
+    max_len: usize,
+) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 if output.len() < max_len => output.push(symbol as u8),
+            0..=255 => bail!("Deflate stream inflates past {} bytes", max_len),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    bail!("Invalid deflate length code");
+                }
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= DIST_BASE.len() {
+                    bail!("Invalid deflate distance code");
+                }
+                let distance =
+                    DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    bail!("Deflate distance reaches before the start of the output");
+                }
+                if output.len() + length > max_len {
+                    bail!("Deflate stream inflates past {} bytes", max_len);
+                }
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Encoder
+// ============================================================================
+
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, n: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed most significant bit first
+    fn put_code(&mut self, code: u32, n: u32) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
+
+fn put_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.put_code(0x30 + symbol, 8),
+        144..=255 => writer.put_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.put_code(symbol - 256, 7),
+        _ => writer.put_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn put_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1;
+    put_literal(writer, 257 + index as u32);
+    writer.put(
+        (length - LENGTH_BASE[index] as usize) as u32,
+        LENGTH_EXTRA[index] as u32,
+    );
+
+    let index = DIST_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    writer.put_code(index as u32, 5);
+    writer.put(
+        (distance - DIST_BASE[index] as usize) as u32,
+        DIST_EXTRA[index] as u32,
+    );
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let value = u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], 0]);
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Record `pos` as the most recent occurrence of its hash
+fn insert(data: &[u8], pos: usize, head: &mut [u32], prev: &mut [u32]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        prev[pos % WINDOW_SIZE] = head[h];
+        head[h] = pos as u32 + 1;
+    }
+}
+
+/// Compress `data` into a raw deflate stream
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        output: Vec::with_capacity(data.len() / 2),
+        buffer: 0,
+        count: 0,
+    };
+    // Single final block with the fixed Huffman code
+    writer.put(1, 1);
+    writer.put(1, 2);
+
+    // Most recent position + 1 for each hash, and the previous position with the same hash
+    let mut head = vec![0u32; 1 << HASH_BITS];
+    let mut prev = vec![0u32; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(data, pos)] as usize;
+            let mut chain = 0;
+            while candidate > 0 && chain < MAX_CHAIN {
+                let start = candidate - 1;
+                if pos - start > WINDOW_SIZE {
+                    break;
+                }
+                let length = data[start..]
+                    .iter()
+                    .zip(&data[pos..pos + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, pos - start);
+                    if length == max_length {
+                        break;
+                    }
+                }
+                candidate = prev[start % WINDOW_SIZE] as usize;
+                chain += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            put_match(&mut writer, best.0, best.1);
+            for p in pos..pos + best.0 {
+                insert(data, p, &mut head, &mut prev);
+            }
+            pos += best.0;
+        } else {
+            put_literal(&mut writer, data[pos] as u32);
+            insert(data, pos, &mut head, &mut prev);
+            pos += 1;
+        }
+    }
+
+    put_literal(&mut writer, 256);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut data = b"WAPP WAPP WAPP pixels pixels pixels".repeat(100);
+        data.extend((0..5000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8));
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        assert_eq!(decompress(&compress(b""), 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_decodes_stored_and_dynamic_blocks() {
+        // Stored block containing "abc"
+        let stored = [0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        assert_eq!(decompress(&stored, 3).unwrap(), b"abc");
+
+        // Dynamic block produced by zlib at level 9
+        let dynamic = [
+            0xb5, 0xca, 0x51, 0x01, 0x80, 0x20, 0x0c, 0x05, 0xc0, 0x2a, 0x2f, 0x81, 0x69, 0x28,
+            0x30, 0x61, 0x22, 0x82, 0x6c, 0x82, 0x28, 0x9a, 0x5e, 0x4b, 0xf0, 0x7d, 0x67, 0x56,
+            0xc6, 0xd1, 0x82, 0x8d, 0x98, 0x8b, 0xdc, 0x19, 0x8b, 0x74, 0x6c, 0x6d, 0xd7, 0x0a,
+            0xb9, 0xb8, 0xe0, 0xfc, 0x39, 0xd1, 0xfb, 0xc0, 0x89, 0x9f, 0x60, 0x86, 0x65, 0x0d,
+            0x9d, 0x53, 0x05, 0x65, 0x07, 0x25, 0x1b, 0xc9, 0x73, 0xfd, 0x00,
+        ];
+        let mut expected = b"The quick brown fox jumps over the lazy dog. ".repeat(3);
+        expected.extend_from_slice(b"pixels and packages");
+        assert_eq!(decompress(&dynamic, expected.len()).unwrap(), expected);
+
+        assert!(decompress(&[0x07], 0).is_err());
+    }
+
+    #[test]
+    fn test_stops_inflating_past_max_len() {
+        let zeros = vec![0u8; 1 << 20];
+        let compressed = compress(&zeros);
+        assert_eq!(decompress(&compressed, zeros.len()).unwrap(), zeros);
+        assert!(decompress(&compressed, 1000).is_err());
+        // A huge expected size only caps the output, the stream still decodes
+        assert_eq!(decompress(&compressed, usize::MAX).unwrap(), zeros);
+
+        let stored = [0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        assert!(decompress(&stored, 2).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Binary Delta Updates
+//!
+//! `wapps diff` encodes a new package version as a patch against an old one
+//! and `wapps apply` rebuilds the new version from the old package and the
+//! patch, so updates only transfer the bytes that changed. Patches are zstd
+//! frames compressed with the old package as a raw-content dictionary
+//! ("patch-from" mode), framed with the SHA-256 of both versions so a patch
+//! is never applied to the wrong base.
+//!
+//! Patch layout:
+//! - Bytes 0-7: Magic "WAPPDIFF"
+//! - Bytes 8-11: Patch format version (1, u32 LE)
+//! - Bytes 12-43: SHA-256 of the old package
+//! - Bytes 44-75: SHA-256 of the new package
+//! - Bytes 76-83: Length of the new package (u64 LE)
+//! - Bytes 84+: zstd frame
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::info;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use zstd::bulk::{Compressor, Decompressor};
+use zstd::zstd_safe::{CParameter, DParameter};
+
+/// Magic bytes for patch files
+const PATCH_MAGIC: &[u8; 8] = b"WAPPDIFF";
+
+/// Current patch format version
+const PATCH_VERSION: u32 = 1;
+
+/// Size of the fixed patch header
+const PATCH_HEADER_SIZE: usize = 8 + 4 + 32 + 32 + 8;
+
+/// Compression level for patches; they are built once and downloaded many times
+const PATCH_LEVEL: i32 = 19;
+
+/// Largest zstd window, bounding the package sizes patches can reference
+const MAX_WINDOW_LOG: u32 = 31;
+
+/// Arguments of `wapps diff`
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Package version the patch applies to
+    #[arg(value_name = "OLD")]
+    old: PathBuf,
+
+    /// Package version the patch produces
+    #[arg(value_name = "NEW")]
+    new: PathBuf,
+
+    /// Output patch path
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+}
+
+/// Arguments of `wapps apply`
+#[derive(Args, Debug)]
+pub struct ApplyArgs {
+    /// Package version the patch was built against
+    #[arg(value_name = "OLD")]
+    old: PathBuf,
+
+    /// Patch produced by `wapps diff`
+    #[arg(value_name = "PATCH")]
+    patch: PathBuf,
+
+    /// Output path for the updated package
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+}
+
+/// Run `wapps diff`
+pub fn run_diff(args: &DiffArgs) -> Result<()> {
+    let old = read(&args.old)?;
+    let new = read(&args.new)?;
+    let patch = diff(&old, &new)?;
+    fs::write(&args.output, &patch)
+        .with_context(|| format!("Could not write patch: {}", args.output.display()))?;
+    info!(
+        "Wrote {} ({} bytes, {:.1}% of the new package)",
+        args.output.display(),
+        patch.len(),
+        patch.len() as f64 / new.len().max(1) as f64 * 100.0
+    );
+    Ok(())
+}
+
+/// Run `wapps apply`
+pub fn run_apply(args: &ApplyArgs) -> Result<()> {
+    let old = read(&args.old)?;
+    let patch = read(&args.patch)?;
+    let new = apply(&old, &patch)?;
+    fs::write(&args.output, &new)
+        .with_context(|| format!("Could not write package: {}", args.output.display()))?;
+    info!("Wrote {} ({} bytes)", args.output.display(), new.len());
+    Ok(())
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Could not read file: {}", path.display()))
+}
+
+/// Window large enough for matches to reach back across the whole old package
+fn window_log(old_len: usize, new_len: usize) -> Result<u32> {
+    let span = (old_len + new_len).max(1) as u64;
+    let log = 64 - (span - 1).leading_zeros();
+    if log > MAX_WINDOW_LOG {
+        bail!("Packages are too large to diff ({} bytes combined)", span);
+    }
+    Ok(log.max(10))
+}
+
+/// Encode `new` as a patch against `old`
+pub fn diff(old: &[u8], new: &[u8]) -> Result<Vec<u8>> {
+    let mut compressor =
+        Compressor::with_dictionary(PATCH_LEVEL, old).context("Failed to load base package")?;
+    compressor
+        .set_parameter(CParameter::WindowLog(window_log(old.len(), new.len())?))
+        .context("Failed to configure compressor")?;
+    compressor
+        .set_parameter(CParameter::EnableLongDistanceMatching(true))
+        .context("Failed to configure compressor")?;
+    let payload = compressor
+        .compress(new)
+        .context("Failed to compress patch")?;
+
+    let mut patch = Vec::with_capacity(PATCH_HEADER_SIZE + payload.len());
+    patch.extend_from_slice(PATCH_MAGIC);
+    patch.extend_from_slice(&PATCH_VERSION.to_le_bytes());
+    patch.extend_from_slice(&Sha256::digest(old));
+    patch.extend_from_slice(&Sha256::digest(new));
+    patch.extend_from_slice(&(new.len() as u64).to_le_bytes());
+    patch.extend_from_slice(&payload);
+    Ok(patch)
+}
+
+/// Rebuild the new package from `old` and a patch produced by [`diff`]
+pub fn apply(old: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    if patch.len() < PATCH_HEADER_SIZE || &patch[0..8] != PATCH_MAGIC {
+        bail!("Not a WAPP patch file");
+    }
+    let version = u32::from_le_bytes(patch[8..12].try_into().expect("4-byte slice"));
+    if version != PATCH_VERSION {
+        bail!(
+            "Unsupported patch version: {}. This host supports version {} only.",
+            version,
+            PATCH_VERSION
+        );
+    }
+    let old_hash = &patch[12..44];
+    let new_hash = &patch[44..76];
+    let new_len = u64::from_le_bytes(patch[76..84].try_into().expect("8-byte slice"));
+
+    if Sha256::digest(old).as_slice() != old_hash {
+        bail!("Patch was built against a different version of this package");
+    }
+    let new_len = usize::try_from(new_len).context("Patched package too large")?;
+
+    let mut decompressor =
+        Decompressor::with_dictionary(old).context("Failed to load base package")?;
+    decompressor
+        .set_parameter(DParameter::WindowLogMax(MAX_WINDOW_LOG))
+        .context("Failed to configure decompressor")?;
+    let new = decompressor
+        .decompress(&patch[PATCH_HEADER_SIZE..], new_len)
+        .context("Patch is corrupted")?;
+
+    if new.len() != new_len || Sha256::digest(&new).as_slice() != new_hash {
+        bail!("Patched package does not match the expected checksum");
+    }
+    Ok(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(seed: u8, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u32).wrapping_mul(2654435761).to_le_bytes()[seed as usize % 4])
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let old = package(1, 200_000);
+        let mut new = old.clone();
+        new[1000..1100].fill(7);
+        new.extend_from_slice(b"appended");
+
+        let patch = diff(&old, &new).unwrap();
+        assert!(patch.len() < 1000, "patch is {} bytes", patch.len());
+        assert_eq!(apply(&old, &patch).unwrap(), new);
+    }
+
+    #[test]
+    fn test_rejects_wrong_base() {
+        let old = package(1, 10_000);
+        let new = package(2, 10_000);
+        let patch = diff(&old, &new).unwrap();
+        assert!(apply(&new, &patch).is_err());
+        assert!(apply(&old, &patch[..PATCH_HEADER_SIZE - 1]).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Display Settings
+//!
+//! How an app asks for its frames to be shown: the layout constraints it
+//! declares, the size of its window, how its frame is scaled into the window
+//! and what the mouse cursor looks like over it.
+
+use clap::ValueEnum;
+
+/// Display constraints a guest declares for its layout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplayConstraints {
+    /// Required width:height ratio; the frame is letterboxed to keep it
+    pub aspect_ratio: Option<(u32, u32)>,
+    /// Smallest window size the layout supports
+    pub min_size: Option<(u32, u32)>,
+}
+
+/// Largest window side a guest may ask for
+pub const MAX_WINDOW_SIZE: u32 = 16384;
+
+/// Window size set by a guest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowSize {
+    /// Size of the window, or `None` to size it to each new frame size again
+    pub size: Option<(u32, u32)>,
+    /// Whether the user may resize the window
+    pub resizable: bool,
+}
+
+impl WindowSize {
+    /// Convert the arguments of `wapps::set_window_size`; 0x0 follows the frame size
+    pub fn from_raw(width: i32, height: i32, resizable: i32) -> Option<Self> {
+        let size = match (width, height) {
+            (0, 0) => None,
+            (1.., 1..) if width as u32 <= MAX_WINDOW_SIZE && height as u32 <= MAX_WINDOW_SIZE => {
+                Some((width as u32, height as u32))
+            }
+            _ => return None,
+        };
+        Some(Self {
+            size,
+            resizable: resizable != 0,
+        })
+    }
+}
+
+/// How a frame is scaled into the viewport
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ScalingMode {
+    /// Fill the viewport, distorting the frame if its aspect ratio differs
+    #[default]
+    Stretch,
+    /// Largest size with the frame's aspect ratio, letterboxed
+    Fit,
+    /// Largest whole multiple of the frame size, letterboxed, so every frame
+    /// pixel covers the same number of screen pixels
+    Integer,
+}
+
+impl ScalingMode {
+    /// Convert a mode passed to `wapps::set_scaling_mode`
+    pub fn from_raw(mode: i32) -> Option<Self> {
+        match mode {
+            0 => Some(ScalingMode::Stretch),
+            1 => Some(ScalingMode::Fit),
+            2 => Some(ScalingMode::Integer),
+            _ => None,
+        }
+    }
+}
+
+/// Mouse cursor shapes, as passed to `wapps::set_cursor`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CursorShape {
+    #[default]
+    Arrow,
+    /// Text insertion bar
+    IBeam,
+    Crosshair,
+    /// Pointing hand, over links and buttons
+    Hand,
+    /// Four-way arrow, for dragging things around
+    Move,
+    ResizeHorizontal,
+    ResizeVertical,
+    NotAllowed,
+    Wait,
+}
+
+impl CursorShape {
+    /// Convert a shape passed to `wapps::set_cursor`
+    pub fn from_raw(shape: i32) -> Option<Self> {
+        match shape {
+            0 => Some(CursorShape::Arrow),
+            1 => Some(CursorShape::IBeam),
+            2 => Some(CursorShape::Crosshair),
+            3 => Some(CursorShape::Hand),
+            4 => Some(CursorShape::Move),
+            5 => Some(CursorShape::ResizeHorizontal),
+            6 => Some(CursorShape::ResizeVertical),
+            7 => Some(CursorShape::NotAllowed),
+            8 => Some(CursorShape::Wait),
+            _ => None,
+        }
+    }
+}
+
+/// How the mouse behaves over an app's window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CursorSettings {
+    pub hidden: bool,
+    pub shape: CursorShape,
+    /// Capture the mouse, hiding the cursor and reporting only relative
+    /// motion, as first-person games want
+    pub relative: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_sizes() {
+        let fixed = WindowSize::from_raw(640, 480, 0).unwrap();
+        assert_eq!((fixed.size, fixed.resizable), (Some((640, 480)), false));
+        assert_eq!(WindowSize::from_raw(0, 0, 1).unwrap().size, None);
+        assert_eq!(WindowSize::from_raw(640, 0, 1), None);
+        assert_eq!(WindowSize::from_raw(-1, 480, 1), None);
+        assert_eq!(WindowSize::from_raw(16385, 480, 1), None);
+    }
+}
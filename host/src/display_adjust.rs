@@ -0,0 +1,244 @@
+//! Display Adjustment
+//!
+//! Brightness, contrast and gamma applied to frames as they are presented, so
+//! pixel art authored on bright monitors stays readable on dim TVs and
+//! projectors. The viewer sets them with `--brightness`, `--contrast` and
+//! `--gamma`, or at runtime with F9/F10 (Shift for contrast, Alt for gamma).
+//! An app may ask for its own through `wapps::set_display_adjustment`; the
+//! viewer's settings then apply on top of it. Only the window changes: frame
+//! hashes, recordings and the inspector see frames as the guest drew them.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Accepted brightness offsets, in fractions of full intensity
+pub const BRIGHTNESS_RANGE: RangeInclusive<f32> = -1.0..=1.0;
+
+/// Accepted contrast factors, around mid-grey
+pub const CONTRAST_RANGE: RangeInclusive<f32> = 0.0..=4.0;
+
+/// Accepted gamma values
+pub const GAMMA_RANGE: RangeInclusive<f32> = 0.1..=4.0;
+
+/// One of the adjustable settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Brightness,
+    Contrast,
+    Gamma,
+}
+
+impl Control {
+    /// Change of one hotkey press
+    fn step(self) -> f32 {
+        match self {
+            Control::Brightness => 0.05,
+            Control::Contrast | Control::Gamma => 0.1,
+        }
+    }
+}
+
+/// Brightness, contrast and gamma of presented frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustment {
+    /// Offset added to every channel, 0 leaves frames unchanged
+    pub brightness: f32,
+    /// Factor stretching channels away from mid-grey, 1 leaves frames unchanged
+    pub contrast: f32,
+    /// Exponent applied to channels, above 1 lightens midtones
+    pub gamma: f32,
+}
+
+impl Default for Adjustment {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl Adjustment {
+    /// An adjustment, or `None` if a value is out of range
+    pub fn new(brightness: f32, contrast: f32, gamma: f32) -> Option<Self> {
+        (BRIGHTNESS_RANGE.contains(&brightness)
+            && CONTRAST_RANGE.contains(&contrast)
+            && GAMMA_RANGE.contains(&gamma))
+        .then_some(Self {
+            brightness,
+            contrast,
+            gamma,
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// This adjustment with `control` moved by `steps` hotkey presses
+    pub fn nudge(self, control: Control, steps: i32) -> Self {
+        let change = control.step() * steps as f32;
+        // Round to the step so repeated presses land back on the defaults
+        let snap = |value: f32, range: RangeInclusive<f32>| {
+            let value = ((value + change) / control.step()).round() * control.step();
+            value.clamp(*range.start(), *range.end())
+        };
+        let mut adjusted = self;
+        match control {
+            Control::Brightness => adjusted.brightness = snap(self.brightness, BRIGHTNESS_RANGE),
+            Control::Contrast => adjusted.contrast = snap(self.contrast, CONTRAST_RANGE),
+            Control::Gamma => adjusted.gamma = snap(self.gamma, GAMMA_RANGE),
+        }
+        adjusted
+    }
+
+    /// Adjust a channel intensity in `0.0..=1.0`
+    fn map(&self, value: f32) -> f32 {
+        let value = value.powf(1.0 / self.gamma);
+        let value = (value - 0.5) * self.contrast + 0.5 + self.brightness;
+        value.clamp(0.0, 1.0)
+    }
+}
+
+impl fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "brightness {:+.2}, contrast {:.1}, gamma {:.1}",
+            self.brightness, self.contrast, self.gamma
+        )
+    }
+}
+
+/// Parse a `--brightness` value
+pub fn parse_brightness(value: &str) -> Result<f32, String> {
+    parse_in_range(value, BRIGHTNESS_RANGE)
+}
+
+/// Parse a `--contrast` value
+pub fn parse_contrast(value: &str) -> Result<f32, String> {
+    parse_in_range(value, CONTRAST_RANGE)
+}
+
+/// Parse a `--gamma` value
+pub fn parse_gamma(value: &str) -> Result<f32, String> {
+    parse_in_range(value, GAMMA_RANGE)
+}
+
+fn parse_in_range(value: &str, range: RangeInclusive<f32>) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(number) if range.contains(&number) => Ok(number),
+        _ => Err(format!(
+            "invalid value {:?}, expected a number from {} to {}",
+            value,
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+/// Applies the app's and the viewer's adjustments to RGBA frames
+pub struct DisplayAdjuster {
+    /// Adjustment requested by the app
+    app: Adjustment,
+    /// Adjustment chosen by the viewer, applied after the app's
+    viewer: Adjustment,
+    /// Both adjustments combined, per channel byte
+    table: [u8; 256],
+    /// Reusable output buffer
+    output: Vec<u8>,
+}
+
+impl DisplayAdjuster {
+    pub fn new(viewer: Adjustment) -> Self {
+        let mut adjuster = Self {
+            app: Adjustment::default(),
+            viewer,
+            table: [0; 256],
+            output: Vec::new(),
+        };
+        adjuster.rebuild();
+        adjuster
+    }
+
+    pub fn viewer(&self) -> Adjustment {
+        self.viewer
+    }
+
+    pub fn set_viewer(&mut self, viewer: Adjustment) {
+        self.viewer = viewer;
+        self.rebuild();
+    }
+
+    pub fn set_app(&mut self, app: Adjustment) {
+        self.app = app;
+        self.rebuild();
+    }
+
+    /// Whether frames are presented as drawn
+    pub fn is_identity(&self) -> bool {
+        self.app.is_identity() && self.viewer.is_identity()
+    }
+
+    /// Return the adjusted version of an RGBA frame, or the frame itself when
+    /// nothing is adjusted
+    pub fn apply<'a>(&'a mut self, pixels: &'a [u8]) -> &'a [u8] {
+        if self.is_identity() {
+            return pixels;
+        }
+        self.output.resize(pixels.len(), 0);
+        for (pixel, out) in pixels.chunks_exact(4).zip(self.output.chunks_exact_mut(4)) {
+            out[0] = self.table[pixel[0] as usize];
+            out[1] = self.table[pixel[1] as usize];
+            out[2] = self.table[pixel[2] as usize];
+            out[3] = pixel[3];
+        }
+        &self.output
+    }
+
+    fn rebuild(&mut self) {
+        for (i, value) in self.table.iter_mut().enumerate() {
+            let adjusted = self.viewer.map(self.app.map(i as f32 / 255.0));
+            *value = (adjusted * 255.0).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adjustments_map_channels() {
+        let mut adjuster = DisplayAdjuster::new(Adjustment::default());
+        let frame = [0, 64, 128, 77, 255, 255, 255, 255];
+        assert_eq!(adjuster.apply(&frame), frame);
+
+        // Brighter and lighter midtones, alpha untouched
+        adjuster.set_viewer(Adjustment::new(0.1, 1.0, 2.0).unwrap());
+        let adjusted = adjuster.apply(&frame).to_vec();
+        assert_eq!(adjusted[0], 26);
+        assert!(adjusted[1] > 64 && adjusted[2] > 128);
+        assert_eq!(adjusted[3], 77);
+        assert_eq!(&adjusted[4..], &[255; 4]);
+
+        // The app's adjustment applies first
+        adjuster.set_viewer(Adjustment::default());
+        adjuster.set_app(Adjustment::new(0.0, 0.0, 1.0).unwrap());
+        assert_eq!(&adjuster.apply(&frame)[..3], &[128, 128, 128]);
+
+        assert!(Adjustment::new(0.0, 5.0, 1.0).is_none());
+        assert!(parse_gamma("0").is_err());
+        assert_eq!(parse_brightness("-0.5"), Ok(-0.5));
+
+        let nudged = Adjustment::default().nudge(Control::Gamma, 3);
+        assert_eq!(nudged.nudge(Control::Gamma, -3), Adjustment::default());
+        assert_eq!(
+            Adjustment::default()
+                .nudge(Control::Brightness, -100)
+                .brightness,
+            -1.0
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Guest Documents
+//!
+//! Files the user hands to a guest, by picking them in a file dialog it
+//! requested or by dropping them on its window. The guest gets their bytes,
+//! read whole up to `MAX_FILE_SIZE`, and at most their name, never a path.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::host_interface::MAX_FILE_SIZE;
+
+/// Read a file for the guest, refusing files over `MAX_FILE_SIZE`
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    fs::File::open(path)?
+        .take(MAX_FILE_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > MAX_FILE_SIZE {
+        return Err(io::Error::other(format!(
+            "larger than {} bytes",
+            MAX_FILE_SIZE
+        )));
+    }
+    Ok(data)
+}
+
+/// Name of the file at `path` as shown to the guest, without its directory
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_documents_are_read_whole() {
+        let path = std::env::temp_dir().join(format!("wapps-document-{}.txt", std::process::id()));
+        fs::write(&path, b"hello").unwrap();
+        let data = read(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(data.unwrap(), b"hello");
+        assert!(read(&path).is_err());
+        assert_eq!(
+            file_name(&path),
+            format!("wapps-document-{}.txt", std::process::id())
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Remote Packages
+//!
+//! `wapps https://example.com/app.wapp` downloads the package before running
+//! it, so packages can be distributed from any HTTP server. Downloads are
+//! cached under the user cache directory, named by a hash of the URL, with the
+//! server's `ETag`: later runs only download the package again if it changed,
+//! and fall back to the cached copy when the server cannot be reached.
+//! Packages are checked against their `Content-Length` and `MAX_PACKAGE_SIZE`
+//! while they download, so a truncated or oversized download is never run.
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::memory_limit::format_size;
+use crate::storage;
+
+/// Largest package the host downloads
+pub const MAX_PACKAGE_SIZE: u64 = 256 << 20;
+
+/// Time to connect, and the longest the server may go without sending data
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Bytes read at a time, between progress checks
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Whether `arg` is a package URL rather than a path
+pub fn is_url(arg: &str) -> bool {
+    arg.starts_with("https://") || arg.starts_with("http://")
+}
+
+/// Download the package at `url`, or reuse the cached copy if it did not
+/// change, returning the path of the local copy
+pub fn fetch(url: &str) -> Result<PathBuf> {
+    let dir = storage::cache_dir()
+        .context("No cache directory to download packages to")?
+        .join("downloads");
+    let path = dir.join(cache_name(url));
+    let etag_path = path.with_extension("etag");
+    let cached_etag = if path.exists() {
+        fs::read_to_string(&etag_path).ok()
+    } else {
+        None
+    };
+
+    let mut request = agent().get(url);
+    if let Some(etag) = &cached_etag {
+        request = request.set("If-None-Match", etag.trim());
+    }
+    let response = match request.call() {
+        Ok(response) if response.status() == 304 => {
+            info!("Using the cached copy of {}", url);
+            return Ok(path);
+        }
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, _)) => bail!("Server returned {} for {}", status, url),
+        Err(e) if path.exists() => {
+            warn!("Could not reach {} ({}); using the cached copy", url, e);
+            return Ok(path);
+        }
+        Err(e) => return Err(e).with_context(|| format!("Could not download {}", url)),
+    };
+    let etag = response.header("ETag").map(str::to_string);
+    let bytes = read_body(url, response)?;
+
+    store(&path, &bytes)?;
+    match etag {
+        Some(etag) => fs::write(&etag_path, etag)
+            .with_context(|| format!("Could not write {}", etag_path.display()))?,
+        None => {
+            let _ = fs::remove_file(&etag_path);
+        }
+    }
+    info!("Downloaded {} to {}", url, path.display());
+    Ok(path)
+}
+
+/// Download `url` without caching it, e.g. a package index
+pub fn download(url: &str) -> Result<Vec<u8>> {
+    match agent().get(url).call() {
+        Ok(response) => read_body(url, response),
+        Err(ureq::Error::Status(status, _)) => bail!("Server returned {} for {}", status, url),
+        Err(e) => Err(e).with_context(|| format!("Could not download {}", url)),
+    }
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(TIMEOUT)
+        .timeout_read(TIMEOUT)
+        .build()
+}
+
+/// Read the body of the `response` from `url`, logging progress and checking
+/// its length
+fn read_body(url: &str, response: ureq::Response) -> Result<Vec<u8>> {
+    let declared = response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok());
+    if let Some(len) = declared.filter(|&len| len > MAX_PACKAGE_SIZE) {
+        bail!(
+            "{} is {}, over the {} limit for packages",
+            url,
+            format_size(len),
+            format_size(MAX_PACKAGE_SIZE)
+        );
+    }
+    info!(
+        "Downloading {} ({})",
+        url,
+        declared.map_or("unknown size".to_string(), format_size)
+    );
+
+    let mut reader = response.into_reader().take(MAX_PACKAGE_SIZE + 1);
+    let mut bytes = Vec::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut reported = 0;
+    loop {
+        let read = reader
+            .read(&mut chunk)
+            .with_context(|| format!("Download of {} failed", url))?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..read]);
+        if let Some(len) = declared.filter(|&len| len > 0) {
+            let percent = bytes.len() as u64 * 100 / len;
+            if percent >= reported + 10 {
+                reported = percent - percent % 10;
+                info!("Downloaded {}% of {}", reported.min(100), url);
+            }
+        }
+    }
+    check_length(bytes.len() as u64, declared)
+        .with_context(|| format!("Bad download of {}", url))?;
+    Ok(bytes)
+}
+
+/// Cache file name for the package at `url`
+fn cache_name(url: &str) -> String {
+    let hash = Sha256::digest(url.as_bytes());
+    let hex: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}.wapp", hex)
+}
+
+/// Check that `received` bytes match the `declared` length and the size limit
+fn check_length(received: u64, declared: Option<u64>) -> Result<()> {
+    if received > MAX_PACKAGE_SIZE {
+        bail!(
+            "over the {} limit for packages",
+            format_size(MAX_PACKAGE_SIZE)
+        );
+    }
+    match declared {
+        Some(len) if len != received => bail!("received {} of {} bytes", received, len),
+        _ => Ok(()),
+    }
+}
+
+/// Write `bytes` to `path` through a temporary file, so an interrupted
+/// download never leaves a partial package behind
+pub fn store(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+    }
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp, bytes).with_context(|| format!("Could not write {}", temp.display()))?;
+    fs::rename(&temp, path).with_context(|| format!("Could not replace {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls_and_lengths() {
+        assert!(is_url("https://example.com/app.wapp"));
+        assert!(is_url("http://localhost:8000/app.wapp"));
+        assert!(!is_url("apps/app.wapp"));
+        assert!(!is_url("wapps://life"));
+
+        assert_eq!(cache_name("https://a/app.wapp").len(), 37);
+        assert_ne!(
+            cache_name("https://a/app.wapp"),
+            cache_name("https://b/app.wapp")
+        );
+
+        assert!(check_length(10, Some(10)).is_ok());
+        assert!(check_length(10, None).is_ok());
+        assert!(check_length(9, Some(10)).is_err());
+        assert!(check_length(MAX_PACKAGE_SIZE + 1, None).is_err());
+    }
+}
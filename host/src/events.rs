@@ -0,0 +1,368 @@
+//! Guest Events
+//!
+//! Translates SDL2 events into a host-independent representation so they can
+//! be queued per app instance and dispatched to the guest on any thread.
+//! Each event keeps the time the OS reported it, which guests read with
+//! `wapps::event_time` to compute gesture velocities independently of the
+//! frame rate.
+
+#[cfg(feature = "window")]
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Mod,
+    mouse::{MouseButton, MouseWheelDirection},
+};
+use serde::{Deserialize, Serialize};
+
+/// Modifier bits passed to `on_key_down`
+pub const MOD_SHIFT: i32 = 1;
+pub const MOD_CTRL: i32 = 2;
+pub const MOD_ALT: i32 = 4;
+pub const MOD_SUPER: i32 = 8;
+
+/// An input event destined for one of the guest's exported callbacks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GuestEvent {
+    /// Window resized (`on_resize`)
+    Resize { width: i32, height: i32 },
+    /// The window's pixels per window unit changed (`on_scale_changed`),
+    /// e.g. after moving to a HiDPI display
+    ScaleChanged { scale: f32 },
+    /// Pointer moved (`on_pointer_move`)
+    ///
+    /// `xrel` and `yrel` are the motion since the previous move, in window
+    /// pixels; with relative mouse mode on they keep coming while the
+    /// position stays put.
+    PointerMove {
+        x: i32,
+        y: i32,
+        #[serde(default)]
+        xrel: i32,
+        #[serde(default)]
+        yrel: i32,
+    },
+    /// Pointer button pressed (`on_pointer_down`)
+    PointerDown { x: i32, y: i32, button: i32 },
+    /// Pointer button released (`on_pointer_up`)
+    PointerUp { x: i32, y: i32, button: i32 },
+    /// Finger touched the screen (`on_touch_down`)
+    ///
+    /// `id` tells apart the fingers touching at once, and stays the same
+    /// until the finger lifts. Positions are in frame pixels, fractional
+    /// since fingers are not pinned to pixels, and `pressure` goes from 0
+    /// to 1. SDL also reports touches as left button pointer events, so
+    /// guests without touch callbacks stay usable.
+    TouchDown {
+        id: i64,
+        x: f32,
+        y: f32,
+        pressure: f32,
+    },
+    /// Touching finger moved (`on_touch_move`)
+    TouchMove {
+        id: i64,
+        x: f32,
+        y: f32,
+        pressure: f32,
+    },
+    /// Finger lifted from the screen (`on_touch_up`)
+    TouchUp {
+        id: i64,
+        x: f32,
+        y: f32,
+        pressure: f32,
+    },
+    /// Wheel or trackpad scrolled (`on_scroll_precise`, else `on_scroll`)
+    ///
+    /// Positive `dy` scrolls up (away from the user) and positive `dx` right,
+    /// whatever the OS "natural scrolling" setting. The integer deltas count
+    /// whole wheel notches; the precise ones include trackpad fractions.
+    Scroll {
+        dx: i32,
+        dy: i32,
+        precise_dx: f32,
+        precise_dy: f32,
+    },
+    /// Key pressed (`on_key_down`)
+    ///
+    /// `modifiers` combines the `MOD_*` bits of the modifier keys held, and
+    /// `repeat` is set for presses generated by holding the key down.
+    KeyDown {
+        scancode: i32,
+        #[serde(default)]
+        modifiers: i32,
+        #[serde(default)]
+        repeat: bool,
+    },
+    /// Key released (`on_key_up`)
+    KeyUp { scancode: i32 },
+    /// Text typed, including text committed by an input method (`on_text_input`)
+    TextInput { text: String },
+    /// Input method composition changed (`on_text_editing`); `cursor` is in characters
+    TextEditing { text: String, cursor: i32 },
+    /// No input for the `--attract-after` period (`on_idle`)
+    Idle,
+    /// The window gained keyboard focus (`on_focus`)
+    Focus,
+    /// The window lost keyboard focus (`on_blur`)
+    Blur,
+    /// The window was minimized or hidden, or shown again (`on_visibility`)
+    Visibility { visible: bool },
+    /// The host keeps dropping frames (`on_performance_warning`): level 1
+    /// drops some, level 2 many, and level 0 means it recovered
+    PerformanceWarning { level: i32 },
+    /// The host changed how often it updates the guest, with `--degrade`
+    /// (`on_perf_hint`): `update` runs every 2^level frames
+    PerfHint { level: i32 },
+    /// The events that follow come from netplay player `index` (`on_player`)
+    Player { index: i32 },
+    /// The guest's memory grew close to the `--max-memory` cap
+    /// (`on_memory_pressure`); sizes are in 64 KiB pages
+    MemoryPressure {
+        current_pages: i32,
+        limit_pages: i32,
+    },
+    /// A message arrived on the guest's WebSocket `handle` (`on_ws_message`)
+    WsMessage { handle: i32, data: Vec<u8> },
+    /// The user chose a file in a dialog the guest asked for, or cancelled
+    /// it with `None` (`on_file_opened`)
+    FileOpened { data: Option<Vec<u8>> },
+    /// A save dialog the guest asked for closed, with a `FILE_SAVED`,
+    /// `FILE_CANCELLED` or `FILE_WRITE_FAILED` status (`on_file_saved`)
+    FileSaved { status: i32 },
+    /// A file was dropped on the window (`on_file_dropped`), given by name
+    /// only
+    FileDropped { name: String, data: Vec<u8> },
+    /// Another app sent a message on the bus under a topic the guest
+    /// subscribed to (`on_bus_message`)
+    BusMessage { topic: String, data: Vec<u8> },
+}
+
+/// A guest event with the time it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub event: GuestEvent,
+    /// Seconds since the host started (millisecond precision)
+    pub time: f64,
+}
+
+#[cfg(feature = "window")]
+impl TimedEvent {
+    /// Convert an SDL event, keeping its timestamp
+    pub fn from_sdl(event: &Event) -> Option<Self> {
+        Some(Self {
+            event: GuestEvent::from_sdl(event)?,
+            time: event.get_timestamp() as f64 / 1000.0,
+        })
+    }
+}
+
+impl GuestEvent {
+    /// Apply `f` to the position of pointer events
+    pub fn map_position(self, f: impl FnOnce(i32, i32) -> (i32, i32)) -> Self {
+        match self {
+            GuestEvent::PointerMove { x, y, xrel, yrel } => {
+                let (x, y) = f(x, y);
+                GuestEvent::PointerMove { x, y, xrel, yrel }
+            }
+            GuestEvent::PointerDown { x, y, button } => {
+                let (x, y) = f(x, y);
+                GuestEvent::PointerDown { x, y, button }
+            }
+            GuestEvent::PointerUp { x, y, button } => {
+                let (x, y) = f(x, y);
+                GuestEvent::PointerUp { x, y, button }
+            }
+            other => other,
+        }
+    }
+
+    /// Apply `f` to the position of touch events
+    pub fn map_touch(self, f: impl FnOnce(f32, f32) -> (f32, f32)) -> Self {
+        match self {
+            GuestEvent::TouchDown { id, x, y, pressure } => {
+                let (x, y) = f(x, y);
+                GuestEvent::TouchDown { id, x, y, pressure }
+            }
+            GuestEvent::TouchMove { id, x, y, pressure } => {
+                let (x, y) = f(x, y);
+                GuestEvent::TouchMove { id, x, y, pressure }
+            }
+            GuestEvent::TouchUp { id, x, y, pressure } => {
+                let (x, y) = f(x, y);
+                GuestEvent::TouchUp { id, x, y, pressure }
+            }
+            other => other,
+        }
+    }
+
+    /// Whether this is a touch event
+    pub fn is_touch(&self) -> bool {
+        matches!(
+            self,
+            GuestEvent::TouchDown { .. }
+                | GuestEvent::TouchMove { .. }
+                | GuestEvent::TouchUp { .. }
+        )
+    }
+
+    /// Whether this comes from someone using the app: keys, text, pointers,
+    /// scrolling and touches
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            GuestEvent::KeyDown { .. }
+                | GuestEvent::KeyUp { .. }
+                | GuestEvent::TextInput { .. }
+                | GuestEvent::PointerMove { .. }
+                | GuestEvent::PointerDown { .. }
+                | GuestEvent::PointerUp { .. }
+                | GuestEvent::Scroll { .. }
+        ) || self.is_touch()
+    }
+}
+
+#[cfg(feature = "window")]
+impl GuestEvent {
+    /// Convert an SDL event into a guest event, if the guest has a callback for it
+    pub fn from_sdl(event: &Event) -> Option<Self> {
+        match *event {
+            Event::Window {
+                win_event: WindowEvent::Resized(width, height),
+                ..
+            } => Some(GuestEvent::Resize { width, height }),
+            Event::Window { win_event, .. } => match win_event {
+                WindowEvent::FocusGained => Some(GuestEvent::Focus),
+                WindowEvent::FocusLost => Some(GuestEvent::Blur),
+                WindowEvent::Minimized | WindowEvent::Hidden => {
+                    Some(GuestEvent::Visibility { visible: false })
+                }
+                WindowEvent::Restored | WindowEvent::Shown => {
+                    Some(GuestEvent::Visibility { visible: true })
+                }
+                _ => None,
+            },
+            Event::MouseMotion {
+                x, y, xrel, yrel, ..
+            } => Some(GuestEvent::PointerMove { x, y, xrel, yrel }),
+            Event::MouseButtonDown {
+                x, y, mouse_btn, ..
+            } => Some(GuestEvent::PointerDown {
+                x,
+                y,
+                button: mouse_button_to_int(mouse_btn),
+            }),
+            Event::MouseButtonUp {
+                x, y, mouse_btn, ..
+            } => Some(GuestEvent::PointerUp {
+                x,
+                y,
+                button: mouse_button_to_int(mouse_btn),
+            }),
+            // Positions are normalized to the window until the app maps them
+            Event::FingerDown {
+                finger_id,
+                x,
+                y,
+                pressure,
+                ..
+            } => Some(GuestEvent::TouchDown {
+                id: finger_id,
+                x,
+                y,
+                pressure,
+            }),
+            Event::FingerMotion {
+                finger_id,
+                x,
+                y,
+                pressure,
+                ..
+            } => Some(GuestEvent::TouchMove {
+                id: finger_id,
+                x,
+                y,
+                pressure,
+            }),
+            Event::FingerUp {
+                finger_id,
+                x,
+                y,
+                pressure,
+                ..
+            } => Some(GuestEvent::TouchUp {
+                id: finger_id,
+                x,
+                y,
+                pressure,
+            }),
+            Event::MouseWheel {
+                x,
+                y,
+                precise_x,
+                precise_y,
+                direction,
+                ..
+            } => {
+                // Flipped deltas follow the fingers; report the wheel's direction
+                let sign = match direction {
+                    MouseWheelDirection::Flipped => -1,
+                    _ => 1,
+                };
+                Some(GuestEvent::Scroll {
+                    dx: x * sign,
+                    dy: y * sign,
+                    precise_dx: precise_x * sign as f32,
+                    precise_dy: precise_y * sign as f32,
+                })
+            }
+            Event::KeyDown {
+                scancode: Some(sc),
+                keymod,
+                repeat,
+                ..
+            } => Some(GuestEvent::KeyDown {
+                scancode: sc as i32,
+                modifiers: modifier_bits(keymod),
+                repeat,
+            }),
+            Event::KeyUp {
+                scancode: Some(sc), ..
+            } => Some(GuestEvent::KeyUp {
+                scancode: sc as i32,
+            }),
+            Event::TextInput { ref text, .. } => Some(GuestEvent::TextInput { text: text.clone() }),
+            Event::TextEditing {
+                ref text, start, ..
+            } => Some(GuestEvent::TextEditing {
+                text: text.clone(),
+                cursor: start,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// `MOD_*` bits of the held modifier keys, either side of the keyboard
+#[cfg(feature = "window")]
+fn modifier_bits(keymod: Mod) -> i32 {
+    [
+        (Mod::LSHIFTMOD | Mod::RSHIFTMOD, MOD_SHIFT),
+        (Mod::LCTRLMOD | Mod::RCTRLMOD, MOD_CTRL),
+        (Mod::LALTMOD | Mod::RALTMOD, MOD_ALT),
+        (Mod::LGUIMOD | Mod::RGUIMOD, MOD_SUPER),
+    ]
+    .into_iter()
+    .filter(|(keys, _)| keymod.intersects(*keys))
+    .fold(0, |bits, (_, bit)| bits | bit)
+}
+
+#[cfg(feature = "window")]
+fn mouse_button_to_int(btn: MouseButton) -> i32 {
+    match btn {
+        MouseButton::Left => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Right => 3,
+        _ => 0,
+    }
+}
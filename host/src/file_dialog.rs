@@ -0,0 +1,51 @@
+//! Guest File Dialogs
+//!
+//! Guests have no filesystem access, but document-based apps can ask the
+//! host for a native dialog with `wapps::request_open_file` and
+//! `wapps::request_save_file`. The user picks the file, and the guest only
+//! gets the bytes of the file chosen for it (`on_file_opened`) or learns
+//! whether its bytes were saved (`on_file_saved`), never a path. Dialogs
+//! block the host until they close, like File > Open in the menu bar.
+
+use log::warn;
+use std::fs;
+
+use crate::documents;
+use crate::events::GuestEvent;
+use crate::host_interface::{FileRequest, FILE_CANCELLED, FILE_SAVED, FILE_WRITE_FAILED};
+
+/// Show the dialog `app_name` asked for and carry out the user's choice,
+/// returning the event reporting it to the guest
+pub fn show(request: FileRequest, app_name: &str) -> GuestEvent {
+    match request {
+        FileRequest::Open { extensions } => {
+            let mut dialog = rfd::FileDialog::new().set_title(format!("Open - {}", app_name));
+            if !extensions.is_empty() {
+                dialog = dialog.add_filter("Documents", &extensions);
+            }
+            let data = dialog.pick_file().and_then(|path| {
+                documents::read(&path)
+                    .map_err(|e| warn!("{}: failed to open {:?}: {}", app_name, path, e))
+                    .ok()
+            });
+            GuestEvent::FileOpened { data }
+        }
+        FileRequest::Save { name, data } => {
+            let path = rfd::FileDialog::new()
+                .set_title(format!("Save - {}", app_name))
+                .set_file_name(name)
+                .save_file();
+            let status = match path {
+                None => FILE_CANCELLED,
+                Some(path) => match fs::write(&path, data) {
+                    Ok(()) => FILE_SAVED,
+                    Err(e) => {
+                        warn!("{}: failed to save {:?}: {}", app_name, path, e);
+                        FILE_WRITE_FAILED
+                    }
+                },
+            };
+            GuestEvent::FileSaved { status }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Overlay Font
+//!
+//! The 3x5 bitmap font of `wapps::draw_text`, for text the host draws over
+//! frames (the pixel inspector's readout, the crash screen). Text is drawn as
+//! filled overlay rectangles, one per horizontal run of lit pixels, in upper
+//! case.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::inspector::OverlayRect;
+use crate::text::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+/// Horizontal distance between the left edges of two characters
+pub fn advance(scale: i32) -> i32 {
+    (GLYPH_WIDTH + 1) * scale
+}
+
+/// Vertical distance between the tops of two lines
+pub fn line_height(scale: i32) -> i32 {
+    (GLYPH_HEIGHT + 1) * scale
+}
+
+/// Append the rectangles drawing `text` at `scale` with its top-left corner
+/// at (`x`, `y`)
+pub fn draw_text(
+    rects: &mut Vec<OverlayRect>,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: i32,
+    color: Color,
+) {
+    for (i, c) in text.chars().enumerate() {
+        let glyph_left = x + i as i32 * advance(scale);
+        for (row, bits) in glyph(c).iter().enumerate() {
+            let top = y + row as i32 * scale;
+            let mut column = 0;
+            while column < GLYPH_WIDTH {
+                let lit = |column: i32| bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0;
+                if !lit(column) {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                while column < GLYPH_WIDTH && lit(column) {
+                    column += 1;
+                }
+                let run = Rect::new(
+                    glyph_left + start * scale,
+                    top,
+                    ((column - start) * scale) as u32,
+                    scale as u32,
+                );
+                rects.push((run, color));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_is_drawn_in_runs() {
+        let white = Color::RGB(255, 255, 255);
+        let mut rects = Vec::new();
+        // Rows of E: a full run, one pixel, a full run, one pixel, a full run
+        draw_text(&mut rects, "e", 10, 20, 2, white);
+        assert_eq!(rects.len(), 5);
+        assert_eq!(rects[0].0, Rect::new(10, 20, 6, 2));
+        assert_eq!(rects[1].0, Rect::new(10, 22, 2, 2));
+
+        // Characters the font lacks are drawn as question marks
+        rects.clear();
+        draw_text(&mut rects, " \u{e9}", 0, 0, 1, white);
+        let mut question = Vec::new();
+        draw_text(&mut question, "?", advance(1), 0, 1, white);
+        assert_eq!(rects, question);
+    }
+}
@@ -0,0 +1,120 @@
+//! Frame Diff Visualization
+//!
+//! Debug view that highlights the pixels a guest changed since its previous
+//! frame. Changed pixels are tinted magenta and unchanged pixels are dimmed to
+//! gray, so unnecessary full redraws stand out immediately.
+
+/// Highlight color blended into changed pixels
+const HIGHLIGHT: [u8; 3] = [255, 0, 255];
+
+/// Produces diff visualizations of consecutive RGBA frames
+pub struct FrameDiff {
+    /// Previous frame as received from the guest
+    previous: Vec<u8>,
+    previous_width: u32,
+    previous_height: u32,
+    /// Reusable output buffer for the visualization
+    output: Vec<u8>,
+    /// Pixels changed in the most recent frame
+    changed_pixels: usize,
+    /// Total pixels in the most recent frame
+    total_pixels: usize,
+}
+
+impl FrameDiff {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            previous_width: 0,
+            previous_height: 0,
+            output: Vec::new(),
+            changed_pixels: 0,
+            total_pixels: 0,
+        }
+    }
+
+    /// Compare `pixels` against the previous frame and return the visualization
+    ///
+    /// A frame with different dimensions than the previous one counts as fully changed.
+    pub fn apply(&mut self, width: u32, height: u32, pixels: &[u8]) -> &[u8] {
+        let comparable = width == self.previous_width
+            && height == self.previous_height
+            && self.previous.len() == pixels.len();
+
+        self.output.resize(pixels.len(), 0);
+        self.changed_pixels = 0;
+        self.total_pixels = pixels.len() / 4;
+
+        for (i, (pixel, out)) in pixels
+            .chunks_exact(4)
+            .zip(self.output.chunks_exact_mut(4))
+            .enumerate()
+        {
+            let changed = !comparable || self.previous[i * 4..i * 4 + 4] != *pixel;
+            if changed {
+                self.changed_pixels += 1;
+                for c in 0..3 {
+                    out[c] = ((pixel[c] as u16 + HIGHLIGHT[c] as u16) / 2) as u8;
+                }
+            } else {
+                let luma =
+                    (pixel[0] as u32 * 77 + pixel[1] as u32 * 150 + pixel[2] as u32 * 29) >> 8;
+                let dimmed = (luma / 4) as u8;
+                out[..3].fill(dimmed);
+            }
+            out[3] = 255;
+        }
+
+        self.previous.clear();
+        self.previous.extend_from_slice(pixels);
+        self.previous_width = width;
+        self.previous_height = height;
+
+        &self.output
+    }
+
+    /// Number of pixels that changed in the most recent frame
+    pub fn changed_pixels(&self) -> usize {
+        self.changed_pixels
+    }
+
+    /// Changed pixels as a percentage of the most recent frame
+    pub fn changed_percent(&self) -> f64 {
+        if self.total_pixels == 0 {
+            0.0
+        } else {
+            self.changed_pixels as f64 / self.total_pixels as f64 * 100.0
+        }
+    }
+}
+
+impl Default for FrameDiff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_changed_pixels() {
+        let mut diff = FrameDiff::new();
+        let first = [10u8, 20, 30, 255, 40, 50, 60, 255];
+        diff.apply(2, 1, &first);
+        assert_eq!(diff.changed_pixels(), 2);
+
+        let mut second = first;
+        second[4] = 41;
+        let output = diff.apply(2, 1, &second).to_vec();
+        assert_eq!(diff.changed_pixels(), 1);
+        assert_eq!(diff.changed_percent(), 50.0);
+        // Unchanged pixel is dimmed gray, changed pixel is tinted
+        assert_eq!(output[0], output[1]);
+        assert_eq!(output[4], ((41u16 + 255) / 2) as u8);
+
+        diff.apply(2, 1, &second);
+        assert_eq!(diff.changed_pixels(), 0);
+    }
+}
@@ -0,0 +1,83 @@
+//! Frame Hashes
+//!
+//! With `--frame-hashes`, the host writes a hash of every frame a guest hands
+//! over, before debug views alter it, so external tools can check that two
+//! runs, or two machines in a lockstep experiment, produce identical frames.
+//! Each line reads `<frame> <hash> <app>`: the frame index counts from 0 per
+//! app, and the hash is the hex xxh3-64 of the frame's size and RGBA bytes.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use xxhash_rust::xxh3::Xxh3;
+
+/// Destination of frame hashes, shared by every app
+#[derive(Clone)]
+pub struct FrameHashLog {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl FrameHashLog {
+    /// Write to `path`, or to stdout if it is `-`
+    pub fn create(path: &Path) -> Result<Self> {
+        let out: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            let file = File::create(path)
+                .with_context(|| format!("Could not create frame hash file: {}", path.display()))?;
+            Box::new(BufWriter::new(file))
+        };
+        Ok(Self {
+            out: Arc::new(Mutex::new(out)),
+        })
+    }
+
+    /// Write the hash of frame `index` of app `name`
+    pub fn write(
+        &self,
+        name: &str,
+        index: u64,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<()> {
+        let line = format!(
+            "{} {:016x} {}",
+            index,
+            hash_frame(width, height, pixels),
+            name
+        );
+        let mut out = self
+            .out
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Frame hash log poisoned"))?;
+        // Flush every line so tools can compare hashes while the apps run
+        writeln!(out, "{}", line)
+            .and_then(|_| out.flush())
+            .context("Failed to write frame hash")
+    }
+}
+
+/// Hash of a frame, covering its size so that reshaped pixels differ
+pub fn hash_frame(width: u32, height: u32, pixels: &[u8]) -> u64 {
+    let mut hasher = Xxh3::new();
+    hasher.update(&width.to_le_bytes());
+    hasher.update(&height.to_le_bytes());
+    hasher.update(pixels);
+    hasher.digest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_covers_size() {
+        let pixels = [7u8; 16];
+        assert_eq!(hash_frame(2, 2, &pixels), hash_frame(2, 2, &pixels));
+        assert_ne!(hash_frame(2, 2, &pixels), hash_frame(4, 1, &pixels));
+        assert_ne!(hash_frame(2, 2, &pixels), hash_frame(2, 2, &[7u8; 15]));
+    }
+}
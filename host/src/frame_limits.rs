@@ -0,0 +1,129 @@
+//! Frame Limits
+//!
+//! A hostile or buggy guest could present absurd frames, such as 100000 x
+//! 100000 pixels, or present thousands of times in one `update`, making the
+//! host allocate huge buffers and upload its textures again and again.
+//! Frames, layers and canvases wider or taller than `--max-frame-size` are
+//! rejected with `wapps_abi::INVALID_DIMENSIONS`, and presents past
+//! `--max-frame-calls` in one update with `wapps_abi::OUT_OF_BOUNDS`; the
+//! frame on screen stays the last one accepted.
+
+use log::warn;
+
+/// Largest frame side when `--max-frame-size` is not given
+pub const DEFAULT_MAX_FRAME_SIDE: u32 = 8192;
+
+/// Most presents per update when `--max-frame-calls` is not given
+pub const DEFAULT_MAX_FRAME_CALLS: u32 = 256;
+
+/// How large and how frequent the frames a guest presents may be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Most frames, layers and canvases presented per update
+    pub max_calls: u32,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self {
+            max_width: DEFAULT_MAX_FRAME_SIDE,
+            max_height: DEFAULT_MAX_FRAME_SIDE,
+            max_calls: DEFAULT_MAX_FRAME_CALLS,
+        }
+    }
+}
+
+/// Counts the presents of the current update against the limits
+#[derive(Debug, Default)]
+pub struct FrameGate {
+    limits: FrameLimits,
+    /// Presents since the update started, rejected ones included
+    calls: u32,
+}
+
+impl FrameGate {
+    pub fn new(limits: FrameLimits) -> Self {
+        Self { limits, calls: 0 }
+    }
+
+    /// Start counting the presents of a new update
+    pub fn start_update(&mut self) {
+        self.calls = 0;
+    }
+
+    /// Count a `width` x `height` present from `import`, returning
+    /// `wapps_abi::OK` or the status rejecting it
+    ///
+    /// Only the first present rejected for being too frequent in an update
+    /// is logged.
+    pub fn admit(&mut self, import: &str, width: u32, height: u32) -> i32 {
+        let limits = self.limits;
+        if width > limits.max_width || height > limits.max_height {
+            warn!(
+                "{}: {}x{} frame over the limit of {}x{}",
+                import, width, height, limits.max_width, limits.max_height
+            );
+            return wapps_abi::INVALID_DIMENSIONS;
+        }
+        self.calls = self.calls.saturating_add(1);
+        if self.calls > limits.max_calls {
+            if Some(self.calls) == limits.max_calls.checked_add(1) {
+                warn!(
+                    "{}: over {} frames presented in one update; rejecting the rest",
+                    import, limits.max_calls
+                );
+            }
+            return wapps_abi::OUT_OF_BOUNDS;
+        }
+        wapps_abi::OK
+    }
+}
+
+/// Parse a `--max-frame-size` value of the form `WIDTHxHEIGHT`
+pub fn parse_frame_size(value: &str) -> Result<(u32, u32), String> {
+    value
+        .split_once(['x', 'X'])
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("invalid frame size {:?}, expected e.g. 1920x1080", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_huge_and_repeated_frames_are_rejected() {
+        let mut gate = FrameGate::new(FrameLimits {
+            max_width: 1920,
+            max_height: 1080,
+            max_calls: 3,
+        });
+        assert_eq!(
+            gate.admit("update_frame", 100_000, 100_000),
+            wapps_abi::INVALID_DIMENSIONS
+        );
+        assert_eq!(
+            gate.admit("update_frame", 1921, 1),
+            wapps_abi::INVALID_DIMENSIONS
+        );
+        assert_eq!(gate.admit("update_frame", 1920, 1080), wapps_abi::OK);
+
+        // Rejected sizes do not use up the update's presents
+        assert_eq!(gate.admit("update_layer", 16, 16), wapps_abi::OK);
+        assert_eq!(gate.admit("update_frame", 16, 16), wapps_abi::OK);
+        for _ in 0..1000 {
+            assert_eq!(gate.admit("update_frame", 16, 16), wapps_abi::OUT_OF_BOUNDS);
+        }
+        gate.start_update();
+        assert_eq!(gate.admit("update_frame", 16, 16), wapps_abi::OK);
+
+        assert_eq!(parse_frame_size("1920x1080"), Ok((1920, 1080)));
+        assert_eq!(parse_frame_size("64X48"), Ok((64, 48)));
+        assert!(parse_frame_size("0x10").is_err());
+        assert!(parse_frame_size("1920").is_err());
+        assert!(parse_frame_size("-1x10").is_err());
+    }
+}
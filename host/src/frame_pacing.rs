@@ -0,0 +1,121 @@
+//! Frame Pacing
+//!
+//! The host loop runs `--fps` frames a second, or as fast as it can with
+//! `--uncapped`. With `--fixed-dt`, apps are updated with a constant dt
+//! instead, as many times each frame as the time elapsed calls for, the way
+//! physics engines step. Apps can also pick their own cadence with
+//! `wapps::request_frame_rate`, e.g. a simulation stepping ten times a
+//! second: they skip frames and get the time of those skipped with their next
+//! update. Either way, the time passed after a stall, such as a debugger
+//! break, is clamped so that apps slow down instead of leaping ahead.
+
+/// Most updates run in one frame to catch up with `--fixed-dt`
+pub const MAX_STEPS: u32 = 8;
+
+/// Share of an interval an update may come early at a requested rate, so
+/// frames jittering around it do not skip whole updates
+const EARLY_SHARE: f64 = 0.1;
+
+/// Parse a `--fps` value
+pub fn parse_fps(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(fps) if fps.is_finite() && fps > 0.0 => Ok(fps),
+        _ => Err(format!(
+            "invalid frame rate {:?}, expected a positive number",
+            value
+        )),
+    }
+}
+
+/// Parse a `--fixed-dt` value, in seconds or as a fraction like `1/120`
+pub fn parse_fixed_dt(value: &str) -> Result<f64, String> {
+    let seconds = match value.trim().split_once('/') {
+        Some((numerator, denominator)) => numerator
+            .trim()
+            .parse::<f64>()
+            .and_then(|n| Ok(n / denominator.trim().parse::<f64>()?)),
+        None => value.trim().parse(),
+    };
+    match seconds {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Ok(seconds),
+        _ => Err(format!(
+            "invalid timestep {:?}, expected seconds like 0.01 or 1/120",
+            value
+        )),
+    }
+}
+
+/// Steps elapsed time in constant increments, with `--fixed-dt`
+#[derive(Debug, Clone)]
+pub struct FixedStep {
+    step: f64,
+    /// Time elapsed that has not been stepped yet
+    accumulated: f64,
+}
+
+impl FixedStep {
+    pub fn new(step: f64) -> Self {
+        Self {
+            step,
+            accumulated: 0.0,
+        }
+    }
+
+    /// The constant dt passed to updates
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Add a frame's `dt`, returning how many steps to run now
+    pub fn advance(&mut self, dt: f64) -> u32 {
+        self.accumulated += dt;
+        // Tolerate rounding, so 1/60 s frames run exactly one 1/60 s step
+        let steps = (self.accumulated / self.step + 1e-9).floor();
+        if steps > MAX_STEPS as f64 {
+            // Too far behind to catch up: drop the backlog
+            self.accumulated = 0.0;
+            return MAX_STEPS;
+        }
+        self.accumulated = (self.accumulated - steps * self.step).max(0.0);
+        steps as u32
+    }
+}
+
+/// The dt to pass an app that asked for updates every `interval` seconds,
+/// `elapsed` seconds after its last one, or `None` if it is not due yet
+pub fn requested_dt(elapsed: f64, interval: f64) -> Option<f64> {
+    (elapsed >= interval * (1.0 - EARLY_SHARE)).then(|| elapsed.min(interval * MAX_STEPS as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_steps_catch_up_with_elapsed_time() {
+        let mut fixed = FixedStep::new(1.0 / 120.0);
+        // A 60 FPS frame runs two steps
+        assert_eq!(fixed.advance(1.0 / 60.0), 2);
+        // Shorter frames carry their remainder over
+        assert_eq!(fixed.advance(0.005), 0);
+        assert_eq!(fixed.advance(0.005), 1);
+        // A stall runs at most MAX_STEPS, then starts over
+        assert_eq!(fixed.advance(2.0), MAX_STEPS);
+        assert_eq!(fixed.advance(0.001), 0);
+
+        let mut frame_rate = FixedStep::new(1.0 / 60.0);
+        assert!((0..100).all(|_| frame_rate.advance(1.0 / 60.0) == 1));
+
+        // Updates at 10 FPS come on the frame closest to their interval
+        assert_eq!(requested_dt(5.0 / 60.0, 0.1), None);
+        assert_eq!(requested_dt(0.099, 0.1), Some(0.099));
+        assert_eq!(requested_dt(3.0, 0.1), Some(0.8));
+
+        assert_eq!(parse_fixed_dt("1/120"), Ok(1.0 / 120.0));
+        assert_eq!(parse_fixed_dt("0.01"), Ok(0.01));
+        assert!(parse_fixed_dt("1/0").is_err());
+        assert!(parse_fixed_dt("fast").is_err());
+        assert_eq!(parse_fps("144"), Ok(144.0));
+        assert!(parse_fps("0").is_err());
+    }
+}
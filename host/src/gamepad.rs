@@ -0,0 +1,80 @@
+//! Gamepad Feedback
+//!
+//! Keeps every connected game controller open and applies the rumble and LED
+//! changes apps request with `wapps::gamepad_rumble` and
+//! `wapps::gamepad_set_led`. Pads are numbered in the order they were
+//! connected, among those still connected. Requests for missing pads, or for
+//! controllers without rumble motors or an LED, are dropped silently.
+
+use anyhow::Result;
+use log::{debug, info};
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+use crate::graphics::GraphicsContext;
+use crate::host_interface::GamepadRequest;
+
+/// The connected game controllers
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    /// Open controllers, in the order they were connected
+    controllers: Vec<GameController>,
+}
+
+impl Gamepads {
+    /// Initialize the game controller subsystem; controllers connected
+    /// already are reported as added by the next events
+    pub fn new(context: &GraphicsContext) -> Result<Self> {
+        Ok(Self {
+            subsystem: context.game_controllers()?,
+            controllers: Vec::new(),
+        })
+    }
+
+    /// Open or close controllers as they are connected and disconnected
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    info!(
+                        "Gamepad {} connected: {}",
+                        self.controllers.len(),
+                        controller.name()
+                    );
+                    self.controllers.push(controller);
+                }
+                Err(e) => debug!("Failed to open game controller {}: {}", which, e),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers
+                    .retain(|controller| controller.instance_id() != which);
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply a request, ignoring it when the pad or its feature is missing
+    pub fn apply(&mut self, request: GamepadRequest) {
+        let result = match request {
+            GamepadRequest::Rumble {
+                pad,
+                low,
+                high,
+                duration_ms,
+            } => match self.controllers.get_mut(pad as usize) {
+                Some(controller) if controller.has_rumble() => {
+                    controller.set_rumble(low, high, duration_ms)
+                }
+                _ => return,
+            },
+            GamepadRequest::Led { pad, r, g, b } => match self.controllers.get_mut(pad as usize) {
+                Some(controller) if controller.has_led() => controller.set_led(r, g, b),
+                _ => return,
+            },
+        };
+        if let Err(e) = result {
+            debug!("Gamepad request {:?} failed: {}", request, e);
+        }
+    }
+}
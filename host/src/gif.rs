@@ -0,0 +1,286 @@
+//! GIF Encoding
+//!
+//! Minimal animated GIF encoder for video recordings, written without an
+//! image library like the PNG encoder. Each frame has its own color table:
+//! the frame's exact colors when it has at most 256 of them, as pixel art
+//! usually does, otherwise a uniform 6x7x6 color cube. Alpha is ignored and
+//! the animation loops forever.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Largest code of the LZW dictionary, which is 12 bits wide
+const MAX_CODE: u16 = 4095;
+
+/// Writes an animated GIF frame by frame
+pub struct GifEncoder<W: Write> {
+    out: W,
+    width: u16,
+    height: u16,
+}
+
+impl<W: Write> GifEncoder<W> {
+    /// Start a `width` x `height` animation on `out`
+    pub fn new(mut out: W, width: u32, height: u32) -> Result<Self> {
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            bail!("{}x{} frames are too large for a GIF", width, height);
+        };
+        let mut header = b"GIF89a".to_vec();
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        // No global color table, background color 0, square pixels
+        header.extend_from_slice(&[0, 0, 0]);
+        // Loop forever
+        header.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+        out.write_all(&header).context("Could not write GIF")?;
+        Ok(Self { out, width, height })
+    }
+
+    /// Append a frame of RGBA `pixels` shown for `delay` hundredths of a second
+    pub fn write_frame(&mut self, pixels: &[u8], delay: u16) -> Result<()> {
+        let (palette, indices) = quantize(pixels);
+        // Color tables hold a power of two of at least two colors
+        let bits = (palette.len().max(2) as u32)
+            .next_power_of_two()
+            .trailing_zeros();
+
+        // Graphic control extension: no disposal, no transparency
+        let mut frame = vec![0x21, 0xf9, 0x04, 0x00];
+        frame.extend_from_slice(&delay.to_le_bytes());
+        frame.extend_from_slice(&[0x00, 0x00]);
+
+        // Image descriptor covering the whole screen, with a local color table
+        frame.push(0x2c);
+        frame.extend_from_slice(&[0, 0, 0, 0]);
+        frame.extend_from_slice(&self.width.to_le_bytes());
+        frame.extend_from_slice(&self.height.to_le_bytes());
+        frame.push(0x80 | (bits as u8 - 1));
+        for index in 0..1 << bits {
+            frame.extend_from_slice(palette.get(index).unwrap_or(&[0; 3]));
+        }
+
+        // LZW data in sub-blocks of at most 255 bytes
+        let min_code_size = bits.max(2) as u8;
+        frame.push(min_code_size);
+        for block in lzw_encode(min_code_size, &indices).chunks(255) {
+            frame.push(block.len() as u8);
+            frame.extend_from_slice(block);
+        }
+        frame.push(0);
+        self.out.write_all(&frame).context("Could not write GIF")
+    }
+
+    /// End the animation, returning the writer
+    pub fn finish(mut self) -> Result<W> {
+        self.out
+            .write_all(&[0x3b])
+            .and_then(|()| self.out.flush())
+            .context("Could not write GIF")?;
+        Ok(self.out)
+    }
+}
+
+/// Color table and per-pixel indices for RGBA `pixels`
+fn quantize(pixels: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(pixels.len() / 4);
+    for pixel in pixels.chunks_exact(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = *lookup.entry(color).or_insert_with(|| {
+            palette.push(color);
+            palette.len() - 1
+        });
+        if palette.len() > 256 {
+            return quantize_to_cube(pixels);
+        }
+        indices.push(index as u8);
+    }
+    (palette, indices)
+}
+
+/// Map RGBA `pixels` to the nearest colors of a 6x7x6 cube (more greens,
+/// which the eye tells apart best)
+fn quantize_to_cube(pixels: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    const LEVELS: [u32; 3] = [6, 7, 6];
+    let level = |value: u8, levels: u32| (value as u32 * (levels - 1) + 127) / 255;
+    let mut palette = Vec::with_capacity(252);
+    for r in 0..LEVELS[0] {
+        for g in 0..LEVELS[1] {
+            for b in 0..LEVELS[2] {
+                let value = |step: u32, levels: u32| (step * 255 / (levels - 1)) as u8;
+                palette.push([
+                    value(r, LEVELS[0]),
+                    value(g, LEVELS[1]),
+                    value(b, LEVELS[2]),
+                ]);
+            }
+        }
+    }
+    let indices = pixels
+        .chunks_exact(4)
+        .map(|pixel| {
+            let r = level(pixel[0], LEVELS[0]);
+            let g = level(pixel[1], LEVELS[1]);
+            let b = level(pixel[2], LEVELS[2]);
+            ((r * LEVELS[1] + g) * LEVELS[2] + b) as u8
+        })
+        .collect();
+    (palette, indices)
+}
+
+/// Packs variable-width codes into bytes, least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Compress color `indices` with GIF's variant of LZW
+fn lzw_encode(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut width = min_code_size as u32 + 1;
+    let mut next = end + 1;
+    writer.write(clear, width);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        writer.write(end, width);
+        return writer.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = dictionary.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        writer.write(prefix, width);
+        if next > MAX_CODE {
+            // The dictionary is full: start over
+            writer.write(clear, width);
+            dictionary.clear();
+            width = min_code_size as u32 + 1;
+            next = end + 1;
+        } else {
+            dictionary.insert((prefix, index), next);
+            next += 1;
+            if next > 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+        prefix = index as u16;
+    }
+    writer.write(prefix, width);
+    // Decoders add an entry for the last code too, which may widen the end code
+    if next + 1 > 1 << width && width < 12 {
+        width += 1;
+    }
+    writer.write(end, width);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decompress GIF LZW data, as decoders do
+    fn lzw_decode(min_code_size: u8, data: &[u8]) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let initial: Vec<Vec<u8>> = (0..clear + 2).map(|i| vec![i as u8]).collect();
+        let mut table = initial.clone();
+        let mut width = min_code_size as usize + 1;
+        let (mut position, mut previous, mut out) = (0, None::<Vec<u8>>, Vec::new());
+        loop {
+            let code = (0..width).fold(0, |code, bit| {
+                let bit_index = position + bit;
+                code | ((data[bit_index / 8] as usize >> (bit_index % 8)) & 1) << bit
+            });
+            position += width;
+            if code == clear {
+                table = initial.clone();
+                width = min_code_size as usize + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [&previous[..], &previous[..1]].concat(),
+                (None, None) => panic!("invalid code {}", code),
+            };
+            out.extend_from_slice(&entry);
+            if let Some(previous) = previous {
+                if table.len() < 4096 {
+                    table.push([&previous[..], &entry[..1]].concat());
+                }
+            }
+            previous = Some(entry);
+            if table.len() >= 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_lzw_round_trips() {
+        let runs: Vec<u8> = (0..5000).map(|i| (i / 7 % 4) as u8).collect();
+        // Enough distinct sequences to fill the dictionary and start over
+        let noise: Vec<u8> = (0..60_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        for (min_code_size, indices) in
+            [(2, &runs[..]), (8, &noise[..]), (2, &[1][..]), (2, &[][..])]
+        {
+            let data = lzw_encode(min_code_size, indices);
+            assert_eq!(lzw_decode(min_code_size, &data), indices);
+        }
+
+        // Few colors are kept exactly; many fall back to the color cube
+        let (palette, indices) = quantize(&[9, 9, 9, 255, 1, 2, 3, 255, 9, 9, 9, 0]);
+        assert_eq!(
+            (palette, indices),
+            (vec![[9, 9, 9], [1, 2, 3]], vec![0, 1, 0])
+        );
+        let gradient: Vec<u8> = (0..300u32)
+            .flat_map(|i| [(i % 256) as u8, (i / 2) as u8, 0, 255])
+            .collect();
+        let (palette, indices) = quantize(&gradient);
+        assert_eq!(palette.len(), 252);
+        assert_eq!(palette[indices[299] as usize], [51, 170, 0]);
+
+        let mut gif = GifEncoder::new(Vec::new(), 2, 1).unwrap();
+        gif.write_frame(&[0, 0, 0, 255, 255, 255, 255, 255], 5)
+            .unwrap();
+        let data = gif.finish().unwrap();
+        assert!(data.starts_with(b"GIF89a\x02\x00\x01\x00"));
+        assert_eq!(data.last(), Some(&0x3b));
+    }
+}
@@ -0,0 +1,493 @@
+//! wgpu Presentation
+//!
+//! The `wgpu` backend draws each window with the GPU through wgpu, on
+//! whichever of Vulkan, Metal, DirectX 12 or OpenGL the system has. Frames,
+//! the guest's HUD and overlay rectangles are all drawn as instanced quads by
+//! one pipeline: the frame and HUD sample their textures, and the overlay a
+//! white pixel tinted by each rectangle's color.
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::video::Window;
+
+use crate::inspector::OverlayRect;
+use crate::presenter::{Presenter, VsyncMode};
+
+const SHADER: &str = r#"
+struct Quad {
+    // Top-left corner and size, in clip space
+    @location(0) dest: vec4<f32>,
+    // Top-left corner and size, in texture coordinates
+    @location(1) source: vec4<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+struct Varyings {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@group(0) @binding(0) var image: texture_2d<f32>;
+@group(0) @binding(1) var image_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, quad: Quad) -> Varyings {
+    var corners = array<vec2<f32>, 6>(
+        vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0),
+        vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+    );
+    let corner = corners[index];
+    var out: Varyings;
+    out.position = vec4(quad.dest.xy + corner * quad.dest.zw, 0.0, 1.0);
+    out.uv = quad.source.xy + corner * quad.source.zw;
+    out.color = quad.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: Varyings) -> @location(0) vec4<f32> {
+    return textureSample(image, image_sampler, in.uv) * in.color;
+}
+"#;
+
+/// Floats per quad: destination, source and color
+const QUAD_FLOATS: usize = 12;
+
+/// Quads the vertex buffer holds at first; it grows with the overlay
+const INITIAL_QUADS: usize = 64;
+
+/// The texture of a frame or HUD and the bind group sampling it
+struct FrameTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    size: (u32, u32),
+}
+
+/// Presents with wgpu
+pub struct GpuPresenter {
+    // Declared first so it is dropped before the window it draws into
+    surface: wgpu::Surface<'static>,
+    window: Window,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Samples a single white pixel, for overlay rectangles
+    solid: wgpu::BindGroup,
+    frame: Option<FrameTexture>,
+    hud: Option<FrameTexture>,
+    quads: wgpu::Buffer,
+}
+
+impl GpuPresenter {
+    pub fn new(window: &Window, vsync: VsyncMode) -> Result<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        // SAFETY: the presenter keeps a handle to the window, so it outlives
+        // the surface, which is dropped first
+        let surface = unsafe {
+            let target = wgpu::SurfaceTargetUnsafe::from_window(window)
+                .context("Failed to get the window's handle")?;
+            instance.create_surface_unsafe(target)
+        }
+        .context("Failed to create wgpu surface")?;
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            compatible_surface: Some(&surface),
+            ..Default::default()
+        }))
+        .context("No GPU adapter can present to this window")?;
+        debug!("Presenting with {:?}", adapter.get_info());
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("wapps"),
+                required_limits:
+                    wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+                ..Default::default()
+            },
+            None,
+        ))
+        .context("Failed to open GPU device")?;
+
+        let (width, height) = window.drawable_size();
+        let mut config = surface
+            .get_default_config(&adapter, width.max(1), height.max(1))
+            .context("The GPU adapter cannot present to this window")?;
+        // Colors pass through unconverted, as with SDL's renderer
+        let capabilities = surface.get_capabilities(&adapter);
+        if let Some(&format) = capabilities.formats.iter().find(|format| !format.is_srgb()) {
+            config.format = format;
+        }
+        config.present_mode = match vsync {
+            VsyncMode::On => wgpu::PresentMode::AutoVsync,
+            VsyncMode::Off => wgpu::PresentMode::AutoNoVsync,
+            VsyncMode::Adaptive
+                if capabilities
+                    .present_modes
+                    .contains(&wgpu::PresentMode::FifoRelaxed) =>
+            {
+                wgpu::PresentMode::FifoRelaxed
+            }
+            VsyncMode::Adaptive => {
+                debug!("Adaptive vsync unavailable, using vsync");
+                wgpu::PresentMode::AutoVsync
+            }
+        };
+        surface.configure(&device, &config);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("image"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("present"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("present"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("present"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: (QUAD_FLOATS * 4) as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x4,
+                        1 => Float32x4,
+                        2 => Float32x4
+                    ],
+                }],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+        // Zoomed pixels stay crisp
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("nearest"),
+            ..Default::default()
+        });
+
+        let (white, solid) = create_image(&device, &bind_group_layout, &sampler, (1, 1));
+        write_image(&queue, &white, (1, 1), &[255; 4]);
+
+        Ok(Self {
+            surface,
+            window: window.clone(),
+            quads: create_quad_buffer(&device, INITIAL_QUADS),
+            device,
+            queue,
+            config,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            solid,
+            frame: None,
+            hud: None,
+        })
+    }
+
+    fn create_texture(&self, size: (u32, u32)) -> FrameTexture {
+        let (texture, bind_group) =
+            create_image(&self.device, &self.bind_group_layout, &self.sampler, size);
+        FrameTexture {
+            texture,
+            bind_group,
+            size,
+        }
+    }
+
+    /// Match the surface to the window's drawable size, returning whether it has any
+    fn fit_surface(&mut self) -> bool {
+        let (width, height) = self.window.drawable_size();
+        if width == 0 || height == 0 {
+            return false;
+        }
+        if (width, height) != (self.config.width, self.config.height) {
+            self.config.width = width;
+            self.config.height = height;
+            self.surface.configure(&self.device, &self.config);
+        }
+        true
+    }
+}
+
+impl Presenter for GpuPresenter {
+    fn upload(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+        if self.frame.as_ref().map(|frame| frame.size) != Some((width, height)) {
+            debug!("Creating new {}x{} frame texture", width, height);
+            self.frame = Some(self.create_texture((width, height)));
+        }
+        if let Some(frame) = &self.frame {
+            write_image(&self.queue, &frame.texture, frame.size, pixels);
+        }
+        Ok(())
+    }
+
+    fn upload_hud(&mut self, hud: Option<(u32, u32, &[u8])>) -> Result<()> {
+        let Some((width, height, pixels)) = hud else {
+            self.hud = None;
+            return Ok(());
+        };
+        if self.hud.as_ref().map(|hud| hud.size) != Some((width, height)) {
+            debug!("Creating new {}x{} HUD texture", width, height);
+            self.hud = Some(self.create_texture((width, height)));
+        }
+        if let Some(hud) = &self.hud {
+            write_image(&self.queue, &hud.texture, hud.size, pixels);
+        }
+        Ok(())
+    }
+
+    fn draw(
+        &mut self,
+        clear: Color,
+        frame: Option<(Rect, Rect)>,
+        overlay: &[OverlayRect],
+    ) -> Result<()> {
+        if !self.fit_surface() {
+            return Ok(());
+        }
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            // Reconfigured for the next frame, this one is skipped
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                self.surface.configure(&self.device, &self.config);
+                return Ok(());
+            }
+            Err(e) => return Err(anyhow!("Failed to get the next surface texture: {}", e)),
+        };
+
+        let window = (self.config.width, self.config.height);
+        let frame = self.frame.as_ref().zip(frame);
+        let mut floats = Vec::with_capacity((overlay.len() + 2) * QUAD_FLOATS);
+        if let Some((texture, (source, dest))) = frame {
+            let (width, height) = (texture.size.0 as f32, texture.size.1 as f32);
+            let source = [
+                source.x() as f32 / width,
+                source.y() as f32 / height,
+                source.width() as f32 / width,
+                source.height() as f32 / height,
+            ];
+            floats.extend(quad(dest, window, source, [1.0; 4]));
+        }
+        if self.hud.is_some() {
+            let whole = Rect::new(0, 0, window.0, window.1);
+            floats.extend(quad(whole, window, [0.0, 0.0, 1.0, 1.0], [1.0; 4]));
+        }
+        for &(rect, color) in overlay {
+            // Drawn opaque, like SDL's renderer draws them
+            let color = [color.r, color.g, color.b].map(|c| c as f32 / 255.0);
+            floats.extend(quad(
+                rect,
+                window,
+                [0.0, 0.0, 1.0, 1.0],
+                [color[0], color[1], color[2], 1.0],
+            ));
+        }
+        let bytes: Vec<u8> = floats.iter().flat_map(|f| f.to_ne_bytes()).collect();
+        if bytes.len() as u64 > self.quads.size() {
+            self.quads = create_quad_buffer(&self.device, (overlay.len() + 2).next_power_of_two());
+        }
+        if !bytes.is_empty() {
+            self.queue.write_buffer(&self.quads, 0, &bytes);
+        }
+
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("present"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: clear.r as f64 / 255.0,
+                            g: clear.g as f64 / 255.0,
+                            b: clear.b as f64 / 255.0,
+                            a: 1.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_vertex_buffer(0, self.quads.slice(..));
+            let mut first = 0;
+            if let Some((texture, _)) = frame {
+                pass.set_bind_group(0, &texture.bind_group, &[]);
+                pass.draw(0..6, 0..1);
+                first = 1;
+            }
+            if let Some(hud) = &self.hud {
+                pass.set_bind_group(0, &hud.bind_group, &[]);
+                pass.draw(0..6, first..first + 1);
+                first += 1;
+            }
+            if !overlay.is_empty() {
+                pass.set_bind_group(0, &self.solid, &[]);
+                pass.draw(0..6, first..first + overlay.len() as u32);
+            }
+        }
+        self.queue.submit([encoder.finish()]);
+        output.present();
+        Ok(())
+    }
+}
+
+/// A texture of `size` with a bind group sampling it
+fn create_image(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    (width, height): (u32, u32),
+) -> (wgpu::Texture, wgpu::BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("image"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("image"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+    (texture, bind_group)
+}
+
+fn write_image(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    (width, height): (u32, u32),
+    pixels: &[u8],
+) {
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        pixels,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(width * 4),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+fn create_quad_buffer(device: &wgpu::Device, quads: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("quads"),
+        size: (quads * QUAD_FLOATS * 4) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Instance data drawing the `source` texture region, in texture
+/// coordinates, tinted by `color` into `dest`, in pixels of a `window`-sized
+/// surface
+fn quad(dest: Rect, window: (u32, u32), source: [f32; 4], color: [f32; 4]) -> [f32; QUAD_FLOATS] {
+    let (width, height) = (window.0 as f32, window.1 as f32);
+    [
+        dest.x() as f32 / width * 2.0 - 1.0,
+        1.0 - dest.y() as f32 / height * 2.0,
+        dest.width() as f32 / width * 2.0,
+        -(dest.height() as f32) / height * 2.0,
+        source[0],
+        source[1],
+        source[2],
+        source[3],
+        color[0],
+        color[1],
+        color[2],
+        color[3],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quads_map_pixels_to_clip_space() {
+        let centered = quad(Rect::new(100, 50, 200, 100), (400, 200), [0.0; 4], [1.0; 4]);
+        // Half the width and height, flipped upwards
+        assert_eq!(centered[..4], [-0.5, 0.5, 1.0, -1.0]);
+        let full = quad(Rect::new(0, 0, 400, 200), (400, 200), [0.0; 4], [1.0; 4]);
+        assert_eq!(full[..4], [-1.0, 1.0, 2.0, -2.0]);
+    }
+}
@@ -1,148 +1,846 @@
 //! Graphics Module
 //!
-//! Handles SDL2 window creation, texture management, and rendering.
-//! Uses streaming textures for efficient pixel buffer updates.
+//! Handles SDL2 window creation and where frames go in each window: scaling,
+//! letterboxing and the debug zoom. Drawing them is left to the window's
+//! presentation backend, see `presenter`. Many guests resend identical
+//! pixels every frame, so frames are hashed and an unchanged one is neither
+//! uploaded nor rendered again.
 
 use anyhow::{Context, Result};
 use log::debug;
 use sdl2::event::Event;
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::render::{Canvas, Texture, TextureCreator};
-use sdl2::video::{Window, WindowContext};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::surface::Surface;
+use sdl2::video::{FullscreenType, Window};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::display::{CursorSettings, CursorShape, DisplayConstraints, ScalingMode, WindowSize};
+use crate::frame_hash::hash_frame;
+use crate::inspector::OverlayRect;
+use crate::orientation::Orientation;
+use crate::presenter::{self, Backend, Presenter, VsyncMode};
+use sdl2::keyboard::Mod;
+use sdl2::mouse::{Cursor, SystemCursor};
+use sdl2::AudioSubsystem;
 use sdl2::EventPump;
+use sdl2::GameControllerSubsystem;
 use sdl2::Sdl;
+use sdl2::VideoSubsystem;
 
-/// Graphics manager handling SDL2 window and rendering
-pub struct Graphics {
-    #[allow(dead_code)]
+/// Largest debug zoom factor
+const MAX_ZOOM: u32 = 64;
+
+/// When SDL was initialized, the origin of event timestamps and present times
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Time since SDL was initialized
+pub fn host_time() -> Duration {
+    EPOCH.get_or_init(Instant::now).elapsed()
+}
+
+/// Color packed as `0xRRGGBBAA`, as passed to `wapps::set_clear_color`
+pub fn unpack_color(rgba: u32) -> Color {
+    let [r, g, b, a] = rgba.to_be_bytes();
+    Color::RGBA(r, g, b, a)
+}
+
+/// Parse a `#rrggbb` or `#rrggbbaa` color
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !matches!(hex.len(), 6 | 8) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("invalid color {:?}, expected #rrggbb", value));
+    }
+    let rgba = u32::from_str_radix(hex, 16).expect("validated hex digits");
+    // Colors without alpha are opaque
+    let rgba = if hex.len() == 6 {
+        (rgba << 8) | 0xff
+    } else {
+        rgba
+    };
+    Ok(unpack_color(rgba))
+}
+
+/// Shared SDL2 state: the video subsystem and the single event pump
+/// from which events for every window are polled
+pub struct GraphicsContext {
     sdl_context: Sdl,
-    canvas: Canvas<Window>,
-    texture_creator: TextureCreator<WindowContext>,
-    texture: Option<Texture<'static>>,
+    video_subsystem: VideoSubsystem,
     event_pump: EventPump,
-    current_width: u32,
-    current_height: u32,
-    needs_render: bool,
+    backend: Backend,
+    orientation: Orientation,
 }
 
-impl Graphics {
-    /// Create a new graphics context with an SDL2 window
-    pub fn new(title: &str, width: u32, height: u32) -> Result<Self> {
+impl GraphicsContext {
+    /// Initialize SDL2 and its video subsystem
+    pub fn new() -> Result<Self> {
         debug!("Initializing SDL2...");
 
+        // Scale frames with nearest-neighbor sampling so zoomed pixels stay crisp
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
+        // Size windows in display-independent units on Windows too, drawing
+        // into more pixels on HiDPI displays like macOS and Wayland do
+        sdl2::hint::set("SDL_WINDOWS_DPI_SCALING", "1");
+
         let sdl_context =
             sdl2::init().map_err(|e| anyhow::anyhow!("Failed to initialize SDL2: {}", e))?;
+        EPOCH.get_or_init(Instant::now);
 
         let video_subsystem = sdl_context
             .video()
             .map_err(|e| anyhow::anyhow!("Failed to initialize video subsystem: {}", e))?;
 
-        debug!("Creating window {}x{}", width, height);
-
-        let window = video_subsystem
-            .window(title, width, height)
-            .position_centered()
-            .resizable()
-            .build()
-            .context("Failed to create window")?;
-
-        let canvas = window
-            .into_canvas()
-            .accelerated()
-            .present_vsync()
-            .build()
-            .context("Failed to create canvas")?;
-
-        let texture_creator = canvas.texture_creator();
+        // Deliver typed text and input method compositions as text events
+        video_subsystem.text_input().start();
 
         let event_pump = sdl_context
             .event_pump()
             .map_err(|e| anyhow::anyhow!("Failed to get event pump: {}", e))?;
 
-        debug!("Graphics initialized successfully");
-
         Ok(Self {
             sdl_context,
-            canvas,
-            // SAFETY: texture_creator lifetime is tied to canvas which we own
-            #[allow(clippy::useless_transmute)]
-            texture_creator: unsafe { std::mem::transmute(texture_creator) },
-            texture: None,
+            video_subsystem,
             event_pump,
-            current_width: width,
-            current_height: height,
-            needs_render: true,
+            backend: Backend::default(),
+            orientation: Orientation::default(),
         })
     }
 
+    /// Render every window created from now on in software rather than
+    /// with the GPU driver
+    pub fn use_software_renderer(&self) {
+        sdl2::hint::set("SDL_RENDER_DRIVER", "software");
+    }
+
+    /// Present every window created from now on with `backend`
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Turn and flip the contents of every window created from now on
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// Initialize the audio subsystem, for apps that play sound
+    pub fn audio(&self) -> Result<AudioSubsystem> {
+        self.sdl_context
+            .audio()
+            .map_err(|e| anyhow::anyhow!("Failed to initialize audio subsystem: {}", e))
+    }
+
+    /// Initialize the game controller subsystem, for gamepad feedback
+    pub fn game_controllers(&self) -> Result<GameControllerSubsystem> {
+        self.sdl_context
+            .game_controller()
+            .map_err(|e| anyhow::anyhow!("Failed to initialize game controller subsystem: {}", e))
+    }
+
+    /// Create a new window with its own canvas
+    ///
+    /// Presenting with vsync blocks until the next refresh, so callers driving
+    /// several windows from one thread should disable it and pace frames themselves.
+    pub fn create_window(
+        &self,
+        title: &str,
+        width: u32,
+        height: u32,
+        vsync: VsyncMode,
+    ) -> Result<Graphics> {
+        Graphics::new(
+            &self.video_subsystem,
+            title,
+            width,
+            height,
+            vsync,
+            self.backend,
+            self.orientation,
+        )
+    }
+
     /// Poll for SDL events
     pub fn poll_events(&mut self) -> Vec<Event> {
         self.event_pump.poll_iter().collect()
     }
 
-    /// Update the texture with new pixel data
+    /// Wait up to `timeout` for an SDL event, then poll for any others
+    pub fn wait_events(&mut self, timeout: Duration) -> Vec<Event> {
+        let ms = timeout.as_millis().min(u32::MAX as u128) as u32;
+        let mut events: Vec<Event> = self.event_pump.wait_event_timeout(ms).into_iter().collect();
+        events.extend(self.event_pump.poll_iter());
+        events
+    }
+
+    /// Hide the mouse cursor over every window
+    pub fn hide_cursor(&self) {
+        self.sdl_context.mouse().show_cursor(false);
+    }
+
+    /// Whether either Ctrl key is currently held
+    pub fn ctrl_held(&self) -> bool {
+        self.sdl_context
+            .keyboard()
+            .mod_state()
+            .intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+    }
+}
+
+/// Debug zoom and pan applied when presenting a frame, invisible to the guest
+#[derive(Debug, Clone, Copy)]
+struct View {
+    /// Magnification factor (1 = whole frame)
+    zoom: u32,
+    /// Frame coordinate shown at the window's top-left corner
+    origin: (f64, f64),
+}
+
+/// Where presented frames go: a window, or files when running headless
+pub trait FrameSink {
+    /// Take the guest's latest frame, `width` x `height` RGBA pixels
+    fn update_frame(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()>;
+
+    /// Present the latest frame, returning whether anything was output
+    fn present(&mut self) -> Result<bool>;
+}
+
+/// Graphics manager handling a single SDL2 window and its rendering
+pub struct Graphics {
+    presenter: Box<dyn Presenter>,
+    window: Window,
+    /// Whether the guest presented a frame yet
+    has_frame: bool,
+    /// Hash of the frame last uploaded, with its size
+    frame_hash: Option<u64>,
+    current_width: u32,
+    current_height: u32,
+    /// Texture pixels per frame pixel, when a post-processing filter
+    /// upscaled the frame
+    texture_scale: u32,
+    /// Whether the window is resized to each new frame size, unless the
+    /// guest sized it itself
+    follow_frame_size: bool,
+    needs_render: bool,
+    /// Debug overlay drawn over the frame
+    overlay: Vec<OverlayRect>,
+    /// Debug zoom and pan
+    view: View,
+    /// Aspect ratio the frame is letterboxed to, if the guest requires one
+    aspect_ratio: Option<(u32, u32)>,
+    /// Color behind the frame, filling the letterbox bars
+    clear_color: Color,
+    /// How the frame is scaled into the viewport
+    scaling: ScalingMode,
+    /// Cursor shown over the window, kept alive while SDL uses it
+    cursor: Option<Cursor>,
+    /// How the window's contents are turned and flipped on screen
+    orientation: Orientation,
+}
+
+impl Graphics {
+    /// Create a new SDL2 window
+    fn new(
+        video_subsystem: &VideoSubsystem,
+        title: &str,
+        width: u32,
+        height: u32,
+        vsync: VsyncMode,
+        backend: Backend,
+        orientation: Orientation,
+    ) -> Result<Self> {
+        debug!("Creating window {}x{}", width, height);
+
+        let mut builder = video_subsystem.window(title, width, height);
+        builder.position_centered().resizable().allow_highdpi();
+        // wgpu needs a Metal layer to draw into on macOS
+        #[cfg(target_os = "macos")]
+        if backend == Backend::Wgpu {
+            builder.metal_view();
+        }
+        let window = builder.build().context("Failed to create window")?;
+        let mut presenter = presenter::create(backend, &window, vsync)?;
+        if !orientation.is_identity() {
+            presenter.set_orientation(orientation)?;
+        }
+
+        debug!("Graphics initialized successfully");
+
+        Ok(Self {
+            presenter,
+            window,
+            has_frame: false,
+            frame_hash: None,
+            current_width: width,
+            current_height: height,
+            texture_scale: 1,
+            follow_frame_size: true,
+            needs_render: true,
+            overlay: Vec::new(),
+            view: View {
+                zoom: 1,
+                origin: (0.0, 0.0),
+            },
+            aspect_ratio: None,
+            clear_color: Color::BLACK,
+            scaling: ScalingMode::default(),
+            cursor: None,
+            orientation,
+        })
+    }
+
+    /// SDL window ID, used to route events to this window
+    pub fn window_id(&self) -> u32 {
+        self.window.id()
+    }
+
+    /// Change the window title
+    pub fn set_title(&mut self, title: &str) {
+        if let Err(e) = self.window.set_title(title) {
+            debug!("Failed to set window title: {}", e);
+        }
+    }
+
+    /// Set the icon shown in the title bar and task switcher to `width` x `height` RGBA pixels
+    pub fn set_icon(&mut self, width: u32, height: u32, pixels: &mut [u8]) {
+        match Surface::from_data(pixels, width, height, width * 4, PixelFormatEnum::RGBA32) {
+            Ok(icon) => self.window.set_icon(icon),
+            Err(e) => debug!("Failed to create window icon: {}", e),
+        }
+    }
+
+    /// Bring the window to the front and give it focus
+    pub fn raise(&mut self) {
+        self.window.raise();
+    }
+
+    /// Make the mouse cursor look and behave as `settings` asks
+    ///
+    /// SDL has a single cursor, so this should be called for the focused
+    /// window only, and again whenever another window gains focus.
+    pub fn apply_cursor(&mut self, settings: CursorSettings) {
+        let mouse = self.window.subsystem().sdl().mouse();
+        let system = match settings.shape {
+            CursorShape::Arrow => SystemCursor::Arrow,
+            CursorShape::IBeam => SystemCursor::IBeam,
+            CursorShape::Crosshair => SystemCursor::Crosshair,
+            CursorShape::Hand => SystemCursor::Hand,
+            CursorShape::Move => SystemCursor::SizeAll,
+            CursorShape::ResizeHorizontal => SystemCursor::SizeWE,
+            CursorShape::ResizeVertical => SystemCursor::SizeNS,
+            CursorShape::NotAllowed => SystemCursor::No,
+            CursorShape::Wait => SystemCursor::Wait,
+        };
+        match Cursor::from_system(system) {
+            Ok(cursor) => {
+                cursor.set();
+                self.cursor = Some(cursor);
+            }
+            Err(e) => debug!("Failed to create {:?} cursor: {}", settings.shape, e),
+        }
+        mouse.show_cursor(!settings.hidden);
+        mouse.set_relative_mouse_mode(settings.relative);
+    }
+
+    /// Switch between a window and borderless fullscreen on the window's display
+    pub fn set_fullscreen(&mut self, fullscreen: bool) -> Result<()> {
+        let mode = if fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Off
+        };
+        self.window
+            .set_fullscreen(mode)
+            .map_err(|e| anyhow::anyhow!("Failed to change fullscreen mode: {}", e))?;
+        self.clamp_view();
+        self.needs_render = true;
+        Ok(())
+    }
+
+    /// Current window size, as laid out: with width and height swapped when
+    /// the contents are turned a quarter
+    ///
+    /// Positions within the window passed to `Graphics` are in this layout,
+    /// see [`window_to_layout`](Self::window_to_layout).
+    pub fn window_size(&self) -> (u32, u32) {
+        self.orientation.layout_size(self.window.size())
+    }
+
+    /// Size of the window's drawable area in pixels, larger than the window
+    /// size on HiDPI displays
+    pub fn drawable_size(&self) -> (u32, u32) {
+        self.orientation.layout_size(self.window.drawable_size())
+    }
+
+    /// Resize the window to show a layout of `width` x `height`
+    fn resize_window(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let (width, height) = self.orientation.layout_size((width, height));
+        self.window
+            .set_size(width, height)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Map a window coordinate, as SDL reports it, to the layout
+    pub fn window_to_layout(&self, x: i32, y: i32) -> (i32, i32) {
+        let (x, y) = self
+            .orientation
+            .to_layout(x as f64 + 0.5, y as f64 + 0.5, self.window.size());
+        (x.floor() as i32, y.floor() as i32)
+    }
+
+    /// Map a touch position normalized to the window to one normalized to
+    /// the layout
+    pub fn touch_to_layout(&self, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = self.orientation.to_layout(x as f64, y as f64, (1, 1));
+        (x as f32, y as f32)
+    }
+
+    /// Map a movement by (`dx`, `dy`) in the window to the layout
+    pub fn delta_to_layout(&self, dx: i32, dy: i32) -> (i32, i32) {
+        self.orientation.delta_to_layout(dx, dy)
+    }
+
+    /// Refresh rate of the window's display in Hz, 0 if SDL cannot tell
+    pub fn refresh_rate(&self) -> i32 {
+        self.window
+            .display_mode()
+            .map_or(0, |mode| mode.refresh_rate.max(0))
+    }
+
+    /// Pixels per window unit: 1 on most displays, 2 on most HiDPI ones
+    pub fn scale_factor(&self) -> f32 {
+        let (width, _) = self.window_size();
+        let (drawable_width, _) = self.drawable_size();
+        if width == 0 || drawable_width == 0 {
+            return 1.0;
+        }
+        drawable_width as f32 / width as f32
+    }
+
+    /// The SDL window, e.g. to attach a native menu bar
+    #[cfg(feature = "menu")]
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    /// Resize the window to `factor` times the frame size
+    #[cfg(feature = "menu")]
+    pub fn set_scale(&mut self, factor: u32) {
+        let (width, height) = (self.current_width * factor, self.current_height * factor);
+        if let Err(e) = self.resize_window(width, height) {
+            debug!("Failed to resize window: {}", e);
+        }
+        self.needs_render = true;
+    }
+
+    /// Apply a window size set by the guest
+    pub fn set_window_size(&mut self, size: WindowSize) {
+        self.window.set_resizable(size.resizable);
+        self.follow_frame_size = size.size.is_none();
+        let (width, height) = size
+            .size
+            .unwrap_or((self.current_width, self.current_height));
+        if let Err(e) = self.resize_window(width, height) {
+            debug!("Failed to resize window: {}", e);
+        }
+        self.clamp_view();
+        self.needs_render = true;
+    }
+
+    /// Apply a guest's display constraints
+    ///
+    /// The minimum size becomes the window's minimum size, growing the window
+    /// if needed; the aspect ratio letterboxes the frame inside the window.
+    pub fn set_constraints(&mut self, constraints: DisplayConstraints) {
+        let (min_w, min_h) = constraints.min_size.unwrap_or((0, 0));
+        let (window_min_w, window_min_h) = self.orientation.layout_size((min_w, min_h));
+        if let Err(e) = self.window.set_minimum_size(window_min_w, window_min_h) {
+            debug!("Failed to set minimum window size: {}", e);
+        }
+        let (win_w, win_h) = self.window_size();
+        if win_w < min_w || win_h < min_h {
+            let _ = self.resize_window(win_w.max(min_w), win_h.max(min_h));
+        }
+        self.aspect_ratio = constraints.aspect_ratio;
+        self.needs_render = true;
+    }
+
+    /// Change the color behind the frame and in the letterbox bars
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.clear_color = color;
+        self.needs_render = true;
+    }
+
+    /// Change how the frame is scaled into the viewport
+    pub fn set_scaling_mode(&mut self, scaling: ScalingMode) {
+        self.scaling = scaling;
+        self.needs_render = true;
+    }
+
+    /// Window region reported to the guest as its size: the whole window, or
+    /// the largest centered rectangle with the required aspect ratio
+    pub fn viewport(&self) -> Rect {
+        let (win_w, win_h) = self.window_size();
+        let Some((ratio_w, ratio_h)) = self.aspect_ratio else {
+            return Rect::new(0, 0, win_w, win_h);
+        };
+        let (ratio_w, ratio_h) = (ratio_w as u64, ratio_h as u64);
+        let (width, height) = if win_w as u64 * ratio_h > win_h as u64 * ratio_w {
+            ((win_h as u64 * ratio_w / ratio_h) as u32, win_h)
+        } else {
+            (win_w, (win_w as u64 * ratio_h / ratio_w) as u32)
+        };
+        Rect::new(
+            ((win_w - width) / 2) as i32,
+            ((win_h - height) / 2) as i32,
+            width.max(1),
+            height.max(1),
+        )
+    }
+
+    /// Window region the frame is drawn in, within the viewport
+    fn frame_rect(&self) -> Rect {
+        let viewport = self.viewport();
+        let (view_w, view_h) = (viewport.width(), viewport.height());
+        let (frame_w, frame_h) = (self.current_width.max(1), self.current_height.max(1));
+        let fit = || {
+            let (frame_w, frame_h) = (frame_w as u64, frame_h as u64);
+            if view_w as u64 * frame_h > view_h as u64 * frame_w {
+                ((view_h as u64 * frame_w / frame_h) as u32, view_h)
+            } else {
+                (view_w, (view_w as u64 * frame_h / frame_w) as u32)
+            }
+        };
+        let (width, height) = match self.scaling {
+            ScalingMode::Stretch => return viewport,
+            ScalingMode::Fit => fit(),
+            ScalingMode::Integer => match (view_w / frame_w).min(view_h / frame_h) {
+                // Frames larger than the viewport can only be shrunk to fit
+                0 => fit(),
+                factor => (frame_w * factor, frame_h * factor),
+            },
+        };
+        Rect::new(
+            viewport.x() + ((view_w - width) / 2) as i32,
+            viewport.y() + ((view_h - height) / 2) as i32,
+            width.max(1),
+            height.max(1),
+        )
+    }
+
+    /// Map a window coordinate to the viewport, scaled so that the frame
+    /// fills it
+    fn window_to_viewport(&self, x: i32, y: i32) -> (i32, i32) {
+        let viewport = self.viewport();
+        let rect = self.frame_rect();
+        let scale = |offset: i32, view: u32, drawn: u32| {
+            (offset as i64 * view as i64 / drawn as i64) as i32
+        };
+        (
+            scale(x - rect.x(), viewport.width(), rect.width()),
+            scale(y - rect.y(), viewport.height(), rect.height()),
+        )
+    }
+
+    /// Map a window coordinate to the framebuffer, as reported to the guest
+    ///
+    /// Positions outside the frame map beyond its edges, so that guests keep
+    /// tracking drags leaving it.
+    pub fn window_to_guest(&self, x: i32, y: i32) -> (i32, i32) {
+        let (frame_x, frame_y) = self
+            .window_to_frame_exact(x as f64, y as f64)
+            .unwrap_or_default();
+        (frame_x.floor() as i32, frame_y.floor() as i32)
+    }
+
+    /// Map a touch position, normalized to the window, to the framebuffer
+    ///
+    /// Like pointer positions, touches outside the frame map beyond its edges.
+    pub fn touch_to_guest(&self, x: f32, y: f32) -> (f32, f32) {
+        let (width, height) = self.window_size();
+        let (x, y) = (x as f64 * width as f64, y as f64 * height as f64);
+        let (frame_x, frame_y) = self.window_to_frame_exact(x, y).unwrap_or_default();
+        (frame_x as f32, frame_y as f32)
+    }
+
+    /// Map a window coordinate to the framebuffer pixel displayed there
+    pub fn window_to_frame(&self, x: i32, y: i32) -> Option<(u32, u32)> {
+        // No frame to map onto yet
+        if !self.has_frame {
+            return None;
+        }
+        let (frame_x, frame_y) = self.window_to_frame_exact(x as f64, y as f64)?;
+        if frame_x < 0.0
+            || frame_y < 0.0
+            || frame_x >= self.current_width as f64
+            || frame_y >= self.current_height as f64
+        {
+            return None;
+        }
+        Some((frame_x as u32, frame_y as u32))
+    }
+
+    /// Frame coordinate shown at a window coordinate, taking letterboxing,
+    /// zoom and pan into account
+    fn window_to_frame_exact(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let (visible_w, visible_h) = self.visible_size();
+        let source = (self.view.origin.0, self.view.origin.1, visible_w, visible_h);
+        Some(window_to_source(x, y, self.frame_rect(), source))
+    }
+
+    /// Size of the frame region visible at the current zoom, in frame pixels
+    fn visible_size(&self) -> (f64, f64) {
+        let zoom = self.view.zoom as f64;
+        (
+            self.current_width as f64 / zoom,
+            self.current_height as f64 / zoom,
+        )
+    }
+
+    /// Whether the debug view is magnified
+    pub fn is_zoomed(&self) -> bool {
+        self.view.zoom > 1
+    }
+
+    /// Zoom in (positive `steps`) or out by powers of two, keeping the frame
+    /// pixel under window coordinate (`x`, `y`) in place
+    pub fn zoom_at(&mut self, x: i32, y: i32, steps: i32) {
+        let Some(anchor) = self.window_to_frame_exact(x as f64, y as f64) else {
+            return;
+        };
+        let zoom = if steps >= 0 {
+            self.view.zoom.saturating_mul(1 << steps.min(6))
+        } else {
+            self.view.zoom >> (-steps).min(6)
+        };
+        let zoom = zoom.clamp(1, MAX_ZOOM);
+        if zoom == self.view.zoom {
+            return;
+        }
+        self.view.zoom = zoom;
+
+        let viewport = self.viewport();
+        let (x, y) = self.window_to_viewport(x, y);
+        let (visible_w, visible_h) = self.visible_size();
+        self.view.origin = (
+            anchor.0 - x as f64 * visible_w / viewport.width() as f64,
+            anchor.1 - y as f64 * visible_h / viewport.height() as f64,
+        );
+        self.clamp_view();
+        debug!("Debug view zoom {}x", zoom);
+    }
+
+    /// Pan the zoomed view by a distance in window pixels
+    pub fn pan_by(&mut self, dx: i32, dy: i32) {
+        if !self.is_zoomed() {
+            return;
+        }
+        let rect = self.frame_rect();
+        let (visible_w, visible_h) = self.visible_size();
+        self.view.origin.0 -= dx as f64 * visible_w / rect.width() as f64;
+        self.view.origin.1 -= dy as f64 * visible_h / rect.height() as f64;
+        self.clamp_view();
+    }
+
+    /// Keep the visible region inside the frame
+    fn clamp_view(&mut self) {
+        let (visible_w, visible_h) = self.visible_size();
+        let max_x = (self.current_width as f64 - visible_w).max(0.0);
+        let max_y = (self.current_height as f64 - visible_h).max(0.0);
+        self.view.origin = (
+            self.view.origin.0.clamp(0.0, max_x),
+            self.view.origin.1.clamp(0.0, max_y),
+        );
+        self.needs_render = true;
+    }
+
+    /// Part of the frame to present: all of it, or its visible region when zoomed,
+    /// in texture pixels
+    fn source_rect(&self) -> Rect {
+        let scale = self.texture_scale;
+        if !self.is_zoomed() {
+            return Rect::new(
+                0,
+                0,
+                self.current_width * scale,
+                self.current_height * scale,
+            );
+        }
+        let (visible_w, visible_h) = self.visible_size();
+        Rect::new(
+            self.view.origin.0 as i32 * scale as i32,
+            self.view.origin.1 as i32 * scale as i32,
+            (visible_w.ceil() as u32).max(1) * scale,
+            (visible_h.ceil() as u32).max(1) * scale,
+        )
+    }
+
+    /// Replace the debug overlay drawn over the frame
+    pub fn set_overlay(&mut self, overlay: Vec<OverlayRect>) {
+        if overlay != self.overlay {
+            self.overlay = overlay;
+            self.needs_render = true;
+        }
+    }
+
+    /// Show a new `width` x `height` frame of RGBA pixels, resizing the
+    /// window to it when its size changes, unless the guest sized the window
     ///
-    /// Reuses the existing texture if dimensions match.
-    /// Pixel format: RGBA (4 bytes per pixel)
+    /// A frame identical to the one shown is skipped, leaving the window as is.
     pub fn update_texture(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
-        // Check if we need to recreate the texture
-        if self.texture.is_none() || width != self.current_width || height != self.current_height {
-            debug!("Creating new texture {}x{}", width, height);
+        self.update_texture_scaled(width, height, 1, pixels)
+    }
+
+    /// Show a new `width` x `height` frame that a post-processing filter
+    /// upscaled by `scale`, so `pixels` is `scale` times as wide and tall
+    ///
+    /// The window, pointer coordinates and zoom follow the frame's own size.
+    pub fn update_texture_scaled(
+        &mut self,
+        width: u32,
+        height: u32,
+        scale: u32,
+        pixels: &[u8],
+    ) -> Result<()> {
+        // The pixels' length tells the scale apart for frames of one size
+        let hash = hash_frame(width, height, pixels);
+        if self.has_frame && self.frame_hash == Some(hash) {
+            return Ok(());
+        }
+        if !self.has_frame || width != self.current_width || height != self.current_height {
             self.current_width = width;
             self.current_height = height;
 
             // Resize window to match content
-            let (win_w, win_h) = self.canvas.window().size();
-            if win_w != width || win_h != height {
-                let _ = self.canvas.window_mut().set_size(width, height);
+            let (win_w, win_h) = self.window_size();
+            if self.follow_frame_size && (win_w != width || win_h != height) {
+                let _ = self.resize_window(width, height);
             }
-
-            // Create new streaming texture
-            let texture = self
-                .texture_creator
-                .create_texture_streaming(PixelFormatEnum::RGBA32, width, height)
-                .context("Failed to create streaming texture")?;
-
-            // SAFETY: texture lifetime is managed manually, texture_creator outlives texture
-            self.texture =
-                Some(unsafe { std::mem::transmute::<Texture<'_>, Texture<'static>>(texture) });
+            self.clamp_view();
         }
 
-        // Update texture with pixel data
-        if let Some(ref mut texture) = self.texture {
-            let pitch = (width * 4) as usize;
-            texture
-                .update(None, pixels, pitch)
-                .map_err(|e| anyhow::anyhow!("Failed to update texture: {}", e))?;
-        }
+        // A failed upload may leave the texture partly written
+        self.frame_hash = None;
+        self.texture_scale = scale;
+        self.presenter
+            .upload(width * scale, height * scale, pixels)?;
+        self.frame_hash = Some(hash);
+        self.has_frame = true;
+        self.needs_render = true;
+        Ok(())
+    }
 
+    /// Replace the HUD the guest draws over the whole window with `width` x
+    /// `height` RGBA pixels, or remove it with `None`
+    pub fn update_hud(&mut self, hud: Option<(u32, u32, &[u8])>) -> Result<()> {
+        self.presenter.upload_hud(hud)?;
         self.needs_render = true;
         Ok(())
     }
 
     /// Render the current frame to screen
-    pub fn render(&mut self) -> Result<()> {
-        if !self.needs_render && self.texture.is_some() {
+    ///
+    /// Returns whether a frame was presented; nothing is drawn when unchanged.
+    pub fn render(&mut self) -> Result<bool> {
+        if !self.needs_render && self.has_frame {
             // No changes, skip render
-            return Ok(());
+            return Ok(false);
         }
 
-        // Clear with black
-        self.canvas
-            .set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
-        self.canvas.clear();
+        // Scaled into the viewport, once there is a frame, turned from the
+        // layout into the window, and from window units to the pixels drawn
+        // on HiDPI displays
+        let window = self.window.size();
+        let (drawable_width, drawable_height) = self.window.drawable_size();
+        let scale = (
+            drawable_width as f64 / window.0.max(1) as f64,
+            drawable_height as f64 / window.1.max(1) as f64,
+        );
+        let orientation = self.orientation;
+        let to_window = |rect| to_drawable(orientation.rect_to_window(rect, window), scale);
+        let frame = self
+            .has_frame
+            .then(|| (self.source_rect(), to_window(self.frame_rect())));
+        let overlay: Vec<OverlayRect> = self
+            .overlay
+            .iter()
+            .map(|&(rect, color)| (to_window(rect), color))
+            .collect();
+        self.presenter.draw(self.clear_color, frame, &overlay)?;
+        self.needs_render = false;
 
-        // Copy texture if available
-        if let Some(ref texture) = self.texture {
-            self.canvas
-                .copy(texture, None, None)
-                .map_err(|e| anyhow::anyhow!("Failed to copy texture: {}", e))?;
-        }
+        Ok(true)
+    }
+}
 
-        // Present
-        self.canvas.present();
-        self.needs_render = false;
+/// Frame coordinate shown at window coordinate (`x`, `y`) when the `source`
+/// region of the frame, as `(x, y, width, height)`, is drawn into the `dest`
+/// region of the window
+///
+/// Mapping in one step keeps positions from being rounded twice, which would
+/// shift them by a frame pixel once the frame is scaled.
+fn window_to_source(x: f64, y: f64, dest: Rect, source: (f64, f64, f64, f64)) -> (f64, f64) {
+    let (source_x, source_y, source_w, source_h) = source;
+    (
+        source_x + (x - dest.x() as f64) * source_w / dest.width() as f64,
+        source_y + (y - dest.y() as f64) * source_h / dest.height() as f64,
+    )
+}
 
-        Ok(())
+/// Scale a `rect` in window units by the drawable `scale`, to pixels
+fn to_drawable(rect: Rect, scale: (f64, f64)) -> Rect {
+    let scale_x = |x: i32| (x as f64 * scale.0).round() as i32;
+    let scale_y = |y: i32| (y as f64 * scale.1).round() as i32;
+    let (left, top) = (scale_x(rect.left()), scale_y(rect.top()));
+    let (right, bottom) = (scale_x(rect.right()), scale_y(rect.bottom()));
+    Rect::new(
+        left,
+        top,
+        (right - left).max(1) as u32,
+        (bottom - top).max(1) as u32,
+    )
+}
+
+impl FrameSink for Graphics {
+    fn update_frame(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+        self.update_texture(width, height, pixels)
+    }
+
+    fn present(&mut self) -> Result<bool> {
+        self.render()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_positions_map_to_frame_pixels() {
+        // A 320x200 frame letterboxed into 960x600 at (80, 0)
+        let dest = Rect::new(80, 0, 960, 600);
+        let source = (0.0, 0.0, 320.0, 200.0);
+        assert_eq!(window_to_source(80.0, 0.0, dest, source), (0.0, 0.0));
+        assert_eq!(
+            window_to_source(82.0, 5.0, dest, source),
+            (2.0 / 3.0, 5.0 / 3.0)
+        );
+        assert_eq!(
+            window_to_source(1037.0, 599.0, dest, source).0.floor(),
+            319.0
+        );
+        // Positions over the bars fall outside the frame, on either side
+        assert!(window_to_source(79.0, 0.0, dest, source).0 < 0.0);
+        assert!(window_to_source(1040.0, 0.0, dest, source).0 >= 320.0);
+
+        // Zoomed in 4x on the region starting at (100, 50)
+        let source = (100.0, 50.0, 80.0, 50.0);
+        assert_eq!(window_to_source(92.0, 12.0, dest, source), (101.0, 51.0));
+    }
+
+    #[test]
+    fn test_rects_scale_to_drawable_pixels() {
+        let rect = Rect::new(10, 5, 3, 1);
+        assert_eq!(to_drawable(rect, (2.0, 2.0)), Rect::new(20, 10, 6, 2));
+        assert_eq!(to_drawable(rect, (1.5, 1.5)), Rect::new(15, 8, 5, 1));
     }
 }
@@ -0,0 +1,147 @@
+//! Guest Allocations
+//!
+//! The host passes variable-length data (typed text, files, WebSocket and bus
+//! messages) to guests by writing it into memory the guest allocates. Guests
+//! export `wapps_alloc(len) -> ptr`, returning a buffer of at least `len`
+//! bytes or 0 when they are out of memory, and optionally
+//! `wapps_free(ptr, len)`, which the host calls once the callback receiving
+//! the buffer returns. Data a guest cannot allocate is dropped with a warning
+//! rather than crashing it, while a buffer outside the guest's memory is a
+//! guest bug reported as an error.
+
+use anyhow::{bail, Context, Result};
+use log::warn;
+use wasmtime::{AsContextMut, Instance, TypedFunc};
+
+use crate::wasi_threads::GuestMemory;
+
+/// The allocator exports of a guest
+#[derive(Clone)]
+pub struct GuestAllocator {
+    alloc: TypedFunc<i32, i32>,
+    free: Option<TypedFunc<(i32, i32), ()>>,
+}
+
+impl GuestAllocator {
+    /// The allocator of `instance`, or `None` if it exports no `wapps_alloc`
+    pub fn find(mut store: impl AsContextMut, instance: &Instance) -> Option<Self> {
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "wapps_alloc")
+            .ok()?;
+        let free = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "wapps_free")
+            .ok();
+        Some(Self { alloc, free })
+    }
+
+    /// Copy `bytes` into a buffer from the guest's `wapps_alloc`, returning its
+    /// (pointer, length), or `None` if the guest is out of memory
+    pub fn copy_in(
+        &self,
+        mut store: impl AsContextMut,
+        memory: &GuestMemory,
+        bytes: &[u8],
+    ) -> Result<Option<(i32, i32)>> {
+        let len = i32::try_from(bytes.len()).context("Data too large for guest memory")?;
+        let ptr = self
+            .alloc
+            .call(&mut store, len)
+            .context("Error calling guest 'wapps_alloc' function")?;
+        if ptr == 0 {
+            warn!("Guest is out of memory for {} bytes; dropping them", len);
+            return Ok(None);
+        }
+        let Some(range) = buffer_range(ptr, bytes.len(), memory.data_size(&store)) else {
+            bail!(
+                "Guest 'wapps_alloc' returned {} bytes at {:#x}, out of bounds",
+                len,
+                ptr as u32
+            );
+        };
+        memory.write(&mut store, range.start, bytes)?;
+        Ok(Some((ptr, len)))
+    }
+
+    /// Release a buffer from `copy_in` through the guest's `wapps_free`, if it
+    /// exports one
+    pub fn free(&self, mut store: impl AsContextMut, ptr: i32, len: i32) -> Result<()> {
+        if let Some(free) = &self.free {
+            free.call(&mut store, (ptr, len))
+                .context("Error calling guest 'wapps_free' function")?;
+        }
+        Ok(())
+    }
+}
+
+/// Range of guest memory holding a `len`-byte buffer at `ptr`, if it fits in
+/// `memory_size` bytes
+fn buffer_range(ptr: i32, len: usize, memory_size: usize) -> Option<std::ops::Range<usize>> {
+    let start = ptr as u32 as usize;
+    let end = start.checked_add(len)?;
+    (end <= memory_size).then_some(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Engine, Module, Store};
+
+    /// A bump allocator over one page that returns 0 once it is full, and an
+    /// allocator returning the last byte of memory whatever the size asked
+    const GUEST: &str = r#"(module
+        (memory (export "memory") 1)
+        (global $next (mut i32) (i32.const 1024))
+        (func (export "wapps_alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (if (i32.gt_u (i32.add (global.get $next) (local.get $len)) (i32.const 65536))
+                (then (return (i32.const 0))))
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+        (func (export "wapps_free") (param i32 i32)
+            (global.set $next (i32.sub (global.get $next) (local.get 1))))
+        (func (export "bad_alloc") (param i32) (result i32)
+            (i32.const 65535)))"#;
+
+    #[test]
+    fn test_copies_in_bounds_and_survives_oom() {
+        assert_eq!(buffer_range(8, 4, 16), Some(8..12));
+        assert_eq!(buffer_range(8, 8, 16), Some(8..16));
+        assert_eq!(buffer_range(8, 9, 16), None);
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, GUEST).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let memory = GuestMemory::from(instance.get_memory(&mut store, "memory").unwrap());
+        let allocator = GuestAllocator::find(&mut store, &instance).unwrap();
+
+        let (ptr, len) = allocator
+            .copy_in(&mut store, &memory, b"hello")
+            .unwrap()
+            .unwrap();
+        assert_eq!((ptr, len), (1024, 5));
+        assert_eq!(&*memory.read(&store, 1024, 5).unwrap(), b"hello");
+        allocator.free(&mut store, ptr, len).unwrap();
+
+        // Out of memory: the data is dropped, the guest keeps running
+        let large = vec![0; 65536];
+        assert_eq!(
+            allocator.copy_in(&mut store, &memory, &large).unwrap(),
+            None
+        );
+        assert!(allocator
+            .copy_in(&mut store, &memory, b"ok")
+            .unwrap()
+            .is_some());
+
+        let bad = GuestAllocator {
+            alloc: instance
+                .get_typed_func::<i32, i32>(&mut store, "bad_alloc")
+                .unwrap(),
+            free: None,
+        };
+        assert!(bad.copy_in(&mut store, &memory, b"x").unwrap().is_some());
+        assert!(bad.copy_in(&mut store, &memory, b"xy").is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! Guest Configuration
+//!
+//! Apps read startup settings, such as a grid size, a difficulty or the URL
+//! of their data, with `wapps::config_get`, so the same package can be
+//! launched in different ways. The package's manifest gives defaults in its
+//! `config` section, and each `--guest-arg key=value` overrides one key for
+//! every app launched. Keys and values are strings, which guests parse.
+
+use std::collections::HashMap;
+
+/// Parse a `--guest-arg` value of the form `key=value`
+pub fn parse_guest_arg(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value, got {:?}", arg)),
+    }
+}
+
+/// The package's `defaults` with `overrides` applied, later ones winning
+pub fn resolve(
+    defaults: &HashMap<String, String>,
+    overrides: &[(String, String)],
+) -> HashMap<String, String> {
+    let mut config = defaults.clone();
+    config.extend(overrides.iter().cloned());
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guest_args_override_manifest_config() {
+        assert_eq!(
+            parse_guest_arg("url=https://example.com/?a=b"),
+            Ok(("url".to_string(), "https://example.com/?a=b".to_string()))
+        );
+        assert_eq!(
+            parse_guest_arg("empty="),
+            Ok(("empty".to_string(), String::new()))
+        );
+        assert!(parse_guest_arg("size").is_err());
+        assert!(parse_guest_arg("=3").is_err());
+
+        let defaults = HashMap::from([
+            ("size".to_string(), "64".to_string()),
+            ("difficulty".to_string(), "easy".to_string()),
+        ]);
+        let overrides = [
+            ("size".to_string(), "128".to_string()),
+            ("seed".to_string(), "1".to_string()),
+            ("size".to_string(), "256".to_string()),
+        ];
+        let config = resolve(&defaults, &overrides);
+        assert_eq!(config.len(), 3);
+        assert_eq!(config["size"], "256");
+        assert_eq!(config["difficulty"], "easy");
+        assert_eq!(config["seed"], "1");
+    }
+}
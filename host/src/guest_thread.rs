@@ -0,0 +1,127 @@
+//! Guest Thread
+//!
+//! Runs a single app's updates on a thread of its own, so that a slow guest
+//! no longer freezes its window: the main thread keeps pumping SDL events
+//! and presenting the last frame at the display's rate while the update
+//! runs. The runtime moves to the thread with the queued events and comes
+//! back with the update's result. The main thread waits up to a frame for it,
+//! so guests that keep up present every frame as before, while events and dt
+//! pile up for the next update of guests that don't.
+
+use anyhow::{anyhow, Context, Result};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an update waits for the guest before the previous frame is
+/// presented again, about a frame at 60 Hz
+pub const FRAME_WAIT: Duration = Duration::from_millis(16);
+
+type Job<T> = Box<dyn FnOnce() -> T + Send + 'static>;
+
+/// Output of a job, with when it started and how long it took
+pub struct Finished<T> {
+    pub output: T,
+    pub start: Instant,
+    pub elapsed: Duration,
+}
+
+/// A thread running one job at a time, whose results are polled
+pub struct GuestThread<T> {
+    jobs: Sender<Job<T>>,
+    results: Receiver<Finished<T>>,
+    busy: bool,
+}
+
+impl<T: Send + 'static> GuestThread<T> {
+    pub fn spawn() -> Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job<T>>();
+        let (sender, results) = mpsc::channel();
+        // The thread is left to finish on its own when dropped, so a stuck
+        // guest doesn't hold up the host's exit
+        thread::Builder::new()
+            .name("wapps-guest".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    let start = Instant::now();
+                    let output = job();
+                    let finished = Finished {
+                        output,
+                        start,
+                        elapsed: start.elapsed(),
+                    };
+                    if sender.send(finished).is_err() {
+                        break;
+                    }
+                }
+            })
+            .context("Failed to spawn the guest thread")?;
+        Ok(Self {
+            jobs,
+            results,
+            busy: false,
+        })
+    }
+
+    /// Whether a job is running
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Start `job` on the thread, which must not be busy
+    pub fn dispatch(&mut self, job: impl FnOnce() -> T + Send + 'static) -> Result<()> {
+        self.jobs
+            .send(Box::new(job))
+            .map_err(|_| anyhow!("Guest thread has stopped"))?;
+        self.busy = true;
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for the running job, returning `None` if it is
+    /// still running or there is none
+    pub fn wait(&mut self, timeout: Duration) -> Option<Result<Finished<T>>> {
+        if !self.busy {
+            return None;
+        }
+        match self.results.recv_timeout(timeout) {
+            Ok(finished) => {
+                self.busy = false;
+                Some(Ok(finished))
+            }
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.busy = false;
+                Some(Err(anyhow!("Guest thread panicked during update")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_jobs_are_waited_for_across_polls() {
+        let mut thread = GuestThread::spawn().unwrap();
+        assert!(thread.wait(Duration::ZERO).is_none());
+
+        let (release, gate) = mpsc::channel::<()>();
+        thread
+            .dispatch(move || {
+                gate.recv().unwrap();
+                42
+            })
+            .unwrap();
+        assert!(thread.is_busy());
+        assert!(thread.wait(Duration::from_millis(10)).is_none());
+
+        release.send(()).unwrap();
+        let finished = thread.wait(Duration::MAX).unwrap().unwrap();
+        assert_eq!(finished.output, 42);
+        assert!(!thread.is_busy());
+
+        thread.dispatch(|| panic!("guest bug")).unwrap();
+        assert!(thread.wait(Duration::MAX).unwrap().is_err());
+    }
+}
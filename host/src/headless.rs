@@ -0,0 +1,283 @@
+//! Headless Rendering
+//!
+//! With `--headless`, the host runs a single app without initializing SDL:
+//! no window, audio or input. The guest is updated at a fixed time step for a
+//! set number of frames, and the frames it presents are written to PATH
+//! (`--headless-output`): numbered PNGs in a directory, or, when PATH ends in
+//! `.y4m`, a YUV4MPEG2 video stream that video tools read directly. PNGs are
+//! numbered by host frame, so frames the guest did not redraw leave gaps; the
+//! video repeats the last frame to keep its timing.
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::deeplink;
+use crate::frame_limits::FrameLimits;
+use crate::graphics::FrameSink;
+use crate::guest_config;
+use crate::host_interface::HostInterface;
+use crate::loader;
+use crate::png;
+use crate::runtime::WasmRuntime;
+use crate::storage::AppStorage;
+use crate::wasi_policy::{ClockPolicy, WasiPolicy};
+
+/// What `--headless` runs and where its frames go
+#[derive(Debug)]
+pub struct HeadlessOptions {
+    /// Package file or `wapps://` URL
+    pub file: PathBuf,
+    pub output: PathBuf,
+    pub frames: u32,
+    /// Frames per second of guest time, giving the fixed `dt`
+    pub fps: u32,
+    pub clock: Option<ClockPolicy>,
+    pub random_seed: Option<u64>,
+    pub allow_unknown_imports: bool,
+    pub max_memory: Option<usize>,
+    pub frame_budget: Option<Duration>,
+    pub frame_limits: FrameLimits,
+    /// Settings overriding the package's `config`, with `--guest-arg`
+    pub guest_config: Vec<(String, String)>,
+}
+
+/// Writes presented frames to files
+pub enum HeadlessSink {
+    /// One `frame-NNNNNN.png` per new frame in a directory
+    Png {
+        dir: PathBuf,
+        frame: u64,
+        pending: Option<(u32, u32, Vec<u8>)>,
+    },
+    /// A YUV4MPEG2 stream with one picture per host frame
+    Y4m {
+        out: BufWriter<File>,
+        fps: u32,
+        /// Size of the stream, fixed by its first frame
+        size: Option<(u32, u32)>,
+        /// Latest frame, converted to Y, U and V planes
+        planes: Vec<u8>,
+    },
+}
+
+impl HeadlessSink {
+    /// Write frames to `path`: a .y4m stream, or PNGs in a directory
+    pub fn create(path: &Path, fps: u32) -> Result<Self> {
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("y4m"))
+        {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+            }
+            let file = File::create(path)
+                .with_context(|| format!("Could not create {}", path.display()))?;
+            return Ok(HeadlessSink::Y4m {
+                out: BufWriter::new(file),
+                fps,
+                size: None,
+                planes: Vec::new(),
+            });
+        }
+        fs::create_dir_all(path)
+            .with_context(|| format!("Could not create directory: {}", path.display()))?;
+        Ok(HeadlessSink::Png {
+            dir: path.to_path_buf(),
+            frame: 0,
+            pending: None,
+        })
+    }
+
+    /// Flush buffered output
+    pub fn finish(&mut self) -> Result<()> {
+        if let HeadlessSink::Y4m { out, .. } = self {
+            out.flush().context("Could not write video")?;
+        }
+        Ok(())
+    }
+}
+
+impl FrameSink for HeadlessSink {
+    fn update_frame(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+        match self {
+            HeadlessSink::Png { pending, .. } => {
+                *pending = Some((width, height, pixels.to_vec()));
+            }
+            HeadlessSink::Y4m {
+                out,
+                fps,
+                size,
+                planes,
+            } => {
+                match *size {
+                    None => {
+                        writeln!(
+                            out,
+                            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                            width, height, fps
+                        )
+                        .context("Could not write video")?;
+                        *size = Some((width, height));
+                    }
+                    Some(size) if size != (width, height) => bail!(
+                        "The app resized its frame from {}x{} to {}x{}, which a .y4m \
+                         stream cannot hold; write PNGs instead",
+                        size.0,
+                        size.1,
+                        width,
+                        height
+                    ),
+                    Some(_) => {}
+                }
+                *planes = to_yuv444(pixels);
+            }
+        }
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<bool> {
+        match self {
+            HeadlessSink::Png {
+                dir,
+                frame,
+                pending,
+            } => {
+                let index = *frame;
+                *frame += 1;
+                let Some((width, height, pixels)) = pending.take() else {
+                    return Ok(false);
+                };
+                let path = dir.join(format!("frame-{:06}.png", index));
+                fs::write(&path, png::encode_rgba(width, height, &pixels))
+                    .with_context(|| format!("Could not write {}", path.display()))?;
+                Ok(true)
+            }
+            HeadlessSink::Y4m { out, planes, .. } => {
+                // Nothing to show until the guest presents its first frame
+                if planes.is_empty() {
+                    return Ok(false);
+                }
+                out.write_all(b"FRAME\n")
+                    .and_then(|()| out.write_all(planes))
+                    .context("Could not write video")?;
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// RGBA pixels as full-resolution Y, U and V planes (BT.601, studio range)
+fn to_yuv444(pixels: &[u8]) -> Vec<u8> {
+    let count = pixels.len() / 4;
+    let mut planes = vec![0; count * 3];
+    let (y, uv) = planes.split_at_mut(count);
+    let (u, v) = uv.split_at_mut(count);
+    for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+        let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+        y[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        u[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+        v[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+    planes
+}
+
+/// Run the app headlessly as described by `options`
+pub fn run(options: &HeadlessOptions) -> Result<()> {
+    if options.fps == 0 {
+        bail!("--headless-fps must be positive");
+    }
+    let (path, guest_args) = deeplink::resolve_argument(&options.file)?;
+    let package = loader::load_package(&path)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", path))?;
+    let assets = Arc::new(package.assets());
+    let wasm_bytes = package.module().data.clone();
+    let metadata = package.metadata;
+
+    let mut host_interface = HostInterface::new();
+    host_interface.set_app_info(metadata.name.clone(), metadata.version.clone());
+    host_interface.set_strings(metadata.strings.clone());
+    host_interface.set_config(guest_config::resolve(
+        &metadata.config,
+        &options.guest_config,
+    ));
+    host_interface.set_assets(assets);
+    // Rendering offline must not touch the app's saved data
+    host_interface.set_storage(AppStorage::in_memory());
+    host_interface.set_capabilities(metadata.capabilities.clone());
+    host_interface.set_frame_limits(options.frame_limits);
+    let policy = WasiPolicy::resolve(&metadata.wasi, options.clock, options.random_seed);
+    let mut args = vec![metadata.name.clone()];
+    args.extend(guest_args);
+    let mut runtime = WasmRuntime::new(
+        &wasm_bytes,
+        None,
+        host_interface,
+        &args,
+        None,
+        &policy,
+        options.allow_unknown_imports,
+        options.max_memory,
+    )
+    .context("Failed to initialize WASM runtime")?;
+    runtime.set_frame_budget(options.frame_budget);
+
+    let mut sink = HeadlessSink::create(&options.output, options.fps)?;
+    let dt = 1.0 / options.fps as f64;
+    let mut written = 0;
+    for frame in 0..options.frames {
+        runtime
+            .run_frame(&[], dt)
+            .with_context(|| format!("Guest failed at frame {}", frame))?;
+        if let Some(result) = runtime.with_frame_data(|width, height, pixels| {
+            sink.update_frame(width as u32, height as u32, pixels)
+        }) {
+            result?;
+        }
+        if sink.present()? {
+            written += 1;
+        }
+    }
+    runtime.call_shutdown()?;
+    sink.finish()?;
+
+    info!(
+        "{}: {} of {} frames written to {}",
+        metadata.name,
+        written,
+        options.frames,
+        options.output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_y4m_repeats_frames_and_keeps_its_size() {
+        let path = std::env::temp_dir().join(format!("wapps-headless-{}.y4m", std::process::id()));
+        let mut sink = HeadlessSink::create(&path, 30).unwrap();
+        assert!(!sink.present().unwrap());
+
+        // White then black, each pixel's planes in Y, U, V order
+        sink.update_frame(2, 1, &[255, 255, 255, 255, 0, 0, 0, 255])
+            .unwrap();
+        assert!(sink.present().unwrap());
+        assert!(sink.present().unwrap());
+        assert!(sink.update_frame(1, 1, &[0; 4]).is_err());
+        sink.finish().unwrap();
+
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let header = b"YUV4MPEG2 W2 H1 F30:1 Ip A1:1 C444\n";
+        let frame = b"FRAME\n\xeb\x10\x80\x80\x80\x80";
+        assert_eq!(data, [&header[..], frame, frame].concat());
+    }
+}
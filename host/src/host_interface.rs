@@ -1,77 +1,1352 @@
 //! Host Interface
 //!
 //! Manages the shared state between the WASM guest and the host application.
-//! Stores the latest frame data from update_frame calls.
+//! Stores the latest frame layers from update_frame and update_layer calls.
 //!
-//! Performance: Uses a pre-allocated buffer that is reused across frames
-//! to avoid heap allocations on every update_frame call.
+//! Performance: Layer buffers are reused across frames to avoid heap
+//! allocations on every update_frame call. Guests exporting
+//! `get_framebuffer` skip the copy entirely: presenting that buffer only
+//! records its size, and the host reads the pixels straight from linear
+//! memory when it updates the texture. Images drawn with `wapps::draw_image`
+//! are composited over a copy of the layers, so the layers stay reusable.
+
+use log::Level;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::audio::{AudioFormat, CapturedAudio, PendingAudio};
+use crate::canvas::CanvasDraw;
+use crate::capabilities::Capability;
+use crate::display::{CursorSettings, CursorShape, DisplayConstraints, ScalingMode, WindowSize};
+use crate::display_adjust::Adjustment;
+use crate::frame_limits::{FrameGate, FrameLimits};
+use crate::http::HttpClient;
+use crate::images::{ImageDraw, ImageStore};
+use crate::layers::{LayerStack, BASE_LAYER};
+use crate::loader::Assets;
+use crate::permissions::FirstUse;
+use crate::pixel_format::PixelFormat;
+use crate::save_state::{SavedLayer, SnapshotRequest};
+use crate::scores::{self, ScoreKey};
+use crate::storage::AppStorage;
+use crate::text::TextDraw;
+use crate::timers::Timers;
+use crate::wasm_features::DisabledFeatures;
+use crate::ws::WsClient;
 
 /// Host interface for communication between WASM guest and host
 pub struct HostInterface {
-    /// Latest frame width
-    frame_width: i32,
-    /// Latest frame height  
-    frame_height: i32,
-    /// Reusable pixel buffer (RGBA format) - avoids allocation per frame
-    frame_buffer: Vec<u8>,
-    /// Actual length of valid data in frame_buffer
-    frame_len: usize,
-    /// Flag indicating new frame data is available
-    frame_dirty: bool,
+    /// Latest guest layers (RGBA) and their composite
+    layers: LayerStack,
+    /// Guest memory offset of the buffer returned by `get_framebuffer`
+    framebuffer_ptr: Option<u32>,
+    /// Size of the frame presented from that buffer, standing in for the base
+    /// layer, and whether it changed since it was last read
+    shared_frame: Option<(u32, u32)>,
+    shared_frame_dirty: bool,
+    /// Reusable buffer for frames expanded to RGBA from other pixel formats
+    converted_frame: Vec<u8>,
+    /// RGBA colors of indexed frames, set via `wapps::set_palette`
+    palette: Vec<[u8; 4]>,
+    /// Size and indices of the indexed frame on screen, recolored when the
+    /// palette changes
+    indexed_size: Option<(u32, u32)>,
+    indexed_frame: Vec<u8>,
+    /// Size and count limits of the frames presented each update
+    frame_gate: FrameGate,
+    /// Images uploaded via `wapps::create_image` and the draws queued for the next frame
+    images: ImageStore,
+    /// Reusable buffer for the layers composite with the queued images drawn over it
+    sprite_frame: Vec<u8>,
+    /// Size of the frame in `sprite_frame` while it is the latest frame
+    sprite_size: Option<(u32, u32)>,
+    /// Audio pushed via `wapps::push_audio`, waiting to be queued on the device
+    audio: PendingAudio,
+    /// Frames queued on the audio device when the audio was last handed off
+    audio_device_frames: u32,
+    /// Microphone samples for `wapps::audio_capture_read`, filled by the
+    /// app's capture device
+    captured_audio: Arc<Mutex<CapturedAudio>>,
+    /// Microphone start or stop asked for since the last poll
+    capture_request: Option<CaptureRequest>,
+    /// Layout constraints declared by the guest, and whether they changed
+    constraints: DisplayConstraints,
+    constraints_changed: bool,
+    /// Clear color set via `wapps::set_clear_color` since the last poll
+    clear_color: Option<u32>,
+    /// HUD set or removed via `wapps::update_overlay` since the last poll
+    overlay: Option<OverlayUpdate>,
+    /// Scaling mode set via `wapps::set_scaling_mode` since the last poll
+    scaling_mode: Option<ScalingMode>,
+    /// Adjustment set via `wapps::set_display_adjustment` since the last poll
+    display_adjustment: Option<Adjustment>,
+    /// Fullscreen state requested via `wapps::set_fullscreen` since the last poll
+    fullscreen_request: Option<bool>,
+    /// Title set via `wapps::set_window_title` since the last poll
+    window_title: Option<String>,
+    /// Size set via `wapps::set_window_size` since the last poll
+    window_size: Option<WindowSize>,
+    /// Cursor set via `wapps::set_cursor_visible`, `wapps::set_cursor` and
+    /// `wapps::set_relative_mouse`, and whether it changed since the last poll
+    cursor: CursorSettings,
+    cursor_changed: bool,
+    /// Whether the host stays fullscreen whatever the guest asks (`--kiosk`)
+    fullscreen_locked: bool,
+    /// Whether the guest may launch other packages
+    launch_allowed: bool,
+    /// Capabilities declared by the package, if it has a manifest
+    capabilities: Option<BTreeSet<Capability>>,
+    /// Permissions asked about when the guest first uses them
+    first_use: Vec<FirstUse>,
+    /// Whether the runtime counts the guest's calls to each import
+    count_import_calls: bool,
+    /// Whether the guest may use a shared memory and start threads
+    threads_enabled: bool,
+    /// WebAssembly proposals the runtime compiles the guest without
+    disabled_features: DisabledFeatures,
+    /// Packages the guest asked to launch since the last poll
+    launch_requests: Vec<String>,
+    /// Whether the guest may use the message bus between running apps
+    bus_allowed: bool,
+    /// Topics subscribed to via `wapps::bus_subscribe`
+    bus_topics: BTreeSet<String>,
+    /// Messages sent via `wapps::bus_send` since the last poll, as (topic, data)
+    bus_messages: Vec<(String, Vec<u8>)>,
+    /// Localized package strings readable via `wapps::get_string`
+    strings: HashMap<String, String>,
+    /// Startup settings readable via `wapps::config_get`
+    config: HashMap<String, String>,
+    /// Package assets readable via `wapps::asset_size` and `wapps::asset_read`,
+    /// shared so reading one does not copy the whole bundle
+    assets: Arc<Assets>,
+    /// Scancodes of the keys held down, readable via `wapps::query_key_state`
+    held_keys: HashSet<i32>,
+    /// Timestamp of the event being dispatched, readable via `wapps::event_time`
+    event_time: f64,
+    /// Seconds between updates the guest asked for via
+    /// `wapps::request_frame_rate` (`None` updates every frame)
+    frame_interval: Option<f64>,
+    /// Bytes the guest's allocator had in use at its latest `wapps::report_allocations`
+    heap_bytes: Option<u64>,
+    /// Latest lines logged via `wapps::log` since the last poll, at most `LOG_HISTORY`
+    log_lines: VecDeque<(Level, String)>,
+    /// Packaged app name and version, readable via `wapps::app_name` and `wapps::app_version`
+    app_name: String,
+    app_version: String,
+    /// User locale tag and theme, readable via `wapps::get_locale` and
+    /// `wapps::get_prefers_dark`
+    locale: String,
+    prefers_dark: bool,
+    /// Viewport size last passed to `init` or `on_resize`, the display's
+    /// refresh rate in Hz (0 if unknown) and the frames presented so far,
+    /// readable via `wapps::get_window_size`, `wapps::get_display_refresh_rate`
+    /// and `wapps::get_frame_count`
+    viewport: (i32, i32),
+    refresh_rate: i32,
+    frame_count: u64,
+    /// Persistent key-value store behind `wapps::storage_get` and `wapps::storage_set`
+    storage: AppStorage,
+    /// Requests started via `wapps::http_fetch`
+    http: HttpClient,
+    /// Connections opened via `wapps::ws_connect`
+    ws: WsClient,
+    /// Timers scheduled via `wapps::set_timer`
+    timers: Timers,
+    /// Whether the guest wants another frame under `--power-save`, via
+    /// `wapps::request_redraw`; set until the first frame runs
+    redraw_requested: bool,
+    /// Seconds after the last frame started by which the guest asked for an
+    /// update via `wapps::request_update_in`
+    update_due: Option<f64>,
+    /// Key signing leaderboard entries (`None` leaves them unsigned)
+    score_key: Option<ScoreKey>,
+    /// Id of the package, which leaderboard entries are signed for
+    package_id: String,
+    /// File save states are written to (`None` denies them)
+    state_path: Option<PathBuf>,
+    /// Directory preopened into WASI (`None` gives the guest no files)
+    files_dir: Option<PathBuf>,
+    /// Save or restore asked for via `wapps::request_snapshot` or
+    /// `wapps::request_restore` since the last poll
+    snapshot_request: Option<SnapshotRequest>,
+    /// Whether the host can show file dialogs for the guest
+    file_dialogs_allowed: bool,
+    /// File dialog requested via `wapps::request_open_file` or
+    /// `wapps::request_save_file`, until the host shows it
+    file_request: Option<FileRequest>,
+    /// Rumble and LED changes requested via `wapps::gamepad_rumble` and
+    /// `wapps::gamepad_set_led` since the last poll
+    gamepad_requests: Vec<GamepadRequest>,
+}
+
+/// A file dialog the guest asked the host to show
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileRequest {
+    /// Pick a file to read, with one of `extensions` unless empty
+    Open { extensions: Vec<String> },
+    /// Pick where to write `data`, suggesting `name`
+    Save { name: String, data: Vec<u8> },
+}
+
+/// Controller feedback the guest asked the host for, on the `pad`th
+/// connected controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadRequest {
+    /// Run the low and high frequency rumble motors at the given strengths
+    /// for `duration_ms`; zero strengths stop them
+    Rumble {
+        pad: u32,
+        low: u16,
+        high: u16,
+        duration_ms: u32,
+    },
+    /// Set the controller's LED color
+    Led { pad: u32, r: u8, g: u8, b: u8 },
 }
 
+/// Gamepad requests kept per frame; later ones are dropped
+const MAX_GAMEPAD_REQUESTS: usize = 32;
+
+/// Longest rumble a guest may request, in milliseconds
+pub const MAX_RUMBLE_MS: u32 = 10_000;
+
+/// Status codes returned by `wapps::launch`
+pub const LAUNCH_OK: i32 = 0;
+pub const LAUNCH_DENIED: i32 = -1;
+pub const LAUNCH_INVALID: i32 = -2;
+
+/// Status codes returned by `wapps::bus_send` and `wapps::bus_subscribe`
+pub const BUS_OK: i32 = 0;
+pub const BUS_DENIED: i32 = -1;
+pub const BUS_INVALID: i32 = -2;
+pub const BUS_FULL: i32 = -3;
+
+/// Longest bus topic, in bytes
+pub const MAX_BUS_TOPIC: usize = 64;
+
+/// Largest bus message, in bytes
+pub const MAX_BUS_MESSAGE: usize = 64 * 1024;
+
+/// Bus messages an app may send per frame, and topics it may subscribe to
+const MAX_BUS_QUEUE: usize = 64;
+
+/// Status codes returned by `wapps::request_snapshot` and `wapps::request_restore`
+pub const SNAPSHOT_OK: i32 = 0;
+pub const SNAPSHOT_DENIED: i32 = -1;
+pub const SNAPSHOT_NOT_FOUND: i32 = -2;
+
+/// Returned by `wapps::get_string` when the key is unknown or invalid
+pub const STRING_NOT_FOUND: i32 = -1;
+
+/// Returned by `wapps::config_get` when the key is unset or invalid
+pub const CONFIG_NOT_FOUND: i32 = -1;
+
+/// Returned by `wapps::asset_size` and `wapps::asset_read` for names the
+/// package has no asset for
+pub const ASSET_NOT_FOUND: i32 = -1;
+
+/// Returned by `wapps::app_name` and `wapps::app_version` when the buffer is out of bounds
+pub const BUFFER_INVALID: i32 = -1;
+
+/// Status codes returned by `wapps::set_scaling_mode`
+pub const SCALING_OK: i32 = 0;
+pub const SCALING_INVALID: i32 = -1;
+
+/// Status codes returned by `wapps::set_display_adjustment`
+pub const ADJUSTMENT_OK: i32 = 0;
+pub const ADJUSTMENT_INVALID: i32 = -1;
+
+/// Modes and status codes of `wapps::set_fullscreen`
+pub const FULLSCREEN_OFF: i32 = 0;
+pub const FULLSCREEN_ON: i32 = 1;
+pub const FULLSCREEN_OK: i32 = 0;
+pub const FULLSCREEN_INVALID: i32 = -1;
+pub const FULLSCREEN_DENIED: i32 = -2;
+
+/// Most lines logged via `wapps::log` kept between polls
+pub const LOG_HISTORY: usize = 64;
+
+/// Status codes returned by `wapps::set_window_title`, `wapps::set_window_size`
+/// and `wapps::get_window_size`
+pub const WINDOW_OK: i32 = 0;
+pub const WINDOW_INVALID: i32 = -1;
+
+/// Status codes returned by `wapps::set_cursor`
+pub const CURSOR_OK: i32 = 0;
+pub const CURSOR_INVALID: i32 = -1;
+
+/// Largest width or height of a `wapps::update_overlay` HUD
+pub const MAX_OVERLAY_SIDE: u32 = 8192;
+
+/// A change to the HUD a guest draws over its whole window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverlayUpdate {
+    /// Draw `width` x `height` RGBA pixels stretched over the window
+    Set {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    Remove,
+}
+
+/// Status codes returned by `wapps::push_audio`
+pub const AUDIO_OK: i32 = 0;
+pub const AUDIO_INVALID: i32 = -1;
+
+/// Status codes returned by `wapps::audio_capture_start`; 0 means the
+/// microphone permission was denied
+pub const AUDIO_CAPTURE_OK: i32 = 1;
+pub const AUDIO_CAPTURE_INVALID: i32 = -1;
+
+/// A change to microphone capture the guest asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureRequest {
+    /// Open the microphone in this format
+    Start(AudioFormat),
+    /// Close the microphone
+    Stop,
+}
+
+/// Status codes returned by `wapps::request_open_file` and
+/// `wapps::request_save_file`; 0 means the `files` capability was denied
+pub const FILE_DIALOG_OK: i32 = 1;
+pub const FILE_DIALOG_UNAVAILABLE: i32 = -1;
+pub const FILE_DIALOG_BUSY: i32 = -2;
+pub const FILE_DIALOG_INVALID: i32 = -3;
+
+/// Results passed to `on_file_saved`
+pub const FILE_SAVED: i32 = 0;
+pub const FILE_CANCELLED: i32 = -1;
+pub const FILE_WRITE_FAILED: i32 = -2;
+
+/// Largest file passed to `on_file_opened` or saved for the guest, in bytes
+pub const MAX_FILE_SIZE: usize = 64 * 1024 * 1024;
+
 impl HostInterface {
     /// Create a new host interface
     pub fn new() -> Self {
         Self {
-            frame_width: 0,
-            frame_height: 0,
-            frame_buffer: Vec::new(),
-            frame_len: 0,
-            frame_dirty: false,
+            layers: LayerStack::new(),
+            framebuffer_ptr: None,
+            shared_frame: None,
+            shared_frame_dirty: false,
+            converted_frame: Vec::new(),
+            palette: Vec::new(),
+            indexed_size: None,
+            indexed_frame: Vec::new(),
+            frame_gate: FrameGate::default(),
+            images: ImageStore::new(),
+            sprite_frame: Vec::new(),
+            sprite_size: None,
+            audio: PendingAudio::default(),
+            audio_device_frames: 0,
+            captured_audio: Arc::default(),
+            capture_request: None,
+            constraints: DisplayConstraints::default(),
+            constraints_changed: false,
+            clear_color: None,
+            overlay: None,
+            scaling_mode: None,
+            display_adjustment: None,
+            fullscreen_request: None,
+            window_title: None,
+            window_size: None,
+            cursor: CursorSettings::default(),
+            cursor_changed: false,
+            fullscreen_locked: false,
+            launch_allowed: false,
+            capabilities: None,
+            first_use: Vec::new(),
+            count_import_calls: false,
+            threads_enabled: false,
+            disabled_features: DisabledFeatures::default(),
+            launch_requests: Vec::new(),
+            bus_allowed: false,
+            bus_topics: BTreeSet::new(),
+            bus_messages: Vec::new(),
+            strings: HashMap::new(),
+            config: HashMap::new(),
+            assets: Arc::default(),
+            held_keys: HashSet::new(),
+            event_time: 0.0,
+            frame_interval: None,
+            heap_bytes: None,
+            log_lines: VecDeque::new(),
+            app_name: String::new(),
+            app_version: String::new(),
+            locale: String::new(),
+            prefers_dark: false,
+            viewport: (0, 0),
+            refresh_rate: 0,
+            frame_count: 0,
+            storage: AppStorage::in_memory(),
+            http: HttpClient::default(),
+            ws: WsClient::default(),
+            timers: Timers::default(),
+            redraw_requested: true,
+            update_due: None,
+            score_key: None,
+            package_id: String::new(),
+            state_path: None,
+            files_dir: None,
+            snapshot_request: None,
+            file_dialogs_allowed: false,
+            file_request: None,
+            gamepad_requests: Vec::new(),
         }
     }
 
-    /// Store a new frame from the guest
+    /// Set the packaged name and version the guest can read back
+    pub fn set_app_info(&mut self, name: String, version: String) {
+        self.app_name = name;
+        self.app_version = version;
+    }
+
+    /// Packaged app name (localized)
+    pub fn app_name(&self) -> &str {
+        &self.app_name
+    }
+
+    /// Packaged app version; empty if the package does not declare one
+    pub fn app_version(&self) -> &str {
+        &self.app_version
+    }
+
+    /// Set the user's locale tag (e.g. `pt-BR`) and whether they prefer a
+    /// dark theme
+    pub fn set_user_preferences(&mut self, locale: String, prefers_dark: bool) {
+        self.locale = locale;
+        self.prefers_dark = prefers_dark;
+    }
+
+    /// User locale tag; empty if unknown
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn prefers_dark(&self) -> bool {
+        self.prefers_dark
+    }
+
+    /// Set the viewport size the guest was last told about
+    pub fn set_viewport(&mut self, width: i32, height: i32) {
+        self.viewport = (width, height);
+    }
+
+    pub fn viewport(&self) -> (i32, i32) {
+        self.viewport
+    }
+
+    /// Set the refresh rate of the window's display in Hz, 0 if unknown
+    pub fn set_refresh_rate(&mut self, refresh_rate: i32) {
+        self.refresh_rate = refresh_rate;
+    }
+
+    pub fn refresh_rate(&self) -> i32 {
+        self.refresh_rate
+    }
+
+    /// Set the number of frames presented so far
+    pub fn set_frame_count(&mut self, frame_count: u64) {
+        self.frame_count = frame_count;
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Set the strings the guest can look up by key
+    pub fn set_strings(&mut self, strings: HashMap<String, String>) {
+        self.strings = strings;
+    }
+
+    /// Look up a package string by key
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(String::as_str)
+    }
+
+    /// Set the startup settings the guest can look up by key
+    pub fn set_config(&mut self, config: HashMap<String, String>) {
+        self.config = config;
+    }
+
+    /// Look up a startup setting by key
+    pub fn config_value(&self, key: &str) -> Option<&str> {
+        self.config.get(key).map(String::as_str)
+    }
+
+    /// Set the assets the guest can read by name
+    pub fn set_assets(&mut self, assets: Arc<Assets>) {
+        self.assets = assets;
+    }
+
+    /// Package assets, shared so the host can be unlocked while one is copied
+    pub fn assets(&self) -> Arc<Assets> {
+        self.assets.clone()
+    }
+
+    /// Set the store the guest reads and writes through the storage imports
+    pub fn set_storage(&mut self, storage: AppStorage) {
+        self.storage = storage;
+    }
+
+    /// Value stored under `key`
+    pub fn storage_get(&self, key: &str) -> Option<&[u8]> {
+        self.storage.get(key)
+    }
+
+    /// Store `value` under `key`, returning a `STORAGE_*` status
+    pub fn storage_set(&mut self, key: &str, value: &[u8]) -> i32 {
+        self.storage.set(key, value)
+    }
+
+    /// Hosts `wapps::http_fetch` and `wapps::ws_connect` may reach, from the
+    /// package manifest
+    pub fn set_allowed_hosts(&mut self, hosts: Vec<String>) {
+        self.http.set_allowed_hosts(hosts.clone());
+        self.ws.set_allowed_hosts(hosts);
+    }
+
+    /// Start an HTTP request, returning its handle or an `HTTP_*` error
+    pub fn http_fetch(&mut self, method: &str, url: &str, body: Vec<u8>) -> i32 {
+        self.http.fetch(method, url, body)
+    }
+
+    /// Status of an HTTP request, see `HttpClient::poll`
+    pub fn http_poll(&self, handle: i32) -> i32 {
+        self.http.poll(handle)
+    }
+
+    /// Response body of a completed HTTP request
+    pub fn http_body(&self, handle: i32) -> Option<Vec<u8>> {
+        self.http.body(handle)
+    }
+
+    /// Release an HTTP request
+    pub fn http_close(&mut self, handle: i32) {
+        self.http.close(handle);
+    }
+
+    /// Open a WebSocket, returning its handle or a `WS_*` error
+    pub fn ws_connect(&mut self, url: &str) -> i32 {
+        self.ws.connect(url)
+    }
+
+    /// Send a message on a WebSocket, see `WsClient::send`
+    pub fn ws_send(&self, handle: i32, message: Vec<u8>) -> i32 {
+        self.ws.send(handle, message)
+    }
+
+    /// State of a WebSocket, see `WsClient::status`
+    pub fn ws_status(&self, handle: i32) -> i32 {
+        self.ws.status(handle)
+    }
+
+    /// Close a WebSocket
+    pub fn ws_close(&mut self, handle: i32) {
+        self.ws.close(handle);
+    }
+
+    /// Messages received on the guest's WebSockets since the last call, as
+    /// (handle, message)
+    pub fn take_ws_messages(&mut self) -> Vec<(i32, Vec<u8>)> {
+        self.ws.take_messages()
+    }
+
+    /// Schedule a guest timer, returning a `TIMER_*` status
+    pub fn set_timer(&mut self, id: i32, seconds: f64, repeating: bool) -> i32 {
+        self.timers.set(id, seconds, repeating)
+    }
+
+    /// Cancel a guest timer, returning a `TIMER_*` status
+    pub fn cancel_timer(&mut self, id: i32) -> i32 {
+        self.timers.cancel(id)
+    }
+
+    /// Advance the guest's timers by `dt`, returning the ids of those that fired
+    pub fn advance_timers(&mut self, dt: f64) -> Vec<i32> {
+        self.timers.advance(dt)
+    }
+
+    /// Seconds until the guest's next timer fires, if it has one
+    pub fn next_timer(&self) -> Option<f64> {
+        self.timers.next()
+    }
+
+    /// Ask for another frame under `--power-save`
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    /// Whether the guest asked for another frame since the last one started
+    pub fn redraw_requested(&self) -> bool {
+        self.redraw_requested
+    }
+
+    /// Forget the redraw request, as a frame starts
+    pub fn clear_redraw_request(&mut self) {
+        self.redraw_requested = false;
+    }
+
+    /// Ask for an update within `seconds` of the last frame under
+    /// `--power-save`, keeping any earlier request
+    pub fn request_update_in(&mut self, seconds: f64) {
+        self.update_due = Some(self.update_due.map_or(seconds, |due| due.min(seconds)));
+    }
+
+    /// Forget the requested update, as a frame starts
+    pub fn clear_update_request(&mut self) {
+        self.update_due = None;
+    }
+
+    /// Limit the size of presented frames and how many are presented per
+    /// update, see `frame_limits`
+    pub fn set_frame_limits(&mut self, limits: FrameLimits) {
+        self.frame_gate = FrameGate::new(limits);
+    }
+
+    /// Count a `width` x `height` frame, layer or canvas presented by
+    /// `import`, returning the `wapps_abi` status rejecting it, if any
+    pub fn admit_frame(&mut self, import: &str, width: u32, height: u32) -> i32 {
+        self.frame_gate.admit(import, width, height)
+    }
+
+    /// Start counting the presents of a new update
+    pub fn start_frame_count(&mut self) {
+        self.frame_gate.start_update();
+    }
+
+    /// Seconds after the last frame until the next timer fires or the
+    /// requested update is due, whichever comes first
+    pub fn next_wake(&self) -> Option<f64> {
+        match (self.next_timer(), self.update_due) {
+            (Some(timer), Some(due)) => Some(timer.min(due)),
+            (timer, due) => timer.or(due),
+        }
+    }
+
+    /// Set the key leaderboard entries are signed and verified with
+    pub fn set_score_key(&mut self, key: Option<ScoreKey>) {
+        self.score_key = key;
+    }
+
+    /// Set the package id leaderboard entries are signed for, see
+    /// `WappPackage::id`
+    pub fn set_package_id(&mut self, id: String) {
+        self.package_id = id;
+    }
+
+    /// Add a score to the app's leaderboard, returning its rank or a `SCORE_*` status
+    pub fn score_submit(&mut self, value: i64, name: &str) -> i32 {
+        scores::submit(
+            &mut self.storage,
+            self.score_key.as_ref(),
+            &self.package_id,
+            value,
+            name,
+        )
+    }
+
+    /// The app's leaderboard, encoded for `wapps::score_list`
+    pub fn score_list(&self) -> Vec<u8> {
+        scores::encode(&scores::list(
+            &self.storage,
+            self.score_key.as_ref(),
+            &self.package_id,
+        ))
+    }
+
+    /// Record a key press or release dispatched to the guest
+    pub fn set_key_held(&mut self, scancode: i32, held: bool) {
+        if held {
+            self.held_keys.insert(scancode);
+        } else {
+            self.held_keys.remove(&scancode);
+        }
+    }
+
+    /// Whether the key with `scancode` is held down
+    pub fn key_held(&self, scancode: i32) -> bool {
+        self.held_keys.contains(&scancode)
+    }
+
+    /// Set the timestamp of the event about to be dispatched
+    pub fn set_event_time(&mut self, time: f64) {
+        self.event_time = time;
+    }
+
+    /// Timestamp of the event being dispatched, or of the last one outside callbacks
+    pub fn event_time(&self) -> f64 {
+        self.event_time
+    }
+
+    /// Ask for `fps` updates a second, or for one every frame with 0
+    pub fn request_frame_rate(&mut self, fps: f64) {
+        self.frame_interval = (fps.is_finite() && fps > 0.0).then(|| 1.0 / fps);
+    }
+
+    /// Seconds between updates the guest asked for, if it did
+    pub fn frame_interval(&self) -> Option<f64> {
+        self.frame_interval
+    }
+
+    /// Record the bytes the guest's allocator has in use
+    pub fn report_allocations(&mut self, bytes_in_use: u64) {
+        self.heap_bytes = Some(bytes_in_use);
+    }
+
+    /// Bytes the guest's allocator had in use when it last reported them, if
+    /// it ever did
+    pub fn heap_bytes(&self) -> Option<u64> {
+        self.heap_bytes
+    }
+
+    /// Keep a line the guest logged, dropping the oldest beyond `LOG_HISTORY`
+    pub fn push_log(&mut self, level: Level, line: String) {
+        if self.log_lines.len() == LOG_HISTORY {
+            self.log_lines.pop_front();
+        }
+        self.log_lines.push_back((level, line));
+    }
+
+    /// Lines logged since the last call, oldest first
+    pub fn take_log_lines(&mut self) -> Vec<(Level, String)> {
+        self.log_lines.drain(..).collect()
+    }
+
+    /// Grant or revoke the permission to launch other packages
+    pub fn set_launch_allowed(&mut self, allowed: bool) {
+        self.launch_allowed = allowed;
+    }
+
+    /// Grant or revoke the permission to use the message bus
+    pub fn set_bus_allowed(&mut self, allowed: bool) {
+        self.bus_allowed = allowed;
+    }
+
+    /// Restrict the imports linked to those of the declared `capabilities`
+    pub fn set_capabilities(&mut self, capabilities: Option<BTreeSet<Capability>>) {
+        self.capabilities = capabilities;
+    }
+
+    /// Capabilities declared by the package, or `None` if every import is linked
+    pub fn capabilities(&self) -> Option<&BTreeSet<Capability>> {
+        self.capabilities.as_ref()
+    }
+
+    /// Ask about `first_use` permissions when the guest first calls their imports
+    pub fn set_first_use(&mut self, first_use: Vec<FirstUse>) {
+        self.first_use = first_use;
+    }
+
+    /// Permissions asked about when the guest first uses them
+    pub fn first_use(&self) -> &[FirstUse] {
+        &self.first_use
+    }
+
+    /// Have the runtime count the guest's calls to each import, see
+    /// `WasmRuntime::import_calls`
+    pub fn set_count_import_calls(&mut self, count: bool) {
+        self.count_import_calls = count;
+    }
+
+    pub fn counts_import_calls(&self) -> bool {
+        self.count_import_calls
+    }
+
+    /// Let the runtime link modules using a shared memory and run their
+    /// threads, see `wasi_threads`
+    pub fn set_threads_enabled(&mut self, enabled: bool) {
+        self.threads_enabled = enabled;
+    }
+
+    pub fn threads_enabled(&self) -> bool {
+        self.threads_enabled
+    }
+
+    /// Have the runtime compile the guest without these proposals, see
+    /// `wasm_features`
+    pub fn set_disabled_features(&mut self, disabled: DisabledFeatures) {
+        self.disabled_features = disabled;
+    }
+
+    pub fn disabled_features(&self) -> &DisabledFeatures {
+        &self.disabled_features
+    }
+
+    /// Queue a launch request from the guest, returning a `LAUNCH_*` status
+    pub fn request_launch(&mut self, target: String) -> i32 {
+        if !self.launch_allowed {
+            return LAUNCH_DENIED;
+        }
+        self.launch_requests.push(target);
+        LAUNCH_OK
+    }
+
+    /// Subscribe to messages sent on the bus under `topic`, returning a
+    /// `BUS_*` status
+    pub fn bus_subscribe(&mut self, topic: String) -> i32 {
+        if !self.bus_allowed {
+            return BUS_DENIED;
+        }
+        if !valid_bus_topic(&topic) {
+            return BUS_INVALID;
+        }
+        if self.bus_topics.len() >= MAX_BUS_QUEUE && !self.bus_topics.contains(&topic) {
+            return BUS_FULL;
+        }
+        self.bus_topics.insert(topic);
+        BUS_OK
+    }
+
+    /// Queue a message for the other apps subscribed to `topic`, returning a
+    /// `BUS_*` status
+    pub fn bus_send(&mut self, topic: String, data: Vec<u8>) -> i32 {
+        if !self.bus_allowed {
+            return BUS_DENIED;
+        }
+        if !valid_bus_topic(&topic) || data.len() > MAX_BUS_MESSAGE {
+            return BUS_INVALID;
+        }
+        if self.bus_messages.len() >= MAX_BUS_QUEUE {
+            return BUS_FULL;
+        }
+        self.bus_messages.push((topic, data));
+        BUS_OK
+    }
+
+    /// Whether the guest receives bus messages sent under `topic`
+    pub fn bus_subscribed(&self, topic: &str) -> bool {
+        self.bus_allowed && self.bus_topics.contains(topic)
+    }
+
+    /// Allow save states, written to `path`, or deny them with `None`
+    pub fn set_state_path(&mut self, path: Option<PathBuf>) {
+        self.state_path = path;
+    }
+
+    /// Give the guest `dir` as its WASI filesystem, or none with `None`
+    pub fn set_files_dir(&mut self, dir: Option<PathBuf>) {
+        self.files_dir = dir;
+    }
+
+    /// Directory preopened into WASI, if any
+    pub fn files_dir(&self) -> Option<&Path> {
+        self.files_dir.as_deref()
+    }
+
+    /// Queue a save or restore from the guest, returning a `SNAPSHOT_*` status
+    pub fn request_snapshot(&mut self, request: SnapshotRequest) -> i32 {
+        let Some(path) = &self.state_path else {
+            return SNAPSHOT_DENIED;
+        };
+        if request == SnapshotRequest::Restore && !path.exists() {
+            return SNAPSHOT_NOT_FOUND;
+        }
+        self.snapshot_request = Some(request);
+        SNAPSHOT_OK
+    }
+
+    /// Save or restore requested since the last call
+    pub fn take_snapshot_request(&mut self) -> Option<SnapshotRequest> {
+        self.snapshot_request.take()
+    }
+
+    /// Allow or refuse file dialogs requested by the guest
+    pub fn set_file_dialogs_allowed(&mut self, allowed: bool) {
+        self.file_dialogs_allowed = allowed;
+    }
+
+    /// Queue a file dialog for the host to show, returning a `FILE_DIALOG_*`
+    /// status; one may be pending at a time
+    pub fn request_file(&mut self, request: FileRequest) -> i32 {
+        if !self.file_dialogs_allowed {
+            return FILE_DIALOG_UNAVAILABLE;
+        }
+        if self.file_request.is_some() {
+            return FILE_DIALOG_BUSY;
+        }
+        if matches!(&request, FileRequest::Save { data, .. } if data.len() > MAX_FILE_SIZE) {
+            return FILE_DIALOG_INVALID;
+        }
+        self.file_request = Some(request);
+        FILE_DIALOG_OK
+    }
+
+    /// File dialog requested since the last call
+    pub fn take_file_request(&mut self) -> Option<FileRequest> {
+        self.file_request.take()
+    }
+
+    /// Queue a rumble or LED change for the host to apply to a controller
+    pub fn request_gamepad(&mut self, request: GamepadRequest) {
+        if self.gamepad_requests.len() < MAX_GAMEPAD_REQUESTS {
+            self.gamepad_requests.push(request);
+        }
+    }
+
+    /// Gamepad requests queued since the last call, oldest first
+    pub fn take_gamepad_requests(&mut self) -> Vec<GamepadRequest> {
+        std::mem::take(&mut self.gamepad_requests)
+    }
+
+    /// The frame layers, for a save state
+    ///
+    /// Frames presented from the shared framebuffer live in guest memory and
+    /// are saved with it.
+    pub fn saved_layers(&self) -> Vec<SavedLayer> {
+        if self.shared_frame.is_some() {
+            return Vec::new();
+        }
+        self.layers
+            .iter()
+            .map(|(id, width, height, opacity, pixels)| SavedLayer {
+                id,
+                width,
+                height,
+                opacity,
+                pixels: pixels.to_vec(),
+            })
+            .collect()
+    }
+
+    /// Show the frame layers of a save state
+    pub fn restore_layers(&mut self, layers: &[SavedLayer]) {
+        if layers.is_empty() && self.shared_frame.is_some() {
+            self.shared_frame_dirty = true;
+            return;
+        }
+        self.shared_frame = None;
+        self.indexed_size = None;
+        self.sprite_size = None;
+        self.layers.clear();
+        for layer in layers {
+            self.layers.set(
+                layer.id,
+                layer.width,
+                layer.height,
+                &layer.pixels,
+                layer.opacity,
+            );
+        }
+    }
+
+    /// Require a width:height aspect ratio, or clear it with `None`
+    pub fn set_aspect_ratio(&mut self, ratio: Option<(u32, u32)>) {
+        self.constraints.aspect_ratio = ratio;
+        self.constraints_changed = true;
+    }
+
+    /// Require a minimum window size, or clear it with `None`
+    pub fn set_min_size(&mut self, size: Option<(u32, u32)>) {
+        self.constraints.min_size = size;
+        self.constraints_changed = true;
+    }
+
+    /// Constraints declared since the last call, if they changed
+    pub fn take_constraints(&mut self) -> Option<DisplayConstraints> {
+        std::mem::take(&mut self.constraints_changed).then_some(self.constraints)
+    }
+
+    /// Set the color behind the frame, packed as `0xRRGGBBAA`
+    pub fn set_clear_color(&mut self, rgba: u32) {
+        self.clear_color = Some(rgba);
+    }
+
+    /// Clear color set since the last call, if any
+    pub fn take_clear_color(&mut self) -> Option<u32> {
+        self.clear_color.take()
+    }
+
+    /// Replace or remove the HUD; only the latest change of a frame is kept
+    pub fn set_overlay(&mut self, update: OverlayUpdate) {
+        self.overlay = Some(update);
+    }
+
+    /// HUD change since the last call, if any
+    pub fn take_overlay(&mut self) -> Option<OverlayUpdate> {
+        self.overlay.take()
+    }
+
+    /// Change how frames are scaled into the window
+    pub fn set_scaling_mode(&mut self, mode: i32) -> i32 {
+        match ScalingMode::from_raw(mode) {
+            Some(scaling) => {
+                self.scaling_mode = Some(scaling);
+                SCALING_OK
+            }
+            None => SCALING_INVALID,
+        }
+    }
+
+    /// Scaling mode set since the last call, if any
+    pub fn take_scaling_mode(&mut self) -> Option<ScalingMode> {
+        self.scaling_mode.take()
+    }
+
+    /// Adjust the brightness, contrast and gamma of presented frames
+    pub fn set_display_adjustment(&mut self, brightness: f32, contrast: f32, gamma: f32) -> i32 {
+        match Adjustment::new(brightness, contrast, gamma) {
+            Some(adjustment) => {
+                self.display_adjustment = Some(adjustment);
+                ADJUSTMENT_OK
+            }
+            None => ADJUSTMENT_INVALID,
+        }
+    }
+
+    /// Display adjustment set since the last call, if any
+    pub fn take_display_adjustment(&mut self) -> Option<Adjustment> {
+        self.display_adjustment.take()
+    }
+
+    /// Keep the host fullscreen, refusing guest requests to leave it
+    pub fn set_fullscreen_locked(&mut self, locked: bool) {
+        self.fullscreen_locked = locked;
+    }
+
+    /// Ask the host to enter or leave borderless fullscreen
+    pub fn request_fullscreen(&mut self, mode: i32) -> i32 {
+        let fullscreen = match mode {
+            FULLSCREEN_OFF => false,
+            FULLSCREEN_ON => true,
+            _ => return FULLSCREEN_INVALID,
+        };
+        if self.fullscreen_locked {
+            return FULLSCREEN_DENIED;
+        }
+        self.fullscreen_request = Some(fullscreen);
+        FULLSCREEN_OK
+    }
+
+    /// Fullscreen state requested since the last call, if any
+    pub fn take_fullscreen_request(&mut self) -> Option<bool> {
+        self.fullscreen_request.take()
+    }
+
+    /// Replace the package name in the window title
+    pub fn set_window_title(&mut self, title: String) {
+        self.window_title = Some(title);
+    }
+
+    /// Title set since the last call, if any
+    pub fn take_window_title(&mut self) -> Option<String> {
+        self.window_title.take()
+    }
+
+    /// Resize the window, returning a `WINDOW_*` status
+    pub fn set_window_size(&mut self, width: i32, height: i32, resizable: i32) -> i32 {
+        match WindowSize::from_raw(width, height, resizable) {
+            Some(size) => {
+                self.window_size = Some(size);
+                WINDOW_OK
+            }
+            None => WINDOW_INVALID,
+        }
+    }
+
+    /// Window size set since the last call, if any
+    pub fn take_window_size(&mut self) -> Option<WindowSize> {
+        self.window_size.take()
+    }
+
+    /// Show or hide the mouse cursor over the window
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor.hidden = !visible;
+        self.cursor_changed = true;
+    }
+
+    /// Change the shape of the mouse cursor over the window
+    pub fn set_cursor_shape(&mut self, shape: i32) -> i32 {
+        let Some(shape) = CursorShape::from_raw(shape) else {
+            return CURSOR_INVALID;
+        };
+        self.cursor.shape = shape;
+        self.cursor_changed = true;
+        CURSOR_OK
+    }
+
+    /// Capture or release the mouse
+    pub fn set_relative_mouse(&mut self, relative: bool) {
+        self.cursor.relative = relative;
+        self.cursor_changed = true;
+    }
+
+    /// Cursor settings, if they changed since the last call
+    pub fn take_cursor(&mut self) -> Option<CursorSettings> {
+        std::mem::take(&mut self.cursor_changed).then_some(self.cursor)
+    }
+
+    /// Take the launch requests queued since the last call
+    pub fn take_launch_requests(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.launch_requests)
+    }
+
+    /// Take the bus messages sent since the last call, oldest first
+    pub fn take_bus_messages(&mut self) -> Vec<(String, Vec<u8>)> {
+        std::mem::take(&mut self.bus_messages)
+    }
+
+    /// Store a new frame from the guest as the base layer
     ///
     /// Performance: Reuses existing buffer capacity when possible,
     /// only reallocates if the new frame is larger than current capacity.
     pub fn set_frame(&mut self, width: i32, height: i32, pixels: &[u8]) {
-        self.frame_width = width;
-        self.frame_height = height;
-        self.frame_len = pixels.len();
-        
-        // Reuse buffer if capacity is sufficient, otherwise grow
-        if self.frame_buffer.capacity() < pixels.len() {
-            // Reserve exact capacity to avoid over-allocation
-            self.frame_buffer.reserve(pixels.len() - self.frame_buffer.capacity());
-        }
-        
-        // Resize to exact length (cheap if capacity exists)
-        self.frame_buffer.resize(pixels.len(), 0);
-        
-        // Copy pixel data into reused buffer
-        self.frame_buffer.copy_from_slice(pixels);
-        self.frame_dirty = true;
-    }
-
-    /// Borrow the latest frame data if available (clears the dirty flag)
+        self.shared_frame = None;
+        self.indexed_size = None;
+        self.layers
+            .set(BASE_LAYER, width as u32, height as u32, pixels, 1.0);
+    }
+
+    /// Store a new frame in `format` from the guest as the base layer, expanded to RGBA
+    pub fn set_frame_in_format(
+        &mut self,
+        width: i32,
+        height: i32,
+        format: PixelFormat,
+        pixels: &[u8],
+    ) {
+        if format == PixelFormat::Rgba32 {
+            self.set_frame(width, height, pixels);
+            return;
+        }
+        if format == PixelFormat::Indexed8 {
+            self.indexed_frame.clear();
+            self.indexed_frame.extend_from_slice(pixels);
+            self.indexed_size = Some((width as u32, height as u32));
+        } else {
+            self.indexed_size = None;
+        }
+        format.to_rgba(pixels, &self.palette, &mut self.converted_frame);
+        self.shared_frame = None;
+        self.layers.set(
+            BASE_LAYER,
+            width as u32,
+            height as u32,
+            &self.converted_frame,
+            1.0,
+        );
+    }
+
+    /// Replace the palette of indexed frames with `entries`, 4 RGBA bytes each
+    ///
+    /// An indexed frame on screen is recolored, so palette cycling does not
+    /// require resubmitting it.
+    pub fn set_palette(&mut self, entries: &[u8]) {
+        self.palette.clear();
+        self.palette
+            .extend(entries.chunks_exact(4).map(|c| [c[0], c[1], c[2], c[3]]));
+        if let Some((width, height)) = self.indexed_size {
+            PixelFormat::Indexed8.to_rgba(
+                &self.indexed_frame,
+                &self.palette,
+                &mut self.converted_frame,
+            );
+            self.layers
+                .set(BASE_LAYER, width, height, &self.converted_frame, 1.0);
+        }
+    }
+
+    /// Record the guest memory offset of its shared framebuffer
+    pub fn set_framebuffer_ptr(&mut self, ptr: u32) {
+        self.framebuffer_ptr = Some(ptr);
+    }
+
+    /// Present a frame without copying it, if `ptr` is the shared framebuffer
     ///
-    /// Returns a reference to the internal buffer, avoiding ownership transfer.
+    /// Returns `false` when the frame must be copied instead: the guest passed
+    /// another buffer, or layers above the base need a composite.
+    pub fn present_shared_frame(&mut self, width: i32, height: i32, ptr: i32) -> bool {
+        if self.framebuffer_ptr != Some(ptr as u32) || self.layers.has_overlays() {
+            return false;
+        }
+        self.layers.remove(BASE_LAYER);
+        self.indexed_size = None;
+        self.shared_frame = Some((width as u32, height as u32));
+        self.shared_frame_dirty = true;
+        true
+    }
+
+    /// Size and offset of the shared frame, while it stands in for the base layer
+    pub fn shared_frame(&self) -> Option<(u32, u32, u32)> {
+        let (width, height) = self.shared_frame?;
+        Some((width, height, self.framebuffer_ptr?))
+    }
+
+    /// Size and offset of the shared frame if it changed since the last call
+    pub fn take_shared_frame(&mut self) -> Option<(u32, u32, u32)> {
+        if !std::mem::take(&mut self.shared_frame_dirty) {
+            return None;
+        }
+        self.shared_frame()
+    }
+
+    /// Start a `width` x `height` base layer filled with `rgba`, packed as
+    /// `0xRRGGBBAA`, for guests drawing only images
+    pub fn clear_canvas(&mut self, width: u32, height: u32, rgba: u32) {
+        self.converted_frame.clear();
+        for _ in 0..width as usize * height as usize {
+            self.converted_frame.extend_from_slice(&rgba.to_be_bytes());
+        }
+        self.shared_frame = None;
+        self.indexed_size = None;
+        self.layers
+            .set(BASE_LAYER, width, height, &self.converted_frame, 1.0);
+    }
+
+    /// Keep an RGBA image from the guest, returning its id or a `wapps_abi`
+    /// status
+    pub fn create_image(&mut self, width: u32, height: u32, pixels: &[u8]) -> i32 {
+        self.images
+            .create(width, height, pixels)
+            .unwrap_or(wapps_abi::OUT_OF_BOUNDS)
+    }
+
+    /// Free an image the guest no longer draws
+    pub fn destroy_image(&mut self, id: i32) {
+        self.images.destroy(id);
+    }
+
+    /// Queue an image to be drawn over the next frame, returning a `wapps_abi`
+    /// status
+    pub fn draw_image(&mut self, draw: ImageDraw) -> i32 {
+        if self.images.draw(draw) {
+            wapps_abi::OK
+        } else {
+            wapps_abi::OUT_OF_BOUNDS
+        }
+    }
+
+    /// Queue text to be drawn over the next frame, returning a `wapps_abi` status
+    pub fn draw_text(&mut self, draw: TextDraw) -> i32 {
+        if self.images.draw_text(draw) {
+            wapps_abi::OK
+        } else {
+            wapps_abi::OUT_OF_BOUNDS
+        }
+    }
+
+    /// Queue canvas drawing over the next frame, returning a `wapps_abi` status
+    pub fn draw_canvas(&mut self, draw: CanvasDraw) -> i32 {
+        if self.images.draw_canvas(draw) {
+            wapps_abi::OK
+        } else {
+            wapps_abi::OUT_OF_BOUNDS
+        }
+    }
+
+    /// Whether images are queued to be drawn over the next frame
+    pub fn has_image_draws(&self) -> bool {
+        self.images.has_draws()
+    }
+
+    /// Store a layer from the guest, composited over lower ids
+    pub fn set_layer(&mut self, id: i32, width: i32, height: i32, pixels: &[u8], opacity: f32) {
+        self.layers
+            .set(id, width as u32, height as u32, pixels, opacity);
+    }
+
+    /// Remove a layer the guest no longer draws
+    pub fn remove_layer(&mut self, id: i32) {
+        self.layers.remove(id);
+    }
+
+    /// Borrow the latest composited frame if available (clears the dirty flag)
+    ///
+    /// Returns a reference to an internal buffer, avoiding ownership transfer.
     /// The caller should use this data immediately before the next set_frame call.
     pub fn borrow_frame(&mut self) -> Option<(i32, i32, &[u8])> {
-        if self.frame_dirty {
-            self.frame_dirty = false;
-            Some((self.frame_width, self.frame_height, &self.frame_buffer[..self.frame_len]))
-        } else {
-            None
+        if !self.images.has_draws() {
+            let (width, height, pixels) = self.layers.take_composite()?;
+            self.sprite_size = None;
+            return Some((width as i32, height as i32, pixels));
+        }
+
+        // Queued images are drawn over every frame they are queued for
+        self.layers.invalidate();
+        let Some((width, height, pixels)) = self.layers.take_composite() else {
+            self.images.discard_draws();
+            return None;
+        };
+        self.sprite_frame.clear();
+        self.sprite_frame.extend_from_slice(pixels);
+        self.images.composite(&mut self.sprite_frame, width, height);
+        self.sprite_size = Some((width, height));
+        Some((width as i32, height as i32, &self.sprite_frame))
+    }
+
+    /// A copy of the latest frame presented through the layers, for screenshots
+    pub fn last_frame(&mut self) -> Option<(u32, u32, Vec<u8>)> {
+        match self.sprite_size {
+            Some((width, height)) => Some((width, height, self.sprite_frame.clone())),
+            None => self.layers.current_composite(),
         }
     }
 
+    /// Buffer interleaved samples pushed by the guest
+    pub fn push_audio(&mut self, format: AudioFormat, samples: &[f32]) {
+        self.audio.push(format, samples);
+    }
+
+    /// Take the audio pushed since the last call
+    pub fn take_audio(&mut self) -> Option<(AudioFormat, Vec<f32>)> {
+        self.audio.take()
+    }
+
+    /// Capture the microphone in `format` once the host opens it, dropping
+    /// samples captured in a previous format
+    pub fn start_audio_capture(&mut self, format: AudioFormat) {
+        self.lock_captured_audio().reset(Some(format));
+        self.capture_request = Some(CaptureRequest::Start(format));
+    }
+
+    /// Stop capturing the microphone, dropping the samples not read yet
+    pub fn stop_audio_capture(&mut self) {
+        self.lock_captured_audio().reset(None);
+        self.capture_request = Some(CaptureRequest::Stop);
+    }
+
+    /// Take up to `max_frames` frames of captured samples, oldest first,
+    /// returning the number of frames and the interleaved samples
+    pub fn read_audio_capture(&self, max_frames: usize) -> (usize, Vec<f32>) {
+        let mut captured = self.lock_captured_audio();
+        let samples = captured.read(max_frames);
+        (samples.len() / captured.channels(), samples)
+    }
+
+    /// Microphone start or stop asked for since the last call
+    pub fn take_capture_request(&mut self) -> Option<CaptureRequest> {
+        self.capture_request.take()
+    }
+
+    /// Buffer the app's capture device fills
+    pub fn captured_audio(&self) -> Arc<Mutex<CapturedAudio>> {
+        self.captured_audio.clone()
+    }
+
+    fn lock_captured_audio(&self) -> MutexGuard<'_, CapturedAudio> {
+        self.captured_audio
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record how many frames the audio device has yet to play
+    pub fn set_audio_device_frames(&mut self, frames: u32) {
+        self.audio_device_frames = frames;
+    }
+
+    /// Frames pushed by the guest and not yet played
+    pub fn audio_queued_frames(&self) -> u32 {
+        self.audio_device_frames + self.audio.frames()
+    }
+
     /// Get the current frame dimensions
     #[allow(dead_code)]
     pub fn frame_dimensions(&self) -> (i32, i32) {
-        (self.frame_width, self.frame_height)
+        let (width, height) = self.shared_frame.unwrap_or(self.layers.dimensions());
+        (width as i32, height as i32)
     }
 }
 
@@ -80,3 +1355,36 @@ impl Default for HostInterface {
         Self::new()
     }
 }
+
+/// Extensions of a `wapps::request_open_file` filter, a comma-separated list
+/// such as `"png, jpg"`; empty allows any file
+pub fn filter_extensions(filter: &str) -> Vec<String> {
+    filter
+        .split(',')
+        .map(|extension| {
+            extension
+                .trim()
+                .trim_start_matches('.')
+                .to_ascii_lowercase()
+        })
+        .filter(|extension| !extension.is_empty())
+        .collect()
+}
+
+/// Level of a `wapps::log` line: 1 error, 2 warn, 3 info, 4 debug, 5 trace;
+/// other values are clamped to the nearest
+pub fn log_level(level: i32) -> Level {
+    match level {
+        ..=1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        5.. => Level::Trace,
+    }
+}
+
+/// Whether `topic` names a bus topic: 1 to `MAX_BUS_TOPIC` bytes without
+/// control characters
+fn valid_bus_topic(topic: &str) -> bool {
+    !topic.is_empty() && topic.len() <= MAX_BUS_TOPIC && !topic.chars().any(char::is_control)
+}
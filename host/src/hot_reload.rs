@@ -0,0 +1,129 @@
+//! Hot Reload
+//!
+//! With `--watch`, the host polls the package (or, with `--watch=MODULE`, a
+//! .wasm build output) and swaps in the new module whenever the file changes,
+//! keeping the window, audio device and debug views open. The guest starts
+//! from a fresh instance and is told through its `on_reload` export; with
+//! `--watch-keep-memory` its linear memory is first restored from the previous
+//! instance, so apps whose state layout did not change carry on where they
+//! were. Builds that fail to load leave the running version in place.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::loader;
+use crate::signing::Keyring;
+
+/// How often the watched file is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What `--watch` reloads and how
+#[derive(Clone, Debug, Default)]
+pub struct WatchOptions {
+    /// Module to load instead of the package's, if given
+    pub module: Option<PathBuf>,
+    /// Restore the previous instance's linear memory into the new one
+    pub keep_memory: bool,
+}
+
+/// Modification time and length of a file, compared to detect changes
+type Stamp = (SystemTime, u64);
+
+/// Polls a file for changes
+pub struct FileWatcher {
+    path: PathBuf,
+    stamp: Option<Stamp>,
+    /// Changed stamp seen on the last poll, reported once it stops changing
+    pending: Option<Stamp>,
+    next_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path, now: Instant) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            stamp: stamp(path),
+            pending: None,
+            next_poll: now + POLL_INTERVAL,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file changed since it was last reported, checking at most
+    /// once per poll interval
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if now < self.next_poll {
+            return false;
+        }
+        self.next_poll = now + POLL_INTERVAL;
+        self.observe(stamp(&self.path))
+    }
+
+    /// Record the file's current stamp, returning whether it changed
+    ///
+    /// A change is only reported once two polls agree, so files still being
+    /// written by a build are not loaded half-way; missing files are ignored
+    /// as the build may be replacing them.
+    fn observe(&mut self, stamp: Option<Stamp>) -> bool {
+        let Some(stamp) = stamp.filter(|stamp| Some(*stamp) != self.stamp) else {
+            self.pending = None;
+            return false;
+        };
+        if self.pending == Some(stamp) {
+            self.stamp = Some(stamp);
+            self.pending = None;
+            true
+        } else {
+            self.pending = Some(stamp);
+            false
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Read the module of the package or bare .wasm file at `path`
+pub fn read_module(path: &Path, keyring: &Keyring) -> Result<Vec<u8>> {
+    let data = fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+    if data.starts_with(b"\0asm") {
+        return Ok(data);
+    }
+    let package = loader::parse_package(&data)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", path))?;
+    keyring.check(&package.metadata.name, package.signer.as_ref())?;
+    Ok(package.module().data.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_are_reported_once_settled() {
+        let mut watcher = FileWatcher::new(Path::new("missing.wasm"), Instant::now());
+        let first = (SystemTime::UNIX_EPOCH, 10);
+        let second = (SystemTime::UNIX_EPOCH + Duration::from_secs(1), 20);
+
+        // A new file is reported on the second poll that sees it
+        assert!(!watcher.observe(Some(first)));
+        assert!(watcher.observe(Some(first)));
+        assert!(!watcher.observe(Some(first)));
+
+        // Still being written: wait until it stops changing
+        assert!(!watcher.observe(Some((second.0, 15))));
+        assert!(!watcher.observe(Some(second)));
+        assert!(watcher.observe(Some(second)));
+
+        // Deleted during a rebuild
+        assert!(!watcher.observe(None));
+        assert!(!watcher.observe(Some(second)));
+    }
+}
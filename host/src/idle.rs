@@ -0,0 +1,127 @@
+//! Idle Detection
+//!
+//! Kiosk and museum installations switch to an attract mode once visitors walk
+//! away. With `--attract-after`, the host tracks when input was last received;
+//! once the period passes without any, guests are notified through their
+//! `on_idle` export, and when several apps run their windows are brought to
+//! the front in turn, one per period, until input resumes.
+
+use sdl2::event::Event;
+use std::time::{Duration, Instant};
+
+/// Tracks the time since the last user input
+pub struct IdleTimer {
+    period: Duration,
+    last_input: Instant,
+    /// Idle periods already reported since the last input
+    reported: u32,
+}
+
+impl IdleTimer {
+    pub fn new(period: Duration, now: Instant) -> Self {
+        Self {
+            period,
+            last_input: now,
+            reported: 0,
+        }
+    }
+
+    /// Record user input, leaving idle mode
+    pub fn input(&mut self, now: Instant) {
+        self.last_input = now;
+        self.reported = 0;
+    }
+
+    /// Number of whole idle periods elapsed, when it increased since the last
+    /// call: 1 when the host just became idle, 2 one period later, and so on
+    pub fn poll(&mut self, now: Instant) -> Option<u32> {
+        let idle = now.saturating_duration_since(self.last_input);
+        let periods = (idle.as_secs_f64() / self.period.as_secs_f64()) as u32;
+        if periods > self.reported {
+            self.reported = periods;
+            Some(periods)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether an SDL event comes from someone using the host (window management
+/// and other system events do not count)
+pub fn is_user_input(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::KeyDown { .. }
+            | Event::KeyUp { .. }
+            | Event::TextInput { .. }
+            | Event::MouseMotion { .. }
+            | Event::MouseButtonDown { .. }
+            | Event::MouseButtonUp { .. }
+            | Event::MouseWheel { .. }
+            | Event::FingerDown { .. }
+            | Event::FingerMotion { .. }
+            | Event::FingerUp { .. }
+            | Event::ControllerButtonDown { .. }
+            | Event::ControllerAxisMotion { .. }
+            | Event::JoyButtonDown { .. }
+            | Event::JoyAxisMotion { .. }
+    )
+}
+
+/// Parse a duration such as `120s`, `2m`, `1h` or `500ms`; plain numbers are seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration {:?}", value))?;
+    let seconds = match unit {
+        "" | "s" => number,
+        "ms" => number / 1000.0,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => {
+            return Err(format!(
+                "unknown duration unit {:?} (use ms, s, m or h)",
+                unit
+            ))
+        }
+    };
+    if seconds <= 0.0 {
+        return Err("duration must be positive".to_string());
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("120s"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("5 days").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    #[test]
+    fn test_idle_periods_are_reported_once() {
+        let start = Instant::now();
+        let mut timer = IdleTimer::new(Duration::from_secs(10), start);
+        assert_eq!(timer.poll(start + Duration::from_secs(9)), None);
+        assert_eq!(timer.poll(start + Duration::from_secs(10)), Some(1));
+        assert_eq!(timer.poll(start + Duration::from_secs(15)), None);
+        assert_eq!(timer.poll(start + Duration::from_secs(31)), Some(3));
+
+        timer.input(start + Duration::from_secs(32));
+        assert_eq!(timer.poll(start + Duration::from_secs(41)), None);
+        assert_eq!(timer.poll(start + Duration::from_secs(42)), Some(1));
+    }
+}
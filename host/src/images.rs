@@ -0,0 +1,234 @@
+//! Host-Side Images
+//!
+//! Sprite-based games redraw the same few images every frame. With
+//! `wapps::create_image` a guest uploads each RGBA image once, then queues
+//! `wapps::draw_image` calls with a position, scale and rotation every frame
+//! instead of sending a whole framebuffer. Draws are composited in call order
+//! over the frame's layers, or over a solid canvas started with
+//! `wapps::clear_canvas` when the guest sends no framebuffer at all, and are
+//! cleared once the frame is shown. Text from `wapps::draw_text` and canvas
+//! drawing (`wapps::clear`, `wapps::fill_rect` and `wapps::blit`) are queued
+//! with them.
+
+use std::collections::HashMap;
+
+use crate::canvas::{self, CanvasDraw, MAX_BLIT_BYTES};
+use crate::layers::blend_pixel;
+use crate::text::{self, TextDraw};
+
+/// Most images a guest may keep at once
+pub const MAX_IMAGES: usize = 4096;
+
+/// Most bytes of pixels a guest may keep in images
+pub const MAX_IMAGE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Most draws queued per frame
+pub const MAX_DRAWS: usize = 65536;
+
+/// An uploaded RGBA image
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// A queued `wapps::draw_image` call
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageDraw {
+    pub id: i32,
+    /// Frame position of the image's center
+    pub x: f32,
+    pub y: f32,
+    pub scale: f32,
+    /// Clockwise, in radians
+    pub rotation: f32,
+}
+
+/// A queued image, text or canvas draw
+enum Draw {
+    Image(ImageDraw),
+    Text(TextDraw),
+    Canvas(CanvasDraw),
+}
+
+/// A guest's uploaded images and the draws queued for the next frame
+#[derive(Default)]
+pub struct ImageStore {
+    images: HashMap<i32, Image>,
+    next_id: i32,
+    bytes: usize,
+    draws: Vec<Draw>,
+    /// Bytes of blitted pixels in `draws`
+    blit_bytes: usize,
+}
+
+impl ImageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep a `width` x `height` RGBA image, returning its id, or `None`
+    /// when the image limits would be exceeded
+    pub fn create(&mut self, width: u32, height: u32, pixels: &[u8]) -> Option<i32> {
+        if self.images.len() >= MAX_IMAGES || self.bytes + pixels.len() > MAX_IMAGE_BYTES {
+            return None;
+        }
+        self.next_id += 1;
+        self.bytes += pixels.len();
+        self.images.insert(
+            self.next_id,
+            Image {
+                width,
+                height,
+                pixels: pixels.to_vec(),
+            },
+        );
+        Some(self.next_id)
+    }
+
+    /// Free image `id`, returning whether it existed
+    pub fn destroy(&mut self, id: i32) -> bool {
+        let Some(image) = self.images.remove(&id) else {
+            return false;
+        };
+        self.bytes -= image.pixels.len();
+        true
+    }
+
+    /// Queue a draw for the next frame, returning whether the image exists
+    /// and the queue has room
+    pub fn draw(&mut self, draw: ImageDraw) -> bool {
+        if !self.images.contains_key(&draw.id) || self.draws.len() >= MAX_DRAWS {
+            return false;
+        }
+        self.draws.push(Draw::Image(draw));
+        true
+    }
+
+    /// Queue text for the next frame, returning whether the queue has room
+    pub fn draw_text(&mut self, draw: TextDraw) -> bool {
+        if self.draws.len() >= MAX_DRAWS {
+            return false;
+        }
+        self.draws.push(Draw::Text(draw));
+        true
+    }
+
+    /// Queue canvas drawing for the next frame, returning whether the queue
+    /// has room
+    pub fn draw_canvas(&mut self, draw: CanvasDraw) -> bool {
+        let bytes = match &draw {
+            CanvasDraw::Blit { pixels, .. } => pixels.len(),
+            _ => 0,
+        };
+        if self.draws.len() >= MAX_DRAWS || self.blit_bytes + bytes > MAX_BLIT_BYTES {
+            return false;
+        }
+        self.blit_bytes += bytes;
+        self.draws.push(Draw::Canvas(draw));
+        true
+    }
+
+    /// Whether draws are queued for the next frame
+    pub fn has_draws(&self) -> bool {
+        !self.draws.is_empty()
+    }
+
+    /// Drop the queued draws, e.g. when there is no frame to draw them on
+    pub fn discard_draws(&mut self) {
+        self.draws.clear();
+        self.blit_bytes = 0;
+    }
+
+    /// Composite the queued draws over the `width` x `height` RGBA `target`
+    /// and clear the queue
+    pub fn composite(&mut self, target: &mut [u8], width: u32, height: u32) {
+        for draw in self.draws.drain(..) {
+            match draw {
+                Draw::Image(draw) => {
+                    if let Some(image) = self.images.get(&draw.id) {
+                        draw_transformed(target, width, height, image, &draw);
+                    }
+                }
+                Draw::Text(draw) => text::draw(target, width, height, &draw),
+                Draw::Canvas(draw) => canvas::draw(target, width, height, &draw),
+            }
+        }
+        self.blit_bytes = 0;
+    }
+}
+
+/// Draw `image` into `target`, sampling the nearest source pixel of each
+/// target pixel the transformed image covers
+fn draw_transformed(target: &mut [u8], width: u32, height: u32, image: &Image, draw: &ImageDraw) {
+    if !draw.scale.is_finite() || draw.scale <= 0.0 || !draw.x.is_finite() || !draw.y.is_finite() {
+        return;
+    }
+    let (sin, cos) = draw.rotation.sin_cos();
+    if !sin.is_finite() {
+        return;
+    }
+    let (half_w, half_h) = (image.width as f32 / 2.0, image.height as f32 / 2.0);
+
+    // Bounding box of the rotated, scaled image, clipped to the target
+    let extent_x = (half_w * cos.abs() + half_h * sin.abs()) * draw.scale;
+    let extent_y = (half_w * sin.abs() + half_h * cos.abs()) * draw.scale;
+    let x0 = (draw.x - extent_x).floor().max(0.0) as u32;
+    let y0 = (draw.y - extent_y).floor().max(0.0) as u32;
+    let x1 = ((draw.x + extent_x).ceil().max(0.0) as u32).min(width);
+    let y1 = ((draw.y + extent_y).ceil().max(0.0) as u32).min(height);
+
+    for py in y0..y1 {
+        for px in x0..x1 {
+            // Map the pixel center back into image space
+            let dx = px as f32 + 0.5 - draw.x;
+            let dy = py as f32 + 0.5 - draw.y;
+            let u = (dx * cos + dy * sin) / draw.scale + half_w;
+            let v = (dy * cos - dx * sin) / draw.scale + half_h;
+            if u < 0.0 || v < 0.0 || u >= image.width as f32 || v >= image.height as f32 {
+                continue;
+            }
+            let src = (v as usize * image.width as usize + u as usize) * 4;
+            let dst = (py as usize * width as usize + px as usize) * 4;
+            blend_pixel(&mut target[dst..dst + 4], &image.pixels[src..src + 4], 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn test_draws_are_transformed_and_cleared() {
+        let mut store = ImageStore::new();
+        // 2x1 image: red on the left, blue on the right
+        let id = store.create(2, 1, &[RED, BLUE].concat()).unwrap();
+        let draw = |x, y, scale, rotation| ImageDraw {
+            id,
+            x,
+            y,
+            scale,
+            rotation,
+        };
+
+        let mut target = vec![0u8; 4 * 4 * 4];
+        assert!(store.draw(draw(2.0, 0.5, 1.0, 0.0)));
+        // Turned half a turn and doubled, on the last two rows
+        assert!(store.draw(draw(2.0, 3.0, 2.0, std::f32::consts::PI)));
+        store.composite(&mut target, 4, 4);
+        assert!(!store.has_draws());
+
+        let pixel = |x: usize, y: usize| &target[(y * 4 + x) * 4..][..4];
+        assert_eq!((pixel(1, 0), pixel(2, 0)), (&RED[..], &BLUE[..]));
+        assert_eq!(pixel(0, 1), &[0, 0, 0, 0]);
+        assert_eq!((pixel(0, 2), pixel(1, 3)), (&BLUE[..], &BLUE[..]));
+        assert_eq!((pixel(2, 2), pixel(3, 3)), (&RED[..], &RED[..]));
+
+        assert!(store.destroy(id));
+        assert!(!store.draw(draw(0.0, 0.0, 1.0, 0.0)));
+    }
+}
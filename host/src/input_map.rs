@@ -0,0 +1,190 @@
+//! Input Remapping
+//!
+//! `--input-map FILE` remaps keys before they reach apps, and turns game
+//! controller buttons into key presses for apps that only read the keyboard.
+//! A map is a TOML file with a `[keys]` table, from the key pressed to the key
+//! the app sees, and a `[gamepad]` table, from a controller button to the key
+//! it presses:
+//!
+//! ```toml
+//! # Swap WASD and the arrow keys
+//! [keys]
+//! W = "Up"
+//! Up = "W"
+//!
+//! [gamepad]
+//! a = "Space"
+//! start = "Return"
+//! dpup = "Up"
+//! ```
+//!
+//! Keys go by their SDL scancode names ("A", "Left Shift", "Keypad 8"),
+//! buttons by their SDL controller button names ("a", "b", "x", "y", "back",
+//! "start", "leftshoulder", "dpup", ...). Mappings are applied once, so maps
+//! can swap keys. A file named after a package's id in `input/` under the user
+//! data directory (e.g. `input/Life-<hash>.toml`, see `WappPackage::id`)
+//! overrides the entries of the global map for that app, and applies even
+//! without `--input-map`. The id does not change with the locale, so neither
+//! does the file.
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use sdl2::controller::Button;
+use sdl2::keyboard::Scancode;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::events::GuestEvent;
+use crate::storage;
+
+/// An input map file, with keys and buttons by name
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MapFile {
+    #[serde(default)]
+    keys: BTreeMap<String, String>,
+    #[serde(default)]
+    gamepad: BTreeMap<String, String>,
+}
+
+/// Key and controller button remapping, by scancode and button
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputMap {
+    keys: HashMap<i32, i32>,
+    buttons: HashMap<i32, i32>,
+}
+
+impl InputMap {
+    /// Read the input map at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read input map: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid input map: {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let file: MapFile = toml::from_str(text)?;
+        let mut map = Self::default();
+        for (from, to) in &file.keys {
+            map.keys.insert(scancode(from)?, scancode(to)?);
+        }
+        for (button, key) in &file.gamepad {
+            let Some(button) = Button::from_string(button) else {
+                bail!("unknown controller button {:?}", button);
+            };
+            map.buttons.insert(button as i32, scancode(key)?);
+        }
+        Ok(map)
+    }
+
+    /// The map of the package with id `id`: `global` with the entries of the
+    /// app's override file, if either exists
+    pub fn for_app(global: Option<&InputMap>, id: &str) -> Option<InputMap> {
+        let path = override_path(id).filter(|path| path.exists());
+        let overrides = match path.as_deref().map(|path| (path, Self::load(path))) {
+            Some((path, Ok(overrides))) => {
+                info!("Using the input map {}", path.display());
+                Some(overrides)
+            }
+            Some((_, Err(e))) => {
+                warn!("{:#}", e);
+                None
+            }
+            None => None,
+        };
+        match (global, overrides) {
+            (Some(global), Some(overrides)) => {
+                let mut map = global.clone();
+                map.keys.extend(overrides.keys);
+                map.buttons.extend(overrides.buttons);
+                Some(map)
+            }
+            (global, overrides) => overrides.or_else(|| global.cloned()),
+        }
+    }
+
+    /// `event` with its key remapped
+    pub fn map_event(&self, event: GuestEvent) -> GuestEvent {
+        let map = |scancode| *self.keys.get(&scancode).unwrap_or(&scancode);
+        match event {
+            GuestEvent::KeyDown {
+                scancode,
+                modifiers,
+                repeat,
+            } => GuestEvent::KeyDown {
+                scancode: map(scancode),
+                modifiers,
+                repeat,
+            },
+            GuestEvent::KeyUp { scancode } => GuestEvent::KeyUp {
+                scancode: map(scancode),
+            },
+            other => other,
+        }
+    }
+
+    /// The key event a controller `button` press or release maps to, if any
+    pub fn button_event(&self, button: Button, pressed: bool) -> Option<GuestEvent> {
+        let scancode = *self.buttons.get(&(button as i32))?;
+        Some(if pressed {
+            GuestEvent::KeyDown {
+                scancode,
+                modifiers: 0,
+                repeat: false,
+            }
+        } else {
+            GuestEvent::KeyUp { scancode }
+        })
+    }
+}
+
+/// Scancode of the key named `name`
+fn scancode(name: &str) -> Result<i32> {
+    match Scancode::from_name(name) {
+        Some(scancode) => Ok(scancode as i32),
+        None => bail!("unknown key {:?}", name),
+    }
+}
+
+/// Input map overriding the global one for the package with id `id`
+fn override_path(id: &str) -> Option<PathBuf> {
+    let file_name = format!("{}.toml", storage::file_stem(id));
+    Some(storage::data_dir()?.join("input").join(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swaps_keys_and_maps_buttons() {
+        let map = InputMap::parse(
+            r#"
+            [keys]
+            W = "Up"
+            Up = "W"
+
+            [gamepad]
+            a = "Space"
+            "#,
+        )
+        .unwrap();
+        let key_up = |scancode: Scancode| GuestEvent::KeyUp {
+            scancode: scancode as i32,
+        };
+        assert_eq!(map.map_event(key_up(Scancode::W)), key_up(Scancode::Up));
+        assert_eq!(map.map_event(key_up(Scancode::Up)), key_up(Scancode::W));
+        assert_eq!(map.map_event(key_up(Scancode::A)), key_up(Scancode::A));
+        assert_eq!(
+            map.button_event(Button::A, false),
+            Some(key_up(Scancode::Space))
+        );
+        assert_eq!(map.button_event(Button::B, true), None);
+
+        assert!(InputMap::parse("[keys]\nW = \"Nope\"").is_err());
+        assert!(InputMap::parse("[gamepad]\nz = \"W\"").is_err());
+        assert!(InputMap::parse("[mouse]").is_err());
+    }
+}
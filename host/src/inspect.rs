@@ -0,0 +1,142 @@
+//! `wapps inspect` Command
+//!
+//! Prints a package's metadata without running it. With `--licenses`, prints
+//! only the license of the app and its bundled assets, flagging missing or
+//! malformed declarations, so distributors can audit packages before
+//! redistributing them.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use std::path::PathBuf;
+
+use crate::codec::CodecRegistry;
+use crate::license;
+use crate::loader::{self, WappMetadata, WappPackage};
+use crate::signing;
+
+/// Arguments of `wapps inspect`
+#[derive(Args, Debug)]
+pub struct InspectArgs {
+    /// Package to inspect
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// Only list the license of the app and its bundled assets
+    #[arg(long)]
+    licenses: bool,
+}
+
+/// Run `wapps inspect`
+pub fn run(args: &InspectArgs) -> Result<()> {
+    let package = loader::load_package(&args.file)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", args.file))?;
+
+    if !args.licenses {
+        print_metadata(&package.metadata);
+        print_sections(&package);
+        println!();
+    }
+    print_licenses(&package.metadata);
+    Ok(())
+}
+
+fn print_metadata(metadata: &WappMetadata) {
+    println!("Name:        {}", metadata.name);
+    if !metadata.version.is_empty() {
+        println!("Version:     {}", metadata.version);
+    }
+    if !metadata.description.is_empty() {
+        println!("Description: {}", metadata.description);
+    }
+    if let Some(author) = &metadata.author {
+        println!("Author:      {}", author);
+    }
+    if let Some(homepage) = &metadata.homepage {
+        println!("Homepage:    {}", homepage);
+    }
+    if let Some(min_host_version) = &metadata.min_host_version {
+        println!("Needs host:  {} or newer", min_host_version);
+    }
+    if let Some(rating) = metadata.age_rating {
+        println!("Age rating:  {}+", rating);
+    }
+    if !metadata.locales.is_empty() {
+        let mut locales: Vec<_> = metadata.locales.keys().map(String::as_str).collect();
+        locales.sort_unstable();
+        println!("Locales:     {}", locales.join(", "));
+    }
+    if let Some(clock) = metadata.wasi.clock {
+        println!("Clock:       {:?}", clock);
+    }
+    if let Some(seed) = metadata.wasi.random_seed {
+        println!("Random seed: {}", seed);
+    }
+    if !metadata.strings.is_empty() {
+        println!("Strings:     {}", metadata.strings.len());
+    }
+    match &metadata.capabilities {
+        Some(capabilities) => {
+            let names: Vec<&str> = capabilities.iter().map(|c| c.name()).collect();
+            println!("Capabilities: {}", names.join(", "));
+        }
+        None => println!("Capabilities: (no manifest, all imports linked)"),
+    }
+    if !metadata.allowed_hosts.is_empty() {
+        println!("Hosts:       {}", metadata.allowed_hosts.join(", "));
+    }
+}
+
+fn print_sections(package: &WappPackage) {
+    println!("Format:      version {}", package.format_version);
+    if let Some(signer) = &package.signer {
+        println!("Signed by:   {}", signing::to_hex(signer));
+    }
+    let codecs = CodecRegistry::new();
+    for section in &package.sections {
+        let label = match section.name.as_str() {
+            "" => format!("{:?}:", section.kind),
+            name => format!("{:?} {}:", section.kind, name),
+        };
+        let codec = codecs
+            .get(section.codec)
+            .map_or("unknown", |codec| codec.name());
+        println!(
+            "{:<12} {} bytes ({}, {} stored)",
+            label,
+            section.data.len(),
+            codec,
+            section.stored_len
+        );
+    }
+}
+
+fn print_licenses(metadata: &WappMetadata) {
+    match &metadata.license {
+        Some(expression) => println!("License:     {}{}", expression, validity_note(expression)),
+        None => println!("License:     (none declared)"),
+    }
+
+    if metadata.asset_licenses.is_empty() {
+        return;
+    }
+    println!("Assets:");
+    for asset in &metadata.asset_licenses {
+        println!(
+            "  {}: {}{}",
+            asset.path,
+            asset.license,
+            validity_note(&asset.license)
+        );
+        if let Some(attribution) = &asset.attribution {
+            println!("    {}", attribution);
+        }
+    }
+}
+
+/// Suffix flagging a malformed SPDX expression
+fn validity_note(expression: &str) -> String {
+    match license::validate_expression(expression) {
+        Ok(()) => String::new(),
+        Err(problem) => format!(" (invalid SPDX expression: {})", problem),
+    }
+}
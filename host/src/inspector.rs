@@ -0,0 +1,185 @@
+//! Pixel Inspector
+//!
+//! Debug view that shows a magnified loupe around the framebuffer pixel under
+//! the cursor, along with its coordinate and RGBA value. The overlay is drawn
+//! by the host on top of the presented frame, so guests are unaffected.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::font;
+
+/// Pixels shown on each side of the inspected pixel
+const LOUPE_RADIUS: i32 = 5;
+/// On-screen size of each magnified pixel
+const LOUPE_SCALE: i32 = 10;
+/// Distance between the cursor and the loupe
+const CURSOR_OFFSET: i32 = 16;
+/// On-screen size of each font pixel in the readout
+const TEXT_SCALE: i32 = 2;
+/// Padding around the loupe and readout
+const PADDING: i32 = 4;
+
+/// A filled rectangle drawn over the presented frame
+pub type OverlayRect = (Rect, Color);
+
+/// Inspects the pixel under the cursor in the latest guest frame
+pub struct PixelInspector {
+    /// Copy of the latest frame, since the host interface only hands out new ones
+    frame: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl PixelInspector {
+    pub fn new() -> Self {
+        Self {
+            frame: Vec::new(),
+            width: 0,
+            height: 0,
+        }
+    }
+
+    /// Keep a copy of a new guest frame for inspection
+    pub fn capture(&mut self, width: u32, height: u32, pixels: &[u8]) {
+        self.frame.clear();
+        self.frame.extend_from_slice(pixels);
+        self.width = width;
+        self.height = height;
+    }
+
+    /// RGBA value of the framebuffer pixel at (`x`, `y`)
+    pub fn pixel(&self, x: i32, y: i32) -> Option<[u8; 4]> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        let rgba = self.frame.get(offset..offset + 4)?;
+        Some([rgba[0], rgba[1], rgba[2], rgba[3]])
+    }
+
+    /// Loupe and readout for framebuffer pixel `target`, placed next to the
+    /// window-space `cursor` and kept inside a window of `window_size`
+    pub fn overlay(
+        &self,
+        cursor: (i32, i32),
+        target: (u32, u32),
+        window_size: (u32, u32),
+    ) -> Vec<OverlayRect> {
+        let (tx, ty) = (target.0 as i32, target.1 as i32);
+        let Some(rgba) = self.pixel(tx, ty) else {
+            return Vec::new();
+        };
+
+        let position = format!("{},{}", tx, ty);
+        let value = format!(
+            "{:02X}{:02X}{:02X}{:02X}",
+            rgba[0], rgba[1], rgba[2], rgba[3]
+        );
+
+        let loupe_size = (LOUPE_RADIUS * 2 + 1) * LOUPE_SCALE;
+        let line_height = font::line_height(TEXT_SCALE);
+        let panel_width = loupe_size + PADDING * 2;
+        let panel_height = loupe_size + PADDING * 3 + line_height * 2;
+
+        // Place the panel below-right of the cursor, flipping when it would leave the window
+        let (window_width, window_height) = (window_size.0 as i32, window_size.1 as i32);
+        let mut left = cursor.0 + CURSOR_OFFSET;
+        if left + panel_width > window_width {
+            left = cursor.0 - CURSOR_OFFSET - panel_width;
+        }
+        let mut top = cursor.1 + CURSOR_OFFSET;
+        if top + panel_height > window_height {
+            top = cursor.1 - CURSOR_OFFSET - panel_height;
+        }
+
+        let mut rects = vec![(
+            rect(left, top, panel_width, panel_height),
+            Color::RGB(0, 0, 0),
+        )];
+
+        // Magnified neighborhood, with pixels outside the frame left black
+        let (loupe_left, loupe_top) = (left + PADDING, top + PADDING);
+        for dy in -LOUPE_RADIUS..=LOUPE_RADIUS {
+            for dx in -LOUPE_RADIUS..=LOUPE_RADIUS {
+                if let Some([r, g, b, _]) = self.pixel(tx + dx, ty + dy) {
+                    rects.push((
+                        rect(
+                            loupe_left + (dx + LOUPE_RADIUS) * LOUPE_SCALE,
+                            loupe_top + (dy + LOUPE_RADIUS) * LOUPE_SCALE,
+                            LOUPE_SCALE,
+                            LOUPE_SCALE,
+                        ),
+                        Color::RGB(r, g, b),
+                    ));
+                }
+            }
+        }
+
+        // Outline the inspected pixel
+        let center_left = loupe_left + LOUPE_RADIUS * LOUPE_SCALE;
+        let center_top = loupe_top + LOUPE_RADIUS * LOUPE_SCALE;
+        let white = Color::RGB(255, 255, 255);
+        rects.extend([
+            (
+                rect(center_left - 1, center_top - 1, LOUPE_SCALE + 2, 1),
+                white,
+            ),
+            (
+                rect(
+                    center_left - 1,
+                    center_top + LOUPE_SCALE,
+                    LOUPE_SCALE + 2,
+                    1,
+                ),
+                white,
+            ),
+            (rect(center_left - 1, center_top, 1, LOUPE_SCALE), white),
+            (
+                rect(center_left + LOUPE_SCALE, center_top, 1, LOUPE_SCALE),
+                white,
+            ),
+        ]);
+
+        let text_top = loupe_top + loupe_size + PADDING;
+        font::draw_text(
+            &mut rects, &position, loupe_left, text_top, TEXT_SCALE, white,
+        );
+        font::draw_text(
+            &mut rects,
+            &value,
+            loupe_left,
+            text_top + line_height,
+            TEXT_SCALE,
+            white,
+        );
+
+        rects
+    }
+}
+
+impl Default for PixelInspector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+    Rect::new(x, y, width as u32, height as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_lookup() {
+        let mut inspector = PixelInspector::new();
+        inspector.capture(2, 1, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(inspector.pixel(1, 0), Some([5, 6, 7, 8]));
+        assert_eq!(inspector.pixel(2, 0), None);
+        assert_eq!(inspector.pixel(-1, 0), None);
+        assert!(inspector.overlay((0, 0), (5, 5), (800, 600)).is_empty());
+        assert!(!inspector.overlay((0, 0), (0, 0), (800, 600)).is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+//! Input Latency Measurement
+//!
+//! `--measure-latency` periodically queues a synthetic marker input for each
+//! app and times how long the app takes to show a response: from the moment
+//! the marker is queued to the present of the first frame whose pixels differ
+//! from the frame on screen at that moment. This covers event queueing, the
+//! guest update, frame upload and rendering, so it validates changes to frame
+//! pacing and buffering. Apps that animate continuously change every frame,
+//! so results are only meaningful for apps that redraw in response to input.
+
+use clap::ValueEnum;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::events::GuestEvent;
+
+/// Pause between a marker being answered (or given up on) and the next one
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time after which a marker counts as unanswered
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// USB HID scancode of the space bar
+const SPACE_SCANCODE: i32 = 44;
+
+/// Synthetic input used as a latency marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LatencyMarker {
+    /// Press and release the space bar
+    Key,
+    /// Click the left button at the center of the viewport
+    Pointer,
+}
+
+impl LatencyMarker {
+    /// Events making up the marker, in a `width` x `height` viewport
+    pub fn events(self, width: i32, height: i32) -> [GuestEvent; 2] {
+        let (x, y) = (width / 2, height / 2);
+        match self {
+            LatencyMarker::Key => [
+                GuestEvent::KeyDown {
+                    scancode: SPACE_SCANCODE,
+                    modifiers: 0,
+                    repeat: false,
+                },
+                GuestEvent::KeyUp {
+                    scancode: SPACE_SCANCODE,
+                },
+            ],
+            LatencyMarker::Pointer => [
+                GuestEvent::PointerDown { x, y, button: 1 },
+                GuestEvent::PointerUp { x, y, button: 1 },
+            ],
+        }
+    }
+}
+
+/// Marker injection and response timing for one app
+pub struct LatencyProbe {
+    marker: LatencyMarker,
+    /// Hash of the frame on screen
+    current_frame: Option<u64>,
+    /// When the pending marker was queued and the frame on screen then
+    pending: Option<(Instant, Option<u64>)>,
+    /// Earliest time to queue the next marker
+    next_at: Instant,
+    samples: Vec<Duration>,
+    unanswered: u32,
+}
+
+impl LatencyProbe {
+    pub fn new(marker: LatencyMarker, now: Instant) -> Self {
+        Self {
+            marker,
+            current_frame: None,
+            pending: None,
+            next_at: now + INTERVAL,
+            samples: Vec::new(),
+            unanswered: 0,
+        }
+    }
+
+    /// The marker to queue now, if one is due
+    ///
+    /// A marker left unanswered for too long is given up on first.
+    pub fn poll(&mut self, now: Instant) -> Option<LatencyMarker> {
+        if let Some((queued_at, _)) = self.pending {
+            if now.duration_since(queued_at) < TIMEOUT {
+                return None;
+            }
+            self.pending = None;
+            self.unanswered += 1;
+            self.next_at = now + INTERVAL;
+        }
+        if now < self.next_at {
+            return None;
+        }
+        self.pending = Some((now, self.current_frame));
+        Some(self.marker)
+    }
+
+    /// Record a frame with hash `hash` presented at `now`
+    pub fn record_frame(&mut self, hash: u64, now: Instant) {
+        self.current_frame = Some(hash);
+        if let Some((queued_at, before)) = self.pending {
+            if before != Some(hash) {
+                self.samples.push(now.duration_since(queued_at));
+                self.pending = None;
+                self.next_at = now + INTERVAL;
+            }
+        }
+    }
+
+    /// Statistics over the markers answered so far
+    pub fn report(&self, app: &str) -> LatencyReport {
+        let mut samples = self.samples.clone();
+        samples.sort();
+        let percentile = |p: usize| {
+            samples
+                .get((samples.len() * p / 100).min(samples.len().saturating_sub(1)))
+                .copied()
+        };
+        LatencyReport {
+            app: app.to_string(),
+            answered: samples.len(),
+            unanswered: self.unanswered,
+            min: samples.first().copied(),
+            median: percentile(50),
+            p95: percentile(95),
+            max: samples.last().copied(),
+        }
+    }
+}
+
+/// Event-to-photon latency of one app, printed on exit
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyReport {
+    pub app: String,
+    pub answered: usize,
+    pub unanswered: u32,
+    pub min: Option<Duration>,
+    pub median: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (Some(min), Some(median), Some(p95), Some(max)) =
+            (self.min, self.median, self.p95, self.max)
+        else {
+            return write!(
+                f,
+                "{}: input latency unknown, {} markers unanswered",
+                self.app, self.unanswered
+            );
+        };
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{}: input latency over {} markers: min {:.1} ms, median {:.1} ms, p95 {:.1} ms, max {:.1} ms ({} unanswered)",
+            self.app,
+            self.answered,
+            ms(min),
+            ms(median),
+            ms(p95),
+            ms(max),
+            self.unanswered
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markers_are_timed_until_the_frame_changes() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut probe = LatencyProbe::new(LatencyMarker::Key, start);
+        probe.record_frame(1, at(10));
+        assert_eq!(probe.poll(at(500)), None);
+
+        assert_eq!(probe.poll(at(1000)), Some(LatencyMarker::Key));
+        assert_eq!(probe.poll(at(1010)), None);
+        probe.record_frame(1, at(1016));
+        probe.record_frame(2, at(1040));
+
+        // The next marker is never answered
+        assert_eq!(probe.poll(at(2040)), Some(LatencyMarker::Key));
+        probe.record_frame(2, at(2100));
+        assert_eq!(probe.poll(at(3100)), None);
+        assert_eq!(probe.poll(at(4100)), Some(LatencyMarker::Key));
+        probe.record_frame(3, at(4120));
+
+        let report = probe.report("App");
+        assert_eq!((report.answered, report.unanswered), (2, 1));
+        assert_eq!(report.min, Some(Duration::from_millis(20)));
+        assert_eq!(report.max, Some(Duration::from_millis(40)));
+    }
+}
@@ -0,0 +1,531 @@
+//! App Launcher
+//!
+//! Given a directory instead of a package, the host opens a gallery of the
+//! `.wapp` files inside: a grid of tiles showing each package's icon, name,
+//! version, author and description. Clicking a tile, or picking it with the arrow keys and
+//! Enter, runs the app with the host's usual options. Esc in the app, or the
+//! app quitting or crashing, brings the gallery back, rescanned so packages
+//! added meanwhile show up. Esc in the gallery, or closing it, quits.
+//!
+//! The gallery is drawn in software into a frame the size of the window and
+//! presented like a guest frame, with the overlay font for its text.
+
+use anyhow::{bail, Context, Result};
+use log::{error, info, warn};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::font;
+use crate::graphics::{FrameSink, GraphicsContext};
+use crate::loader;
+use crate::locale;
+use crate::orientation::Orientation;
+use crate::png;
+use crate::presenter::{Backend, VsyncMode};
+
+const WINDOW_WIDTH: u32 = 800;
+const WINDOW_HEIGHT: u32 = 600;
+/// Time between gallery frames
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+const TILE_WIDTH: i32 = 240;
+const TILE_HEIGHT: i32 = 200;
+/// Space between tiles and around the grid
+const MARGIN: i32 = 16;
+/// Space between a tile's edge and its contents
+const PADDING: i32 = 12;
+const ICON_SIZE: i32 = 96;
+const NAME_SCALE: i32 = 3;
+const DESCRIPTION_SCALE: i32 = 2;
+const DESCRIPTION_LINES: usize = 3;
+/// Pixels scrolled per mouse wheel step
+const SCROLL_STEP: i32 = 48;
+
+const BACKGROUND: Color = Color::RGB(24, 24, 32);
+const TILE_COLOR: Color = Color::RGB(44, 44, 58);
+const SELECTED_COLOR: Color = Color::RGB(70, 70, 110);
+const ICON_PLACEHOLDER: Color = Color::RGB(90, 90, 110);
+const NAME_COLOR: Color = Color::RGB(255, 255, 255);
+const DESCRIPTION_COLOR: Color = Color::RGB(170, 170, 190);
+const BYLINE_COLOR: Color = Color::RGB(130, 130, 160);
+
+/// A package shown in the gallery
+struct Entry {
+    path: PathBuf,
+    name: String,
+    /// Version and author, e.g. "v1.2.0 by Ada"; empty if the package has neither
+    byline: String,
+    description: String,
+    /// Decoded RGBA icon, if the package has a valid one
+    icon: Option<(u32, u32, Vec<u8>)>,
+}
+
+/// Show the gallery of `dir`, presented with `backend`, until the user quits,
+/// running each chosen package with `launch`
+pub fn run(
+    dir: &Path,
+    kiosk: bool,
+    backend: Backend,
+    orientation: Orientation,
+    mut launch: impl FnMut(&Path) -> Result<()>,
+) -> Result<()> {
+    loop {
+        let entries = scan(dir)?;
+        if entries.is_empty() {
+            bail!("No .wapp files in {}", dir.display());
+        }
+        let Some(path) = choose(Gallery::new(entries), kiosk, backend, orientation)? else {
+            return Ok(());
+        };
+        info!("Launching {} from the gallery", path.display());
+        if let Err(e) = launch(&path) {
+            error!("{:#}", e);
+        }
+    }
+}
+
+/// The packages in `dir`, sorted by file name; unreadable ones are skipped
+fn scan(dir: &Path) -> Result<Vec<Entry>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Could not read directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("wapp"))
+        })
+        .collect();
+    paths.sort();
+
+    let locale = locale::user_locale();
+    let entries = paths
+        .into_iter()
+        .filter_map(|path| {
+            let package = match loader::load_package(&path) {
+                Ok(package) => package,
+                Err(e) => {
+                    warn!("Skipping {}: {:#}", path.display(), e);
+                    return None;
+                }
+            };
+            let icon = package.icon().and_then(|data| png::decode_rgba(data).ok());
+            let localized = locale::localize(&package.metadata, locale.as_deref());
+            let name = if localized.name.is_empty() {
+                path.file_stem()?.to_string_lossy().into_owned()
+            } else {
+                localized.name
+            };
+            let metadata = &package.metadata;
+            Some(Entry {
+                path,
+                name,
+                byline: byline(&metadata.version, metadata.author.as_deref()),
+                description: localized.description,
+                icon,
+            })
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Show `gallery` in its own window, returning the package picked, or `None`
+/// once the user quits
+fn choose(
+    mut gallery: Gallery,
+    kiosk: bool,
+    backend: Backend,
+    orientation: Orientation,
+) -> Result<Option<PathBuf>> {
+    let mut context = GraphicsContext::new().context("Failed to initialize graphics")?;
+    context.set_backend(backend);
+    context.set_orientation(orientation);
+    if kiosk {
+        context.hide_cursor();
+    }
+    let mut graphics =
+        context.create_window("WAPPS", WINDOW_WIDTH, WINDOW_HEIGHT, VsyncMode::Off)?;
+    if kiosk {
+        graphics.set_fullscreen(true)?;
+    }
+
+    loop {
+        let (width, height) = graphics.window_size();
+        for event in context.poll_events() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return Ok(None),
+                Event::MouseMotion { x, y, .. } => {
+                    let (x, y) = graphics.window_to_layout(x, y);
+                    if let Some((x, y)) = graphics.window_to_frame(x, y) {
+                        if let Some(index) = gallery.tile_at(x as i32, y as i32, width) {
+                            gallery.selected = index;
+                        }
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let (x, y) = graphics.window_to_layout(x, y);
+                    let index = graphics
+                        .window_to_frame(x, y)
+                        .and_then(|(x, y)| gallery.tile_at(x as i32, y as i32, width));
+                    if let Some(index) = index {
+                        return Ok(Some(gallery.entries[index].path.clone()));
+                    }
+                }
+                Event::MouseWheel { y, .. } => gallery.scroll_by(-y * SCROLL_STEP, width, height),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => match keycode {
+                    Keycode::Left => gallery.move_selection(-1, 0, width, height),
+                    Keycode::Right => gallery.move_selection(1, 0, width, height),
+                    Keycode::Up => gallery.move_selection(0, -1, width, height),
+                    Keycode::Down => gallery.move_selection(0, 1, width, height),
+                    Keycode::Return | Keycode::KpEnter => {
+                        return Ok(Some(gallery.entries[gallery.selected].path.clone()));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let pixels = gallery.render(width, height);
+        graphics.update_frame(width, height, &pixels)?;
+        graphics.present()?;
+        std::thread::sleep(FRAME_INTERVAL);
+    }
+}
+
+/// The grid of tiles, its scroll position and selected tile
+struct Gallery {
+    entries: Vec<Entry>,
+    selected: usize,
+    /// Pixels scrolled down from the top of the grid
+    scroll: i32,
+}
+
+impl Gallery {
+    fn new(entries: Vec<Entry>) -> Self {
+        Self {
+            entries,
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    /// Number of tiles per row in a frame `width` pixels wide
+    fn columns(width: u32) -> usize {
+        ((width as i32 - MARGIN) / (TILE_WIDTH + MARGIN)).max(1) as usize
+    }
+
+    /// Where tile `index` is drawn, scrolled
+    fn tile_rect(&self, index: usize, width: u32) -> Rect {
+        let columns = Self::columns(width);
+        let (column, row) = ((index % columns) as i32, (index / columns) as i32);
+        // Rows are centered horizontally
+        let grid_width = columns as i32 * (TILE_WIDTH + MARGIN) - MARGIN;
+        let left = ((width as i32 - grid_width) / 2).max(MARGIN);
+        Rect::new(
+            left + column * (TILE_WIDTH + MARGIN),
+            MARGIN + row * (TILE_HEIGHT + MARGIN) - self.scroll,
+            TILE_WIDTH as u32,
+            TILE_HEIGHT as u32,
+        )
+    }
+
+    /// Tile under frame coordinate (`x`, `y`)
+    fn tile_at(&self, x: i32, y: i32, width: u32) -> Option<usize> {
+        (0..self.entries.len()).find(|&index| self.tile_rect(index, width).contains_point((x, y)))
+    }
+
+    fn scroll_by(&mut self, delta: i32, width: u32, height: u32) {
+        let rows = self.entries.len().div_ceil(Self::columns(width)) as i32;
+        let content_height = MARGIN + rows * (TILE_HEIGHT + MARGIN);
+        let max_scroll = (content_height - height as i32).max(0);
+        self.scroll = (self.scroll + delta).clamp(0, max_scroll);
+    }
+
+    /// Move the selection by `dx` columns and `dy` rows, scrolling it into view
+    fn move_selection(&mut self, dx: i32, dy: i32, width: u32, height: u32) {
+        let columns = Self::columns(width) as i32;
+        let target = self.selected as i32 + dx + dy * columns;
+        if target < 0 || target >= self.entries.len() as i32 {
+            return;
+        }
+        self.selected = target as usize;
+        let rect = self.tile_rect(self.selected, width);
+        if rect.top() < MARGIN {
+            self.scroll_by(rect.top() - MARGIN, width, height);
+        } else if rect.bottom() > height as i32 - MARGIN {
+            self.scroll_by(rect.bottom() - (height as i32 - MARGIN), width, height);
+        }
+    }
+
+    /// RGBA pixels of the gallery in a `width` x `height` frame
+    fn render(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut canvas = Canvas {
+            pixels: vec![0; width as usize * height as usize * 4],
+            width,
+            height,
+        };
+        canvas.fill(Rect::new(0, 0, width, height), BACKGROUND);
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let tile = self.tile_rect(index, width);
+            if tile.bottom() < 0 || tile.top() > height as i32 {
+                continue;
+            }
+            let color = if index == self.selected {
+                SELECTED_COLOR
+            } else {
+                TILE_COLOR
+            };
+            canvas.fill(tile, color);
+
+            let icon = Rect::new(
+                tile.x() + (TILE_WIDTH - ICON_SIZE) / 2,
+                tile.y() + PADDING,
+                ICON_SIZE as u32,
+                ICON_SIZE as u32,
+            );
+            match &entry.icon {
+                Some((icon_width, icon_height, pixels)) => {
+                    canvas.draw_image(icon, *icon_width, *icon_height, pixels, color)
+                }
+                None => canvas.fill(icon, ICON_PLACEHOLDER),
+            }
+
+            let text_width = TILE_WIDTH - PADDING * 2;
+            let mut rects = Vec::new();
+            let mut top = icon.bottom() + PADDING;
+            let name_columns = (text_width / font::advance(NAME_SCALE)) as usize;
+            font::draw_text(
+                &mut rects,
+                &truncate(&entry.name, name_columns),
+                tile.x() + PADDING,
+                top,
+                NAME_SCALE,
+                NAME_COLOR,
+            );
+            top += font::line_height(NAME_SCALE) + PADDING / 2;
+            let description_columns = (text_width / font::advance(DESCRIPTION_SCALE)) as usize;
+            let mut description_lines = DESCRIPTION_LINES;
+            if !entry.byline.is_empty() {
+                font::draw_text(
+                    &mut rects,
+                    &truncate(&entry.byline, description_columns),
+                    tile.x() + PADDING,
+                    top,
+                    DESCRIPTION_SCALE,
+                    BYLINE_COLOR,
+                );
+                top += font::line_height(DESCRIPTION_SCALE);
+                description_lines -= 1;
+            }
+            for line in wrap(&entry.description, description_columns, description_lines) {
+                font::draw_text(
+                    &mut rects,
+                    &line,
+                    tile.x() + PADDING,
+                    top,
+                    DESCRIPTION_SCALE,
+                    DESCRIPTION_COLOR,
+                );
+                top += font::line_height(DESCRIPTION_SCALE);
+            }
+            for (rect, color) in rects {
+                canvas.fill(rect, color);
+            }
+        }
+        canvas.pixels
+    }
+}
+
+/// An RGBA frame being drawn, clipping everything to its bounds
+struct Canvas {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl Canvas {
+    fn fill(&mut self, rect: Rect, color: Color) {
+        let Some(rect) = rect.intersection(Rect::new(0, 0, self.width, self.height)) else {
+            return;
+        };
+        for y in rect.top()..rect.bottom() {
+            let row = y as usize * self.width as usize;
+            for x in rect.left()..rect.right() {
+                let offset = (row + x as usize) * 4;
+                self.pixels[offset..offset + 4].copy_from_slice(&[color.r, color.g, color.b, 255]);
+            }
+        }
+    }
+
+    /// Draw RGBA `pixels` scaled to fit `rect` (nearest neighbour, keeping
+    /// their aspect ratio), blended over `background`
+    fn draw_image(
+        &mut self,
+        rect: Rect,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        background: Color,
+    ) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let scale = (rect.width() as f64 / width as f64).min(rect.height() as f64 / height as f64);
+        let (drawn_width, drawn_height) = (
+            (width as f64 * scale).max(1.0) as i32,
+            (height as f64 * scale).max(1.0) as i32,
+        );
+        let left = rect.x() + (rect.width() as i32 - drawn_width) / 2;
+        let top = rect.y() + (rect.height() as i32 - drawn_height) / 2;
+        for y in 0..drawn_height {
+            for x in 0..drawn_width {
+                let (frame_x, frame_y) = (left + x, top + y);
+                if frame_x < 0
+                    || frame_y < 0
+                    || frame_x >= self.width as i32
+                    || frame_y >= self.height as i32
+                {
+                    continue;
+                }
+                let source_x = ((x as f64 / scale) as u32).min(width - 1);
+                let source_y = ((y as f64 / scale) as u32).min(height - 1);
+                let source = (source_y * width + source_x) as usize * 4;
+                let Some(pixel) = pixels.get(source..source + 4) else {
+                    continue;
+                };
+                let alpha = pixel[3] as u32;
+                let blend = |value: u8, under: u8| {
+                    ((value as u32 * alpha + under as u32 * (255 - alpha)) / 255) as u8
+                };
+                let offset = (frame_y as usize * self.width as usize + frame_x as usize) * 4;
+                self.pixels[offset..offset + 4].copy_from_slice(&[
+                    blend(pixel[0], background.r),
+                    blend(pixel[1], background.g),
+                    blend(pixel[2], background.b),
+                    255,
+                ]);
+            }
+        }
+    }
+}
+
+/// Line under a package's name showing its version and author
+fn byline(version: &str, author: Option<&str>) -> String {
+    match (version, author) {
+        ("", None) => String::new(),
+        ("", Some(author)) => format!("by {}", author),
+        (version, None) => format!("v{}", version),
+        (version, Some(author)) => format!("v{} by {}", version, author),
+    }
+}
+
+/// `text` cut to `columns` characters, ending with `..` if shortened
+fn truncate(text: &str, columns: usize) -> String {
+    if text.chars().count() <= columns {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(columns.saturating_sub(2)).collect();
+    format!("{}..", kept)
+}
+
+/// `text` wrapped at word boundaries into at most `lines` lines of `columns`
+/// characters, the last one truncated if the text doesn't fit
+fn wrap(text: &str, columns: usize, lines: usize) -> Vec<String> {
+    let mut wrapped: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let fits = wrapped
+            .last()
+            .is_some_and(|line| line.chars().count() + 1 + word.chars().count() <= columns);
+        if fits {
+            let line = wrapped.last_mut().expect("checked above");
+            line.push(' ');
+            line.push_str(word);
+        } else if wrapped.len() < lines {
+            wrapped.push(truncate(word, columns));
+        } else {
+            // Show that the text goes on
+            let last = wrapped.pop().unwrap_or_default();
+            wrapped.push(truncate(&format!("{} {}", last, word), columns));
+            break;
+        }
+    }
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str) -> Entry {
+        Entry {
+            path: PathBuf::from(format!("{}.wapp", name)),
+            name: name.to_string(),
+            byline: String::new(),
+            description: String::new(),
+            icon: Some((1, 1, vec![255, 0, 0, 255])),
+        }
+    }
+
+    #[test]
+    fn test_tiles_are_laid_out_and_hit_tested() {
+        let mut gallery = Gallery::new((0..5).map(|i| entry(&i.to_string())).collect());
+        // Three columns fit in 800 pixels
+        assert_eq!(Gallery::columns(800), 3);
+        let pixels = gallery.render(800, 300);
+        assert_eq!(pixels.len(), 800 * 300 * 4);
+        let tile = gallery.tile_rect(0, 800);
+        let (x, y) = (
+            tile.x() + TILE_WIDTH / 2,
+            tile.y() + PADDING + ICON_SIZE / 2,
+        );
+        let offset = (y as usize * 800 + x as usize) * 4;
+        assert_eq!(pixels[offset..offset + 4], [255, 0, 0, 255]);
+
+        let second_row = gallery.tile_rect(3, 800);
+        assert_eq!(
+            gallery.tile_at(second_row.x() + 1, second_row.y() + 1, 800),
+            Some(3)
+        );
+        assert_eq!(gallery.tile_at(0, 0, 800), None);
+
+        gallery.move_selection(0, 1, 800, 300);
+        assert_eq!(gallery.selected, 3);
+        // Scrolled so the second row is fully visible
+        assert!(gallery.tile_rect(3, 800).bottom() <= 300 - MARGIN);
+        gallery.move_selection(0, 1, 800, 300);
+        assert_eq!(gallery.selected, 3);
+    }
+
+    #[test]
+    fn test_descriptions_wrap_at_words() {
+        assert_eq!(
+            wrap("A simple game of life", 10, 3),
+            ["A simple", "game of", "life"]
+        );
+        assert_eq!(wrap("one two three four", 9, 2), ["one two", "three f.."]);
+        assert!(wrap("", 10, 3).is_empty());
+        assert_eq!(truncate("Conway", 4), "Co..");
+        assert_eq!(byline("1.2.0", Some("Ada")), "v1.2.0 by Ada");
+        assert_eq!(byline("", None), "");
+    }
+}
@@ -0,0 +1,217 @@
+//! Frame Layers
+//!
+//! Guests may submit several RGBA surfaces per tick with `wapps::update_layer`,
+//! e.g. a static background and a frequently redrawn UI. Layers persist until
+//! replaced, so a guest only resubmits what changed, and the host composites
+//! them in increasing id order. `wapps::update_frame` sets layer 0. A HUD set
+//! with `wapps::update_overlay` is drawn over all of them at the window's
+//! resolution instead.
+
+use std::collections::BTreeMap;
+
+/// Layer set by `wapps::update_frame`
+pub const BASE_LAYER: i32 = 0;
+
+/// One guest surface
+struct Layer {
+    width: u32,
+    height: u32,
+    /// RGBA pixels, reused across updates
+    pixels: Vec<u8>,
+    /// Opacity applied on top of the per-pixel alpha, in `0.0..=1.0`
+    opacity: f32,
+}
+
+/// Guest layers and their composited result
+///
+/// The composite has the size of the lowest layer; layers above it are drawn
+/// at its top-left corner and clipped.
+pub struct LayerStack {
+    layers: BTreeMap<i32, Layer>,
+    /// Reusable output buffer for the composite
+    composite: Vec<u8>,
+    /// Whether a layer changed since the last composite
+    dirty: bool,
+}
+
+impl LayerStack {
+    pub fn new() -> Self {
+        Self {
+            layers: BTreeMap::new(),
+            composite: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    /// Replace layer `id`
+    ///
+    /// `pixels` must hold `width * height` RGBA pixels. Opacity is clamped to
+    /// `0.0..=1.0`, with NaN treated as fully transparent.
+    pub fn set(&mut self, id: i32, width: u32, height: u32, pixels: &[u8], opacity: f32) {
+        let layer = self.layers.entry(id).or_insert_with(|| Layer {
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+            opacity: 1.0,
+        });
+        layer.width = width;
+        layer.height = height;
+        layer.pixels.clear();
+        layer.pixels.extend_from_slice(pixels);
+        layer.opacity = if opacity >= 0.0 {
+            opacity.min(1.0)
+        } else {
+            0.0
+        };
+        self.dirty = true;
+    }
+
+    /// Composite again on the next call to `take_composite`, even if no layer changed
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Remove layer `id`, if present
+    pub fn remove(&mut self, id: i32) {
+        if self.layers.remove(&id).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// Every layer in increasing id order, as (id, width, height, opacity, pixels)
+    pub fn iter(&self) -> impl Iterator<Item = (i32, u32, u32, f32, &[u8])> {
+        self.layers.iter().map(|(&id, layer)| {
+            (
+                id,
+                layer.width,
+                layer.height,
+                layer.opacity,
+                &layer.pixels[..],
+            )
+        })
+    }
+
+    /// Remove every layer
+    pub fn clear(&mut self) {
+        self.layers.clear();
+        self.dirty = true;
+    }
+
+    /// Whether any layer other than the base is set
+    pub fn has_overlays(&self) -> bool {
+        self.layers.keys().any(|&id| id != BASE_LAYER)
+    }
+
+    /// Size of the composite: that of the lowest layer
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.layers
+            .values()
+            .next()
+            .map_or((0, 0), |layer| (layer.width, layer.height))
+    }
+
+    /// Composite the layers if any changed since the last call (clears the dirty flag)
+    ///
+    /// A single opaque layer is returned as-is, so guests that only call
+    /// `update_frame` pay no compositing cost.
+    pub fn take_composite(&mut self) -> Option<(u32, u32, &[u8])> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+
+        let (width, height) = self.dimensions();
+        let base = self.layers.values().next()?;
+        if self.layers.len() == 1 && base.opacity == 1.0 {
+            return Some((width, height, &base.pixels));
+        }
+
+        self.composite.clear();
+        self.composite.resize(base.pixels.len(), 0);
+        for layer in self.layers.values() {
+            blend(&mut self.composite, width, height, layer);
+        }
+        Some((width, height, &self.composite))
+    }
+
+    /// A copy of the composite, whether or not a layer changed, leaving the
+    /// next `take_composite` unaffected
+    pub fn current_composite(&mut self) -> Option<(u32, u32, Vec<u8>)> {
+        let dirty = std::mem::replace(&mut self.dirty, true);
+        let frame = self
+            .take_composite()
+            .map(|(width, height, pixels)| (width, height, pixels.to_vec()));
+        self.dirty = dirty;
+        frame
+    }
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Draw `layer` over the `width` x `height` `target` with source-over blending
+fn blend(target: &mut [u8], width: u32, height: u32, layer: &Layer) {
+    let columns = layer.width.min(width) as usize;
+    let rows = layer.height.min(height) as usize;
+    for y in 0..rows {
+        let src = &layer.pixels[y * layer.width as usize * 4..][..columns * 4];
+        let dst = &mut target[y * width as usize * 4..][..columns * 4];
+        for (s, d) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            blend_pixel(d, s, layer.opacity);
+        }
+    }
+}
+
+/// Draw the RGBA pixel `s` over `d` at `opacity` with source-over blending
+pub fn blend_pixel(d: &mut [u8], s: &[u8], opacity: f32) {
+    let alpha = s[3] as f32 / 255.0 * opacity;
+    if alpha >= 1.0 {
+        d.copy_from_slice(s);
+    } else if alpha > 0.0 {
+        for c in 0..3 {
+            d[c] = (s[c] as f32 * alpha + d[c] as f32 * (1.0 - alpha)).round() as u8;
+        }
+        d[3] = ((alpha + d[3] as f32 / 255.0 * (1.0 - alpha)) * 255.0).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_opaque_layer_is_not_copied() {
+        let mut stack = LayerStack::new();
+        assert!(stack.take_composite().is_none());
+
+        stack.set(BASE_LAYER, 1, 1, &[1, 2, 3, 255], 1.0);
+        let (width, height, pixels) = stack.take_composite().unwrap();
+        assert_eq!((width, height, pixels), (1, 1, &[1, 2, 3, 255][..]));
+        assert!(stack.take_composite().is_none());
+
+        // Copies for screenshots don't consume changes
+        assert_eq!(stack.current_composite(), Some((1, 1, vec![1, 2, 3, 255])));
+        assert!(stack.take_composite().is_none());
+    }
+
+    #[test]
+    fn test_layers_blend_in_id_order() {
+        let mut stack = LayerStack::new();
+        stack.set(5, 1, 1, &[255, 255, 255, 255], 0.5);
+        stack.set(BASE_LAYER, 2, 1, &[0, 0, 0, 255, 0, 0, 200, 255], 1.0);
+        stack.set(9, 1, 2, &[10, 20, 30, 0, 1, 1, 1, 255], 1.0);
+
+        let (width, height, pixels) = stack.take_composite().unwrap();
+        assert_eq!((width, height), (2, 1));
+        assert_eq!(pixels, &[128, 128, 128, 255, 0, 0, 200, 255]);
+
+        assert!(stack.has_overlays());
+
+        stack.remove(5);
+        let (_, _, pixels) = stack.take_composite().unwrap();
+        assert_eq!(&pixels[..4], &[0, 0, 0, 255]);
+    }
+}
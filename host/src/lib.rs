@@ -0,0 +1,90 @@
+//! WAPPS Host Library
+//!
+//! Loads and runs WAPP packages without SDL, so that other Rust applications
+//! can embed them; see [`Runner`]. The `wapps-host` binary builds its windows,
+//! audio and input on top of the same modules with the default `window`
+//! feature. Build with `default-features = false` to leave SDL out.
+//!
+//! Only `Runner` and the types it takes and returns are meant for embedders,
+//! along with [`testing`] for golden-frame tests with the `testing` feature;
+//! the other modules are shared with the binary and may change at any time.
+
+mod runner;
+
+pub use events::GuestEvent;
+pub use loader::WappMetadata;
+pub use runner::{Frame, Runner};
+
+#[doc(hidden)]
+pub mod audio;
+#[doc(hidden)]
+pub mod canvas;
+#[doc(hidden)]
+pub mod capabilities;
+#[doc(hidden)]
+pub mod codec;
+#[doc(hidden)]
+pub mod deflate;
+#[doc(hidden)]
+pub mod display;
+#[doc(hidden)]
+pub mod display_adjust;
+pub mod events;
+#[doc(hidden)]
+pub mod frame_limits;
+#[doc(hidden)]
+pub mod guest_alloc;
+#[doc(hidden)]
+pub mod host_interface;
+#[doc(hidden)]
+pub mod http;
+#[doc(hidden)]
+pub mod images;
+#[doc(hidden)]
+pub mod layers;
+#[doc(hidden)]
+pub mod license;
+#[doc(hidden)]
+pub mod loader;
+#[doc(hidden)]
+pub mod memory_limit;
+#[doc(hidden)]
+pub mod module_cache;
+#[doc(hidden)]
+pub mod permissions;
+#[doc(hidden)]
+pub mod pixel_format;
+#[doc(hidden)]
+pub mod png;
+#[doc(hidden)]
+pub mod rating;
+#[doc(hidden)]
+pub mod recording;
+#[doc(hidden)]
+pub mod runtime;
+#[doc(hidden)]
+pub mod save_state;
+#[doc(hidden)]
+pub mod scores;
+#[doc(hidden)]
+pub mod signing;
+#[doc(hidden)]
+pub mod storage;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[doc(hidden)]
+pub mod text;
+#[doc(hidden)]
+pub mod timers;
+#[doc(hidden)]
+pub mod version;
+#[doc(hidden)]
+pub mod wasi_policy;
+#[doc(hidden)]
+pub mod wasi_threads;
+#[doc(hidden)]
+pub mod wasm_features;
+#[doc(hidden)]
+pub mod watchdog;
+#[doc(hidden)]
+pub mod ws;
@@ -0,0 +1,127 @@
+//! License Metadata
+//!
+//! Packages declare their own license and those of bundled assets as SPDX
+//! license expressions (e.g. `MIT OR Apache-2.0`, `GPL-2.0-only WITH
+//! Classpath-exception-2.0`). The host only checks that expressions are
+//! well formed; it does not verify identifiers against the SPDX list.
+
+use serde::Deserialize;
+
+/// License of a bundled asset or group of assets
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AssetLicense {
+    /// Asset path or glob the license applies to (e.g. `sprites/*.png`)
+    pub path: String,
+    /// SPDX license expression
+    pub license: String,
+    /// Copyright holder or attribution text, if the license requires one
+    #[serde(default)]
+    pub attribution: Option<String>,
+}
+
+/// Check that `expression` is a syntactically valid SPDX license expression
+///
+/// Returns a description of the first problem found.
+pub fn validate_expression(expression: &str) -> Result<(), String> {
+    let tokens = tokenize(expression);
+    if tokens.is_empty() {
+        return Err("empty license expression".to_string());
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    parser.expression()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(()),
+        Some(token) => Err(format!("unexpected {:?}", token)),
+    }
+}
+
+fn tokenize(expression: &str) -> Vec<String> {
+    expression
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Recursive descent parser for `expr := term (("AND" | "OR") term)*`,
+/// `term := "(" expr ")" | id ["+"] ["WITH" id]`
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn expression(&mut self) -> Result<(), String> {
+        self.term()?;
+        while matches!(self.peek(), Some("AND" | "OR")) {
+            self.pos += 1;
+            self.term()?;
+        }
+        Ok(())
+    }
+
+    fn term(&mut self) -> Result<(), String> {
+        match self.next() {
+            Some("(") => {
+                self.expression()?;
+                match self.next() {
+                    Some(")") => Ok(()),
+                    _ => Err("unbalanced parentheses".to_string()),
+                }
+            }
+            Some(id) if is_identifier(id) => {
+                if self.peek() == Some("WITH") {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(exception) if is_identifier(exception) => {}
+                        _ => return Err("expected an exception after WITH".to_string()),
+                    }
+                }
+                Ok(())
+            }
+            Some(token) => Err(format!("invalid license identifier {:?}", token)),
+            None => Err("expression ends unexpectedly".to_string()),
+        }
+    }
+}
+
+/// License or exception identifier, optionally with a trailing `+`
+fn is_identifier(token: &str) -> bool {
+    let id = token.strip_suffix('+').unwrap_or(token);
+    !id.is_empty()
+        && !matches!(id, "AND" | "OR" | "WITH")
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_expression() {
+        assert!(validate_expression("MIT").is_ok());
+        assert!(validate_expression("MIT OR Apache-2.0").is_ok());
+        assert!(validate_expression("(MIT AND CC-BY-4.0) OR GPL-2.0+").is_ok());
+        assert!(validate_expression("GPL-2.0-only WITH Classpath-exception-2.0").is_ok());
+        assert!(validate_expression("LicenseRef-custom").is_ok());
+
+        assert!(validate_expression("").is_err());
+        assert!(validate_expression("MIT OR").is_err());
+        assert!(validate_expression("(MIT").is_err());
+        assert!(validate_expression("MIT Apache-2.0").is_err());
+        assert!(validate_expression("MIT/Apache").is_err());
+    }
+}
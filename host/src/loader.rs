@@ -6,18 +6,43 @@
 //! - Bytes 8-11: Header Length (N, u32 LE)
 //! - Bytes 12..12+N: JSON Metadata (UTF-8)
 //! - Bytes 12+N+: WebAssembly module binary
+//!
+//! Version 2 replaces the raw module with a sequence of sections running to
+//! the end of the file, each compressed with its own codec:
+//! - Kind (u8): 0 = module, 1 = icon (PNG), 2 = asset, 3 = signature (always
+//!   last, see the `signing` module), 4 = the module precompiled by wasmtime
+//!   (see `wapps pack --precompile`); unknown kinds are skipped
+//! - Codec id (u8), see the `codec` module
+//! - Name length (u16 LE), stored length (u32 LE), raw length (u32 LE)
+//! - Name (UTF-8), then the stored bytes
 
 use anyhow::{bail, Context, Result};
 use log::debug;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::path::Path;
 
+use crate::capabilities::Capability;
+use crate::codec::CodecRegistry;
+use crate::license::AssetLicense;
+use crate::signing::{self, PublicKey};
+use crate::version;
+use crate::wasi_policy::WasiSettings;
+use crate::wasm_features::Proposal;
+
 /// Magic bytes for WAPP format
-const WAPP_MAGIC: &[u8; 4] = b"WAPP";
+pub const WAPP_MAGIC: &[u8; 4] = b"WAPP";
+
+/// Original format version: JSON header followed by the raw module
+pub const WAPP_VERSION: u32 = 1;
 
-/// Current supported format version
-const WAPP_VERSION: u32 = 1;
+/// Format version with compressed sections
+pub const WAPP_SECTIONED_VERSION: u32 = 2;
+
+/// Size of a section header: kind, codec, name length, stored and raw lengths
+pub const SECTION_HEADER_SIZE: usize = 1 + 1 + 2 + 4 + 4;
 
 /// Minimum valid WAPP file size (4 magic + 4 version + 4 length + 2 json {})
 const WAPP_MIN_SIZE: usize = 4 + 4 + 4 + 2;
@@ -31,8 +56,199 @@ pub struct WappMetadata {
     /// Application description
     #[serde(default)]
     pub description: String,
+    /// Application version, a semantic version such as "1.2.0"
+    #[serde(default)]
+    pub version: String,
+    /// Author or publisher shown in the gallery and by `wapps inspect`
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Web page of the app
+    #[serde(default)]
+    pub homepage: Option<String>,
+    /// Oldest host version able to run the package; older hosts refuse to load it
+    #[serde(default)]
+    pub min_host_version: Option<String>,
+    /// Minimum recommended age of the audience, if the package is rated
+    #[serde(default)]
+    pub age_rating: Option<u8>,
+    /// SPDX license expression for the app
+    #[serde(default)]
+    pub license: Option<String>,
+    /// Licenses of bundled assets
+    #[serde(default)]
+    pub asset_licenses: Vec<AssetLicense>,
+    /// Strings the guest can read via `wapps::get_string`, in the default language
+    #[serde(default)]
+    pub strings: HashMap<String, String>,
+    /// Translations keyed by locale tag (e.g. "fr", "pt-BR")
+    #[serde(default)]
+    pub locales: HashMap<String, LocaleStrings>,
+    /// Startup settings the guest can read via `wapps::config_get`, which
+    /// `--guest-arg` overrides
+    #[serde(default)]
+    pub config: HashMap<String, String>,
+    /// Clock precision and random seed the package asks for
+    #[serde(default)]
+    pub wasi: WasiSettings,
+    /// Color behind the frame and in the letterbox bars, as `#rrggbb`
+    #[serde(default)]
+    pub clear_color: Option<String>,
+    /// Host capabilities the app needs; `None` for packages without a manifest
+    #[serde(default)]
+    pub capabilities: Option<BTreeSet<Capability>>,
+    /// Hosts `wapps::http_fetch` and `wapps::ws_connect` may reach; `*.`
+    /// entries match subdomains
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// WebAssembly proposals the module runs without, as with `--disable-feature`
+    #[serde(default)]
+    pub disabled_features: Vec<Proposal>,
+}
+
+/// Translated metadata for one locale; anything missing falls back to the default
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LocaleStrings {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub strings: HashMap<String, String>,
+}
+
+/// What a package section contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Module,
+    Icon,
+    Asset,
+    Signature,
+    Precompiled,
+}
+
+impl SectionKind {
+    pub fn id(self) -> u8 {
+        match self {
+            SectionKind::Module => 0,
+            SectionKind::Icon => 1,
+            SectionKind::Asset => 2,
+            SectionKind::Signature => 3,
+            SectionKind::Precompiled => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(SectionKind::Module),
+            1 => Some(SectionKind::Icon),
+            2 => Some(SectionKind::Asset),
+            3 => Some(SectionKind::Signature),
+            4 => Some(SectionKind::Precompiled),
+            _ => None,
+        }
+    }
+}
+
+/// A decompressed package section
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub kind: SectionKind,
+    /// Asset path; empty for other sections
+    pub name: String,
+    /// Id of the codec the section was stored with
+    pub codec: u8,
+    /// Size of the section as stored in the file
+    pub stored_len: usize,
+    pub data: Vec<u8>,
 }
 
+/// A parsed package: metadata plus its sections
+#[derive(Debug, Clone)]
+pub struct WappPackage {
+    pub format_version: u32,
+    pub metadata: WappMetadata,
+    /// JSON header exactly as stored in the file
+    pub manifest: Vec<u8>,
+    /// Sections other than the signature
+    pub sections: Vec<Section>,
+    /// Key the package was signed with, its signature having been checked
+    pub signer: Option<PublicKey>,
+}
+
+impl WappPackage {
+    /// The WebAssembly module section
+    pub fn module(&self) -> &Section {
+        self.sections
+            .iter()
+            .find(|section| section.kind == SectionKind::Module)
+            .expect("parsed packages always have a module")
+    }
+
+    /// The PNG icon, if the package has one
+    pub fn icon(&self) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|section| section.kind == SectionKind::Icon)
+            .map(|section| section.data.as_slice())
+    }
+
+    /// The module precompiled by wasmtime, if the package has one
+    pub fn precompiled(&self) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|section| section.kind == SectionKind::Precompiled)
+            .map(|section| section.data.as_slice())
+    }
+
+    /// Id the data the package keeps between runs is filed under
+    ///
+    /// Display names change with the locale, and any package may claim any
+    /// name, so the id is the unlocalized name, or the stem of `path` if there
+    /// is none, followed by a fingerprint of the signing key, which updates
+    /// from the same signer keep, or of the whole package if it is unsigned.
+    pub fn id(&self, path: &Path) -> String {
+        let mut hasher = Sha256::new();
+        match &self.signer {
+            Some(key) => {
+                hasher.update(b"signer");
+                hasher.update(key);
+            }
+            None => {
+                hasher.update(b"contents");
+                hasher.update((self.manifest.len() as u64).to_le_bytes());
+                hasher.update(&self.manifest);
+                for section in &self.sections {
+                    hasher.update([section.kind.id()]);
+                    hasher.update((section.name.len() as u64).to_le_bytes());
+                    hasher.update(section.name.as_bytes());
+                    hasher.update((section.data.len() as u64).to_le_bytes());
+                    hasher.update(&section.data);
+                }
+            }
+        }
+        let name = match self.metadata.name.as_str() {
+            "" => path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("app"),
+            name => name,
+        };
+        format!("{}-{}", name, signing::to_hex(&hasher.finalize()[..16]))
+    }
+
+    /// Bundled assets by name, readable by the guest via `wapps::asset_read`
+    pub fn assets(&self) -> Assets {
+        self.sections
+            .iter()
+            .filter(|section| section.kind == SectionKind::Asset)
+            .map(|section| (section.name.clone(), section.data.clone()))
+            .collect()
+    }
+}
+
+/// A package's assets by name
+pub type Assets = HashMap<String, Vec<u8>>;
+
 /// Load and validate a WAPP file, returning the WASM binary contents.
 ///
 /// # Arguments
@@ -48,12 +264,24 @@ pub struct WappMetadata {
 /// - File too small to contain valid WASM
 /// - Invalid metadata (invalid JSON)
 pub fn load_wapp(path: &Path) -> Result<(Vec<u8>, WappMetadata)> {
+    let package = load_package(path)?;
+    let module = package.module().data.clone();
+    Ok((module, package.metadata))
+}
+
+/// Load and validate a WAPP file with all of its sections
+pub fn load_package(path: &Path) -> Result<WappPackage> {
     // Read the entire file
     let data =
         fs::read(path).with_context(|| format!("Could not read file: {}", path.display()))?;
 
     debug!("Read {} bytes from {:?}", data.len(), path);
 
+    parse_package(&data)
+}
+
+/// Parse and validate the bytes of a WAPP file
+pub fn parse_package(data: &[u8]) -> Result<WappPackage> {
     // Validate minimum size
     if data.len() < WAPP_MIN_SIZE {
         bail!(
@@ -86,13 +314,14 @@ pub fn load_wapp(path: &Path) -> Result<(Vec<u8>, WappMetadata)> {
     // Validate version (Bytes 4-7, u32 LE)
     let version_bytes: [u8; 4] = data[4..8].try_into().expect("slice with incorrect length");
     let version = u32::from_le_bytes(version_bytes);
-    if version != WAPP_VERSION {
+    if version != WAPP_VERSION && version != WAPP_SECTIONED_VERSION {
         bail!(
             "Unsupported WAPP version: {}. \
-            This host supports version {} only. \
+            This host supports versions {} and {} only. \
             The WAPP file may have been created with a newer tool version.",
             version,
-            WAPP_VERSION
+            WAPP_VERSION,
+            WAPP_SECTIONED_VERSION
         );
     }
 
@@ -121,10 +350,37 @@ pub fn load_wapp(path: &Path) -> Result<(Vec<u8>, WappMetadata)> {
     
     debug!("Parsed Metadata: name={:?}, description={:?}", metadata.name, metadata.description);
 
-    // Extract and return WASM bytes (everything after the header)
-    let wasm_bytes = data[header_end..].to_vec();
+    if let Some(min_host_version) = &metadata.min_host_version {
+        if let Err(e) = version::check_host_version(min_host_version) {
+            bail!("Unsupported WAPP file: {}", e);
+        }
+    }
+
+    let (sections, signer) = if version == WAPP_VERSION {
+        // Extract WASM bytes (everything after the header)
+        let wasm_bytes = data[header_end..].to_vec();
+        let module = Section {
+            kind: SectionKind::Module,
+            name: String::new(),
+            codec: 0,
+            stored_len: wasm_bytes.len(),
+            data: wasm_bytes,
+        };
+        (vec![module], None)
+    } else {
+        parse_sections(data, header_end)?
+    };
+
+    let package = WappPackage {
+        format_version: version,
+        metadata,
+        manifest: json_bytes.to_vec(),
+        sections,
+        signer,
+    };
 
     // Basic WASM validation: check for WASM magic number
+    let wasm_bytes = &package.module().data;
     if wasm_bytes.len() >= 4 {
         let wasm_magic = &wasm_bytes[0..4];
         if wasm_magic != b"\0asm" {
@@ -137,7 +393,70 @@ pub fn load_wapp(path: &Path) -> Result<(Vec<u8>, WappMetadata)> {
         }
     }
 
-    Ok((wasm_bytes, metadata))
+    Ok(package)
+}
+
+/// Parse and decompress the sections of a version 2 package, which start at
+/// `start` in `file`, checking its signature if it is signed
+fn parse_sections(file: &[u8], start: usize) -> Result<(Vec<Section>, Option<PublicKey>)> {
+    let codecs = CodecRegistry::new();
+    let mut sections = Vec::new();
+    let mut signer = None;
+    let mut data = &file[start..];
+
+    while !data.is_empty() {
+        let offset = file.len() - data.len();
+        if data.len() < SECTION_HEADER_SIZE {
+            bail!("Invalid WAPP file: truncated section header");
+        }
+        let kind = data[0];
+        let codec = data[1];
+        let name_len = u16::from_le_bytes([data[2], data[3]]) as usize;
+        let stored_len = u32::from_le_bytes(data[4..8].try_into().expect("4-byte slice")) as usize;
+        let raw_len = u32::from_le_bytes(data[8..12].try_into().expect("4-byte slice")) as usize;
+
+        let body_end = SECTION_HEADER_SIZE + name_len + stored_len;
+        if data.len() < body_end {
+            bail!("Invalid WAPP file: section extends past the end of the file");
+        }
+        let name = std::str::from_utf8(&data[SECTION_HEADER_SIZE..SECTION_HEADER_SIZE + name_len])
+            .context("Invalid WAPP file: section name is not UTF-8")?
+            .to_string();
+        let stored = &data[SECTION_HEADER_SIZE + name_len..body_end];
+        data = &data[body_end..];
+
+        let Some(kind) = SectionKind::from_id(kind) else {
+            debug!("Skipping unknown section kind {} ({:?})", kind, name);
+            continue;
+        };
+        let contents = codecs
+            .decompress(codec, stored, raw_len)
+            .with_context(|| format!("Invalid {:?} section {:?}", kind, name))?;
+        if kind == SectionKind::Signature {
+            if !data.is_empty() {
+                bail!("Invalid WAPP file: data after the signature section");
+            }
+            signer = Some(signing::verify(&contents, &file[..offset])?);
+            break;
+        }
+        sections.push(Section {
+            kind,
+            name,
+            codec,
+            stored_len,
+            data: contents,
+        });
+    }
+
+    let modules = sections
+        .iter()
+        .filter(|section| section.kind == SectionKind::Module)
+        .count();
+    match modules {
+        1 => Ok((sections, signer)),
+        0 => bail!("Invalid WAPP file: no module section"),
+        _ => bail!("Invalid WAPP file: more than one module section"),
+    }
 }
 
 #[cfg(test)]
@@ -153,4 +472,49 @@ mod tests {
     fn test_wapp_version_constant() {
         assert_eq!(WAPP_VERSION, 0x01);
     }
+
+    #[test]
+    fn test_min_host_version_is_enforced() {
+        let package = |header: &str| {
+            let mut data = WAPP_MAGIC.to_vec();
+            data.extend_from_slice(&WAPP_VERSION.to_le_bytes());
+            data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+            data.extend_from_slice(header.as_bytes());
+            data.extend_from_slice(b"\0asm\x01\0\0\0");
+            data
+        };
+        let parsed = parse_package(&package(r#"{"author": "Ada", "min_host_version": "0.0.1"}"#));
+        assert_eq!(parsed.unwrap().metadata.author.as_deref(), Some("Ada"));
+        assert!(parse_package(&package(r#"{"min_host_version": "999.0.0"}"#)).is_err());
+        assert!(parse_package(&package(r#"{"min_host_version": "soon"}"#)).is_err());
+    }
+
+    #[test]
+    fn test_ids_follow_the_signer_or_the_contents() {
+        let package = |header: &str| {
+            let mut data = WAPP_MAGIC.to_vec();
+            data.extend_from_slice(&WAPP_VERSION.to_le_bytes());
+            data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+            data.extend_from_slice(header.as_bytes());
+            data.extend_from_slice(b"\0asm\x01\0\0\0");
+            parse_package(&data).unwrap()
+        };
+        let signed = |mut package: WappPackage, key| {
+            package.signer = Some([key; 32]);
+            package
+        };
+        let path = Path::new("apps/life.wapp");
+        let release = package(r#"{"name": "Life", "version": "1.0.0"}"#);
+        let update = package(r#"{"name": "Life", "version": "1.1.0"}"#);
+
+        assert!(release.id(path).starts_with("Life-"));
+        assert_ne!(release.id(path), update.id(path));
+        assert_eq!(
+            signed(release.clone(), 1).id(path),
+            signed(update.clone(), 1).id(path)
+        );
+        assert_ne!(signed(release.clone(), 1).id(path), signed(update, 2).id(path));
+        assert_ne!(signed(release.clone(), 1).id(path), release.id(path));
+        assert!(package("{}").id(path).starts_with("life-"));
+    }
 }
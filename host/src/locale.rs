@@ -0,0 +1,127 @@
+//! Locale Selection
+//!
+//! Picks the translation of a package's name, description and guest strings
+//! that best matches the user's locale. Locales are BCP 47 style tags
+//! (`fr`, `pt-BR`); POSIX values such as `pt_BR.UTF-8` are accepted too.
+
+use std::collections::HashMap;
+
+use crate::loader::WappMetadata;
+
+/// Metadata resolved for one locale
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Localized {
+    pub name: String,
+    pub description: String,
+    /// Guest-readable strings, translated where available
+    pub strings: HashMap<String, String>,
+}
+
+/// The user's preferred locale from the environment, if set
+///
+/// Follows POSIX precedence: `LC_ALL`, then `LC_MESSAGES`, then `LANG`.
+pub fn user_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty() && value != "C" && value != "POSIX")
+}
+
+/// BCP 47 style tag of `locale`, as guests read it via `wapps::get_locale`
+/// (`pt_BR.UTF-8` → `pt-BR`)
+pub fn tag(locale: &str) -> String {
+    locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or("")
+        .replace('_', "-")
+}
+
+/// Tags to try for `locale`, most specific first (`pt_BR.UTF-8` → `pt-BR`, `pt`)
+fn candidates(locale: &str) -> Vec<String> {
+    let tag = tag(locale);
+    let mut candidates = Vec::new();
+    let mut prefix = tag.as_str();
+    while !prefix.is_empty() {
+        candidates.push(prefix.to_string());
+        prefix = prefix.rsplit_once('-').map_or("", |(head, _)| head);
+    }
+    candidates
+}
+
+/// Resolve `metadata` for `locale`, falling back to the untranslated values
+pub fn localize(metadata: &WappMetadata, locale: Option<&str>) -> Localized {
+    let mut localized = Localized {
+        name: metadata.name.clone(),
+        description: metadata.description.clone(),
+        strings: metadata.strings.clone(),
+    };
+
+    let translation = locale.into_iter().flat_map(candidates).find_map(|tag| {
+        metadata
+            .locales
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&tag))
+            .map(|(_, translation)| translation)
+    });
+
+    if let Some(translation) = translation {
+        if let Some(name) = &translation.name {
+            localized.name = name.clone();
+        }
+        if let Some(description) = &translation.description {
+            localized.description = description.clone();
+        }
+        localized.strings.extend(
+            translation
+                .strings
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+    }
+
+    localized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> WappMetadata {
+        serde_json::from_str(
+            r#"{
+                "name": "Life",
+                "description": "Cellular automaton",
+                "strings": { "start": "Start", "quit": "Quit" },
+                "locales": {
+                    "fr": { "name": "Vie", "strings": { "start": "Démarrer" } },
+                    "pt-BR": { "description": "Autômato celular" }
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_candidates() {
+        assert_eq!(candidates("pt_BR.UTF-8"), vec!["pt-BR", "pt"]);
+        assert_eq!(candidates("fr"), vec!["fr"]);
+        assert!(candidates("").is_empty());
+        assert_eq!(tag("de_DE.UTF-8@euro"), "de-DE");
+    }
+
+    #[test]
+    fn test_localize_falls_back_per_field() {
+        let french = localize(&metadata(), Some("fr_CA.UTF-8"));
+        assert_eq!(french.name, "Vie");
+        assert_eq!(french.description, "Cellular automaton");
+        assert_eq!(french.strings["start"], "Démarrer");
+        assert_eq!(french.strings["quit"], "Quit");
+
+        let brazilian = localize(&metadata(), Some("pt-br"));
+        assert_eq!(brazilian.name, "Life");
+        assert_eq!(brazilian.description, "Autômato celular");
+
+        assert_eq!(localize(&metadata(), None).name, "Life");
+    }
+}
@@ -0,0 +1,70 @@
+//! Low-Latency Mode
+//!
+//! With vsync, presents block until the display refreshes, so a host that
+//! reads input right after the previous present shows it almost a whole
+//! refresh later. Under `--low-latency`, the host instead waits out most of
+//! the refresh after each present, then polls events, calls the guest's
+//! `update` on the main thread and presents the frame as soon as it is
+//! uploaded, finishing just before the next refresh. How long polling and
+//! updating take is tracked, and twice that is kept free before the refresh
+//! for them and the upload; a slow frame widens the margin at once, while
+//! fast ones only narrow it gradually.
+
+use std::time::Duration;
+
+/// Time kept free before the refresh on top of the expected work
+const SAFETY_MARGIN: Duration = Duration::from_millis(2);
+/// Time kept free for the work of a frame, in multiples of recent frames'
+/// polling and updates
+const WORK_FACTOR: u32 = 2;
+
+/// Decides how long to wait after a present before reading input
+#[derive(Debug, Default)]
+pub struct LatePoll {
+    /// Recent time from polling events to the end of the updates, if known
+    work: Option<Duration>,
+}
+
+impl LatePoll {
+    /// Record a frame whose polling and updates took `elapsed`
+    pub fn record_work(&mut self, elapsed: Duration) {
+        self.work = Some(match self.work {
+            Some(work) if elapsed < work => (work * 7 + elapsed) / 8,
+            _ => elapsed,
+        });
+    }
+
+    /// How long to wait after a present on a display refreshing every
+    /// `period` before polling events for the next frame
+    pub fn delay(&self, period: Duration) -> Duration {
+        match self.work {
+            Some(work) => period.saturating_sub(work * WORK_FACTOR + SAFETY_MARGIN),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slow_frames_widen_the_margin_at_once() {
+        let period = Duration::from_micros(16_667);
+        let mut poll = LatePoll::default();
+        assert_eq!(poll.delay(period), Duration::ZERO);
+
+        poll.record_work(Duration::from_millis(3));
+        assert_eq!(poll.delay(period), Duration::from_micros(8_667));
+        poll.record_work(Duration::from_millis(8));
+        assert_eq!(poll.delay(period), Duration::ZERO);
+
+        // Fast frames only narrow it gradually
+        poll.record_work(Duration::from_millis(1));
+        assert_eq!(poll.delay(period), Duration::from_micros(417));
+        for _ in 0..100 {
+            poll.record_work(Duration::from_millis(1));
+        }
+        assert!(poll.delay(period) > Duration::from_millis(12));
+    }
+}
@@ -3,50 +3,808 @@
 //! This application loads and runs WAPP packages, which contain WebAssembly
 //! modules that render pixel-based graphics through SDL2.
 
+mod app;
+mod bench;
+mod bindgen;
+mod color_filter;
+mod compare;
+mod console_overlay;
+mod crash_report;
+mod crash_screen;
+mod deeplink;
+mod delta;
+mod documents;
+mod download;
+#[cfg(feature = "dialogs")]
+mod file_dialog;
+mod font;
+mod frame_diff;
+mod frame_hash;
+mod frame_pacing;
+mod gamepad;
+mod gif;
+#[cfg(feature = "wgpu")]
+mod gpu_presenter;
 mod graphics;
-mod host_interface;
-mod loader;
-mod runtime;
+mod guest_config;
+mod guest_thread;
+mod headless;
+mod hot_reload;
+mod idle;
+mod input_map;
+mod inspect;
+mod inspector;
+mod latency;
+mod launcher;
+mod locale;
+mod low_latency;
+#[cfg(feature = "menu")]
+mod menu;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod netplay;
+mod orientation;
+mod packer;
+mod perf;
+mod post_filter;
+mod presenter;
+mod profile;
+mod registry;
+mod replay_file;
+mod scenario;
+mod screenshot;
+#[cfg(feature = "softbuffer")]
+mod soft_presenter;
+mod stats;
+mod supervisor;
+mod suspend;
+mod theme;
+mod thumbnail;
+mod time_control;
+mod timing_overlay;
+mod unpack;
+mod update_rate;
+mod usage;
+mod validate;
+mod video;
+mod virtual_controls;
+mod window_identity;
+mod worker_pool;
 
-use anyhow::{Context, Result};
-use clap::Parser;
-use log::{debug, error, info};
-use std::path::PathBuf;
-use std::time::Instant;
+// Modules shared with embedders, see lib.rs
+use wapps_host::{
+    audio, capabilities, codec, deflate, display, display_adjust, events, frame_limits,
+    host_interface, license, loader, memory_limit, module_cache, permissions, png, rating,
+    recording, runtime, save_state, scores, signing, storage, text, version, wasi_policy,
+    wasm_features, watchdog,
+};
 
-use graphics::Graphics;
-use host_interface::HostInterface;
-use runtime::WasmRuntime;
+use anyhow::{bail, Context, Result};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use log::{debug, error, info, warn};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use app::{AppInstance, AppOptions};
+use color_filter::Deficiency;
+use crash_report::{CrashReporter, TailLogger};
+use display::ScalingMode;
+use display_adjust::{Adjustment, Control};
+use events::{GuestEvent, TimedEvent};
+use frame_hash::FrameHashLog;
+use frame_limits::FrameLimits;
+use frame_pacing::FixedStep;
+use gamepad::Gamepads;
+use graphics::GraphicsContext;
+use headless::HeadlessOptions;
+use hot_reload::WatchOptions;
+use idle::IdleTimer;
+use input_map::InputMap;
+use latency::LatencyMarker;
+use low_latency::LatePoll;
+use netplay::{Netplay, NetplayRole, NETPLAY_DT};
+use orientation::{Orientation, Rotation};
+use post_filter::PostFilter;
+use presenter::{Backend, VsyncMode};
+use profile::{Profiler, SaveProfileOnDrop};
+use rating::{GatePolicy, ParentalGate};
+use recording::{SaveOnDrop, Session, SNAPSHOT_INTERVAL};
+use replay_file::SaveReplayOnDrop;
+use signing::{Keyring, UntrustedPolicy};
+use supervisor::{OnCrash, RestartPolicy};
+use theme::Theme;
+use time_control::TimeControl;
+use wasi_policy::{ClockPolicy, DETERMINISTIC_DT};
+use wasm_features::{DisabledFeatures, Proposal};
+use worker_pool::WorkerPool;
 
 /// WAPPS Host - Run portable WebAssembly graphics applications
 #[derive(Parser, Debug)]
 #[command(name = "wapps")]
 #[command(version, about, long_about = None)]
+#[command(after_help = "Debug controls (in an app window):
+  F1                Toggle the frame timing overlay
+  F2 / F3           Save / restore the app's state
+  F4                Toggle the frame diff view
+  F5                Toggle the pixel inspector
+  F6                Cycle color vision deficiency simulations
+  F7                Print the app's description of its screen
+  F8                Pause or resume every app
+  Shift+F8          Run a single update while paused
+  Ctrl+F9 / F10     Slow down / speed up time, from 0.25x to 4x
+  F9 / F10          Lower / raise brightness (Shift: contrast, Alt: gamma)
+  F11               Start / stop recording a GIF of the app
+  F12               Save a screenshot of the app
+  Ctrl+scroll       Zoom the presented frame
+  Ctrl+drag         Pan the zoomed frame
+  R / Q             Restart / quit a crashed app")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Path to the .wapp file to run
-    #[arg(value_name = "FILE")]
-    wapp_file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the .wapp file(s) to run; each app opens in its own window.
+    /// A `wapps://name?key=value` URL runs the installed package `name`, passing
+    /// its query to the guest as arguments.
+    /// An `https://` or `http://` URL is downloaded, and cached for later runs.
+    /// A directory opens a gallery of its packages to pick from
+    #[arg(value_name = "FILE", required_unless_present = "register_url_scheme")]
+    wapp_files: Vec<PathBuf>,
+
+    /// Set for apps started from the gallery, which Esc returns to
+    #[arg(skip)]
+    from_gallery: bool,
+
+    /// Register this executable as the handler for wapps:// URLs and exit
+    #[arg(long)]
+    register_url_scheme: bool,
+
+    /// Number of worker threads updating apps when several run at once
+    /// (defaults to the number of available cores)
+    #[arg(long, value_name = "N")]
+    workers: Option<usize>,
+
+    /// Update rate for unfocused windows when several apps run at once
+    /// (0 disables throttling)
+    #[arg(long, value_name = "FPS", default_value_t = 10.0)]
+    background_fps: f64,
+
+    /// Stop updating apps while their window is unfocused, to save CPU
+    #[arg(long)]
+    pause_on_blur: bool,
+
+    /// Only update apps when they receive input, a timer they set fires or
+    /// they call `wapps::request_redraw`, waiting for input in between so
+    /// static apps use no CPU
+    #[arg(long, conflicts_with_all = ["record", "save_replay", "replay", "netplay"])]
+    power_save: bool,
+
+    /// Frames per second the host loop aims for
+    #[arg(long, value_name = "N", default_value_t = 60.0, value_parser = frame_pacing::parse_fps)]
+    fps: f64,
+
+    /// Run frames as fast as possible instead of at --fps (vsync, when on,
+    /// still waits for the display)
+    #[arg(long, conflicts_with = "fps")]
+    uncapped: bool,
+
+    /// Update apps with a constant dt of SECONDS, e.g. 1/120, as many times
+    /// each frame as the time elapsed calls for, so simulations step the same
+    /// whatever the frame rate
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = frame_pacing::parse_fixed_dt,
+        conflicts_with_all = ["deterministic", "power_save", "record", "save_replay", "replay", "netplay"]
+    )]
+    fixed_dt: Option<f64>,
+
+    /// Make runs reproducible: virtual clocks advanced by a constant dt of
+    /// 1/60 s each update, and random numbers seeded by --seed (0 by default)
+    #[arg(long, conflicts_with_all = ["clock", "netplay", "power_save"])]
+    deterministic: bool,
+
+    /// Start with every app paused, to step through updates with Shift+F8
+    #[arg(long)]
+    paused: bool,
+
+    /// Scale the dt passed to `update` by FACTOR, from 0.25 (slow motion) to
+    /// 4 (fast forward); Ctrl+F9 and Ctrl+F10 change it while running
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value_t = 1.0,
+        value_parser = time_control::parse_time_scale,
+        conflicts_with_all = ["replay", "netplay"]
+    )]
+    time_scale: f64,
+
+    /// Show the latest lines each app logs via `wapps::log` at the bottom of
+    /// its window
+    #[arg(long)]
+    console: bool,
+
+    /// What to do when a guest traps or runs past its frame budget: quit the
+    /// host with the error, show the error in its window, or reinstantiate it
+    /// after an exponential backoff
+    #[arg(long, value_name = "ACTION", default_value = "exit")]
+    on_crash: OnCrash,
+
+    /// Show the error instead once an app has been restarted N times; needs
+    /// `--on-crash restart`
+    #[arg(long, value_name = "N")]
+    max_restarts: Option<u32>,
+
+    /// Same as `--on-crash restart --max-restarts MAX`
+    #[arg(
+        long,
+        value_name = "MAX",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with_all = ["on_crash", "max_restarts"]
+    )]
+    restart_on_crash: Option<Option<u32>>,
+
+    /// Allow apps to launch other packages (e.g. a launcher menu written as a
+    /// WAPP) without asking
+    #[arg(long)]
+    allow_launch: bool,
+
+    /// Give apps declaring the `filesystem` capability a private directory
+    /// under the user data directory as their WASI filesystem
+    #[arg(long)]
+    allow_storage: bool,
+
+    /// Start packages that import functions this host does not provide, linking
+    /// stubs that warn when called and return zeros
+    #[arg(long)]
+    allow_unknown_imports: bool,
+
+    /// Record every nondeterministic guest input (dt, events, clocks, random)
+    /// to FILE for bit-exact replay
+    #[arg(
+        long,
+        visible_alias = "record-inputs",
+        value_name = "FILE",
+        conflicts_with = "replay"
+    )]
+    record: Option<PathBuf>,
+
+    /// Record the session into a .wappreplay FILE to share: the session log
+    /// plus the package hash and WASI overrides, played with `wapps replay`
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    save_replay: Option<PathBuf>,
+
+    /// Replay a session recorded with --record instead of live input
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Stop replaying after this many frames, keeping the last frame on screen
+    #[arg(long, value_name = "FRAME", requires = "replay")]
+    replay_until: Option<usize>,
+
+    /// Run a deterministic package in lockstep with a second host, sharing
+    /// input events (experimental): `host` waits for a peer, `join` connects
+    #[arg(
+        long,
+        value_name = "ROLE",
+        requires = "netplay_address",
+        conflicts_with = "replay"
+    )]
+    netplay: Option<NetplayRole>,
+
+    /// Address to listen on (`--netplay host`) or connect to (`--netplay join`)
+    #[arg(long, value_name = "ADDR", requires = "netplay")]
+    netplay_address: Option<String>,
+
+    /// Only run packages rated for this age or younger without confirmation;
+    /// unrated packages count as above the limit
+    #[arg(long, value_name = "AGE")]
+    max_age_rating: Option<u8>,
+
+    /// What to do with packages above --max-age-rating
+    #[arg(
+        long,
+        value_name = "POLICY",
+        default_value = "confirm",
+        requires = "max_age_rating"
+    )]
+    over_rating: GatePolicy,
+
+    /// Trust packages signed by KEY (64 hex digits); repeatable
+    #[arg(long, value_name = "KEY")]
+    trusted_key: Vec<String>,
+
+    /// Trust the keys listed in FILE, one per line, instead of the
+    /// `trusted_keys` file in the user data directory
+    #[arg(long, value_name = "FILE")]
+    keyring: Option<PathBuf>,
+
+    /// What to do with unsigned or untrusted packages once trusted keys are set
+    #[arg(long, value_name = "POLICY", default_value = "warn")]
+    untrusted: UntrustedPolicy,
+
+    /// Clock precision for guests, overriding what packages ask for
+    #[arg(long, value_name = "POLICY")]
+    clock: Option<ClockPolicy>,
+
+    /// Seed the guests' random numbers, overriding what packages ask for
+    #[arg(long, visible_alias = "seed", value_name = "SEED")]
+    random_seed: Option<u64>,
+
+    /// Locale for package names and strings, e.g. `fr` or `pt-BR`
+    /// (defaults to LC_ALL, LC_MESSAGES or LANG)
+    #[arg(long, value_name = "TAG")]
+    locale: Option<String>,
+
+    /// Theme apps are told the user prefers (defaults to the desktop's)
+    #[arg(long, value_name = "THEME")]
+    theme: Option<Theme>,
+
+    /// Start with the frame diff view, which highlights pixels changed since
+    /// the previous frame (toggle at runtime with F4)
+    #[arg(long)]
+    frame_diff: bool,
+
+    /// Start simulating a color vision deficiency (cycle at runtime with F6)
+    #[arg(long, value_name = "DEFICIENCY")]
+    color_filter: Option<Deficiency>,
+
+    /// Brighten (positive) or darken (negative) presented frames, from -1 to 1
+    /// (adjust at runtime with F9/F10)
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        default_value_t = 0.0,
+        value_parser = display_adjust::parse_brightness
+    )]
+    brightness: f32,
+
+    /// Scale the contrast of presented frames, from 0 to 4 (adjust at runtime
+    /// with Shift+F9/F10)
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        default_value_t = 1.0,
+        value_parser = display_adjust::parse_contrast
+    )]
+    contrast: f32,
+
+    /// Gamma of presented frames, from 0.1 to 4; values above 1 lighten dark
+    /// tones (adjust at runtime with Alt+F9/F10)
+    #[arg(
+        long,
+        value_name = "GAMMA",
+        default_value_t = 1.0,
+        value_parser = display_adjust::parse_gamma
+    )]
+    gamma: f32,
+
+    /// Upscale frames through a post-processing filter, so low-resolution
+    /// apps look smoother on large displays
+    #[arg(long, value_name = "FILTER")]
+    filter: Option<PostFilter>,
+
+    /// Color behind the frame and in the letterbox bars, as `#rrggbb`,
+    /// overriding the package's `clear_color` (defaults to black)
+    #[arg(long, value_name = "COLOR", value_parser = graphics::parse_color)]
+    clear_color: Option<Color>,
+
+    /// How frames are scaled into their window; apps can change it through
+    /// `wapps::set_scaling_mode`
+    #[arg(long, value_name = "MODE", default_value = "stretch")]
+    scaling: ScalingMode,
+
+    /// What draws frames into the windows; `wgpu` and `softbuffer` need the
+    /// host built with the feature of the same name
+    #[arg(long, value_name = "BACKEND", default_value = "sdl")]
+    backend: Backend,
+
+    /// When presents wait for the display's refresh: `on` never tears,
+    /// `off` presents at once, and `adaptive` tears only frames that are
+    /// late (defaults to `on` for a single window and `off` for several)
+    #[arg(long, value_name = "MODE")]
+    vsync: Option<VsyncMode>,
+
+    /// Read input as late as vsync allows, run updates on the main thread
+    /// and present each frame as soon as it is uploaded, for the shortest
+    /// input latency (shown by the timing overlay, F1)
+    #[arg(long)]
+    low_latency: bool,
+
+    /// Turn what every window shows clockwise by 90, 180 or 270 degrees,
+    /// for displays mounted in portrait or upside down; pointer and touch
+    /// positions are turned back before reaching apps (needs the sdl backend)
+    #[arg(
+        long,
+        value_name = "DEGREES",
+        default_value = "0",
+        value_parser = orientation::parse_rotation
+    )]
+    rotate: Rotation,
+
+    /// Mirror what every window shows left to right, for displays seen
+    /// through a mirror (needs the sdl backend)
+    #[arg(long)]
+    mirror: bool,
+
+    /// Start every window in borderless fullscreen; apps can leave it
+    /// through `wapps::set_fullscreen`
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Start every window in borderless fullscreen with the mouse cursor
+    /// hidden, and refuse app requests to leave fullscreen, for kiosks
+    #[arg(long)]
+    kiosk: bool,
+
+    /// Remap keys, and map controller buttons to keys, with the TOML input
+    /// map FILE; files named after an app in `input/` under the user data
+    /// directory override it for that app
+    #[arg(long, value_name = "FILE")]
+    input_map: Option<PathBuf>,
+
+    /// Draw a D-pad and A and B buttons over apps, pressing the arrow keys,
+    /// Space and Return when touched or clicked, for touch-only devices;
+    /// input maps choose other keys as for a controller's buttons
+    #[arg(long)]
+    virtual_controls: bool,
+
+    /// Run with audio, networking, post-processing and GPU rendering off and
+    /// every permission denied, to tell app problems from host problems or
+    /// to run packages that are not trusted at all
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "allow_launch", "allow_storage", "netplay", "frame_diff", "color_filter", "brightness",
+            "contrast", "gamma", "filter", "backend",
+        ]
+    )]
+    safe_mode: bool,
+
+    /// Print each app's textual description of its screen (from its
+    /// `on_describe` export) to stdout whenever it changes, for screen readers
+    #[arg(long)]
+    describe: bool,
+
+    /// Write a hash of every frame the apps produce to FILE (`-` for stdout),
+    /// one `<frame> <hash> <app>` line per frame, to compare runs or machines
+    #[arg(long, value_name = "FILE")]
+    frame_hashes: Option<PathBuf>,
+
+    /// Show each app's frame rate, guest CPU time and memory in its window title
+    #[arg(long)]
+    show_usage: bool,
+
+    /// Print total frames, average FPS, dropped frames, guest CPU time and
+    /// peak memory for each app on exit
+    #[arg(long)]
+    stats: bool,
+
+    /// Cap each app's memory at SIZE (e.g. 512M or 2G); apps are told
+    /// through their `on_memory_pressure` export when they come close, and
+    /// apps needing more from the start are rejected; 0 removes the cap
+    #[arg(
+        long,
+        value_name = "SIZE",
+        default_value = "256M",
+        value_parser = memory_limit::parse_size
+    )]
+    max_memory: usize,
+
+    /// Interrupt a guest call (`update` or an event callback) running longer
+    /// than MS milliseconds, as a crash; 0 never interrupts
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = watchdog::DEFAULT_FRAME_BUDGET.as_millis() as u64
+    )]
+    frame_budget_ms: u64,
+
+    /// Reject frames, layers and canvases wider or taller than WxH with an
+    /// error status to the guest, rather than allocating for them
+    #[arg(
+        long,
+        value_name = "WxH",
+        default_value = "8192x8192",
+        value_parser = frame_limits::parse_frame_size
+    )]
+    max_frame_size: (u32, u32),
+
+    /// Reject the frames a guest presents past N in one update with an error
+    /// status, rather than uploading each of them
+    #[arg(long, value_name = "N", default_value_t = frame_limits::DEFAULT_MAX_FRAME_CALLS)]
+    max_frame_calls: u32,
+
+    /// Rather than letting slow updates stall the window, update an app
+    /// every other frame (or every fourth) with the time of both while its
+    /// `update` keeps taking longer than a frame; the guest is told through
+    /// its `on_perf_hint` export
+    #[arg(long, conflicts_with_all = ["replay", "netplay", "deterministic"])]
+    degrade: bool,
+
+    /// Run apps built for the wasi-threads ABI, whose threads share a memory
+    /// and run on several cores; their threads are not deterministic, so
+    /// they cannot be recorded, replayed, played over the network or resumed
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "record", "save_replay", "replay", "netplay", "deterministic", "session",
+        ]
+    )]
+    enable_threads: bool,
+
+    /// Set the startup setting KEY, which apps read with `wapps::config_get`,
+    /// overriding the package's `config`; may be repeated
+    #[arg(long, value_name = "KEY=VALUE", value_parser = guest_config::parse_guest_arg)]
+    guest_arg: Vec<(String, String)>,
+
+    /// Run apps without the WebAssembly PROPOSAL, refusing modules that use
+    /// it with an error naming it; may be repeated
+    #[arg(long, value_name = "PROPOSAL")]
+    disable_feature: Vec<Proposal>,
+
+    /// Compile the WASM module on every launch instead of reusing the copy
+    /// compiled by an earlier run from the user cache directory
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Once a second, send each app a synthetic input (a space bar press, or
+    /// a click at the center with `=pointer`) and print on exit how long the
+    /// app took to show a changed frame, for apps that redraw only on input
+    #[arg(
+        long,
+        value_name = "MARKER",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "key",
+        conflicts_with = "replay"
+    )]
+    measure_latency: Option<LatencyMarker>,
+
+    /// When an app crashes, write a report to DIR with the error and guest
+    /// backtrace, the latest log lines, host details and the --record
+    /// session, zipped for attaching to bug reports
+    #[arg(long, value_name = "DIR")]
+    crash_reports: Option<PathBuf>,
+
+    /// Include a snapshot of the app's memory in crash reports
+    #[arg(long, requires = "crash_reports")]
+    crash_report_memory: bool,
+
+    /// Reload the app when its package changes, keeping its window open, or
+    /// run the .wasm MODULE (e.g. a build output) instead of the package's
+    /// and reload that; the guest's `on_reload` export is called after
+    #[arg(
+        long,
+        value_name = "MODULE",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with_all = ["record", "save_replay", "replay", "netplay"]
+    )]
+    watch: Option<Option<PathBuf>>,
+
+    /// Carry the app's memory over to each reloaded build, keeping its state
+    /// as long as the build did not change how that state is laid out
+    #[arg(long, requires = "watch")]
+    watch_keep_memory: bool,
+
+    /// Suspend the app to FILE when it exits, and offer to resume from FILE
+    /// where it was left off when launched again with the same build
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["record", "save_replay", "replay", "netplay", "safe_mode"]
+    )]
+    session: Option<PathBuf>,
+
+    /// Save a screenshot of each app once it has presented N frames (take
+    /// more at runtime with F12)
+    #[arg(long, value_name = "N")]
+    screenshot_after: Option<u64>,
+
+    /// Record the app's frames to FILE, timed by the guest's dt: an animated
+    /// GIF, or any video format ffmpeg can write, such as .mp4 (start and stop
+    /// GIF recordings at runtime with F11)
+    #[arg(long, value_name = "FILE")]
+    record_video: Option<PathBuf>,
+
+    /// Record where each frame's time goes (event dispatch, guest update,
+    /// frame copy, texture upload, present) and write it to FILE on exit as a
+    /// Chrome trace, viewable in chrome://tracing or https://ui.perfetto.dev
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+
+    /// Append each app's session statistics to FILE as JSON lines on exit
+    #[arg(long, value_name = "FILE")]
+    stats_file: Option<PathBuf>,
+
+    /// Also log the --stats summaries, or append them to --stats-file, every
+    /// SECS seconds while the apps run
+    #[arg(long, value_name = "SECS")]
+    stats_interval: Option<u64>,
+
+    /// Serve frame rate, uptime, crash count and guest memory as JSON
+    /// at http://ADDR/metrics (e.g. 127.0.0.1:9898)
+    #[cfg(feature = "metrics")]
+    #[arg(long, value_name = "ADDR")]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Once no input has been received for this long (e.g. `120s` or `2m`),
+    /// notify apps through their `on_idle` export and, when several apps run,
+    /// bring their windows to the front in turn every period, for kiosk
+    /// attract modes
+    #[arg(long, value_name = "DURATION", value_parser = idle::parse_duration)]
+    attract_after: Option<Duration>,
+
+    /// Run the app without a window, audio or input, writing the frames it
+    /// presents to --headless-output instead of showing them
+    #[arg(
+        long,
+        conflicts_with_all = ["record", "save_replay", "replay", "netplay", "watch", "profile"]
+    )]
+    headless: bool,
+
+    /// Number of frames to run headlessly
+    #[arg(long, value_name = "N", default_value_t = 600, requires = "headless")]
+    headless_frames: u32,
+
+    /// Frames per second of guest time when headless, each `update` getting
+    /// a fixed dt of 1/FPS
+    #[arg(long, value_name = "FPS", default_value_t = 60, requires = "headless")]
+    headless_fps: u32,
+
+    /// Where headless frames go: a directory of numbered PNGs, or a video
+    /// stream if PATH ends in `.y4m`
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value = "frames",
+        requires = "headless"
+    )]
+    headless_output: PathBuf,
 
     /// Enable verbose logging
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     verbose: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a package's metadata without running it
+    Inspect(inspect::InspectArgs),
+    /// Build a reproducible package from a WebAssembly module and a JSON manifest
+    Pack(packer::PackArgs),
+    /// Check that a package would load on this host, without running it
+    Validate(validate::ValidateArgs),
+    /// Extract a package's module, manifest, icon and assets into a directory
+    Unpack(unpack::UnpackArgs),
+    /// Write a patch that turns one package version into another
+    Diff(delta::DiffArgs),
+    /// Rebuild a new package version from an old one and a patch
+    Apply(delta::ApplyArgs),
+    /// Run a package headlessly and save a PNG thumbnail of its screen
+    Thumbnail(thumbnail::ThumbnailArgs),
+    /// Run two packages in lockstep with identical inputs and report the
+    /// first frame where their output diverges
+    Compare(compare::CompareArgs),
+    /// Review, revoke or reset the permissions granted to an app
+    Permissions(permissions::PermissionsArgs),
+    /// Create a key to sign packages with `wapps pack --sign-key`, printing
+    /// its public key
+    Keygen(signing::KeygenArgs),
+    /// Play back a replay saved with --save-replay, after checking that its
+    /// package is the one it was recorded with
+    Replay(replay_file::ReplayArgs),
+    /// Run scripted scenarios against packages headlessly, optionally
+    /// writing a JUnit or JSON report for CI
+    Test(scenario::TestArgs),
+    /// Run a package headlessly and report its update and frame copy times
+    /// and memory growth
+    Bench(bench::BenchArgs),
+    /// Print guest bindings for the wapps host interface, generated from its
+    /// WIT definition
+    Bindgen(bindgen::BindgenArgs),
+    /// Download a package by name from a package index into the library
+    Install(registry::InstallArgs),
+    /// Run a package installed with `wapps install`
+    Run(registry::RunArgs),
+    /// List the packages installed with `wapps install`
+    List,
+}
+
 fn main() -> Result<()> {
     // Parse CLI arguments
-    let args = Args::parse();
+    let mut args = Args::parse();
+    if args.max_restarts.is_some() && args.on_crash != OnCrash::Restart {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--max-restarts only applies with --on-crash restart",
+            )
+            .exit();
+    }
 
     // Initialize logging
     let log_level = if args.verbose { "debug" } else { "info" };
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level))
-        .format_timestamp_millis()
-        .init();
+    TailLogger::init(
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level))
+            .format_timestamp_millis()
+            .build(),
+    );
+
+    if args.no_cache {
+        module_cache::disable();
+    }
+
+    let mut shared_replay = None;
+    match args.command.take() {
+        Some(Command::Replay(replay_args)) => {
+            let (package, replay) = replay_file::open(&replay_args)?;
+            args.wapp_files = vec![package];
+            args.replay_until = replay_args.until;
+            args.random_seed = replay.random_seed;
+            args.clock = replay.clock;
+            shared_replay = Some(Session::from_log(replay.session)?);
+        }
+        Some(Command::Run(run_args)) => {
+            args.wapp_files = vec![registry::installed_path(&run_args.name)?];
+        }
+        Some(command) => return run_command(&command),
+        None => {}
+    }
+
+    // Deterministic runs read virtual clocks and seeded random numbers
+    if args.deterministic {
+        args.clock = Some(ClockPolicy::Virtual);
+        args.random_seed = Some(args.random_seed.unwrap_or(0));
+    }
+
+    if args.register_url_scheme {
+        return deeplink::register_url_scheme();
+    }
+
+    if args.headless {
+        let [file] = args.wapp_files.as_slice() else {
+            bail!("--headless runs a single app");
+        };
+        return headless::run(&HeadlessOptions {
+            file: file.clone(),
+            output: args.headless_output.clone(),
+            frames: args.headless_frames,
+            fps: args.headless_fps,
+            clock: args.clock,
+            random_seed: args.random_seed,
+            allow_unknown_imports: args.allow_unknown_imports,
+            max_memory: (args.max_memory > 0).then_some(args.max_memory),
+            frame_budget: (args.frame_budget_ms > 0)
+                .then(|| Duration::from_millis(args.frame_budget_ms)),
+            frame_limits: requested_frame_limits(&args),
+            guest_config: args.guest_arg.clone(),
+        });
+    }
 
     info!("WAPPS Host starting...");
-    debug!("Loading: {:?}", args.wapp_file);
+    debug!("Loading: {:?}", args.wapp_files);
 
-    // Run the application
-    if let Err(e) = run_app(&args.wapp_file) {
+    if let [dir] = args.wapp_files.as_slice() {
+        if dir.is_dir() {
+            let (dir, kiosk, backend) = (dir.clone(), args.kiosk, args.backend);
+            let orientation = requested_orientation(&args);
+            args.from_gallery = true;
+            return launcher::run(&dir, kiosk, backend, orientation, |path| {
+                args.wapp_files = vec![path.to_path_buf()];
+                run_apps(&args, None)
+            });
+        }
+    }
+
+    // Run the application(s)
+    if let Err(e) = run_apps(&args, shared_replay) {
         error!("Application error: {:#}", e);
         std::process::exit(1);
     }
@@ -55,126 +813,921 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn run_app(wapp_path: &PathBuf) -> Result<()> {
-    // Load and validate the WAPP file
-    let (wasm_bytes, metadata) = loader::load_wapp(wapp_path)
-        .with_context(|| format!("Failed to load WAPP file: {:?}", wapp_path))?;
+/// Run a subcommand other than `replay`
+fn run_command(command: &Command) -> Result<()> {
+    match command {
+        Command::Inspect(inspect_args) => inspect::run(inspect_args),
+        Command::Pack(pack_args) => packer::run(pack_args),
+        Command::Unpack(unpack_args) => unpack::run(unpack_args),
+        Command::Validate(validate_args) => validate::run(validate_args),
+        Command::Diff(diff_args) => delta::run_diff(diff_args),
+        Command::Apply(apply_args) => delta::run_apply(apply_args),
+        Command::Thumbnail(thumbnail_args) => thumbnail::run(thumbnail_args),
+        Command::Compare(compare_args) => compare::run(compare_args),
+        Command::Permissions(permissions_args) => permissions::run(permissions_args),
+        Command::Keygen(keygen_args) => signing::run_keygen(keygen_args),
+        Command::Test(test_args) => scenario::run(test_args),
+        Command::Bench(bench_args) => bench::run(bench_args),
+        Command::Bindgen(bindgen_args) => bindgen::run(bindgen_args),
+        Command::Install(install_args) => registry::run_install(install_args),
+        Command::List => registry::run_list(),
+        Command::Replay(_) | Command::Run(_) => unreachable!("these commands run the apps"),
+    }
+}
 
-    info!(
-        "WAPP loaded successfully ({} bytes of WASM). Name: {:?}",
-        wasm_bytes.len(),
-        metadata.name
-    );
+/// Run the apps named on the command line, or the package of `shared_replay`
+fn run_apps(args: &Args, shared_replay: Option<Session>) -> Result<()> {
+    // Show a single app as its own application in task switchers
+    if let [wapp_file] = args.wapp_files.as_slice() {
+        window_identity::identify_as_package(wapp_file);
+    }
+    let mut context = GraphicsContext::new().context("Failed to initialize graphics")?;
+    if args.safe_mode {
+        info!("Safe mode: audio, networking, post-processing and GPU rendering are off");
+        context.use_software_renderer();
+    }
+    context.set_backend(args.backend);
+    context.set_orientation(requested_orientation(args));
+    if args.kiosk {
+        context.hide_cursor();
+    }
 
-    if !metadata.description.is_empty() {
-        info!("Description: {}", metadata.description);
+    let recording = args.record.is_some() || args.save_replay.is_some();
+    let session = match (recording, &args.replay, shared_replay) {
+        (_, _, Some(session)) => Some(session),
+        (false, None, None) => None,
+        _ if args.wapp_files.len() > 1 => {
+            bail!("--record, --save-replay and --replay support a single app")
+        }
+        (true, _, None) => Some(Session::record()),
+        (false, Some(path), None) => Some(Session::replay(path)?),
+    };
+    let mut netplay = match (args.netplay, &args.netplay_address) {
+        (Some(_), _) if args.wapp_files.len() > 1 => bail!("--netplay supports a single app"),
+        (Some(role), Some(address)) => {
+            let (path, _) = deeplink::resolve_argument(&args.wapp_files[0])?;
+            Some(Netplay::connect(role, address, &path)?)
+        }
+        _ => None,
+    };
+    if matches!(args.watch, Some(Some(_))) && args.wapp_files.len() > 1 {
+        bail!("--watch=MODULE supports a single app");
+    }
+    if args.record_video.is_some() && args.wapp_files.len() > 1 {
+        bail!("--record-video supports a single app");
+    }
+    if args.session.is_some() && args.wapp_files.len() > 1 {
+        bail!("--session supports a single app");
     }
 
-    // Determine window title
-    let title = if !metadata.name.is_empty() {
-        metadata.name
-    } else {
-        wapp_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("WAPPS")
-            .to_string()
+    // Write the recording on every exit path, including guest crashes
+    let _save_recording = args
+        .record
+        .clone()
+        .zip(session.clone())
+        .map(|(path, session)| SaveOnDrop::new(session, path));
+    let _save_replay = match (&args.save_replay, &session) {
+        (Some(path), Some(session)) => {
+            let (package, _) = deeplink::resolve_argument(&args.wapp_files[0])?;
+            Some(SaveReplayOnDrop::new(
+                path.clone(),
+                session.clone(),
+                &package,
+                args.random_seed,
+                args.clock,
+            )?)
+        }
+        _ => None,
     };
+    let profiler = args.profile.as_ref().map(|_| Profiler::new());
+    let _save_profile = args
+        .profile
+        .clone()
+        .zip(profiler.clone())
+        .map(|(path, profiler)| SaveProfileOnDrop::new(profiler, path));
 
-    // Initialize graphics
-    let mut graphics = Graphics::new(&title, 800, 600).context("Failed to initialize graphics")?;
+    let options = AppOptions {
+        // Presenting several windows with vsync would block once per window each
+        // frame, so multi-app mode relies on the frame timing below by default
+        vsync: args
+            .vsync
+            .unwrap_or(if args.wapp_files.len() == 1 && !args.allow_launch {
+                VsyncMode::On
+            } else {
+                VsyncMode::Off
+            }),
+        low_latency: args.low_latency,
+        show_usage: args.show_usage,
+        allow_launch: args.allow_launch,
+        allow_storage: args.allow_storage,
+        allow_unknown_imports: args.allow_unknown_imports,
+        frame_diff: args.frame_diff,
+        color_filter: args.color_filter,
+        display_adjustment: Adjustment {
+            brightness: args.brightness,
+            contrast: args.contrast,
+            gamma: args.gamma,
+        },
+        filter: args.filter,
+        clear_color: args.clear_color,
+        scaling: args.scaling,
+        fullscreen: args.fullscreen,
+        kiosk: args.kiosk,
+        describe: args.describe,
+        locale: args.locale.clone(),
+        theme: args.theme.unwrap_or_else(Theme::system),
+        parental_gate: args
+            .max_age_rating
+            .map(|age| ParentalGate::new(age, args.over_rating)),
+        keyring: Keyring::load(&args.trusted_key, args.keyring.as_deref(), args.untrusted)?,
+        session: session.clone(),
+        frame_hashes: args
+            .frame_hashes
+            .as_deref()
+            .map(FrameHashLog::create)
+            .transpose()?,
+        // Lockstep guests must read the same clocks and random numbers
+        clock: match netplay {
+            Some(_) => Some(ClockPolicy::Disabled),
+            None => args.clock,
+        },
+        random_seed: netplay.as_ref().map(Netplay::seed).or(args.random_seed),
+        measure_latency: args.measure_latency,
+        max_memory: (args.max_memory > 0).then_some(args.max_memory),
+        frame_budget: (args.frame_budget_ms > 0)
+            .then(|| Duration::from_millis(args.frame_budget_ms)),
+        frame_limits: requested_frame_limits(args),
+        crash_reports: args
+            .crash_reports
+            .clone()
+            .map(|dir| CrashReporter::new(dir, args.crash_report_memory)),
+        watch: args.watch.clone().map(|module| WatchOptions {
+            module,
+            keep_memory: args.watch_keep_memory,
+        }),
+        safe_mode: args.safe_mode,
+        screenshot_after: args.screenshot_after,
+        record_video: args.record_video.clone(),
+        profiler,
+        // The guest thread merges the dt of updates it could not keep up
+        // with, leaving the previous frame on screen, which --low-latency
+        // avoids and which would break --fixed-dt's constant steps
+        guest_thread: session.is_none()
+            && netplay.is_none()
+            && !args.deterministic
+            && !args.low_latency
+            && args.fixed_dt.is_none(),
+        pause_on_blur: args.pause_on_blur,
+        power_save: args.power_save,
+        fixed_dt: args.fixed_dt.is_some(),
+        console: args.console,
+        // `--restart-on-crash` leaves `--on-crash` at its default, but falls
+        // back to the error screen like `--on-crash restart`
+        exit_on_crash: args.on_crash == OnCrash::Exit && args.restart_on_crash.is_none(),
+        count_import_calls: args.stats
+            || args.stats_file.is_some()
+            || args.stats_interval.is_some(),
+        suspend_file: args.session.clone(),
+        input_map: args.input_map.as_deref().map(InputMap::load).transpose()?,
+        virtual_controls: args.virtual_controls,
+        degrade: args.degrade,
+        enable_threads: args.enable_threads,
+        disabled_features: DisabledFeatures::new(args.disable_feature.iter().copied()),
+        guest_config: args.guest_arg.clone(),
+    };
 
-    // Initialize WASM runtime with host interface
-    let host_interface = HostInterface::new();
-    let mut runtime = WasmRuntime::new(&wasm_bytes, host_interface)
-        .context("Failed to initialize WASM runtime")?;
+    let mut apps = args
+        .wapp_files
+        .iter()
+        .map(|arg| {
+            let (path, guest_args) = deeplink::resolve_argument(arg)?;
+            AppInstance::load(&context, &path, guest_args, &options)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let restart_policy = match (args.restart_on_crash, args.on_crash) {
+        (Some(max_restarts), _) => Some(RestartPolicy::new(max_restarts)),
+        (None, OnCrash::Restart) => Some(RestartPolicy::new(args.max_restarts)),
+        (None, OnCrash::Overlay | OnCrash::Exit) => None,
+    };
+
+    // The watched module, the video and the session file only concern the
+    // apps given
+    let options = AppOptions {
+        watch: options.watch.clone().map(|watch| WatchOptions {
+            module: None,
+            ..watch
+        }),
+        record_video: None,
+        suspend_file: None,
+        ..options
+    };
+
+    let mut pool = None;
+    configure_multi_app(&mut apps, &mut pool, args)?;
+
+    #[cfg(feature = "menu")]
+    let mut menu_bar = menu::HostMenu::new().context("Failed to create the menu bar")?;
+    #[cfg(feature = "menu")]
+    for app in &apps {
+        menu_bar.attach(app.window());
+        menu_bar.add_recent(app.path());
+    }
+
+    #[cfg(feature = "metrics")]
+    if args.safe_mode && args.metrics_addr.is_some() {
+        bail!("--metrics-addr serves over the network, which is off in safe mode");
+    }
+    #[cfg(feature = "metrics")]
+    let metrics = args.metrics_addr.map(metrics::Metrics::serve).transpose()?;
+    #[cfg(feature = "metrics")]
+    let mut last_publish = Instant::now();
+
+    // Safe mode leaves the game controller subsystem uninitialized
+    let mut gamepads = match (!args.safe_mode).then(|| Gamepads::new(&context)) {
+        None => None,
+        Some(Ok(gamepads)) => Some(gamepads),
+        Some(Err(e)) => {
+            warn!("Gamepad feedback unavailable: {:#}", e);
+            None
+        }
+    };
 
     // Main event loop
     let mut last_time = Instant::now();
-    let target_frame_time = std::time::Duration::from_secs_f64(1.0 / 60.0);
+    let mut last_stats = Instant::now();
+    let mut replay_ended = false;
+    let mut time = TimeControl::new(args.paused, args.time_scale);
+    #[cfg(feature = "menu")]
+    menu_bar.set_paused(args.paused);
+    let mut idle_timer = args
+        .attract_after
+        .map(|period| IdleTimer::new(period, Instant::now()));
+    let target_frame_time = std::time::Duration::from_secs_f64(1.0 / args.fps);
+    let mut fixed_step = args.fixed_dt.map(FixedStep::new);
+    // How long to wait for input once every app is idle under --power-save
+    let mut idle_wait = None;
+    // Under --low-latency with vsync, input is read just in time for the
+    // refresh instead of right after the previous present
+    let mut late_poll = (args.low_latency && options.vsync.waits()).then(LatePoll::default);
 
     'main_loop: loop {
+        if let Some(late_poll) = late_poll.as_ref().filter(|_| idle_wait.is_none()) {
+            let period = apps
+                .first()
+                .and_then(AppInstance::refresh_period)
+                .unwrap_or(target_frame_time);
+            std::thread::sleep(late_poll.delay(period));
+        }
+
+        // Idle apps under --power-save wait for input, counted in this frame's dt
+        let events = match idle_wait.take() {
+            Some(timeout) => context.wait_events(timeout),
+            None => context.poll_events(),
+        };
+
         // Calculate delta time
         let now = Instant::now();
         let dt = now.duration_since(last_time).as_secs_f64();
         last_time = now;
 
-        // Process SDL events
-        for event in graphics.poll_events() {
-            use sdl2::event::Event;
-            use sdl2::event::WindowEvent;
+        // Process SDL events, queueing each for the app whose window it targets
+        let mut close_vetoed = false;
+        for event in events {
+            if let Some(timer) = idle_timer.as_mut().filter(|_| idle::is_user_input(&event)) {
+                timer.input(now);
+            }
+            if let Some(gamepads) = &mut gamepads {
+                gamepads.handle_event(&event);
+            }
 
             match event {
+                // Closing the last window also sends a quit event, which its
+                // app already answered
+                Event::Quit { .. } if close_vetoed => continue,
                 Event::Quit { .. } => {
                     info!("Quit event received");
-                    break 'main_loop;
+                    // Every app is asked, so that each can save its state
+                    let vetoes = apps
+                        .iter_mut()
+                        .map(|app| app.request_close())
+                        .filter(|closed| !closed)
+                        .count();
+                    if vetoes == 0 {
+                        break 'main_loop;
+                    }
+                    continue;
                 }
                 Event::Window {
-                    win_event: WindowEvent::Resized(w, h),
+                    window_id,
+                    win_event: WindowEvent::Close,
                     ..
                 } => {
-                    debug!("Window resized to {}x{}", w, h);
-                    runtime.call_on_resize(w, h)?;
+                    let Some(index) = apps.iter().position(|app| app.window_id() == window_id)
+                    else {
+                        continue;
+                    };
+                    if !apps[index].request_close() {
+                        close_vetoed = true;
+                        continue;
+                    }
+                    info!("Closing {:?}", apps[index].name());
+                    apps.remove(index).shutdown();
+                    if apps.is_empty() {
+                        break 'main_loop;
+                    }
+                    continue;
                 }
-                Event::MouseMotion { x, y, .. } => {
-                    runtime.call_on_pointer_move(x, y)?;
+                Event::Window {
+                    window_id,
+                    win_event: win_event @ (WindowEvent::FocusGained | WindowEvent::FocusLost),
+                    ..
+                } => {
+                    let focused = win_event == WindowEvent::FocusGained;
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        app.set_focused(focused);
+                    }
                 }
-                Event::MouseButtonDown {
-                    x, y, mouse_btn, ..
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Leave,
+                    ..
                 } => {
-                    let button = mouse_button_to_int(mouse_btn);
-                    runtime.call_on_pointer_down(x, y, button)?;
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        app.set_cursor(None);
+                    }
+                }
+                // Ctrl+scroll zooms the presented frame without the guest knowing
+                Event::MouseWheel {
+                    window_id,
+                    y,
+                    mouse_x,
+                    mouse_y,
+                    ..
+                } if context.ctrl_held() => {
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        app.zoom_view(mouse_x, mouse_y, y.signum());
+                    }
+                    continue;
                 }
-                Event::MouseButtonUp {
-                    x, y, mouse_btn, ..
+                // Ctrl+drag pans a zoomed view; the guest sees no pointer input meanwhile
+                Event::MouseMotion {
+                    window_id,
+                    mousestate,
+                    x,
+                    y,
+                    xrel,
+                    yrel,
+                    ..
+                } => {
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        app.set_cursor(Some((x, y)));
+                        if app.is_view_zoomed() && context.ctrl_held() {
+                            if mousestate.left() {
+                                app.pan_view(xrel, yrel);
+                            }
+                            continue;
+                        }
+                    }
+                }
+                Event::MouseButtonDown { window_id, .. }
+                | Event::MouseButtonUp { window_id, .. }
+                    if context.ctrl_held()
+                        && apps
+                            .iter()
+                            .any(|app| app.window_id() == window_id && app.is_view_zoomed()) =>
+                {
+                    continue;
+                }
+                // A crashed app's window only takes the crash screen's keys
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    window_id,
+                    repeat: false,
+                    ..
+                } if apps
+                    .iter()
+                    .any(|app| app.window_id() == window_id && app.is_crashed()) =>
+                {
+                    let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) else {
+                        continue;
+                    };
+                    match keycode {
+                        Keycode::R => app.restart_after_crash(),
+                        Keycode::Q | Keycode::Escape => {
+                            if let Some(error) = app.take_crash() {
+                                return Err(error);
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    repeat: false,
+                    ..
+                } if args.from_gallery => {
+                    info!("Returning to the gallery");
+                    break 'main_loop;
+                }
+                // Host debug hotkeys are not forwarded to the guest
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            keycode @ (Keycode::F1
+                            | Keycode::F4
+                            | Keycode::F5
+                            | Keycode::F6
+                            | Keycode::F7),
+                        ),
+                    window_id,
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        match keycode {
+                            Keycode::F1 => app.toggle_timing_overlay(),
+                            Keycode::F4 => app.toggle_frame_diff(),
+                            Keycode::F5 => app.toggle_inspector(),
+                            Keycode::F6 => app.cycle_color_filter(),
+                            _ => {
+                                if let Err(e) = app.print_description() {
+                                    warn!("Failed to describe {:?}: {:#}", app.name(), e);
+                                }
+                            }
+                        }
+                    }
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::F9 | Keycode::F10)),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if session.as_ref().is_some_and(Session::is_replaying) || netplay.is_some() {
+                        warn!("The time scale is fixed while replaying or in netplay");
+                    } else {
+                        let steps = if keycode == Keycode::F9 { -1 } else { 1 };
+                        info!("Time scale {}x", time.change_scale(steps));
+                    }
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::F9 | Keycode::F10)),
+                    keymod,
+                    window_id,
+                    ..
+                } => {
+                    let control = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        Control::Contrast
+                    } else if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+                        Control::Gamma
+                    } else {
+                        Control::Brightness
+                    };
+                    let steps = if keycode == Keycode::F9 { -1 } else { 1 };
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        app.adjust_display(control, steps);
+                    }
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::F2 | Keycode::F3)),
+                    window_id,
+                    repeat: false,
+                    ..
                 } => {
-                    let button = mouse_button_to_int(mouse_btn);
-                    runtime.call_on_pointer_up(x, y, button)?;
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        if keycode == Keycode::F2 {
+                            app.save_state();
+                        } else {
+                            app.restore_state();
+                        }
+                    }
+                    continue;
                 }
                 Event::KeyDown {
-                    scancode: Some(sc), ..
+                    keycode: Some(keycode @ (Keycode::F11 | Keycode::F12)),
+                    window_id,
+                    repeat: false,
+                    ..
                 } => {
-                    runtime.call_on_key_down(sc as i32)?;
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        if keycode == Keycode::F11 {
+                            app.toggle_video();
+                        } else {
+                            app.screenshot();
+                        }
+                    }
+                    continue;
+                }
+                // Controller buttons reach the focused app as the keys its
+                // input map assigns to them
+                Event::ControllerButtonDown {
+                    timestamp, button, ..
                 }
-                Event::KeyUp {
-                    scancode: Some(sc), ..
+                | Event::ControllerButtonUp {
+                    timestamp, button, ..
                 } => {
-                    runtime.call_on_key_up(sc as i32)?;
+                    let pressed = matches!(event, Event::ControllerButtonDown { .. });
+                    if let Some(app) = apps.iter_mut().find(|app| app.is_focused()) {
+                        app.press_button(button, pressed, timestamp as f64 / 1000.0);
+                    }
+                    continue;
+                }
+                Event::DropFile {
+                    timestamp,
+                    window_id,
+                    ref filename,
+                } => {
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        app.drop_file(Path::new(filename), timestamp as f64 / 1000.0);
+                    }
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                    time.step();
+                    #[cfg(feature = "menu")]
+                    menu_bar.set_paused(true);
+                    continue;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    repeat: false,
+                    ..
+                } => {
+                    let paused = !time.is_paused();
+                    time.set_paused(paused);
+                    info!("{}", if paused { "Paused" } else { "Resumed" });
+                    #[cfg(feature = "menu")]
+                    menu_bar.set_paused(paused);
+                    continue;
                 }
                 _ => {}
             }
+
+            let Some(guest_event) = TimedEvent::from_sdl(&event) else {
+                continue;
+            };
+
+            if let GuestEvent::Resize { width, height } = guest_event.event {
+                debug!("Window resized to {}x{}", width, height);
+            }
+
+            match event.get_window_id() {
+                Some(window_id) => {
+                    if let Some(app) = apps.iter_mut().find(|app| app.window_id() == window_id) {
+                        app.push_event(guest_event);
+                    }
+                }
+                // SDL does not say which window a touch landed in, so
+                // touches go to the focused one
+                None if guest_event.event.is_touch() => {
+                    if let Some(app) = apps.iter_mut().find(|app| app.is_focused()) {
+                        app.push_event(guest_event);
+                    }
+                }
+                None => {
+                    for app in apps.iter_mut() {
+                        app.push_event(guest_event.clone());
+                    }
+                }
+            }
+        }
+
+        // Attract mode: notify guests once input stops, then cycle through windows
+        if let Some(periods) = idle_timer.as_mut().and_then(|timer| timer.poll(now)) {
+            if periods == 1 {
+                info!("No input received, entering attract mode");
+                let time = graphics::host_time().as_secs_f64();
+                for app in apps.iter_mut() {
+                    app.push_event(TimedEvent {
+                        event: GuestEvent::Idle,
+                        time,
+                    });
+                }
+            }
+            let count = apps.len();
+            if count > 1 {
+                apps[periods as usize % count].raise_window();
+            }
+        }
+
+        #[cfg(feature = "menu")]
+        for action in menu_bar.poll() {
+            match action {
+                menu::MenuAction::Quit => {
+                    info!("Quit chosen from the menu");
+                    break 'main_loop;
+                }
+                menu::MenuAction::SetPaused(value) => {
+                    info!("{}", if value { "Paused" } else { "Resumed" });
+                    time.set_paused(value);
+                }
+                menu::MenuAction::Open(_) if session.is_some() => {
+                    warn!("Cannot open packages while recording or replaying");
+                }
+                menu::MenuAction::Open(path) => {
+                    match AppInstance::load(&context, &path, Vec::new(), &options) {
+                        Ok(app) => {
+                            menu_bar.attach(app.window());
+                            menu_bar.add_recent(&path);
+                            apps.push(app);
+                            configure_multi_app(&mut apps, &mut pool, args)?;
+                        }
+                        Err(e) => warn!("Failed to open {:?}: {:#}", path, e),
+                    }
+                }
+                action => {
+                    // View and debug actions apply to the focused window
+                    let focused = apps.iter().position(AppInstance::is_focused).unwrap_or(0);
+                    if let Some(app) = apps.get_mut(focused) {
+                        run_menu_action(app, action);
+                    }
+                }
+            }
         }
 
-        // Call guest update
-        runtime.call_update(dt)?;
+        // Swap in new builds under --watch
+        for app in apps.iter_mut() {
+            if let Err(error) = app.poll_reload() {
+                app.handle_crash(error, restart_policy.as_ref())?;
+            }
+        }
 
-        // Get the latest frame from the host interface and update graphics
-        // Uses zero-copy borrow pattern to avoid allocating a new Vec each frame
-        if let Some(result) = runtime.with_frame_data(|width, height, pixels| {
-            graphics.update_texture(width as u32, height as u32, pixels)
-        }) {
-            result?;
+        // Netplay merges both players' inputs and fixes dt, as do deterministic
+        // runs; the time scale applies on top of the latter
+        let mut dt = if args.deterministic {
+            DETERMINISTIC_DT
+        } else {
+            dt
+        } * time.scale();
+        let paused = !time.take_update();
+        if let Some(netplay) = netplay.as_mut().filter(|_| !paused) {
+            let events = netplay.exchange(apps[0].pending_events())?;
+            apps[0].set_pending_events(events);
+            dt = NETPLAY_DT;
         }
 
-        // Render
-        graphics.render()?;
+        // Recorded sessions capture this frame's inputs; replays substitute them
+        let mut run_update = !paused;
+        if let Some(session) = session.as_ref().filter(|_| !paused) {
+            match step_session(session, &mut apps[0], dt, args.replay_until) {
+                Some(session_dt) => dt = session_dt,
+                None => {
+                    if !replay_ended {
+                        info!("Replay paused at frame {}", session.frame_index());
+                        replay_ended = true;
+                    }
+                    run_update = false;
+                }
+            }
+        }
+
+        // Call guest updates (in parallel when a pool is available), in as
+        // many constant steps as the frame's time calls for under --fixed-dt
+        if run_update {
+            let (steps, dt) = match &mut fixed_step {
+                Some(fixed) => (fixed.advance(dt), fixed.step()),
+                None => (1, dt),
+            };
+            for _ in 0..steps {
+                for (index, error) in app::update_all(&mut apps, pool.as_ref(), dt) {
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &metrics {
+                        metrics.record_crash();
+                    }
+                    apps[index].handle_crash(error, restart_policy.as_ref())?;
+                }
+            }
+            if let Some(session) = &session {
+                let frame = session.frame_index();
+                if frame % SNAPSHOT_INTERVAL == 0 {
+                    apps[0].snapshot_memory(session, frame);
+                }
+            }
+        }
+
+        // Hand off the latest frames and render on the main thread; the
+        // presents themselves may wait for vsync, so their time is left out
+        if let Some(late_poll) = &mut late_poll {
+            late_poll.record_work(now.elapsed());
+        }
+        for app in apps.iter_mut() {
+            app.present()
+                .with_context(|| format!("Failed to present {:?}", app.name()))?;
+            if let Err(error) = app.report_present() {
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &metrics {
+                    metrics.record_crash();
+                }
+                app.handle_crash(error, restart_policy.as_ref())?;
+            }
+            if let Err(e) = app.poll_description() {
+                warn!("Failed to describe {:?}: {:#}", app.name(), e);
+            }
+        }
 
-        // Frame timing
+        // Pass messages apps sent on the bus to the apps subscribed to them
+        app::relay_bus_messages(&mut apps);
+
+        // Apply the rumble and LED changes guests asked for
+        for app in apps.iter_mut() {
+            let requests = app.take_gamepad_requests();
+            if let Some(gamepads) = &mut gamepads {
+                requests
+                    .into_iter()
+                    .for_each(|request| gamepads.apply(request));
+            }
+        }
+
+        // Open windows for packages launched by guests
+        let launches: Vec<_> = apps
+            .iter_mut()
+            .flat_map(|app| app.take_launch_requests())
+            .collect();
+        if !launches.is_empty() {
+            for path in launches {
+                info!("Launching {:?}", path);
+                match AppInstance::load(&context, &path, Vec::new(), &options) {
+                    Ok(app) => {
+                        #[cfg(feature = "menu")]
+                        menu_bar.attach(app.window());
+                        apps.push(app);
+                    }
+                    Err(e) => warn!("Failed to launch {:?}: {:#}", path, e),
+                }
+            }
+            configure_multi_app(&mut apps, &mut pool, args)?;
+        }
+
+        if let Some(interval) = args.stats_interval {
+            if last_stats.elapsed() >= Duration::from_secs(interval) {
+                last_stats = Instant::now();
+                log_stats(&apps, args)?;
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &metrics {
+            if last_publish.elapsed() >= std::time::Duration::from_secs(1) {
+                last_publish = Instant::now();
+                metrics.publish(
+                    apps.iter()
+                        .map(|app| {
+                            metrics::AppMetrics::new(app.name(), app.usage(), app.session_summary())
+                        })
+                        .collect(),
+                );
+            }
+        }
+
+        // Frame timing; idle apps under --power-save wait for input instead,
+        // --low-latency waits before polling and --uncapped never waits
+        idle_wait = args.power_save.then(|| app::idle_wait(&apps)).flatten();
         let elapsed = Instant::now().duration_since(now);
-        if elapsed < target_frame_time {
+        if late_poll.is_none()
+            && idle_wait.is_none()
+            && !args.uncapped
+            && elapsed < target_frame_time
+        {
             std::thread::sleep(target_frame_time - elapsed);
         }
     }
 
+    for app in &mut apps {
+        app.shutdown();
+    }
+    if args.measure_latency.is_some() {
+        for app in &apps {
+            if let Some(report) = app.latency_report() {
+                println!("{}", report);
+            }
+        }
+    }
+    report_stats(&apps, args)
+}
+
+/// Apply a view or debug action chosen from the menu bar to `app`
+#[cfg(feature = "menu")]
+fn run_menu_action(app: &mut AppInstance, action: menu::MenuAction) {
+    match action {
+        menu::MenuAction::Scale(factor) => app.scale_window(factor),
+        menu::MenuAction::ZoomIn => app.zoom_view_centered(1),
+        menu::MenuAction::ZoomOut => app.zoom_view_centered(-1),
+        menu::MenuAction::CycleColorFilter => app.cycle_color_filter(),
+        menu::MenuAction::ResetDisplayAdjustment => app.reset_display_adjustment(),
+        menu::MenuAction::ToggleFrameDiff => app.toggle_frame_diff(),
+        menu::MenuAction::ToggleInspector => app.toggle_inspector(),
+        menu::MenuAction::ToggleConsole => app.toggle_console(),
+        menu::MenuAction::Describe => {
+            if let Err(e) = app.print_description() {
+                warn!("Failed to describe {:?}: {:#}", app.name(), e);
+            }
+        }
+        menu::MenuAction::Open(_) | menu::MenuAction::Quit | menu::MenuAction::SetPaused(_) => {}
+    }
+}
+
+/// Print and/or save the session statistics requested with `--stats` and `--stats-file`
+fn report_stats(apps: &[AppInstance], args: &Args) -> Result<()> {
+    if !args.stats && args.stats_file.is_none() {
+        return Ok(());
+    }
+    let summaries: Vec<_> = apps.iter().map(AppInstance::session_summary).collect();
+    if args.stats {
+        for summary in &summaries {
+            println!("{}", summary);
+        }
+    }
+    if let Some(path) = &args.stats_file {
+        stats::append(path, &summaries)?;
+        info!("Session statistics appended to {}", path.display());
+    }
     Ok(())
 }
 
-fn mouse_button_to_int(btn: sdl2::mouse::MouseButton) -> i32 {
-    use sdl2::mouse::MouseButton;
-    match btn {
-        MouseButton::Left => 1,
-        MouseButton::Middle => 2,
-        MouseButton::Right => 3,
-        _ => 0,
+/// How windows are turned and flipped, from `--rotate` and `--mirror`
+fn requested_orientation(args: &Args) -> Orientation {
+    Orientation {
+        rotation: args.rotate,
+        mirror: args.mirror,
     }
 }
+
+/// Frame limits of `--max-frame-size` and `--max-frame-calls`
+fn requested_frame_limits(args: &Args) -> FrameLimits {
+    let (max_width, max_height) = args.max_frame_size;
+    FrameLimits {
+        max_width,
+        max_height,
+        max_calls: args.max_frame_calls,
+    }
+}
+
+/// Log the running apps' statistics every `--stats-interval`, appending them
+/// to `--stats-file` if given
+fn log_stats(apps: &[AppInstance], args: &Args) -> Result<()> {
+    let summaries: Vec<_> = apps.iter().map(AppInstance::session_summary).collect();
+    if let Some(path) = &args.stats_file {
+        return stats::append(path, &summaries);
+    }
+    for summary in &summaries {
+        info!("{}", summary);
+    }
+    Ok(())
+}
+
+/// Record the frame's inputs, or substitute the recorded ones when replaying
+///
+/// Returns the `dt` to pass to the guest, or `None` once a replay has ended.
+fn step_session(
+    session: &Session,
+    app: &mut AppInstance,
+    dt: f64,
+    replay_until: Option<usize>,
+) -> Option<f64> {
+    if !session.is_replaying() {
+        session.record_frame(dt, app.pending_events());
+        return Some(dt);
+    }
+    if replay_until.is_some_and(|last| session.frame_index() >= last) {
+        return None;
+    }
+    let frame = session.next_frame()?;
+    app.set_pending_events(frame.events);
+    Some(frame.dt)
+}
+
+/// Enable multi-app behavior once more than one app is running: background
+/// throttling for unfocused windows and a worker pool for parallel updates
+fn configure_multi_app(
+    apps: &mut [AppInstance],
+    pool: &mut Option<WorkerPool>,
+    args: &Args,
+) -> Result<()> {
+    if apps.len() < 2 {
+        return Ok(());
+    }
+
+    // Throttle unfocused windows so background apps don't keep laptops busy
+    for app in apps.iter_mut() {
+        app.set_background_fps(Some(args.background_fps));
+    }
+
+    // Guest updates run in parallel on a worker pool when several apps are open
+    if pool.is_none() {
+        let size = args
+            .workers
+            .unwrap_or_else(WorkerPool::default_size)
+            .min(apps.len());
+        info!("Running {} apps on {} worker threads", apps.len(), size);
+        *pool = Some(WorkerPool::new(size)?);
+    }
+    Ok(())
+}
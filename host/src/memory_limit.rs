@@ -0,0 +1,186 @@
+//! Guest Memory Limit
+//!
+//! `--max-memory` caps how large each guest's linear memory may grow; growth
+//! past the cap fails like an out-of-memory `memory.grow`. Before that
+//! happens, every growth that leaves the guest close to the cap is reported
+//! through its `on_memory_pressure` export, so it can trim its caches while
+//! allocations still succeed. Modules whose declared minimum is already over
+//! the cap are rejected before they are instantiated, and a guest crashing
+//! after a denied growth is reported as running out of memory.
+
+use anyhow::{bail, Result};
+use wasmtime::{ExternType, Module, ResourceLimiter};
+
+/// Size of a WebAssembly page
+pub const PAGE_SIZE: usize = 64 * 1024;
+
+/// Cap of `--max-memory` when not given
+pub const DEFAULT_MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Share of the cap from which growth is reported as pressure
+const PRESSURE_THRESHOLD: f64 = 0.8;
+
+/// Enforces the memory cap and notes when the guest nears it
+#[derive(Debug, Default)]
+pub struct MemoryLimiter {
+    /// Most bytes of linear memory (`None` = unlimited)
+    limit: Option<usize>,
+    /// Pages in use and allowed after the latest growth past the threshold,
+    /// not yet reported to the guest
+    pressure: Option<(u32, u32)>,
+    /// Whether a growth was denied since the last `take_denied`
+    denied: bool,
+}
+
+impl MemoryLimiter {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            pressure: None,
+            denied: false,
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Whether a growth was denied since the last call
+    pub fn take_denied(&mut self) -> bool {
+        std::mem::take(&mut self.denied)
+    }
+
+    /// Take the memory pressure to report to the guest, as (current, limit) pages
+    pub fn take_pressure(&mut self) -> Option<(u32, u32)> {
+        self.pressure.take()
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        let Some(limit) = self.limit else {
+            return Ok(true);
+        };
+        if desired > limit {
+            self.denied = true;
+            return Ok(false);
+        }
+        if desired as f64 >= limit as f64 * PRESSURE_THRESHOLD {
+            self.pressure = Some(((desired / PAGE_SIZE) as u32, (limit / PAGE_SIZE) as u32));
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+/// Fail if a memory of `module` starts out larger than `limit` bytes
+pub fn check_minimum(module: &Module, limit: usize) -> Result<()> {
+    let imports = module.imports().map(|import| import.ty());
+    let exports = module.exports().map(|export| export.ty());
+    for ty in imports.chain(exports) {
+        let ExternType::Memory(memory) = ty else {
+            continue;
+        };
+        let minimum = memory.minimum().saturating_mul(PAGE_SIZE as u64);
+        if minimum > limit as u64 {
+            bail!(
+                "The app needs at least {} of memory, over the --max-memory cap of {}",
+                format_size(minimum),
+                format_size(limit as u64)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Parse a `--max-memory` size: kibibytes, mebibytes or gibibytes with a K,
+/// M or G suffix, mebibytes without one
+pub fn parse_size(value: &str) -> Result<usize, String> {
+    const UNITS: [(&str, usize); 9] = [
+        ("kib", 1 << 10),
+        ("kb", 1 << 10),
+        ("k", 1 << 10),
+        ("mib", 1 << 20),
+        ("mb", 1 << 20),
+        ("m", 1 << 20),
+        ("gib", 1 << 30),
+        ("gb", 1 << 30),
+        ("g", 1 << 30),
+    ];
+    let lower = value.trim().to_ascii_lowercase();
+    let (number, unit) = UNITS
+        .iter()
+        .find_map(|&(suffix, unit)| Some((lower.strip_suffix(suffix)?, unit)))
+        .unwrap_or((&lower, 1 << 20));
+    number
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| format!("invalid size {:?}, expected e.g. 256M or 2G", value))
+}
+
+/// Human-readable size, in the largest whole binary unit
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0 => "0 bytes".to_string(),
+        _ if bytes.is_multiple_of(1 << 30) => format!("{} GiB", bytes >> 30),
+        _ if bytes.is_multiple_of(1 << 20) => format!("{} MiB", bytes >> 20),
+        _ if bytes.is_multiple_of(1 << 10) => format!("{} KiB", bytes >> 10),
+        _ => format!("{} bytes", bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_growth_near_the_cap_is_reported() {
+        let mut limiter = MemoryLimiter::new(Some(10 * PAGE_SIZE));
+        assert!(limiter.memory_growing(0, 2 * PAGE_SIZE, None).unwrap());
+        assert_eq!(limiter.take_pressure(), None);
+
+        assert!(limiter.memory_growing(0, 8 * PAGE_SIZE, None).unwrap());
+        assert_eq!(limiter.take_pressure(), Some((8, 10)));
+        assert_eq!(limiter.take_pressure(), None);
+
+        assert!(!limiter.take_denied());
+        assert!(!limiter.memory_growing(0, 11 * PAGE_SIZE, None).unwrap());
+        assert!(limiter.take_denied());
+        assert!(MemoryLimiter::new(None)
+            .memory_growing(0, usize::MAX, None)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_sizes_and_declared_minimums() {
+        assert_eq!(parse_size("256M"), Ok(256 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("512k"), Ok(512 << 10));
+        assert_eq!(parse_size("64"), Ok(64 << 20));
+        assert_eq!(parse_size("0"), Ok(0));
+        assert!(parse_size("lots").is_err());
+        assert_eq!(format_size(DEFAULT_MAX_MEMORY as u64), "256 MiB");
+        assert_eq!(format_size(3 << 30), "3 GiB");
+
+        let engine = wasmtime::Engine::default();
+        let module = Module::new(&engine, r#"(module (memory (export "memory") 4096))"#).unwrap();
+        assert!(check_minimum(&module, DEFAULT_MAX_MEMORY).is_ok());
+        let error = check_minimum(&module, 128 << 20).unwrap_err();
+        assert!(error.to_string().contains("256 MiB"));
+    }
+}
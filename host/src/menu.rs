@@ -0,0 +1,295 @@
+//! Native Menu Bar
+//!
+//! Exposes common host actions (opening packages, window scaling, color
+//! filters, debug views and pausing) in the platform menu bar, where they are
+//! discoverable and reachable by screen readers instead of hidden behind
+//! hotkeys. Only compiled with the `menu` cargo feature, on Windows and macOS;
+//! SDL windows elsewhere have no native menu bar to attach to.
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+compile_error!("the `menu` feature is only supported on Windows and macOS");
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use muda::{CheckMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu};
+use sdl2::video::Window;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+
+/// Most packages listed under File > Open Recent
+const MAX_RECENT: usize = 10;
+
+/// Window sizes offered under View, as multiples of the frame size
+const SCALES: [u32; 4] = [1, 2, 3, 4];
+
+/// A host action chosen from the menu bar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuAction {
+    /// Run a package in a new window
+    Open(PathBuf),
+    Quit,
+    /// Resize the focused window to a multiple of its frame size
+    Scale(u32),
+    ZoomIn,
+    ZoomOut,
+    CycleColorFilter,
+    /// Undo brightness, contrast and gamma changes made with the hotkeys
+    ResetDisplayAdjustment,
+    ToggleFrameDiff,
+    ToggleInspector,
+    ToggleConsole,
+    Describe,
+    /// Stop or resume updating every app
+    SetPaused(bool),
+}
+
+/// The host's menu bar and the actions behind its items
+pub struct HostMenu {
+    menu: Menu,
+    open: MenuItem,
+    pause: CheckMenuItem,
+    recent_menu: Submenu,
+    /// Items of the Open Recent submenu and the packages they open
+    recent_items: Vec<(MenuItem, PathBuf)>,
+    /// Recently opened packages, most recent first
+    recent: Vec<PathBuf>,
+    /// Actions of the remaining items
+    actions: Vec<(MenuId, MenuAction)>,
+}
+
+impl HostMenu {
+    /// Build the menu bar; SDL video must be initialized first
+    ///
+    /// On macOS the menu bar is installed for the whole application. On
+    /// Windows it must be attached to each window with [`HostMenu::attach`].
+    pub fn new() -> Result<Self> {
+        let mut actions = Vec::new();
+        let mut item = |text: &str, action: MenuAction| {
+            let item = MenuItem::new(text, true, None);
+            actions.push((item.id().clone(), action));
+            item
+        };
+
+        let open = MenuItem::new("&Open…", true, None);
+        let recent_menu = Submenu::new("Open &Recent", true);
+        let file_menu = Submenu::with_items(
+            "&File",
+            true,
+            &[
+                &open,
+                &recent_menu,
+                &PredefinedMenuItem::separator(),
+                &item("&Quit", MenuAction::Quit),
+            ],
+        )?;
+
+        let view_menu = Submenu::new("&View", true);
+        for scale in SCALES {
+            view_menu.append(&item(
+                &format!("Window Size {}x", scale),
+                MenuAction::Scale(scale),
+            ))?;
+        }
+        view_menu.append_items(&[
+            &PredefinedMenuItem::separator(),
+            &item("Zoom &In", MenuAction::ZoomIn),
+            &item("Zoom &Out", MenuAction::ZoomOut),
+            &PredefinedMenuItem::separator(),
+            &item("Cycle &Color Filter", MenuAction::CycleColorFilter),
+            &item(
+                "&Reset Brightness and Contrast",
+                MenuAction::ResetDisplayAdjustment,
+            ),
+        ])?;
+
+        let pause = CheckMenuItem::new("&Pause", true, false, None);
+        let debug_menu = Submenu::with_items(
+            "&Debug",
+            true,
+            &[
+                &item("Frame &Diff Overlay", MenuAction::ToggleFrameDiff),
+                &item("Pixel &Inspector Overlay", MenuAction::ToggleInspector),
+                &item("Guest &Console Overlay", MenuAction::ToggleConsole),
+                &item("&Describe Screen", MenuAction::Describe),
+                &PredefinedMenuItem::separator(),
+                &pause,
+            ],
+        )?;
+
+        let menu = Menu::new();
+        // macOS uses the first submenu as the application menu
+        #[cfg(target_os = "macos")]
+        menu.append(&Submenu::with_items(
+            "wapps",
+            true,
+            &[
+                &PredefinedMenuItem::about(None, None),
+                &PredefinedMenuItem::separator(),
+                &PredefinedMenuItem::hide(None),
+                &PredefinedMenuItem::quit(None),
+            ],
+        )?)?;
+        menu.append_items(&[&file_menu, &view_menu, &debug_menu])?;
+        #[cfg(target_os = "macos")]
+        menu.init_for_nsapp();
+
+        let mut host_menu = Self {
+            menu,
+            open,
+            pause,
+            recent_menu,
+            recent_items: Vec::new(),
+            recent: load_recent(),
+            actions,
+        };
+        host_menu.rebuild_recent()?;
+        Ok(host_menu)
+    }
+
+    /// Show the menu bar in `window`
+    ///
+    /// Windows menu bars belong to a single window; on macOS this does nothing.
+    pub fn attach(&self, window: &Window) {
+        #[cfg(target_os = "windows")]
+        {
+            use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+            let hwnd = match window.window_handle().map(|handle| handle.as_raw()) {
+                Ok(RawWindowHandle::Win32(handle)) => handle.hwnd.get(),
+                _ => {
+                    warn!("Window has no Win32 handle; not showing the menu bar");
+                    return;
+                }
+            };
+            // SAFETY: the handle belongs to a live window created by SDL
+            if let Err(e) = unsafe { self.menu.init_for_hwnd(hwnd) } {
+                warn!("Failed to show the menu bar: {}", e);
+            }
+        }
+        #[cfg(target_os = "macos")]
+        let _ = window;
+    }
+
+    /// Take the actions chosen since the last call
+    ///
+    /// Choosing File > Open shows a file dialog, blocking until it is closed.
+    pub fn poll(&self) -> Vec<MenuAction> {
+        let mut actions = Vec::new();
+        while let Ok(event) = MenuEvent::receiver().try_recv() {
+            let id = event.id();
+            if id == self.open.id() {
+                let path = rfd::FileDialog::new()
+                    .add_filter("WAPP package", &["wapp"])
+                    .pick_file();
+                actions.extend(path.map(MenuAction::Open));
+            } else if id == self.pause.id() {
+                // Check items toggle themselves when chosen
+                actions.push(MenuAction::SetPaused(self.pause.is_checked()));
+            } else if let Some((_, path)) =
+                self.recent_items.iter().find(|(item, _)| item.id() == id)
+            {
+                actions.push(MenuAction::Open(path.clone()));
+            } else if let Some((_, action)) = self.actions.iter().find(|(item_id, _)| item_id == id)
+            {
+                actions.push(action.clone());
+            }
+        }
+        actions
+    }
+
+    /// Sync the Pause item with a pause toggled by hotkey
+    pub fn set_paused(&self, paused: bool) {
+        self.pause.set_checked(paused);
+    }
+
+    /// Move `path` to the top of File > Open Recent
+    pub fn add_recent(&mut self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        push_recent(&mut self.recent, path);
+        if let Err(e) = save_recent(&self.recent) {
+            warn!("Failed to save recent packages: {:#}", e);
+        }
+        if let Err(e) = self.rebuild_recent() {
+            warn!("Failed to update the recent packages menu: {:#}", e);
+        }
+    }
+
+    /// Replace the items of the Open Recent submenu with the recent list
+    fn rebuild_recent(&mut self) -> Result<()> {
+        for (item, _) in self.recent_items.drain(..) {
+            self.recent_menu.remove(&item)?;
+        }
+        for path in &self.recent {
+            let item = MenuItem::new(path.display().to_string(), true, None);
+            self.recent_menu.append(&item)?;
+            self.recent_items.push((item, path.clone()));
+        }
+        self.recent_menu.set_enabled(!self.recent.is_empty());
+        Ok(())
+    }
+}
+
+/// Add `path` to the front of a recent list, removing duplicates and the oldest entries
+fn push_recent(recent: &mut Vec<PathBuf>, path: PathBuf) {
+    recent.retain(|existing| *existing != path);
+    recent.insert(0, path);
+    recent.truncate(MAX_RECENT);
+}
+
+/// File listing recently opened packages
+fn recent_file() -> Option<PathBuf> {
+    Some(storage::data_dir()?.join("recent.json"))
+}
+
+fn load_recent() -> Vec<PathBuf> {
+    let Some(path) = recent_file() else {
+        return Vec::new();
+    };
+    match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+            warn!(
+                "Ignoring corrupt recent packages file {}: {}",
+                path.display(),
+                e
+            );
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn save_recent(recent: &[PathBuf]) -> Result<()> {
+    let Some(path) = recent_file() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+    }
+    let json = serde_json::to_vec_pretty(recent).context("Failed to serialize recent packages")?;
+    fs::write(&path, json)
+        .with_context(|| format!("Could not write recent packages: {}", path.display()))?;
+    debug!("Saved recent packages to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_list_is_deduplicated_and_bounded() {
+        let mut recent = Vec::new();
+        for i in 0..MAX_RECENT + 2 {
+            push_recent(&mut recent, PathBuf::from(format!("{}.wapp", i)));
+        }
+        push_recent(&mut recent, PathBuf::from("5.wapp"));
+
+        assert_eq!(recent.len(), MAX_RECENT);
+        assert_eq!(recent[0], PathBuf::from("5.wapp"));
+        assert_eq!(recent[1], PathBuf::from("11.wapp"));
+        assert_eq!(recent.iter().filter(|p| **p == recent[0]).count(), 1);
+    }
+}
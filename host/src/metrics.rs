@@ -0,0 +1,160 @@
+//! Metrics Endpoint
+//!
+//! Serves runtime metrics (frame rate, uptime, crash count, guest memory) as
+//! JSON over a minimal local HTTP endpoint, so operators running WAPP signage
+//! can monitor fleets. Each app also carries its session statistics, import
+//! calls included when `--stats` counts them. Only compiled with the
+//! `metrics` cargo feature.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use crate::stats::SessionSummary;
+use crate::usage::UsageSnapshot;
+
+/// Metrics reported for a single app
+#[derive(Debug, Clone, Serialize)]
+pub struct AppMetrics {
+    pub name: String,
+    pub fps: f64,
+    pub cpu_percent: f64,
+    pub memory_bytes: usize,
+    /// Bytes in use by the guest's allocator, for guests reporting it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heap_bytes: Option<u64>,
+    /// Totals since the app was loaded
+    pub session: SessionSummary,
+}
+
+impl AppMetrics {
+    pub fn new(name: &str, usage: Option<UsageSnapshot>, session: SessionSummary) -> Self {
+        let usage = usage.unwrap_or(UsageSnapshot {
+            fps: 0.0,
+            cpu_percent: 0.0,
+            memory_bytes: 0,
+            heap_bytes: None,
+        });
+        Self {
+            name: name.to_string(),
+            fps: usage.fps,
+            cpu_percent: usage.cpu_percent,
+            memory_bytes: usage.memory_bytes,
+            heap_bytes: usage.heap_bytes,
+            session,
+        }
+    }
+}
+
+/// JSON document served by the endpoint
+#[derive(Debug, Serialize)]
+struct MetricsReport<'a> {
+    uptime_secs: f64,
+    crashes: u64,
+    apps: &'a [AppMetrics],
+}
+
+struct MetricsState {
+    apps: Vec<AppMetrics>,
+    crashes: u64,
+}
+
+/// Handle shared between the main loop (which publishes) and the server thread
+#[derive(Clone)]
+pub struct Metrics {
+    started: Instant,
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl Metrics {
+    /// Start serving metrics on `addr` from a background thread
+    pub fn serve(addr: SocketAddr) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .with_context(|| format!("Failed to bind metrics endpoint to {}", addr))?;
+
+        let metrics = Self {
+            started: Instant::now(),
+            state: Arc::new(Mutex::new(MetricsState {
+                apps: Vec::new(),
+                crashes: 0,
+            })),
+        };
+
+        let server = metrics.clone();
+        thread::Builder::new()
+            .name("wapps-metrics".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            if let Err(e) = server.handle(stream) {
+                                debug!("Metrics request failed: {}", e);
+                            }
+                        }
+                        Err(e) => warn!("Metrics connection failed: {}", e),
+                    }
+                }
+            })
+            .context("Failed to spawn metrics thread")?;
+
+        info!("Serving metrics on http://{}/metrics", addr);
+
+        Ok(metrics)
+    }
+
+    /// Replace the per-app figures with the latest values
+    pub fn publish(&self, apps: Vec<AppMetrics>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.apps = apps;
+        }
+    }
+
+    /// Count a guest crash
+    pub fn record_crash(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.crashes += 1;
+        }
+    }
+
+    fn report_json(&self) -> Result<String> {
+        let state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Metrics state poisoned"))?;
+        let report = MetricsReport {
+            uptime_secs: self.started.elapsed().as_secs_f64(),
+            crashes: state.crashes,
+            apps: &state.apps,
+        };
+        Ok(serde_json::to_string(&report)?)
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        // Only the request line matters: "GET /metrics HTTP/1.1"
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+        let path = request_line.split_whitespace().nth(1).unwrap_or("");
+
+        let (status, body) = if path == "/metrics" {
+            ("200 OK", self.report_json()?)
+        } else {
+            ("404 Not Found", String::from("{\"error\":\"not found\"}"))
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        stream.flush()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Compiled Module Cache
+//!
+//! Compiling a large guest takes seconds, so compiled modules are kept in
+//! `modules/` under the user cache directory and loaded back on the next
+//! launch. Entries are keyed by a hash of the WASM bytes and by the engine's
+//! compatibility hash, which covers the wasmtime version and the settings
+//! that affect compiled code, so a host upgrade simply misses the cache.
+//! `--no-cache` compiles every time and leaves the cache untouched.
+//! Packages may also carry the module precompiled for a given host, see
+//! `wapps pack --precompile`, which is used as is when it matches this one.
+//!
+//! Loading compiled code trusts the files in the cache directory, which only
+//! the user can write to; entries are written to a temporary file first so a
+//! crash never leaves a truncated one behind.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use wasmtime::{Engine, Module};
+
+use crate::storage;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Turn the cache off for the rest of the process, with `--no-cache`
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// Compile `wasm_bytes` for `engine`, or load them compiled by an earlier run
+///
+/// A `precompiled` copy of the module is used first if `engine` can run it;
+/// the caller must have checked that it comes from a trusted package, since
+/// it is native code.
+pub fn compile(engine: &Engine, wasm_bytes: &[u8], precompiled: Option<&[u8]>) -> Result<Module> {
+    if let Some(precompiled) = precompiled {
+        // SAFETY: the package's signer is trusted to have built this with
+        // `wapps pack --precompile`; wasmtime rejects other engines' output
+        match unsafe { Module::deserialize(engine, precompiled) } {
+            Ok(module) => {
+                debug!("Using the precompiled module");
+                return Ok(module);
+            }
+            Err(e) => info!(
+                "Compiling the portable module instead of the precompiled one: {:#}",
+                e
+            ),
+        }
+    }
+
+    let path = if ENABLED.load(Ordering::Relaxed) {
+        entry_path(engine, wasm_bytes)
+    } else {
+        None
+    };
+    let Some(path) = path else {
+        return Module::new(engine, wasm_bytes).context("Failed to compile WASM module");
+    };
+
+    if path.exists() {
+        // SAFETY: entries are only written by `store`, from modules this host
+        // compiled with an engine of the same compatibility hash
+        match unsafe { Module::deserialize_file(engine, &path) } {
+            Ok(module) => {
+                debug!("Loaded compiled module from {}", path.display());
+                return Ok(module);
+            }
+            Err(e) => warn!("Ignoring cached module {}: {:#}", path.display(), e),
+        }
+    }
+
+    let module = Module::new(engine, wasm_bytes).context("Failed to compile WASM module")?;
+    if let Err(e) = store(&module, &path) {
+        warn!("Failed to cache the compiled module: {:#}", e);
+    }
+    Ok(module)
+}
+
+/// Cache file for `wasm_bytes` compiled by `engine`, if there is a user cache
+/// directory
+fn entry_path(engine: &Engine, wasm_bytes: &[u8]) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    engine.precompile_compatibility_hash().hash(&mut hasher);
+    Some(
+        storage::cache_dir()?
+            .join("modules")
+            .join(entry_name(wasm_bytes, hasher.finish())),
+    )
+}
+
+/// File name of a cache entry, from the module's bytes and the engine's
+/// compatibility hash
+fn entry_name(wasm_bytes: &[u8], compatibility: u64) -> String {
+    let hash = Sha256::digest(wasm_bytes);
+    let hex: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{:016x}.cwasm", hex, compatibility)
+}
+
+fn store(module: &Module, path: &Path) -> Result<()> {
+    let bytes = module.serialize().context("Failed to serialize module")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+    }
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp, bytes)
+        .with_context(|| format!("Could not write cache entry: {}", temp.display()))?;
+    fs::rename(&temp, path)
+        .with_context(|| format!("Could not replace cache entry: {}", path.display()))?;
+    debug!("Cached compiled module at {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_are_keyed_by_module_and_engine() {
+        // An empty module
+        let module = b"\0asm\x01\0\0\0";
+        let name = entry_name(module, 1);
+        assert!(name.ends_with("-0000000000000001.cwasm"));
+        assert_ne!(name, entry_name(module, 2));
+        assert_ne!(name, entry_name(b"\0asm\x01\0\0\0\0", 1));
+
+        let engine = Engine::default();
+        let path = std::env::temp_dir().join(format!("wapps-module-{}.cwasm", std::process::id()));
+        let compiled = Module::new(&engine, module).unwrap();
+        store(&compiled, &path).unwrap();
+        let loaded = unsafe { Module::deserialize_file(&engine, &path) };
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.is_ok());
+    }
+}
@@ -0,0 +1,228 @@
+//! Lockstep Netplay (experimental)
+//!
+//! `--netplay host` waits for a second host to connect and `--netplay join`
+//! connects to it; both run the same package. Every frame, each host sends
+//! the input events of its window to the other and waits for the peer's
+//! before updating, so both guests receive the same events, in the same
+//! order, with the same fixed `dt`. A deterministic guest therefore runs the
+//! same simulation on both machines without any networking code of its own.
+//!
+//! Each player's events are preceded by a `Player` event (`on_player`),
+//! player 0 being the host and player 1 the joiner. To keep guests in sync,
+//! netplay disables the WASI clocks and seeds the random source with a value
+//! chosen by the host; present times and audio queue lengths stay local, so
+//! guests must not let them affect their simulation. Frames advance at the
+//! pace of the slower host plus one network round trip, which suits a LAN.
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+use wasmtime_wasi::RngCore;
+
+use crate::events::{GuestEvent, TimedEvent};
+
+/// Version of the netplay protocol
+const NETPLAY_VERSION: u32 = 1;
+
+/// Delta time passed to `update` on both hosts
+pub const NETPLAY_DT: f64 = 1.0 / 60.0;
+
+/// How long to wait for the peer's inputs before giving up
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Which side of the session this host is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NetplayRole {
+    /// Listen for a peer; player 0
+    Host,
+    /// Connect to a listening host; player 1
+    Join,
+}
+
+/// First message of each side
+#[derive(Debug, Serialize, Deserialize)]
+struct Hello {
+    version: u32,
+    /// SHA-256 of the package file, in hex
+    package: String,
+    /// Random seed for both guests, chosen by the host
+    seed: u64,
+}
+
+/// Input events of one side for one frame
+#[derive(Debug, Serialize, Deserialize)]
+struct Frame {
+    index: u64,
+    events: Vec<TimedEvent>,
+}
+
+/// A connection to the other host of a lockstep session
+pub struct Netplay {
+    role: NetplayRole,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Random seed agreed on with the peer
+    seed: u64,
+    frame: u64,
+}
+
+impl Netplay {
+    /// Wait for a peer (`Host`) or connect to one (`Join`) at `address`, and
+    /// check that both sides run the package at `package`
+    pub fn connect(role: NetplayRole, address: &str, package: &Path) -> Result<Self> {
+        let data = std::fs::read(package)
+            .with_context(|| format!("Could not read package: {}", package.display()))?;
+        let package_hash: String = Sha256::digest(&data)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let stream = match role {
+            NetplayRole::Host => {
+                let listener = TcpListener::bind(address)
+                    .with_context(|| format!("Could not listen on {}", address))?;
+                info!("Waiting for a netplay peer on {}", address);
+                let (stream, peer) = listener.accept().context("Failed to accept a peer")?;
+                info!("Netplay peer connected from {}", peer);
+                stream
+            }
+            NetplayRole::Join => {
+                let stream = TcpStream::connect(address)
+                    .with_context(|| format!("Could not connect to {}", address))?;
+                info!("Connected to netplay host {}", address);
+                stream
+            }
+        };
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(PEER_TIMEOUT))?;
+
+        let mut netplay = Self {
+            role,
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            seed: 0,
+            frame: 0,
+        };
+        let mut hello = Hello {
+            version: NETPLAY_VERSION,
+            package: package_hash,
+            seed: 0,
+        };
+        let peer: Hello = match role {
+            NetplayRole::Host => {
+                hello.seed = wasmtime_wasi::thread_rng().next_u64();
+                netplay.send(&hello)?;
+                netplay.receive()?
+            }
+            NetplayRole::Join => {
+                let peer: Hello = netplay.receive()?;
+                hello.seed = peer.seed;
+                netplay.send(&hello)?;
+                peer
+            }
+        };
+        if peer.version != NETPLAY_VERSION {
+            bail!(
+                "Netplay peer speaks protocol version {}, expected {}",
+                peer.version,
+                NETPLAY_VERSION
+            );
+        }
+        if peer.package != hello.package {
+            bail!("Netplay peer runs a different package");
+        }
+        netplay.seed = hello.seed;
+        Ok(netplay)
+    }
+
+    /// Random seed both guests use
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Send this frame's `local` events and return the events of both players
+    /// for the frame, blocking until the peer's arrive
+    pub fn exchange(&mut self, local: &[TimedEvent]) -> Result<Vec<TimedEvent>> {
+        self.send(&Frame {
+            index: self.frame,
+            events: local.to_vec(),
+        })?;
+        let remote: Frame = self.receive()?;
+        if remote.index != self.frame {
+            bail!(
+                "Netplay peer sent frame {}, expected {}",
+                remote.index,
+                self.frame
+            );
+        }
+        self.frame += 1;
+        Ok(merge(self.role, local, &remote.events))
+    }
+
+    fn send<T: Serialize>(&mut self, message: &T) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer
+            .write_all(&line)
+            .context("Lost connection to the netplay peer")
+    }
+
+    fn receive<T: for<'de> Deserialize<'de>>(&mut self) -> Result<T> {
+        let mut line = String::new();
+        let read = self
+            .reader
+            .read_line(&mut line)
+            .context("Netplay peer stopped responding")?;
+        if read == 0 {
+            bail!("Netplay peer disconnected");
+        }
+        serde_json::from_str(&line).context("Invalid message from the netplay peer")
+    }
+}
+
+/// Events of both players in player order, each run preceded by a `Player` event
+fn merge(role: NetplayRole, local: &[TimedEvent], remote: &[TimedEvent]) -> Vec<TimedEvent> {
+    let (first, second) = match role {
+        NetplayRole::Host => (local, remote),
+        NetplayRole::Join => (remote, local),
+    };
+    let mut events = Vec::with_capacity(first.len() + second.len() + 2);
+    for (index, player_events) in [first, second].into_iter().enumerate() {
+        events.push(TimedEvent {
+            event: GuestEvent::Player {
+                index: index as i32,
+            },
+            time: player_events.first().map_or(0.0, |event| event.time),
+        });
+        events.extend_from_slice(player_events);
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_both_sides_see_the_same_events() {
+        let key = |scancode| TimedEvent {
+            event: GuestEvent::KeyUp { scancode },
+            time: 1.5,
+        };
+        let host = [key(4)];
+        let join = [key(7), key(8)];
+
+        let merged = merge(NetplayRole::Host, &host, &join);
+        assert_eq!(merged, merge(NetplayRole::Join, &join, &host));
+        assert_eq!(merged.len(), 5);
+        assert_eq!(merged[0].event, GuestEvent::Player { index: 0 });
+        assert_eq!(merged[2].event, GuestEvent::Player { index: 1 });
+        assert_eq!(merged[4], key(8));
+    }
+}
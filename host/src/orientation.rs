@@ -0,0 +1,167 @@
+//! Display Orientation
+//!
+//! Kiosks often mount their displays in portrait, upside down or behind a
+//! mirror. `--rotate` and `--mirror` turn and flip what each window shows
+//! when it is presented, so apps need no changes: the frame, the HUD and the
+//! overlays are laid out in a window turned the way the display is mounted,
+//! and pointer and touch positions are turned back into that layout before
+//! they reach the app. The window's contents are mirrored left to right
+//! first, then turned clockwise.
+
+use sdl2::rect::Rect;
+
+/// How far the window's contents are turned clockwise
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    /// The rotation in degrees
+    pub fn degrees(self) -> f64 {
+        match self {
+            Rotation::None => 0.0,
+            Rotation::Quarter => 90.0,
+            Rotation::Half => 180.0,
+            Rotation::ThreeQuarters => 270.0,
+        }
+    }
+}
+
+/// Parse a `--rotate` value in degrees
+pub fn parse_rotation(value: &str) -> Result<Rotation, String> {
+    match value.trim() {
+        "0" => Ok(Rotation::None),
+        "90" => Ok(Rotation::Quarter),
+        "180" => Ok(Rotation::Half),
+        "270" => Ok(Rotation::ThreeQuarters),
+        _ => Err(format!(
+            "invalid rotation {:?}, expected 90, 180 or 270",
+            value
+        )),
+    }
+}
+
+/// How windows show their contents, with `--rotate` and `--mirror`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Orientation {
+    pub rotation: Rotation,
+    pub mirror: bool,
+}
+
+impl Orientation {
+    /// Whether contents are shown as laid out
+    pub fn is_identity(self) -> bool {
+        self == Orientation::default()
+    }
+
+    /// Whether widths are shown as heights and heights as widths
+    pub fn swaps_axes(self) -> bool {
+        matches!(self.rotation, Rotation::Quarter | Rotation::ThreeQuarters)
+    }
+
+    /// Size of the layout shown in a window of `size`, or of the window
+    /// showing a layout of `size`
+    pub fn layout_size(self, size: (u32, u32)) -> (u32, u32) {
+        if self.swaps_axes() {
+            (size.1, size.0)
+        } else {
+            size
+        }
+    }
+
+    /// Where a layout position is shown in a window of `window` size
+    pub fn to_window(self, x: f64, y: f64, window: (u32, u32)) -> (f64, f64) {
+        let (width, height) = self.layout_size(window);
+        let (width, height) = (width as f64, height as f64);
+        let x = if self.mirror { width - x } else { x };
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (height - y, x),
+            Rotation::Half => (width - x, height - y),
+            Rotation::ThreeQuarters => (y, width - x),
+        }
+    }
+
+    /// The layout position shown at a position of a window of `window` size
+    pub fn to_layout(self, x: f64, y: f64, window: (u32, u32)) -> (f64, f64) {
+        let (width, height) = self.layout_size(window);
+        let (width, height) = (width as f64, height as f64);
+        let (x, y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (y, height - x),
+            Rotation::Half => (width - x, height - y),
+            Rotation::ThreeQuarters => (width - y, x),
+        };
+        (if self.mirror { width - x } else { x }, y)
+    }
+
+    /// A movement by (`dx`, `dy`) in the window, as a movement in the layout
+    pub fn delta_to_layout(self, dx: i32, dy: i32) -> (i32, i32) {
+        let (x, y) = self.to_layout(dx as f64, dy as f64, (0, 0));
+        (x as i32, y as i32)
+    }
+
+    /// Where a layout `rect` is shown in a window of `window` size
+    pub fn rect_to_window(self, rect: Rect, window: (u32, u32)) -> Rect {
+        let (x0, y0) = self.to_window(rect.left() as f64, rect.top() as f64, window);
+        let (x1, y1) = self.to_window(rect.right() as f64, rect.bottom() as f64, window);
+        Rect::new(
+            x0.min(x1) as i32,
+            y0.min(y1) as i32,
+            (x1 - x0).abs() as u32,
+            (y1 - y0).abs() as u32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_positions_turn_and_flip_into_the_window() {
+        // A 300x200 layout turned a quarter fills a 200x300 window
+        let window = (200, 300);
+        let quarter = Orientation {
+            rotation: Rotation::Quarter,
+            mirror: false,
+        };
+        assert_eq!(quarter.layout_size(window), (300, 200));
+        // Its top-left corner is shown top-right
+        assert_eq!(quarter.to_window(0.0, 0.0, window), (200.0, 0.0));
+        assert_eq!(quarter.to_window(300.0, 200.0, window), (0.0, 300.0));
+        assert_eq!(
+            quarter.rect_to_window(Rect::new(10, 20, 30, 40), window),
+            Rect::new(140, 10, 40, 30)
+        );
+
+        for rotation in [
+            Rotation::None,
+            Rotation::Quarter,
+            Rotation::Half,
+            Rotation::ThreeQuarters,
+        ] {
+            for mirror in [false, true] {
+                let orientation = Orientation { rotation, mirror };
+                let (x, y) = orientation.to_window(12.5, 34.0, window);
+                assert_eq!(orientation.to_layout(x, y, window), (12.5, 34.0));
+            }
+        }
+
+        let mirrored = Orientation {
+            rotation: Rotation::None,
+            mirror: true,
+        };
+        assert_eq!(mirrored.to_window(10.0, 20.0, window), (190.0, 20.0));
+        assert_eq!(mirrored.delta_to_layout(5, -3), (-5, -3));
+        assert_eq!(quarter.delta_to_layout(5, -3), (-3, -5));
+
+        assert_eq!(parse_rotation("270"), Ok(Rotation::ThreeQuarters));
+        assert!(parse_rotation("45").is_err());
+    }
+}
@@ -0,0 +1,468 @@
+//! `wapps pack` Command
+//!
+//! Builds a .wapp package from a WebAssembly module and a JSON manifest.
+//! Output is reproducible: the manifest is re-serialized canonically (sorted
+//! keys, no insignificant whitespace), the module is embedded verbatim and
+//! nothing time- or machine-dependent is written, so packing the same inputs
+//! twice yields byte-identical files that can be verified against signatures.
+//!
+//! With `--codec`, the module is written as a compressed section of a version 2
+//! package, followed by the optional icon and asset sections; without it,
+//! packages keep the version 1 layout older hosts read. Assets are packed in
+//! sorted path order so directory listing order never changes the output;
+//! guests read them by relative path with `wapps::asset_read` instead of
+//! embedding large data in their module.
+//! With `--sign-key`, the package ends with an ed25519 signature section;
+//! ed25519 signatures are deterministic, so signed output stays reproducible.
+//! With `--precompile`, the module is also stored compiled by wasmtime for
+//! this host or `--precompile-target`, for kiosks and embedded devices to
+//! skip compilation; hosts whose wasmtime or target differ, and any host for
+//! a package not signed by a key it trusts, compile the portable module.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use ed25519_dalek::SigningKey;
+use log::{info, warn};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmtime::Engine;
+
+use crate::capabilities;
+use crate::codec::{CodecKind, CodecRegistry};
+use crate::loader::{
+    self, SectionKind, WappMetadata, SECTION_HEADER_SIZE, WAPP_MAGIC, WAPP_SECTIONED_VERSION,
+    WAPP_VERSION,
+};
+use crate::png;
+use crate::runtime;
+use crate::signing;
+use crate::version::Version;
+
+/// Arguments of `wapps pack`
+#[derive(Args, Debug)]
+pub struct PackArgs {
+    /// WebAssembly module to package
+    #[arg(value_name = "MODULE")]
+    module: PathBuf,
+
+    /// JSON manifest with the package metadata (name, description, ...)
+    #[arg(long, value_name = "FILE")]
+    manifest: PathBuf,
+
+    /// Output package path
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+
+    /// Compress the module with CODEC, producing a version 2 package
+    #[arg(long, value_name = "CODEC")]
+    codec: Option<CodecKind>,
+
+    /// PNG icon to bundle, shown as the window icon
+    #[arg(long, value_name = "FILE", requires = "codec")]
+    icon: Option<PathBuf>,
+
+    /// Directory whose files are bundled as assets, named by their relative path
+    #[arg(long, value_name = "DIR", requires = "codec")]
+    assets: Option<PathBuf>,
+
+    /// Sign the package with the secret key in FILE, created by `wapps keygen`
+    #[arg(long, value_name = "FILE", requires = "codec")]
+    sign_key: Option<PathBuf>,
+
+    /// Also store the module compiled ahead of time, which hosts built with
+    /// the same wasmtime for the same target load instead of compiling it
+    #[arg(long, requires = "codec")]
+    precompile: bool,
+
+    /// Target triple to precompile for (defaults to this host's)
+    #[arg(long, value_name = "TRIPLE", requires = "precompile")]
+    precompile_target: Option<String>,
+}
+
+/// A section to bundle besides the module
+#[derive(Debug, Clone)]
+pub struct Resource {
+    pub kind: SectionKind,
+    /// Asset path; empty for other sections
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Run `wapps pack`
+pub fn run(args: &PackArgs) -> Result<()> {
+    let wasm_bytes = fs::read(&args.module)
+        .with_context(|| format!("Could not read module: {}", args.module.display()))?;
+    let manifest = fs::read(&args.manifest)
+        .with_context(|| format!("Could not read manifest: {}", args.manifest.display()))?;
+
+    let mut resources = Vec::new();
+    if let Some(icon) = &args.icon {
+        let data =
+            fs::read(icon).with_context(|| format!("Could not read icon: {}", icon.display()))?;
+        png::decode_rgba(&data)
+            .with_context(|| format!("Icon is not a supported PNG: {}", icon.display()))?;
+        resources.push(Resource {
+            kind: SectionKind::Icon,
+            name: String::new(),
+            data,
+        });
+    }
+    if let Some(dir) = &args.assets {
+        resources.extend(collect_assets(dir)?);
+    }
+    if args.precompile {
+        if args.sign_key.is_none() {
+            warn!("Hosts only use the precompiled module of packages signed by a key they trust");
+        }
+        resources.push(Resource {
+            kind: SectionKind::Precompiled,
+            name: String::new(),
+            data: precompile(&wasm_bytes, args.precompile_target.as_deref())?,
+        });
+    }
+
+    let mut package = pack(&manifest, &wasm_bytes, &resources, args.codec)?;
+    if let Some(path) = &args.sign_key {
+        let key = signing::load_signing_key(path)?;
+        append_signature(&mut package, &key)?;
+    }
+    fs::write(&args.output, &package)
+        .with_context(|| format!("Could not write package: {}", args.output.display()))?;
+
+    // Make sure the host accepts what was just written
+    let (_, metadata) = loader::load_wapp(&args.output).context("Packed file failed validation")?;
+    if let Some(declared) = &metadata.capabilities {
+        for (import, capability) in capabilities::undeclared(&wasm_bytes, declared) {
+            warn!(
+                "The module imports {} but the manifest does not declare {:?}; calls will be denied",
+                import,
+                capability.name()
+            );
+        }
+    }
+    match args.codec {
+        Some(codec) => info!(
+            "Packed {} ({} bytes, {}-byte module compressed with {:?})",
+            args.output.display(),
+            package.len(),
+            wasm_bytes.len(),
+            codec
+        ),
+        None => info!("Packed {} ({} bytes)", args.output.display(), package.len()),
+    }
+    Ok(())
+}
+
+/// Build the bytes of a package from a JSON manifest, a module and extra
+/// resources, compressing every section with `codec` if given
+pub fn pack(
+    manifest: &[u8],
+    wasm_bytes: &[u8],
+    resources: &[Resource],
+    codec: Option<CodecKind>,
+) -> Result<Vec<u8>> {
+    if !wasm_bytes.starts_with(b"\0asm") {
+        bail!("Module is not a WebAssembly binary (missing '\\0asm' magic)");
+    }
+
+    let value: Value = serde_json::from_slice(manifest).context("Manifest is not valid JSON")?;
+    if !value.is_object() {
+        bail!("Manifest must be a JSON object");
+    }
+    let metadata =
+        serde_json::from_value::<WappMetadata>(value.clone()).context("Invalid manifest")?;
+    // Packages may require a newer host than the one packing them
+    for (field, text) in [
+        (
+            "version",
+            Some(&metadata.version).filter(|text| !text.is_empty()),
+        ),
+        ("min_host_version", metadata.min_host_version.as_ref()),
+    ] {
+        if let Some(Err(e)) = text.map(|text| Version::parse(text)) {
+            bail!(
+                "Invalid manifest: {} is not a semantic version: {}",
+                field,
+                e
+            );
+        }
+    }
+    let header =
+        serde_json::to_vec(&canonicalize(value)).context("Failed to serialize manifest")?;
+    let header_len = u32::try_from(header.len()).context("Manifest too large")?;
+
+    let version = match codec {
+        Some(_) => WAPP_SECTIONED_VERSION,
+        None if resources.is_empty() => WAPP_VERSION,
+        None => bail!("Icons and assets can only be stored in compressed packages (use --codec)"),
+    };
+
+    let mut package = Vec::with_capacity(12 + header.len() + wasm_bytes.len());
+    package.extend_from_slice(WAPP_MAGIC);
+    package.extend_from_slice(&version.to_le_bytes());
+    package.extend_from_slice(&header_len.to_le_bytes());
+    package.extend_from_slice(&header);
+    match codec {
+        Some(codec) => {
+            write_section(&mut package, SectionKind::Module, "", wasm_bytes, codec)?;
+            for resource in resources {
+                write_section(
+                    &mut package,
+                    resource.kind,
+                    &resource.name,
+                    &resource.data,
+                    codec,
+                )?;
+            }
+        }
+        None => package.extend_from_slice(wasm_bytes),
+    }
+    Ok(package)
+}
+
+/// Compile `wasm_bytes` ahead of time for the engine guests run on, on
+/// `target` if given
+fn precompile(wasm_bytes: &[u8], target: Option<&str>) -> Result<Vec<u8>> {
+    let mut config = runtime::engine_config();
+    if let Some(target) = target {
+        config
+            .target(target)
+            .with_context(|| format!("Unsupported target {:?}", target))?;
+    }
+    let engine = Engine::new(&config).context("Failed to create WASM engine")?;
+    engine
+        .precompile_module(wasm_bytes)
+        .context("Failed to precompile module")
+}
+
+/// End a version 2 package with a section signing everything before it
+pub fn append_signature(package: &mut Vec<u8>, key: &SigningKey) -> Result<()> {
+    let signature = signing::sign(key, package);
+    write_section(
+        package,
+        SectionKind::Signature,
+        "",
+        &signature,
+        CodecKind::None,
+    )
+}
+
+/// Read every file under `dir` as an asset, sorted by relative path
+fn collect_assets(dir: &Path) -> Result<Vec<Resource>> {
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files)?;
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|(name, path)| {
+            let data = fs::read(&path)
+                .with_context(|| format!("Could not read asset: {}", path.display()))?;
+            Ok(Resource {
+                kind: SectionKind::Asset,
+                name,
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Recursively list the files under `dir` with their `/`-separated paths relative to `root`
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Could not read assets directory: {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+            continue;
+        }
+        let relative = path
+            .strip_prefix(root)
+            .expect("path is under the assets root");
+        let name = relative
+            .iter()
+            .map(|component| component.to_str())
+            .collect::<Option<Vec<_>>>()
+            .with_context(|| format!("Asset path is not UTF-8: {}", path.display()))?
+            .join("/");
+        files.push((name, path));
+    }
+    Ok(())
+}
+
+/// Append a section holding `data` compressed with `codec`
+fn write_section(
+    package: &mut Vec<u8>,
+    kind: SectionKind,
+    name: &str,
+    data: &[u8],
+    codec: CodecKind,
+) -> Result<()> {
+    let stored = CodecRegistry::new().get(codec.id())?.compress(data)?;
+    let name_len = u16::try_from(name.len()).context("Section name too long")?;
+    let stored_len = u32::try_from(stored.len()).context("Section too large")?;
+    let raw_len = u32::try_from(data.len()).context("Section too large")?;
+
+    package.reserve(SECTION_HEADER_SIZE + name.len() + stored.len());
+    package.push(kind.id());
+    package.push(codec.id());
+    package.extend_from_slice(&name_len.to_le_bytes());
+    package.extend_from_slice(&stored_len.to_le_bytes());
+    package.extend_from_slice(&raw_len.to_le_bytes());
+    package.extend_from_slice(name.as_bytes());
+    package.extend_from_slice(&stored);
+    Ok(())
+}
+
+/// Rebuild `value` with object keys in sorted order at every level
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonicalize(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(canonicalize).collect()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    #[test]
+    fn test_pack_is_reproducible() {
+        let a = pack(
+            br#"{"name": "Life", "description": "Demo"}"#,
+            MODULE,
+            &[],
+            None,
+        )
+        .unwrap();
+        let b = pack(
+            br#"{
+                "description": "Demo",
+                "name": "Life"
+            }"#,
+            MODULE,
+            &[],
+            None,
+        )
+        .unwrap();
+        assert_eq!(a, b);
+        assert!(a.ends_with(MODULE));
+    }
+
+    #[test]
+    fn test_pack_rejects_invalid_inputs() {
+        assert!(pack(br#"{"name": "Life"}"#, b"not wasm", &[], None).is_err());
+        assert!(pack(b"[]", MODULE, &[], None).is_err());
+        assert!(pack(br#"{"age_rating": "teen"}"#, MODULE, &[], None).is_err());
+        assert!(pack(br#"{"version": "1.0"}"#, MODULE, &[], None).is_err());
+        assert!(pack(br#"{"min_host_version": "next"}"#, MODULE, &[], None).is_err());
+    }
+
+    #[test]
+    fn test_compressed_sections_round_trip() {
+        let mut module = MODULE.to_vec();
+        module.extend_from_slice(&[0; 4096]);
+        for codec in [CodecKind::None, CodecKind::Zstd, CodecKind::Deflate] {
+            let bytes = pack(br#"{"name": "Life"}"#, &module, &[], Some(codec)).unwrap();
+            let package = loader::parse_package(&bytes).unwrap();
+            assert_eq!(package.format_version, WAPP_SECTIONED_VERSION);
+            assert_eq!(package.module().codec, codec.id());
+            assert_eq!(package.module().data, module);
+        }
+    }
+
+    #[test]
+    fn test_resources_round_trip() {
+        let resources = [
+            Resource {
+                kind: SectionKind::Icon,
+                name: String::new(),
+                data: b"icon".to_vec(),
+            },
+            Resource {
+                kind: SectionKind::Asset,
+                name: "sprites/player.png".to_string(),
+                data: b"player".to_vec(),
+            },
+        ];
+        assert!(pack(br#"{"name": "Life"}"#, MODULE, &resources, None).is_err());
+
+        let bytes = pack(
+            br#"{"name": "Life"}"#,
+            MODULE,
+            &resources,
+            Some(CodecKind::Deflate),
+        )
+        .unwrap();
+        let package = loader::parse_package(&bytes).unwrap();
+        assert_eq!(package.manifest, br#"{"name":"Life"}"#);
+        assert_eq!(package.sections.len(), 3);
+        assert_eq!(package.sections[1].kind, SectionKind::Icon);
+        assert_eq!(package.sections[2].name, "sprites/player.png");
+        assert_eq!(package.sections[2].data, b"player");
+    }
+
+    #[test]
+    fn test_precompiled_module_round_trips() {
+        let precompiled = precompile(MODULE, None).unwrap();
+        let resources = [Resource {
+            kind: SectionKind::Precompiled,
+            name: String::new(),
+            data: precompiled.clone(),
+        }];
+        let bytes = pack(
+            br#"{"name": "Life"}"#,
+            MODULE,
+            &resources,
+            Some(CodecKind::Zstd),
+        )
+        .unwrap();
+        let package = loader::parse_package(&bytes).unwrap();
+        assert_eq!(package.precompiled(), Some(&precompiled[..]));
+
+        let engine = Engine::new(&runtime::engine_config()).unwrap();
+        let module = unsafe { wasmtime::Module::deserialize(&engine, &precompiled) };
+        assert!(module.is_ok());
+    }
+
+    #[test]
+    fn test_signed_packages_are_verified() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut bytes = pack(br#"{"name": "Life"}"#, MODULE, &[], Some(CodecKind::None)).unwrap();
+        append_signature(&mut bytes, &key).unwrap();
+        let package = loader::parse_package(&bytes).unwrap();
+        assert_eq!(package.signer, Some(key.verifying_key().to_bytes()));
+        assert_eq!(package.sections.len(), 1);
+
+        // Flip a byte of the module
+        let module_end = bytes.len() - SECTION_HEADER_SIZE - signing::SIGNATURE_SECTION_LEN;
+        bytes[module_end - 1] ^= 1;
+        assert!(loader::parse_package(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_loader_skips_unknown_sections() {
+        let mut bytes = pack(br#"{"name": "Life"}"#, MODULE, &[], Some(CodecKind::Zstd)).unwrap();
+        // A section kind from a newer packer, stored with a codec this host lacks
+        bytes.extend_from_slice(&[9, 200, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0xFF]);
+        let package = loader::parse_package(&bytes).unwrap();
+        assert_eq!(package.sections.len(), 1);
+
+        bytes[12 + 15 + 1] = 200;
+        assert!(loader::parse_package(&bytes).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Performance Advice
+//!
+//! Watches how many frames an app drops and, when the host keeps falling
+//! behind, tells the guest through its `on_performance_warning` export so it
+//! can shrink its simulation or resolution. Frames are counted in fixed
+//! windows; a level is only reported once it held for several windows in a
+//! row, so a single hitch (a window drag, a GC pause) is ignored. Level 0 is
+//! reported when a warned app recovers, so it can scale back up.
+
+use std::time::{Duration, Instant};
+
+use crate::stats;
+
+/// Length of a measurement window
+const WINDOW: Duration = Duration::from_secs(2);
+
+/// Consecutive windows a new level must hold before it is reported
+const SUSTAIN: u32 = 2;
+
+/// Share of frames dropped from which each warning level applies
+const LEVELS: [(u32, f64); 2] = [(2, 0.33), (1, 0.1)];
+
+/// Tracks dropped frames and decides when to warn the guest
+pub struct PerformanceMonitor {
+    window_start: Option<Instant>,
+    last_frame: Option<Instant>,
+    /// Frames presented and dropped in the current window
+    presented: u64,
+    dropped: u64,
+    /// Level of the previous windows and how many in a row had it
+    candidate: u32,
+    streak: u32,
+    /// Level last reported to the guest
+    level: u32,
+}
+
+impl PerformanceMonitor {
+    pub fn new() -> Self {
+        Self {
+            window_start: None,
+            last_frame: None,
+            presented: 0,
+            dropped: 0,
+            candidate: 0,
+            streak: 0,
+            level: 0,
+        }
+    }
+
+    /// Record a frame presented at `now`, returning the level to report to
+    /// the guest when it changed
+    pub fn record_frame(&mut self, now: Instant) -> Option<u32> {
+        if let Some(last) = self.last_frame.replace(now) {
+            self.dropped += stats::dropped_frames(now.duration_since(last));
+        }
+        self.presented += 1;
+        let window_start = *self.window_start.get_or_insert(now);
+        if now.duration_since(window_start) < WINDOW {
+            return None;
+        }
+
+        let share = self.dropped as f64 / (self.presented + self.dropped) as f64;
+        let level = LEVELS
+            .iter()
+            .find(|(_, threshold)| share >= *threshold)
+            .map_or(0, |(level, _)| *level);
+        self.window_start = Some(now);
+        self.presented = 0;
+        self.dropped = 0;
+
+        if level == self.candidate {
+            self.streak += 1;
+        } else {
+            self.candidate = level;
+            self.streak = 1;
+        }
+        if self.streak >= SUSTAIN && level != self.level {
+            self.level = level;
+            Some(level)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for PerformanceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Present frames every `interval` for `duration`, collecting reported levels
+    fn run(
+        monitor: &mut PerformanceMonitor,
+        now: &mut Instant,
+        interval: Duration,
+        duration: Duration,
+    ) -> Vec<u32> {
+        let end = *now + duration;
+        let mut reported = Vec::new();
+        while *now < end {
+            *now += interval;
+            reported.extend(monitor.record_frame(*now));
+        }
+        reported
+    }
+
+    #[test]
+    fn test_sustained_drops_are_reported_once() {
+        let mut monitor = PerformanceMonitor::new();
+        let mut now = Instant::now();
+        let smooth = Duration::from_millis(16);
+        let slow = Duration::from_millis(50);
+
+        assert!(run(&mut monitor, &mut now, smooth, Duration::from_secs(5)).is_empty());
+        // A single slow window is not enough
+        assert!(run(&mut monitor, &mut now, slow, Duration::from_secs(2)).is_empty());
+        assert!(run(&mut monitor, &mut now, smooth, Duration::from_secs(4)).is_empty());
+
+        assert_eq!(
+            run(&mut monitor, &mut now, slow, Duration::from_secs(10)),
+            [2]
+        );
+        assert_eq!(
+            run(&mut monitor, &mut now, smooth, Duration::from_secs(10)),
+            [0]
+        );
+    }
+}
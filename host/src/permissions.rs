@@ -0,0 +1,596 @@
+//! Package Permissions
+//!
+//! Some capabilities are granted per package: keeping data between runs
+//! (storage and leaderboards), launching other packages, exchanging messages
+//! with other running packages over the bus, using the network and recording
+//! the microphone. The first time a package importing the first three runs,
+//! a dialog lists what it asks for; sensitive ones like the network and the
+//! microphone are asked about the first time the guest actually uses them
+//! instead. Answers are
+//! remembered in `permissions.json` under the user data directory, keyed by
+//! package id, so a package signed by someone else, or an unsigned one with
+//! other contents, is asked again even if it has the same name.
+//! `wapps permissions <APP>` reviews, revokes and resets those decisions. Apps denied storage get an empty store that is never written,
+//! denied network calls fail with `ACCES` and denied captures never start; `--allow-launch` grants launching
+//! without asking, and kiosks never ask, keeping storage and denying the rest.
+//! `--safe-mode` denies everything, whatever was decided before.
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::capabilities::Capability;
+use crate::loader;
+use crate::rating;
+use crate::runtime;
+use crate::storage;
+
+/// Id of the import section in a WebAssembly binary
+const IMPORT_SECTION: u8 = 2;
+
+/// A capability the user grants per package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// Keep settings and high scores between runs
+    Storage,
+    /// Launch other packages via `wapps::launch`
+    Launch,
+    /// Use WASI sockets, `wapps::http_fetch` and `wapps::ws_connect`
+    Network,
+    /// Record audio via `wapps::audio_capture_start`
+    Microphone,
+    /// Exchange messages with other running packages via `wapps::bus_send`
+    /// and `wapps::bus_subscribe`
+    Messaging,
+}
+
+impl Permission {
+    /// What the permission allows, as shown in the prompt
+    pub fn describe(self) -> &'static str {
+        match self {
+            Permission::Storage => "keep settings and high scores between runs",
+            Permission::Launch => "launch other packages",
+            Permission::Network => "connect to the network",
+            Permission::Microphone => "record audio from the microphone",
+            Permission::Messaging => "exchange messages with other running apps",
+        }
+    }
+
+    /// Whether the permission is granted when the user cannot be asked
+    fn default_granted(self) -> bool {
+        match self {
+            Permission::Storage => true,
+            Permission::Launch
+            | Permission::Network
+            | Permission::Microphone
+            | Permission::Messaging => false,
+        }
+    }
+
+    /// Whether the user is asked when the guest first uses the permission,
+    /// rather than when the package starts
+    pub fn asked_on_first_use(self) -> bool {
+        matches!(self, Permission::Network | Permission::Microphone)
+    }
+
+    /// The permission a host import needs, if any
+    pub(crate) fn from_import(module: &str, name: &str) -> Option<Self> {
+        match (runtime::unversioned(module), name) {
+            ("wapps", "storage_get" | "storage_set" | "score_submit" | "score_list") => {
+                Some(Permission::Storage)
+            }
+            ("wapps", "launch") => Some(Permission::Launch),
+            ("wapps", "bus_send" | "bus_subscribe") => Some(Permission::Messaging),
+            ("wapps", "http_fetch" | "http_poll" | "http_read" | "http_close") => {
+                Some(Permission::Network)
+            }
+            ("wapps", "ws_connect" | "ws_send" | "ws_status" | "ws_close") => {
+                Some(Permission::Network)
+            }
+            ("wasi_snapshot_preview1", name) if name.starts_with("sock_") => {
+                Some(Permission::Network)
+            }
+            ("wapps", "audio_capture_start" | "audio_capture_read" | "audio_capture_stop") => {
+                Some(Permission::Microphone)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Permissions a module asks for through its imports
+///
+/// A module whose imports cannot be read asks for none, so it runs with the
+/// defaults; the runtime rejects modules that are actually malformed.
+pub fn requested(wasm: &[u8]) -> BTreeSet<Permission> {
+    match imports(wasm) {
+        Ok(imports) => imports
+            .iter()
+            .filter_map(|(module, name)| Permission::from_import(module, name))
+            .collect(),
+        Err(e) => {
+            warn!("Could not read module imports: {:#}", e);
+            BTreeSet::new()
+        }
+    }
+}
+
+/// What a running package may use
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    /// Permissions granted when the package started
+    pub granted: BTreeSet<Permission>,
+    /// Permissions asked about when the guest first uses them
+    pub on_first_use: Vec<FirstUse>,
+    /// Capabilities declared by the package manifest, if it has one
+    pub capabilities: Option<BTreeSet<Capability>>,
+    /// Hosts `wapps::http_fetch` and `wapps::ws_connect` may reach, from the
+    /// package manifest
+    pub allowed_hosts: Vec<String>,
+}
+
+/// Decide which of the permissions `requested` by the package with id `id`
+/// and display name `name` are granted, asking about undecided ones if
+/// `prompt` is set and remembering the answer
+///
+/// Permissions asked on first use are left out and returned as `FirstUse`
+/// gates instead.
+pub fn resolve(id: &str, name: &str, requested: &BTreeSet<Permission>, prompt: bool) -> Access {
+    let (later, now): (Vec<Permission>, Vec<Permission>) = requested
+        .iter()
+        .copied()
+        .partition(|permission| permission.asked_on_first_use());
+    let mut decisions = Decisions::load();
+    let undecided: Vec<Permission> = now
+        .iter()
+        .copied()
+        .filter(|&permission| decisions.get(id, permission).is_none())
+        .collect();
+    if !undecided.is_empty() && prompt {
+        let list: Vec<String> = undecided
+            .iter()
+            .map(|permission| format!("  - {}", permission.describe()))
+            .collect();
+        let message = format!("{:?} would like to:\n{}\nAllow?", name, list.join("\n"));
+        ask(&mut decisions, id, &undecided, &message);
+    }
+
+    Access {
+        granted: now
+            .into_iter()
+            .filter(|&permission| {
+                decisions
+                    .get(id, permission)
+                    .unwrap_or(permission.default_granted())
+            })
+            .collect(),
+        on_first_use: later
+            .into_iter()
+            .map(|permission| FirstUse::new(id, name, permission, prompt))
+            .collect(),
+        capabilities: None,
+        allowed_hosts: Vec::new(),
+    }
+}
+
+/// Deny every permission `requested` by the package with id `id` without
+/// asking, for packages run with `--safe-mode`; remembered decisions are
+/// ignored
+pub fn deny_all(id: &str, name: &str, requested: &BTreeSet<Permission>) -> Access {
+    Access {
+        granted: BTreeSet::new(),
+        on_first_use: requested
+            .iter()
+            .filter(|permission| permission.asked_on_first_use())
+            .map(|&permission| FirstUse::denied(id, name, permission))
+            .collect(),
+        capabilities: None,
+        allowed_hosts: Vec::new(),
+    }
+}
+
+/// Show `message` and remember the answer as the decision on `permissions`,
+/// returning it, or `None` if the dialog could not be shown
+fn ask(
+    decisions: &mut Decisions,
+    app: &str,
+    permissions: &[Permission],
+    message: &str,
+) -> Option<bool> {
+    let allowed = match rating::confirm("Permissions", message) {
+        Ok(allowed) => allowed,
+        Err(e) => {
+            warn!("{:#}; using default permissions", e);
+            return None;
+        }
+    };
+    info!(
+        "{} {:?} for {:?}",
+        if allowed { "Granted" } else { "Denied" },
+        permissions,
+        app
+    );
+    for &permission in permissions {
+        decisions.set(app, permission, allowed);
+    }
+    if let Err(e) = decisions.save() {
+        warn!("Failed to save permissions: {:#}", e);
+    }
+    Some(allowed)
+}
+
+/// A permission decided the first time the guest uses it
+///
+/// Clones share the decision, so the user is asked at most once per run.
+#[derive(Debug, Clone)]
+pub struct FirstUse {
+    /// Package id the decision is remembered under
+    id: String,
+    /// Display name shown when asking
+    name: String,
+    permission: Permission,
+    prompt: bool,
+    decision: Arc<Mutex<Option<bool>>>,
+}
+
+impl FirstUse {
+    pub fn new(id: &str, name: &str, permission: Permission, prompt: bool) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            permission,
+            prompt,
+            decision: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// A use that is always denied
+    pub fn denied(id: &str, name: &str, permission: Permission) -> Self {
+        Self {
+            decision: Arc::new(Mutex::new(Some(false))),
+            ..Self::new(id, name, permission, false)
+        }
+    }
+
+    pub fn permission(&self) -> Permission {
+        self.permission
+    }
+
+    /// Whether the guest may use the permission, asking the user if it was
+    /// never decided
+    pub fn allowed(&self) -> bool {
+        let mut decision = self.decision.lock().unwrap_or_else(|e| e.into_inner());
+        *decision.get_or_insert_with(|| {
+            let mut decisions = Decisions::load();
+            if let Some(allowed) = decisions.get(&self.id, self.permission) {
+                return allowed;
+            }
+            let message = format!(
+                "{:?} would like to {}.\nAllow?",
+                self.name,
+                self.permission.describe()
+            );
+            self.prompt
+                .then(|| ask(&mut decisions, &self.id, &[self.permission], &message))
+                .flatten()
+                .unwrap_or(self.permission.default_granted())
+        })
+    }
+}
+
+/// Remembered answers, by package id and permission
+#[derive(Debug, Default)]
+struct Decisions {
+    apps: BTreeMap<String, BTreeMap<Permission, bool>>,
+    /// File the decisions are persisted to (`None` keeps them in memory)
+    path: Option<PathBuf>,
+}
+
+impl Decisions {
+    fn load() -> Self {
+        let Some(path) = storage::data_dir().map(|dir| dir.join("permissions.json")) else {
+            return Self::default();
+        };
+        let apps = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring corrupt permissions file {}: {}",
+                    path.display(),
+                    e
+                );
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self {
+            apps,
+            path: Some(path),
+        }
+    }
+
+    fn get(&self, app: &str, permission: Permission) -> Option<bool> {
+        self.apps.get(app)?.get(&permission).copied()
+    }
+
+    fn set(&mut self, app: &str, permission: Permission, allowed: bool) {
+        self.apps
+            .entry(app.to_string())
+            .or_default()
+            .insert(permission, allowed);
+    }
+
+    /// Forget every decision about `app`, returning whether there were any
+    fn reset(&mut self, app: &str) -> bool {
+        self.apps.remove(app).is_some()
+    }
+
+    /// Write the decisions to a temporary file and move it over the old one
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+        }
+        let json =
+            serde_json::to_vec_pretty(&self.apps).context("Failed to serialize permissions")?;
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, json)
+            .with_context(|| format!("Could not write permissions file: {}", temp.display()))?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("Could not replace permissions file: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Arguments of `wapps permissions`
+#[derive(Args, Debug)]
+pub struct PermissionsArgs {
+    /// App id, or a package to read it from
+    #[arg(value_name = "APP")]
+    app: String,
+
+    /// Deny PERMISSION from now on
+    #[arg(long, value_name = "PERMISSION")]
+    revoke: Vec<Permission>,
+
+    /// Forget every decision, so the app asks again on its next run
+    #[arg(long, conflicts_with = "revoke")]
+    reset: bool,
+}
+
+/// Run `wapps permissions`
+pub fn run(args: &PermissionsArgs) -> Result<()> {
+    let app = app_id(&args.app)?;
+    let mut decisions = Decisions::load();
+
+    if args.reset {
+        if decisions.reset(&app) {
+            decisions.save()?;
+        }
+        println!("{:?} will ask for its permissions on its next run", app);
+        return Ok(());
+    }
+    if !args.revoke.is_empty() {
+        for &permission in &args.revoke {
+            decisions.set(&app, permission, false);
+        }
+        decisions.save()?;
+    }
+
+    let Some(granted) = decisions.apps.get(&app) else {
+        println!("No permissions decided for {:?}", app);
+        return Ok(());
+    };
+    println!("Permissions of {:?}:", app);
+    for (permission, allowed) in granted {
+        println!(
+            "  {:<10} {:<8} {}",
+            format!("{:?}", permission).to_lowercase(),
+            if *allowed { "allowed" } else { "denied" },
+            permission.describe()
+        );
+    }
+    Ok(())
+}
+
+/// Id decisions are kept under for `app`: the id itself, or the id of the
+/// package at that path
+fn app_id(app: &str) -> Result<String> {
+    let path = Path::new(app);
+    if !path.is_file() {
+        return Ok(app.to_string());
+    }
+    let package = loader::load_package(path)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", path))?;
+    Ok(package.id(path))
+}
+
+/// Name a package is shown under: its unlocalized name, or its file stem if
+/// it has none
+pub fn package_name(name: &str, path: &Path) -> String {
+    if !name.is_empty() {
+        return name.to_string();
+    }
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("WAPPS")
+        .to_string()
+}
+
+/// (module, name) of every import of a WebAssembly binary
+pub fn imports(wasm: &[u8]) -> Result<Vec<(String, String)>> {
+    if !wasm.starts_with(b"\0asm") {
+        bail!("Not a WebAssembly module");
+    }
+    let mut reader = Reader(&wasm[8.min(wasm.len())..]);
+    while !reader.0.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.leb()? as usize;
+        let mut section = Reader(reader.take(size)?);
+        if id != IMPORT_SECTION {
+            continue;
+        }
+        let count = section.leb()?;
+        let mut imports = Vec::new();
+        for _ in 0..count {
+            let module = section.name()?;
+            let name = section.name()?;
+            section.skip_import_desc()?;
+            imports.push((module, name));
+        }
+        return Ok(imports);
+    }
+    Ok(Vec::new())
+}
+
+/// Cursor over the bytes of a WebAssembly binary
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.0.len() {
+            bail!("Unexpected end of WebAssembly module");
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Unsigned LEB128 integer
+    fn leb(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Invalid LEB128 integer in WebAssembly module")
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let len = self.leb()? as usize;
+        let bytes = self.take(len)?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// Skip the type of an import: a function, table, memory, global or tag
+    fn skip_import_desc(&mut self) -> Result<()> {
+        match self.byte()? {
+            0x00 => {
+                self.leb()?;
+            }
+            0x01 => {
+                self.byte()?;
+                self.skip_limits()?;
+            }
+            0x02 => self.skip_limits()?,
+            0x03 => {
+                self.take(2)?;
+            }
+            0x04 => {
+                self.byte()?;
+                self.leb()?;
+            }
+            kind => bail!("Unknown import kind {:#x}", kind),
+        }
+        Ok(())
+    }
+
+    fn skip_limits(&mut self) -> Result<()> {
+        let flags = self.byte()?;
+        self.leb()?;
+        if flags & 0x01 != 0 {
+            self.leb()?;
+        }
+        // Custom page size
+        if flags & 0x08 != 0 {
+            self.leb()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requested_permissions_come_from_imports() {
+        // A memory import, then wapps::storage_set and wapps::launch functions
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        let mut section = vec![3];
+        section.extend_from_slice(b"\x03env\x06memory\x02\x01\x01\x02");
+        section.extend_from_slice(b"\x05wapps\x0bstorage_set\x00\x00");
+        section.extend_from_slice(b"\x05wapps\x06launch\x00\x01");
+        wasm.extend_from_slice(&[IMPORT_SECTION, section.len() as u8]);
+        wasm.extend_from_slice(&section);
+
+        assert_eq!(
+            requested(&wasm),
+            BTreeSet::from([Permission::Storage, Permission::Launch])
+        );
+        assert!(requested(b"\0asm\x01\0\0\0").is_empty());
+        let network = Permission::from_import("wasi_snapshot_preview1", "sock_send");
+        assert_eq!(network, Some(Permission::Network));
+        assert!(network.is_some_and(Permission::asked_on_first_use));
+        let microphone = Permission::from_import("wapps", "audio_capture_start");
+        assert!(microphone.is_some_and(Permission::asked_on_first_use));
+        let messaging = Permission::from_import("wapps", "bus_subscribe");
+        assert_eq!(messaging, Some(Permission::Messaging));
+        assert!(!messaging.is_some_and(Permission::asked_on_first_use));
+
+        let mut decisions = Decisions::default();
+        decisions.set("Life", Permission::Launch, true);
+        assert_eq!(decisions.get("Life", Permission::Launch), Some(true));
+        assert_eq!(decisions.get("Life", Permission::Storage), None);
+        assert!(decisions.reset("Life"));
+
+        let access = deny_all(
+            "Life-0",
+            "Life",
+            &BTreeSet::from([Permission::Storage, Permission::Network]),
+        );
+        assert!(access.granted.is_empty());
+        assert_eq!(access.on_first_use[0].permission(), Permission::Network);
+        assert!(!access.on_first_use[0].allowed());
+    }
+
+    #[test]
+    fn test_decisions_do_not_carry_over_to_other_signers() {
+        let header = br#"{"name": "Life"}"#;
+        let mut data = loader::WAPP_MAGIC.to_vec();
+        data.extend_from_slice(&loader::WAPP_VERSION.to_le_bytes());
+        data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        data.extend_from_slice(header);
+        data.extend_from_slice(b"\0asm\x01\0\0\0");
+        let mut original = loader::parse_package(&data).unwrap();
+        original.signer = Some([1; 32]);
+        let mut impostor = original.clone();
+        impostor.signer = Some([2; 32]);
+        let path = Path::new("life.wapp");
+
+        let mut decisions = Decisions::default();
+        decisions.set(&original.id(path), Permission::Launch, true);
+        assert_eq!(
+            decisions.get(&original.id(path), Permission::Launch),
+            Some(true)
+        );
+        // Undecided, so the impostor is asked again
+        assert_eq!(decisions.get(&impostor.id(path), Permission::Launch), None);
+        assert_eq!(decisions.get("Life", Permission::Launch), None);
+    }
+}
@@ -0,0 +1,126 @@
+//! Pixel Formats
+//!
+//! `wapps::update_frame_ex` accepts frames in formats smaller or more
+//! convenient than RGBA, so memory-constrained guests can halve their
+//! framebuffer. Frames are expanded to RGBA on arrival, since layers, the
+//! debug views, frame hashes and thumbnails all work on RGBA. Indexed frames
+//! look their colors up in the palette set with `wapps::set_palette`.
+
+/// Most colors in a palette
+pub const PALETTE_SIZE: usize = 256;
+
+/// Color of indices beyond the end of the palette
+const UNSET_COLOR: [u8; 4] = [0, 0, 0, 255];
+
+/// Layout of the pixels passed to `wapps::update_frame_ex`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 4 bytes per pixel: red, green, blue, alpha
+    Rgba32,
+    /// 3 bytes per pixel: red, green, blue
+    Rgb24,
+    /// 4 bytes per pixel: blue, green, red, alpha
+    Bgra32,
+    /// 2 bytes per pixel, little-endian: 5 bits red, 6 green, 5 blue
+    Rgb565,
+    /// 1 byte per pixel: luminance
+    Gray8,
+    /// 1 byte per pixel: index into the palette
+    Indexed8,
+}
+
+impl PixelFormat {
+    /// Convert a format passed to `wapps::update_frame_ex`
+    pub fn from_raw(format: i32) -> Option<Self> {
+        match format {
+            0 => Some(PixelFormat::Rgba32),
+            1 => Some(PixelFormat::Rgb24),
+            2 => Some(PixelFormat::Bgra32),
+            3 => Some(PixelFormat::Rgb565),
+            4 => Some(PixelFormat::Gray8),
+            5 => Some(PixelFormat::Indexed8),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba32 | PixelFormat::Bgra32 => 4,
+            PixelFormat::Rgb24 => 3,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Gray8 | PixelFormat::Indexed8 => 1,
+        }
+    }
+
+    /// Replace the contents of `rgba` with the pixels of `src` expanded to
+    /// RGBA, looking indexed colors up in `palette`
+    pub fn to_rgba(self, src: &[u8], palette: &[[u8; 4]], rgba: &mut Vec<u8>) {
+        let pixels = src.chunks_exact(self.bytes_per_pixel());
+        rgba.clear();
+        rgba.reserve(pixels.len() * 4);
+        match self {
+            PixelFormat::Rgba32 => rgba.extend_from_slice(src),
+            PixelFormat::Rgb24 => {
+                for p in pixels {
+                    rgba.extend_from_slice(&[p[0], p[1], p[2], 255]);
+                }
+            }
+            PixelFormat::Bgra32 => {
+                for p in pixels {
+                    rgba.extend_from_slice(&[p[2], p[1], p[0], p[3]]);
+                }
+            }
+            PixelFormat::Rgb565 => {
+                for p in pixels {
+                    let value = u16::from_le_bytes([p[0], p[1]]);
+                    let r = (value >> 11) as u8 & 0x1f;
+                    let g = (value >> 5) as u8 & 0x3f;
+                    let b = value as u8 & 0x1f;
+                    // Replicate the high bits so full intensity maps to 255
+                    rgba.extend_from_slice(&[
+                        (r << 3) | (r >> 2),
+                        (g << 2) | (g >> 4),
+                        (b << 3) | (b >> 2),
+                        255,
+                    ]);
+                }
+            }
+            PixelFormat::Gray8 => {
+                for p in pixels {
+                    rgba.extend_from_slice(&[p[0], p[0], p[0], 255]);
+                }
+            }
+            PixelFormat::Indexed8 => {
+                for p in pixels {
+                    rgba.extend_from_slice(palette.get(p[0] as usize).unwrap_or(&UNSET_COLOR));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_expand_to_rgba() {
+        let mut rgba = Vec::new();
+        PixelFormat::Rgb24.to_rgba(&[1, 2, 3, 4, 5, 6], &[], &mut rgba);
+        assert_eq!(rgba, [1, 2, 3, 255, 4, 5, 6, 255]);
+
+        PixelFormat::Bgra32.to_rgba(&[1, 2, 3, 4], &[], &mut rgba);
+        assert_eq!(rgba, [3, 2, 1, 4]);
+
+        // Pure red, then pure green and blue at full intensity
+        PixelFormat::Rgb565.to_rgba(&[0x00, 0xf8, 0xff, 0x07], &[], &mut rgba);
+        assert_eq!(rgba, [255, 0, 0, 255, 0, 255, 255, 255]);
+
+        PixelFormat::Gray8.to_rgba(&[7], &[], &mut rgba);
+        assert_eq!(rgba, [7, 7, 7, 255]);
+
+        let palette = [[10, 20, 30, 40], [50, 60, 70, 80]];
+        PixelFormat::Indexed8.to_rgba(&[1, 0, 9], &palette, &mut rgba);
+        assert_eq!(rgba, [50, 60, 70, 80, 10, 20, 30, 40, 0, 0, 0, 255]);
+    }
+}
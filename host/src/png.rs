@@ -0,0 +1,261 @@
+//! PNG Encoding and Decoding
+//!
+//! Minimal encoder for 8-bit RGBA images, used to write thumbnails without an
+//! image library. Pixel rows are compressed with the package codec's deflate
+//! encoder and wrapped in a zlib stream, as PNG requires. The decoder reads
+//! package icons and golden-frame references: non-interlaced images with 8
+//! bits per channel, in any color type, expanded to RGBA.
+
+use anyhow::{bail, Context, Result};
+
+use crate::deflate;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Largest width or height the decoder accepts
+const MAX_DIMENSION: u32 = 4096;
+
+/// Encode `width` x `height` RGBA pixels as a PNG file
+pub fn encode_rgba(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 6 (RGBA), default compression, filter and no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Each row starts with its filter type; 0 leaves the bytes unfiltered
+    let row_len = width as usize * 4;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in pixels.chunks_exact(row_len.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    // zlib stream: header for a 32 KiB deflate window, data, Adler-32
+    let mut zlib = vec![0x78, 0x01];
+    zlib.extend_from_slice(&deflate::compress(&raw));
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Decode a PNG file into its width, height and RGBA pixels
+pub fn decode_rgba(png: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let mut data = png.strip_prefix(&SIGNATURE[..]).context("Not a PNG file")?;
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut zlib = Vec::new();
+    loop {
+        let (kind, chunk, rest) = read_chunk(data)?;
+        data = rest;
+        match &kind {
+            b"IHDR" => header = Some(chunk),
+            b"PLTE" => palette = chunk,
+            b"tRNS" => transparency = chunk,
+            b"IDAT" => zlib.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header
+        .filter(|header| header.len() == 13)
+        .context("PNG has no valid IHDR chunk")?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let (depth, color_type, interlace) = (header[8], header[9], header[12]);
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        bail!("Unsupported PNG size {}x{}", width, height);
+    }
+    if depth != 8 || interlace != 0 {
+        bail!("Unsupported PNG: only non-interlaced 8-bit images are supported");
+    }
+    let channels = match color_type {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => bail!("Invalid PNG color type {}", color_type),
+    };
+
+    if zlib.len() < 2 || zlib[0] & 0x0f != 8 {
+        bail!("PNG image data is not a deflate stream");
+    }
+    let row_len = width as usize * channels;
+    let raw = deflate::decompress(&zlib[2..], (row_len + 1) * height as usize)
+        .context("Corrupted PNG image data")?;
+    if raw.len() < (row_len + 1) * height as usize {
+        bail!("PNG image data is truncated");
+    }
+
+    // Undo the per-row filters in place, against the previous decoded row
+    let mut samples = vec![0u8; row_len * height as usize];
+    for y in 0..height as usize {
+        let filter = raw[y * (row_len + 1)];
+        let src = &raw[y * (row_len + 1) + 1..][..row_len];
+        let (done, row) = samples.split_at_mut(y * row_len);
+        let above = done.get(done.len().wrapping_sub(row_len)..);
+        let row = &mut row[..row_len];
+        for i in 0..row_len {
+            let a = if i >= channels { row[i - channels] } else { 0 };
+            let b = above.map_or(0, |above| above[i]);
+            let c = match above {
+                Some(above) if i >= channels => above[i - channels],
+                _ => 0,
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => bail!("Invalid PNG filter type {}", filter),
+            };
+            row[i] = src[i].wrapping_add(predicted);
+        }
+    }
+
+    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+    for pixel in samples.chunks_exact(channels) {
+        match color_type {
+            0 => rgba.extend_from_slice(&[pixel[0], pixel[0], pixel[0], 255]),
+            3 => {
+                let index = pixel[0] as usize;
+                let color = palette
+                    .get(index * 3..index * 3 + 3)
+                    .context("PNG palette index out of range")?;
+                let alpha = transparency.get(index).copied().unwrap_or(255);
+                rgba.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+            }
+            4 => rgba.extend_from_slice(&[pixel[0], pixel[0], pixel[0], pixel[1]]),
+            2 => rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]),
+            _ => rgba.extend_from_slice(pixel),
+        }
+    }
+    Ok((width, height, rgba))
+}
+
+/// Split the chunk at the start of `data` into its kind, its data and the
+/// bytes after it, checking its CRC
+fn read_chunk(data: &[u8]) -> Result<([u8; 4], &[u8], &[u8])> {
+    let len = data
+        .get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .context("PNG ends without an IEND chunk")?;
+    let end = 8usize
+        .checked_add(len)
+        .filter(|&end| end + 4 <= data.len())
+        .context("PNG chunk extends past the end of the file")?;
+    let crc = u32::from_be_bytes([data[end], data[end + 1], data[end + 2], data[end + 3]]);
+    if crc32(&data[4..end]) != crc {
+        bail!("PNG chunk checksum mismatch");
+    }
+    let kind = [data[4], data[5], data[6], data[7]];
+    Ok((kind, &data[8..end], &data[end + 4..]))
+}
+
+/// Paeth predictor: whichever of left, above and upper left is closest to
+/// `a + b - c`
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// CRC-32 (ISO-HDLC), as used by PNG chunks and zip archives
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_decode_round_trips_and_unfilters() {
+        let pixels: Vec<u8> = (0..3 * 2 * 4).map(|i| (i * 7) as u8).collect();
+        let png = encode_rgba(3, 2, &pixels);
+        assert_eq!(decode_rgba(&png).unwrap(), (3, 2, pixels));
+
+        // 2x2 grayscale, rows filtered with Sub and Paeth
+        let raw = [1, 10, 5, 4, 20, 5];
+        let mut zlib = vec![0x78, 0x01];
+        zlib.extend_from_slice(&deflate::compress(&raw));
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 2, 8, 0, 0, 0, 0]);
+        write_chunk(&mut png, b"IDAT", &zlib);
+        write_chunk(&mut png, b"IEND", &[]);
+        let (_, _, rgba) = decode_rgba(&png).unwrap();
+        let gray: Vec<u8> = rgba.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(gray, [10, 15, 30, 35]);
+
+        assert!(decode_rgba(b"not a png").is_err());
+    }
+
+    #[test]
+    fn test_encoded_pixels_round_trip() {
+        let pixels = [255, 0, 0, 255, 0, 255, 0, 128];
+        let png = encode_rgba(2, 1, &pixels);
+        assert_eq!(png[..8], SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+
+        // IDAT follows the 25-byte IHDR chunk
+        let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let zlib = &png[41..41 + idat_len];
+        let raw = deflate::decompress(&zlib[2..zlib.len() - 4], 9).unwrap();
+        assert_eq!(raw[0], 0);
+        assert_eq!(raw[1..], pixels);
+        assert!(png.ends_with(&[0xae, 0x42, 0x60, 0x82]));
+    }
+}
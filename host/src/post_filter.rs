@@ -0,0 +1,301 @@
+//! Post-Processing Filters
+//!
+//! Low-resolution apps are scaled up to their window with hard pixel edges,
+//! which looks blocky on modern displays. `--filter` runs each frame through a
+//! filter that upscales it by a whole factor before it is uploaded: `crt` and
+//! `scanlines` darken the gaps between lines, `hq2x` smooths diagonal edges
+//! and `bilinear` blends neighboring pixels. Filters run on the CPU so they
+//! work the same with every presentation backend, and the window, pointer
+//! coordinates and zoom keep using the guest's frame size; only the texture
+//! is larger.
+
+use clap::ValueEnum;
+
+/// Largest filtered frame side; larger frames are presented unfiltered
+const MAX_OUTPUT_SIDE: u32 = 4096;
+
+/// Brightness of the dark line of each scanline pair, in 1/256
+const SCANLINE_WEIGHT: u32 = 128;
+
+/// Brightness of the gap below each CRT line, in 1/256
+const CRT_GAP_WEIGHT: u32 = 140;
+
+/// Brightness of the two other channels in each CRT phosphor, in 1/256
+const CRT_MASK_WEIGHT: u32 = 160;
+
+/// Largest luma, blue and red chroma differences of colors `hq2x` treats as
+/// the same, hq2x's own thresholds
+const SIMILAR_YUV: [f32; 3] = [48.0, 7.0, 6.0];
+
+/// A post-processing filter for presented frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PostFilter {
+    /// Red, green and blue phosphors with dark gaps between lines, at 3x
+    Crt,
+    /// Dark gaps between lines, at 2x
+    Scanlines,
+    /// Smoothed diagonal edges, at 2x
+    Hq2x,
+    /// Interpolated pixels, at 4x
+    Bilinear,
+}
+
+impl PostFilter {
+    /// Factor the filter upscales frames by
+    pub fn scale(self) -> u32 {
+        match self {
+            PostFilter::Crt => 3,
+            PostFilter::Scanlines | PostFilter::Hq2x => 2,
+            PostFilter::Bilinear => 4,
+        }
+    }
+}
+
+/// Applies a post-processing filter to frames, reusing its output buffer
+pub struct PostProcessor {
+    filter: PostFilter,
+    output: Vec<u8>,
+}
+
+impl PostProcessor {
+    pub fn new(filter: PostFilter) -> Self {
+        Self {
+            filter,
+            output: Vec::new(),
+        }
+    }
+
+    /// Filter a `width` x `height` frame of RGBA pixels, returning the factor
+    /// it was upscaled by and the upscaled pixels
+    ///
+    /// Frames too large to upscale are returned as they are, with a factor of 1.
+    pub fn apply<'a>(&'a mut self, width: u32, height: u32, pixels: &'a [u8]) -> (u32, &'a [u8]) {
+        let scale = self.filter.scale();
+        let fits = width.max(height).saturating_mul(scale) <= MAX_OUTPUT_SIDE;
+        if !fits || width == 0 || height == 0 || pixels.len() != (width * height * 4) as usize {
+            return (1, pixels);
+        }
+        let frame = Frame {
+            pixels,
+            width: width as usize,
+            height: height as usize,
+        };
+        let scale = scale as usize;
+        self.output.resize(pixels.len() * scale * scale, 0);
+        let output = &mut self.output;
+        match self.filter {
+            PostFilter::Crt => masked(&frame, scale, output, |column, row, channel| {
+                let gap = if row == 2 { CRT_GAP_WEIGHT } else { 256 };
+                let phosphor = if channel == column {
+                    256
+                } else {
+                    CRT_MASK_WEIGHT
+                };
+                gap * phosphor / 256
+            }),
+            PostFilter::Scanlines => masked(&frame, scale, output, |_, row, _| {
+                if row == 1 {
+                    SCANLINE_WEIGHT
+                } else {
+                    256
+                }
+            }),
+            PostFilter::Hq2x => smooth_edges(&frame, output),
+            PostFilter::Bilinear => bilinear(&frame, scale, output),
+        }
+        (scale as u32, &self.output)
+    }
+}
+
+/// A frame being filtered
+struct Frame<'a> {
+    pixels: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl Frame<'_> {
+    /// Pixel at (`x`, `y`), clamped to the frame's edges
+    fn at(&self, x: isize, y: isize) -> [u8; 4] {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        let index = (y * self.width + x) * 4;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.pixels[index..index + 4]);
+        pixel
+    }
+}
+
+/// Write `pixel` at (`x`, `y`) of an output `width` pixels wide
+fn put(output: &mut [u8], width: usize, x: usize, y: usize, pixel: [u8; 4]) {
+    let index = (y * width + x) * 4;
+    output[index..index + 4].copy_from_slice(&pixel);
+}
+
+/// Upscale each pixel to a `scale` x `scale` block, weighting the color
+/// channels of each block pixel by `weight(column, row, channel)`, in 1/256
+fn masked(
+    frame: &Frame,
+    scale: usize,
+    output: &mut [u8],
+    weight: impl Fn(usize, usize, usize) -> u32,
+) {
+    let out_width = frame.width * scale;
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let pixel = frame.at(x as isize, y as isize);
+            for row in 0..scale {
+                for column in 0..scale {
+                    let mut out = pixel;
+                    for channel in 0..3 {
+                        let value = pixel[channel] as u32 * weight(column, row, channel) / 256;
+                        out[channel] = value.min(255) as u8;
+                    }
+                    put(output, out_width, x * scale + column, y * scale + row, out);
+                }
+            }
+        }
+    }
+}
+
+/// Upscale 2x with the Scale2x rules, comparing colors with hq2x's YUV
+/// thresholds and blending the corners they round off instead of copying
+/// the neighbor's color
+fn smooth_edges(frame: &Frame, output: &mut [u8]) {
+    let out_width = frame.width * 2;
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let (xi, yi) = (x as isize, y as isize);
+            let center = frame.at(xi, yi);
+            let above = frame.at(xi, yi - 1);
+            let below = frame.at(xi, yi + 1);
+            let left = frame.at(xi - 1, yi);
+            let right = frame.at(xi + 1, yi);
+
+            let mut corners = [center; 4];
+            if !similar(above, below) && !similar(left, right) {
+                let corner = |a: [u8; 4], b: [u8; 4]| {
+                    if similar(a, b) {
+                        mix(center, a)
+                    } else {
+                        center
+                    }
+                };
+                corners = [
+                    corner(left, above),
+                    corner(above, right),
+                    corner(left, below),
+                    corner(below, right),
+                ];
+            }
+            for (i, pixel) in corners.into_iter().enumerate() {
+                put(output, out_width, x * 2 + i % 2, y * 2 + i / 2, pixel);
+            }
+        }
+    }
+}
+
+/// Whether two colors are close enough for `hq2x` to treat as one
+fn similar(a: [u8; 4], b: [u8; 4]) -> bool {
+    let (a, b) = (yuv(a), yuv(b));
+    (0..3).all(|i| (a[i] - b[i]).abs() <= SIMILAR_YUV[i])
+}
+
+fn yuv(pixel: [u8; 4]) -> [f32; 3] {
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(f32::from);
+    [
+        0.299 * r + 0.587 * g + 0.114 * b,
+        -0.169 * r - 0.331 * g + 0.5 * b,
+        0.5 * r - 0.419 * g - 0.081 * b,
+    ]
+}
+
+/// One part `center` to three parts `edge`
+fn mix(center: [u8; 4], edge: [u8; 4]) -> [u8; 4] {
+    let mut out = [0; 4];
+    for i in 0..4 {
+        out[i] = ((center[i] as u32 + edge[i] as u32 * 3 + 2) / 4) as u8;
+    }
+    out
+}
+
+/// Upscale by `scale`, interpolating each output pixel from the four source
+/// pixels around its center
+fn bilinear(frame: &Frame, scale: usize, output: &mut [u8]) {
+    let (out_width, out_height) = (frame.width * scale, frame.height * scale);
+    // Weights are in 1/(2 * scale), the spacing of output pixel centers
+    let unit = (2 * scale) as u32;
+    for out_y in 0..out_height {
+        let (y0, y1, weight_y) = taps(out_y, scale, frame.height);
+        for out_x in 0..out_width {
+            let (x0, x1, weight_x) = taps(out_x, scale, frame.width);
+            let [top_left, top_right, bottom_left, bottom_right] =
+                [(x0, y0), (x1, y0), (x0, y1), (x1, y1)]
+                    .map(|(x, y)| frame.at(x as isize, y as isize));
+            let mut pixel = [0; 4];
+            for i in 0..4 {
+                let top = top_left[i] as u32 * (unit - weight_x) + top_right[i] as u32 * weight_x;
+                let bottom =
+                    bottom_left[i] as u32 * (unit - weight_x) + bottom_right[i] as u32 * weight_x;
+                let value = top * (unit - weight_y) + bottom * weight_y;
+                pixel[i] = ((value + unit * unit / 2) / (unit * unit)) as u8;
+            }
+            put(output, out_width, out_x, out_y, pixel);
+        }
+    }
+}
+
+/// Source pixels the center of output pixel `out` falls between along an
+/// axis `len` source pixels long, and the weight of the second
+fn taps(out: usize, scale: usize, len: usize) -> (usize, usize, u32) {
+    let (source, sub) = (out / scale, out % scale);
+    // Offset of the output pixel's center from the source pixel's left edge
+    let offset = 2 * sub + 1;
+    if offset < scale {
+        (source.saturating_sub(1), source, (scale + offset) as u32)
+    } else {
+        (source, (source + 1).min(len - 1), (offset - scale) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_upscale_frames() {
+        let solid = [40, 80, 120, 255].repeat(6);
+        for filter in PostFilter::value_variants() {
+            let mut processor = PostProcessor::new(*filter);
+            let (scale, output) = processor.apply(3, 2, &solid);
+            assert_eq!(scale, filter.scale());
+            assert_eq!(output.len(), solid.len() * (scale * scale) as usize);
+        }
+
+        // Smoothing filters leave flat areas alone
+        for filter in [PostFilter::Hq2x, PostFilter::Bilinear] {
+            let mut processor = PostProcessor::new(filter);
+            let (_, output) = processor.apply(3, 2, &solid);
+            assert!(output.chunks(4).all(|pixel| pixel == [40, 80, 120, 255]));
+        }
+
+        let mut processor = PostProcessor::new(PostFilter::Scanlines);
+        let (_, output) = processor.apply(3, 2, &solid);
+        assert_eq!(&output[..4], [40, 80, 120, 255]);
+        // The second row of the first pair is darkened
+        assert_eq!(&output[6 * 4..6 * 4 + 4], [20, 40, 60, 255]);
+
+        let wide = vec![0; 4096 * 4];
+        assert_eq!(processor.apply(4096, 1, &wide).0, 1);
+    }
+
+    #[test]
+    fn test_bilinear_interpolates_between_pixels() {
+        let mut processor = PostProcessor::new(PostFilter::Bilinear);
+        let pixels = [0, 0, 0, 255, 255, 255, 255, 255];
+        let (_, output) = processor.apply(2, 1, &pixels);
+        let reds: Vec<u8> = output[..8 * 4].chunks(4).map(|pixel| pixel[0]).collect();
+        // Edges are clamped; the two pixels blend across their boundary
+        assert_eq!(reds, [0, 0, 32, 96, 159, 223, 255, 255]);
+    }
+}
@@ -0,0 +1,305 @@
+//! Presentation Backends
+//!
+//! Windows, input and audio always go through SDL2, but drawing a window's
+//! frame, scaled and letterboxed with the debug overlay on top, is left to a
+//! backend chosen with `--backend`: SDL2's own renderer by default, `wgpu`
+//! (built with the `wgpu` feature) for setups where SDL's renderer
+//! misbehaves, such as some Wayland and HiDPI ones, or `softbuffer` (built
+//! with the `softbuffer` feature), which draws on the CPU and works wherever
+//! a window opens. The other backends reach the SDL window through
+//! raw-window-handle.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use log::debug;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{SwapInterval, Window, WindowContext};
+
+use crate::inspector::OverlayRect;
+use crate::orientation::Orientation;
+
+/// Frame textures are allocated in multiples of this many pixels per side, so
+/// resizing by a few pixels at a time reuses the same texture
+const TEXTURE_BUCKET: u32 = 256;
+
+/// What draws frames into windows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// SDL2's renderer
+    #[default]
+    Sdl,
+    /// wgpu, on Vulkan, Metal, DirectX 12 or OpenGL
+    Wgpu,
+    /// softbuffer, on the CPU
+    Softbuffer,
+}
+
+/// When presents wait for the display's refresh
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum VsyncMode {
+    /// Wait for every refresh, never tearing
+    On,
+    /// Present as soon as a frame is drawn, tearing if needed
+    #[default]
+    Off,
+    /// Wait for the refresh unless the frame is late, then present at once
+    /// rather than waiting a whole refresh more; falls back to `on` where
+    /// unsupported
+    Adaptive,
+}
+
+impl VsyncMode {
+    /// Whether presents may block until the next refresh
+    pub fn waits(self) -> bool {
+        self != VsyncMode::Off
+    }
+}
+
+/// Draws one window's frames
+pub trait Presenter {
+    /// Replace the frame with `width` x `height` RGBA pixels
+    fn upload(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()>;
+
+    /// Replace the guest's HUD with `width` x `height` RGBA pixels, or
+    /// remove it with `None`
+    fn upload_hud(&mut self, hud: Option<(u32, u32, &[u8])>) -> Result<()>;
+
+    /// Fill the window with `clear`, draw the `source` region of the frame
+    /// scaled into the `dest` region of the window, if given as
+    /// `(source, dest)`, then the HUD blended over the whole window, then the
+    /// overlay, and show the result
+    ///
+    /// Window regions are in drawable pixels, which outnumber window units on
+    /// HiDPI displays.
+    fn draw(
+        &mut self,
+        clear: Color,
+        frame: Option<(Rect, Rect)>,
+        overlay: &[OverlayRect],
+    ) -> Result<()>;
+
+    /// Draw the frame and HUD textures turned and flipped by `orientation`
+    /// into their regions of the window
+    fn set_orientation(&mut self, orientation: Orientation) -> Result<()> {
+        let _ = orientation;
+        anyhow::bail!("Only the sdl backend can rotate and mirror frames")
+    }
+}
+
+/// A presenter drawing into `window` with `backend`
+///
+/// Presenting with vsync blocks until the next refresh; backends that cannot
+/// turn it off ignore `vsync`.
+pub fn create(backend: Backend, window: &Window, vsync: VsyncMode) -> Result<Box<dyn Presenter>> {
+    debug!("Presenting with the {:?} backend", backend);
+    match backend {
+        Backend::Sdl => Ok(Box::new(SdlPresenter::new(window.clone(), vsync)?)),
+        #[cfg(feature = "wgpu")]
+        Backend::Wgpu => Ok(Box::new(crate::gpu_presenter::GpuPresenter::new(
+            window, vsync,
+        )?)),
+        #[cfg(not(feature = "wgpu"))]
+        Backend::Wgpu => {
+            anyhow::bail!("This host was built without the wgpu backend; rebuild it with `--features wgpu`")
+        }
+        #[cfg(feature = "softbuffer")]
+        Backend::Softbuffer => Ok(Box::new(crate::soft_presenter::SoftPresenter::new(
+            window,
+        )?)),
+        #[cfg(not(feature = "softbuffer"))]
+        Backend::Softbuffer => anyhow::bail!(
+            "This host was built without the softbuffer backend; rebuild it with `--features softbuffer`"
+        ),
+    }
+}
+
+/// Presents with an SDL2 renderer, copying frames through a streaming texture
+struct SdlPresenter {
+    // Declared first so they are destroyed before the renderer
+    texture: Option<Texture<'static>>,
+    hud: Option<Texture<'static>>,
+    /// Allocated size of `texture`; frames occupy its top-left corner
+    texture_size: (u32, u32),
+    texture_creator: TextureCreator<WindowContext>,
+    canvas: Canvas<Window>,
+    orientation: Orientation,
+}
+
+impl SdlPresenter {
+    fn new(window: Window, vsync: VsyncMode) -> Result<Self> {
+        let video = window.subsystem().clone();
+        let mut canvas_builder = window.into_canvas().accelerated();
+        if vsync.waits() {
+            canvas_builder = canvas_builder.present_vsync();
+        }
+        let canvas = canvas_builder.build().context("Failed to create canvas")?;
+        // Late swaps tear instead of waiting, where the renderer draws with
+        // OpenGL and its driver allows it
+        if vsync == VsyncMode::Adaptive {
+            if let Err(e) = video.gl_set_swap_interval(SwapInterval::LateSwapTearing) {
+                debug!("Adaptive vsync unavailable, using vsync: {}", e);
+            }
+        }
+        let texture_creator = canvas.texture_creator();
+        Ok(Self {
+            texture: None,
+            hud: None,
+            texture_size: (0, 0),
+            // SAFETY: texture_creator lifetime is tied to canvas which we own
+            #[allow(clippy::useless_transmute)]
+            texture_creator: unsafe { std::mem::transmute(texture_creator) },
+            canvas,
+            orientation: Orientation::default(),
+        })
+    }
+}
+
+impl Presenter for SdlPresenter {
+    /// Frames are uploaded into the top-left corner of a texture allocated in
+    /// size buckets, which is only recreated when a frame outgrows it, so
+    /// guests resizing every frame don't reallocate it every frame.
+    fn upload(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+        let size = texture_size(self.texture_size, width, height);
+        if self.texture.is_none() || size != self.texture_size {
+            debug!(
+                "Creating new {}x{} texture for {}x{} frames",
+                size.0, size.1, width, height
+            );
+            // Drop the old texture before allocating its replacement
+            self.texture = None;
+            let texture = self
+                .texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGBA32, size.0, size.1)
+                .context("Failed to create streaming texture")?;
+
+            // SAFETY: texture lifetime is managed manually, texture_creator outlives texture
+            self.texture =
+                Some(unsafe { std::mem::transmute::<Texture<'_>, Texture<'static>>(texture) });
+            self.texture_size = size;
+        }
+
+        if let Some(ref mut texture) = self.texture {
+            let pitch = (width * 4) as usize;
+            texture
+                .update(Rect::new(0, 0, width, height), pixels, pitch)
+                .map_err(|e| anyhow::anyhow!("Failed to update texture: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn upload_hud(&mut self, hud: Option<(u32, u32, &[u8])>) -> Result<()> {
+        let Some((width, height, pixels)) = hud else {
+            self.hud = None;
+            return Ok(());
+        };
+        let size = self.hud.as_ref().map(|hud| {
+            let query = hud.query();
+            (query.width, query.height)
+        });
+        if size != Some((width, height)) {
+            debug!("Creating new {}x{} HUD texture", width, height);
+            self.hud = None;
+            let mut texture = self
+                .texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGBA32, width, height)
+                .context("Failed to create HUD texture")?;
+            texture.set_blend_mode(BlendMode::Blend);
+            // SAFETY: texture lifetime is managed manually, texture_creator outlives texture
+            self.hud =
+                Some(unsafe { std::mem::transmute::<Texture<'_>, Texture<'static>>(texture) });
+        }
+        if let Some(ref mut texture) = self.hud {
+            texture
+                .update(None, pixels, (width * 4) as usize)
+                .map_err(|e| anyhow::anyhow!("Failed to update HUD texture: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn draw(
+        &mut self,
+        clear: Color,
+        frame: Option<(Rect, Rect)>,
+        overlay: &[OverlayRect],
+    ) -> Result<()> {
+        self.canvas.set_draw_color(clear);
+        self.canvas.clear();
+
+        let orientation = self.orientation;
+        // Textures are drawn as laid out, centered on their region of the
+        // window, then flipped and turned about its center
+        let unturned = |dest: Rect| match orientation.swaps_axes() {
+            true => Rect::from_center(dest.center(), dest.height(), dest.width()),
+            false => dest,
+        };
+        if let (Some(texture), Some((source, dest))) = (&self.texture, frame) {
+            self.canvas
+                .copy_ex(
+                    texture,
+                    source,
+                    unturned(dest),
+                    orientation.rotation.degrees(),
+                    None,
+                    orientation.mirror,
+                    false,
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to copy texture: {}", e))?;
+        }
+        if let Some(hud) = &self.hud {
+            let (width, height) = self.canvas.output_size().map_err(anyhow::Error::msg)?;
+            self.canvas
+                .copy_ex(
+                    hud,
+                    None,
+                    unturned(Rect::new(0, 0, width, height)),
+                    orientation.rotation.degrees(),
+                    None,
+                    orientation.mirror,
+                    false,
+                )
+                .map_err(|e| anyhow::anyhow!("Failed to copy HUD texture: {}", e))?;
+        }
+
+        for &(rect, color) in overlay {
+            self.canvas.set_draw_color(color);
+            self.canvas
+                .fill_rect(rect)
+                .map_err(|e| anyhow::anyhow!("Failed to draw overlay: {}", e))?;
+        }
+
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn set_orientation(&mut self, orientation: Orientation) -> Result<()> {
+        self.orientation = orientation;
+        Ok(())
+    }
+}
+
+/// Size of the texture holding `width` x `height` frames, given the current
+/// one: unchanged while frames fit, otherwise grown to whole buckets
+fn texture_size(current: (u32, u32), width: u32, height: u32) -> (u32, u32) {
+    if width <= current.0 && height <= current.1 {
+        return current;
+    }
+    let bucket = |size: u32| size.div_ceil(TEXTURE_BUCKET) * TEXTURE_BUCKET;
+    (current.0.max(bucket(width)), current.1.max(bucket(height)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_textures_grow_in_buckets() {
+        assert_eq!(texture_size((0, 0), 320, 200), (512, 256));
+        // Frames that fit reuse the texture, however much smaller
+        assert_eq!(texture_size((512, 256), 300, 256), (512, 256));
+        assert_eq!(texture_size((512, 256), 1, 1), (512, 256));
+        // Growing one side keeps the other
+        assert_eq!(texture_size((512, 256), 400, 257), (512, 512));
+    }
+}
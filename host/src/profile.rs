@@ -0,0 +1,211 @@
+//! Frame Profiling
+//!
+//! With `--profile FILE`, the host records where each frame's time goes:
+//! dispatching events to the guest, its `update`, copying its frame out of
+//! the host interface (including debug views and filters), uploading the
+//! frame to the GPU, and presenting it. On exit the spans are written as a
+//! Chrome trace, which chrome://tracing and https://ui.perfetto.dev open,
+//! with one track per app.
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most spans kept, about an hour of one app at 60 FPS
+const MAX_SPANS: usize = 1_000_000;
+
+/// Process id of every trace event; tracks are told apart by thread id
+const PROCESS_ID: u32 = 1;
+
+/// An event of the Chrome trace event format
+#[derive(Serialize)]
+struct TraceEvent {
+    name: String,
+    /// Phase: `X` for a complete span, `M` for metadata
+    ph: &'static str,
+    /// Start, in microseconds since the profiler was created
+    ts: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<f64>,
+    pid: u32,
+    tid: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<serde_json::Value>,
+}
+
+/// A trace file: its events and the unit viewers show times in
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile<'a> {
+    trace_events: &'a [TraceEvent],
+    display_time_unit: &'static str,
+}
+
+struct Trace {
+    events: Vec<TraceEvent>,
+    tracks: u32,
+    /// Spans not kept once `MAX_SPANS` was reached
+    dropped: u64,
+}
+
+/// Collects the spans of every app, shared between them
+#[derive(Clone)]
+pub struct Profiler {
+    epoch: Instant,
+    trace: Arc<Mutex<Trace>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let process_name = TraceEvent {
+            name: "process_name".to_string(),
+            ph: "M",
+            ts: 0.0,
+            dur: None,
+            pid: PROCESS_ID,
+            tid: 0,
+            args: Some(serde_json::json!({ "name": "wapps" })),
+        };
+        Self {
+            epoch: Instant::now(),
+            trace: Arc::new(Mutex::new(Trace {
+                events: vec![process_name],
+                tracks: 0,
+                dropped: 0,
+            })),
+        }
+    }
+
+    /// A new track for the spans of the app named `name`
+    pub fn track(&self, name: &str) -> ProfileTrack {
+        let mut trace = self.trace.lock().unwrap_or_else(|e| e.into_inner());
+        trace.tracks += 1;
+        let tid = trace.tracks;
+        trace.events.push(TraceEvent {
+            name: "thread_name".to_string(),
+            ph: "M",
+            ts: 0.0,
+            dur: None,
+            pid: PROCESS_ID,
+            tid,
+            args: Some(serde_json::json!({ "name": name })),
+        });
+        ProfileTrack {
+            profiler: self.clone(),
+            tid,
+        }
+    }
+
+    /// Write the trace collected so far to `path`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let trace = self.trace.lock().unwrap_or_else(|e| e.into_inner());
+        let file = File::create(path)
+            .with_context(|| format!("Could not create profile: {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        let file = TraceFile {
+            trace_events: &trace.events,
+            display_time_unit: "ms",
+        };
+        serde_json::to_writer(&mut out, &file).context("Failed to serialize profile")?;
+        out.flush()
+            .with_context(|| format!("Could not write profile: {}", path.display()))?;
+        if trace.dropped > 0 {
+            warn!(
+                "Profile truncated: {} spans after the first {} were dropped",
+                trace.dropped, MAX_SPANS
+            );
+        }
+        info!("Profile written to {}", path.display());
+        Ok(())
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records the spans of one app
+#[derive(Clone)]
+pub struct ProfileTrack {
+    profiler: Profiler,
+    tid: u32,
+}
+
+impl ProfileTrack {
+    /// Record a span `name` that started at `start` and lasted `duration`
+    pub fn span(&self, name: &str, start: Instant, duration: Duration) {
+        let mut trace = self
+            .profiler
+            .trace
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if trace.events.len() >= MAX_SPANS {
+            trace.dropped += 1;
+            return;
+        }
+        let micros = |duration: Duration| duration.as_secs_f64() * 1_000_000.0;
+        trace.events.push(TraceEvent {
+            name: name.to_string(),
+            ph: "X",
+            ts: micros(start.saturating_duration_since(self.profiler.epoch)),
+            dur: Some(micros(duration)),
+            pid: PROCESS_ID,
+            tid: self.tid,
+            args: None,
+        });
+    }
+}
+
+/// Writes the profile when dropped, so that it is saved on every exit path
+pub struct SaveProfileOnDrop {
+    profiler: Profiler,
+    path: PathBuf,
+}
+
+impl SaveProfileOnDrop {
+    pub fn new(profiler: Profiler, path: PathBuf) -> Self {
+        Self { profiler, path }
+    }
+}
+
+impl Drop for SaveProfileOnDrop {
+    fn drop(&mut self) {
+        if let Err(e) = self.profiler.save(&self.path) {
+            warn!("Failed to save profile: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans_are_written_per_track() {
+        let profiler = Profiler::new();
+        let track = profiler.track("Life");
+        let start = profiler.epoch + Duration::from_millis(2);
+        track.span("update", start, Duration::from_micros(1500));
+
+        let path = std::env::temp_dir().join(format!("wapps-profile-{}.json", std::process::id()));
+        profiler.save(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events[1]["args"]["name"], "Life");
+        assert_eq!(events[2]["name"], "update");
+        assert_eq!(events[2]["ph"], "X");
+        assert_eq!(events[2]["ts"], 2000.0);
+        assert_eq!(events[2]["dur"], 1500.0);
+        assert_eq!(events[2]["tid"], events[1]["tid"]);
+    }
+}
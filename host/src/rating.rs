@@ -0,0 +1,125 @@
+//! Content Rating
+//!
+//! Packages may declare a minimum recommended age in their metadata
+//! (`"age_rating": 12`). A parental gate configured on the host either asks
+//! for confirmation before running packages rated above its limit, or refuses
+//! to run them at all — for kiosks in schools and similar deployments.
+//! Packages without a rating are treated as above any limit.
+
+#[cfg(feature = "window")]
+use anyhow::Context;
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use log::info;
+#[cfg(feature = "window")]
+use sdl2::messagebox::{
+    show_message_box, ButtonData, ClickedButton, MessageBoxButtonFlag, MessageBoxFlag,
+};
+
+/// What to do with packages rated above the configured limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GatePolicy {
+    /// Ask for confirmation before running them
+    Confirm,
+    /// Refuse to run them
+    Block,
+}
+
+/// Host-side limit on the content rating of packages it runs
+#[derive(Debug, Clone, Copy)]
+pub struct ParentalGate {
+    max_rating: u8,
+    policy: GatePolicy,
+}
+
+impl ParentalGate {
+    pub fn new(max_rating: u8, policy: GatePolicy) -> Self {
+        Self { max_rating, policy }
+    }
+
+    /// Whether a package with `rating` may run without confirmation
+    pub fn permits(&self, rating: Option<u8>) -> bool {
+        rating.is_some_and(|rating| rating <= self.max_rating)
+    }
+
+    /// Check that `name` may run, asking for confirmation if the policy requires it
+    pub fn check(&self, name: &str, rating: Option<u8>) -> Result<()> {
+        if self.permits(rating) {
+            return Ok(());
+        }
+
+        let reason = match rating {
+            Some(rating) => format!("{:?} is rated {}+", name, rating),
+            None => format!("{:?} has no content rating", name),
+        };
+        match self.policy {
+            GatePolicy::Block => bail!(
+                "{}, above the allowed rating of {}+",
+                reason,
+                self.max_rating
+            ),
+            GatePolicy::Confirm => {
+                let message = format!(
+                    "{}, above the allowed rating of {}+.\nRun it anyway?",
+                    reason, self.max_rating
+                );
+                if !confirm("Content rating", &message)? {
+                    bail!("{}; declined at the parental gate", reason);
+                }
+                info!("{}; allowed after confirmation", reason);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Show a modal Allow/Cancel dialog, returning whether Allow was chosen
+#[cfg(feature = "window")]
+pub fn confirm(title: &str, message: &str) -> Result<bool> {
+    const ALLOW: i32 = 1;
+    let buttons = [
+        ButtonData {
+            flags: MessageBoxButtonFlag::ESCAPEKEY_DEFAULT
+                | MessageBoxButtonFlag::RETURNKEY_DEFAULT,
+            button_id: 0,
+            text: "Cancel",
+        },
+        ButtonData {
+            flags: MessageBoxButtonFlag::NOTHING,
+            button_id: ALLOW,
+            text: "Allow",
+        },
+    ];
+    let clicked = show_message_box(
+        MessageBoxFlag::WARNING,
+        &buttons,
+        title,
+        message,
+        None,
+        None,
+    )
+    .map_err(|e| anyhow::anyhow!("{:?}", e))
+    .context("Failed to show confirmation dialog")?;
+    Ok(matches!(clicked, ClickedButton::CustomButton(button) if button.button_id == ALLOW))
+}
+
+/// Without a window there is nobody to ask
+#[cfg(not(feature = "window"))]
+pub fn confirm(title: &str, _message: &str) -> Result<bool> {
+    bail!("Cannot ask for confirmation without a window: {}", title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permits() {
+        let gate = ParentalGate::new(12, GatePolicy::Block);
+        assert!(gate.permits(Some(7)));
+        assert!(gate.permits(Some(12)));
+        assert!(!gate.permits(Some(16)));
+        assert!(!gate.permits(None));
+        assert!(gate.check("Life", Some(16)).is_err());
+    }
+}
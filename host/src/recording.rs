@@ -0,0 +1,564 @@
+//! Session Recording and Replay
+//!
+//! Records every nondeterministic input a guest observes — frame `dt` values,
+//! input events, WASI random bytes, clock readings, present times and audio
+//! queue lengths — into a session log.
+//! Replaying the log feeds the guest exactly the same values in the same order,
+//! so a deterministic guest reproduces the recorded session bit for bit and
+//! developers can stop at the exact frame a bug appeared. Every
+//! `SNAPSHOT_INTERVAL` frames, a digest of guest memory is recorded too; a
+//! replay compares its own and reports the first checkpoint where the guest
+//! diverged from the recording.
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock, RngCore, WasiCtxBuilder};
+
+use crate::events::TimedEvent;
+use crate::wasi_policy::{ClockPolicy, VirtualTime, WasiPolicy};
+
+/// Version of the session log format
+const SESSION_LOG_VERSION: u32 = 2;
+
+/// Frames between memory snapshots
+pub const SNAPSHOT_INTERVAL: usize = 300;
+
+/// Inputs delivered to the guest for one frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameRecord {
+    /// Delta time passed to `update`
+    pub dt: f64,
+    /// Events dispatched before `update`, with their timestamps
+    pub events: Vec<TimedEvent>,
+}
+
+/// Everything nondeterministic a guest observed during a session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionLog {
+    pub version: u32,
+    pub frames: Vec<FrameRecord>,
+    /// Bytes returned by the WASI secure random source, in order
+    pub random: Vec<u8>,
+    /// Bytes returned by the WASI insecure random source, in order
+    pub insecure_random: Vec<u8>,
+    /// Wall clock readings in nanoseconds since the Unix epoch, in order
+    pub wall_clock: Vec<u64>,
+    /// Monotonic clock readings in nanoseconds, in order
+    pub monotonic_clock: Vec<u64>,
+    /// Present times passed to `on_present` in microseconds, in order
+    #[serde(default)]
+    pub present_times: Vec<u64>,
+    /// Values returned by `wapps::get_audio_queued_frames`, in order
+    #[serde(default)]
+    pub audio_queued_frames: Vec<u64>,
+    /// Values returned by `wapps::now_micros`, in order
+    #[serde(default)]
+    pub now_micros: Vec<u64>,
+    /// Digests of guest memory taken every `SNAPSHOT_INTERVAL` frames
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+}
+
+/// Digest of guest memory after a frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Number of frames run before the snapshot
+    pub frame: usize,
+    /// SHA-256 of the guest's linear memory, in hex
+    pub memory_sha256: String,
+}
+
+/// Read positions into each stream of a log being replayed
+#[derive(Debug, Default)]
+struct Cursors {
+    frame: usize,
+    random: usize,
+    insecure_random: usize,
+    wall_clock: usize,
+    monotonic_clock: usize,
+    present_times: usize,
+    audio_queued_frames: usize,
+    now_micros: usize,
+}
+
+struct SessionState {
+    log: SessionLog,
+    cursors: Cursors,
+    /// Whether a replay stream ran dry (reported once)
+    exhausted: bool,
+    /// Whether a replayed snapshot differed from the recorded one (reported once)
+    diverged: bool,
+}
+
+/// A session being recorded or replayed, shared with the guest's WASI sources
+#[derive(Clone)]
+pub struct Session {
+    replaying: bool,
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl Session {
+    /// Start recording a new session
+    pub fn record() -> Self {
+        Self::with_log(
+            SessionLog {
+                version: SESSION_LOG_VERSION,
+                ..Default::default()
+            },
+            false,
+        )
+    }
+
+    /// Load a recorded session for replay
+    pub fn replay(path: &Path) -> Result<Self> {
+        let data = fs::read(path)
+            .with_context(|| format!("Could not read session log: {}", path.display()))?;
+        let log: SessionLog =
+            serde_json::from_slice(&data).context("Failed to parse session log")?;
+        info!(
+            "Replaying {} frames from {}",
+            log.frames.len(),
+            path.display()
+        );
+        Self::from_log(log)
+    }
+
+    /// Replay a session log read from elsewhere, such as a replay file
+    pub fn from_log(log: SessionLog) -> Result<Self> {
+        if log.version != SESSION_LOG_VERSION {
+            bail!(
+                "Unsupported session log version: {}. This host supports version {} only.",
+                log.version,
+                SESSION_LOG_VERSION
+            );
+        }
+        Ok(Self::with_log(log, true))
+    }
+
+    fn with_log(log: SessionLog, replaying: bool) -> Self {
+        Self {
+            replaying,
+            state: Arc::new(Mutex::new(SessionState {
+                log,
+                cursors: Cursors::default(),
+                exhausted: false,
+                diverged: false,
+            })),
+        }
+    }
+
+    /// Whether this session replays a log rather than recording one
+    pub fn is_replaying(&self) -> bool {
+        self.replaying
+    }
+
+    /// Record the inputs of the frame about to run
+    pub fn record_frame(&self, dt: f64, events: &[TimedEvent]) {
+        self.lock().log.frames.push(FrameRecord {
+            dt,
+            events: events.to_vec(),
+        });
+    }
+
+    /// Next recorded frame, or `None` once the replay is complete
+    pub fn next_frame(&self) -> Option<FrameRecord> {
+        let mut state = self.lock();
+        let frame = state.log.frames.get(state.cursors.frame).cloned()?;
+        state.cursors.frame += 1;
+        Some(frame)
+    }
+
+    /// Number of frames recorded or replayed so far
+    pub fn frame_index(&self) -> usize {
+        let state = self.lock();
+        if self.replaying {
+            state.cursors.frame
+        } else {
+            state.log.frames.len()
+        }
+    }
+
+    /// Record a snapshot of guest `memory` after `frame` frames, or compare it
+    /// with the recorded one when replaying
+    pub fn snapshot(&self, frame: usize, memory: &[u8]) {
+        let memory_sha256: String = Sha256::digest(memory)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let mut state = self.lock();
+        if !self.replaying {
+            state.log.snapshots.push(Snapshot {
+                frame,
+                memory_sha256,
+            });
+            return;
+        }
+        let recorded = state
+            .log
+            .snapshots
+            .iter()
+            .find(|snapshot| snapshot.frame == frame);
+        if recorded.is_some_and(|snapshot| snapshot.memory_sha256 != memory_sha256)
+            && !state.diverged
+        {
+            state.diverged = true;
+            warn!(
+                "Guest memory differs from the recording after frame {}; the replay has diverged",
+                frame
+            );
+        }
+    }
+
+    /// Run `f` on the log recorded or being replayed
+    pub fn read_log<R>(&self, f: impl FnOnce(&SessionLog) -> R) -> R {
+        f(&self.lock().log)
+    }
+
+    /// Write the recorded log to `path`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let state = self.lock();
+        let json = serde_json::to_vec(&state.log).context("Failed to serialize session log")?;
+        fs::write(path, json)
+            .with_context(|| format!("Could not write session log: {}", path.display()))?;
+        info!(
+            "Recorded {} frames to {}",
+            state.log.frames.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Route the guest's WASI random and clock sources through this session
+    ///
+    /// Recordings capture the values the guest sees under `policy`, with
+    /// virtual clocks showing `time`.
+    pub fn configure_wasi(
+        &self,
+        builder: &mut WasiCtxBuilder,
+        policy: &WasiPolicy,
+        time: &VirtualTime,
+    ) {
+        builder
+            .secure_random(SessionRng::new(
+                self.clone(),
+                RandomStream::Secure,
+                policy.secure_rng(),
+            ))
+            .insecure_random(SessionRng::new(
+                self.clone(),
+                RandomStream::Insecure,
+                policy.insecure_rng(),
+            ))
+            .wall_clock(SessionWallClock {
+                session: self.clone(),
+                clock: policy.clock,
+                time: time.clone(),
+            })
+            .monotonic_clock(SessionMonotonicClock {
+                session: self.clone(),
+                clock: policy.clock,
+                origin: Instant::now(),
+                time: time.clone(),
+            });
+    }
+
+    /// Record a present time, or return the next recorded one when replaying
+    pub fn present_time(&self, live: impl FnOnce() -> u64) -> u64 {
+        self.clock_reading(ClockStream::Present, live)
+    }
+
+    /// Record an audio queue length, or return the next recorded one when replaying
+    pub fn audio_queued_frames(&self, live: impl FnOnce() -> u64) -> u64 {
+        self.clock_reading(ClockStream::AudioQueue, live)
+    }
+
+    /// Record a `now_micros` reading, or return the next recorded one when replaying
+    pub fn now_micros(&self, live: impl FnOnce() -> u64) -> u64 {
+        self.clock_reading(ClockStream::Micros, live)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        // Keep recording even if a guest thread panicked while holding the lock
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record a clock reading, or return the next recorded one when replaying
+    fn clock_reading(&self, clock: ClockStream, live: impl FnOnce() -> u64) -> u64 {
+        let mut state = self.lock();
+        let state = &mut *state;
+        if self.replaying {
+            let index = clock.cursor(&mut state.cursors);
+            let value = clock.values(&state.log).get(*index).copied();
+            *index += 1;
+            value.unwrap_or_else(|| {
+                state.report_exhausted(clock.name());
+                clock.values(&state.log).last().copied().unwrap_or(0)
+            })
+        } else {
+            let value = live();
+            clock.values_mut(&mut state.log).push(value);
+            value
+        }
+    }
+}
+
+/// Saves a recording when dropped, so the log survives a guest crash
+pub struct SaveOnDrop {
+    session: Session,
+    path: PathBuf,
+}
+
+impl SaveOnDrop {
+    pub fn new(session: Session, path: PathBuf) -> Self {
+        Self { session, path }
+    }
+}
+
+impl Drop for SaveOnDrop {
+    fn drop(&mut self) {
+        if let Err(e) = self.session.save(&self.path) {
+            warn!("Failed to save session recording: {:#}", e);
+        }
+    }
+}
+
+impl SessionState {
+    fn report_exhausted(&mut self, stream: &str) {
+        if !self.exhausted {
+            self.exhausted = true;
+            warn!(
+                "Replay ran out of recorded {} values; the guest has diverged from the recording",
+                stream
+            );
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum RandomStream {
+    Secure,
+    Insecure,
+}
+
+#[derive(Clone, Copy)]
+enum ClockStream {
+    Wall,
+    Monotonic,
+    Present,
+    AudioQueue,
+    Micros,
+}
+
+impl ClockStream {
+    fn name(self) -> &'static str {
+        match self {
+            ClockStream::Wall => "wall clock",
+            ClockStream::Monotonic => "monotonic clock",
+            ClockStream::Present => "present time",
+            ClockStream::AudioQueue => "audio queue length",
+            ClockStream::Micros => "now_micros",
+        }
+    }
+
+    fn cursor(self, cursors: &mut Cursors) -> &mut usize {
+        match self {
+            ClockStream::Wall => &mut cursors.wall_clock,
+            ClockStream::Monotonic => &mut cursors.monotonic_clock,
+            ClockStream::Present => &mut cursors.present_times,
+            ClockStream::AudioQueue => &mut cursors.audio_queued_frames,
+            ClockStream::Micros => &mut cursors.now_micros,
+        }
+    }
+
+    fn values(self, log: &SessionLog) -> &Vec<u64> {
+        match self {
+            ClockStream::Wall => &log.wall_clock,
+            ClockStream::Monotonic => &log.monotonic_clock,
+            ClockStream::Present => &log.present_times,
+            ClockStream::AudioQueue => &log.audio_queued_frames,
+            ClockStream::Micros => &log.now_micros,
+        }
+    }
+
+    fn values_mut(self, log: &mut SessionLog) -> &mut Vec<u64> {
+        match self {
+            ClockStream::Wall => &mut log.wall_clock,
+            ClockStream::Monotonic => &mut log.monotonic_clock,
+            ClockStream::Present => &mut log.present_times,
+            ClockStream::AudioQueue => &mut log.audio_queued_frames,
+            ClockStream::Micros => &mut log.now_micros,
+        }
+    }
+}
+
+/// WASI random source that records or replays the bytes it hands out
+struct SessionRng {
+    session: Session,
+    stream: RandomStream,
+    live: Box<dyn RngCore + Send>,
+}
+
+impl SessionRng {
+    fn new(session: Session, stream: RandomStream, live: Box<dyn RngCore + Send>) -> Self {
+        Self {
+            session,
+            stream,
+            live,
+        }
+    }
+}
+
+impl RngCore for SessionRng {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut state = self.session.lock();
+        let state = &mut *state;
+        let (bytes, cursor) = match self.stream {
+            RandomStream::Secure => (&mut state.log.random, &mut state.cursors.random),
+            RandomStream::Insecure => (
+                &mut state.log.insecure_random,
+                &mut state.cursors.insecure_random,
+            ),
+        };
+
+        if self.session.replaying {
+            let available = bytes.len().saturating_sub(*cursor).min(dest.len());
+            dest[..available].copy_from_slice(&bytes[*cursor..*cursor + available]);
+            dest[available..].fill(0);
+            *cursor += available;
+            if available < dest.len() {
+                state.report_exhausted("random");
+            }
+        } else {
+            self.live.fill_bytes(dest);
+            bytes.extend_from_slice(dest);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// WASI wall clock that records or replays its readings
+struct SessionWallClock {
+    session: Session,
+    clock: ClockPolicy,
+    time: VirtualTime,
+}
+
+impl HostWallClock for SessionWallClock {
+    fn resolution(&self) -> Duration {
+        self.clock.resolution()
+    }
+
+    fn now(&self) -> Duration {
+        let nanos = self
+            .session
+            .clock_reading(ClockStream::Wall, || self.clock.wall_now(&self.time));
+        Duration::from_nanos(nanos)
+    }
+}
+
+/// WASI monotonic clock that records or replays its readings
+struct SessionMonotonicClock {
+    session: Session,
+    clock: ClockPolicy,
+    origin: Instant,
+    time: VirtualTime,
+}
+
+impl HostMonotonicClock for SessionMonotonicClock {
+    fn resolution(&self) -> u64 {
+        self.clock.resolution().as_nanos() as u64
+    }
+
+    fn now(&self) -> u64 {
+        self.session.clock_reading(ClockStream::Monotonic, || {
+            self.clock.monotonic_now(self.origin, &self.time)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::GuestEvent;
+
+    const KEY_DOWN: TimedEvent = TimedEvent {
+        event: GuestEvent::KeyDown {
+            scancode: 44,
+            modifiers: 0,
+            repeat: false,
+        },
+        time: 1.5,
+    };
+
+    fn wall_clock(session: &Session) -> SessionWallClock {
+        SessionWallClock {
+            session: session.clone(),
+            clock: ClockPolicy::Precise,
+            time: VirtualTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_replay_returns_recorded_values() {
+        let recording = Session::record();
+        recording.record_frame(0.016, &[KEY_DOWN]);
+        let policy = WasiPolicy::default();
+        let mut rng = SessionRng::new(recording.clone(), RandomStream::Secure, policy.secure_rng());
+        let mut recorded_bytes = [0u8; 16];
+        rng.fill_bytes(&mut recorded_bytes);
+        let recorded_time = wall_clock(&recording).now();
+        assert_eq!(recording.now_micros(|| 1_500), 1_500);
+
+        let log = std::mem::take(&mut recording.lock().log);
+        let replay = Session::with_log(log, true);
+        let mut rng = SessionRng::new(replay.clone(), RandomStream::Secure, policy.secure_rng());
+        let mut replayed_bytes = [0u8; 16];
+        rng.fill_bytes(&mut replayed_bytes);
+
+        assert_eq!(replayed_bytes, recorded_bytes);
+        assert_eq!(wall_clock(&replay).now(), recorded_time);
+        assert_eq!(replay.now_micros(|| 9_000), 1_500);
+        assert_eq!(
+            replay.next_frame(),
+            Some(FrameRecord {
+                dt: 0.016,
+                events: vec![KEY_DOWN],
+            })
+        );
+        assert_eq!(replay.next_frame(), None);
+    }
+
+    #[test]
+    fn test_replay_reports_diverging_snapshots() {
+        let recording = Session::record();
+        recording.snapshot(300, b"memory");
+        let log = std::mem::take(&mut recording.lock().log);
+        assert_eq!(log.snapshots.len(), 1);
+
+        let replay = Session::with_log(log, true);
+        replay.snapshot(300, b"memory");
+        assert!(!replay.lock().diverged);
+        replay.snapshot(600, b"unrecorded");
+        assert!(!replay.lock().diverged);
+        replay.snapshot(300, b"changed");
+        assert!(replay.lock().diverged);
+    }
+}
@@ -0,0 +1,230 @@
+//! Package Registry
+//!
+//! `wapps install NAME` looks NAME up in a package index, downloads the
+//! package it lists, checks it against the SHA-256 in the index and that it
+//! loads on this host, then keeps it in the library under the user data
+//! directory. `wapps run NAME` runs an installed package and `wapps list`
+//! shows them. An index is a JSON document served over HTTP(S), given with
+//! `--index` or the `WAPPS_INDEX` environment variable:
+//!
+//! ```json
+//! {"packages": [{"name": "life", "version": "1.2.0", "url": "life.wapp", "sha256": "…"}]}
+//! ```
+//!
+//! Package URLs may be relative to the index.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::info;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::download;
+use crate::loader;
+use crate::storage;
+
+/// Environment variable naming the index when `--index` is not given
+const INDEX_VAR: &str = "WAPPS_INDEX";
+
+/// A package index
+#[derive(Debug, Deserialize)]
+struct Index {
+    packages: Vec<IndexEntry>,
+}
+
+/// A package listed in an index
+#[derive(Debug, Deserialize)]
+struct IndexEntry {
+    name: String,
+    #[serde(default)]
+    version: String,
+    /// Package URL, absolute or relative to the index
+    url: String,
+    /// Hex SHA-256 of the package
+    sha256: String,
+}
+
+/// Arguments of `wapps install`
+#[derive(Args, Debug)]
+pub struct InstallArgs {
+    /// Name of the package in the index
+    #[arg(value_name = "NAME")]
+    name: String,
+
+    /// URL of the package index (defaults to the WAPPS_INDEX environment
+    /// variable)
+    #[arg(long, value_name = "URL")]
+    index: Option<String>,
+}
+
+/// Arguments of `wapps run`
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Name of an installed package
+    #[arg(value_name = "NAME")]
+    pub name: String,
+}
+
+/// Run `wapps install`
+pub fn run_install(args: &InstallArgs) -> Result<()> {
+    check_name(&args.name)?;
+    let index_url = match &args.index {
+        Some(url) => url.clone(),
+        None => std::env::var(INDEX_VAR)
+            .with_context(|| format!("No package index: pass --index or set {}", INDEX_VAR))?,
+    };
+    let index: Index = serde_json::from_slice(&download::download(&index_url)?)
+        .with_context(|| format!("Invalid package index: {}", index_url))?;
+    let Some(entry) = index.packages.iter().find(|entry| entry.name == args.name) else {
+        bail!("No package named {:?} in {}", args.name, index_url);
+    };
+
+    let url = resolve_url(&index_url, &entry.url);
+    let bytes = download::download(&url)?;
+    let digest: String = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if !digest.eq_ignore_ascii_case(entry.sha256.trim()) {
+        bail!(
+            "{} does not match the index: its SHA-256 is {}, expected {}",
+            url,
+            digest,
+            entry.sha256
+        );
+    }
+
+    // Only packages that load on this host replace an installed version
+    let metadata = loader::parse_package(&bytes)
+        .with_context(|| format!("{} is not a package this host can run", url))?
+        .metadata;
+    let path = library_dir()?.join(format!("{}.wapp", args.name));
+    download::store(&path, &bytes)?;
+    info!(
+        "Installed {} {} ({:?}) to {}",
+        args.name,
+        if metadata.version.is_empty() {
+            &entry.version
+        } else {
+            &metadata.version
+        },
+        metadata.name,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Path of the installed package `name`, for `wapps run`
+pub fn installed_path(name: &str) -> Result<PathBuf> {
+    check_name(name)?;
+    let path = library_dir()?.join(format!("{}.wapp", name));
+    if !path.is_file() {
+        bail!(
+            "{:?} is not installed; install it with `wapps install {}`",
+            name,
+            name
+        );
+    }
+    Ok(path)
+}
+
+/// Run `wapps list`
+pub fn run_list() -> Result<()> {
+    let dir = library_dir()?;
+    let mut packages: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wapp"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    if packages.is_empty() {
+        println!("No packages installed; install one with `wapps install NAME`");
+        return Ok(());
+    }
+    packages.sort();
+    for path in packages {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        match loader::load_wapp(&path) {
+            Ok((_, metadata)) => {
+                print!("{:<20} {:<10} {}", name, metadata.version, metadata.name);
+                if let Some(author) = &metadata.author {
+                    print!(" by {}", author);
+                }
+                println!();
+                if !metadata.description.is_empty() {
+                    println!("{:<20} {}", "", metadata.description);
+                }
+            }
+            Err(e) => println!("{:<20} unreadable: {:#}", name, e),
+        }
+    }
+    Ok(())
+}
+
+/// Directory holding installed packages
+fn library_dir() -> Result<PathBuf> {
+    Ok(storage::data_dir()
+        .context("No data directory to install packages to")?
+        .join("library"))
+}
+
+/// Check that `name` is usable as a file name in the library
+pub fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+    if !valid {
+        bail!(
+            "Invalid package name {:?}: use lowercase letters, digits, - and _",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// `url` resolved against the URL of the index listing it
+fn resolve_url(index_url: &str, url: &str) -> String {
+    if download::is_url(url) {
+        return url.to_string();
+    }
+    let (scheme, rest) = index_url.split_once("://").unwrap_or(("https", index_url));
+    let base = match url.strip_prefix('/') {
+        // Relative to the server's root
+        Some(_) => rest.split('/').next().unwrap_or(rest),
+        None => rest.rsplit_once('/').map_or(rest, |(base, _)| base),
+    };
+    format!("{}://{}/{}", scheme, base, url.trim_start_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_and_urls() {
+        assert!(check_name("life").is_ok());
+        assert!(check_name("space_war-2").is_ok());
+        assert!(check_name("../life").is_err());
+        assert!(check_name("Life").is_err());
+        assert!(check_name("").is_err());
+
+        let index = "https://example.com/apps/index.json";
+        assert_eq!(
+            resolve_url(index, "life.wapp"),
+            "https://example.com/apps/life.wapp"
+        );
+        assert_eq!(
+            resolve_url(index, "/pkg/life.wapp"),
+            "https://example.com/pkg/life.wapp"
+        );
+        assert_eq!(
+            resolve_url(index, "http://cdn.example.com/life.wapp"),
+            "http://cdn.example.com/life.wapp"
+        );
+    }
+}
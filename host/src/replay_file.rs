@@ -0,0 +1,235 @@
+//! Shareable Replays
+//!
+//! `--save-replay FILE` records a session like `--record` and writes it as a
+//! `.wappreplay` file others can watch with `wapps replay FILE`. Besides the
+//! session log (inputs, clock readings, random bytes and memory snapshots),
+//! the file names the package and holds its SHA-256, so a replay never starts
+//! against a different package, and keeps the `--random-seed` and `--clock`
+//! overrides so the replaying host resolves the same WASI policy.
+//!
+//! Layout: the magic `WREPLAY\0`, the format version (u32 LE), the length of
+//! the JSON document (u32 LE), then the document deflated.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::deflate;
+use crate::recording::{Session, SessionLog};
+use crate::wasi_policy::ClockPolicy;
+
+/// Magic bytes of a replay file
+const REPLAY_MAGIC: &[u8; 8] = b"WREPLAY\0";
+
+/// Version of the replay file format
+const REPLAY_VERSION: u32 = 1;
+
+/// Package a replay was recorded with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageRef {
+    /// File name of the package, looked up next to the replay by default
+    pub file_name: String,
+    /// SHA-256 of the package file, in hex
+    pub sha256: String,
+}
+
+/// Contents of a replay file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub package: PackageRef,
+    /// Seed given with `--random-seed`, if any
+    pub random_seed: Option<u64>,
+    /// Clock precision given with `--clock`, if any
+    pub clock: Option<ClockPolicy>,
+    pub session: SessionLog,
+}
+
+impl PackageRef {
+    /// Describe the package file at `path`
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = fs::read(path)
+            .with_context(|| format!("Could not read package: {}", path.display()))?;
+        Ok(Self {
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            sha256: sha256_hex(&data),
+        })
+    }
+}
+
+impl ReplayFile {
+    /// Write the replay to `path`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, encode(self)?)
+            .with_context(|| format!("Could not write replay: {}", path.display()))?;
+        info!(
+            "Saved a replay of {} frames to {}",
+            self.session.frames.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Read the replay at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            fs::read(path).with_context(|| format!("Could not read replay: {}", path.display()))?;
+        decode(&data).with_context(|| format!("Invalid replay file: {}", path.display()))
+    }
+}
+
+fn encode(replay: &ReplayFile) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec(replay).context("Failed to serialize replay")?;
+    let mut data = REPLAY_MAGIC.to_vec();
+    data.extend_from_slice(&REPLAY_VERSION.to_le_bytes());
+    data.extend_from_slice(
+        &u32::try_from(json.len())
+            .context("Replay too large")?
+            .to_le_bytes(),
+    );
+    data.extend_from_slice(&deflate::compress(&json));
+    Ok(data)
+}
+
+fn decode(data: &[u8]) -> Result<ReplayFile> {
+    if data.len() < 16 || !data.starts_with(REPLAY_MAGIC) {
+        bail!("not a .wappreplay file");
+    }
+    let version = u32::from_le_bytes(data[8..12].try_into().expect("4-byte slice"));
+    if version != REPLAY_VERSION {
+        bail!(
+            "unsupported replay version {}; this host supports version {} only",
+            version,
+            REPLAY_VERSION
+        );
+    }
+    let json_len = u32::from_le_bytes(data[12..16].try_into().expect("4-byte slice")) as usize;
+    let json = deflate::decompress(&data[16..], json_len)?;
+    if json.len() != json_len {
+        bail!("replay is truncated");
+    }
+    serde_json::from_slice(&json).context("failed to parse replay")
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Saves a shareable replay when dropped, so it survives a guest crash
+pub struct SaveReplayOnDrop {
+    path: PathBuf,
+    session: Session,
+    package: PackageRef,
+    random_seed: Option<u64>,
+    clock: Option<ClockPolicy>,
+}
+
+impl SaveReplayOnDrop {
+    /// Prepare to save `session`, recorded running the package at `package`
+    pub fn new(
+        path: PathBuf,
+        session: Session,
+        package: &Path,
+        random_seed: Option<u64>,
+        clock: Option<ClockPolicy>,
+    ) -> Result<Self> {
+        Ok(Self {
+            path,
+            session,
+            package: PackageRef::read(package)?,
+            random_seed,
+            clock,
+        })
+    }
+}
+
+impl Drop for SaveReplayOnDrop {
+    fn drop(&mut self) {
+        let replay = ReplayFile {
+            package: self.package.clone(),
+            random_seed: self.random_seed,
+            clock: self.clock,
+            session: self.session.read_log(SessionLog::clone),
+        };
+        if let Err(e) = replay.save(&self.path) {
+            warn!("Failed to save replay: {:#}", e);
+        }
+    }
+}
+
+/// Arguments of `wapps replay`
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// Replay file saved with `--save-replay`
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Package to replay, instead of the one named in the replay, looked up
+    /// next to the replay file
+    #[arg(long, value_name = "FILE")]
+    pub package: Option<PathBuf>,
+
+    /// Stop replaying after this many frames, keeping the last frame on screen
+    #[arg(long, value_name = "FRAME")]
+    pub until: Option<usize>,
+}
+
+/// Load the replay named by `args` and find its package, checking that it is
+/// the one the replay was recorded with
+pub fn open(args: &ReplayArgs) -> Result<(PathBuf, ReplayFile)> {
+    let replay = ReplayFile::load(&args.file)?;
+    let package = match &args.package {
+        Some(package) => package.clone(),
+        None => args
+            .file
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(&replay.package.file_name),
+    };
+    if PackageRef::read(&package)?.sha256 != replay.package.sha256 {
+        bail!(
+            "{} is not the package this replay was recorded with",
+            package.display()
+        );
+    }
+    Ok((package, replay))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_files_round_trip() {
+        let replay = ReplayFile {
+            package: PackageRef {
+                file_name: "life.wapp".to_string(),
+                sha256: sha256_hex(b"package"),
+            },
+            random_seed: Some(42),
+            clock: Some(ClockPolicy::Coarse),
+            session: SessionLog {
+                version: 2,
+                random: vec![7; 64],
+                ..Default::default()
+            },
+        };
+        let data = encode(&replay).unwrap();
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.package, replay.package);
+        assert_eq!(decoded.random_seed, Some(42));
+        assert_eq!(decoded.session.random, vec![7; 64]);
+
+        assert!(decode(&data[..20]).is_err());
+        assert!(decode(b"WAPP").is_err());
+    }
+}
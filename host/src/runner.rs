@@ -0,0 +1,161 @@
+//! Embedding
+//!
+//! `Runner` runs a package inside another Rust application: an editor, a game
+//! engine, a test harness. There is no window, audio or input of its own; the
+//! embedder passes in the events it wants the guest to see, advances it with
+//! `tick` and draws the frames it gets back however it likes. Like
+//! `--headless`, apps get in-memory storage and are never asked permissions,
+//! so only what their package grants without asking is available.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::events::{GuestEvent, TimedEvent};
+use crate::host_interface::HostInterface;
+use crate::loader::{self, WappMetadata};
+use crate::runtime::WasmRuntime;
+use crate::storage::AppStorage;
+use crate::wasi_policy::WasiPolicy;
+use crate::wasm_features::DisabledFeatures;
+
+/// The latest frame presented by the guest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA pixels, row by row; empty until the guest presents a frame
+    pub pixels: Vec<u8>,
+}
+
+/// A running app, advanced one frame at a time by the embedder
+pub struct Runner {
+    runtime: WasmRuntime,
+    metadata: WappMetadata,
+    frame: Frame,
+    /// Seconds of guest time so far, the timestamp of events
+    time: f64,
+}
+
+impl Runner {
+    /// Load and start the package at `path`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (wasm_bytes, metadata) = loader::load_wapp(path)
+            .with_context(|| format!("Failed to load WAPP file: {:?}", path))?;
+        Self::new(&wasm_bytes, metadata)
+    }
+
+    /// Start the guest module `wasm_bytes` of a package described by `metadata`
+    pub fn new(wasm_bytes: &[u8], metadata: WappMetadata) -> Result<Self> {
+        let policy = WasiPolicy::resolve(&metadata.wasi, None, None);
+        Self::with_policy(wasm_bytes, metadata, &policy)
+    }
+
+    /// Start the guest with `policy` instead of the package's WASI settings
+    pub(crate) fn with_policy(
+        wasm_bytes: &[u8],
+        metadata: WappMetadata,
+        policy: &WasiPolicy,
+    ) -> Result<Self> {
+        let mut host_interface = HostInterface::new();
+        host_interface.set_app_info(metadata.name.clone(), metadata.version.clone());
+        host_interface.set_strings(metadata.strings.clone());
+        host_interface.set_config(metadata.config.clone());
+        host_interface.set_storage(AppStorage::in_memory());
+        host_interface.set_capabilities(metadata.capabilities.clone());
+        host_interface.set_disabled_features(DisabledFeatures::new(
+            metadata.disabled_features.iter().copied(),
+        ));
+        let runtime = WasmRuntime::new(
+            wasm_bytes,
+            None,
+            host_interface,
+            std::slice::from_ref(&metadata.name),
+            None,
+            policy,
+            false,
+            None,
+        )
+        .context("Failed to initialize WASM runtime")?;
+        Ok(Self {
+            runtime,
+            metadata,
+            frame: Frame::default(),
+            time: 0.0,
+        })
+    }
+
+    /// The package's metadata
+    pub fn metadata(&self) -> &WappMetadata {
+        &self.metadata
+    }
+
+    /// The guest's latest frame, as returned by the last `tick`
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Abort guest calls that run longer than `budget`
+    #[cfg(feature = "testing")]
+    pub(crate) fn set_frame_budget(&mut self, budget: Option<std::time::Duration>) {
+        self.runtime.set_frame_budget(budget);
+    }
+
+    /// Deliver `events` to the guest, advance it by `dt` seconds and return
+    /// its latest frame
+    pub fn tick(&mut self, dt: f64, events: &[GuestEvent]) -> Result<&Frame> {
+        let events: Vec<TimedEvent> = events
+            .iter()
+            .map(|event| TimedEvent {
+                event: event.clone(),
+                time: self.time,
+            })
+            .collect();
+        self.time += dt;
+        self.runtime.run_frame(&events, dt)?;
+
+        let frame = &mut self.frame;
+        self.runtime.with_frame_data(|width, height, pixels| {
+            frame.width = width as u32;
+            frame.height = height as u32;
+            frame.pixels.clear();
+            frame.pixels.extend_from_slice(pixels);
+        });
+        Ok(&self.frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module_cache;
+
+    #[test]
+    fn test_ticks_deliver_events_and_return_frames() {
+        module_cache::disable();
+        // Draws one pixel whose red channel is the last key released
+        let guest = r#"(module
+            (import "wapps" "update_frame" (func $update_frame (param i32 i32 i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 0) "\00\00\00\ff")
+            (func (export "on_key_up") (param i32)
+                (i32.store8 (i32.const 0) (local.get 0)))
+            (func (export "update") (param f64)
+                (call $update_frame (i32.const 1) (i32.const 1) (i32.const 0))))"#;
+        let metadata = WappMetadata {
+            name: "Pixel".to_string(),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(guest.as_bytes(), metadata).unwrap();
+        assert_eq!(runner.metadata().name, "Pixel");
+
+        let frame = runner.tick(1.0 / 60.0, &[]).unwrap();
+        assert_eq!((frame.width, frame.height), (1, 1));
+        assert_eq!(frame.pixels, [0, 0, 0, 255]);
+
+        let frame = runner
+            .tick(1.0 / 60.0, &[GuestEvent::KeyUp { scancode: 42 }])
+            .unwrap();
+        assert_eq!(frame.pixels, [42, 0, 0, 255]);
+    }
+}
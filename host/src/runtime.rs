@@ -2,15 +2,61 @@
 //!
 //! Manages the Wasmtime engine, WASI context, and module instantiation.
 //! Configures minimal WASI capabilities for security (clock, random, stdio only).
+//!
+//! Guest exports are called in a fixed order. `get_framebuffer` runs during
+//! instantiation, then `init` once, before any event or `update`: with the
+//! window size when the host calls it, or with 0x0 from the first
+//! `run_frame` of windowless hosts. Each frame delivers its events, in the
+//! order they happened, then the timers that fired (`on_timer`), before
+//! `update`. `shutdown` runs last, when the host exits or closes the window,
+//! and nothing is called after it. Crashed guests get no `shutdown`;
+//! restarted ones are initialized again, while guests whose memory is
+//! restored keep the initialized state it holds.
 
 use anyhow::{bail, Context, Result};
 use log::{debug, warn};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use wasmtime::*;
 use wasmtime_wasi::preview1::{self, WasiP1Ctx};
-use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+
+use crate::audio::{AudioFormat, CapturedAudio};
+use crate::canvas::{self, CanvasDraw};
+use crate::capabilities::Capability;
+use crate::display::{CursorSettings, DisplayConstraints, ScalingMode, WindowSize};
+use crate::display_adjust::Adjustment;
+use crate::events::{GuestEvent, TimedEvent};
+use crate::guest_alloc::GuestAllocator;
+use crate::host_interface::{
+    self, CaptureRequest, FileRequest, GamepadRequest, HostInterface, OverlayUpdate,
+};
+use crate::http;
+use crate::images::ImageDraw;
+use crate::memory_limit::{self, MemoryLimiter};
+use crate::module_cache;
+use crate::permissions::{FirstUse, Permission};
+use crate::pixel_format::{self, PixelFormat};
+use crate::recording::Session;
+use crate::save_state::{GlobalValue, SaveState, SnapshotRequest};
+use crate::scores;
+use crate::storage;
+use crate::text::{self, TextDraw};
+use crate::timers;
+use crate::wasi_policy::{ClockPolicy, VirtualTime, WasiPolicy};
+use crate::wasi_threads::{self, GuestMemory, WasiThreads};
+use crate::watchdog::{self, Watchdog};
+use crate::ws;
 
-use crate::host_interface::HostInterface;
+/// WASI error number returned by denied WASI calls (`ACCES`)
+const WASI_ERRNO_ACCES: i32 = 2;
+
+/// Capacity passed to `on_describe`: one WebAssembly page
+const DESCRIBE_BUFFER_SIZE: i32 = 65536;
 
 /// Combined state for the WASM store
 pub struct StoreState {
@@ -18,28 +64,2233 @@ pub struct StoreState {
     wasi: WasiP1Ctx,
     /// Host interface for graphics
     host: Arc<Mutex<HostInterface>>,
+    /// Session recording or replaying host values read by the guest
+    session: Option<Session>,
+    /// Cap on the guest's linear memory
+    limiter: MemoryLimiter,
+    /// Time shown by virtual clocks, advanced by each update's dt
+    time: VirtualTime,
+    /// Clock policy `wapps::now_micros` follows, like the WASI clocks
+    clock: ClockPolicy,
+    /// When the guest started, the origin of `wapps::now_micros`
+    started: Instant,
+    /// Threads started via `wasi::thread-spawn`, with `--enable-threads`
+    threads: Option<Arc<WasiThreads>>,
 }
 
 impl StoreState {
-    fn new(host: HostInterface) -> Self {
-        // Configure minimal WASI - security restricted:
-        // - Inherit stdout/stderr for debugging
-        // - Allow basic time/random access
-        // - NO file system access
-        // - NO network access
-        // - NO environment variables
-        let wasi = WasiCtxBuilder::new()
-            .inherit_stdout()
-            .inherit_stderr()
-            // Note: clock and random are enabled by default in WASI
-            // File system is NOT inherited - sandboxed
-            .build_p1();
-
+    fn new(
+        host: HostInterface,
+        args: &[String],
+        session: Option<&Session>,
+        policy: &WasiPolicy,
+    ) -> Self {
+        let time = VirtualTime::default();
         Self {
-            wasi,
+            wasi: wasi_ctx(host.files_dir(), args, session, policy, &time),
             host: Arc::new(Mutex::new(host)),
+            session: session.cloned(),
+            limiter: MemoryLimiter::default(),
+            time,
+            clock: policy.clock,
+            started: Instant::now(),
+            threads: None,
+        }
+    }
+
+    /// State of a thread the guest started, sharing the main thread's host
+    /// interface and clock with a WASI context of its own
+    fn for_thread(
+        host: Arc<Mutex<HostInterface>>,
+        args: &[String],
+        policy: &WasiPolicy,
+        time: VirtualTime,
+        started: Instant,
+        threads: Arc<WasiThreads>,
+    ) -> Self {
+        let files_dir = host
+            .lock()
+            .ok()
+            .and_then(|host| host.files_dir().map(Path::to_path_buf));
+        Self {
+            wasi: wasi_ctx(files_dir.as_deref(), args, None, policy, &time),
+            host,
+            session: None,
+            limiter: MemoryLimiter::default(),
+            time,
+            clock: policy.clock,
+            started,
+            threads: Some(threads),
+        }
+    }
+}
+
+/// WASI context of a guest with `args` and access to `files_dir`, whose
+/// clocks and random values follow `policy` or `session`
+fn wasi_ctx(
+    files_dir: Option<&Path>,
+    args: &[String],
+    session: Option<&Session>,
+    policy: &WasiPolicy,
+    time: &VirtualTime,
+) -> WasiP1Ctx {
+    // Configure minimal WASI - security restricted:
+    // - Pass launch arguments (e.g. deep-link parameters)
+    // - Inherit stdout/stderr for debugging
+    // - Allow time/random access, as restricted by the clock/random policy
+    // - NO file system access, except the app's own directory if allowed
+    // - NO network access
+    // - NO environment variables
+    let mut builder = WasiCtxBuilder::new();
+    builder.args(args).inherit_stdout().inherit_stderr();
+    // Note: clock and random are enabled by default in WASI
+    // File system is NOT inherited - sandboxed
+    if let Some(dir) = files_dir {
+        if let Err(e) = preopen_files_dir(&mut builder, dir) {
+            warn!("Not giving the guest its directory: {:#}", e);
+        }
+    }
+
+    // Recorded/replayed sessions intercept clock and random values
+    match session {
+        Some(session) => session.configure_wasi(&mut builder, policy, time),
+        None => policy.configure_wasi(&mut builder, time),
+    }
+
+    builder.build_p1()
+}
+
+/// Preopen `dir`, created if missing, as the guest's `/`
+fn preopen_files_dir(builder: &mut WasiCtxBuilder, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+    builder
+        .preopened_dir(dir, "/", DirPerms::all(), FilePerms::all())
+        .with_context(|| format!("Could not open directory: {}", dir.display()))?;
+    debug!("Guest files: {}", dir.display());
+    Ok(())
+}
+
+/// Import modules of the host ABI, oldest revision first
+///
+/// A breaking change to an import keeps the old signature in its namespace
+/// and adds the new one to the next: `wapps2` provides every `wapps` import,
+/// with revised signatures where they differ, so old guests keep linking
+/// while new ones opt in by importing from the newer namespace.
+pub const WAPPS_NAMESPACES: [&str; 3] = ["wapps", "wapps2", "wapps3"];
+
+/// `module` with any revision of the host namespace mapped to `wapps`, to
+/// match imports by name whatever revision a guest links against
+pub fn unversioned(module: &str) -> &str {
+    if WAPPS_NAMESPACES.contains(&module) {
+        WAPPS_NAMESPACES[0]
+    } else {
+        module
+    }
+}
+
+/// Create a linker providing every host import: WASI preview 1 and each
+/// revision of the `wapps` module (see [`WAPPS_NAMESPACES`])
+///
+/// The `wapps` imports are specified in `wit/wapps.wit`; a test in `bindgen`
+/// checks that they match.
+pub fn create_linker(engine: &Engine) -> Result<Linker<StoreState>> {
+    let mut linker: Linker<StoreState> = Linker::new(engine);
+
+    // Add WASI Preview 1 sync functions
+    preview1::add_to_linker_sync(&mut linker, |state: &mut StoreState| &mut state.wasi)
+        .context("Failed to add WASI functions to linker")?;
+
+    // Add the wasi-threads import: wasi::thread-spawn(start_arg) -> tid
+    linker
+        .func_wrap(
+            "wasi",
+            "thread-spawn",
+            |caller: Caller<'_, StoreState>, start_arg: i32| -> i32 {
+                match &caller.data().threads {
+                    Some(threads) => threads.spawn(start_arg),
+                    None => {
+                        warn!("thread-spawn: threads are only available with --enable-threads");
+                        -1
+                    }
+                }
+            },
+        )
+        .context("Failed to register thread-spawn import")?;
+
+    // The graphics imports are shared with `wapps3`, which returns their
+    // `wapps_abi` statuses; this revision reports every failure as -1
+
+    // Add our host import: wapps::update_frame(width, height, pixels_ptr)
+    linker
+        .func_wrap(
+            "wapps",
+            "update_frame",
+            |mut caller: Caller<'_, StoreState>, width: i32, height: i32, pixels_ptr: i32| {
+                let rgba = PixelFormat::Rgba32 as i32;
+                present_frame(&mut caller, "update_frame", width, height, pixels_ptr, rgba);
+            },
+        )
+        .context("Failed to register update_frame import")?;
+
+    // Add our host import: wapps::update_frame_ex(width, height, pixels_ptr, format) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "update_frame_ex",
+            |mut caller: Caller<'_, StoreState>,
+             width: i32,
+             height: i32,
+             pixels_ptr: i32,
+             format: i32|
+             -> i32 {
+                legacy_status(present_frame(
+                    &mut caller,
+                    "update_frame_ex",
+                    width,
+                    height,
+                    pixels_ptr,
+                    format,
+                ))
+            },
+        )
+        .context("Failed to register update_frame_ex import")?;
+
+    // Add our host import: wapps::set_palette(entries_ptr, count) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "set_palette",
+            |caller: Caller<'_, StoreState>, entries_ptr: i32, count: i32| -> i32 {
+                legacy_status(set_palette(caller, entries_ptr, count))
+            },
+        )
+        .context("Failed to register set_palette import")?;
+
+    // Add our host import: wapps::update_overlay(width, height, pixels_ptr) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "update_overlay",
+            |caller: Caller<'_, StoreState>, width: i32, height: i32, pixels_ptr: i32| -> i32 {
+                legacy_status(update_overlay(caller, width, height, pixels_ptr))
+            },
+        )
+        .context("Failed to register update_overlay import")?;
+
+    // Add our host import: wapps::update_layer(id, width, height, pixels_ptr, opacity)
+    linker
+        .func_wrap(
+            "wapps",
+            "update_layer",
+            |caller: Caller<'_, StoreState>,
+             id: i32,
+             width: i32,
+             height: i32,
+             pixels_ptr: i32,
+             opacity: f32| {
+                update_layer(caller, id, width, height, pixels_ptr, opacity);
+            },
+        )
+        .context("Failed to register update_layer import")?;
+
+    // Add our host import: wapps::create_image(pixels_ptr, width, height) -> id or status
+    linker
+        .func_wrap(
+            "wapps",
+            "create_image",
+            |caller: Caller<'_, StoreState>, pixels_ptr: i32, width: i32, height: i32| -> i32 {
+                legacy_status(create_image(caller, pixels_ptr, width, height))
+            },
+        )
+        .context("Failed to register create_image import")?;
+
+    // Add our host import: wapps::destroy_image(id)
+    linker
+        .func_wrap(
+            "wapps",
+            "destroy_image",
+            |caller: Caller<'_, StoreState>, id: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.destroy_image(id);
+                }
+            },
+        )
+        .context("Failed to register destroy_image import")?;
+
+    // Add our host import: wapps::draw_image(id, x, y, scale, rotation) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "draw_image",
+            |caller: Caller<'_, StoreState>,
+             id: i32,
+             x: f32,
+             y: f32,
+             scale: f32,
+             rotation: f32|
+             -> i32 { legacy_status(draw_image(caller, id, x, y, scale, rotation)) },
+        )
+        .context("Failed to register draw_image import")?;
+
+    // Add our host import: wapps::clear_canvas(width, height, rgba), packed as 0xRRGGBBAA
+    linker
+        .func_wrap(
+            "wapps",
+            "clear_canvas",
+            |caller: Caller<'_, StoreState>, width: i32, height: i32, rgba: i32| {
+                clear_canvas(caller, width, height, rgba);
+            },
+        )
+        .context("Failed to register clear_canvas import")?;
+
+    // Add our host import: wapps::clear(rgba), packed as 0xRRGGBBAA
+    linker
+        .func_wrap(
+            "wapps",
+            "clear",
+            |caller: Caller<'_, StoreState>, rgba: i32| {
+                clear(caller, rgba);
+            },
+        )
+        .context("Failed to register clear import")?;
+
+    // Add our host import: wapps::fill_rect(x, y, width, height, rgba)
+    linker
+        .func_wrap(
+            "wapps",
+            "fill_rect",
+            |caller: Caller<'_, StoreState>, x: i32, y: i32, width: i32, height: i32, rgba: i32| {
+                fill_rect(caller, x, y, width, height, rgba);
+            },
+        )
+        .context("Failed to register fill_rect import")?;
+
+    // Add our host import: wapps::blit(src_ptr, width, height, x, y) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "blit",
+            |caller: Caller<'_, StoreState>,
+             src_ptr: i32,
+             width: i32,
+             height: i32,
+             x: i32,
+             y: i32|
+             -> i32 { legacy_status(blit(caller, src_ptr, width, height, x, y)) },
+        )
+        .context("Failed to register blit import")?;
+
+    // Add our host import: wapps::draw_text(x, y, ptr, len, rgba, size) -> status,
+    // rgba packed as 0xRRGGBBAA
+    linker
+        .func_wrap(
+            "wapps",
+            "draw_text",
+            |caller: Caller<'_, StoreState>,
+             x: i32,
+             y: i32,
+             ptr: i32,
+             len: i32,
+             rgba: i32,
+             size: i32|
+             -> i32 { legacy_status(draw_text(caller, x, y, ptr, len, rgba, size)) },
+        )
+        .context("Failed to register draw_text import")?;
+
+    // Add our host import: wapps::measure_text(ptr, len, size, out_w_ptr, out_h_ptr) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "measure_text",
+            |caller: Caller<'_, StoreState>,
+             ptr: i32,
+             len: i32,
+             size: i32,
+             out_w_ptr: i32,
+             out_h_ptr: i32|
+             -> i32 {
+                legacy_status(measure_text(caller, ptr, len, size, out_w_ptr, out_h_ptr))
+            },
+        )
+        .context("Failed to register measure_text import")?;
+
+    // Add our host import: wapps::set_aspect_ratio(width, height); 0 clears it
+    linker
+        .func_wrap(
+            "wapps",
+            "set_aspect_ratio",
+            |caller: Caller<'_, StoreState>, width: i32, height: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.set_aspect_ratio(positive_size(width, height));
+                }
+            },
+        )
+        .context("Failed to register set_aspect_ratio import")?;
+
+    // Add our host import: wapps::set_min_size(width, height); 0 clears it
+    linker
+        .func_wrap(
+            "wapps",
+            "set_min_size",
+            |caller: Caller<'_, StoreState>, width: i32, height: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.set_min_size(positive_size(width, height));
+                }
+            },
+        )
+        .context("Failed to register set_min_size import")?;
+
+    // Add our host import: wapps::set_clear_color(rgba), packed as 0xRRGGBBAA
+    linker
+        .func_wrap(
+            "wapps",
+            "set_clear_color",
+            |caller: Caller<'_, StoreState>, rgba: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.set_clear_color(rgba as u32);
+                }
+            },
+        )
+        .context("Failed to register set_clear_color import")?;
+
+    // Add our host import: wapps::set_scaling_mode(mode) -> status; 0 stretches
+    // the frame over the window, 1 fits it, 2 scales it by whole multiples
+    linker
+        .func_wrap(
+            "wapps",
+            "set_scaling_mode",
+            |caller: Caller<'_, StoreState>, mode: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.set_scaling_mode(mode),
+                    Err(_) => host_interface::SCALING_INVALID,
+                }
+            },
+        )
+        .context("Failed to register set_scaling_mode import")?;
+
+    // Add our host import: wapps::set_display_adjustment(brightness, contrast,
+    // gamma) -> status; the viewer's own adjustment applies on top
+    linker
+        .func_wrap(
+            "wapps",
+            "set_display_adjustment",
+            |caller: Caller<'_, StoreState>, brightness: f32, contrast: f32, gamma: f32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.set_display_adjustment(brightness, contrast, gamma),
+                    Err(_) => host_interface::ADJUSTMENT_INVALID,
+                }
+            },
+        )
+        .context("Failed to register set_display_adjustment import")?;
+
+    // Add our host import: wapps::set_fullscreen(mode) -> status; 1 enters
+    // borderless fullscreen, 0 returns to a window
+    linker
+        .func_wrap(
+            "wapps",
+            "set_fullscreen",
+            |caller: Caller<'_, StoreState>, mode: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.request_fullscreen(mode),
+                    Err(_) => host_interface::FULLSCREEN_DENIED,
+                }
+            },
+        )
+        .context("Failed to register set_fullscreen import")?;
+
+    // Add our host import: wapps::set_window_title(ptr, len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "set_window_title",
+            |mut caller: Caller<'_, StoreState>, ptr: i32, len: i32| -> i32 {
+                let Some(title) = read_guest_bytes(&mut caller, ptr, len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("set_window_title: invalid title");
+                    return host_interface::WINDOW_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => {
+                        host.set_window_title(title);
+                        host_interface::WINDOW_OK
+                    }
+                    Err(_) => host_interface::WINDOW_INVALID,
+                }
+            },
+        )
+        .context("Failed to register set_window_title import")?;
+
+    // Add our host import: wapps::set_window_size(width, height, resizable) -> status;
+    // 0x0 sizes the window to each new frame size again
+    linker
+        .func_wrap(
+            "wapps",
+            "set_window_size",
+            |caller: Caller<'_, StoreState>, width: i32, height: i32, resizable: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.set_window_size(width, height, resizable),
+                    Err(_) => host_interface::WINDOW_INVALID,
+                }
+            },
+        )
+        .context("Failed to register set_window_size import")?;
+
+    // Add our host import: wapps::get_window_size(out_w_ptr, out_h_ptr) -> status
+    // Writes the viewport size last passed to init or on_resize as two i32s
+    linker
+        .func_wrap(
+            "wapps",
+            "get_window_size",
+            |mut caller: Caller<'_, StoreState>, out_w_ptr: i32, out_h_ptr: i32| -> i32 {
+                let (width, height) = match caller.data().host.lock() {
+                    Ok(host) => host.viewport(),
+                    Err(_) => return host_interface::WINDOW_INVALID,
+                };
+                let written = write_guest_bytes(&mut caller, out_w_ptr, 4, width.to_le_bytes())
+                    .and_then(|_| {
+                        write_guest_bytes(&mut caller, out_h_ptr, 4, height.to_le_bytes())
+                    });
+                match written {
+                    Some(_) => host_interface::WINDOW_OK,
+                    None => {
+                        warn!("get_window_size: pointer out of bounds");
+                        host_interface::WINDOW_INVALID
+                    }
+                }
+            },
+        )
+        .context("Failed to register get_window_size import")?;
+
+    // Add our host import: wapps::get_display_refresh_rate() -> hz, 0 if unknown
+    linker
+        .func_wrap(
+            "wapps",
+            "get_display_refresh_rate",
+            |caller: Caller<'_, StoreState>| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(host) => host.refresh_rate(),
+                    Err(_) => 0,
+                }
+            },
+        )
+        .context("Failed to register get_display_refresh_rate import")?;
+
+    // Add our host import: wapps::get_frame_count() -> frames presented so far
+    linker
+        .func_wrap(
+            "wapps",
+            "get_frame_count",
+            |caller: Caller<'_, StoreState>| -> i64 {
+                match caller.data().host.lock() {
+                    Ok(host) => host.frame_count() as i64,
+                    Err(_) => 0,
+                }
+            },
+        )
+        .context("Failed to register get_frame_count import")?;
+
+    // Add our host import: wapps::set_cursor_visible(visible)
+    linker
+        .func_wrap(
+            "wapps",
+            "set_cursor_visible",
+            |caller: Caller<'_, StoreState>, visible: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.set_cursor_visible(visible != 0);
+                }
+            },
+        )
+        .context("Failed to register set_cursor_visible import")?;
+
+    // Add our host import: wapps::set_cursor(shape) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "set_cursor",
+            |caller: Caller<'_, StoreState>, shape: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.set_cursor_shape(shape),
+                    Err(_) => host_interface::CURSOR_INVALID,
+                }
+            },
+        )
+        .context("Failed to register set_cursor import")?;
+
+    // Add our host import: wapps::set_relative_mouse(enabled); while enabled
+    // the cursor is hidden and held in the window, and only motion is reported
+    linker
+        .func_wrap(
+            "wapps",
+            "set_relative_mouse",
+            |caller: Caller<'_, StoreState>, enabled: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.set_relative_mouse(enabled != 0);
+                }
+            },
+        )
+        .context("Failed to register set_relative_mouse import")?;
+
+    // Add our host import: wapps::push_audio(samples_ptr, frames, channels, sample_rate) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "push_audio",
+            |mut caller: Caller<'_, StoreState>,
+             samples_ptr: i32,
+             frames: i32,
+             channels: i32,
+             sample_rate: i32|
+             -> i32 {
+                let Some(format) = AudioFormat::new(channels, sample_rate) else {
+                    warn!(
+                        "push_audio: unsupported format ({} channels at {} Hz)",
+                        channels, sample_rate
+                    );
+                    return host_interface::AUDIO_INVALID;
+                };
+                let len = usize::try_from(frames)
+                    .ok()
+                    .and_then(|frames| frames.checked_mul(format.channels as usize * 4))
+                    .and_then(|len| i32::try_from(len).ok());
+                let Some(bytes) =
+                    len.and_then(|len| read_guest_bytes(&mut caller, samples_ptr, len))
+                else {
+                    warn!("push_audio: samples out of bounds");
+                    return host_interface::AUDIO_INVALID;
+                };
+                let samples: Vec<f32> = bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+
+                match caller.data().host.lock() {
+                    Ok(mut host) => {
+                        host.push_audio(format, &samples);
+                        host_interface::AUDIO_OK
+                    }
+                    Err(_) => host_interface::AUDIO_INVALID,
+                }
+            },
+        )
+        .context("Failed to register push_audio import")?;
+
+    // Add our host import: wapps::audio_capture_start(sample_rate, channels) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "audio_capture_start",
+            |caller: Caller<'_, StoreState>, sample_rate: i32, channels: i32| -> i32 {
+                let Some(format) = AudioFormat::new(channels, sample_rate) else {
+                    warn!(
+                        "audio_capture_start: unsupported format ({} channels at {} Hz)",
+                        channels, sample_rate
+                    );
+                    return host_interface::AUDIO_CAPTURE_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => {
+                        host.start_audio_capture(format);
+                        host_interface::AUDIO_CAPTURE_OK
+                    }
+                    Err(_) => host_interface::AUDIO_CAPTURE_INVALID,
+                }
+            },
+        )
+        .context("Failed to register audio_capture_start import")?;
+
+    // Add our host import: wapps::audio_capture_read(out_ptr, max_frames) -> frames
+    linker
+        .func_wrap(
+            "wapps",
+            "audio_capture_read",
+            |mut caller: Caller<'_, StoreState>, out_ptr: i32, max_frames: i32| -> i32 {
+                let (frames, samples) = match caller.data().host.lock() {
+                    Ok(host) => host.read_audio_capture(max_frames.max(0) as usize),
+                    Err(_) => return host_interface::AUDIO_CAPTURE_INVALID,
+                };
+                // Samples read into a buffer out of bounds are lost
+                let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+                match write_guest_bytes(&mut caller, out_ptr, bytes.len() as i32, &bytes) {
+                    Some(_) => frames as i32,
+                    None => {
+                        warn!("audio_capture_read: buffer out of bounds");
+                        host_interface::AUDIO_CAPTURE_INVALID
+                    }
+                }
+            },
+        )
+        .context("Failed to register audio_capture_read import")?;
+
+    // Add our host import: wapps::audio_capture_stop()
+    linker
+        .func_wrap(
+            "wapps",
+            "audio_capture_stop",
+            |caller: Caller<'_, StoreState>| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.stop_audio_capture();
+                }
+            },
+        )
+        .context("Failed to register audio_capture_stop import")?;
+
+    // Add our host import: wapps::get_audio_queued_frames() -> frames
+    linker
+        .func_wrap(
+            "wapps",
+            "get_audio_queued_frames",
+            |caller: Caller<'_, StoreState>| -> i32 {
+                let state = caller.data();
+                let live = || match state.host.lock() {
+                    Ok(host) => host.audio_queued_frames() as u64,
+                    Err(_) => 0,
+                };
+                let frames = match &state.session {
+                    Some(session) => session.audio_queued_frames(live),
+                    None => live(),
+                };
+                frames.min(i32::MAX as u64) as i32
+            },
+        )
+        .context("Failed to register get_audio_queued_frames import")?;
+
+    // Add our host import: wapps::launch(ptr, len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "launch",
+            |mut caller: Caller<'_, StoreState>, ptr: i32, len: i32| -> i32 {
+                let Some(bytes) = read_guest_bytes(&mut caller, ptr, len) else {
+                    warn!("launch: target out of bounds");
+                    return host_interface::LAUNCH_INVALID;
+                };
+                let Ok(target) = String::from_utf8(bytes) else {
+                    warn!("launch: target is not valid UTF-8");
+                    return host_interface::LAUNCH_INVALID;
+                };
+
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.request_launch(target),
+                    Err(_) => host_interface::LAUNCH_INVALID,
+                }
+            },
+        )
+        .context("Failed to register launch import")?;
+
+    // Add our host import: wapps::bus_subscribe(topic_ptr, topic_len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "bus_subscribe",
+            |mut caller: Caller<'_, StoreState>, ptr: i32, len: i32| -> i32 {
+                let Some(topic) = read_bus_topic(&mut caller, ptr, len) else {
+                    warn!("bus_subscribe: invalid topic");
+                    return host_interface::BUS_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.bus_subscribe(topic),
+                    Err(_) => host_interface::BUS_INVALID,
+                }
+            },
+        )
+        .context("Failed to register bus_subscribe import")?;
+
+    // Add our host import: wapps::bus_send(topic_ptr, topic_len, data_ptr, data_len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "bus_send",
+            |mut caller: Caller<'_, StoreState>,
+             topic_ptr: i32,
+             topic_len: i32,
+             data_ptr: i32,
+             data_len: i32|
+             -> i32 {
+                let Some(topic) = read_bus_topic(&mut caller, topic_ptr, topic_len) else {
+                    warn!("bus_send: invalid topic");
+                    return host_interface::BUS_INVALID;
+                };
+                if data_len as u32 as usize > host_interface::MAX_BUS_MESSAGE {
+                    return host_interface::BUS_INVALID;
+                }
+                let Some(data) = read_guest_bytes(&mut caller, data_ptr, data_len) else {
+                    warn!("bus_send: message out of bounds");
+                    return host_interface::BUS_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.bus_send(topic, data),
+                    Err(_) => host_interface::BUS_INVALID,
+                }
+            },
+        )
+        .context("Failed to register bus_send import")?;
+
+    // Add our host import: wapps::get_string(key_ptr, key_len, buf_ptr, buf_cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "get_string",
+            |mut caller: Caller<'_, StoreState>,
+             key_ptr: i32,
+             key_len: i32,
+             buf_ptr: i32,
+             buf_cap: i32|
+             -> i32 {
+                let Some(key) = read_guest_bytes(&mut caller, key_ptr, key_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("get_string: invalid key");
+                    return host_interface::STRING_NOT_FOUND;
+                };
+                let Some(value) = caller
+                    .data()
+                    .host
+                    .lock()
+                    .ok()
+                    .and_then(|host| host.string(&key).map(str::to_owned))
+                else {
+                    return host_interface::STRING_NOT_FOUND;
+                };
+
+                write_guest_bytes(&mut caller, buf_ptr, buf_cap, &value).unwrap_or_else(|| {
+                    warn!("get_string: buffer out of bounds");
+                    host_interface::STRING_NOT_FOUND
+                })
+            },
+        )
+        .context("Failed to register get_string import")?;
+
+    // Add our host import: wapps::config_get(key_ptr, key_len, out_ptr, cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "config_get",
+            |mut caller: Caller<'_, StoreState>,
+             key_ptr: i32,
+             key_len: i32,
+             out_ptr: i32,
+             cap: i32|
+             -> i32 {
+                let Some(key) = read_guest_bytes(&mut caller, key_ptr, key_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("config_get: invalid key");
+                    return host_interface::CONFIG_NOT_FOUND;
+                };
+                let Some(value) = caller
+                    .data()
+                    .host
+                    .lock()
+                    .ok()
+                    .and_then(|host| host.config_value(&key).map(str::to_owned))
+                else {
+                    return host_interface::CONFIG_NOT_FOUND;
+                };
+
+                write_guest_bytes(&mut caller, out_ptr, cap, &value).unwrap_or_else(|| {
+                    warn!("config_get: buffer out of bounds");
+                    host_interface::CONFIG_NOT_FOUND
+                })
+            },
+        )
+        .context("Failed to register config_get import")?;
+
+    // Add our host import: wapps::asset_size(name_ptr, name_len) -> size
+    linker
+        .func_wrap(
+            "wapps",
+            "asset_size",
+            |mut caller: Caller<'_, StoreState>, name_ptr: i32, name_len: i32| -> i32 {
+                let Some(name) = read_guest_bytes(&mut caller, name_ptr, name_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("asset_size: invalid name");
+                    return host_interface::ASSET_NOT_FOUND;
+                };
+                let Ok(host) = caller.data().host.lock() else {
+                    return host_interface::ASSET_NOT_FOUND;
+                };
+                host.assets()
+                    .get(&name)
+                    .map_or(host_interface::ASSET_NOT_FOUND, |data| data.len() as i32)
+            },
+        )
+        .context("Failed to register asset_size import")?;
+
+    // Add our host import: wapps::asset_read(name_ptr, name_len, buf_ptr, buf_cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "asset_read",
+            |mut caller: Caller<'_, StoreState>,
+             name_ptr: i32,
+             name_len: i32,
+             buf_ptr: i32,
+             buf_cap: i32|
+             -> i32 {
+                let Some(name) = read_guest_bytes(&mut caller, name_ptr, name_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("asset_read: invalid name");
+                    return host_interface::ASSET_NOT_FOUND;
+                };
+                let Ok(assets) = caller.data().host.lock().map(|host| host.assets()) else {
+                    return host_interface::ASSET_NOT_FOUND;
+                };
+                let Some(data) = assets.get(&name) else {
+                    return host_interface::ASSET_NOT_FOUND;
+                };
+
+                write_guest_bytes(&mut caller, buf_ptr, buf_cap, data).unwrap_or_else(|| {
+                    warn!("asset_read: buffer out of bounds");
+                    host_interface::ASSET_NOT_FOUND
+                })
+            },
+        )
+        .context("Failed to register asset_read import")?;
+
+    // Add our host import: wapps::event_time() -> seconds
+    linker
+        .func_wrap(
+            "wapps",
+            "event_time",
+            |caller: Caller<'_, StoreState>| -> f64 {
+                match caller.data().host.lock() {
+                    Ok(host) => host.event_time(),
+                    Err(_) => 0.0,
+                }
+            },
+        )
+        .context("Failed to register event_time import")?;
+
+    // Add our host import: wapps::request_frame_rate(fps)
+    linker
+        .func_wrap(
+            "wapps",
+            "request_frame_rate",
+            |caller: Caller<'_, StoreState>, fps: f64| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.request_frame_rate(fps);
+                }
+            },
+        )
+        .context("Failed to register request_frame_rate import")?;
+
+    // Add our host import: wapps::now_micros() -> microseconds since the guest started
+    linker
+        .func_wrap(
+            "wapps",
+            "now_micros",
+            |caller: Caller<'_, StoreState>| -> i64 {
+                let state = caller.data();
+                let live = || state.clock.monotonic_now(state.started, &state.time) / 1_000;
+                let micros = match &state.session {
+                    Some(session) => session.now_micros(live),
+                    None => live(),
+                };
+                micros.min(i64::MAX as u64) as i64
+            },
+        )
+        .context("Failed to register now_micros import")?;
+
+    // Add our host import: wapps::set_timer(id, seconds, repeating) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "set_timer",
+            |caller: Caller<'_, StoreState>, id: i32, seconds: f64, repeating: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.set_timer(id, seconds, repeating != 0),
+                    Err(_) => timers::TIMER_INVALID,
+                }
+            },
+        )
+        .context("Failed to register set_timer import")?;
+
+    // Add our host import: wapps::cancel_timer(id) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "cancel_timer",
+            |caller: Caller<'_, StoreState>, id: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.cancel_timer(id),
+                    Err(_) => timers::TIMER_NOT_FOUND,
+                }
+            },
+        )
+        .context("Failed to register cancel_timer import")?;
+
+    // Add our host import: wapps::request_redraw()
+    linker
+        .func_wrap(
+            "wapps",
+            "request_redraw",
+            |caller: Caller<'_, StoreState>| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.request_redraw();
+                }
+            },
+        )
+        .context("Failed to register request_redraw import")?;
+
+    // Add our host import: wapps::request_update_in(ms)
+    linker
+        .func_wrap(
+            "wapps",
+            "request_update_in",
+            |caller: Caller<'_, StoreState>, ms: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.request_update_in(ms.max(0) as f64 / 1000.0);
+                }
+            },
+        )
+        .context("Failed to register request_update_in import")?;
+
+    // Add our host import: wapps::query_key_state(scancode) -> 1 if held, else 0
+    linker
+        .func_wrap(
+            "wapps",
+            "query_key_state",
+            |caller: Caller<'_, StoreState>, scancode: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(host) => host.key_held(scancode) as i32,
+                    Err(_) => 0,
+                }
+            },
+        )
+        .context("Failed to register query_key_state import")?;
+
+    // Add our host import: wapps::app_name(buf_ptr, buf_cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "app_name",
+            |mut caller: Caller<'_, StoreState>, buf_ptr: i32, buf_cap: i32| -> i32 {
+                let name = match caller.data().host.lock() {
+                    Ok(host) => host.app_name().to_owned(),
+                    Err(_) => String::new(),
+                };
+                write_guest_bytes(&mut caller, buf_ptr, buf_cap, &name).unwrap_or_else(|| {
+                    warn!("app_name: buffer out of bounds");
+                    host_interface::BUFFER_INVALID
+                })
+            },
+        )
+        .context("Failed to register app_name import")?;
+
+    // Add our host import: wapps::app_version(buf_ptr, buf_cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "app_version",
+            |mut caller: Caller<'_, StoreState>, buf_ptr: i32, buf_cap: i32| -> i32 {
+                let version = match caller.data().host.lock() {
+                    Ok(host) => host.app_version().to_owned(),
+                    Err(_) => String::new(),
+                };
+                write_guest_bytes(&mut caller, buf_ptr, buf_cap, &version).unwrap_or_else(|| {
+                    warn!("app_version: buffer out of bounds");
+                    host_interface::BUFFER_INVALID
+                })
+            },
+        )
+        .context("Failed to register app_version import")?;
+
+    // Add our host import: wapps::get_locale(out_ptr, cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "get_locale",
+            |mut caller: Caller<'_, StoreState>, out_ptr: i32, cap: i32| -> i32 {
+                let locale = match caller.data().host.lock() {
+                    Ok(host) => host.locale().to_owned(),
+                    Err(_) => String::new(),
+                };
+                write_guest_bytes(&mut caller, out_ptr, cap, &locale).unwrap_or_else(|| {
+                    warn!("get_locale: buffer out of bounds");
+                    host_interface::BUFFER_INVALID
+                })
+            },
+        )
+        .context("Failed to register get_locale import")?;
+
+    // Add our host import: wapps::get_prefers_dark() -> i32
+    linker
+        .func_wrap(
+            "wapps",
+            "get_prefers_dark",
+            |caller: Caller<'_, StoreState>| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(host) => host.prefers_dark() as i32,
+                    Err(_) => 0,
+                }
+            },
+        )
+        .context("Failed to register get_prefers_dark import")?;
+
+    // Add our host import: wapps::storage_get(key_ptr, key_len, out_ptr, out_cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "storage_get",
+            |mut caller: Caller<'_, StoreState>,
+             key_ptr: i32,
+             key_len: i32,
+             out_ptr: i32,
+             out_cap: i32|
+             -> i32 {
+                let Some(key) = read_guest_bytes(&mut caller, key_ptr, key_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("storage_get: invalid key");
+                    return storage::STORAGE_NOT_FOUND;
+                };
+                let Some(value) = caller
+                    .data()
+                    .host
+                    .lock()
+                    .ok()
+                    .and_then(|host| host.storage_get(&key).map(<[u8]>::to_vec))
+                else {
+                    return storage::STORAGE_NOT_FOUND;
+                };
+
+                write_guest_bytes(&mut caller, out_ptr, out_cap, &value).unwrap_or_else(|| {
+                    warn!("storage_get: buffer out of bounds");
+                    storage::STORAGE_NOT_FOUND
+                })
+            },
+        )
+        .context("Failed to register storage_get import")?;
+
+    // Add our host import: wapps::storage_set(key_ptr, key_len, value_ptr, value_len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "storage_set",
+            |mut caller: Caller<'_, StoreState>,
+             key_ptr: i32,
+             key_len: i32,
+             value_ptr: i32,
+             value_len: i32|
+             -> i32 {
+                let Some(key) = read_guest_bytes(&mut caller, key_ptr, key_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("storage_set: invalid key");
+                    return storage::STORAGE_INVALID;
+                };
+                let Some(value) = read_guest_bytes(&mut caller, value_ptr, value_len) else {
+                    warn!("storage_set: value out of bounds");
+                    return storage::STORAGE_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.storage_set(&key, &value),
+                    Err(_) => storage::STORAGE_IO_ERROR,
+                }
+            },
+        )
+        .context("Failed to register storage_set import")?;
+
+    // Add our host import: wapps::score_submit(value, name_ptr, name_len) -> rank
+    linker
+        .func_wrap(
+            "wapps",
+            "score_submit",
+            |mut caller: Caller<'_, StoreState>, value: i64, name_ptr: i32, name_len: i32| -> i32 {
+                let Some(name) = read_guest_bytes(&mut caller, name_ptr, name_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("score_submit: invalid name");
+                    return scores::SCORE_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.score_submit(value, &name),
+                    Err(_) => scores::SCORE_IO_ERROR,
+                }
+            },
+        )
+        .context("Failed to register score_submit import")?;
+
+    // Add our host import: wapps::score_list(buf_ptr, buf_cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "score_list",
+            |mut caller: Caller<'_, StoreState>, buf_ptr: i32, buf_cap: i32| -> i32 {
+                let list = match caller.data().host.lock() {
+                    Ok(host) => host.score_list(),
+                    Err(_) => return host_interface::BUFFER_INVALID,
+                };
+                write_guest_bytes(&mut caller, buf_ptr, buf_cap, &list).unwrap_or_else(|| {
+                    warn!("score_list: buffer out of bounds");
+                    host_interface::BUFFER_INVALID
+                })
+            },
+        )
+        .context("Failed to register score_list import")?;
+
+    // Add our host import: wapps::report_allocations(bytes_in_use)
+    linker
+        .func_wrap(
+            "wapps",
+            "report_allocations",
+            |caller: Caller<'_, StoreState>, bytes_in_use: i64| {
+                let Ok(bytes_in_use) = u64::try_from(bytes_in_use) else {
+                    warn!("report_allocations: negative size {}", bytes_in_use);
+                    return;
+                };
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.report_allocations(bytes_in_use);
+                }
+            },
+        )
+        .context("Failed to register report_allocations import")?;
+
+    // Add our host import: wapps::log(level, ptr, len); lines go through the
+    // host's logger with the app name as target, so RUST_LOG filters them
+    linker
+        .func_wrap(
+            "wapps",
+            "log",
+            |mut caller: Caller<'_, StoreState>, level: i32, ptr: i32, len: i32| {
+                let Some(bytes) = read_guest_bytes(&mut caller, ptr, len) else {
+                    warn!("log: message out of bounds");
+                    return;
+                };
+                let line = String::from_utf8_lossy(&bytes).into_owned();
+                let level = host_interface::log_level(level);
+                if let Ok(mut host) = caller.data().host.lock() {
+                    log::log!(target: host.app_name(), level, "{}", line);
+                    host.push_log(level, line);
+                }
+            },
+        )
+        .context("Failed to register log import")?;
+
+    // Add our host import:
+    // wapps::http_fetch(method_ptr, method_len, url_ptr, url_len, body_ptr, body_len) -> handle
+    linker
+        .func_wrap(
+            "wapps",
+            "http_fetch",
+            |mut caller: Caller<'_, StoreState>,
+             method_ptr: i32,
+             method_len: i32,
+             url_ptr: i32,
+             url_len: i32,
+             body_ptr: i32,
+             body_len: i32|
+             -> i32 {
+                let text = |caller: &mut Caller<'_, StoreState>, ptr, len| {
+                    read_guest_bytes(caller, ptr, len)
+                        .and_then(|bytes| String::from_utf8(bytes).ok())
+                };
+                let (Some(method), Some(url), Some(body)) = (
+                    text(&mut caller, method_ptr, method_len),
+                    text(&mut caller, url_ptr, url_len),
+                    read_guest_bytes(&mut caller, body_ptr, body_len),
+                ) else {
+                    warn!("http_fetch: invalid request");
+                    return http::HTTP_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.http_fetch(&method, &url, body),
+                    Err(_) => http::HTTP_INVALID,
+                }
+            },
+        )
+        .context("Failed to register http_fetch import")?;
+
+    // Add our host import: wapps::http_poll(handle) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "http_poll",
+            |caller: Caller<'_, StoreState>, handle: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(host) => host.http_poll(handle),
+                    Err(_) => http::HTTP_NOT_FOUND,
+                }
+            },
+        )
+        .context("Failed to register http_poll import")?;
+
+    // Add our host import: wapps::http_read(handle, buf_ptr, buf_cap) -> len
+    linker
+        .func_wrap(
+            "wapps",
+            "http_read",
+            |mut caller: Caller<'_, StoreState>, handle: i32, buf_ptr: i32, buf_cap: i32| -> i32 {
+                let body = match caller.data().host.lock() {
+                    Ok(host) => host.http_body(handle),
+                    Err(_) => None,
+                };
+                let Some(body) = body else {
+                    return http::HTTP_NOT_FOUND;
+                };
+                write_guest_bytes(&mut caller, buf_ptr, buf_cap, &body).unwrap_or_else(|| {
+                    warn!("http_read: buffer out of bounds");
+                    host_interface::BUFFER_INVALID
+                })
+            },
+        )
+        .context("Failed to register http_read import")?;
+
+    // Add our host import: wapps::http_close(handle)
+    linker
+        .func_wrap(
+            "wapps",
+            "http_close",
+            |caller: Caller<'_, StoreState>, handle: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.http_close(handle);
+                }
+            },
+        )
+        .context("Failed to register http_close import")?;
+
+    // Add our host import: wapps::ws_connect(url_ptr, url_len) -> handle
+    linker
+        .func_wrap(
+            "wapps",
+            "ws_connect",
+            |mut caller: Caller<'_, StoreState>, url_ptr: i32, url_len: i32| -> i32 {
+                let Some(url) = read_guest_bytes(&mut caller, url_ptr, url_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("ws_connect: invalid URL");
+                    return ws::WS_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.ws_connect(&url),
+                    Err(_) => ws::WS_INVALID,
+                }
+            },
+        )
+        .context("Failed to register ws_connect import")?;
+
+    // Add our host import: wapps::ws_send(handle, ptr, len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "ws_send",
+            |mut caller: Caller<'_, StoreState>, handle: i32, ptr: i32, len: i32| -> i32 {
+                let Some(message) = read_guest_bytes(&mut caller, ptr, len) else {
+                    warn!("ws_send: message out of bounds");
+                    return ws::WS_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(host) => host.ws_send(handle, message),
+                    Err(_) => ws::WS_NOT_FOUND,
+                }
+            },
+        )
+        .context("Failed to register ws_send import")?;
+
+    // Add our host import: wapps::ws_status(handle) -> state
+    linker
+        .func_wrap(
+            "wapps",
+            "ws_status",
+            |caller: Caller<'_, StoreState>, handle: i32| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(host) => host.ws_status(handle),
+                    Err(_) => ws::WS_NOT_FOUND,
+                }
+            },
+        )
+        .context("Failed to register ws_status import")?;
+
+    // Add our host import: wapps::ws_close(handle)
+    linker
+        .func_wrap(
+            "wapps",
+            "ws_close",
+            |caller: Caller<'_, StoreState>, handle: i32| {
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.ws_close(handle);
+                }
+            },
+        )
+        .context("Failed to register ws_close import")?;
+
+    // Add our host import: wapps::request_snapshot() -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "request_snapshot",
+            |caller: Caller<'_, StoreState>| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.request_snapshot(SnapshotRequest::Save),
+                    Err(_) => host_interface::SNAPSHOT_DENIED,
+                }
+            },
+        )
+        .context("Failed to register request_snapshot import")?;
+
+    // Add our host import: wapps::request_restore() -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "request_restore",
+            |caller: Caller<'_, StoreState>| -> i32 {
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.request_snapshot(SnapshotRequest::Restore),
+                    Err(_) => host_interface::SNAPSHOT_DENIED,
+                }
+            },
+        )
+        .context("Failed to register request_restore import")?;
+
+    // Add our host import: wapps::request_open_file(filter_ptr, filter_len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "request_open_file",
+            |mut caller: Caller<'_, StoreState>, filter_ptr: i32, filter_len: i32| -> i32 {
+                let Some(filter) = read_guest_bytes(&mut caller, filter_ptr, filter_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                else {
+                    warn!("request_open_file: invalid filter");
+                    return host_interface::FILE_DIALOG_INVALID;
+                };
+                let extensions = host_interface::filter_extensions(&filter);
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.request_file(FileRequest::Open { extensions }),
+                    Err(_) => host_interface::FILE_DIALOG_UNAVAILABLE,
+                }
+            },
+        )
+        .context("Failed to register request_open_file import")?;
+
+    // Add our host import:
+    // wapps::request_save_file(name_ptr, name_len, data_ptr, data_len) -> status
+    linker
+        .func_wrap(
+            "wapps",
+            "request_save_file",
+            |mut caller: Caller<'_, StoreState>,
+             name_ptr: i32,
+             name_len: i32,
+             data_ptr: i32,
+             data_len: i32|
+             -> i32 {
+                let name = read_guest_bytes(&mut caller, name_ptr, name_len)
+                    .and_then(|bytes| String::from_utf8(bytes).ok());
+                let (Some(name), Some(data)) =
+                    (name, read_guest_bytes(&mut caller, data_ptr, data_len))
+                else {
+                    warn!("request_save_file: invalid name or data");
+                    return host_interface::FILE_DIALOG_INVALID;
+                };
+                match caller.data().host.lock() {
+                    Ok(mut host) => host.request_file(FileRequest::Save { name, data }),
+                    Err(_) => host_interface::FILE_DIALOG_UNAVAILABLE,
+                }
+            },
+        )
+        .context("Failed to register request_save_file import")?;
+
+    // Add our host import: wapps::gamepad_rumble(pad, low, high, duration_ms)
+    linker
+        .func_wrap(
+            "wapps",
+            "gamepad_rumble",
+            |caller: Caller<'_, StoreState>, pad: i32, low: i32, high: i32, duration_ms: i32| {
+                let Ok(pad) = u32::try_from(pad) else {
+                    return;
+                };
+                let strength = |value: i32| value.clamp(0, u16::MAX as i32) as u16;
+                let request = GamepadRequest::Rumble {
+                    pad,
+                    low: strength(low),
+                    high: strength(high),
+                    duration_ms: duration_ms.clamp(0, host_interface::MAX_RUMBLE_MS as i32) as u32,
+                };
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.request_gamepad(request);
+                }
+            },
+        )
+        .context("Failed to register gamepad_rumble import")?;
+
+    // Add our host import: wapps::gamepad_set_led(pad, r, g, b)
+    linker
+        .func_wrap(
+            "wapps",
+            "gamepad_set_led",
+            |caller: Caller<'_, StoreState>, pad: i32, r: i32, g: i32, b: i32| {
+                let Ok(pad) = u32::try_from(pad) else {
+                    return;
+                };
+                let channel = |value: i32| value.clamp(0, 255) as u8;
+                let request = GamepadRequest::Led {
+                    pad,
+                    r: channel(r),
+                    g: channel(g),
+                    b: channel(b),
+                };
+                if let Ok(mut host) = caller.data().host.lock() {
+                    host.request_gamepad(request);
+                }
+            },
+        )
+        .context("Failed to register gamepad_set_led import")?;
+
+    // Revision 2 of the ABI: everything from `wapps`, then the imports whose
+    // signatures changed
+    linker
+        .alias_module("wapps", "wapps2")
+        .context("Failed to register the wapps2 imports")?;
+    linker.allow_shadowing(true);
+
+    // Add our host import: wapps2::update_frame(width, height, pixels_ptr) -> status
+    linker
+        .func_wrap(
+            "wapps2",
+            "update_frame",
+            |mut caller: Caller<'_, StoreState>, width: i32, height: i32, pixels_ptr: i32| -> i32 {
+                let rgba = PixelFormat::Rgba32 as i32;
+                legacy_status(present_frame(
+                    &mut caller,
+                    "update_frame",
+                    width,
+                    height,
+                    pixels_ptr,
+                    rgba,
+                ))
+            },
+        )
+        .context("Failed to register wapps2 update_frame import")?;
+
+    // Revision 3 of the ABI: everything from `wapps2`, with the graphics and
+    // fullscreen imports returning the `wapps_abi` status of every call
+    linker
+        .alias_module("wapps2", "wapps3")
+        .context("Failed to register the wapps3 imports")?;
+
+    linker
+        .func_wrap(
+            "wapps3",
+            "update_frame",
+            |mut caller: Caller<'_, StoreState>, width: i32, height: i32, pixels_ptr: i32| -> i32 {
+                let rgba = PixelFormat::Rgba32 as i32;
+                present_frame(&mut caller, "update_frame", width, height, pixels_ptr, rgba)
+            },
+        )
+        .context("Failed to register wapps3 update_frame import")?;
+    linker
+        .func_wrap(
+            "wapps3",
+            "update_frame_ex",
+            |mut caller: Caller<'_, StoreState>,
+             width: i32,
+             height: i32,
+             pixels_ptr: i32,
+             format: i32|
+             -> i32 {
+                present_frame(
+                    &mut caller,
+                    "update_frame_ex",
+                    width,
+                    height,
+                    pixels_ptr,
+                    format,
+                )
+            },
+        )
+        .context("Failed to register wapps3 update_frame_ex import")?;
+    linker
+        .func_wrap("wapps3", "set_palette", set_palette)
+        .context("Failed to register wapps3 set_palette import")?;
+    linker
+        .func_wrap("wapps3", "update_overlay", update_overlay)
+        .context("Failed to register wapps3 update_overlay import")?;
+    linker
+        .func_wrap("wapps3", "update_layer", update_layer)
+        .context("Failed to register wapps3 update_layer import")?;
+    linker
+        .func_wrap("wapps3", "create_image", create_image)
+        .context("Failed to register wapps3 create_image import")?;
+    linker
+        .func_wrap("wapps3", "draw_image", draw_image)
+        .context("Failed to register wapps3 draw_image import")?;
+    linker
+        .func_wrap("wapps3", "clear_canvas", clear_canvas)
+        .context("Failed to register wapps3 clear_canvas import")?;
+    linker
+        .func_wrap("wapps3", "clear", clear)
+        .context("Failed to register wapps3 clear import")?;
+    linker
+        .func_wrap("wapps3", "fill_rect", fill_rect)
+        .context("Failed to register wapps3 fill_rect import")?;
+    linker
+        .func_wrap("wapps3", "blit", blit)
+        .context("Failed to register wapps3 blit import")?;
+    linker
+        .func_wrap("wapps3", "draw_text", draw_text)
+        .context("Failed to register wapps3 draw_text import")?;
+    linker
+        .func_wrap("wapps3", "measure_text", measure_text)
+        .context("Failed to register wapps3 measure_text import")?;
+
+    // Add our host import: wapps3::set_fullscreen(mode) -> status
+    linker
+        .func_wrap(
+            "wapps3",
+            "set_fullscreen",
+            |caller: Caller<'_, StoreState>, mode: i32| -> i32 {
+                let Ok(mut host) = caller.data().host.lock() else {
+                    return wapps_abi::PERMISSION_DENIED;
+                };
+                match host.request_fullscreen(mode) {
+                    host_interface::FULLSCREEN_OK => wapps_abi::OK,
+                    host_interface::FULLSCREEN_DENIED => wapps_abi::PERMISSION_DENIED,
+                    _ => wapps_abi::UNSUPPORTED,
+                }
+            },
+        )
+        .context("Failed to register wapps3 set_fullscreen import")?;
+
+    linker.allow_shadowing(false);
+    Ok(linker)
+}
+
+/// Collapse a `wapps_abi` status to the -1 every failure of the `wapps` and
+/// `wapps2` graphics imports returns, keeping ids
+fn legacy_status(status: i32) -> i32 {
+    status.max(wapps_abi::OUT_OF_BOUNDS)
+}
+
+/// Count a `width` x `height` present from `import` against the guest's
+/// frame limits, returning the `wapps_abi` status rejecting it, if any
+fn admit_frame(caller: &Caller<'_, StoreState>, import: &str, width: u32, height: u32) -> i32 {
+    match caller.data().host.lock() {
+        Ok(mut host) => host.admit_frame(import, width, height),
+        Err(_) => wapps_abi::UNSUPPORTED,
+    }
+}
+
+/// Present a frame from guest memory, returning a `wapps_abi` status
+fn present_frame(
+    caller: &mut Caller<'_, StoreState>,
+    import: &str,
+    width: i32,
+    height: i32,
+    pixels_ptr: i32,
+    format: i32,
+) -> i32 {
+    let Some(format) = PixelFormat::from_raw(format) else {
+        warn!("{}: unknown pixel format {}", import, format);
+        return wapps_abi::UNSUPPORTED;
+    };
+    let Some((width, height)) = positive_size(width, height) else {
+        warn!("{}: invalid size {}x{}", import, width, height);
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    let status = admit_frame(caller, import, width, height);
+    if status != wapps_abi::OK {
+        return status;
+    }
+    let Some(len) = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(format.bytes_per_pixel()))
+    else {
+        warn!("{}: frame too large", import);
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    let Some(memory) = caller_memory(caller) else {
+        warn!("{}: guest has no memory export", import);
+        return wapps_abi::OUT_OF_BOUNDS;
+    };
+    let Some(pixels) = memory.read(&*caller, pixels_ptr as u32 as usize, len) else {
+        warn!("{}: pixel buffer out of bounds", import);
+        return wapps_abi::OUT_OF_BOUNDS;
+    };
+
+    if let Ok(mut host) = caller.data().host.lock() {
+        let (width, height) = (width as i32, height as i32);
+        if format != PixelFormat::Rgba32 || !host.present_shared_frame(width, height, pixels_ptr) {
+            host.set_frame_in_format(width, height, format, &pixels);
+        }
+    }
+    wapps_abi::OK
+}
+
+/// `set_palette(entries_ptr, count) -> status`: set the colors of indexed frames
+fn set_palette(mut caller: Caller<'_, StoreState>, entries_ptr: i32, count: i32) -> i32 {
+    if !(0..=pixel_format::PALETTE_SIZE as i32).contains(&count) {
+        warn!("set_palette: invalid color count {}", count);
+        return wapps_abi::INVALID_DIMENSIONS;
+    }
+    let Some(entries) = read_guest_bytes(&mut caller, entries_ptr, count * 4) else {
+        warn!("set_palette: palette out of bounds");
+        return wapps_abi::OUT_OF_BOUNDS;
+    };
+    if let Ok(mut host) = caller.data().host.lock() {
+        host.set_palette(&entries);
+    }
+    wapps_abi::OK
+}
+
+/// `update_overlay(width, height, pixels_ptr) -> status`: set the HUD drawn
+/// over the window, or remove it when 0x0
+fn update_overlay(
+    mut caller: Caller<'_, StoreState>,
+    width: i32,
+    height: i32,
+    pixels_ptr: i32,
+) -> i32 {
+    let update = if width == 0 && height == 0 {
+        OverlayUpdate::Remove
+    } else {
+        let max = host_interface::MAX_OVERLAY_SIDE as i32;
+        if !(1..=max).contains(&width) || !(1..=max).contains(&height) {
+            warn!("update_overlay: invalid size {}x{}", width, height);
+            return wapps_abi::INVALID_DIMENSIONS;
+        }
+        let len = width * height * 4;
+        let Some(pixels) = read_guest_bytes(&mut caller, pixels_ptr, len) else {
+            warn!("update_overlay: pixel buffer out of bounds");
+            return wapps_abi::OUT_OF_BOUNDS;
+        };
+        OverlayUpdate::Set {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        }
+    };
+    match caller.data().host.lock() {
+        Ok(mut host) => {
+            host.set_overlay(update);
+            wapps_abi::OK
+        }
+        Err(_) => wapps_abi::UNSUPPORTED,
+    }
+}
+
+/// `update_layer(id, width, height, pixels_ptr, opacity) -> status`: set a
+/// layer, or remove it when empty
+fn update_layer(
+    mut caller: Caller<'_, StoreState>,
+    id: i32,
+    width: i32,
+    height: i32,
+    pixels_ptr: i32,
+    opacity: f32,
+) -> i32 {
+    if width <= 0 || height <= 0 {
+        if let Ok(mut host) = caller.data().host.lock() {
+            host.remove_layer(id);
+        }
+        return wapps_abi::OK;
+    }
+
+    let status = admit_frame(&caller, "update_layer", width as u32, height as u32);
+    if status != wapps_abi::OK {
+        return status;
+    }
+    let Some(len) = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+    else {
+        warn!("update_layer: layer too large");
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    let Some(memory) = caller_memory(&mut caller) else {
+        warn!("update_layer: guest has no memory export");
+        return wapps_abi::OUT_OF_BOUNDS;
+    };
+    let Some(pixels) = memory.read(&caller, pixels_ptr as u32 as usize, len) else {
+        warn!("update_layer: pixel buffer out of bounds");
+        return wapps_abi::OUT_OF_BOUNDS;
+    };
+
+    if let Ok(mut host) = caller.data().host.lock() {
+        // Overlays are composited over a copy of the base layer
+        if let Some((base_width, base_height, base_ptr)) = host.shared_frame() {
+            let len = base_width as usize * base_height as usize * 4;
+            if let Some(base) = memory.read(&caller, base_ptr as usize, len) {
+                host.set_frame(base_width as i32, base_height as i32, &base);
+            }
+        }
+        host.set_layer(id, width, height, &pixels, opacity);
+    }
+    wapps_abi::OK
+}
+
+/// `create_image(pixels_ptr, width, height) -> id or status`: keep an RGBA image
+fn create_image(
+    mut caller: Caller<'_, StoreState>,
+    pixels_ptr: i32,
+    width: i32,
+    height: i32,
+) -> i32 {
+    let Some((width, height)) = positive_size(width, height) else {
+        warn!("create_image: invalid size {}x{}", width, height);
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    let Some(len) = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .filter(|&len| len <= i32::MAX as usize)
+    else {
+        warn!("create_image: image too large");
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    let Some(pixels) = read_guest_bytes(&mut caller, pixels_ptr, len as i32) else {
+        warn!("create_image: pixel buffer out of bounds");
+        return wapps_abi::OUT_OF_BOUNDS;
+    };
+    match caller.data().host.lock() {
+        Ok(mut host) => host.create_image(width, height, &pixels),
+        Err(_) => wapps_abi::UNSUPPORTED,
+    }
+}
+
+/// `draw_image(id, x, y, scale, rotation) -> status`: draw an image over the
+/// next frame
+fn draw_image(
+    caller: Caller<'_, StoreState>,
+    id: i32,
+    x: f32,
+    y: f32,
+    scale: f32,
+    rotation: f32,
+) -> i32 {
+    let Ok(mut host) = caller.data().host.lock() else {
+        return wapps_abi::UNSUPPORTED;
+    };
+    host.draw_image(ImageDraw {
+        id,
+        x,
+        y,
+        scale,
+        rotation,
+    })
+}
+
+/// `clear_canvas(width, height, rgba) -> status`: present a frame filled with
+/// one color
+fn clear_canvas(caller: Caller<'_, StoreState>, width: i32, height: i32, rgba: i32) -> i32 {
+    let Some((width, height)) = positive_size(width, height) else {
+        warn!("clear_canvas: invalid size {}x{}", width, height);
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    if width as u64 * height as u64 * 4 > i32::MAX as u64 {
+        warn!("clear_canvas: canvas too large");
+        return wapps_abi::INVALID_DIMENSIONS;
+    }
+    let status = admit_frame(&caller, "clear_canvas", width, height);
+    if status != wapps_abi::OK {
+        return status;
+    }
+    if let Ok(mut host) = caller.data().host.lock() {
+        host.clear_canvas(width, height, rgba as u32);
+    }
+    wapps_abi::OK
+}
+
+/// `clear(rgba) -> status`: fill the next frame with one color
+fn clear(caller: Caller<'_, StoreState>, rgba: i32) -> i32 {
+    let Ok(mut host) = caller.data().host.lock() else {
+        return wapps_abi::UNSUPPORTED;
+    };
+    host.draw_canvas(CanvasDraw::Clear { rgba: rgba as u32 })
+}
+
+/// `fill_rect(x, y, width, height, rgba) -> status`: blend a rectangle over
+/// the next frame
+fn fill_rect(
+    caller: Caller<'_, StoreState>,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    rgba: i32,
+) -> i32 {
+    let Some((width, height)) = positive_size(width, height) else {
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    let Ok(mut host) = caller.data().host.lock() else {
+        return wapps_abi::UNSUPPORTED;
+    };
+    host.draw_canvas(CanvasDraw::Fill {
+        x,
+        y,
+        width,
+        height,
+        rgba: rgba as u32,
+    })
+}
+
+/// `blit(src_ptr, width, height, x, y) -> status`: blend a copy of RGBA
+/// pixels over the next frame
+fn blit(
+    mut caller: Caller<'_, StoreState>,
+    src_ptr: i32,
+    width: i32,
+    height: i32,
+    x: i32,
+    y: i32,
+) -> i32 {
+    let Some((width, height)) = positive_size(width, height) else {
+        warn!("blit: invalid size {}x{}", width, height);
+        return wapps_abi::INVALID_DIMENSIONS;
+    };
+    let len = width as u64 * height as u64 * 4;
+    if len > canvas::MAX_BLIT_BYTES as u64 {
+        warn!("blit: image too large");
+        return wapps_abi::INVALID_DIMENSIONS;
+    }
+    let Some(pixels) = read_guest_bytes(&mut caller, src_ptr, len as i32) else {
+        warn!("blit: pixel buffer out of bounds");
+        return wapps_abi::OUT_OF_BOUNDS;
+    };
+    let Ok(mut host) = caller.data().host.lock() else {
+        return wapps_abi::UNSUPPORTED;
+    };
+    host.draw_canvas(CanvasDraw::Blit {
+        x,
+        y,
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// `draw_text(x, y, ptr, len, rgba, size) -> status`: draw text over the next
+/// frame
+fn draw_text(
+    mut caller: Caller<'_, StoreState>,
+    x: i32,
+    y: i32,
+    ptr: i32,
+    len: i32,
+    rgba: i32,
+    size: i32,
+) -> i32 {
+    let text = match read_guest_text(&mut caller, ptr, len) {
+        Ok(text) => text,
+        Err(status) => {
+            warn!("draw_text: invalid text");
+            return status;
+        }
+    };
+    let Ok(mut host) = caller.data().host.lock() else {
+        return wapps_abi::UNSUPPORTED;
+    };
+    host.draw_text(TextDraw {
+        x,
+        y,
+        text,
+        rgba: rgba as u32,
+        size,
+    })
+}
+
+/// `measure_text(ptr, len, size, out_w_ptr, out_h_ptr) -> status`: write the
+/// size of text drawn by `draw_text`
+fn measure_text(
+    mut caller: Caller<'_, StoreState>,
+    ptr: i32,
+    len: i32,
+    size: i32,
+    out_w_ptr: i32,
+    out_h_ptr: i32,
+) -> i32 {
+    let text = match read_guest_text(&mut caller, ptr, len) {
+        Ok(text) => text,
+        Err(status) => {
+            warn!("measure_text: invalid text");
+            return status;
+        }
+    };
+    let (width, height) = text::measure(&text, size);
+    let written = write_guest_bytes(&mut caller, out_w_ptr, 4, width.to_le_bytes())
+        .and_then(|_| write_guest_bytes(&mut caller, out_h_ptr, 4, height.to_le_bytes()));
+    match written {
+        Some(_) => wapps_abi::OK,
+        None => {
+            warn!("measure_text: pointer out of bounds");
+            wapps_abi::OUT_OF_BOUNDS
+        }
+    }
+}
+
+/// Imports of `module` that `linker` does not provide
+///
+/// Provided imports may still have the wrong type; `Linker::instantiate_pre`
+/// reports those without running the module.
+pub fn missing_imports<'a>(
+    engine: &Engine,
+    linker: &Linker<StoreState>,
+    module: &'a Module,
+) -> Vec<ImportType<'a>> {
+    let state = StoreState::new(HostInterface::new(), &[], None, &WasiPolicy::default());
+    let mut store = Store::new(engine, state);
+    module
+        .imports()
+        .filter(|import| linker.get_by_import(&mut store, import).is_none())
+        .collect()
+}
+
+/// Names of the items `linker` provides in the import module `module`
+pub fn provided_imports(engine: &Engine, linker: &Linker<StoreState>, module: &str) -> Vec<String> {
+    let state = StoreState::new(HostInterface::new(), &[], None, &WasiPolicy::default());
+    let mut store = Store::new(engine, state);
+    linker
+        .iter(&mut store)
+        .filter(|(provider, _, _)| *provider == module)
+        .map(|(_, name, _)| name.to_string())
+        .collect()
+}
+
+/// Link stubs for the function imports of `module` that `linker` does not provide
+///
+/// Each stub logs a warning the first time it is called and returns zeros, so
+/// packages built against newer optional APIs still start on this host.
+/// Returns the stubbed imports as `module::name`.
+pub fn stub_unknown_imports(
+    engine: &Engine,
+    linker: &mut Linker<StoreState>,
+    module: &Module,
+) -> Result<Vec<String>> {
+    let mut stubbed = Vec::new();
+    for import in missing_imports(engine, linker, module) {
+        // Unknown memories, tables and globals still fail instantiation
+        let ExternType::Func(ty) = import.ty() else {
+            continue;
+        };
+        let name = format!("{}::{}", import.module(), import.name());
+        let result_types: Vec<ValType> = ty.results().collect();
+        let called = AtomicBool::new(false);
+        let stub_name = name.clone();
+        linker
+            .func_new(
+                import.module(),
+                import.name(),
+                ty,
+                move |_caller, _params, results| {
+                    if !called.swap(true, Ordering::Relaxed) {
+                        warn!("Guest called unknown import {}; returning zeros", stub_name);
+                    }
+                    for (result, ty) in results.iter_mut().zip(&result_types) {
+                        *result = zero_value(ty)?;
+                    }
+                    Ok(())
+                },
+            )
+            .with_context(|| format!("Failed to stub import {}", name))?;
+        stubbed.push(name);
+    }
+    Ok(stubbed)
+}
+
+/// Wrap the function imports of `module` that need one of the `first_use`
+/// permissions, so the user is asked on the first call
+///
+/// Once allowed, calls go through to the host's implementation; denied calls
+/// return zeros, or `ACCES` for WASI functions. Returns the gated imports as
+/// `module::name`.
+pub fn gate_first_use_imports(
+    store: &mut Store<StoreState>,
+    linker: &mut Linker<StoreState>,
+    module: &Module,
+    first_use: &[FirstUse],
+) -> Result<Vec<String>> {
+    let mut gated = Vec::new();
+    linker.allow_shadowing(true);
+    for import in module.imports() {
+        let ExternType::Func(ty) = import.ty() else {
+            continue;
+        };
+        let Some(gate) = Permission::from_import(import.module(), import.name())
+            .and_then(|permission| {
+                first_use
+                    .iter()
+                    .find(|gate| gate.permission() == permission)
+            })
+            .cloned()
+        else {
+            continue;
+        };
+        let Some(host_func) = linker
+            .get(&mut *store, import.module(), import.name())
+            .and_then(Extern::into_func)
+        else {
+            continue;
+        };
+        let name = format!("{}::{}", import.module(), import.name());
+        let denied_results = ty
+            .results()
+            .map(|ty| denied_value(import.module(), &ty))
+            .collect::<Result<Vec<Val>>>()?;
+        linker
+            .func_new(
+                import.module(),
+                import.name(),
+                ty,
+                move |mut caller, params, results| {
+                    if gate.allowed() {
+                        return host_func.call(&mut caller, params, results);
+                    }
+                    results.clone_from_slice(&denied_results);
+                    Ok(())
+                },
+            )
+            .with_context(|| format!("Failed to gate import {}", name))?;
+        gated.push(name);
+    }
+    linker.allow_shadowing(false);
+    Ok(gated)
+}
+
+/// Calls the guest made to each of its function imports
+#[derive(Debug, Default)]
+pub struct ImportCalls(Vec<(String, AtomicU64)>);
+
+impl ImportCalls {
+    /// Imports called at least once, as `module::name`, with their call count
+    pub fn counts(&self) -> Vec<(String, u64)> {
+        self.0
+            .iter()
+            .map(|(name, calls)| (name.clone(), calls.load(Ordering::Relaxed)))
+            .filter(|&(_, calls)| calls > 0)
+            .collect()
+    }
+}
+
+/// Wrap the function imports of `module` to count their calls, shadowing
+/// whatever the linker provides, stubs and gates included
+pub fn count_import_calls(
+    store: &mut Store<StoreState>,
+    linker: &mut Linker<StoreState>,
+    module: &Module,
+) -> Result<Arc<ImportCalls>> {
+    let imports: Vec<_> = module
+        .imports()
+        .filter_map(|import| match import.ty() {
+            ExternType::Func(ty) => Some((import.module(), import.name(), ty)),
+            _ => None,
+        })
+        .collect();
+    let calls = Arc::new(ImportCalls(
+        imports
+            .iter()
+            .map(|(module, name, _)| (format!("{}::{}", module, name), AtomicU64::new(0)))
+            .collect(),
+    ));
+    linker.allow_shadowing(true);
+    for (index, (module, name, ty)) in imports.into_iter().enumerate() {
+        let Some(host_func) = linker
+            .get(&mut *store, module, name)
+            .and_then(Extern::into_func)
+        else {
+            continue;
+        };
+        let counter = calls.clone();
+        linker
+            .func_new(module, name, ty, move |mut caller, params, results| {
+                counter.0[index].1.fetch_add(1, Ordering::Relaxed);
+                host_func.call(&mut caller, params, results)
+            })
+            .with_context(|| format!("Failed to count calls to {}::{}", module, name))?;
+    }
+    linker.allow_shadowing(false);
+    Ok(calls)
+}
+
+/// Link stubs denying the function imports of `module` whose capability is
+/// not `declared`, shadowing the host's implementations
+///
+/// Each stub logs a warning the first time it is called and returns zeros,
+/// or `ACCES` for WASI functions. Returns the denied imports as `module::name`.
+pub fn deny_undeclared_imports(
+    linker: &mut Linker<StoreState>,
+    module: &Module,
+    declared: &BTreeSet<Capability>,
+) -> Result<Vec<String>> {
+    let mut denied = Vec::new();
+    linker.allow_shadowing(true);
+    for import in module.imports() {
+        let ExternType::Func(ty) = import.ty() else {
+            continue;
+        };
+        let Some(capability) = Capability::from_import(import.module(), import.name()) else {
+            continue;
+        };
+        if declared.contains(&capability) {
+            continue;
         }
+        let name = format!("{}::{}", import.module(), import.name());
+        let denied_results = ty
+            .results()
+            .map(|ty| denied_value(import.module(), &ty))
+            .collect::<Result<Vec<Val>>>()?;
+        let called = AtomicBool::new(false);
+        let stub_name = name.clone();
+        linker
+            .func_new(
+                import.module(),
+                import.name(),
+                ty,
+                move |_caller, _params, results| {
+                    if !called.swap(true, Ordering::Relaxed) {
+                        warn!(
+                            "Guest called {} without declaring the {:?} capability; denied",
+                            stub_name,
+                            capability.name()
+                        );
+                    }
+                    results.clone_from_slice(&denied_results);
+                    Ok(())
+                },
+            )
+            .with_context(|| format!("Failed to deny import {}", name))?;
+        denied.push(name);
     }
+    linker.allow_shadowing(false);
+    Ok(denied)
+}
+
+/// Value returned by denied imports: `ACCES` for the errno of WASI
+/// functions, zero otherwise
+fn denied_value(module: &str, ty: &ValType) -> Result<Val> {
+    match ty {
+        ValType::I32 if module == "wasi_snapshot_preview1" => Ok(Val::I32(WASI_ERRNO_ACCES)),
+        ty => zero_value(ty),
+    }
+}
+
+/// Zero of a value type, returned by stubbed imports
+fn zero_value(ty: &ValType) -> Result<Val> {
+    Ok(match ty {
+        ValType::I32 => Val::I32(0),
+        ValType::I64 => Val::I64(0),
+        ValType::F32 => Val::F32(0),
+        ValType::F64 => Val::F64(0),
+        ValType::V128 => Val::V128(0u128.into()),
+        ValType::Ref(_) => bail!("Stubbed imports cannot return references"),
+    })
+}
+
+/// Settings of the engines guests run on, which precompiled modules must
+/// have been built with
+pub fn engine_config() -> Config {
+    let mut config = Config::new();
+    // Epoch interruption lets a watchdog stop runaway guest calls
+    config.epoch_interruption(true);
+    // Crash screens and reports show the guest's call stack
+    config.wasm_backtrace(true);
+    // Modules using shared memories are still only run with --enable-threads,
+    // see `wasi_threads`
+    config.wasm_threads(true);
+    config
 }
 
 /// WASM Runtime manages the Wasmtime execution environment
@@ -49,31 +2300,108 @@ pub struct WasmRuntime {
     instance: Instance,
     // Cached function handles for exports
     update_fn: Option<TypedFunc<f64, ()>>,
+    init_fn: Option<TypedFunc<(i32, i32), ()>>,
+    shutdown_fn: Option<TypedFunc<(), ()>>,
     on_resize_fn: Option<TypedFunc<(i32, i32), ()>>,
-    on_pointer_move_fn: Option<TypedFunc<(i32, i32), ()>>,
+    on_scale_changed_fn: Option<TypedFunc<f32, ()>>,
+    on_pointer_move_fn: Option<TypedFunc<(i32, i32, i32, i32), ()>>,
+    // `on_pointer_move(x, y)`, from before relative motion was passed
+    on_pointer_move_position_fn: Option<TypedFunc<(i32, i32), ()>>,
     on_pointer_down_fn: Option<TypedFunc<(i32, i32, i32), ()>>,
     on_pointer_up_fn: Option<TypedFunc<(i32, i32, i32), ()>>,
-    on_key_down_fn: Option<TypedFunc<i32, ()>>,
+    on_touch_down_fn: Option<TypedFunc<(i64, f32, f32, f32), ()>>,
+    on_touch_move_fn: Option<TypedFunc<(i64, f32, f32, f32), ()>>,
+    on_touch_up_fn: Option<TypedFunc<(i64, f32, f32, f32), ()>>,
+    on_key_down_fn: Option<TypedFunc<(i32, i32, i32), ()>>,
+    // `on_key_down(scancode)`, from before modifiers and repeats were passed
+    on_key_down_scancode_fn: Option<TypedFunc<i32, ()>>,
     on_key_up_fn: Option<TypedFunc<i32, ()>>,
+    on_scroll_fn: Option<TypedFunc<(i32, i32), ()>>,
+    on_scroll_precise_fn: Option<TypedFunc<(f32, f32), ()>>,
+    on_text_input_fn: Option<TypedFunc<(i32, i32), ()>>,
+    on_text_editing_fn: Option<TypedFunc<(i32, i32, i32), ()>>,
+    // Guest allocator receiving the text, files and messages passed to callbacks
+    allocator: Option<GuestAllocator>,
+    on_describe_fn: Option<TypedFunc<(i32, i32), i32>>,
+    on_present_fn: Option<TypedFunc<(i64, i64), ()>>,
+    on_idle_fn: Option<TypedFunc<(), ()>>,
+    on_player_fn: Option<TypedFunc<i32, ()>>,
+    on_performance_warning_fn: Option<TypedFunc<i32, ()>>,
+    on_perf_hint_fn: Option<TypedFunc<i32, ()>>,
+    on_memory_pressure_fn: Option<TypedFunc<(i32, i32), ()>>,
+    on_ws_message_fn: Option<TypedFunc<(i32, i32, i32), ()>>,
+    on_file_opened_fn: Option<TypedFunc<(i32, i32), ()>>,
+    on_file_saved_fn: Option<TypedFunc<i32, ()>>,
+    on_file_dropped_fn: Option<TypedFunc<(i32, i32, i32, i32), ()>>,
+    on_bus_message_fn: Option<TypedFunc<(i32, i32, i32, i32), ()>>,
+    on_timer_fn: Option<TypedFunc<i32, ()>>,
+    on_reload_fn: Option<TypedFunc<(), ()>>,
+    on_focus_fn: Option<TypedFunc<(), ()>>,
+    on_blur_fn: Option<TypedFunc<(), ()>>,
+    on_visibility_fn: Option<TypedFunc<i32, ()>>,
+    on_close_request_fn: Option<TypedFunc<(), i32>>,
+    // Host-owned scratch region in guest memory for on_describe
+    describe_buffer: Option<i32>,
     // Memory reference for frame data access
-    memory: Memory,
+    memory: GuestMemory,
     // Shared host interface
     host_interface: Arc<Mutex<HostInterface>>,
+    // Interrupts guest calls running past their budget, if enabled
+    watchdog: Option<Watchdog>,
+    // Time the last `run_frame` spent delivering events, before `update`
+    dispatch_time: Duration,
+    // Whether `init` was called, or the guest's memory restored
+    initialized: bool,
+    // Calls to each import, if the host asked for them to be counted
+    import_calls: Option<Arc<ImportCalls>>,
+    // Threads the guest started, if it uses a shared memory
+    threads: Option<Arc<WasiThreads>>,
 }
 
 impl WasmRuntime {
     /// Create a new WASM runtime and instantiate the given module
-    pub fn new(wasm_bytes: &[u8], host_interface: HostInterface) -> Result<Self> {
-        // Create engine with default configuration
-        let engine = Engine::default();
+    ///
+    /// `args` are exposed to the guest as WASI arguments (`argv`). The guest's
+    /// clock and random values follow `policy`; when a `session` is given, they
+    /// are also recorded into it or replayed from it. With
+    /// `allow_unknown_imports`, imports this host lacks are linked to stubs.
+    /// Imports of capabilities the package does not declare are denied, see
+    /// `HostInterface::set_capabilities`, and those needing a permission are
+    /// gated until the user allows it, see `HostInterface::set_first_use`.
+    /// Linear memory may not grow past `memory_limit` bytes, if given, and
+    /// modules declaring a larger memory are rejected. A `precompiled` copy of
+    /// the module, from a trusted package, is used instead of compiling it
+    /// when it was built for this host.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wasm_bytes: &[u8],
+        precompiled: Option<&[u8]>,
+        host_interface: HostInterface,
+        args: &[String],
+        session: Option<&Session>,
+        policy: &WasiPolicy,
+        allow_unknown_imports: bool,
+        memory_limit: Option<usize>,
+    ) -> Result<Self> {
+        let disabled_features = host_interface.disabled_features().clone();
+        let mut config = engine_config();
+        disabled_features.configure(&mut config);
+        let engine = Engine::new(&config).context("Failed to create WASM engine")?;
+
+        let declared = host_interface.capabilities().cloned();
+        let first_use = host_interface.first_use().to_vec();
+        let count_calls = host_interface.counts_import_calls();
+        let threads_enabled = host_interface.threads_enabled();
 
         // Create store with combined state
         let host_arc = {
-            let state = StoreState::new(host_interface);
+            let mut state = StoreState::new(host_interface, args, session, policy);
+            state.limiter = MemoryLimiter::new(memory_limit);
             let arc = state.host.clone();
             let mut store = Store::new(&engine, state);
+            store.limiter(|state| &mut state.limiter);
 
-            // Configure trap handler for graceful error reporting
+            // The epoch only advances once a watchdog runs, see `set_frame_budget`
             store.set_epoch_deadline(1);
 
             (store, arc)
@@ -81,46 +2409,64 @@ impl WasmRuntime {
 
         let (mut store, host_arc_clone) = host_arc;
 
-        // Create linker and add WASI functions
-        let mut linker: Linker<StoreState> = Linker::new(&engine);
+        // Create linker with WASI and our host imports
+        let mut linker = create_linker(&engine)?;
 
-        // Add WASI Preview 1 sync functions
-        preview1::add_to_linker_sync(&mut linker, |state: &mut StoreState| &mut state.wasi)
-            .context("Failed to add WASI functions to linker")?;
+        // Compile the module
+        debug!("Compiling WASM module...");
+        let module = module_cache::compile(&engine, wasm_bytes, precompiled)
+            .map_err(|e| disabled_features.explain(wasm_bytes, e))?;
+        if let Some(limit) = memory_limit {
+            memory_limit::check_minimum(&module, limit)?;
+        }
 
-        // Add our host import: wapps::update_frame
-        linker
-            .func_wrap(
-                "wapps",
-                "update_frame",
-                |mut caller: Caller<'_, StoreState>, width: i32, height: i32, pixels_ptr: i32| {
-                    let memory = caller
-                        .get_export("memory")
-                        .and_then(|e| e.into_memory())
-                        .expect("Guest must export 'memory'");
-
-                    let data = memory.data(&caller);
-                    let ptr = pixels_ptr as usize;
-                    let len = (width * height * 4) as usize;
-
-                    if ptr + len > data.len() {
-                        warn!("update_frame: pixel buffer out of bounds");
-                        return;
-                    }
+        if wasi_threads::uses_shared_memory(&module) && !threads_enabled {
+            bail!("The app uses threads; run it with --enable-threads");
+        }
+        let shared_memory =
+            wasi_threads::link_shared_memory(&store, &mut linker, &module, memory_limit)?;
+        if allow_unknown_imports {
+            for name in stub_unknown_imports(&engine, &mut linker, &module)? {
+                warn!(
+                    "Import {} is not provided by this host; linked a stub",
+                    name
+                );
+            }
+        }
 
-                    let pixels = &data[ptr..ptr + len];
+        // Threads link the module like the main thread, gated in their own store
+        let threads = shared_memory.map(|_| {
+            let host = host_arc_clone.clone();
+            let (args, policy) = (args.to_vec(), *policy);
+            let (time, started) = (store.data().time.clone(), store.data().started);
+            let new_state: wasi_threads::StateFactory = Box::new(move |threads| {
+                StoreState::for_thread(host.clone(), &args, &policy, time.clone(), started, threads)
+            });
+            Arc::new(WasiThreads::new(
+                module.clone(),
+                linker.clone(),
+                new_state,
+                first_use.clone(),
+                declared.clone(),
+            ))
+        });
+        store.data_mut().threads = threads.clone();
 
-                    // Store frame data in host interface
-                    if let Ok(mut host) = caller.data().host.lock() {
-                        host.set_frame(width, height, pixels);
-                    }
-                },
-            )
-            .context("Failed to register update_frame import")?;
+        for name in gate_first_use_imports(&mut store, &mut linker, &module, &first_use)? {
+            debug!("Import {} asks for permission on its first call", name);
+        }
+        if let Some(declared) = &declared {
+            for name in deny_undeclared_imports(&mut linker, &module, declared)? {
+                warn!(
+                    "Import {} needs a capability the package does not declare; denied",
+                    name
+                );
+            }
+        }
 
-        // Compile the module
-        debug!("Compiling WASM module...");
-        let module = Module::new(&engine, wasm_bytes).context("Failed to compile WASM module")?;
+        let import_calls = count_calls
+            .then(|| count_import_calls(&mut store, &mut linker, &module))
+            .transpose()?;
 
         // Instantiate
         debug!("Instantiating WASM module...");
@@ -129,20 +2475,38 @@ impl WasmRuntime {
             .context("Failed to instantiate WASM module")?;
 
         // Get memory export (required)
-        let memory = instance
-            .get_memory(&mut store, "memory")
-            .context("Guest must export 'memory'")?;
+        let memory = match instance.get_export(&mut store, "memory") {
+            Some(Extern::Memory(memory)) => GuestMemory::Owned(memory),
+            Some(Extern::SharedMemory(memory)) => GuestMemory::Shared(memory),
+            _ => bail!("Guest must export 'memory'"),
+        };
 
         // Cache optional export functions
         let update_fn = instance
             .get_typed_func::<f64, ()>(&mut store, "update")
             .ok();
 
+        let init_fn = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "init")
+            .ok();
+
+        let shutdown_fn = instance
+            .get_typed_func::<(), ()>(&mut store, "shutdown")
+            .ok();
+
         let on_resize_fn = instance
             .get_typed_func::<(i32, i32), ()>(&mut store, "on_resize")
             .ok();
 
+        let on_scale_changed_fn = instance
+            .get_typed_func::<f32, ()>(&mut store, "on_scale_changed")
+            .ok();
+
         let on_pointer_move_fn = instance
+            .get_typed_func::<(i32, i32, i32, i32), ()>(&mut store, "on_pointer_move")
+            .ok();
+
+        let on_pointer_move_position_fn = instance
             .get_typed_func::<(i32, i32), ()>(&mut store, "on_pointer_move")
             .ok();
 
@@ -154,21 +2518,159 @@ impl WasmRuntime {
             .get_typed_func::<(i32, i32, i32), ()>(&mut store, "on_pointer_up")
             .ok();
 
-        let on_key_down_fn = instance
-            .get_typed_func::<i32, ()>(&mut store, "on_key_down")
+        let on_touch_down_fn = instance
+            .get_typed_func::<(i64, f32, f32, f32), ()>(&mut store, "on_touch_down")
             .ok();
 
-        let on_key_up_fn = instance
-            .get_typed_func::<i32, ()>(&mut store, "on_key_up")
+        let on_touch_move_fn = instance
+            .get_typed_func::<(i64, f32, f32, f32), ()>(&mut store, "on_touch_move")
             .ok();
 
-        // Verify required export exists
-        if update_fn.is_none() {
+        let on_touch_up_fn = instance
+            .get_typed_func::<(i64, f32, f32, f32), ()>(&mut store, "on_touch_up")
+            .ok();
+
+        let on_key_down_fn = instance
+            .get_typed_func::<(i32, i32, i32), ()>(&mut store, "on_key_down")
+            .ok();
+
+        let on_key_down_scancode_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_key_down")
+            .ok();
+
+        let on_key_up_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_key_up")
+            .ok();
+
+        let on_scroll_fn = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "on_scroll")
+            .ok();
+
+        let on_scroll_precise_fn = instance
+            .get_typed_func::<(f32, f32), ()>(&mut store, "on_scroll_precise")
+            .ok();
+
+        let on_text_input_fn = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "on_text_input")
+            .ok();
+
+        let on_text_editing_fn = instance
+            .get_typed_func::<(i32, i32, i32), ()>(&mut store, "on_text_editing")
+            .ok();
+
+        let allocator = GuestAllocator::find(&mut store, &instance);
+
+        let on_describe_fn = instance
+            .get_typed_func::<(i32, i32), i32>(&mut store, "on_describe")
+            .ok();
+
+        let on_present_fn = instance
+            .get_typed_func::<(i64, i64), ()>(&mut store, "on_present")
+            .ok();
+
+        let on_idle_fn = instance
+            .get_typed_func::<(), ()>(&mut store, "on_idle")
+            .ok();
+
+        let on_player_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_player")
+            .ok();
+
+        let on_performance_warning_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_performance_warning")
+            .ok();
+
+        let on_perf_hint_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_perf_hint")
+            .ok();
+
+        let on_memory_pressure_fn = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "on_memory_pressure")
+            .ok();
+
+        let on_ws_message_fn = instance
+            .get_typed_func::<(i32, i32, i32), ()>(&mut store, "on_ws_message")
+            .ok();
+
+        let on_file_opened_fn = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, "on_file_opened")
+            .ok();
+
+        let on_file_saved_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_file_saved")
+            .ok();
+
+        let on_file_dropped_fn = instance
+            .get_typed_func::<(i32, i32, i32, i32), ()>(&mut store, "on_file_dropped")
+            .ok();
+
+        let on_bus_message_fn = instance
+            .get_typed_func::<(i32, i32, i32, i32), ()>(&mut store, "on_bus_message")
+            .ok();
+
+        let on_timer_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_timer")
+            .ok();
+
+        let on_reload_fn = instance
+            .get_typed_func::<(), ()>(&mut store, "on_reload")
+            .ok();
+
+        let on_focus_fn = instance
+            .get_typed_func::<(), ()>(&mut store, "on_focus")
+            .ok();
+
+        let on_blur_fn = instance
+            .get_typed_func::<(), ()>(&mut store, "on_blur")
+            .ok();
+
+        let on_visibility_fn = instance
+            .get_typed_func::<i32, ()>(&mut store, "on_visibility")
+            .ok();
+
+        let on_close_request_fn = instance
+            .get_typed_func::<(), i32>(&mut store, "on_close_request")
+            .ok();
+
+        let get_framebuffer_fn = instance
+            .get_typed_func::<(), i32>(&mut store, "get_framebuffer")
+            .ok();
+
+        // Verify required export exists
+        if update_fn.is_none() {
             bail!("Guest must export 'update(dt: f64)' function");
         }
+        if (on_text_input_fn.is_some() || on_text_editing_fn.is_some()) && allocator.is_none() {
+            warn!("Guest exports text callbacks but no 'wapps_alloc'; text input is disabled");
+        }
+        if on_ws_message_fn.is_some() && allocator.is_none() {
+            warn!("Guest exports 'on_ws_message' but no 'wapps_alloc'; messages are dropped");
+        }
+        if (on_file_opened_fn.is_some() || on_file_dropped_fn.is_some()) && allocator.is_none() {
+            warn!("Guest exports file callbacks but no 'wapps_alloc'; files are not delivered");
+        }
+        if on_bus_message_fn.is_some() && allocator.is_none() {
+            warn!("Guest exports 'on_bus_message' but no 'wapps_alloc'; messages are dropped");
+        }
 
         debug!("WASM module instantiated successfully");
         debug!("  - update: present");
+        debug!(
+            "  - init: {}",
+            if init_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - shutdown: {}",
+            if shutdown_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
         debug!(
             "  - on_resize: {}",
             if on_resize_fn.is_some() {
@@ -177,10 +2679,20 @@ impl WasmRuntime {
                 "absent"
             }
         );
+        debug!(
+            "  - on_scale_changed: {}",
+            if on_scale_changed_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
         debug!(
             "  - on_pointer_move: {}",
             if on_pointer_move_fn.is_some() {
                 "present"
+            } else if on_pointer_move_position_fn.is_some() {
+                "present (position only)"
             } else {
                 "absent"
             }
@@ -201,10 +2713,36 @@ impl WasmRuntime {
                 "absent"
             }
         );
+        debug!(
+            "  - on_touch_down: {}",
+            if on_touch_down_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_touch_move: {}",
+            if on_touch_move_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_touch_up: {}",
+            if on_touch_up_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
         debug!(
             "  - on_key_down: {}",
             if on_key_down_fn.is_some() {
                 "present"
+            } else if on_key_down_scancode_fn.is_some() {
+                "present (scancode only)"
             } else {
                 "absent"
             }
@@ -217,24 +2755,273 @@ impl WasmRuntime {
                 "absent"
             }
         );
+        debug!(
+            "  - on_scroll: {}",
+            if on_scroll_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_scroll_precise: {}",
+            if on_scroll_precise_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_text_input: {}",
+            if on_text_input_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_text_editing: {}",
+            if on_text_editing_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_describe: {}",
+            if on_describe_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_present: {}",
+            if on_present_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_idle: {}",
+            if on_idle_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_player: {}",
+            if on_player_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_performance_warning: {}",
+            if on_performance_warning_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_perf_hint: {}",
+            if on_perf_hint_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_memory_pressure: {}",
+            if on_memory_pressure_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_reload: {}",
+            if on_reload_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_focus: {}",
+            if on_focus_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_blur: {}",
+            if on_blur_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_visibility: {}",
+            if on_visibility_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_close_request: {}",
+            if on_close_request_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_ws_message: {}",
+            if on_ws_message_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_file_opened: {}",
+            if on_file_opened_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_file_saved: {}",
+            if on_file_saved_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_file_dropped: {}",
+            if on_file_dropped_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_bus_message: {}",
+            if on_bus_message_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - on_timer: {}",
+            if on_timer_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+        debug!(
+            "  - get_framebuffer: {}",
+            if get_framebuffer_fn.is_some() {
+                "present"
+            } else {
+                "absent"
+            }
+        );
+
+        // Frames presented from the buffer the guest shares are read in
+        // place; a null pointer opts out
+        if let Some(func) = &get_framebuffer_fn {
+            let ptr = func
+                .call(&mut store, ())
+                .context("Error calling guest 'get_framebuffer' function")?;
+            if ptr != 0 {
+                if let Ok(mut host) = host_arc_clone.lock() {
+                    host.set_framebuffer_ptr(ptr as u32);
+                }
+            }
+        }
 
         Ok(Self {
             store,
             instance,
             update_fn,
+            init_fn,
+            shutdown_fn,
             on_resize_fn,
+            on_scale_changed_fn,
             on_pointer_move_fn,
+            on_pointer_move_position_fn,
             on_pointer_down_fn,
             on_pointer_up_fn,
+            on_touch_down_fn,
+            on_touch_move_fn,
+            on_touch_up_fn,
             on_key_down_fn,
+            on_key_down_scancode_fn,
             on_key_up_fn,
+            on_scroll_fn,
+            on_scroll_precise_fn,
+            on_text_input_fn,
+            on_text_editing_fn,
+            allocator,
+            on_describe_fn,
+            on_present_fn,
+            on_idle_fn,
+            on_player_fn,
+            on_performance_warning_fn,
+            on_perf_hint_fn,
+            on_memory_pressure_fn,
+            on_ws_message_fn,
+            on_file_opened_fn,
+            on_file_saved_fn,
+            on_file_dropped_fn,
+            on_bus_message_fn,
+            on_timer_fn,
+            on_reload_fn,
+            on_focus_fn,
+            on_blur_fn,
+            on_visibility_fn,
+            on_close_request_fn,
+            describe_buffer: None,
             memory,
             host_interface: host_arc_clone,
+            watchdog: None,
+            dispatch_time: Duration::ZERO,
+            initialized: false,
+            import_calls,
+            threads,
         })
     }
 
+    /// Interrupt any call into the guest that runs longer than `budget`, or
+    /// never with `None`
+    pub fn set_frame_budget(&mut self, budget: Option<Duration>) {
+        self.watchdog = budget.map(|budget| Watchdog::start(self.store.engine().clone(), budget));
+    }
+
+    /// Give the next call into the guest its full budget
+    fn arm_watchdog(&mut self) {
+        if let Some(watchdog) = &self.watchdog {
+            self.store.set_epoch_deadline(watchdog.deadline_ticks());
+        }
+    }
+
     /// Call the guest's update function
     pub fn call_update(&mut self, dt: f64) -> Result<()> {
+        self.store.data().time.advance(dt);
+        self.arm_watchdog();
         if let Some(func) = &self.update_fn {
             func.call(&mut self.store, dt)
                 .context("Error calling guest 'update' function")?;
@@ -242,26 +3029,69 @@ impl WasmRuntime {
         Ok(())
     }
 
-    /// Call the guest's on_resize function (if present)
-    pub fn call_on_resize(&mut self, width: i32, height: i32) -> Result<()> {
-        if let Some(func) = &self.on_resize_fn {
+    /// Call the guest's init function (if present) with the window size,
+    /// unless the guest is already initialized
+    pub fn call_init(&mut self, width: i32, height: i32) -> Result<()> {
+        if std::mem::replace(&mut self.initialized, true) {
+            return Ok(());
+        }
+        self.set_viewport(width, height);
+        self.arm_watchdog();
+        if let Some(func) = &self.init_fn {
             func.call(&mut self.store, (width, height))
-                .context("Error calling guest 'on_resize' function")?;
+                .context("Error calling guest 'init' function")?;
         }
         Ok(())
     }
 
-    /// Call the guest's on_pointer_move function (if present)
-    pub fn call_on_pointer_move(&mut self, x: i32, y: i32) -> Result<()> {
-        if let Some(func) = &self.on_pointer_move_fn {
-            func.call(&mut self.store, (x, y))
-                .context("Error calling guest 'on_pointer_move' function")?;
+    /// Call the guest's shutdown function (if present); the guest must not
+    /// be called again afterwards
+    pub fn call_shutdown(&mut self) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.shutdown_fn {
+            func.call(&mut self.store, ())
+                .context("Error calling guest 'shutdown' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_resize function (if present)
+    pub fn call_on_resize(&mut self, width: i32, height: i32) -> Result<()> {
+        self.set_viewport(width, height);
+        self.arm_watchdog();
+        if let Some(func) = &self.on_resize_fn {
+            func.call(&mut self.store, (width, height))
+                .context("Error calling guest 'on_resize' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_scale_changed function (if present)
+    pub fn call_on_scale_changed(&mut self, scale: f32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_scale_changed_fn {
+            func.call(&mut self.store, scale)
+                .context("Error calling guest 'on_scale_changed' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_pointer_move function (if present)
+    pub fn call_on_pointer_move(&mut self, x: i32, y: i32, xrel: i32, yrel: i32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_pointer_move_fn {
+            func.call(&mut self.store, (x, y, xrel, yrel))
+                .context("Error calling guest 'on_pointer_move' function")?;
+        } else if let Some(func) = &self.on_pointer_move_position_fn {
+            func.call(&mut self.store, (x, y))
+                .context("Error calling guest 'on_pointer_move' function")?;
         }
         Ok(())
     }
 
     /// Call the guest's on_pointer_down function (if present)
     pub fn call_on_pointer_down(&mut self, x: i32, y: i32, button: i32) -> Result<()> {
+        self.arm_watchdog();
         if let Some(func) = &self.on_pointer_down_fn {
             func.call(&mut self.store, (x, y, button))
                 .context("Error calling guest 'on_pointer_down' function")?;
@@ -271,6 +3101,7 @@ impl WasmRuntime {
 
     /// Call the guest's on_pointer_up function (if present)
     pub fn call_on_pointer_up(&mut self, x: i32, y: i32, button: i32) -> Result<()> {
+        self.arm_watchdog();
         if let Some(func) = &self.on_pointer_up_fn {
             func.call(&mut self.store, (x, y, button))
                 .context("Error calling guest 'on_pointer_up' function")?;
@@ -278,9 +3109,44 @@ impl WasmRuntime {
         Ok(())
     }
 
-    /// Call the guest's on_key_down function (if present)
-    pub fn call_on_key_down(&mut self, scancode: i32) -> Result<()> {
+    /// Call the guest's on_touch_down function (if present)
+    pub fn call_on_touch_down(&mut self, id: i64, x: f32, y: f32, pressure: f32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_touch_down_fn {
+            func.call(&mut self.store, (id, x, y, pressure))
+                .context("Error calling guest 'on_touch_down' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_touch_move function (if present)
+    pub fn call_on_touch_move(&mut self, id: i64, x: f32, y: f32, pressure: f32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_touch_move_fn {
+            func.call(&mut self.store, (id, x, y, pressure))
+                .context("Error calling guest 'on_touch_move' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_touch_up function (if present)
+    pub fn call_on_touch_up(&mut self, id: i64, x: f32, y: f32, pressure: f32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_touch_up_fn {
+            func.call(&mut self.store, (id, x, y, pressure))
+                .context("Error calling guest 'on_touch_up' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_key_down function (if present), passing the
+    /// modifiers and repeat flag unless it only takes a scancode
+    pub fn call_on_key_down(&mut self, scancode: i32, modifiers: i32, repeat: bool) -> Result<()> {
+        self.arm_watchdog();
         if let Some(func) = &self.on_key_down_fn {
+            func.call(&mut self.store, (scancode, modifiers, repeat as i32))
+                .context("Error calling guest 'on_key_down' function")?;
+        } else if let Some(func) = &self.on_key_down_scancode_fn {
             func.call(&mut self.store, scancode)
                 .context("Error calling guest 'on_key_down' function")?;
         }
@@ -289,6 +3155,7 @@ impl WasmRuntime {
 
     /// Call the guest's on_key_up function (if present)
     pub fn call_on_key_up(&mut self, scancode: i32) -> Result<()> {
+        self.arm_watchdog();
         if let Some(func) = &self.on_key_up_fn {
             func.call(&mut self.store, scancode)
                 .context("Error calling guest 'on_key_up' function")?;
@@ -296,15 +3163,810 @@ impl WasmRuntime {
         Ok(())
     }
 
+    /// Deliver a scroll to `on_scroll_precise` if the guest exports it, else
+    /// to `on_scroll` when at least one whole notch was scrolled
+    pub fn call_on_scroll(
+        &mut self,
+        dx: i32,
+        dy: i32,
+        precise_dx: f32,
+        precise_dy: f32,
+    ) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_scroll_precise_fn {
+            func.call(&mut self.store, (precise_dx, precise_dy))
+                .context("Error calling guest 'on_scroll_precise' function")?;
+        } else if let Some(func) = &self.on_scroll_fn {
+            if dx != 0 || dy != 0 {
+                func.call(&mut self.store, (dx, dy))
+                    .context("Error calling guest 'on_scroll' function")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_text_input function (if present) with typed text
+    pub fn call_on_text_input(&mut self, text: &str) -> Result<()> {
+        self.arm_watchdog();
+        let Some(func) = self.on_text_input_fn.clone() else {
+            return Ok(());
+        };
+        let Some((ptr, len)) = self.copy_to_guest(text.as_bytes())? else {
+            return Ok(());
+        };
+        func.call(&mut self.store, (ptr, len))
+            .context("Error calling guest 'on_text_input' function")?;
+        self.free_in_guest(ptr, len)
+    }
+
+    /// Call the guest's on_text_editing function (if present) with an input
+    /// method composition
+    pub fn call_on_text_editing(&mut self, text: &str, cursor: i32) -> Result<()> {
+        self.arm_watchdog();
+        let Some(func) = self.on_text_editing_fn.clone() else {
+            return Ok(());
+        };
+        let Some((ptr, len)) = self.copy_to_guest(text.as_bytes())? else {
+            return Ok(());
+        };
+        func.call(&mut self.store, (ptr, len, cursor))
+            .context("Error calling guest 'on_text_editing' function")?;
+        self.free_in_guest(ptr, len)
+    }
+
+    /// Copy `bytes` into memory obtained from the guest's `wapps_alloc`
+    ///
+    /// Returns the (pointer, length) pair, or `None` if the guest has no
+    /// allocator or is out of memory.
+    fn copy_to_guest(&mut self, bytes: &[u8]) -> Result<Option<(i32, i32)>> {
+        match &self.allocator {
+            Some(allocator) => allocator.copy_in(&mut self.store, &self.memory, bytes),
+            None => Ok(None),
+        }
+    }
+
+    /// Release memory from [`copy_to_guest`](Self::copy_to_guest) via the guest's
+    /// `wapps_free`; without one the guest owns the allocation
+    fn free_in_guest(&mut self, ptr: i32, len: i32) -> Result<()> {
+        match &self.allocator {
+            Some(allocator) => allocator.free(&mut self.store, ptr, len),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the guest exports `on_present`
+    pub fn wants_present_time(&self) -> bool {
+        self.on_present_fn.is_some()
+    }
+
+    /// Call the guest's on_present function (if present)
+    pub fn call_on_present(&mut self, frame_index: u64, present_time_micros: u64) -> Result<()> {
+        if let Ok(mut host) = self.host_interface.lock() {
+            host.set_frame_count(frame_index + 1);
+        }
+        self.arm_watchdog();
+        if let Some(func) = &self.on_present_fn {
+            func.call(
+                &mut self.store,
+                (frame_index as i64, present_time_micros as i64),
+            )
+            .context("Error calling guest 'on_present' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_idle function (if present)
+    pub fn call_on_idle(&mut self) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_idle_fn {
+            func.call(&mut self.store, ())
+                .context("Error calling guest 'on_idle' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_focus function (if present)
+    pub fn call_on_focus(&mut self) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_focus_fn {
+            func.call(&mut self.store, ())
+                .context("Error calling guest 'on_focus' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_blur function (if present)
+    pub fn call_on_blur(&mut self) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_blur_fn {
+            func.call(&mut self.store, ())
+                .context("Error calling guest 'on_blur' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_visibility function (if present)
+    pub fn call_on_visibility(&mut self, visible: bool) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_visibility_fn {
+            func.call(&mut self.store, visible as i32)
+                .context("Error calling guest 'on_visibility' function")?;
+        }
+        Ok(())
+    }
+
+    /// Ask the guest whether its window may close, via its on_close_request
+    /// function; guests without one always allow it
+    ///
+    /// A nonzero return vetoes the close, e.g. to save first; the guest can
+    /// then exit itself, or the user can close the window again.
+    pub fn call_on_close_request(&mut self) -> Result<bool> {
+        self.arm_watchdog();
+        let Some(func) = &self.on_close_request_fn else {
+            return Ok(true);
+        };
+        let veto = func
+            .call(&mut self.store, ())
+            .context("Error calling guest 'on_close_request' function")?;
+        Ok(veto == 0)
+    }
+
+    /// Call the guest's on_player function (if present)
+    pub fn call_on_player(&mut self, index: i32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_player_fn {
+            func.call(&mut self.store, index)
+                .context("Error calling guest 'on_player' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_performance_warning function (if present)
+    pub fn call_on_performance_warning(&mut self, level: i32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_performance_warning_fn {
+            func.call(&mut self.store, level)
+                .context("Error calling guest 'on_performance_warning' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_perf_hint function (if present)
+    pub fn call_on_perf_hint(&mut self, level: i32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_perf_hint_fn {
+            func.call(&mut self.store, level)
+                .context("Error calling guest 'on_perf_hint' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_memory_pressure function (if present)
+    pub fn call_on_memory_pressure(&mut self, current_pages: i32, limit_pages: i32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_memory_pressure_fn {
+            func.call(&mut self.store, (current_pages, limit_pages))
+                .context("Error calling guest 'on_memory_pressure' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_ws_message function (if present) with a message
+    /// received on the WebSocket `handle`
+    pub fn call_on_ws_message(&mut self, handle: i32, message: &[u8]) -> Result<()> {
+        self.arm_watchdog();
+        let Some(func) = self.on_ws_message_fn.clone() else {
+            return Ok(());
+        };
+        let Some((ptr, len)) = self.copy_to_guest(message)? else {
+            return Ok(());
+        };
+        func.call(&mut self.store, (handle, ptr, len))
+            .context("Error calling guest 'on_ws_message' function")?;
+        self.free_in_guest(ptr, len)
+    }
+
+    /// Call the guest's on_file_opened function (if present) with the
+    /// contents of the file the user chose, or a length of -1 if none
+    pub fn call_on_file_opened(&mut self, data: Option<&[u8]>) -> Result<()> {
+        self.arm_watchdog();
+        let Some(func) = self.on_file_opened_fn.clone() else {
+            return Ok(());
+        };
+        let Some(data) = data else {
+            return func
+                .call(&mut self.store, (0, -1))
+                .context("Error calling guest 'on_file_opened' function");
+        };
+        let Some((ptr, len)) = self.copy_to_guest(data)? else {
+            return Ok(());
+        };
+        func.call(&mut self.store, (ptr, len))
+            .context("Error calling guest 'on_file_opened' function")?;
+        self.free_in_guest(ptr, len)
+    }
+
+    /// Call the guest's on_file_saved function (if present) with a
+    /// `FILE_SAVED`, `FILE_CANCELLED` or `FILE_WRITE_FAILED` status
+    pub fn call_on_file_saved(&mut self, status: i32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_file_saved_fn {
+            func.call(&mut self.store, status)
+                .context("Error calling guest 'on_file_saved' function")?;
+        }
+        Ok(())
+    }
+
+    /// Call the guest's on_timer function (if present) with the id of a
+    /// timer that fired
+    pub fn call_on_timer(&mut self, id: i32) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_timer_fn {
+            func.call(&mut self.store, id)
+                .context("Error calling guest 'on_timer' function")?;
+        }
+        Ok(())
+    }
+
+    /// Whether the guest wants a frame under `--power-save`, `elapsed`
+    /// seconds after its last one: it requested a redraw, or a timer or the
+    /// update it asked for is due
+    pub fn wants_frame(&self, elapsed: f64) -> bool {
+        match self.host_interface.lock() {
+            Ok(host) => {
+                host.redraw_requested() || host.next_wake().is_some_and(|next| next <= elapsed)
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// Seconds after its last frame until the guest's next timer fires or
+    /// the update it asked for is due, if either is scheduled
+    pub fn next_wake(&self) -> Option<f64> {
+        self.host_interface.lock().ok()?.next_wake()
+    }
+
+    /// Advance the guest's timers by `dt` and deliver those that fired
+    fn fire_timers(&mut self, dt: f64) -> Result<()> {
+        let fired = match self.host_interface.lock() {
+            Ok(mut host) => host.advance_timers(dt),
+            Err(_) => return Ok(()),
+        };
+        fired.into_iter().try_for_each(|id| self.call_on_timer(id))
+    }
+
+    /// Whether the guest exports `on_file_dropped`
+    pub fn wants_dropped_files(&self) -> bool {
+        self.on_file_dropped_fn.is_some()
+    }
+
+    /// Call the guest's on_file_dropped function (if present) with the name
+    /// and contents of a file dropped on the window
+    pub fn call_on_file_dropped(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.arm_watchdog();
+        let Some(func) = self.on_file_dropped_fn.clone() else {
+            return Ok(());
+        };
+        let Some((name_ptr, name_len)) = self.copy_to_guest(name.as_bytes())? else {
+            return Ok(());
+        };
+        let Some((data_ptr, data_len)) = self.copy_to_guest(data)? else {
+            return self.free_in_guest(name_ptr, name_len);
+        };
+        func.call(&mut self.store, (name_ptr, name_len, data_ptr, data_len))
+            .context("Error calling guest 'on_file_dropped' function")?;
+        self.free_in_guest(data_ptr, data_len)?;
+        self.free_in_guest(name_ptr, name_len)
+    }
+
+    /// Call the guest's on_bus_message function (if present) with a message
+    /// another app sent under a topic this guest subscribed to
+    pub fn call_on_bus_message(&mut self, topic: &str, data: &[u8]) -> Result<()> {
+        self.arm_watchdog();
+        let Some(func) = self.on_bus_message_fn.clone() else {
+            return Ok(());
+        };
+        let Some((topic_ptr, topic_len)) = self.copy_to_guest(topic.as_bytes())? else {
+            return Ok(());
+        };
+        let Some((data_ptr, data_len)) = self.copy_to_guest(data)? else {
+            return self.free_in_guest(topic_ptr, topic_len);
+        };
+        func.call(&mut self.store, (topic_ptr, topic_len, data_ptr, data_len))
+            .context("Error calling guest 'on_bus_message' function")?;
+        self.free_in_guest(data_ptr, data_len)?;
+        self.free_in_guest(topic_ptr, topic_len)
+    }
+
+    /// Call the guest's on_reload function (if present)
+    pub fn call_on_reload(&mut self) -> Result<()> {
+        self.arm_watchdog();
+        if let Some(func) = &self.on_reload_fn {
+            func.call(&mut self.store, ())
+                .context("Error calling guest 'on_reload' function")?;
+        }
+        Ok(())
+    }
+
+    /// Ask the guest for a textual description of the current screen
+    ///
+    /// Returns `None` if the guest does not export `on_describe(buf, cap) -> len`.
+    /// The host reserves one page past the guest's memory as the buffer, so the
+    /// guest allocator never hands it out. A returned length larger than `cap`
+    /// means the description was truncated.
+    pub fn describe(&mut self) -> Result<Option<String>> {
+        self.arm_watchdog();
+        let Some(func) = &self.on_describe_fn else {
+            return Ok(None);
+        };
+
+        let buffer = match self.describe_buffer {
+            Some(buffer) => buffer,
+            None => {
+                let previous_pages = self
+                    .memory
+                    .grow(&mut self.store, 1)
+                    .context("Failed to reserve a buffer for 'on_describe'")?;
+                let buffer = i32::try_from(previous_pages * DESCRIBE_BUFFER_SIZE as u64)
+                    .context("Guest memory too large for an 'on_describe' buffer")?;
+                self.describe_buffer = Some(buffer);
+                buffer
+            }
+        };
+
+        let len = func
+            .call(&mut self.store, (buffer, DESCRIBE_BUFFER_SIZE))
+            .context("Error calling guest 'on_describe' function")?;
+        if len < 0 {
+            bail!("Guest 'on_describe' returned error {}", len);
+        }
+        if len > DESCRIBE_BUFFER_SIZE {
+            warn!(
+                "on_describe: description truncated from {} to {} bytes",
+                len, DESCRIBE_BUFFER_SIZE
+            );
+        }
+
+        let len = len.min(DESCRIBE_BUFFER_SIZE) as usize;
+        let bytes = self
+            .memory
+            .read(&self.store, buffer as usize, len)
+            .context("on_describe returned a buffer out of bounds")?;
+        Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    /// Track the viewport size for `wapps::get_window_size`
+    fn set_viewport(&mut self, width: i32, height: i32) {
+        if let Ok(mut host) = self.host_interface.lock() {
+            host.set_viewport(width, height);
+        }
+    }
+
+    /// Set the display refresh rate reported by `wapps::get_display_refresh_rate`
+    pub fn set_refresh_rate(&mut self, refresh_rate: i32) {
+        if let Ok(mut host) = self.host_interface.lock() {
+            host.set_refresh_rate(refresh_rate);
+        }
+    }
+
+    /// Track held keys for `wapps::query_key_state`
+    fn set_key_held(&mut self, scancode: i32, held: bool) {
+        if let Ok(mut host) = self.host_interface.lock() {
+            host.set_key_held(scancode, held);
+        }
+    }
+
+    /// Dispatch a queued input event to the matching guest callback
+    pub fn dispatch_event(&mut self, event: &GuestEvent) -> Result<()> {
+        match *event {
+            GuestEvent::Resize { width, height } => self.call_on_resize(width, height),
+            GuestEvent::ScaleChanged { scale } => self.call_on_scale_changed(scale),
+            GuestEvent::PointerMove { x, y, xrel, yrel } => {
+                self.call_on_pointer_move(x, y, xrel, yrel)
+            }
+            GuestEvent::PointerDown { x, y, button } => self.call_on_pointer_down(x, y, button),
+            GuestEvent::PointerUp { x, y, button } => self.call_on_pointer_up(x, y, button),
+            GuestEvent::TouchDown { id, x, y, pressure } => {
+                self.call_on_touch_down(id, x, y, pressure)
+            }
+            GuestEvent::TouchMove { id, x, y, pressure } => {
+                self.call_on_touch_move(id, x, y, pressure)
+            }
+            GuestEvent::TouchUp { id, x, y, pressure } => self.call_on_touch_up(id, x, y, pressure),
+            GuestEvent::Scroll {
+                dx,
+                dy,
+                precise_dx,
+                precise_dy,
+            } => self.call_on_scroll(dx, dy, precise_dx, precise_dy),
+            GuestEvent::KeyDown {
+                scancode,
+                modifiers,
+                repeat,
+            } => {
+                self.set_key_held(scancode, true);
+                self.call_on_key_down(scancode, modifiers, repeat)
+            }
+            GuestEvent::KeyUp { scancode } => {
+                self.set_key_held(scancode, false);
+                self.call_on_key_up(scancode)
+            }
+            GuestEvent::TextInput { ref text } => self.call_on_text_input(text),
+            GuestEvent::TextEditing { ref text, cursor } => self.call_on_text_editing(text, cursor),
+            GuestEvent::Idle => self.call_on_idle(),
+            GuestEvent::Focus => self.call_on_focus(),
+            GuestEvent::Blur => self.call_on_blur(),
+            GuestEvent::Visibility { visible } => self.call_on_visibility(visible),
+            GuestEvent::Player { index } => self.call_on_player(index),
+            GuestEvent::PerformanceWarning { level } => self.call_on_performance_warning(level),
+            GuestEvent::PerfHint { level } => self.call_on_perf_hint(level),
+            GuestEvent::MemoryPressure {
+                current_pages,
+                limit_pages,
+            } => self.call_on_memory_pressure(current_pages, limit_pages),
+            GuestEvent::WsMessage { handle, ref data } => self.call_on_ws_message(handle, data),
+            GuestEvent::FileOpened { ref data } => self.call_on_file_opened(data.as_deref()),
+            GuestEvent::FileSaved { status } => self.call_on_file_saved(status),
+            GuestEvent::FileDropped { ref name, ref data } => self.call_on_file_dropped(name, data),
+            GuestEvent::BusMessage {
+                ref topic,
+                ref data,
+            } => self.call_on_bus_message(topic, data),
+        }
+    }
+
+    /// Dispatch the events queued since the last frame, then call `update`
+    ///
+    /// Each event's timestamp is readable via `wapps::event_time` while it is
+    /// being handled.
+    pub fn run_frame(&mut self, events: &[TimedEvent], dt: f64) -> Result<()> {
+        if let Some(e) = self
+            .threads
+            .as_ref()
+            .and_then(|threads| threads.take_failure())
+        {
+            return Err(e);
+        }
+        let start = Instant::now();
+        if let Ok(mut host) = self.host_interface.lock() {
+            host.clear_redraw_request();
+            host.clear_update_request();
+            host.start_frame_count();
+        }
+        // Hosts without a window leave the size to the guest
+        let result = self.call_init(0, 0).and_then(|()| {
+            events.iter().try_for_each(|event| {
+                if let Ok(mut host) = self.host_interface.lock() {
+                    host.set_event_time(event.time);
+                }
+                self.dispatch_event(&event.event)
+            })
+        });
+        self.dispatch_time = start.elapsed();
+        let result = result
+            .and_then(|()| self.fire_timers(dt))
+            .and_then(|()| self.call_update(dt));
+        let limiter = &mut self.store.data_mut().limiter;
+        let (denied, limit) = (limiter.take_denied(), limiter.limit());
+        match (&self.watchdog, result) {
+            (Some(watchdog), Err(e)) if watchdog::is_timeout(&e) => Err(e.context(format!(
+                "Guest ran past its budget of {} ms; it may be stuck in a loop",
+                watchdog.budget().as_millis()
+            ))),
+            // Guests usually abort when an allocation fails
+            (_, Err(e)) if denied => Err(e.context(format!(
+                "Guest ran out of memory: it tried to grow past the --max-memory cap of {}",
+                memory_limit::format_size(limit.unwrap_or_default() as u64)
+            ))),
+            (_, result) => result,
+        }
+    }
+
+    /// Time the last `run_frame` spent delivering events to the guest, the
+    /// rest of it being the guest's `update`
+    pub fn dispatch_time(&self) -> Duration {
+        self.dispatch_time
+    }
+
+    /// Current size of the guest's linear memory in bytes
+    pub fn memory_size(&self) -> usize {
+        self.memory.data_size(&self.store)
+    }
+
+    /// The guest's linear memory, copied out if it is shared between threads
+    pub fn memory_data(&self) -> Cow<'_, [u8]> {
+        let size = self.memory.data_size(&self.store);
+        self.memory
+            .read(&self.store, 0, size)
+            .expect("the whole memory is in bounds")
+    }
+
+    /// Copy of the guest's linear memory
+    pub fn memory_snapshot(&self) -> Vec<u8> {
+        self.memory_data().into_owned()
+    }
+
+    /// Overwrite the start of the guest's linear memory with `snapshot`,
+    /// growing it to fit
+    pub fn restore_memory(&mut self, snapshot: &[u8]) -> Result<()> {
+        let size = self.memory.data_size(&self.store);
+        if snapshot.len() > size {
+            let pages = (snapshot.len() - size).div_ceil(memory_limit::PAGE_SIZE);
+            self.memory
+                .grow(&mut self.store, pages as u64)
+                .context("Failed to grow guest memory to the snapshot's size")?;
+        }
+        self.memory.write(&mut self.store, 0, snapshot)?;
+        // The snapshot holds an initialized guest
+        self.initialized = true;
+        Ok(())
+    }
+
+    /// Save or restore the guest asked for since the last call
+    pub fn take_snapshot_request(&mut self) -> Option<SnapshotRequest> {
+        self.host_interface.lock().ok()?.take_snapshot_request()
+    }
+
+    /// Take the file dialog the guest requested, if any
+    pub fn take_file_request(&mut self) -> Option<FileRequest> {
+        self.host_interface.lock().ok()?.take_file_request()
+    }
+
+    /// Take the rumble and LED changes the guest requested since the last call
+    pub fn take_gamepad_requests(&mut self) -> Vec<GamepadRequest> {
+        match self.host_interface.lock() {
+            Ok(mut host) => host.take_gamepad_requests(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Capture the guest's memory, exported mutable globals and frame layers,
+    /// tagged with `module_hash`
+    pub fn capture_state(&mut self, module_hash: u64) -> SaveState {
+        let globals = self
+            .mutable_globals()
+            .into_iter()
+            .filter_map(|(name, global)| {
+                let value = GlobalValue::from_val(&global.get(&mut self.store))?;
+                Some((name, value))
+            })
+            .collect();
+        let (layers, app_name, app_version) = self
+            .host_interface
+            .lock()
+            .map(|host| {
+                (
+                    host.saved_layers(),
+                    host.app_name().to_string(),
+                    host.app_version().to_string(),
+                )
+            })
+            .unwrap_or_default();
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        SaveState {
+            module_hash,
+            app_name,
+            app_version,
+            saved_at,
+            memory: self.memory_snapshot(),
+            globals,
+            layers,
+        }
+    }
+
+    /// Put the guest back in a state from `capture_state`
+    ///
+    /// Memory past the end of the state, if it has grown since, is zeroed.
+    pub fn restore_state(&mut self, state: &SaveState) -> Result<()> {
+        let mut globals = self.mutable_globals();
+        for (name, value) in &state.globals {
+            let Some(index) = globals.iter().position(|(export, _)| export == name) else {
+                bail!("The app has no mutable global {:?}", name);
+            };
+            let (_, global) = globals.swap_remove(index);
+            global
+                .set(&mut self.store, value.to_val())
+                .with_context(|| format!("Failed to restore global {:?}", name))?;
+        }
+        self.restore_memory(&state.memory)?;
+        let rest = self.memory.data_size(&self.store) - state.memory.len();
+        self.memory
+            .write(&mut self.store, state.memory.len(), &vec![0; rest])?;
+        if let Ok(mut host) = self.host_interface.lock() {
+            host.restore_layers(&state.layers);
+        }
+        Ok(())
+    }
+
+    /// Exported mutable globals, by name
+    fn mutable_globals(&mut self) -> Vec<(String, Global)> {
+        self.instance
+            .exports(&mut self.store)
+            .filter_map(|export| {
+                let name = export.name().to_string();
+                Some((name, export.into_global()?))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter(|(_, global)| global.ty(&self.store).mutability() == Mutability::Var)
+            .collect()
+    }
+
+    /// Take the memory use to report through `on_memory_pressure`, as (current, limit) pages
+    pub fn take_memory_pressure(&mut self) -> Option<(u32, u32)> {
+        self.store.data_mut().limiter.take_pressure()
+    }
+
+    /// Bytes in use by the guest's allocator, if it reports them via `wapps::report_allocations`
+    pub fn heap_bytes(&self) -> Option<u64> {
+        self.host_interface.lock().ok()?.heap_bytes()
+    }
+
+    /// Calls made to each import so far, if counting was enabled with
+    /// `HostInterface::set_count_import_calls`
+    pub fn import_calls(&self) -> Option<Vec<(String, u64)>> {
+        self.import_calls.as_ref().map(|calls| calls.counts())
+    }
+
+    /// Take the lines the guest logged via `wapps::log` since the last call
+    pub fn take_log_lines(&mut self) -> Vec<(log::Level, String)> {
+        match self.host_interface.lock() {
+            Ok(mut host) => host.take_log_lines(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Take the messages received on the guest's WebSockets since the last
+    /// call, as (handle, message)
+    pub fn take_ws_messages(&mut self) -> Vec<(i32, Vec<u8>)> {
+        match self.host_interface.lock() {
+            Ok(mut host) => host.take_ws_messages(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Take the audio the guest pushed via `wapps::push_audio` since the last call
+    pub fn take_audio(&mut self) -> Option<(AudioFormat, Vec<f32>)> {
+        self.host_interface.lock().ok()?.take_audio()
+    }
+
+    /// Take the microphone capture the guest started or stopped since the
+    /// last call
+    pub fn take_capture_request(&mut self) -> Option<CaptureRequest> {
+        self.host_interface.lock().ok()?.take_capture_request()
+    }
+
+    /// Buffer the app's microphone fills for `wapps::audio_capture_read`
+    pub fn captured_audio(&self) -> Option<Arc<Mutex<CapturedAudio>>> {
+        Some(self.host_interface.lock().ok()?.captured_audio())
+    }
+
+    /// Tell the guest how many frames its audio device has yet to play
+    pub fn set_audio_device_frames(&mut self, frames: u32) {
+        if let Ok(mut host) = self.host_interface.lock() {
+            host.set_audio_device_frames(frames);
+        }
+    }
+
+    /// Take the display constraints the guest declared, if they changed
+    pub fn take_constraints(&mut self) -> Option<DisplayConstraints> {
+        self.host_interface.lock().ok()?.take_constraints()
+    }
+
+    /// Take the clear color the guest set via `wapps::set_clear_color`, if any
+    pub fn take_clear_color(&mut self) -> Option<u32> {
+        self.host_interface.lock().ok()?.take_clear_color()
+    }
+
+    /// Take the HUD the guest set or removed via `wapps::update_overlay`, if any
+    pub fn take_overlay(&mut self) -> Option<OverlayUpdate> {
+        self.host_interface.lock().ok()?.take_overlay()
+    }
+
+    /// Take the scaling mode the guest set via `wapps::set_scaling_mode`, if any
+    pub fn take_scaling_mode(&mut self) -> Option<ScalingMode> {
+        self.host_interface.lock().ok()?.take_scaling_mode()
+    }
+
+    /// Take the adjustment the guest set via `wapps::set_display_adjustment`, if any
+    pub fn take_display_adjustment(&mut self) -> Option<Adjustment> {
+        self.host_interface.lock().ok()?.take_display_adjustment()
+    }
+
+    /// Take the fullscreen state the guest requested via `wapps::set_fullscreen`, if any
+    pub fn take_fullscreen_request(&mut self) -> Option<bool> {
+        self.host_interface.lock().ok()?.take_fullscreen_request()
+    }
+
+    /// Take the title the guest set via `wapps::set_window_title`, if any
+    pub fn take_window_title(&mut self) -> Option<String> {
+        self.host_interface.lock().ok()?.take_window_title()
+    }
+
+    /// Take the window size the guest set via `wapps::set_window_size`, if any
+    pub fn take_window_size(&mut self) -> Option<WindowSize> {
+        self.host_interface.lock().ok()?.take_window_size()
+    }
+
+    /// Take the cursor settings the guest changed, if it changed any
+    pub fn take_cursor(&mut self) -> Option<CursorSettings> {
+        self.host_interface.lock().ok()?.take_cursor()
+    }
+
+    /// Seconds between updates the guest asked for with
+    /// `wapps::request_frame_rate`, if it did
+    pub fn frame_interval(&self) -> Option<f64> {
+        self.host_interface.lock().ok()?.frame_interval()
+    }
+
+    /// Take the messages the guest sent via `wapps::bus_send` since the last
+    /// call, as (topic, data)
+    pub fn take_bus_messages(&mut self) -> Vec<(String, Vec<u8>)> {
+        match self.host_interface.lock() {
+            Ok(mut host) => host.take_bus_messages(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Whether the guest receives bus messages sent under `topic`
+    pub fn bus_subscribed(&self, topic: &str) -> bool {
+        self.host_interface
+            .lock()
+            .is_ok_and(|host| host.bus_subscribed(topic))
+    }
+
+    /// Take the launch targets the guest requested via `wapps::launch`
+    pub fn take_launch_requests(&mut self) -> Vec<String> {
+        match self.host_interface.lock() {
+            Ok(mut host) => host.take_launch_requests(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// A copy of the latest frame the guest presented, whether or not it was
+    /// already processed, for screenshots
+    pub fn capture_frame(&mut self) -> Option<(u32, u32, Vec<u8>)> {
+        let mut host = self.host_interface.lock().ok()?;
+        if let Some((width, height, ptr)) = host.shared_frame() {
+            let len = width as usize * height as usize * 4;
+            let pixels = self.memory.read(&self.store, ptr as usize, len)?;
+            return Some((width, height, pixels.into_owned()));
+        }
+        host.last_frame()
+    }
+
     /// Process the latest frame data from the host interface
     ///
     /// Calls the provided closure with the frame data (width, height, pixels slice)
-    /// if a new frame is available. This avoids copying the pixel data.
+    /// if a new frame is available. This avoids copying the pixel data; frames
+    /// presented from the shared framebuffer are read from guest memory, and
+    /// only copied out of memories shared between threads.
     pub fn with_frame_data<F, R>(&mut self, f: F) -> Option<R>
     where
         F: FnOnce(i32, i32, &[u8]) -> R,
     {
         let mut host = self.host_interface.lock().ok()?;
+        // Images are drawn over a copy of the shared frame
+        if host.has_image_draws() {
+            if let Some((width, height, ptr)) = host.shared_frame() {
+                let len = width as usize * height as usize * 4;
+                if let Some(pixels) = self.memory.read(&self.store, ptr as usize, len) {
+                    host.set_frame(width as i32, height as i32, &pixels);
+                }
+            }
+        }
+        if let Some((width, height, ptr)) = host.take_shared_frame() {
+            let len = width as usize * height as usize * 4;
+            return match self.memory.read(&self.store, ptr as usize, len) {
+                Some(pixels) => Some(f(width as i32, height as i32, &pixels)),
+                None => {
+                    warn!("Shared framebuffer out of bounds");
+                    None
+                }
+            };
+        }
         if let Some((w, h, pixels)) = host.borrow_frame() {
             Some(f(w, h, pixels))
         } else {
@@ -312,3 +3974,76 @@ impl WasmRuntime {
         }
     }
 }
+
+impl Drop for WasmRuntime {
+    fn drop(&mut self) {
+        // Threads would otherwise keep running the guest after it is gone
+        if let Some(threads) = &self.threads {
+            if threads.running() > 0 {
+                debug!("Stopping {} guest threads", threads.running());
+            }
+            threads.stop();
+        }
+    }
+}
+
+/// Copy as much of `value` as fits into the guest buffer at `ptr` of `cap` bytes
+///
+/// Returns the full length of `value`, which tells the guest to retry with a
+/// larger buffer when it exceeds `cap`, or `None` if the buffer is out of bounds.
+fn write_guest_bytes(
+    caller: &mut Caller<'_, StoreState>,
+    ptr: i32,
+    cap: i32,
+    value: impl AsRef<[u8]>,
+) -> Option<i32> {
+    let value = value.as_ref();
+    let len = value.len().min(cap.max(0) as usize);
+    let memory = caller_memory(caller)?;
+    memory
+        .write(&mut *caller, ptr as u32 as usize, &value[..len])
+        .ok()?;
+    Some(value.len() as i32)
+}
+
+/// A guest-supplied size, or `None` unless both dimensions are positive
+fn positive_size(width: i32, height: i32) -> Option<(u32, u32)> {
+    (width > 0 && height > 0).then_some((width as u32, height as u32))
+}
+
+/// The calling guest's `memory` export, owned or shared
+fn caller_memory(caller: &mut Caller<'_, StoreState>) -> Option<GuestMemory> {
+    match caller.get_export("memory")? {
+        Extern::Memory(memory) => Some(GuestMemory::Owned(memory)),
+        Extern::SharedMemory(memory) => Some(GuestMemory::Shared(memory)),
+        _ => None,
+    }
+}
+
+/// Copy `len` bytes at `ptr` out of the calling guest's memory
+///
+/// Returns `None` if the guest has no memory export or the range is out of bounds.
+fn read_guest_bytes(caller: &mut Caller<'_, StoreState>, ptr: i32, len: i32) -> Option<Vec<u8>> {
+    let memory = caller_memory(caller)?;
+    memory
+        .read(&caller, ptr as u32 as usize, len as u32 as usize)
+        .map(Cow::into_owned)
+}
+
+/// UTF-8 text of up to `MAX_TEXT_LEN` bytes from guest memory, for
+/// `draw_text` and `measure_text`, or the `wapps_abi` status rejecting it
+fn read_guest_text(caller: &mut Caller<'_, StoreState>, ptr: i32, len: i32) -> Result<String, i32> {
+    if len as u32 as usize > text::MAX_TEXT_LEN {
+        return Err(wapps_abi::OUT_OF_BOUNDS);
+    }
+    let bytes = read_guest_bytes(caller, ptr, len).ok_or(wapps_abi::OUT_OF_BOUNDS)?;
+    String::from_utf8(bytes).map_err(|_| wapps_abi::UNSUPPORTED)
+}
+
+/// Read a bus topic from guest memory, if it is in bounds, short enough and UTF-8
+fn read_bus_topic(caller: &mut Caller<'_, StoreState>, ptr: i32, len: i32) -> Option<String> {
+    if len as u32 as usize > host_interface::MAX_BUS_TOPIC {
+        return None;
+    }
+    String::from_utf8(read_guest_bytes(caller, ptr, len)?).ok()
+}
@@ -0,0 +1,282 @@
+//! Save States
+//!
+//! F2 saves the state of the focused app, F3 restores it, and guests can ask
+//! for either through `wapps::request_snapshot` and `wapps::request_restore`.
+//! A state holds the guest's linear memory, its exported mutable globals and
+//! the frame layers it last presented, and is written to `states/` under the
+//! user data directory, one file per package. States are taken between calls
+//! into the guest, so nothing it is running is cut short, and only restore
+//! into the build that saved them. `--session FILE` saves one on exit and
+//! offers to resume from it on the next launch.
+//!
+//! Layout: the magic `WSTATE\0\0`, the format version (u32 LE), the length of
+//! the JSON header (u32 LE), the header, then the memory followed by every
+//! layer's pixels, deflated. Version 2 adds the app's name and version and
+//! when the state was saved to the header; version 1 files still load.
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmtime::Val;
+
+use crate::deflate;
+use crate::storage;
+
+/// Magic bytes of a state file
+const STATE_MAGIC: &[u8; 8] = b"WSTATE\0\0";
+
+/// Version of the state file format
+const STATE_VERSION: u32 = 2;
+
+/// Largest memory and layers a state may hold: a full 32-bit memory and as much again
+const MAX_BODY_SIZE: usize = 1 << 33;
+
+/// A save or restore asked for by the guest or the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotRequest {
+    Save,
+    Restore,
+}
+
+/// Value of an exported mutable global; floats are kept as their bits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlobalValue {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+}
+
+impl GlobalValue {
+    /// The value of a numeric global, or `None` for vectors and references
+    pub fn from_val(val: &Val) -> Option<Self> {
+        match *val {
+            Val::I32(v) => Some(GlobalValue::I32(v)),
+            Val::I64(v) => Some(GlobalValue::I64(v)),
+            Val::F32(v) => Some(GlobalValue::F32(v)),
+            Val::F64(v) => Some(GlobalValue::F64(v)),
+            _ => None,
+        }
+    }
+
+    pub fn to_val(self) -> Val {
+        match self {
+            GlobalValue::I32(v) => Val::I32(v),
+            GlobalValue::I64(v) => Val::I64(v),
+            GlobalValue::F32(v) => Val::F32(v),
+            GlobalValue::F64(v) => Val::F64(v),
+        }
+    }
+}
+
+/// One frame layer, as set via `wapps::update_frame` or `wapps::update_layer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedLayer {
+    pub id: i32,
+    pub width: u32,
+    pub height: u32,
+    pub opacity: f32,
+    pub pixels: Vec<u8>,
+}
+
+/// Everything restored into a guest
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+    /// Hash of the module that saved the state
+    pub module_hash: u64,
+    /// Name and version of the app that saved the state
+    pub app_name: String,
+    pub app_version: String,
+    /// When the state was saved, in seconds since the Unix epoch (0 if unknown)
+    pub saved_at: u64,
+    pub memory: Vec<u8>,
+    pub globals: Vec<(String, GlobalValue)>,
+    pub layers: Vec<SavedLayer>,
+}
+
+/// Header of a state file, describing the data that follows it
+#[derive(Serialize, Deserialize)]
+struct Header {
+    module_hash: u64,
+    #[serde(default)]
+    app_name: String,
+    #[serde(default)]
+    app_version: String,
+    #[serde(default)]
+    saved_at: u64,
+    memory_size: usize,
+    globals: Vec<(String, GlobalValue)>,
+    /// Id, width, height and opacity of each layer
+    layers: Vec<(i32, u32, u32, f32)>,
+}
+
+impl SaveState {
+    /// Write the state to `path`, creating its directory
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create {}", dir.display()))?;
+        }
+        fs::write(path, encode(self)?)
+            .with_context(|| format!("Could not write state: {}", path.display()))?;
+        info!(
+            "Saved {} KiB of state to {}",
+            self.memory.len() / 1024,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Read the state at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            fs::read(path).with_context(|| format!("Could not read state: {}", path.display()))?;
+        decode(&data).with_context(|| format!("Invalid state file: {}", path.display()))
+    }
+}
+
+/// File the state of the package with id `id` is saved to, if there is a user
+/// data directory
+pub fn state_path(id: &str) -> Option<PathBuf> {
+    let file_name = format!("{}.wappstate", storage::file_stem(id));
+    Some(storage::data_dir()?.join("states").join(file_name))
+}
+
+fn encode(state: &SaveState) -> Result<Vec<u8>> {
+    let header = Header {
+        module_hash: state.module_hash,
+        app_name: state.app_name.clone(),
+        app_version: state.app_version.clone(),
+        saved_at: state.saved_at,
+        memory_size: state.memory.len(),
+        globals: state.globals.clone(),
+        layers: state
+            .layers
+            .iter()
+            .map(|layer| (layer.id, layer.width, layer.height, layer.opacity))
+            .collect(),
+    };
+    let json = serde_json::to_vec(&header).context("Failed to serialize state")?;
+    let mut body = state.memory.clone();
+    for layer in &state.layers {
+        body.extend_from_slice(&layer.pixels);
+    }
+
+    let mut data = STATE_MAGIC.to_vec();
+    data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+    data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    data.extend_from_slice(&json);
+    data.extend_from_slice(&deflate::compress(&body));
+    Ok(data)
+}
+
+fn decode(data: &[u8]) -> Result<SaveState> {
+    if data.len() < 16 || !data.starts_with(STATE_MAGIC) {
+        bail!("not a .wappstate file");
+    }
+    let version = u32::from_le_bytes(data[8..12].try_into().expect("4-byte slice"));
+    if !(1..=STATE_VERSION).contains(&version) {
+        bail!(
+            "unsupported state version {}; this host supports versions up to {}",
+            version,
+            STATE_VERSION
+        );
+    }
+    let json_len = u32::from_le_bytes(data[12..16].try_into().expect("4-byte slice")) as usize;
+    let Some(json) = data.get(16..16 + json_len) else {
+        bail!("state is truncated");
+    };
+    let header: Header = serde_json::from_slice(json).context("failed to parse state header")?;
+
+    let layer_sizes: Vec<usize> = header
+        .layers
+        .iter()
+        .map(|&(_, width, height, _)| width as usize * height as usize * 4)
+        .collect();
+    let body_len = header.memory_size + layer_sizes.iter().sum::<usize>();
+    if body_len > MAX_BODY_SIZE {
+        bail!("state is too large");
+    }
+    let body = deflate::decompress(&data[16 + json_len..], body_len)?;
+    if body.len() != body_len {
+        bail!("state is truncated");
+    }
+
+    let (memory, mut rest) = body.split_at(header.memory_size);
+    let mut layers = Vec::new();
+    for (&(id, width, height, opacity), size) in header.layers.iter().zip(layer_sizes) {
+        let (pixels, tail) = rest.split_at(size);
+        rest = tail;
+        layers.push(SavedLayer {
+            id,
+            width,
+            height,
+            opacity,
+            pixels: pixels.to_vec(),
+        });
+    }
+    Ok(SaveState {
+        module_hash: header.module_hash,
+        app_name: header.app_name,
+        app_version: header.app_version,
+        saved_at: header.saved_at,
+        memory: memory.to_vec(),
+        globals: header.globals,
+        layers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_states_round_trip() {
+        let state = SaveState {
+            module_hash: 0x1234,
+            app_name: "Life".into(),
+            app_version: "1.2.0".into(),
+            saved_at: 1_700_000_000,
+            memory: (0..70_000).map(|i| (i % 7) as u8).collect(),
+            globals: vec![
+                ("counter".into(), GlobalValue::I32(-3)),
+                ("speed".into(), GlobalValue::F64(1.5f64.to_bits())),
+            ],
+            layers: vec![SavedLayer {
+                id: 0,
+                width: 2,
+                height: 1,
+                opacity: 1.0,
+                pixels: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            }],
+        };
+        let data = encode(&state).unwrap();
+        assert_eq!(decode(&data).unwrap(), state);
+
+        // Version 1 headers have no app or time
+        let json_len = u32::from_le_bytes(data[12..16].try_into().unwrap()) as usize;
+        let mut header: serde_json::Value =
+            serde_json::from_slice(&data[16..16 + json_len]).unwrap();
+        for field in ["app_name", "app_version", "saved_at"] {
+            header.as_object_mut().unwrap().remove(field);
+        }
+        let json = serde_json::to_vec(&header).unwrap();
+        let mut v1 = STATE_MAGIC.to_vec();
+        v1.extend_from_slice(&1u32.to_le_bytes());
+        v1.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        v1.extend_from_slice(&json);
+        v1.extend_from_slice(&data[16 + json_len..]);
+        let old = decode(&v1).unwrap();
+        assert_eq!((old.saved_at, old.memory), (0, state.memory.clone()));
+
+        assert!(decode(&data[..data.len() - 4]).is_err());
+        assert!(decode(b"WREPLAY\0\x01\0\0\0\0\0\0\0").is_err());
+        assert_eq!(
+            GlobalValue::from_val(&Val::I64(7)),
+            Some(GlobalValue::I64(7))
+        );
+    }
+}
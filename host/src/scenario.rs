@@ -0,0 +1,395 @@
+//! `wapps test` Command
+//!
+//! Runs scripted scenarios against packages headlessly, for guest projects
+//! to gate their CI on host integration tests. A scenario is a JSON file:
+//!
+//! ```json
+//! {
+//!   "name": "space starts the game",
+//!   "package": "game.wapp",
+//!   "steps": [
+//!     { "run": 30 },
+//!     { "event": { "KeyDown": { "scancode": 44 } } },
+//!     { "run": 1 },
+//!     { "expect_frame_hash": "9f86d081884c7d65" },
+//!     { "expect_pixel": { "x": 10, "y": 20, "rgba": [255, 0, 0, 255] } }
+//!   ]
+//! }
+//! ```
+//!
+//! `run` updates the guest for that many frames, delivering the events queued
+//! by `event` steps before the first one. Frame hashes are those written by
+//! `--frame-hashes`. Like `wapps compare`, guests get a fixed `dt`, a disabled
+//! clock and a fixed random seed (`random_seed`, 0 by default), so results do
+//! not depend on the machine. Each scenario runs against its own package, or
+//! against every `--package` given. With `--report`, results are written as
+//! JSON if the file name ends in `.json`, as JUnit XML otherwise.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::events::{GuestEvent, TimedEvent};
+use crate::frame_hash::hash_frame;
+use crate::host_interface::HostInterface;
+use crate::loader;
+use crate::memory_limit;
+use crate::runtime::WasmRuntime;
+use crate::storage::AppStorage;
+use crate::wasi_policy::{ClockPolicy, WasiPolicy};
+use crate::watchdog;
+
+/// Time step passed to `update` for each frame
+const FRAME_DT: f64 = 1.0 / 60.0;
+
+/// Arguments of `wapps test`
+#[derive(Args, Debug)]
+pub struct TestArgs {
+    /// Scenario files to run
+    #[arg(value_name = "SCENARIO", required = true)]
+    scenarios: Vec<PathBuf>,
+
+    /// Run every scenario against PACKAGE instead of the package it names;
+    /// repeatable
+    #[arg(long, value_name = "PACKAGE")]
+    package: Vec<PathBuf>,
+
+    /// Write the results to FILE: JSON for a `.json` name, JUnit XML otherwise
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+}
+
+/// A scripted run of a package
+#[derive(Debug, Deserialize)]
+struct Scenario {
+    /// Defaults to the file stem of the scenario
+    #[serde(default)]
+    name: String,
+    /// Package to run, relative to the scenario file
+    #[serde(default)]
+    package: Option<PathBuf>,
+    #[serde(default)]
+    random_seed: u64,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Step {
+    /// Update the guest for this many frames
+    Run(u32),
+    /// Queue an event for the next frame
+    Event(GuestEvent),
+    /// Check the hash of the latest frame
+    ExpectFrameHash(String),
+    /// Check the size of the latest frame
+    ExpectFrameSize([u32; 2]),
+    /// Check a pixel of the latest frame
+    ExpectPixel { x: u32, y: u32, rgba: [u8; 4] },
+}
+
+/// Outcome of one scenario against one package
+#[derive(Debug, Serialize)]
+struct TestResult {
+    scenario: String,
+    package: String,
+    frames: u64,
+    time_secs: f64,
+    /// Why the scenario failed, if it did
+    failure: Option<String>,
+}
+
+/// Run `wapps test`
+pub fn run(args: &TestArgs) -> Result<()> {
+    let mut results = Vec::new();
+    for path in &args.scenarios {
+        let mut scenario: Scenario = serde_json::from_slice(
+            &fs::read(path)
+                .with_context(|| format!("Could not read scenario: {}", path.display()))?,
+        )
+        .with_context(|| format!("Invalid scenario: {}", path.display()))?;
+        if scenario.name.is_empty() {
+            scenario.name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+
+        let packages = match (&scenario.package, args.package.is_empty()) {
+            (_, false) => args.package.clone(),
+            (Some(package), true) => vec![path.parent().unwrap_or(Path::new(".")).join(package)],
+            (None, true) => bail!(
+                "Scenario {} names no package; pass --package",
+                path.display()
+            ),
+        };
+        for package in packages {
+            let result = run_scenario(&scenario, &package);
+            match &result.failure {
+                None => info!("PASS {} ({})", result.scenario, result.package),
+                Some(failure) => {
+                    info!("FAIL {} ({}): {}", result.scenario, result.package, failure)
+                }
+            }
+            results.push(result);
+        }
+    }
+
+    if let Some(report) = &args.report {
+        let contents = if report.extension().is_some_and(|ext| ext == "json") {
+            json_report(&results)?
+        } else {
+            junit_report(&results)
+        };
+        fs::write(report, contents)
+            .with_context(|| format!("Could not write report: {}", report.display()))?;
+    }
+
+    let failures = results.iter().filter(|r| r.failure.is_some()).count();
+    println!("{} passed, {} failed", results.len() - failures, failures);
+    if failures > 0 {
+        bail!("{} of {} scenarios failed", failures, results.len());
+    }
+    Ok(())
+}
+
+fn run_scenario(scenario: &Scenario, package: &Path) -> TestResult {
+    let start = Instant::now();
+    let mut frames = 0;
+    let failure = execute(scenario, package, &mut frames).err();
+    TestResult {
+        scenario: scenario.name.clone(),
+        package: package.display().to_string(),
+        frames,
+        time_secs: start.elapsed().as_secs_f64(),
+        failure: failure.map(|e| format!("{:#}", e)),
+    }
+}
+
+/// Run the steps of `scenario` against `package`, counting `frames` run
+fn execute(scenario: &Scenario, package: &Path, frames: &mut u64) -> Result<()> {
+    let wapp = loader::load_package(package)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", package))?;
+    let assets = Arc::new(wapp.assets());
+    let wasm_bytes = wapp.module().data.clone();
+    let metadata = wapp.metadata;
+    let mut host_interface = HostInterface::new();
+    host_interface.set_app_info(metadata.name.clone(), metadata.version.clone());
+    host_interface.set_strings(metadata.strings.clone());
+    host_interface.set_config(metadata.config.clone());
+    host_interface.set_assets(assets);
+    // Tests must not touch the app's saved data
+    host_interface.set_storage(AppStorage::in_memory());
+    host_interface.set_capabilities(metadata.capabilities);
+    let policy = WasiPolicy {
+        clock: ClockPolicy::Disabled,
+        random_seed: Some(scenario.random_seed),
+    };
+    let mut runtime = WasmRuntime::new(
+        &wasm_bytes,
+        None,
+        host_interface,
+        &[metadata.name],
+        None,
+        &policy,
+        false,
+        Some(memory_limit::DEFAULT_MAX_MEMORY),
+    )
+    .context("Failed to initialize WASM runtime")?;
+    // A guest stuck in a loop fails its test instead of hanging the run
+    runtime.set_frame_budget(Some(watchdog::DEFAULT_FRAME_BUDGET));
+
+    let mut events = Vec::new();
+    for (index, step) in scenario.steps.iter().enumerate() {
+        let step_number = index + 1;
+        match step {
+            Step::Run(count) => {
+                for _ in 0..*count {
+                    runtime
+                        .run_frame(&events, FRAME_DT)
+                        .with_context(|| format!("Guest failed at frame {}", frames))?;
+                    events.clear();
+                    *frames += 1;
+                }
+            }
+            Step::Event(event) => events.push(TimedEvent {
+                event: event.clone(),
+                time: *frames as f64 * FRAME_DT,
+            }),
+            Step::ExpectFrameHash(expected) => {
+                let hash = runtime
+                    .with_frame_data(|width, height, pixels| {
+                        format!("{:016x}", hash_frame(width as u32, height as u32, pixels))
+                    })
+                    .with_context(|| format!("Step {}: no frame presented", step_number))?;
+                if !hash.eq_ignore_ascii_case(expected) {
+                    bail!(
+                        "Step {}: frame hash {} after {} frames, expected {}",
+                        step_number,
+                        hash,
+                        frames,
+                        expected
+                    );
+                }
+            }
+            Step::ExpectFrameSize([width, height]) => {
+                let size = runtime
+                    .with_frame_data(|w, h, _| (w as u32, h as u32))
+                    .with_context(|| format!("Step {}: no frame presented", step_number))?;
+                if size != (*width, *height) {
+                    bail!(
+                        "Step {}: frame is {}x{}, expected {}x{}",
+                        step_number,
+                        size.0,
+                        size.1,
+                        width,
+                        height
+                    );
+                }
+            }
+            Step::ExpectPixel { x, y, rgba } => {
+                let pixel = runtime
+                    .with_frame_data(|width, height, pixels| {
+                        let (w, h) = (width as u32, height as u32);
+                        (*x < w && *y < h).then(|| {
+                            let i = ((y * w + x) * 4) as usize;
+                            [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+                        })
+                    })
+                    .with_context(|| format!("Step {}: no frame presented", step_number))?
+                    .with_context(|| {
+                        format!(
+                            "Step {}: pixel ({}, {}) is off the frame",
+                            step_number, x, y
+                        )
+                    })?;
+                if pixel != *rgba {
+                    bail!(
+                        "Step {}: pixel ({}, {}) is {:?}, expected {:?}",
+                        step_number,
+                        x,
+                        y,
+                        pixel,
+                        rgba
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn json_report(results: &[TestResult]) -> Result<String> {
+    let failures = results.iter().filter(|r| r.failure.is_some()).count();
+    let report = serde_json::json!({
+        "tests": results.len(),
+        "failures": failures,
+        "results": results,
+    });
+    serde_json::to_string_pretty(&report).context("Failed to serialize report")
+}
+
+/// JUnit XML, one test case per scenario and package
+fn junit_report(results: &[TestResult]) -> String {
+    let failures = results.iter().filter(|r| r.failure.is_some()).count();
+    let time: f64 = results.iter().map(|r| r.time_secs).sum();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"wapps\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+        results.len(),
+        failures,
+        time
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"wapps\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+        results.len(),
+        failures,
+        time
+    );
+    for result in results {
+        let _ = write!(
+            xml,
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            escape_xml(&result.package),
+            escape_xml(&result.scenario),
+            result.time_secs
+        );
+        match &result.failure {
+            None => xml.push_str("/>\n"),
+            Some(failure) => {
+                let _ = writeln!(
+                    xml,
+                    ">\n      <failure message=\"{}\"/>\n    </testcase>",
+                    escape_xml(failure)
+                );
+            }
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenarios_parse_and_reports_escape() {
+        let scenario: Scenario = serde_json::from_str(
+            r#"{"steps": [{"run": 2}, {"event": {"KeyUp": {"scancode": 4}}},
+                {"expect_pixel": {"x": 1, "y": 2, "rgba": [0, 0, 0, 255]}}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(scenario.steps[0], Step::Run(2)));
+        assert!(matches!(
+            scenario.steps[1],
+            Step::Event(GuestEvent::KeyUp { scancode: 4 })
+        ));
+
+        let results = [
+            TestResult {
+                scenario: "starts".to_string(),
+                package: "a.wapp".to_string(),
+                frames: 2,
+                time_secs: 0.5,
+                failure: None,
+            },
+            TestResult {
+                scenario: "score <10>".to_string(),
+                package: "a.wapp".to_string(),
+                frames: 1,
+                time_secs: 0.25,
+                failure: Some("pixel is \"red\"".to_string()),
+            },
+        ];
+        let xml = junit_report(&results);
+        assert!(xml.contains("tests=\"2\" failures=\"1\""));
+        assert!(xml.contains("name=\"score &lt;10&gt;\""));
+        assert!(xml.contains("<failure message=\"pixel is &quot;red&quot;\"/>"));
+        assert!(json_report(&results).unwrap().contains("\"failures\": 1"));
+    }
+}
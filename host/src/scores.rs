@@ -0,0 +1,239 @@
+//! High Scores
+//!
+//! `wapps::score_submit` and `wapps::score_list` give games a leaderboard
+//! without inventing their own file format. Entries live in the app's
+//! persistent storage under a reserved key, best first. When the host has a
+//! data directory, each entry is signed for the package id with a key
+//! private to this installation, so scores edited in the storage file are
+//! listed as unverified rather than trusted, while translating the app's name
+//! leaves them verified.
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use wasmtime_wasi::RngCore;
+
+use crate::storage::{self, AppStorage};
+
+/// Storage key holding the leaderboard; guests cannot write reserved keys
+const SCORES_KEY: &str = "wapps:scores";
+
+/// Entries kept per app
+pub const MAX_SCORES: usize = 100;
+
+/// Longest player name, in bytes
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Status codes returned by `wapps::score_submit` (ranks are non-negative)
+pub const SCORE_NOT_RANKED: i32 = -1;
+pub const SCORE_INVALID: i32 = -2;
+pub const SCORE_IO_ERROR: i32 = -3;
+
+/// An entry as stored, with the hex HMAC-SHA256 of its contents if signed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct StoredScore {
+    value: i64,
+    name: String,
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+/// A leaderboard entry as listed to the guest
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    pub value: i64,
+    pub name: String,
+    /// Whether the entry carries a valid signature from this installation
+    pub verified: bool,
+}
+
+/// Key the host signs entries with, created on first use
+pub struct ScoreKey([u8; 32]);
+
+impl ScoreKey {
+    /// Load this installation's key, creating it if needed
+    ///
+    /// Returns `None`, leaving scores unsigned, without a data directory or
+    /// when the key cannot be written.
+    pub fn load_or_create() -> Option<Self> {
+        let path = key_file()?;
+        if let Ok(bytes) = fs::read(&path) {
+            if let Ok(key) = bytes.try_into() {
+                return Some(Self(key));
+            }
+            warn!("Ignoring malformed score key {}", path.display());
+        }
+        let mut key = [0u8; 32];
+        wasmtime_wasi::thread_rng().fill_bytes(&mut key);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, key));
+        if let Err(e) = written {
+            warn!("Could not write score key {}: {}", path.display(), e);
+            return None;
+        }
+        debug!("Created score key {}", path.display());
+        Some(Self(key))
+    }
+
+    /// Hex signature of an entry of the package with id `app`
+    fn sign(&self, app: &str, value: i64, name: &str) -> String {
+        let mut message = Vec::new();
+        message.extend_from_slice(&(app.len() as u32).to_le_bytes());
+        message.extend_from_slice(app.as_bytes());
+        message.extend_from_slice(&value.to_le_bytes());
+        message.extend_from_slice(name.as_bytes());
+        hmac_sha256(&self.0, &message)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Add a score to the leaderboard of the package with id `app`
+///
+/// Returns the entry's rank from 0, `SCORE_NOT_RANKED` if it did not make
+/// the leaderboard, or another negative status on failure.
+pub fn submit(
+    storage: &mut AppStorage,
+    key: Option<&ScoreKey>,
+    app: &str,
+    value: i64,
+    name: &str,
+) -> i32 {
+    if name.len() > MAX_NAME_LEN {
+        return SCORE_INVALID;
+    }
+    let mut scores = load(storage);
+    // Equal scores rank below the ones reached first
+    let rank = scores.partition_point(|score| score.value >= value);
+    if rank >= MAX_SCORES {
+        return SCORE_NOT_RANKED;
+    }
+    scores.insert(
+        rank,
+        StoredScore {
+            value,
+            name: name.to_string(),
+            signature: key.map(|key| key.sign(app, value, name)),
+        },
+    );
+    scores.truncate(MAX_SCORES);
+
+    let json = match serde_json::to_vec(&scores) {
+        Ok(json) => json,
+        Err(_) => return SCORE_IO_ERROR,
+    };
+    match storage.set_reserved(SCORES_KEY, &json) {
+        storage::STORAGE_OK => rank as i32,
+        storage::STORAGE_QUOTA_EXCEEDED => SCORE_INVALID,
+        _ => SCORE_IO_ERROR,
+    }
+}
+
+/// The leaderboard of the package with id `app`, best first
+pub fn list(storage: &AppStorage, key: Option<&ScoreKey>, app: &str) -> Vec<Score> {
+    load(storage)
+        .into_iter()
+        .map(|score| Score {
+            verified: match (key, &score.signature) {
+                (Some(key), Some(signature)) => {
+                    key.sign(app, score.value, &score.name) == *signature
+                }
+                _ => false,
+            },
+            value: score.value,
+            name: score.name,
+        })
+        .collect()
+}
+
+/// Encode scores for `wapps::score_list`: per entry, the value (i64 LE), a
+/// flags byte (bit 0: verified), the name length (u8) and the name
+pub fn encode(scores: &[Score]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for score in scores {
+        out.extend_from_slice(&score.value.to_le_bytes());
+        out.push(score.verified as u8);
+        out.push(score.name.len() as u8);
+        out.extend_from_slice(score.name.as_bytes());
+    }
+    out
+}
+
+fn load(storage: &AppStorage) -> Vec<StoredScore> {
+    let Some(data) = storage.get(SCORES_KEY) else {
+        return Vec::new();
+    };
+    serde_json::from_slice(data).unwrap_or_else(|e| {
+        warn!("Ignoring corrupt leaderboard: {}", e);
+        Vec::new()
+    })
+}
+
+/// File holding this installation's score key
+fn key_file() -> Option<PathBuf> {
+    Some(storage::data_dir()?.join("score.key"))
+}
+
+/// HMAC-SHA256 (RFC 2104) with a key of at most one block
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut inner_pad = [0x36u8; 64];
+    let mut outer_pad = [0x5cu8; 64];
+    for (i, byte) in key.iter().enumerate() {
+        inner_pad[i] ^= byte;
+        outer_pad[i] ^= byte;
+    }
+    let inner = Sha256::new()
+        .chain_update(inner_pad)
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(outer_pad)
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(
+            hex,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_ranking_and_verification() {
+        let mut storage = AppStorage::in_memory();
+        let key = ScoreKey([7; 32]);
+        assert_eq!(submit(&mut storage, Some(&key), "Game", 10, "ann"), 0);
+        assert_eq!(submit(&mut storage, Some(&key), "Game", 30, "bob"), 0);
+        assert_eq!(submit(&mut storage, Some(&key), "Game", 10, "cat"), 2);
+        assert_eq!(submit(&mut storage, None, "Game", 20, "dan"), 1);
+        assert_eq!(
+            submit(&mut storage, Some(&key), "Game", 5, &"x".repeat(33)),
+            SCORE_INVALID
+        );
+
+        let scores = list(&storage, Some(&key), "Game");
+        let names: Vec<_> = scores.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["bob", "dan", "ann", "cat"]);
+        let verified: Vec<_> = scores.iter().map(|s| s.verified).collect();
+        assert_eq!(verified, [true, false, true, true]);
+        // Signatures are bound to the package
+        assert!(list(&storage, Some(&key), "Other")
+            .iter()
+            .all(|s| !s.verified));
+    }
+}
@@ -0,0 +1,91 @@
+//! Screenshots
+//!
+//! F12 saves the frame the focused app last presented as a PNG, and
+//! `--screenshot-after N` does so once each app has presented N frames. The
+//! frame is read from the host interface, as the guest drew it, before
+//! debug views, filters and display adjustments. Screenshots go to
+//! `screenshots/` under the user data directory (or the working directory if
+//! there is none), named after the app and the UTC time they were taken.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::png;
+use crate::storage;
+
+/// Write `width` x `height` RGBA pixels of the app named `name` to a new PNG,
+/// returning its path
+pub fn save(name: &str, width: u32, height: u32, pixels: &[u8]) -> Result<PathBuf> {
+    let dir = match storage::data_dir() {
+        Some(dir) => dir.join("screenshots"),
+        None => PathBuf::from("."),
+    };
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+
+    let stem = format!("{}-{}", storage::file_stem(name), current_timestamp());
+    // Screenshots taken within the same second are numbered
+    let mut path = dir.join(format!("{}.png", stem));
+    let mut index = 2;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.png", stem, index));
+        index += 1;
+    }
+
+    fs::write(&path, png::encode_rgba(width, height, pixels))
+        .with_context(|| format!("Could not write screenshot: {}", path.display()))?;
+    Ok(path)
+}
+
+/// The current UTC time as `YYYYMMDD-HHMMSS`, for file names
+pub fn current_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    timestamp(secs)
+}
+
+/// `YYYYMMDD-HHMMSS` UTC time of `secs` seconds since the Unix epoch
+fn timestamp(secs: u64) -> String {
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01, counting years from March so
+    // leap days end each year
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamps_are_utc_dates() {
+        assert_eq!(timestamp(0), "19700101-000000");
+        // Leap day
+        assert_eq!(timestamp(951_827_696), "20000229-123456");
+        assert_eq!(timestamp(1_767_225_599), "20251231-235959");
+    }
+}
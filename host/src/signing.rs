@@ -0,0 +1,235 @@
+//! Package Signing
+//!
+//! `wapps pack --sign-key FILE` ends a version 2 package with a signature
+//! section: the packer's ed25519 public key followed by its signature over
+//! every byte of the file before the section. The loader rejects signed
+//! packages whose signature does not match, since they were modified after
+//! signing. Whether the signer is trusted is up to the host: keys come from
+//! `--trusted-key` and from a keyring file (`--keyring`, by default
+//! `trusted_keys` in the user data directory) holding one hex key per line.
+//! Once any key is configured, unsigned and untrusted packages are warned
+//! about or refused, following `--untrusted`. `wapps keygen` creates keys.
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use log::{debug, info, warn};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use wasmtime_wasi::RngCore;
+
+use crate::storage;
+
+/// Size of a signature section: public key then signature
+pub const SIGNATURE_SECTION_LEN: usize = 32 + 64;
+
+/// An ed25519 public key
+pub type PublicKey = [u8; 32];
+
+/// Sign `message`, returning the contents of a signature section
+pub fn sign(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+    let mut section = key.verifying_key().to_bytes().to_vec();
+    section.extend_from_slice(&key.sign(message).to_bytes());
+    section
+}
+
+/// Check a signature section against `message`, returning the signer's key
+pub fn verify(section: &[u8], message: &[u8]) -> Result<PublicKey> {
+    let (Ok(key), Ok(signature)) = (
+        <PublicKey>::try_from(&section[..section.len().min(32)]),
+        <[u8; 64]>::try_from(&section[section.len().min(32)..]),
+    ) else {
+        bail!("Invalid WAPP file: malformed signature section");
+    };
+    VerifyingKey::from_bytes(&key)
+        .context("Invalid WAPP file: malformed signing key")?
+        .verify_strict(message, &Signature::from_bytes(&signature))
+        .context("Package signature does not match: the file was modified after signing")?;
+    Ok(key)
+}
+
+/// Read a secret key written by `wapps keygen`
+pub fn load_signing_key(path: &Path) -> Result<SigningKey> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Could not read signing key: {}", path.display()))?;
+    let seed = parse_hex(text.trim())
+        .with_context(|| format!("Invalid signing key: {}", path.display()))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Arguments of `wapps keygen`
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    /// File to write the secret key to; keep it private
+    #[arg(value_name = "FILE")]
+    output: PathBuf,
+}
+
+/// Run `wapps keygen`
+pub fn run_keygen(args: &KeygenArgs) -> Result<()> {
+    // Created in one step, so an existing file is never replaced and the key
+    // is never readable by other users
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = match options.open(&args.output) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            bail!("Refusing to overwrite {}", args.output.display())
+        }
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!("Could not create signing key: {}", args.output.display())
+            })
+        }
+    };
+    let mut seed = [0u8; 32];
+    wasmtime_wasi::thread_rng().fill_bytes(&mut seed);
+    let key = SigningKey::from_bytes(&seed);
+    writeln!(file, "{}", to_hex(&seed))
+        .with_context(|| format!("Could not write signing key: {}", args.output.display()))?;
+    info!("Wrote secret key to {}", args.output.display());
+    println!("{}", to_hex(&key.verifying_key().to_bytes()));
+    Ok(())
+}
+
+/// What to do with unsigned packages and packages signed by unknown keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum UntrustedPolicy {
+    /// Log a warning and run them
+    #[default]
+    Warn,
+    /// Refuse to run them
+    Refuse,
+}
+
+/// Public keys whose packages run without complaint
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    keys: Vec<PublicKey>,
+    policy: UntrustedPolicy,
+}
+
+impl Keyring {
+    /// Keys given on the command line plus those in `file`, or in the default
+    /// keyring if it exists
+    pub fn load(trusted: &[String], file: Option<&Path>, policy: UntrustedPolicy) -> Result<Self> {
+        let mut keys = trusted
+            .iter()
+            .map(|key| parse_hex(key).with_context(|| format!("Invalid trusted key {:?}", key)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let default_file = storage::data_dir().map(|dir| dir.join("trusted_keys"));
+        let file = match file {
+            Some(file) => Some(file.to_path_buf()),
+            None => default_file.filter(|path| path.is_file()),
+        };
+        if let Some(file) = file {
+            let text = fs::read_to_string(&file)
+                .with_context(|| format!("Could not read keyring: {}", file.display()))?;
+            keys.extend(
+                parse_keyring(&text)
+                    .with_context(|| format!("Invalid keyring: {}", file.display()))?,
+            );
+            debug!("Loaded keyring {}", file.display());
+        }
+        Ok(Self { keys, policy })
+    }
+
+    /// Whether `signer` is one of the trusted keys
+    pub fn trusts(&self, signer: Option<&PublicKey>) -> bool {
+        signer.is_some_and(|key| self.keys.contains(key))
+    }
+
+    /// Check whether the package `name` signed by `signer` may run
+    pub fn check(&self, name: &str, signer: Option<&PublicKey>) -> Result<()> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        let problem = match signer {
+            Some(key) if self.keys.contains(key) => return Ok(()),
+            Some(key) => format!("{:?} is signed by an untrusted key {}", name, to_hex(key)),
+            None => format!("{:?} is not signed", name),
+        };
+        match self.policy {
+            UntrustedPolicy::Warn => {
+                warn!("{}", problem);
+                Ok(())
+            }
+            UntrustedPolicy::Refuse => bail!("{}; refusing to run it", problem),
+        }
+    }
+}
+
+/// Keys of a keyring file: one hex key per line, `#` starting a comment
+fn parse_keyring(text: &str) -> Result<Vec<PublicKey>> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(parse_hex)
+        .collect()
+}
+
+/// Parse 32 bytes written as 64 hex digits
+fn parse_hex(text: &str) -> Result<[u8; 32]> {
+    if text.len() != 64 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("expected 64 hex digits");
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(bytes)
+}
+
+/// Lowercase hex digits of `bytes`, as keys are written
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signatures_and_trust() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let section = sign(&key, b"package");
+        assert_eq!(section.len(), SIGNATURE_SECTION_LEN);
+        let signer = verify(&section, b"package").unwrap();
+        assert!(verify(&section, b"packagf").is_err());
+        assert!(verify(&section[..40], b"package").is_err());
+
+        let keyring = Keyring {
+            keys: parse_keyring(&format!("# team\n{}  # release\n\n", to_hex(&signer))).unwrap(),
+            policy: UntrustedPolicy::Refuse,
+        };
+        assert!(keyring.check("Life", Some(&signer)).is_ok());
+        assert!(keyring.check("Life", Some(&[1; 32])).is_err());
+        assert!(keyring.check("Life", None).is_err());
+        assert!(Keyring::default().check("Life", None).is_ok());
+    }
+
+    #[test]
+    fn test_keygen_keeps_keys_private() {
+        let path = std::env::temp_dir().join(format!("wapps-key-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let args = KeygenArgs {
+            output: path.clone(),
+        };
+        run_keygen(&args).unwrap();
+        let key = load_signing_key(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o077, 0);
+        }
+        // A second run must not replace the key
+        assert!(run_keygen(&args).is_err());
+        assert_eq!(load_signing_key(&path).unwrap().to_bytes(), key.to_bytes());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+//! softbuffer Presentation
+//!
+//! The `softbuffer` backend composes each window on the CPU, scaling the
+//! frame and the guest's HUD with nearest-neighbor sampling, and hands the result to the window
+//! system through softbuffer. It needs no GPU driver at all, at the cost of
+//! touching every window pixel on every presented frame.
+
+use anyhow::{anyhow, Result};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::video::Window;
+use softbuffer::{Context, Surface};
+use std::num::NonZeroU32;
+
+use crate::inspector::OverlayRect;
+use crate::presenter::Presenter;
+
+/// The guest's latest frame or HUD, as uploaded
+struct Image {
+    width: u32,
+    height: u32,
+    /// RGBA pixels
+    pixels: Vec<u8>,
+}
+
+/// Presents by composing windows on the CPU
+pub struct SoftPresenter {
+    // Declared first so it is dropped before the window it draws into
+    surface: Surface<Window, Window>,
+    _context: Context<Window>,
+    window: Window,
+    frame: Option<Image>,
+    hud: Option<Image>,
+}
+
+impl SoftPresenter {
+    pub fn new(window: &Window) -> Result<Self> {
+        let context = Context::new(window.clone())
+            .map_err(|e| anyhow!("Failed to connect softbuffer to the display: {}", e))?;
+        let surface = Surface::new(&context, window.clone())
+            .map_err(|e| anyhow!("Failed to create softbuffer surface: {}", e))?;
+        Ok(Self {
+            surface,
+            _context: context,
+            window: window.clone(),
+            frame: None,
+            hud: None,
+        })
+    }
+}
+
+impl Presenter for SoftPresenter {
+    fn upload(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+        let frame = self.frame.get_or_insert_with(|| Image {
+            width,
+            height,
+            pixels: Vec::new(),
+        });
+        frame.width = width;
+        frame.height = height;
+        frame.pixels.clear();
+        frame.pixels.extend_from_slice(pixels);
+        Ok(())
+    }
+
+    fn upload_hud(&mut self, hud: Option<(u32, u32, &[u8])>) -> Result<()> {
+        self.hud = hud.map(|(width, height, pixels)| Image {
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn draw(
+        &mut self,
+        clear: Color,
+        frame: Option<(Rect, Rect)>,
+        overlay: &[OverlayRect],
+    ) -> Result<()> {
+        let (width, height) = self.window.drawable_size();
+        // Minimized windows have nothing to draw into
+        let (Some(nonzero_width), Some(nonzero_height)) =
+            (NonZeroU32::new(width), NonZeroU32::new(height))
+        else {
+            return Ok(());
+        };
+        self.surface
+            .resize(nonzero_width, nonzero_height)
+            .map_err(|e| anyhow!("Failed to resize softbuffer surface: {}", e))?;
+        let mut buffer = self
+            .surface
+            .buffer_mut()
+            .map_err(|e| anyhow!("Failed to get softbuffer buffer: {}", e))?;
+        let frame = self
+            .frame
+            .as_ref()
+            .zip(frame)
+            .map(|(image, (source, dest))| (image, source, dest));
+        compose(&mut buffer, width, clear, frame, self.hud.as_ref(), overlay);
+        buffer
+            .present()
+            .map_err(|e| anyhow!("Failed to present softbuffer buffer: {}", e))
+    }
+}
+
+/// Draw into `buffer`, `width` pixels wide, what `Presenter::draw` draws:
+/// `clear`, then the `source` region of the frame scaled into `dest`, then
+/// the HUD scaled over the whole buffer, both blended by their alpha, then
+/// the overlay
+///
+/// Pixels are packed as `0x00RRGGBB`, as softbuffer takes them.
+fn compose(
+    buffer: &mut [u32],
+    width: u32,
+    clear: Color,
+    frame: Option<(&Image, Rect, Rect)>,
+    hud: Option<&Image>,
+    overlay: &[OverlayRect],
+) {
+    let height = buffer.len() as u32 / width.max(1);
+    let bounds = Rect::new(0, 0, width.max(1), height.max(1));
+    buffer.fill(pack(clear.r, clear.g, clear.b));
+
+    if let Some((image, source, dest)) = frame {
+        blend_image(buffer, width, bounds, image, source, dest);
+    }
+    if let Some(image) = hud {
+        let source = Rect::new(0, 0, image.width, image.height);
+        blend_image(buffer, width, bounds, image, source, bounds);
+    }
+
+    // Overlay rectangles replace what is under them, like SDL's renderer draws them
+    for &(rect, color) in overlay {
+        let Some(rect) = rect.intersection(bounds) else {
+            continue;
+        };
+        let pixel = pack(color.r, color.g, color.b);
+        for y in rect.top()..rect.bottom() {
+            let row = (y as u32 * width) as usize;
+            buffer[row + rect.left() as usize..row + rect.right() as usize].fill(pixel);
+        }
+    }
+}
+
+/// Blend the `source` region of `image` scaled into the `dest` region of
+/// `buffer`, clipped to its `bounds`
+fn blend_image(
+    buffer: &mut [u32],
+    width: u32,
+    bounds: Rect,
+    image: &Image,
+    source: Rect,
+    dest: Rect,
+) {
+    let Some(visible) = dest.intersection(bounds) else {
+        return;
+    };
+    // Nearest neighbor: the image pixel under the center of each window pixel
+    let sample = |offset: i32, from: u32, to: u32, start: i32, limit: u32| {
+        let scaled = (offset as i64 * 2 + 1) * from as i64 / (to as i64 * 2);
+        (start as i64 + scaled).clamp(0, limit.saturating_sub(1) as i64) as usize
+    };
+    for y in visible.top()..visible.bottom() {
+        let image_y = sample(
+            y - dest.y(),
+            source.height(),
+            dest.height(),
+            source.y(),
+            image.height,
+        );
+        let row = &image.pixels[image_y * image.width as usize * 4..];
+        let out = &mut buffer[(y as u32 * width) as usize..];
+        for x in visible.left()..visible.right() {
+            let image_x = sample(
+                x - dest.x(),
+                source.width(),
+                dest.width(),
+                source.x(),
+                image.width,
+            );
+            let [r, g, b, a] = [0, 1, 2, 3].map(|i| row[image_x * 4 + i] as u32);
+            let under = out[x as usize];
+            let blend = |over: u32, shift: u32| {
+                let under = (under >> shift) & 0xff;
+                (over * a + under * (255 - a)) / 255
+            };
+            out[x as usize] = (blend(r, 16) << 16) | (blend(g, 8) << 8) | blend(b, 0);
+        }
+    }
+}
+
+fn pack(r: u8, g: u8, b: u8) -> u32 {
+    ((r as u32) << 16) | ((g as u32) << 8) | b as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_are_scaled_blended_and_overlaid() {
+        // Opaque red, then half-transparent green
+        let image = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 255, 0, 128],
+        };
+        let mut buffer = vec![0; 6 * 2];
+        let frame = (&image, Rect::new(0, 0, 2, 1), Rect::new(1, 0, 4, 2));
+        let overlay = [(Rect::new(5, 1, 3, 3), Color::RGB(0, 0, 255))];
+        compose(
+            &mut buffer,
+            6,
+            Color::RGB(0, 0, 0),
+            Some(frame),
+            None,
+            &overlay,
+        );
+
+        let (black, red, green) = (0x000000, 0xff0000, 0x008000);
+        assert_eq!(buffer[..6], [black, red, red, green, green, black]);
+        assert_eq!(buffer[6..], [black, red, red, green, green, 0x0000ff]);
+
+        // A 3x1 HUD stretched over the buffer, opaque white on its left third
+        let hud = Image {
+            width: 3,
+            height: 1,
+            pixels: vec![255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0],
+        };
+        compose(
+            &mut buffer,
+            6,
+            Color::RGB(0, 0, 0),
+            Some(frame),
+            Some(&hud),
+            &[],
+        );
+        let white = 0xffffff;
+        assert_eq!(buffer[..6], [white, white, red, green, green, black]);
+    }
+}
@@ -0,0 +1,256 @@
+//! Session Statistics
+//!
+//! Accumulates whole-session totals for each app (updates, frames, dropped
+//! frames, average dt, guest CPU time, memory and import calls) so `--stats`
+//! can summarize a run after its window closes, or every few seconds with
+//! `--stats-interval` to watch a misbehaving package while it runs.
+//! Summaries are only printed or appended to a local file; nothing is ever
+//! sent over the network.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Frame interval the host aims for
+const TARGET_FRAME_TIME: Duration = Duration::from_nanos(16_666_667);
+
+/// Bytes in a WebAssembly memory page
+const PAGE_SIZE: usize = 65536;
+
+/// Imports listed in printed summaries, most called first
+const PRINTED_IMPORTS: usize = 5;
+
+/// Totals for one app since it was loaded
+pub struct SessionStats {
+    started: Instant,
+    updates: u64,
+    total_dt: f64,
+    frames: u64,
+    dropped_frames: u64,
+    guest_time: Duration,
+    memory_bytes: usize,
+    peak_memory_bytes: usize,
+    peak_heap_bytes: Option<u64>,
+    last_frame: Option<Instant>,
+}
+
+/// Summary of one app's session, as printed and appended to the stats file
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub app: String,
+    /// Unix time at which the session ended, in seconds
+    pub ended_at: u64,
+    pub duration_secs: f64,
+    /// Calls to the guest's `update`
+    pub updates: u64,
+    /// Average `dt` passed to `update`, in milliseconds
+    pub average_dt_ms: f64,
+    /// Frames presented
+    pub frames: u64,
+    pub average_fps: f64,
+    /// Frames the host should have presented at 60 FPS but missed
+    pub dropped_frames: u64,
+    pub guest_cpu_secs: f64,
+    /// Size of the guest's memory at the last frame, in 64 KiB pages
+    pub memory_pages: usize,
+    pub peak_memory_bytes: usize,
+    /// Most bytes the guest's allocator had in use, for guests reporting it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_heap_bytes: Option<u64>,
+    /// Calls to each host import by the running guest, when counted
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub import_calls: BTreeMap<String, u64>,
+}
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} frames in {:.1}s ({:.1} FPS avg, {} dropped) | {} updates, dt {:.1} ms avg \
+             | guest CPU {:.2}s | {} pages, peak {:.1} MiB",
+            self.app,
+            self.frames,
+            self.duration_secs,
+            self.average_fps,
+            self.dropped_frames,
+            self.updates,
+            self.average_dt_ms,
+            self.guest_cpu_secs,
+            self.memory_pages,
+            self.peak_memory_bytes as f64 / (1024.0 * 1024.0)
+        )?;
+        if let Some(heap_bytes) = self.peak_heap_bytes {
+            write!(
+                f,
+                " (heap {:.1} MiB)",
+                heap_bytes as f64 / (1024.0 * 1024.0)
+            )?;
+        }
+        if !self.import_calls.is_empty() {
+            let mut calls: Vec<_> = self.import_calls.iter().collect();
+            calls.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let listed: Vec<_> = calls
+                .iter()
+                .take(PRINTED_IMPORTS)
+                .map(|(name, calls)| format!("{} {}", name, calls))
+                .collect();
+            write!(f, " | calls: {}", listed.join(", "))?;
+            if calls.len() > PRINTED_IMPORTS {
+                write!(f, ", {} more imports", calls.len() - PRINTED_IMPORTS)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SessionStats {
+    /// Start a session now
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            updates: 0,
+            total_dt: 0.0,
+            frames: 0,
+            dropped_frames: 0,
+            guest_time: Duration::ZERO,
+            memory_bytes: 0,
+            peak_memory_bytes: 0,
+            peak_heap_bytes: None,
+            last_frame: None,
+        }
+    }
+
+    /// Record a call to the guest's `update` with `dt`
+    pub fn record_update(&mut self, dt: f64) {
+        self.updates += 1;
+        self.total_dt += dt;
+    }
+
+    /// Record time spent in guest code
+    pub fn record_guest_time(&mut self, elapsed: Duration) {
+        self.guest_time += elapsed;
+    }
+
+    /// Record a presented frame and the guest's memory size and reported heap
+    /// use at that point
+    pub fn record_frame(&mut self, memory_bytes: usize, heap_bytes: Option<u64>) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            self.dropped_frames += dropped_frames(now.duration_since(last));
+        }
+        self.last_frame = Some(now);
+        self.frames += 1;
+        self.memory_bytes = memory_bytes;
+        self.peak_memory_bytes = self.peak_memory_bytes.max(memory_bytes);
+        self.peak_heap_bytes = self.peak_heap_bytes.max(heap_bytes);
+    }
+
+    /// Summarize the session up to now, with the running guest's `import_calls`
+    pub fn summary(&self, app: &str, import_calls: Vec<(String, u64)>) -> SessionSummary {
+        let duration = self.started.elapsed().as_secs_f64();
+        SessionSummary {
+            app: app.to_string(),
+            ended_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            duration_secs: duration,
+            updates: self.updates,
+            average_dt_ms: if self.updates > 0 {
+                self.total_dt * 1000.0 / self.updates as f64
+            } else {
+                0.0
+            },
+            frames: self.frames,
+            average_fps: if duration > 0.0 {
+                self.frames as f64 / duration
+            } else {
+                0.0
+            },
+            dropped_frames: self.dropped_frames,
+            guest_cpu_secs: self.guest_time.as_secs_f64(),
+            memory_pages: self.memory_bytes / PAGE_SIZE,
+            peak_memory_bytes: self.peak_memory_bytes,
+            peak_heap_bytes: self.peak_heap_bytes,
+            import_calls: import_calls.into_iter().collect(),
+        }
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Number of 60 FPS frame slots missed within `interval` between two presents
+///
+/// Half a frame of slack absorbs ordinary timer jitter.
+pub fn dropped_frames(interval: Duration) -> u64 {
+    let frames = interval.as_secs_f64() / TARGET_FRAME_TIME.as_secs_f64();
+    (frames + 0.5).floor().max(1.0) as u64 - 1
+}
+
+/// Append `summaries` to `path` as JSON Lines, one object per app
+pub fn append(path: &Path, summaries: &[SessionSummary]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Could not open stats file: {}", path.display()))?;
+    for summary in summaries {
+        let line = serde_json::to_string(summary).context("Failed to serialize stats")?;
+        writeln!(file, "{}", line)
+            .with_context(|| format!("Could not write stats file: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dropped_frames() {
+        assert_eq!(dropped_frames(Duration::from_millis(10)), 0);
+        assert_eq!(dropped_frames(Duration::from_millis(17)), 0);
+        assert_eq!(dropped_frames(Duration::from_millis(24)), 0);
+        assert_eq!(dropped_frames(Duration::from_millis(34)), 1);
+        assert_eq!(dropped_frames(Duration::from_millis(100)), 5);
+    }
+
+    #[test]
+    fn test_peak_heap_is_only_summarized_when_reported() {
+        let mut stats = SessionStats::new();
+        stats.record_frame(1 << 20, None);
+        assert_eq!(stats.summary("app", Vec::new()).peak_heap_bytes, None);
+        stats.record_frame(1 << 20, Some(300));
+        stats.record_frame(1 << 20, Some(200));
+        let summary = stats.summary("app", Vec::new());
+        assert_eq!(summary.peak_heap_bytes, Some(300));
+        assert!(summary.to_string().ends_with("(heap 0.0 MiB)"));
+    }
+
+    #[test]
+    fn test_updates_pages_and_import_calls() {
+        let mut stats = SessionStats::new();
+        stats.record_update(0.010);
+        stats.record_update(0.020);
+        stats.record_frame(3 * PAGE_SIZE, None);
+        let calls = (0..7)
+            .map(|i| (format!("wapps::import{}", i), 10 + i))
+            .collect();
+        let summary = stats.summary("app", calls);
+        assert_eq!(summary.updates, 2);
+        assert!((summary.average_dt_ms - 15.0).abs() < 1e-9);
+        assert_eq!(summary.memory_pages, 3);
+        assert!(summary.to_string().ends_with(
+            "calls: wapps::import6 16, wapps::import5 15, wapps::import4 14, \
+             wapps::import3 13, wapps::import2 12, 2 more imports"
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+//! Persistent App Storage
+//!
+//! Guests have no filesystem access, so `wapps::storage_get` and
+//! `wapps::storage_set` give each app a small key-value store for high scores
+//! and settings. Each app's entries live in their own JSON file under the
+//! user data directory, named after the package id (its unlocalized name and
+//! a fingerprint of its signer or contents, see `WappPackage::id`) so they
+//! survive locale changes, and are rewritten atomically on every change.
+//! Keys starting with `wapps:` are reserved for the host, which keeps the
+//! app's leaderboard there.
+//!
+//! Apps that genuinely need files, like note-taking apps or level editors,
+//! can instead declare the `filesystem` capability: when the user runs them
+//! with `--allow-storage`, their own directory under `files/`, also named
+//! after the package id, is preopened into WASI as `/`, and the rest of the
+//! filesystem stays out of reach.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Longest key, in bytes
+pub const MAX_KEY_LEN: usize = 256;
+
+/// Prefix of keys the host keeps for itself, e.g. the leaderboard
+pub const RESERVED_PREFIX: &str = "wapps:";
+
+/// Most bytes of keys and values stored per app
+pub const QUOTA: usize = 1024 * 1024;
+
+/// Status codes returned by `wapps::storage_set`
+pub const STORAGE_OK: i32 = 0;
+pub const STORAGE_INVALID: i32 = -1;
+pub const STORAGE_QUOTA_EXCEEDED: i32 = -2;
+pub const STORAGE_IO_ERROR: i32 = -3;
+
+/// Returned by `wapps::storage_get` when the key is not set
+pub const STORAGE_NOT_FOUND: i32 = -1;
+
+/// One app's key-value store
+#[derive(Debug, Default)]
+pub struct AppStorage {
+    entries: BTreeMap<String, Vec<u8>>,
+    /// File the entries are persisted to (`None` keeps them in memory)
+    path: Option<PathBuf>,
+}
+
+impl AppStorage {
+    /// Storage that is never persisted
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Open the storage of the package with id `id`, starting empty if it
+    /// has none
+    ///
+    /// Falls back to in-memory storage when no data directory is available.
+    pub fn open(id: &str) -> Self {
+        let Some(dir) = storage_dir() else {
+            warn!(
+                "No user data directory; storage for {:?} will not persist",
+                id
+            );
+            return Self::in_memory();
+        };
+        let path = dir.join(file_name(id));
+        let entries = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("Ignoring corrupt storage file {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        debug!("Storage for {:?}: {}", id, path.display());
+        Self {
+            entries,
+            path: Some(path),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Set `key` to `value` for the guest and persist the change, returning
+    /// a status code; reserved keys are refused
+    pub fn set(&mut self, key: &str, value: &[u8]) -> i32 {
+        if key.starts_with(RESERVED_PREFIX) {
+            return STORAGE_INVALID;
+        }
+        self.set_reserved(key, value)
+    }
+
+    /// Set any key, including reserved ones, and persist the change
+    pub fn set_reserved(&mut self, key: &str, value: &[u8]) -> i32 {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return STORAGE_INVALID;
+        }
+        let replaced = self.get(key).map_or(0, |old| key.len() + old.len());
+        if self.used() - replaced + key.len() + value.len() > QUOTA {
+            return STORAGE_QUOTA_EXCEEDED;
+        }
+
+        let previous = self.entries.insert(key.to_string(), value.to_vec());
+        if let Err(e) = self.save() {
+            warn!("{:#}", e);
+            // Keep memory consistent with what is on disk
+            match previous {
+                Some(previous) => self.entries.insert(key.to_string(), previous),
+                None => self.entries.remove(key),
+            };
+            return STORAGE_IO_ERROR;
+        }
+        STORAGE_OK
+    }
+
+    /// Bytes of keys and values stored
+    fn used(&self) -> usize {
+        self.entries.iter().map(|(k, v)| k.len() + v.len()).sum()
+    }
+
+    /// Write the entries to a temporary file and move it over the old one
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+        }
+        let json = serde_json::to_vec(&self.entries).context("Failed to serialize storage")?;
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, json)
+            .with_context(|| format!("Could not write storage file: {}", temp.display()))?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("Could not replace storage file: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// The host's directory under the user data directory
+pub fn data_dir() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let data_dir = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".local/share")))
+    };
+    Some(data_dir?.join("wapps"))
+}
+
+/// The host's directory under the user cache directory
+pub fn cache_dir() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let cache_dir = if cfg!(target_os = "windows") {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Caches"))
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".cache")))
+    };
+    Some(cache_dir?.join("wapps"))
+}
+
+/// Directory holding every app's storage file
+fn storage_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("storage"))
+}
+
+/// Directory preopened into WASI for the package with id `id`
+pub fn files_dir(id: &str) -> Option<PathBuf> {
+    Some(data_dir()?.join("files").join(file_stem(id)))
+}
+
+/// Storage file name for an app
+fn file_name(name: &str) -> String {
+    format!("{}.json", file_stem(name))
+}
+
+/// Name for an app's files: its name, made safe, and a hash that keeps apps
+/// whose names sanitize alike apart
+pub fn file_stem(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .take(32)
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let hash = Sha256::digest(name.as_bytes());
+    let hex: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}", safe, hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_names_are_safe_and_distinct() {
+        let a = file_name("../Game of Life");
+        let b = file_name("__Game_of_Life");
+        assert!(a.starts_with("___Game_of_Life-"));
+        assert!(a.ends_with(".json"));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_quota_and_key_limits() {
+        let mut storage = AppStorage::in_memory();
+        assert_eq!(storage.set("score", b"42"), STORAGE_OK);
+        assert_eq!(storage.get("score"), Some(&b"42"[..]));
+        assert_eq!(storage.set("", b"x"), STORAGE_INVALID);
+        assert_eq!(
+            storage.set(&"k".repeat(MAX_KEY_LEN + 1), b"x"),
+            STORAGE_INVALID
+        );
+        assert_eq!(storage.set("big", &vec![0; QUOTA]), STORAGE_QUOTA_EXCEEDED);
+        // Replacing a value only counts the difference
+        assert_eq!(storage.set("score", &vec![0; QUOTA - 5]), STORAGE_OK);
+        assert_eq!(storage.get("missing"), None);
+        assert_eq!(storage.set("wapps:scores", b"[]"), STORAGE_INVALID);
+    }
+}
@@ -0,0 +1,89 @@
+//! Crash Supervision
+//!
+//! Decides whether a crashed guest should be reinstantiated and how long to
+//! wait before doing so. Restarts back off exponentially so a guest that traps
+//! immediately on startup doesn't spin the host.
+//!
+//! `--on-crash` picks what happens when a guest traps or runs past its frame
+//! budget: quit the host with the error (the default), so a crash is never
+//! missed and a service manager can take over, show the error in its window,
+//! or restart it. Unattended kiosks usually restart, with `--max-restarts`
+//! falling back to the error screen for guests that keep crashing.
+
+use clap::ValueEnum;
+use std::time::Duration;
+
+/// Delay before the first restart
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound on the delay between restarts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What to do with a crashed guest, selected with `--on-crash`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnCrash {
+    /// Reinstantiate the guest, backing off exponentially between attempts
+    Restart,
+    /// Show the error in the app's window until the user restarts it
+    Overlay,
+    /// Quit the host with the error
+    #[default]
+    Exit,
+}
+
+/// Restart policy selected with `--on-crash restart` or `--restart-on-crash[=MAX]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Maximum number of restarts per app (`None` = unlimited)
+    max_restarts: Option<u32>,
+}
+
+impl RestartPolicy {
+    /// Create a policy allowing at most `max_restarts` restarts per app
+    pub fn new(max_restarts: Option<u32>) -> Self {
+        Self { max_restarts }
+    }
+
+    /// Whether an app that has already been restarted `restarts` times may be restarted again
+    pub fn allows(&self, restarts: u32) -> bool {
+        self.max_restarts.is_none_or(|max| restarts < max)
+    }
+
+    /// Delay before restart number `restarts + 1`
+    pub fn backoff(&self, restarts: u32) -> Duration {
+        INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(restarts))
+            .min(MAX_BACKOFF)
+    }
+
+    /// Human-readable restart limit for log messages
+    pub fn limit_description(&self) -> String {
+        match self.max_restarts {
+            Some(max) => max.to_string(),
+            None => "unlimited".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let policy = RestartPolicy::new(None);
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(40), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_restart_limit() {
+        let policy = RestartPolicy::new(Some(2));
+        assert!(policy.allows(0));
+        assert!(policy.allows(1));
+        assert!(!policy.allows(2));
+        assert!(RestartPolicy::new(None).allows(u32::MAX));
+    }
+}
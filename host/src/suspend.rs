@@ -0,0 +1,117 @@
+//! Suspend and Resume
+//!
+//! `wapps --session FILE app.wapp` saves the app's state to FILE when it
+//! exits, in the save state format, and on the next launch with FILE offers
+//! to resume where the user left off. Only states saved by the same build of
+//! the module are offered, since memory laid out by another build would be
+//! meaningless to it. Kiosks resume without asking, and the file of a guest
+//! that crashed is removed, so a broken state is never resumed into.
+
+use log::{info, warn};
+use sdl2::messagebox::{
+    show_message_box, ButtonData, ClickedButton, MessageBoxButtonFlag, MessageBoxFlag,
+};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::save_state::SaveState;
+
+/// The suspended state at `path`, if there is one the module hashing to
+/// `module_hash` can resume from
+pub fn load(path: &Path, module_hash: u64) -> Option<SaveState> {
+    if !path.exists() {
+        return None;
+    }
+    let state = match SaveState::load(path) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("Not resuming: {:#}", e);
+            return None;
+        }
+    };
+    if state.module_hash != module_hash {
+        info!(
+            "Not resuming from {}: it was saved by a different build",
+            path.display()
+        );
+        return None;
+    }
+    Some(state)
+}
+
+/// Whether to resume `name` from `state`, asking the user if `prompt` is set
+///
+/// Resumes when the dialog cannot be shown, as nothing is lost by doing so.
+pub fn confirm(name: &str, state: &SaveState, prompt: bool) -> bool {
+    if !prompt {
+        return true;
+    }
+    const RESUME: i32 = 1;
+    let buttons = [
+        ButtonData {
+            flags: MessageBoxButtonFlag::ESCAPEKEY_DEFAULT,
+            button_id: 0,
+            text: "Start over",
+        },
+        ButtonData {
+            flags: MessageBoxButtonFlag::RETURNKEY_DEFAULT,
+            button_id: RESUME,
+            text: "Resume",
+        },
+    ];
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let message = format!(
+        "Resume {:?} where you left off {}?",
+        name,
+        age(state.saved_at, now)
+    );
+    match show_message_box(
+        MessageBoxFlag::INFORMATION,
+        &buttons,
+        name,
+        &message,
+        None,
+        None,
+    ) {
+        Ok(ClickedButton::CustomButton(button)) => button.button_id == RESUME,
+        Ok(ClickedButton::CloseButton) => false,
+        Err(e) => {
+            warn!("Failed to ask whether to resume {:?}: {:?}", name, e);
+            true
+        }
+    }
+}
+
+/// How long ago a state saved at `saved_at` was, at `now` (both in seconds
+/// since the Unix epoch)
+fn age(saved_at: u64, now: u64) -> String {
+    if saved_at == 0 {
+        return "last time".to_string();
+    }
+    let (count, unit) = match now.saturating_sub(saved_at) {
+        seconds if seconds < 60 => return "just now".to_string(),
+        seconds if seconds < 3600 => (seconds / 60, "minute"),
+        seconds if seconds < 86400 => (seconds / 3600, "hour"),
+        seconds => (seconds / 86400, "day"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_age() {
+        assert_eq!(age(0, 1000), "last time");
+        assert_eq!(age(1000, 1030), "just now");
+        assert_eq!(age(1000, 1060), "1 minute ago");
+        assert_eq!(age(1000, 1000 + 3 * 3600), "3 hours ago");
+        assert_eq!(age(1000, 1000 + 2 * 86400 + 5), "2 days ago");
+        // A clock set back since saving
+        assert_eq!(age(1000, 500), "just now");
+    }
+}
@@ -0,0 +1,281 @@
+//! Golden-Frame Testing
+//!
+//! Helpers for guest authors to check their rendering from `cargo test`,
+//! built with the `testing` feature. A [`GoldenRun`] plays a package
+//! headlessly under the same conditions as `wapps test`: a fixed `dt`, a
+//! disabled clock and a seeded random source, so frames do not depend on the
+//! machine. Its frames are then compared against reference PNGs within a
+//! [`Tolerance`]; a mismatch carries a diff image to save next to the test.
+//!
+//! ```no_run
+//! use wapps_host::testing::{GoldenRun, Tolerance};
+//! use wapps_host::GuestEvent;
+//!
+//! let mut run = GoldenRun::from_file("game.wapp").unwrap();
+//! run.run(30, &[]).unwrap();
+//! run.run(1, &[GuestEvent::KeyDown { scancode: 44, modifiers: 0, repeat: false }])
+//!     .unwrap();
+//! if let Some(mismatch) = run.compare("tests/golden/start.png", Tolerance::default()).unwrap() {
+//!     mismatch.save_diff("target/start.diff.png").unwrap();
+//!     panic!("{}", mismatch);
+//! }
+//! ```
+//!
+//! Setting `WAPPS_UPDATE_GOLDEN=1` makes `compare` write the current frame as
+//! the reference instead, to create or update references after an intended
+//! change.
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::events::GuestEvent;
+use crate::loader::{self, WappMetadata};
+use crate::png;
+use crate::runner::{Frame, Runner};
+use crate::wasi_policy::{ClockPolicy, WasiPolicy};
+use crate::watchdog;
+
+/// Time step passed to `update` for each frame, as in `wapps test`
+pub const FRAME_DT: f64 = 1.0 / 60.0;
+
+/// Environment variable that turns comparisons into reference updates
+const UPDATE_VARIABLE: &str = "WAPPS_UPDATE_GOLDEN";
+
+/// Color of the pixels a diff image marks as differing
+const HIGHLIGHT: [u8; 4] = [255, 0, 255, 255];
+
+/// How far a frame may stray from its reference
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tolerance {
+    /// Largest difference allowed in any channel of a pixel
+    pub channel: u8,
+    /// Pixels allowed to differ by more than `channel`
+    pub pixels: usize,
+}
+
+/// A frame that differs from its reference beyond the tolerance
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    /// Size of the frame
+    pub size: (u32, u32),
+    /// Size of the reference
+    pub reference_size: (u32, u32),
+    /// Pixels differing by more than the tolerance, including those only one
+    /// of the images covers
+    pub differing_pixels: usize,
+    /// The frame dimmed to gray, with the differing pixels in magenta
+    pub diff: Frame,
+}
+
+impl Mismatch {
+    /// Write the diff image to `path` as a PNG
+    pub fn save_diff(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let png = png::encode_rgba(self.diff.width, self.diff.height, &self.diff.pixels);
+        fs::write(path, png).with_context(|| format!("Failed to write diff image {:?}", path))
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.size != self.reference_size {
+            write!(
+                f,
+                "frame is {}x{}, reference is {}x{}; ",
+                self.size.0, self.size.1, self.reference_size.0, self.reference_size.1
+            )?;
+        }
+        write!(f, "{} pixels differ", self.differing_pixels)
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// A package run headlessly with reproducible inputs
+pub struct GoldenRun {
+    runner: Runner,
+    /// Frames updated so far
+    frames: u64,
+}
+
+impl GoldenRun {
+    /// Load and start the package at `path`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (wasm_bytes, metadata) = loader::load_wapp(path)
+            .with_context(|| format!("Failed to load WAPP file: {:?}", path))?;
+        Self::new(&wasm_bytes, metadata, 0)
+    }
+
+    /// Start the guest module `wasm_bytes` of a package described by
+    /// `metadata`, seeding its random source with `random_seed`
+    pub fn new(wasm_bytes: &[u8], metadata: WappMetadata, random_seed: u64) -> Result<Self> {
+        let policy = WasiPolicy {
+            clock: ClockPolicy::Disabled,
+            random_seed: Some(random_seed),
+        };
+        let mut runner = Runner::with_policy(wasm_bytes, metadata, &policy)?;
+        // A guest stuck in a loop fails its test instead of hanging it
+        runner.set_frame_budget(Some(watchdog::DEFAULT_FRAME_BUDGET));
+        Ok(Self { runner, frames: 0 })
+    }
+
+    /// Update the guest for `frames` frames, delivering `events` before the
+    /// first, and return its latest frame
+    pub fn run(&mut self, frames: u32, events: &[GuestEvent]) -> Result<&Frame> {
+        for index in 0..frames {
+            let events = if index == 0 { events } else { &[] };
+            self.runner
+                .tick(FRAME_DT, events)
+                .with_context(|| format!("Guest failed at frame {}", self.frames))?;
+            self.frames += 1;
+        }
+        Ok(self.runner.frame())
+    }
+
+    /// The guest's latest frame
+    pub fn frame(&self) -> &Frame {
+        self.runner.frame()
+    }
+
+    /// Frames updated so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Compare the latest frame against the reference PNG at `path`
+    ///
+    /// With `WAPPS_UPDATE_GOLDEN` set, the frame is written to `path` instead
+    /// and always matches.
+    pub fn compare(
+        &self,
+        path: impl AsRef<Path>,
+        tolerance: Tolerance,
+    ) -> Result<Option<Mismatch>> {
+        let path = path.as_ref();
+        let frame = self.frame();
+        if std::env::var_os(UPDATE_VARIABLE).is_some() {
+            let png = png::encode_rgba(frame.width, frame.height, &frame.pixels);
+            fs::write(path, png)
+                .with_context(|| format!("Failed to write reference {:?}", path))?;
+            return Ok(None);
+        }
+        let reference = load_reference(path)?;
+        Ok(compare_frames(frame, &reference, tolerance))
+    }
+}
+
+/// Read a reference PNG
+pub fn load_reference(path: impl AsRef<Path>) -> Result<Frame> {
+    let path = path.as_ref();
+    let data = fs::read(path).with_context(|| format!("Failed to read reference {:?}", path))?;
+    let (width, height, pixels) = png::decode_rgba(&data)
+        .with_context(|| format!("Failed to decode reference {:?}", path))?;
+    Ok(Frame {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Compare `frame` against `reference`, pixel by pixel over the area either
+/// covers
+pub fn compare_frames(frame: &Frame, reference: &Frame, tolerance: Tolerance) -> Option<Mismatch> {
+    fn pixel(image: &Frame, x: u32, y: u32) -> Option<&[u8]> {
+        (x < image.width && y < image.height).then(|| {
+            let i = ((y * image.width + x) * 4) as usize;
+            &image.pixels[i..i + 4]
+        })
+    }
+    let width = frame.width.max(reference.width);
+    let height = frame.height.max(reference.height);
+    let mut diff = Vec::with_capacity(width as usize * height as usize * 4);
+    let mut differing_pixels = 0;
+    for y in 0..height {
+        for x in 0..width {
+            match (pixel(frame, x, y), pixel(reference, x, y)) {
+                (Some(ours), Some(theirs))
+                    if ours
+                        .iter()
+                        .zip(theirs)
+                        .all(|(a, b)| a.abs_diff(*b) <= tolerance.channel) =>
+                {
+                    let luma =
+                        (ours[0] as u32 * 77 + ours[1] as u32 * 150 + ours[2] as u32 * 29) >> 8;
+                    let dimmed = (luma / 4) as u8;
+                    diff.extend_from_slice(&[dimmed, dimmed, dimmed, 255]);
+                }
+                _ => {
+                    differing_pixels += 1;
+                    diff.extend_from_slice(&HIGHLIGHT);
+                }
+            }
+        }
+    }
+
+    let size = (frame.width, frame.height);
+    let reference_size = (reference.width, reference.height);
+    (differing_pixels > tolerance.pixels || size != reference_size).then_some(Mismatch {
+        size,
+        reference_size,
+        differing_pixels,
+        diff: Frame {
+            width,
+            height,
+            pixels: diff,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, pixels: &[[u8; 4]]) -> Frame {
+        Frame {
+            width,
+            height,
+            pixels: pixels.concat(),
+        }
+    }
+
+    #[test]
+    fn test_frames_match_within_tolerance() {
+        let reference = frame(2, 1, &[[10, 20, 30, 255], [0, 0, 0, 255]]);
+        let close = frame(2, 1, &[[12, 18, 30, 255], [0, 0, 0, 255]]);
+        let tolerance = Tolerance {
+            channel: 2,
+            pixels: 0,
+        };
+        assert!(compare_frames(&close, &reference, tolerance).is_none());
+
+        let mismatch = compare_frames(&close, &reference, Tolerance::default()).unwrap();
+        assert_eq!(mismatch.differing_pixels, 1);
+        assert_eq!(mismatch.diff.pixels[..4], HIGHLIGHT);
+        assert_eq!(mismatch.diff.pixels[4..], [0, 0, 0, 255]);
+        let tolerance = Tolerance {
+            channel: 0,
+            pixels: 1,
+        };
+        assert!(compare_frames(&close, &reference, tolerance).is_none());
+    }
+
+    #[test]
+    fn test_size_changes_always_mismatch() {
+        let reference = frame(1, 1, &[[0, 0, 0, 255]]);
+        let wider = frame(2, 1, &[[0, 0, 0, 255], [0, 0, 0, 255]]);
+        let tolerance = Tolerance {
+            channel: 255,
+            pixels: 1,
+        };
+        let mismatch = compare_frames(&wider, &reference, tolerance).unwrap();
+        assert_eq!(mismatch.differing_pixels, 1);
+        assert_eq!((mismatch.diff.width, mismatch.diff.height), (2, 1));
+        assert_eq!(
+            mismatch.to_string(),
+            "frame is 2x1, reference is 1x1; 1 pixels differ"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Host Text
+//!
+//! Tiny apps can show text without shipping a font rasterizer: they queue
+//! `wapps::draw_text` calls, drawn over the frame like `wapps::draw_image`
+//! (in call order, once the frame is shown), and lay text out with
+//! `wapps::measure_text`. Text uses the host's 3x5 bitmap font, in upper
+//! case, scaled by whole multiples to the requested size; `\n` starts a new
+//! line.
+
+use crate::layers::blend_pixel;
+
+pub const GLYPH_WIDTH: i32 = 3;
+pub const GLYPH_HEIGHT: i32 = 5;
+
+/// Longest text a single draw may hold, in bytes
+pub const MAX_TEXT_LEN: usize = 4096;
+
+/// Largest glyph scale, keeping a draw's cost bounded
+const MAX_SCALE: i32 = 64;
+
+/// Glyph for characters the font lacks
+const UNKNOWN: [u8; 5] = [0b110, 0b001, 0b010, 0b000, 0b010];
+
+/// 3x5 bitmap glyph, one row per byte with the leftmost pixel in bit 2
+pub fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b111, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b111, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0; 5],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        ';' => [0b000, 0b010, 0b000, 0b010, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => UNKNOWN,
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+        '`' => [0b100, 0b010, 0b000, 0b000, 0b000],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '[' => [0b011, 0b010, 0b010, 0b010, 0b011],
+        ']' => [0b110, 0b010, 0b010, 0b010, 0b110],
+        '{' => [0b011, 0b010, 0b110, 0b010, 0b011],
+        '}' => [0b110, 0b010, 0b011, 0b010, 0b110],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '\\' => [0b100, 0b100, 0b010, 0b001, 0b001],
+        '|' => [0b010, 0b010, 0b010, 0b010, 0b010],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '&' => [0b010, 0b101, 0b010, 0b101, 0b011],
+        '@' => [0b010, 0b101, 0b111, 0b100, 0b011],
+        '$' => [0b011, 0b110, 0b010, 0b011, 0b110],
+        '^' => [0b010, 0b101, 0b000, 0b000, 0b000],
+        '~' => [0b000, 0b011, 0b110, 0b000, 0b000],
+        _ => UNKNOWN,
+    }
+}
+
+/// A queued `wapps::draw_text` call
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextDraw {
+    /// Frame position of the text's top-left corner
+    pub x: i32,
+    pub y: i32,
+    pub text: String,
+    /// Packed as `0xRRGGBBAA`
+    pub rgba: u32,
+    pub size: i32,
+}
+
+/// Glyph scale for text `size` pixels high: the largest whole multiple of the
+/// font's height that fits, at least 1
+pub fn scale(size: i32) -> i32 {
+    (size / GLYPH_HEIGHT).clamp(1, MAX_SCALE)
+}
+
+/// Width and height in pixels of `text` drawn at `size`, without the spacing
+/// after the last column and line
+pub fn measure(text: &str, size: i32) -> (i32, i32) {
+    let scale = scale(size);
+    let (lines, columns) = text.split('\n').fold((0, 0), |(lines, columns), line| {
+        (lines + 1, columns.max(line.chars().count() as i32))
+    });
+    let width = match columns {
+        0 => 0,
+        _ => (columns * (GLYPH_WIDTH + 1) - 1) * scale,
+    };
+    (width, (lines * (GLYPH_HEIGHT + 1) - 1) * scale)
+}
+
+/// Draw `draw` into the `width` x `height` RGBA `target`, clipped to it
+pub fn draw(target: &mut [u8], width: u32, height: u32, draw: &TextDraw) {
+    let scale = scale(draw.size);
+    let color = draw.rgba.to_be_bytes();
+    let (width, height) = (width as i64, height as i64);
+    for (line, text) in draw.text.split('\n').enumerate() {
+        let top = draw.y as i64 + line as i64 * ((GLYPH_HEIGHT + 1) * scale) as i64;
+        for (i, c) in text.chars().enumerate() {
+            let left = draw.x as i64 + i as i64 * ((GLYPH_WIDTH + 1) * scale) as i64;
+            if left >= width || top >= height {
+                break;
+            }
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    let x0 = (left + (column * scale) as i64).clamp(0, width);
+                    let x1 = (left + ((column + 1) * scale) as i64).clamp(0, width);
+                    let y0 = (top + row as i64 * scale as i64).clamp(0, height);
+                    let y1 = (top + (row as i64 + 1) * scale as i64).clamp(0, height);
+                    for y in y0..y1 {
+                        for x in x0..x1 {
+                            let i = (y * width + x) as usize * 4;
+                            blend_pixel(&mut target[i..i + 4], &color, 1.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_and_draw() {
+        assert_eq!(scale(0), 1);
+        assert_eq!(scale(12), 2);
+        assert_eq!(measure("", 5), (0, 5));
+        assert_eq!(measure("hi", 5), (7, 5));
+        assert_eq!(measure("hi\nthere", 10), (38, 22));
+
+        // "1" at (1, 1) in a 4x7 frame: its 1-pixel wide stem is in column 2
+        let mut target = vec![0u8; 4 * 7 * 4];
+        let white = TextDraw {
+            x: 1,
+            y: 1,
+            text: "1".to_string(),
+            rgba: 0xffffffff,
+            size: 5,
+        };
+        draw(&mut target, 4, 7, &white);
+        let lit = |x: usize, y: usize| target[(y * 4 + x) * 4 + 3] == 255;
+        assert!(lit(2, 1) && lit(2, 3) && lit(1, 2) && lit(3, 5));
+        assert!(!lit(0, 1) && !lit(3, 1) && !lit(1, 3));
+
+        // Text off the frame is clipped
+        let offscreen = TextDraw {
+            x: -100,
+            y: 3,
+            ..white
+        };
+        draw(&mut target, 4, 7, &offscreen);
+    }
+}
@@ -0,0 +1,90 @@
+//! System Theme
+//!
+//! Whether the user prefers dark interfaces, which guests read with
+//! `wapps::get_prefers_dark` to match their colors. The host asks the desktop
+//! once per run: the GNOME color scheme (or a `:dark` `GTK_THEME`) on Linux,
+//! `AppleInterfaceStyle` on macOS and `AppsUseLightTheme` on Windows. Systems
+//! that cannot tell count as light. `--theme` overrides the answer, e.g. to
+//! test both looks of an app.
+
+use clap::ValueEnum;
+use log::debug;
+use std::process::Command;
+
+/// Light or dark interface preference
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// The desktop's preference, or light if it cannot be read
+    pub fn system() -> Self {
+        let dark = system_prefers_dark().unwrap_or(false);
+        debug!("System theme: {}", if dark { "dark" } else { "light" });
+        if dark {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    }
+
+    pub fn is_dark(self) -> bool {
+        self == Theme::Dark
+    }
+}
+
+/// Output of `program` run with `args`, if it ran and succeeded
+#[cfg_attr(
+    not(any(target_os = "linux", target_os = "macos", target_os = "windows")),
+    allow(dead_code)
+)]
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "linux")]
+fn system_prefers_dark() -> Option<bool> {
+    if let Ok(theme) = std::env::var("GTK_THEME") {
+        return Some(theme.ends_with(":dark"));
+    }
+    let scheme = output(
+        "gsettings",
+        &["get", "org.gnome.desktop.interface", "color-scheme"],
+    )?;
+    Some(scheme.contains("prefer-dark"))
+}
+
+#[cfg(target_os = "macos")]
+fn system_prefers_dark() -> Option<bool> {
+    // The key only exists in dark mode, so reading it fails in light mode
+    Some(
+        output("defaults", &["read", "-g", "AppleInterfaceStyle"])
+            .is_some_and(|style| style.trim() == "Dark"),
+    )
+}
+
+#[cfg(target_os = "windows")]
+fn system_prefers_dark() -> Option<bool> {
+    let query = output(
+        "reg",
+        &[
+            "query",
+            r"HKCU\Software\Microsoft\Windows\CurrentVersion\Themes\Personalize",
+            "/v",
+            "AppsUseLightTheme",
+        ],
+    )?;
+    Some(query.split_whitespace().last()? == "0x0")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn system_prefers_dark() -> Option<bool> {
+    None
+}
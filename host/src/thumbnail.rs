@@ -0,0 +1,181 @@
+//! `wapps thumbnail` Command
+//!
+//! Runs a package headlessly for a number of frames and saves a downscaled
+//! PNG of its last frame, for galleries to show packages that have no
+//! embedded icon. Thumbnails go to the user cache directory by default, named
+//! after a hash of the package so a new version gets a new thumbnail.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::info;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::host_interface::HostInterface;
+use crate::loader;
+use crate::memory_limit;
+use crate::png;
+use crate::runtime::WasmRuntime;
+use crate::storage::{self, AppStorage};
+use crate::wasi_policy::WasiPolicy;
+
+/// Time step passed to the guest's `update` for each headless frame
+const FRAME_DT: f64 = 1.0 / 60.0;
+
+/// Arguments of `wapps thumbnail`
+#[derive(Args, Debug)]
+pub struct ThumbnailArgs {
+    /// Package to render
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// Number of frames to run before capturing
+    #[arg(long, value_name = "N", default_value_t = 60)]
+    frames: u32,
+
+    /// Largest width or height of the thumbnail, in pixels
+    #[arg(long, value_name = "PIXELS", default_value_t = 256)]
+    size: u32,
+
+    /// Where to write the PNG (defaults to the thumbnail cache)
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+/// Run `wapps thumbnail`
+pub fn run(args: &ThumbnailArgs) -> Result<()> {
+    if args.size == 0 {
+        bail!("--size must be positive");
+    }
+    let data = fs::read(&args.file)
+        .with_context(|| format!("Could not read file: {}", args.file.display()))?;
+    let package = loader::parse_package(&data)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", args.file))?;
+
+    let mut host_interface = HostInterface::new();
+    host_interface.set_app_info(
+        package.metadata.name.clone(),
+        package.metadata.version.clone(),
+    );
+    host_interface.set_strings(package.metadata.strings.clone());
+    host_interface.set_config(package.metadata.config.clone());
+    // Rendering a thumbnail must not touch the app's saved data
+    host_interface.set_storage(AppStorage::in_memory());
+    let policy = WasiPolicy::resolve(&package.metadata.wasi, None, None);
+    let mut runtime = WasmRuntime::new(
+        &package.module().data,
+        None,
+        host_interface,
+        std::slice::from_ref(&package.metadata.name),
+        None,
+        &policy,
+        false,
+        Some(memory_limit::DEFAULT_MAX_MEMORY),
+    )
+    .context("Failed to initialize WASM runtime")?;
+
+    for _ in 0..args.frames {
+        runtime.run_frame(&[], FRAME_DT)?;
+    }
+    let (width, height, pixels) = runtime
+        .with_frame_data(|width, height, pixels| {
+            let (width, height) = (width as u32, height as u32);
+            let (thumb_w, thumb_h) = fit(width, height, args.size);
+            let thumb = downscale(width, height, pixels, thumb_w, thumb_h);
+            (thumb_w, thumb_h, thumb)
+        })
+        .context("The app did not present a frame")?;
+    if width == 0 || height == 0 {
+        bail!("The app presented an empty frame");
+    }
+
+    let output = match &args.output {
+        Some(path) => path.clone(),
+        None => cache_path(&data).context("No user cache directory; pass --output")?,
+    };
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+    }
+    fs::write(&output, png::encode_rgba(width, height, &pixels))
+        .with_context(|| format!("Could not write thumbnail: {}", output.display()))?;
+
+    info!("{}x{} thumbnail written", width, height);
+    println!("{}", output.display());
+    Ok(())
+}
+
+/// Cached thumbnail location for a package, keyed by a hash of its bytes
+pub fn cache_path(package: &[u8]) -> Option<PathBuf> {
+    let hash = Sha256::digest(package);
+    let hex: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    Some(
+        storage::cache_dir()?
+            .join("thumbnails")
+            .join(format!("{}.png", hex)),
+    )
+}
+
+/// Size fitting `width` x `height` within `max` x `max`, keeping the aspect
+/// ratio and never enlarging
+fn fit(width: u32, height: u32, max: u32) -> (u32, u32) {
+    let largest = width.max(height);
+    if largest <= max {
+        return (width, height);
+    }
+    let scale = |side: u32| ((side as u64 * max as u64 / largest as u64) as u32).max(1);
+    (scale(width), scale(height))
+}
+
+/// Downscale RGBA pixels by averaging the source pixels covering each output pixel
+fn downscale(width: u32, height: u32, pixels: &[u8], out_w: u32, out_h: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(out_w as usize * out_h as usize * 4);
+    for y in 0..out_h {
+        let (y0, y1) = span(y, out_h, height);
+        for x in 0..out_w {
+            let (x0, x1) = span(x, out_w, width);
+            let mut sum = [0u64; 4];
+            for sy in y0..y1 {
+                let row = &pixels[(sy * width + x0) as usize * 4..(sy * width + x1) as usize * 4];
+                for pixel in row.chunks_exact(4) {
+                    for (total, &channel) in sum.iter_mut().zip(pixel) {
+                        *total += channel as u64;
+                    }
+                }
+            }
+            let count = ((x1 - x0) * (y1 - y0)) as u64;
+            out.extend(sum.iter().map(|total| (total / count) as u8));
+        }
+    }
+    out
+}
+
+/// Source range covered by output index `i` of `out` when scaling from `len`
+fn span(i: u32, out: u32, len: u32) -> (u32, u32) {
+    let start = (i as u64 * len as u64 / out as u64) as u32;
+    let end = ((i as u64 + 1) * len as u64 / out as u64) as u32;
+    (start, end.max(start + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_keeps_aspect_ratio() {
+        assert_eq!(fit(800, 600, 256), (256, 192));
+        assert_eq!(fit(100, 50, 256), (100, 50));
+        assert_eq!(fit(1000, 1, 256), (256, 1));
+    }
+
+    #[test]
+    fn test_downscale_averages() {
+        #[rustfmt::skip]
+        let pixels = [
+            0, 0, 0, 255,    255, 255, 255, 255,
+            255, 255, 255, 255, 0, 0, 0, 255,
+        ];
+        assert_eq!(downscale(2, 2, &pixels, 1, 1), [127, 127, 127, 255]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Time Control
+//!
+//! Debug controls for simulations that need no support from the guest: F8
+//! pauses every app, Shift+F8 runs a single update while paused, and
+//! Ctrl+F9 / Ctrl+F10 slow time down to a quarter or speed it up to four
+//! times real time by scaling the dt passed to `update`. `--paused` and
+//! `--time-scale` set them from launch.
+
+/// Time scales Ctrl+F9 and Ctrl+F10 step through
+const SCALES: [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Pause, single-step and time scale state of the host
+pub struct TimeControl {
+    paused: bool,
+    /// A single update is due while paused
+    step: bool,
+    scale: f64,
+}
+
+impl TimeControl {
+    pub fn new(paused: bool, scale: f64) -> Self {
+        Self {
+            paused,
+            step: false,
+            scale,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.step = false;
+    }
+
+    /// Run one update, pausing first if running
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step = true;
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Move the time scale `steps` notches along 0.25x, 0.5x, 1x, 2x and 4x,
+    /// returning the new scale
+    pub fn change_scale(&mut self, steps: i32) -> f64 {
+        // Scales set with --time-scale may fall between notches
+        let current = SCALES
+            .iter()
+            .position(|&scale| scale >= self.scale)
+            .unwrap_or(SCALES.len() - 1) as i32;
+        let current = if steps > 0 && SCALES[current as usize] > self.scale {
+            current - 1
+        } else {
+            current
+        };
+        let index = (current + steps).clamp(0, SCALES.len() as i32 - 1);
+        self.scale = SCALES[index as usize];
+        self.scale
+    }
+
+    /// Whether apps update this frame, taking the single update due if paused
+    pub fn take_update(&mut self) -> bool {
+        !self.paused || std::mem::take(&mut self.step)
+    }
+}
+
+/// Parse a `--time-scale` value, between 0.25 and 4
+pub fn parse_time_scale(value: &str) -> Result<f64, String> {
+    let scale: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid time scale: {}", value))?;
+    if !(0.25..=4.0).contains(&scale) {
+        return Err("the time scale must be between 0.25 and 4".to_string());
+    }
+    Ok(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_and_scales() {
+        let mut time = TimeControl::new(false, 1.0);
+        assert!(time.take_update());
+        time.step();
+        assert!(time.is_paused());
+        assert!(time.take_update());
+        assert!(!time.take_update());
+        time.set_paused(false);
+        assert!(time.take_update());
+
+        assert_eq!(time.change_scale(-1), 0.5);
+        assert_eq!(time.change_scale(-5), 0.25);
+        assert_eq!(time.change_scale(4), 4.0);
+        assert_eq!(time.change_scale(1), 4.0);
+
+        // Between notches, the next one in either direction
+        let mut time = TimeControl::new(false, 1.5);
+        assert_eq!(time.change_scale(1), 2.0);
+        let mut time = TimeControl::new(false, 1.5);
+        assert_eq!(time.change_scale(-1), 1.0);
+
+        assert_eq!(parse_time_scale("0.25"), Ok(0.25));
+        assert!(parse_time_scale("8").is_err());
+        assert!(parse_time_scale("fast").is_err());
+    }
+}
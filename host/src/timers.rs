@@ -0,0 +1,120 @@
+//! Guest Timers
+//!
+//! Apps that only need occasional work, like clocks and dashboards, schedule
+//! it with `wapps::set_timer(id, seconds, repeating)` instead of adding up
+//! `dt` in every `update`. Timers advance by each frame's `dt`, so they
+//! follow pauses and replayed sessions like `update` does, and fire through
+//! the guest's `on_timer(id)` export before that frame's `update`. A repeating
+//! timer fires at most once per frame; periods missed while the host was busy
+//! are skipped rather than delivered in a burst.
+
+use std::collections::BTreeMap;
+
+/// Timers an app may have scheduled at once
+pub const MAX_TIMERS: usize = 64;
+
+/// Status codes returned by `wapps::set_timer` and `wapps::cancel_timer`
+pub const TIMER_OK: i32 = 0;
+pub const TIMER_INVALID: i32 = -1;
+pub const TIMER_TOO_MANY: i32 = -2;
+pub const TIMER_NOT_FOUND: i32 = -3;
+
+/// A scheduled timer
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Timer {
+    /// Seconds until it fires
+    remaining: f64,
+    /// Seconds between firings of a repeating timer
+    period: Option<f64>,
+}
+
+/// One app's timers, by guest-chosen id
+#[derive(Debug, Default)]
+pub struct Timers {
+    timers: BTreeMap<i32, Timer>,
+}
+
+impl Timers {
+    /// Schedule timer `id` to fire in `seconds`, and every `seconds` after
+    /// that if `repeating`, replacing any timer with that id; returns a
+    /// `TIMER_*` status
+    pub fn set(&mut self, id: i32, seconds: f64, repeating: bool) -> i32 {
+        let valid = seconds.is_finite() && seconds >= 0.0 && !(repeating && seconds == 0.0);
+        if !valid {
+            return TIMER_INVALID;
+        }
+        if self.timers.len() >= MAX_TIMERS && !self.timers.contains_key(&id) {
+            return TIMER_TOO_MANY;
+        }
+        let timer = Timer {
+            remaining: seconds,
+            period: repeating.then_some(seconds),
+        };
+        self.timers.insert(id, timer);
+        TIMER_OK
+    }
+
+    /// Cancel timer `id`, returning `TIMER_OK` or `TIMER_NOT_FOUND`
+    pub fn cancel(&mut self, id: i32) -> i32 {
+        match self.timers.remove(&id) {
+            Some(_) => TIMER_OK,
+            None => TIMER_NOT_FOUND,
+        }
+    }
+
+    /// Seconds until the next timer fires, if any is scheduled
+    pub fn next(&self) -> Option<f64> {
+        self.timers
+            .values()
+            .map(|timer| timer.remaining)
+            .min_by(f64::total_cmp)
+    }
+
+    /// Advance every timer by `dt` seconds, returning the ids of those that
+    /// fired, earliest first
+    pub fn advance(&mut self, dt: f64) -> Vec<i32> {
+        let mut fired: Vec<(f64, i32)> = Vec::new();
+        self.timers.retain(|&id, timer| {
+            timer.remaining -= dt;
+            if timer.remaining > 0.0 {
+                return true;
+            }
+            fired.push((timer.remaining, id));
+            match timer.period {
+                Some(period) => {
+                    timer.remaining = period - (-timer.remaining % period);
+                    true
+                }
+                None => false,
+            }
+        });
+        fired.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        fired.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timers_fire_in_order() {
+        let mut timers = Timers::default();
+        assert_eq!(timers.set(1, 0.5, false), TIMER_OK);
+        assert_eq!(timers.set(2, 0.25, true), TIMER_OK);
+        assert_eq!(timers.set(3, 0.0, true), TIMER_INVALID);
+        assert_eq!(timers.set(3, f64::NAN, false), TIMER_INVALID);
+
+        assert_eq!(timers.advance(0.125), Vec::<i32>::new());
+        assert_eq!(timers.next(), Some(0.125));
+        assert_eq!(timers.advance(0.5), vec![2, 1]);
+        // The repeating timer skips the period it missed
+        assert_eq!(timers.advance(0.625), vec![2]);
+        assert_eq!(timers.advance(0.125), Vec::<i32>::new());
+        assert_eq!(timers.advance(0.125), vec![2]);
+
+        assert_eq!(timers.cancel(2), TIMER_OK);
+        assert_eq!(timers.cancel(1), TIMER_NOT_FOUND);
+        assert!(timers.advance(10.0).is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+//! Timing Overlay
+//!
+//! Debug view toggled with F1 that shows, in the corner of the window, the
+//! app's frame rate, the time its `update` calls take (event callbacks
+//! included), the time uploading its frames to the GPU takes, the size of
+//! those frames, and the input latency: the time from the host reading an
+//! input to presenting the first frame of the update it reached. Figures are
+//! averaged and refreshed twice a second so they stay readable.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::time::{Duration, Instant};
+
+use crate::font;
+use crate::inspector::OverlayRect;
+
+/// How often the figures are recomputed
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+/// On-screen size of each font pixel
+const TEXT_SCALE: i32 = 2;
+/// Margin around the text, inside the panel and between it and the window
+const PADDING: i32 = 4;
+
+const BACKGROUND: Color = Color::RGB(0, 0, 0);
+const TEXT_COLOR: Color = Color::RGB(96, 255, 96);
+
+/// Measures an app's frame timing and draws it over its frames
+pub struct TimingOverlay {
+    interval_start: Instant,
+    frames: u32,
+    update_time: Duration,
+    updates: u32,
+    upload_time: Duration,
+    uploads: u32,
+    frame_size: Option<(u32, u32)>,
+    input_latency: Duration,
+    inputs: u32,
+    /// Text shown, one entry per line
+    lines: Vec<String>,
+}
+
+impl TimingOverlay {
+    pub fn new(now: Instant) -> Self {
+        let mut overlay = Self {
+            interval_start: now,
+            frames: 0,
+            update_time: Duration::ZERO,
+            updates: 0,
+            upload_time: Duration::ZERO,
+            uploads: 0,
+            frame_size: None,
+            input_latency: Duration::ZERO,
+            inputs: 0,
+            lines: Vec::new(),
+        };
+        overlay.refresh(None);
+        overlay
+    }
+
+    /// Record a call to the guest's `update`, with the events delivered before it
+    pub fn record_update(&mut self, elapsed: Duration) {
+        self.update_time += elapsed;
+        self.updates += 1;
+    }
+
+    /// Record the upload of a `width` x `height` frame to the GPU
+    pub fn record_upload(&mut self, width: u32, height: u32, elapsed: Duration) {
+        self.upload_time += elapsed;
+        self.uploads += 1;
+        self.frame_size = Some((width, height));
+    }
+
+    /// Record a frame answering input read `latency` before it was presented
+    pub fn record_input_latency(&mut self, latency: Duration) {
+        self.input_latency += latency;
+        self.inputs += 1;
+    }
+
+    /// Record a presented frame, refreshing the figures when due
+    pub fn record_present(&mut self, now: Instant) {
+        self.frames += 1;
+        let elapsed = now.duration_since(self.interval_start);
+        if elapsed >= REFRESH_INTERVAL {
+            self.refresh(Some(elapsed));
+            self.interval_start = now;
+            self.frames = 0;
+            self.update_time = Duration::ZERO;
+            self.updates = 0;
+            self.upload_time = Duration::ZERO;
+            self.uploads = 0;
+            self.input_latency = Duration::ZERO;
+            self.inputs = 0;
+        }
+    }
+
+    /// Recompute the text from the interval that lasted `elapsed`, if any
+    fn refresh(&mut self, elapsed: Option<Duration>) {
+        let average = |total: Duration, count: u32| match count {
+            0 => "-".to_string(),
+            _ => format!("{:.2} MS", total.as_secs_f64() * 1000.0 / count as f64),
+        };
+        let fps = match elapsed {
+            Some(elapsed) => format!("{:.1}", self.frames as f64 / elapsed.as_secs_f64()),
+            None => "-".to_string(),
+        };
+        let size = match self.frame_size {
+            Some((width, height)) => format!("{}X{}", width, height),
+            None => "-".to_string(),
+        };
+        self.lines = vec![
+            format!("FPS    {}", fps),
+            format!("UPDATE {}", average(self.update_time, self.updates)),
+            format!("UPLOAD {}", average(self.upload_time, self.uploads)),
+            format!("FRAME  {}", size),
+            format!("INPUT  {}", average(self.input_latency, self.inputs)),
+        ];
+    }
+
+    /// Rectangles drawing the figures in the top-left corner of the window
+    pub fn overlay(&self) -> Vec<OverlayRect> {
+        let columns = self.lines.iter().map(|line| line.len()).max().unwrap_or(0) as i32;
+        let line_height = font::line_height(TEXT_SCALE);
+        let width = columns * font::advance(TEXT_SCALE) + PADDING * 2;
+        let height = self.lines.len() as i32 * line_height + PADDING * 2;
+        let mut rects = vec![(
+            Rect::new(PADDING, PADDING, width as u32, height as u32),
+            BACKGROUND,
+        )];
+        for (index, line) in self.lines.iter().enumerate() {
+            let top = PADDING * 2 + index as i32 * line_height;
+            font::draw_text(&mut rects, line, PADDING * 2, top, TEXT_SCALE, TEXT_COLOR);
+        }
+        rects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_figures_are_averaged_per_interval() {
+        let start = Instant::now();
+        let mut timing = TimingOverlay::new(start);
+        assert_eq!(timing.lines[0], "FPS    -");
+
+        for frame in 1..=30 {
+            timing.record_update(Duration::from_millis(2));
+            timing.record_upload(320, 240, Duration::from_micros(500));
+            if frame % 5 == 0 {
+                timing.record_input_latency(Duration::from_millis(frame));
+            }
+            timing.record_present(start + Duration::from_millis(frame * 20));
+        }
+        // The interval closed after 25 frames in half a second
+        assert_eq!(timing.lines[0], "FPS    50.0");
+        assert_eq!(timing.lines[1], "UPDATE 2.00 MS");
+        assert_eq!(timing.lines[2], "UPLOAD 0.50 MS");
+        assert_eq!(timing.lines[3], "FRAME  320X240");
+        assert_eq!(timing.lines[4], "INPUT  15.00 MS");
+        assert_eq!(timing.overlay()[0].1, BACKGROUND);
+    }
+}
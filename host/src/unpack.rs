@@ -0,0 +1,104 @@
+//! `wapps unpack` Command
+//!
+//! Extracts a package's contents into a directory for inspection and
+//! repackaging: `module.wasm`, `manifest.json` (pretty-printed), `icon` if the
+//! package has one, and bundled assets under `assets/`. The layout matches the
+//! inputs of `wapps pack`, so an unpacked package can be edited and repacked.
+//! A precompiled module is extracted to `module.cwasm` for inspection;
+//! repacking with `--precompile` compiles it again.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use log::info;
+use serde_json::Value;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::loader::{self, SectionKind};
+
+/// Arguments of `wapps unpack`
+#[derive(Args, Debug)]
+pub struct UnpackArgs {
+    /// Package to extract
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// Directory to write the contents to (created if missing)
+    #[arg(short, long, value_name = "DIR")]
+    output: PathBuf,
+}
+
+/// Run `wapps unpack`
+pub fn run(args: &UnpackArgs) -> Result<()> {
+    let package = loader::load_package(&args.file)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", args.file))?;
+
+    fs::create_dir_all(&args.output)
+        .with_context(|| format!("Could not create directory: {}", args.output.display()))?;
+
+    let manifest: Value =
+        serde_json::from_slice(&package.manifest).context("Failed to parse manifest")?;
+    let mut manifest =
+        serde_json::to_vec_pretty(&manifest).context("Failed to serialize manifest")?;
+    manifest.push(b'\n');
+    write(&args.output.join("manifest.json"), &manifest)?;
+
+    for section in &package.sections {
+        let path = match section.kind {
+            SectionKind::Module => args.output.join("module.wasm"),
+            SectionKind::Icon => args.output.join("icon"),
+            SectionKind::Asset => asset_path(&args.output.join("assets"), &section.name)?,
+            SectionKind::Precompiled => args.output.join("module.cwasm"),
+            // Never kept in `sections`; repacking signs again
+            SectionKind::Signature => continue,
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create directory: {}", parent.display()))?;
+        }
+        write(&path, &section.data)?;
+    }
+
+    info!(
+        "Unpacked {} ({} sections) to {}",
+        args.file.display(),
+        package.sections.len(),
+        args.output.display()
+    );
+    Ok(())
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<()> {
+    fs::write(path, data).with_context(|| format!("Could not write file: {}", path.display()))
+}
+
+/// Where to extract an asset, refusing names that would escape `assets_dir`
+fn asset_path(assets_dir: &Path, name: &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    let is_safe = !name.is_empty()
+        && relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !is_safe {
+        bail!("Refusing to extract asset with unsafe path {:?}", name);
+    }
+    Ok(assets_dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_path_stays_inside_directory() {
+        let dir = Path::new("out/assets");
+        assert_eq!(
+            asset_path(dir, "sprites/player.png").unwrap(),
+            dir.join("sprites/player.png")
+        );
+        assert!(asset_path(dir, "").is_err());
+        assert!(asset_path(dir, "../escape").is_err());
+        assert!(asset_path(dir, "sprites/../../escape").is_err());
+        assert!(asset_path(dir, "/etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Update Rate Degradation
+//!
+//! With `--degrade`, an app whose `update` keeps taking longer than a frame
+//! is updated less often instead of stalling the host: every other frame at
+//! level 1, every fourth at level 2, each time with the `dt` of all the
+//! frames it skipped. The guest is told of every change through its
+//! `on_perf_hint` export. Updates are judged over windows of frames, like
+//! performance warnings, and the rate only goes back up once updates would
+//! comfortably fit the faster rate, so it does not flap between levels.
+
+use std::time::Duration;
+
+/// Updates in a measurement window
+const WINDOW: u32 = 30;
+
+/// Highest level: updates every 2^MAX_LEVEL frames
+const MAX_LEVEL: u32 = 2;
+
+/// Share of slow updates in a window that lowers the rate
+const SLOW_SHARE: f64 = 0.5;
+
+/// Share of an update's time at the faster rate it must stay under, in every
+/// update of a window, to raise the rate again
+const RECOVER_MARGIN: f64 = 0.75;
+
+/// Decides how often to update an app from how long its updates take
+pub struct UpdateRate {
+    level: u32,
+    /// Frames since the last update
+    skipped: u32,
+    /// Updates, slow updates and updates fitting the faster rate in the
+    /// current window
+    updates: u32,
+    slow: u32,
+    fitting: u32,
+}
+
+impl UpdateRate {
+    pub fn new() -> Self {
+        Self {
+            level: 0,
+            skipped: 0,
+            updates: 0,
+            slow: 0,
+            fitting: 0,
+        }
+    }
+
+    /// Whether to update this frame; frames in between are skipped
+    pub fn tick(&mut self) -> bool {
+        self.skipped += 1;
+        if self.skipped < 1 << self.level {
+            return false;
+        }
+        self.skipped = 0;
+        true
+    }
+
+    /// Record an update that took `elapsed` with frames lasting `frame`,
+    /// returning the new level when it changed
+    pub fn record_update(&mut self, elapsed: Duration, frame: Duration) -> Option<u32> {
+        let allowed = frame * (1 << self.level);
+        self.updates += 1;
+        if elapsed > allowed {
+            self.slow += 1;
+        }
+        if self.level > 0 && elapsed.as_secs_f64() < allowed.as_secs_f64() / 2.0 * RECOVER_MARGIN {
+            self.fitting += 1;
+        }
+        if self.updates < WINDOW {
+            return None;
+        }
+
+        let level = if self.slow as f64 >= self.updates as f64 * SLOW_SHARE {
+            (self.level + 1).min(MAX_LEVEL)
+        } else if self.fitting == self.updates {
+            self.level - 1
+        } else {
+            self.level
+        };
+        self.updates = 0;
+        self.slow = 0;
+        self.fitting = 0;
+        if level == self.level {
+            return None;
+        }
+        self.level = level;
+        self.skipped = 0;
+        Some(level)
+    }
+}
+
+impl Default for UpdateRate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    /// Run `frames` frames whose updates take `elapsed`, collecting the
+    /// reported levels and counting updates
+    fn run(rate: &mut UpdateRate, frames: u32, elapsed: Duration) -> (Vec<u32>, u32) {
+        let mut reported = Vec::new();
+        let mut updates = 0;
+        for _ in 0..frames {
+            if rate.tick() {
+                updates += 1;
+                reported.extend(rate.record_update(elapsed, FRAME));
+            }
+        }
+        (reported, updates)
+    }
+
+    #[test]
+    fn test_slow_updates_lower_the_rate_until_they_fit() {
+        let mut rate = UpdateRate::new();
+        assert_eq!(run(&mut rate, 60, Duration::from_millis(5)), (vec![], 60));
+
+        // 25 ms updates do not fit a frame, but fit two
+        assert_eq!(run(&mut rate, 30, Duration::from_millis(25)).0, vec![1]);
+        assert_eq!(run(&mut rate, 120, Duration::from_millis(25)), (vec![], 60));
+
+        // Never below every fourth frame
+        let (reported, _) = run(&mut rate, 1000, Duration::from_millis(200));
+        assert_eq!(reported, vec![2]);
+
+        // 14 ms would fit a frame, but not with a margin
+        assert_eq!(run(&mut rate, 1000, Duration::from_millis(14)).0, vec![1]);
+        assert_eq!(run(&mut rate, 1000, Duration::from_millis(5)).0, vec![0]);
+        assert_eq!(run(&mut rate, 30, Duration::from_millis(5)), (vec![], 30));
+    }
+}
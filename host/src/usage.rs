@@ -0,0 +1,111 @@
+//! Resource Usage
+//!
+//! Tracks per-app frame rate, time spent inside the guest, and guest linear
+//! memory size, so users can spot which package is using the most resources.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How often usage figures are recomputed
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Resource usage figures for one sampling interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsageSnapshot {
+    /// Frames presented per second
+    pub fps: f64,
+    /// Share of wall-clock time spent executing guest code (percent of one core)
+    pub cpu_percent: f64,
+    /// Guest linear memory size in bytes
+    pub memory_bytes: usize,
+    /// Bytes in use by the guest's allocator, for guests reporting it
+    pub heap_bytes: Option<u64>,
+}
+
+impl fmt::Display for UsageSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} FPS | CPU {:.1}% | {:.1} MiB",
+            self.fps,
+            self.cpu_percent,
+            self.memory_bytes as f64 / (1024.0 * 1024.0)
+        )?;
+        if let Some(heap_bytes) = self.heap_bytes {
+            write!(
+                f,
+                " (heap {:.1} MiB)",
+                heap_bytes as f64 / (1024.0 * 1024.0)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Accumulates per-app resource usage between samples
+pub struct UsageTracker {
+    interval_start: Instant,
+    frames: u32,
+    guest_time: Duration,
+    latest: Option<UsageSnapshot>,
+}
+
+impl UsageTracker {
+    /// Create a tracker whose first interval starts now
+    pub fn new() -> Self {
+        Self {
+            interval_start: Instant::now(),
+            frames: 0,
+            guest_time: Duration::ZERO,
+            latest: None,
+        }
+    }
+
+    /// Record time spent in guest code (event callbacks and `update`)
+    pub fn record_guest_time(&mut self, elapsed: Duration) {
+        self.guest_time += elapsed;
+    }
+
+    /// Record a presented frame
+    pub fn record_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Close the current interval if it has elapsed, returning the new snapshot
+    pub fn sample(
+        &mut self,
+        memory_bytes: usize,
+        heap_bytes: Option<u64>,
+    ) -> Option<UsageSnapshot> {
+        let elapsed = self.interval_start.elapsed();
+        if elapsed < SAMPLE_INTERVAL {
+            return None;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        let snapshot = UsageSnapshot {
+            fps: self.frames as f64 / seconds,
+            cpu_percent: self.guest_time.as_secs_f64() / seconds * 100.0,
+            memory_bytes,
+            heap_bytes,
+        };
+
+        self.interval_start = Instant::now();
+        self.frames = 0;
+        self.guest_time = Duration::ZERO;
+        self.latest = Some(snapshot);
+
+        Some(snapshot)
+    }
+
+    /// Most recent snapshot, if a full interval has elapsed
+    pub fn latest(&self) -> Option<UsageSnapshot> {
+        self.latest
+    }
+}
+
+impl Default for UsageTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
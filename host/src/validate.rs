@@ -0,0 +1,510 @@
+//! `wapps validate` Command
+//!
+//! Statically checks a package against this host without running it: every
+//! import must be provided with a matching type, the required exports must be
+//! present, and optional exports with an unexpected signature (which the host
+//! would silently ignore) are flagged. The capabilities the guest imports are
+//! listed, with warnings for those this host or permission set will refuse
+//! and for mismatches with the capabilities the manifest declares. Metadata
+//! longer than the header spec allows is an error. Exits with an error if the
+//! package would fail to start, or with `--strict` on any warning, and
+//! `--json` prints the report for CI to read.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::PathBuf;
+use wasmtime::{Engine, ExternType, FuncType, Module, ValType};
+
+use crate::capabilities::{self, Capability};
+use crate::loader::{self, WappMetadata};
+use crate::permissions;
+use crate::runtime;
+use crate::wasm_features::DisabledFeatures;
+use wapps_host::wasi_threads;
+
+/// Exports the host calls if present, with the signature it expects
+pub const OPTIONAL_EXPORTS: &[(&str, &str)] = &[
+    ("init", "(i32, i32) -> ()"),
+    ("shutdown", "() -> ()"),
+    ("on_resize", "(i32, i32) -> ()"),
+    ("on_scale_changed", "(f32) -> ()"),
+    ("on_pointer_move", "(i32, i32, i32, i32) -> ()"),
+    ("on_pointer_down", "(i32, i32, i32) -> ()"),
+    ("on_pointer_up", "(i32, i32, i32) -> ()"),
+    ("on_touch_down", "(i64, f32, f32, f32) -> ()"),
+    ("on_touch_move", "(i64, f32, f32, f32) -> ()"),
+    ("on_touch_up", "(i64, f32, f32, f32) -> ()"),
+    ("on_key_down", "(i32, i32, i32) -> ()"),
+    ("on_key_up", "(i32) -> ()"),
+    ("on_scroll", "(i32, i32) -> ()"),
+    ("on_scroll_precise", "(f32, f32) -> ()"),
+    ("on_text_input", "(i32, i32) -> ()"),
+    ("on_text_editing", "(i32, i32, i32) -> ()"),
+    ("wapps_alloc", "(i32) -> (i32)"),
+    ("wapps_free", "(i32, i32) -> ()"),
+    ("on_describe", "(i32, i32) -> (i32)"),
+    ("on_present", "(i64, i64) -> ()"),
+    ("on_idle", "() -> ()"),
+    ("on_focus", "() -> ()"),
+    ("on_blur", "() -> ()"),
+    ("on_visibility", "(i32) -> ()"),
+    ("on_close_request", "() -> (i32)"),
+    ("on_player", "(i32) -> ()"),
+    ("on_performance_warning", "(i32) -> ()"),
+    ("on_perf_hint", "(i32) -> ()"),
+    ("on_memory_pressure", "(i32, i32) -> ()"),
+    ("on_ws_message", "(i32, i32, i32) -> ()"),
+    ("on_file_opened", "(i32, i32) -> ()"),
+    ("on_file_saved", "(i32) -> ()"),
+    ("on_file_dropped", "(i32, i32, i32, i32) -> ()"),
+    ("on_bus_message", "(i32, i32, i32, i32) -> ()"),
+    ("on_timer", "(i32) -> ()"),
+    ("on_reload", "() -> ()"),
+    ("get_framebuffer", "() -> (i32)"),
+];
+
+/// Older signatures of optional exports the host still calls
+const LEGACY_EXPORTS: &[(&str, &str)] = &[
+    ("on_pointer_move", "(i32, i32) -> ()"),
+    ("on_key_down", "(i32) -> ()"),
+];
+
+/// Arguments of `wapps validate`
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Package to check
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// Validate against a host started with --allow-launch
+    #[arg(long)]
+    allow_launch: bool,
+
+    /// Validate against a host started with --allow-unknown-imports
+    #[arg(long)]
+    allow_unknown_imports: bool,
+
+    /// Fail on warnings too
+    #[arg(long)]
+    strict: bool,
+
+    /// Print the report as JSON instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+/// Longest package name and description, in bytes, from the header spec
+const MAX_NAME_LEN: usize = 255;
+const MAX_DESCRIPTION_LEN: usize = 1023;
+
+/// Findings about a package, printed as text or JSON
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    file: PathBuf,
+    name: Option<String>,
+    host_version: &'static str,
+    ok: bool,
+    capabilities: BTreeSet<&'static str>,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+    /// Optional exports the package leaves out, which is fine
+    notes: Vec<String>,
+}
+
+/// Run `wapps validate`
+pub fn run(args: &ValidateArgs) -> Result<()> {
+    let mut report = Report {
+        file: args.file.clone(),
+        host_version: env!("CARGO_PKG_VERSION"),
+        ..Default::default()
+    };
+    if let Err(e) = validate(args, &mut report) {
+        report.errors.push(format!("{:#}", e));
+    }
+    if args.strict {
+        let warnings = std::mem::take(&mut report.warnings);
+        report.errors.extend(warnings);
+    }
+    report.ok = report.errors.is_empty();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", text(&report));
+    }
+    if !report.ok {
+        bail!(
+            "{} failed validation with {} error(s)",
+            args.file.display(),
+            report.errors.len()
+        );
+    }
+    Ok(())
+}
+
+/// Check the package against this host, adding what is found to `report`;
+/// packages that cannot be loaded or compiled fail with an error
+fn validate(args: &ValidateArgs, report: &mut Report) -> Result<()> {
+    let (wasm_bytes, metadata) = loader::load_wapp(&args.file)
+        .with_context(|| format!("Failed to load WAPP file: {:?}", args.file))?;
+    report.name = Some(metadata.name.clone());
+
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm_bytes).context("Failed to compile WASM module")?;
+    let linker = runtime::create_linker(&engine)?;
+
+    let errors = &mut report.errors;
+    let warnings = &mut report.warnings;
+    errors.extend(check_metadata(&metadata));
+
+    // Imports: those this host links are the only ones allowed, besides the
+    // shared memory it creates for apps using threads
+    let threads = wasi_threads::uses_shared_memory(&module);
+    let missing: Vec<_> = runtime::missing_imports(&engine, &linker, &module)
+        .into_iter()
+        .filter(|import| !matches!(import.ty(), ExternType::Memory(ty) if ty.is_shared()))
+        .collect();
+    for import in &missing {
+        let name = format!("{}::{}", import.module(), import.name());
+        if args.allow_unknown_imports && matches!(import.ty(), ExternType::Func(_)) {
+            warnings.push(format!("import {} will be stubbed and return zeros", name));
+        } else {
+            errors.push(format!("import {} is not provided by this host", name));
+        }
+    }
+    if threads {
+        warnings.push("the app uses threads and only runs with --enable-threads".into());
+    } else if missing.is_empty() {
+        if let Err(e) = linker.instantiate_pre(&module) {
+            errors.push(format!("imports do not match this host: {:#}", e));
+        }
+    }
+    if let Some(declared) = &metadata.capabilities {
+        warnings.extend(check_capabilities(&wasm_bytes, declared));
+    }
+    let disabled = DisabledFeatures::new(metadata.disabled_features.iter().copied());
+    for proposal in disabled.used_by(&wasm_bytes) {
+        errors.push(format!(
+            "the manifest disables the {} proposal, which the module uses",
+            proposal.name()
+        ));
+    }
+
+    let capabilities = &mut report.capabilities;
+    for import in module.imports() {
+        if let Some(capability) = capability(import.module(), import.name()) {
+            capabilities.insert(capability);
+        }
+    }
+    if capabilities.contains(LAUNCH) && !args.allow_launch {
+        warnings.push(
+            "wapps::launch will be denied unless the user allows it or the host runs with \
+             --allow-launch"
+                .into(),
+        );
+    }
+    if capabilities.contains(FILESYSTEM) {
+        warnings.push("filesystem calls will fail: no directories are available to guests".into());
+    }
+    if capabilities.contains(NETWORK) {
+        warnings.push("socket calls will fail: guests have no network access".into());
+    }
+    if capabilities.contains(HTTP) && metadata.allowed_hosts.is_empty() {
+        warnings
+            .push("wapps::http_fetch will be refused: the manifest lists no allowed_hosts".into());
+    }
+    if capabilities.contains(WEBSOCKETS) && metadata.allowed_hosts.is_empty() {
+        warnings
+            .push("wapps::ws_connect will be refused: the manifest lists no allowed_hosts".into());
+    }
+
+    // Exports
+    match export_signature(&module, "update") {
+        None => errors.push("required export update(f64) is missing".into()),
+        Some(signature) if signature != "(f64) -> ()" => errors.push(format!(
+            "export update has signature {}, expected (f64) -> ()",
+            signature
+        )),
+        Some(_) => {}
+    }
+    if !matches!(module.get_export("memory"), Some(ExternType::Memory(_))) {
+        errors.push("required export memory is missing".into());
+    }
+    for (name, expected) in OPTIONAL_EXPORTS {
+        match export_signature(&module, name) {
+            None => report
+                .notes
+                .push(format!("optional export {} is missing", name)),
+            Some(signature)
+                if signature != *expected
+                    && !LEGACY_EXPORTS.contains(&(*name, signature.as_str())) =>
+            {
+                warnings.push(format!(
+                    "export {} has signature {}, expected {}; the host will not call it",
+                    name, signature, expected
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// The report for the terminal
+fn text(report: &Report) -> String {
+    let mut out = String::new();
+    if let Some(name) = &report.name {
+        let _ = writeln!(
+            out,
+            "Validating {:?} against wapps host {}",
+            name, report.host_version
+        );
+    }
+    if !report.capabilities.is_empty() {
+        out.push_str("Capabilities:\n");
+    }
+    for capability in &report.capabilities {
+        let _ = writeln!(out, "  {}", capability);
+    }
+    for note in &report.notes {
+        let _ = writeln!(out, "note: {}", note);
+    }
+    for warning in &report.warnings {
+        let _ = writeln!(out, "warning: {}", warning);
+    }
+    for error in &report.errors {
+        let _ = writeln!(out, "error: {}", error);
+    }
+    if report.ok {
+        let _ = writeln!(out, "OK ({} warning(s))", report.warnings.len());
+    }
+    out
+}
+
+/// Metadata fields longer than the header spec allows
+fn check_metadata(metadata: &WappMetadata) -> Vec<String> {
+    let mut fields = vec![
+        ("name", &metadata.name, MAX_NAME_LEN),
+        ("description", &metadata.description, MAX_DESCRIPTION_LEN),
+    ];
+    let mut locales: Vec<_> = metadata.locales.iter().collect();
+    locales.sort_by_key(|(locale, _)| locale.as_str());
+    for (_, strings) in locales {
+        fields.extend(strings.name.iter().map(|name| ("name", name, MAX_NAME_LEN)));
+        fields.extend(
+            strings
+                .description
+                .iter()
+                .map(|description| ("description", description, MAX_DESCRIPTION_LEN)),
+        );
+    }
+    fields
+        .into_iter()
+        .filter(|(_, value, max)| value.len() > *max)
+        .map(|(field, value, max)| {
+            format!(
+                "metadata {} is {} bytes, over the {} allowed",
+                field,
+                value.len(),
+                max
+            )
+        })
+        .collect()
+}
+
+/// Mismatches between the capabilities a manifest declares and those the
+/// module imports
+fn check_capabilities(wasm: &[u8], declared: &BTreeSet<Capability>) -> Vec<String> {
+    let mut findings: Vec<String> = capabilities::undeclared(wasm, declared)
+        .into_iter()
+        .map(|(import, capability)| {
+            format!(
+                "import {} needs the {} capability, which the manifest does not declare; \
+                 calls will be denied",
+                import,
+                capability.name()
+            )
+        })
+        .collect();
+    let imported: BTreeSet<Capability> = permissions::imports(wasm)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|(module, name)| Capability::from_import(module, name))
+        .collect();
+    for capability in declared {
+        // Neither gates an import
+        if matches!(capability, Capability::Filesystem | Capability::Clipboard) {
+            continue;
+        }
+        if !imported.contains(capability) {
+            findings.push(format!(
+                "the manifest declares the {} capability, but no import uses it",
+                capability.name()
+            ));
+        }
+    }
+    findings
+}
+
+const LAUNCH: &str = "launch other packages";
+const FILESYSTEM: &str = "filesystem";
+const NETWORK: &str = "network";
+const HTTP: &str = "HTTP requests";
+const WEBSOCKETS: &str = "WebSockets";
+
+/// Capability a host import gives the guest, if worth listing
+fn capability(module: &str, name: &str) -> Option<&'static str> {
+    let capability = match (runtime::unversioned(module), name) {
+        ("wapps", "update_frame" | "update_frame_ex" | "update_layer" | "update_overlay") => {
+            "display"
+        }
+        ("wapps", "set_clear_color" | "set_scaling_mode" | "set_palette") => "display",
+        ("wapps", "set_display_adjustment") => "display",
+        ("wapps", "create_image" | "destroy_image" | "draw_image" | "clear_canvas") => "images",
+        ("wapps", "draw_text" | "measure_text") => "text",
+        ("wapps", "clear" | "fill_rect" | "blit") => "canvas drawing",
+        ("wapps", "get_window_size" | "get_display_refresh_rate" | "get_frame_count") => {
+            "display info"
+        }
+        ("wapps", "set_aspect_ratio" | "set_min_size") => "display constraints",
+        ("wapps", "set_fullscreen") => "fullscreen",
+        ("wapps", "set_cursor_visible" | "set_cursor" | "set_relative_mouse") => "mouse cursor",
+        ("wapps", "set_window_title" | "set_window_size") => "window title and size",
+        ("wapps", "launch") => LAUNCH,
+        ("wapps", "bus_send" | "bus_subscribe") => "message bus",
+        ("wapps", "push_audio" | "get_audio_queued_frames") => "audio",
+        ("wapps", "get_string") => "package strings",
+        ("wapps", "config_get") => "startup settings",
+        ("wapps", "asset_size" | "asset_read") => "package assets",
+        ("wapps", "event_time") => "event timestamps",
+        ("wapps", "request_frame_rate") => "frame rate",
+        ("wapps", "set_timer" | "cancel_timer") => "timers",
+        ("wapps", "now_micros") => "precise time",
+        ("wapps", "request_redraw" | "request_update_in") => "power saving",
+        ("wapps", "query_key_state") => "keyboard state",
+        ("wapps", "storage_get" | "storage_set") => "persistent storage",
+        ("wapps", "score_submit" | "score_list") => "high scores",
+        ("wapps", "app_name" | "app_version") => "package metadata",
+        ("wapps", "get_locale" | "get_prefers_dark") => "locale and theme",
+        ("wapps", "report_allocations") => "allocation statistics",
+        ("wapps", "log") => "console output",
+        ("wapps", "http_fetch" | "http_poll" | "http_read" | "http_close") => HTTP,
+        ("wapps", "ws_connect" | "ws_send" | "ws_status" | "ws_close") => WEBSOCKETS,
+        ("wapps", "request_snapshot" | "request_restore") => "save states",
+        ("wapps", "request_open_file" | "request_save_file") => "file dialogs",
+        ("wapps", "gamepad_rumble" | "gamepad_set_led") => "gamepad feedback",
+        ("wapps", "audio_capture_start" | "audio_capture_read" | "audio_capture_stop") => {
+            "microphone"
+        }
+        ("wasi", "thread-spawn") => "threads",
+        ("wasi_snapshot_preview1", "clock_time_get" | "clock_res_get") => "clock",
+        ("wasi_snapshot_preview1", "random_get") => "random",
+        ("wasi_snapshot_preview1", "args_get" | "args_sizes_get") => "launch arguments",
+        ("wasi_snapshot_preview1", "fd_write") => "console output",
+        ("wasi_snapshot_preview1", name) if name.starts_with("path_") => FILESYSTEM,
+        ("wasi_snapshot_preview1", name) if name.starts_with("sock_") => NETWORK,
+        _ => return None,
+    };
+    Some(capability)
+}
+
+/// Signature of a function export, e.g. `(i32, i32) -> ()`
+fn export_signature(module: &Module, name: &str) -> Option<String> {
+    match module.get_export(name)? {
+        ExternType::Func(ty) => Some(format_signature(&ty)),
+        other => Some(format!("{:?}", other)),
+    }
+}
+
+fn format_signature(ty: &FuncType) -> String {
+    format!(
+        "({}) -> ({})",
+        type_list(ty.params()),
+        type_list(ty.results())
+    )
+}
+
+fn type_list(types: impl Iterator<Item = ValType>) -> String {
+    types
+        .map(|ty| type_name(&ty))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn type_name(ty: &ValType) -> &'static str {
+    match ty {
+        ValType::I32 => "i32",
+        ValType::I64 => "i64",
+        ValType::F32 => "f32",
+        ValType::F64 => "f64",
+        ValType::V128 => "v128",
+        ValType::Ref(_) => "ref",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        assert_eq!(capability("wapps", "launch"), Some(LAUNCH));
+        assert_eq!(
+            capability("wasi_snapshot_preview1", "path_open"),
+            Some(FILESYSTEM)
+        );
+        assert_eq!(capability("wasi_snapshot_preview1", "proc_exit"), None);
+    }
+
+    #[test]
+    fn test_export_signatures() {
+        let engine = Engine::default();
+        let module = Module::new(
+            &engine,
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "update") (param f64))
+                (func (export "on_describe") (param i32 i32) (result i32) i32.const 0))"#,
+        )
+        .unwrap();
+        assert_eq!(
+            export_signature(&module, "update").as_deref(),
+            Some("(f64) -> ()")
+        );
+        assert_eq!(
+            export_signature(&module, "on_describe").as_deref(),
+            Some("(i32, i32) -> (i32)")
+        );
+        assert_eq!(export_signature(&module, "on_resize"), None);
+    }
+
+    #[test]
+    fn test_metadata_and_manifest_checks() {
+        let mut metadata = WappMetadata {
+            name: "Life".to_string(),
+            ..Default::default()
+        };
+        assert!(check_metadata(&metadata).is_empty());
+        metadata.description = "a".repeat(MAX_DESCRIPTION_LEN + 1);
+        assert_eq!(
+            check_metadata(&metadata),
+            ["metadata description is 1024 bytes, over the 1023 allowed"]
+        );
+
+        // Imports wapps::push_audio while declaring only the gamepad
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        let mut section = vec![1];
+        section.extend_from_slice(b"\x05wapps\x0apush_audio\x00\x00");
+        wasm.extend_from_slice(&[2, section.len() as u8]);
+        wasm.extend_from_slice(&section);
+        let findings = check_capabilities(&wasm, &BTreeSet::from([Capability::Gamepad]));
+        assert_eq!(findings.len(), 2);
+        assert!(findings[0].starts_with("import wapps::push_audio needs the audio capability"));
+        assert!(findings[1].contains("declares the gamepad capability"));
+        let audio = BTreeSet::from([Capability::Audio, Capability::Filesystem]);
+        assert!(check_capabilities(&wasm, &audio).is_empty());
+    }
+}
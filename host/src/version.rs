@@ -0,0 +1,151 @@
+//! Semantic Versions
+//!
+//! Package versions and the `min_host_version` a package requires are
+//! semantic versions: `MAJOR.MINOR.PATCH`, optionally followed by a
+//! `-pre.release` tag, which sorts before the plain version, and `+build`
+//! metadata, which is ignored. Pre-release identifiers compare numerically
+//! when both are numbers and as text otherwise.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Version of this host, compared to the `min_host_version` of packages
+pub const HOST_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A parsed semantic version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Dot-separated pre-release identifiers, empty for a release
+    pub pre: Vec<String>,
+}
+
+impl Version {
+    /// Parse `text`, returning a description of the problem if it is not a
+    /// semantic version
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.split_once('+').map_or(text, |(version, _)| version);
+        let (core, pre) = match text.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (text, None),
+        };
+        let numbers: Vec<&str> = core.split('.').collect();
+        let [major, minor, patch] = numbers[..] else {
+            return Err(format!("{:?} is not MAJOR.MINOR.PATCH", core));
+        };
+        let pre = match pre {
+            Some(pre) => pre.split('.').map(str::to_string).collect(),
+            None => Vec::new(),
+        };
+        if pre.iter().any(|identifier| {
+            identifier.is_empty()
+                || !identifier
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }) {
+            return Err(format!("invalid pre-release tag in {:?}", text));
+        }
+        Ok(Self {
+            major: number(major)?,
+            minor: number(minor)?,
+            patch: number(patch)?,
+            pre,
+        })
+    }
+}
+
+fn number(text: &str) -> Result<u64, String> {
+    if text.len() > 1 && text.starts_with('0') {
+        return Err(format!("{:?} has a leading zero", text));
+    }
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("{:?} is not a number", text));
+    }
+    text.parse().map_err(|_| format!("{:?} is too large", text))
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => {
+                    for (a, b) in self.pre.iter().zip(&other.pre) {
+                        let order = match (a.parse::<u64>(), b.parse::<u64>()) {
+                            (Ok(a), Ok(b)) => a.cmp(&b),
+                            (Ok(_), Err(_)) => Ordering::Less,
+                            (Err(_), Ok(_)) => Ordering::Greater,
+                            (Err(_), Err(_)) => a.cmp(b),
+                        };
+                        if order != Ordering::Equal {
+                            return order;
+                        }
+                    }
+                    self.pre.len().cmp(&other.pre.len())
+                }
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that this host satisfies a package's `min_host_version`
+///
+/// Returns a description of the problem if the requirement is malformed or
+/// newer than the host.
+pub fn check_host_version(min_host_version: &str) -> Result<(), String> {
+    let required =
+        Version::parse(min_host_version).map_err(|e| format!("invalid min_host_version: {}", e))?;
+    let host = Version::parse(HOST_VERSION).expect("the host version is a semantic version");
+    if host < required {
+        return Err(format!(
+            "requires wapps host {} or newer, but this is {}",
+            required, host
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_parse_and_compare() {
+        let version = |text| Version::parse(text).unwrap();
+        assert_eq!(version("1.2.3+build.5").to_string(), "1.2.3");
+        assert!(version("1.10.0") > version("1.9.9"));
+        assert!(version("1.0.0-alpha") < version("1.0.0"));
+        assert!(version("1.0.0-alpha.2") < version("1.0.0-alpha.10"));
+        assert!(version("1.0.0-alpha") < version("1.0.0-alpha.1"));
+        assert!(version("1.0.0-1") < version("1.0.0-beta"));
+
+        assert!(Version::parse("1.2").is_err());
+        assert!(Version::parse("1.02.0").is_err());
+        assert!(Version::parse("1.2.x").is_err());
+        assert!(Version::parse("1.2.3-").is_err());
+
+        assert!(check_host_version("0.0.1").is_ok());
+        assert!(check_host_version("999.0.0").is_err());
+        assert!(check_host_version("latest").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Video Recording
+//!
+//! F11 starts and stops recording the focused app to an animated GIF in
+//! `videos/` under the user data directory, and `--record-video FILE` records
+//! from launch: to a GIF, or through ffmpeg (which must be on the PATH) for
+//! other extensions such as .mp4. Frames are captured as the guest presents
+//! them, at its resolution and before any host filter, and timed by the dt
+//! passed to its updates, so recordings play at the app's pace even when the
+//! host fell behind. A change of frame size ends the recording.
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use crate::gif::GifEncoder;
+use crate::screenshot;
+use crate::storage;
+
+/// Frame rate of videos encoded by ffmpeg; frames are repeated to keep time
+const VIDEO_FPS: u32 = 60;
+
+/// Shortest GIF frame delay, in hundredths of a second; browsers slow down
+/// shorter ones, so briefer frames are dropped
+const MIN_GIF_DELAY: u64 = 2;
+
+/// Where recorded frames are encoded
+enum Encoder {
+    Gif(GifEncoder<BufWriter<File>>),
+    Ffmpeg(Child),
+}
+
+impl Encoder {
+    /// Encoder time units per second
+    fn rate(&self) -> f64 {
+        match self {
+            Encoder::Gif(_) => 100.0,
+            Encoder::Ffmpeg(_) => VIDEO_FPS as f64,
+        }
+    }
+}
+
+/// Records an app's frames to a video file
+pub struct VideoRecorder {
+    path: PathBuf,
+    /// Created for the first frame, which sets the video size
+    encoder: Option<Encoder>,
+    size: (u32, u32),
+    /// Guest time since the recording started, in seconds
+    time: f64,
+    /// Latest frame, written once it is known how long it was shown
+    pending: Option<Vec<u8>>,
+    /// Length of the video written so far, in encoder time units
+    written: u64,
+    /// Number of frames captured
+    frames: u64,
+}
+
+impl VideoRecorder {
+    /// Record to `path`, encoded according to its extension
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            encoder: None,
+            size: (0, 0),
+            time: 0.0,
+            pending: None,
+            written: 0,
+            frames: 0,
+        }
+    }
+
+    /// New GIF file for a recording of the app named `name`, if there is a
+    /// user data directory
+    pub fn default_path(name: &str) -> Option<PathBuf> {
+        let file_name = format!(
+            "{}-{}.gif",
+            storage::file_stem(name),
+            screenshot::current_timestamp()
+        );
+        Some(storage::data_dir()?.join("videos").join(file_name))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Advance the recording by the `dt` of a guest update
+    pub fn advance(&mut self, dt: f64) {
+        self.time += dt;
+    }
+
+    /// Capture a frame of RGBA `pixels` presented by the guest
+    pub fn push_frame(&mut self, width: u32, height: u32, pixels: &[u8]) -> Result<()> {
+        if self.encoder.is_none() {
+            self.encoder = Some(self.create_encoder(width, height)?);
+            self.size = (width, height);
+        } else if self.size != (width, height) {
+            bail!(
+                "the frame size changed from {}x{} to {}x{}",
+                self.size.0,
+                self.size.1,
+                width,
+                height
+            );
+        }
+        self.write_pending(false)?;
+        self.pending = Some(pixels.to_vec());
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Write the last frame and close the file, returning the number of
+    /// frames recorded
+    pub fn finish(mut self) -> Result<u64> {
+        self.close()?;
+        Ok(self.frames)
+    }
+
+    fn create_encoder(&self, width: u32, height: u32) -> Result<Encoder> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create directory: {}", dir.display()))?;
+        }
+        let is_gif = self
+            .path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+        if is_gif {
+            let file = File::create(&self.path)
+                .with_context(|| format!("Could not create {}", self.path.display()))?;
+            return Ok(Encoder::Gif(GifEncoder::new(
+                BufWriter::new(file),
+                width,
+                height,
+            )?));
+        }
+        let child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+            .args(["-pixel_format", "rgba", "-video_size"])
+            .arg(format!("{}x{}", width, height))
+            .arg("-framerate")
+            .arg(VIDEO_FPS.to_string())
+            .args(["-i", "-", "-pix_fmt", "yuv420p"])
+            // yuv420p needs even dimensions
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("Could not start ffmpeg, needed to record videos other than .gif")?;
+        Ok(Encoder::Ffmpeg(child))
+    }
+
+    /// Write the pending frame, shown until now; the last frame is written
+    /// for at least the shortest delay
+    fn write_pending(&mut self, last: bool) -> Result<()> {
+        let (Some(encoder), Some(pixels)) = (&mut self.encoder, self.pending.take()) else {
+            return Ok(());
+        };
+        let end = (self.time * encoder.rate()).round() as u64;
+        let mut duration = end.saturating_sub(self.written);
+        match encoder {
+            Encoder::Gif(gif) => {
+                if last {
+                    duration = duration.max(MIN_GIF_DELAY);
+                } else if duration < MIN_GIF_DELAY {
+                    return Ok(());
+                }
+                gif.write_frame(&pixels, duration.min(u16::MAX as u64) as u16)?;
+            }
+            Encoder::Ffmpeg(child) => {
+                if last {
+                    duration = duration.max(1);
+                }
+                let stdin = child.stdin.as_mut().context("ffmpeg has no input")?;
+                for _ in 0..duration {
+                    stdin.write_all(&pixels).context("ffmpeg stopped")?;
+                }
+            }
+        }
+        self.written += duration;
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.write_pending(true)?;
+        match self.encoder.take() {
+            Some(Encoder::Gif(gif)) => {
+                gif.finish()?;
+            }
+            Some(Encoder::Ffmpeg(mut child)) => {
+                // Closing its input lets ffmpeg finish the file
+                drop(child.stdin.take());
+                let status = child.wait().context("ffmpeg failed")?;
+                if !status.success() {
+                    bail!("ffmpeg failed with {}", status);
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VideoRecorder {
+    fn drop(&mut self) {
+        if self.encoder.is_none() {
+            return;
+        }
+        match self.close() {
+            Ok(()) => info!("Saved {} frames to {}", self.frames, self.path.display()),
+            Err(e) => warn!("Failed to save video {}: {:#}", self.path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gif_frames_are_timed_by_dt() {
+        let path = std::env::temp_dir().join(format!("wapps-video-{}.gif", std::process::id()));
+        let mut video = VideoRecorder::new(path.clone());
+        video.push_frame(1, 1, &[1, 1, 1, 255]).unwrap();
+        // Replaced before it could be shown: dropped
+        video.advance(0.005);
+        video.push_frame(1, 1, &[2, 2, 2, 255]).unwrap();
+        video.advance(0.5);
+        video.push_frame(1, 1, &[3, 3, 3, 255]).unwrap();
+        assert!(video.push_frame(2, 1, &[0; 8]).is_err());
+        video.advance(0.1);
+        assert_eq!(video.finish().unwrap(), 3);
+
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let delays: Vec<u16> = data
+            .windows(6)
+            .filter(|w| w[..4] == [0x21, 0xf9, 0x04, 0x00])
+            .map(|w| u16::from_le_bytes([w[4], w[5]]))
+            .collect();
+        assert_eq!(delays, [51, 10]);
+    }
+}
@@ -0,0 +1,302 @@
+//! Virtual Controls
+//!
+//! With `--virtual-controls`, keyboard-driven apps become playable on touch
+//! screens: a D-pad in the bottom left corner of the window and A and B
+//! buttons in the bottom right are drawn over the app's frames, and touching
+//! or clicking them presses the arrow keys, Space and Return. They stand for
+//! the controller's D-pad and A and B buttons, so the `[gamepad]` entries of
+//! an input map choose other keys for them. Touches and clicks starting on a
+//! control are kept from the app; fingers sliding from one control to
+//! another press the new one instead. Released controls are drawn as
+//! outlines, to keep the frame under them visible.
+
+use sdl2::controller::Button;
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::collections::HashMap;
+
+use crate::events::GuestEvent;
+use crate::font;
+use crate::inspector::OverlayRect;
+
+/// Pointer id of the mouse, next to touch ids
+const MOUSE: i64 = -1;
+/// Margin between the controls and the window's edges
+const MARGIN: i32 = 16;
+/// Width of the outline of released controls
+const OUTLINE: i32 = 3;
+/// On-screen size of each font pixel of the button labels
+const LABEL_SCALE: i32 = 3;
+
+const CONTROL: Color = Color::RGB(224, 224, 224);
+const HELD_LABEL: Color = Color::RGB(32, 32, 32);
+
+/// The controls, in drawing order
+const BUTTONS: [Button; 6] = [
+    Button::DPadUp,
+    Button::DPadLeft,
+    Button::DPadRight,
+    Button::DPadDown,
+    Button::A,
+    Button::B,
+];
+
+/// The on-screen controls of an app's window, and those held
+#[derive(Debug, Default)]
+pub struct VirtualControls {
+    /// Control under each finger and the mouse pressing the controls, by id
+    pointers: HashMap<i64, Option<Button>>,
+}
+
+impl VirtualControls {
+    /// Follow a pointer or touch `event`, in window coordinates, returning
+    /// the buttons pressed and released, or `None` if the event is the app's
+    pub fn handle(
+        &mut self,
+        event: &GuestEvent,
+        window_size: (u32, u32),
+    ) -> Option<Vec<(Button, bool)>> {
+        let (width, height) = (window_size.0 as f32, window_size.1 as f32);
+        let (id, position, down) = match *event {
+            GuestEvent::PointerDown { x, y, button: 1 } => (MOUSE, Some((x, y)), true),
+            GuestEvent::PointerMove { x, y, .. } => (MOUSE, Some((x, y)), false),
+            GuestEvent::PointerUp { button: 1, .. } => (MOUSE, None, false),
+            GuestEvent::TouchDown { id, x, y, .. } => {
+                (id, Some(((x * width) as i32, (y * height) as i32)), true)
+            }
+            GuestEvent::TouchMove { id, x, y, .. } => {
+                (id, Some(((x * width) as i32, (y * height) as i32)), false)
+            }
+            GuestEvent::TouchUp { id, .. } => (id, None, false),
+            _ => return None,
+        };
+        let control = position.and_then(|(x, y)| control_at(x, y, window_size));
+        if !down && !self.pointers.contains_key(&id) {
+            return None;
+        }
+        if down && control.is_none() {
+            return None;
+        }
+
+        let before = self.held();
+        match position {
+            Some(_) => {
+                self.pointers.insert(id, control);
+            }
+            None => {
+                self.pointers.remove(&id);
+            }
+        }
+        let after = self.held();
+        let released = before
+            .iter()
+            .filter(|button| !after.contains(button))
+            .map(|&button| (button, false));
+        let pressed = after
+            .iter()
+            .filter(|button| !before.contains(button))
+            .map(|&button| (button, true));
+        Some(released.chain(pressed).collect())
+    }
+
+    /// Buttons held by any pointer
+    fn held(&self) -> Vec<Button> {
+        BUTTONS
+            .into_iter()
+            .filter(|&button| self.pointers.values().any(|&held| held == Some(button)))
+            .collect()
+    }
+
+    /// Rectangles drawing the controls over a window of `window_size`
+    pub fn overlay(&self, window_size: (u32, u32)) -> Vec<OverlayRect> {
+        let held = self.held();
+        let mut rects = Vec::new();
+        for (button, rect) in layout(window_size) {
+            let pressed = held.contains(&button);
+            if pressed {
+                rects.push((rect, CONTROL));
+            } else {
+                outline(&mut rects, rect);
+            }
+            let label = match button {
+                Button::A => "A",
+                Button::B => "B",
+                _ => continue,
+            };
+            let (x, y) = (
+                rect.center().x() - font::advance(LABEL_SCALE) / 2,
+                rect.center().y() - font::line_height(LABEL_SCALE) / 2,
+            );
+            let color = if pressed { HELD_LABEL } else { CONTROL };
+            font::draw_text(&mut rects, label, x, y, LABEL_SCALE, color);
+        }
+        rects
+    }
+}
+
+/// The key pressed by a virtual control `button` without an input map
+/// entry for it
+pub fn key_event(button: Button, pressed: bool) -> GuestEvent {
+    let scancode = match button {
+        Button::DPadUp => Scancode::Up,
+        Button::DPadDown => Scancode::Down,
+        Button::DPadLeft => Scancode::Left,
+        Button::DPadRight => Scancode::Right,
+        Button::B => Scancode::Return,
+        _ => Scancode::Space,
+    } as i32;
+    if pressed {
+        GuestEvent::KeyDown {
+            scancode,
+            modifiers: 0,
+            repeat: false,
+        }
+    } else {
+        GuestEvent::KeyUp { scancode }
+    }
+}
+
+/// Where each control is drawn in a window of `window_size`: the D-pad's
+/// arms around an empty center, and the B button left of and below A
+fn layout(window_size: (u32, u32)) -> [(Button, Rect); 6] {
+    let (width, height) = (window_size.0 as i32, window_size.1 as i32);
+    let cell = (width.min(height) / 8).max(24);
+    let bottom = height - MARGIN - cell * 3;
+    let square = |column: i32, row: i32, left: i32| {
+        Rect::new(
+            left + column * cell,
+            bottom + row * cell,
+            cell as u32,
+            cell as u32,
+        )
+    };
+    let right = width - MARGIN - cell * 3;
+    [
+        (Button::DPadUp, square(1, 0, MARGIN)),
+        (Button::DPadLeft, square(0, 1, MARGIN)),
+        (Button::DPadRight, square(2, 1, MARGIN)),
+        (Button::DPadDown, square(1, 2, MARGIN)),
+        (Button::A, square(2, 0, right)),
+        (Button::B, square(0, 1, right)),
+    ]
+}
+
+/// The control at (`x`, `y`) in a window of `window_size`, if any
+fn control_at(x: i32, y: i32, window_size: (u32, u32)) -> Option<Button> {
+    layout(window_size)
+        .into_iter()
+        .find(|(_, rect)| rect.contains_point((x, y)))
+        .map(|(button, _)| button)
+}
+
+/// Append the rectangles drawing the edges of `rect`
+fn outline(rects: &mut Vec<OverlayRect>, rect: Rect) {
+    let (width, height) = (rect.width(), rect.height());
+    let inner = height.saturating_sub(OUTLINE as u32 * 2);
+    let right = rect.right() - OUTLINE;
+    let bottom = rect.bottom() - OUTLINE;
+    let below_top = rect.y() + OUTLINE;
+    rects.extend([
+        (
+            Rect::new(rect.x(), rect.y(), width, OUTLINE as u32),
+            CONTROL,
+        ),
+        (Rect::new(rect.x(), bottom, width, OUTLINE as u32), CONTROL),
+        (
+            Rect::new(rect.x(), below_top, OUTLINE as u32, inner),
+            CONTROL,
+        ),
+        (Rect::new(right, below_top, OUTLINE as u32, inner), CONTROL),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: (u32, u32) = (800, 480);
+
+    fn touch(id: i64, (x, y): (i32, i32), phase: i32) -> GuestEvent {
+        let (x, y) = (x as f32 / WINDOW.0 as f32, y as f32 / WINDOW.1 as f32);
+        match phase {
+            0 => GuestEvent::TouchDown {
+                id,
+                x,
+                y,
+                pressure: 1.0,
+            },
+            1 => GuestEvent::TouchMove {
+                id,
+                x,
+                y,
+                pressure: 1.0,
+            },
+            _ => GuestEvent::TouchUp {
+                id,
+                x,
+                y,
+                pressure: 1.0,
+            },
+        }
+    }
+
+    fn center(button: Button) -> (i32, i32) {
+        let (_, rect) = layout(WINDOW)
+            .into_iter()
+            .find(|&(control, _)| control == button)
+            .unwrap();
+        (rect.center().x(), rect.center().y())
+    }
+
+    #[test]
+    fn test_touches_press_and_slide_between_controls() {
+        let mut controls = VirtualControls::default();
+        // Touches away from the controls are the app's
+        assert_eq!(controls.handle(&touch(1, (400, 100), 0), WINDOW), None);
+        assert_eq!(controls.handle(&touch(1, (400, 120), 1), WINDOW), None);
+
+        let up = center(Button::DPadUp);
+        assert_eq!(
+            controls.handle(&touch(2, up, 0), WINDOW),
+            Some(vec![(Button::DPadUp, true)])
+        );
+        // The mouse event SDL adds for the touch holds the same button
+        let click = GuestEvent::PointerDown {
+            x: up.0,
+            y: up.1,
+            button: 1,
+        };
+        assert_eq!(controls.handle(&click, WINDOW), Some(vec![]));
+        let release = GuestEvent::PointerUp {
+            x: up.0,
+            y: up.1,
+            button: 1,
+        };
+        assert_eq!(controls.handle(&release, WINDOW), Some(vec![]));
+
+        assert_eq!(
+            controls.handle(&touch(2, center(Button::DPadRight), 1), WINDOW),
+            Some(vec![(Button::DPadUp, false), (Button::DPadRight, true)])
+        );
+        // Sliding off every control releases it, still kept from the app
+        assert_eq!(
+            controls.handle(&touch(2, (400, 100), 1), WINDOW),
+            Some(vec![(Button::DPadRight, false)])
+        );
+        assert_eq!(
+            controls.handle(&touch(2, (400, 100), 2), WINDOW),
+            Some(vec![])
+        );
+        assert_eq!(controls.handle(&touch(2, (400, 100), 1), WINDOW), None);
+
+        assert_eq!(
+            key_event(Button::DPadLeft, true),
+            GuestEvent::KeyDown {
+                scancode: Scancode::Left as i32,
+                modifiers: 0,
+                repeat: false,
+            }
+        );
+    }
+}
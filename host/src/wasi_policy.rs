@@ -0,0 +1,293 @@
+//! WASI Clock and Random Policy
+//!
+//! Guests read the time and random numbers through WASI. A package can ask for
+//! a coarse or disabled clock (so it cannot time the machine precisely, or to
+//! make it deterministic) and for a fixed random seed in the `wasi` object of
+//! its manifest; the `--clock` and `--random-seed` options override it.
+//!
+//! Virtual clocks ignore real time and advance by the `dt` of each update
+//! instead. With `--deterministic`, which also fixes `dt` and seeds the random
+//! source, a guest then behaves the same on every run given the same input.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wasmtime_wasi::{HostMonotonicClock, HostWallClock, RngCore, WasiCtxBuilder};
+
+/// Constant `dt` of each update under `--deterministic`, in seconds
+pub const DETERMINISTIC_DT: f64 = 1.0 / 60.0;
+
+/// Granularity of coarse clock readings
+pub const COARSE_RESOLUTION: Duration = Duration::from_millis(100);
+
+/// How precisely guests may read the clocks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockPolicy {
+    /// Real clocks with nanosecond resolution
+    #[default]
+    Precise,
+    /// Real clocks rounded down to 100 ms
+    Coarse,
+    /// Clocks stuck at zero (the Unix epoch for the wall clock)
+    Disabled,
+    /// Clocks starting at zero and advanced by each update's dt
+    Virtual,
+}
+
+impl ClockPolicy {
+    pub fn resolution(self) -> Duration {
+        match self {
+            ClockPolicy::Precise | ClockPolicy::Virtual => Duration::from_nanos(1),
+            ClockPolicy::Coarse | ClockPolicy::Disabled => COARSE_RESOLUTION,
+        }
+    }
+
+    /// Apply the policy to a reading in nanoseconds
+    fn apply(self, nanos: u64) -> u64 {
+        match self {
+            ClockPolicy::Precise | ClockPolicy::Virtual => nanos,
+            ClockPolicy::Coarse => nanos - nanos % COARSE_RESOLUTION.as_nanos() as u64,
+            ClockPolicy::Disabled => 0,
+        }
+    }
+
+    /// Wall clock reading in nanoseconds since the Unix epoch
+    pub fn wall_now(self, time: &VirtualTime) -> u64 {
+        if self == ClockPolicy::Virtual {
+            return time.nanos();
+        }
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        self.apply(nanos)
+    }
+
+    /// Monotonic clock reading in nanoseconds since `origin`
+    pub fn monotonic_now(self, origin: Instant, time: &VirtualTime) -> u64 {
+        if self == ClockPolicy::Virtual {
+            return time.nanos();
+        }
+        self.apply(origin.elapsed().as_nanos() as u64)
+    }
+}
+
+/// Time shown by [`ClockPolicy::Virtual`] clocks, shared between a guest's
+/// WASI context and the runtime advancing it
+#[derive(Debug, Clone, Default)]
+pub struct VirtualTime(Arc<AtomicU64>);
+
+impl VirtualTime {
+    /// Move the time forward by `dt` seconds
+    pub fn advance(&self, dt: f64) {
+        let nanos = (dt.max(0.0) * 1e9).round() as u64;
+        self.0.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Nanoseconds since the guest started
+    pub fn nanos(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Clock and random settings requested in a package manifest
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WasiSettings {
+    #[serde(default)]
+    pub clock: Option<ClockPolicy>,
+    /// Seed for the guest's random numbers, making them reproducible
+    #[serde(default)]
+    pub random_seed: Option<u64>,
+}
+
+/// Effective clock and random policy for one guest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WasiPolicy {
+    pub clock: ClockPolicy,
+    pub random_seed: Option<u64>,
+}
+
+impl WasiPolicy {
+    /// Combine a package's settings with command-line overrides, which take precedence
+    pub fn resolve(
+        settings: &WasiSettings,
+        clock: Option<ClockPolicy>,
+        random_seed: Option<u64>,
+    ) -> Self {
+        Self {
+            clock: clock.or(settings.clock).unwrap_or_default(),
+            random_seed: random_seed.or(settings.random_seed),
+        }
+    }
+
+    /// Install this policy's clocks and random sources, keeping the WASI defaults
+    /// for anything left unrestricted; virtual clocks show `time`
+    pub fn configure_wasi(&self, builder: &mut WasiCtxBuilder, time: &VirtualTime) {
+        if self.clock != ClockPolicy::Precise {
+            builder
+                .wall_clock(PolicyWallClock {
+                    clock: self.clock,
+                    time: time.clone(),
+                })
+                .monotonic_clock(PolicyMonotonicClock {
+                    clock: self.clock,
+                    origin: Instant::now(),
+                    time: time.clone(),
+                });
+        }
+        if let Some(seed) = self.random_seed {
+            builder
+                .secure_random(SeededRng::new(seed))
+                .insecure_random(SeededRng::new(!seed));
+        }
+    }
+
+    /// Source for the guest's secure random bytes
+    pub fn secure_rng(&self) -> Box<dyn RngCore + Send> {
+        match self.random_seed {
+            Some(seed) => Box::new(SeededRng::new(seed)),
+            None => wasmtime_wasi::thread_rng(),
+        }
+    }
+
+    /// Source for the guest's insecure random bytes
+    pub fn insecure_rng(&self) -> Box<dyn RngCore + Send> {
+        match self.random_seed {
+            Some(seed) => Box::new(SeededRng::new(!seed)),
+            None => wasmtime_wasi::thread_rng(),
+        }
+    }
+}
+
+/// Deterministic random source for seeded guests (SplitMix64)
+///
+/// Not cryptographically secure: a seeded guest asked for reproducibility.
+struct SeededRng(u64);
+
+impl SeededRng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand_core::impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// WASI wall clock restricted by a [`ClockPolicy`]
+struct PolicyWallClock {
+    clock: ClockPolicy,
+    time: VirtualTime,
+}
+
+impl HostWallClock for PolicyWallClock {
+    fn resolution(&self) -> Duration {
+        self.clock.resolution()
+    }
+
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.clock.wall_now(&self.time))
+    }
+}
+
+/// WASI monotonic clock restricted by a [`ClockPolicy`]
+struct PolicyMonotonicClock {
+    clock: ClockPolicy,
+    origin: Instant,
+    time: VirtualTime,
+}
+
+impl HostMonotonicClock for PolicyMonotonicClock {
+    fn resolution(&self) -> u64 {
+        self.clock.resolution().as_nanos() as u64
+    }
+
+    fn now(&self) -> u64 {
+        self.clock.monotonic_now(self.origin, &self.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line_overrides_manifest() {
+        let settings = WasiSettings {
+            clock: Some(ClockPolicy::Coarse),
+            random_seed: Some(7),
+        };
+        assert_eq!(
+            WasiPolicy::resolve(&settings, None, None),
+            WasiPolicy {
+                clock: ClockPolicy::Coarse,
+                random_seed: Some(7),
+            }
+        );
+        assert_eq!(
+            WasiPolicy::resolve(&settings, Some(ClockPolicy::Precise), Some(1)),
+            WasiPolicy {
+                clock: ClockPolicy::Precise,
+                random_seed: Some(1),
+            }
+        );
+        assert_eq!(
+            WasiPolicy::resolve(&WasiSettings::default(), None, None),
+            WasiPolicy::default()
+        );
+    }
+
+    #[test]
+    fn test_clock_policies() {
+        let nanos = 1_234_567_890;
+        assert_eq!(ClockPolicy::Precise.apply(nanos), nanos);
+        assert_eq!(ClockPolicy::Coarse.apply(nanos), 1_200_000_000);
+        assert_eq!(ClockPolicy::Disabled.apply(nanos), 0);
+
+        let time = VirtualTime::default();
+        time.advance(0.5);
+        time.advance(0.25);
+        let origin = Instant::now() - Duration::from_secs(10);
+        assert_eq!(
+            ClockPolicy::Virtual.monotonic_now(origin, &time),
+            750_000_000
+        );
+        assert_eq!(ClockPolicy::Virtual.wall_now(&time), 750_000_000);
+    }
+
+    #[test]
+    fn test_seeded_random_is_reproducible() {
+        let policy = WasiPolicy {
+            random_seed: Some(42),
+            ..Default::default()
+        };
+        let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+        policy.secure_rng().fill_bytes(&mut a);
+        policy.secure_rng().fill_bytes(&mut b);
+        assert_eq!(a, b);
+        policy.insecure_rng().fill_bytes(&mut b);
+        assert_ne!(a, b);
+    }
+}
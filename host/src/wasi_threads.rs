@@ -0,0 +1,366 @@
+//! WASI Threads
+//!
+//! With `--enable-threads`, modules built for the wasi-threads ABI (such as
+//! Rust's `wasm32-wasip1-threads` target) can spread their work over several
+//! cores. They import a shared memory, which the host creates, and start
+//! threads with `wasi::thread-spawn(start_arg) -> tid`: each runs on a host
+//! thread in a store of its own, instantiating the module again over the same
+//! memory and calling its `wasi_thread_start(tid, start_arg)` export. Threads
+//! share the app's host interface behind its lock, so any of them may present
+//! frames or call other imports, while each has its own WASI file table.
+//! A trap in any thread crashes the app, as it would a single-threaded one.
+//! Without the flag, modules using shared memories are refused and
+//! `thread-spawn` fails.
+
+use anyhow::{bail, Context, Error, Result};
+use log::{debug, warn};
+use std::borrow::Cow;
+use std::cell::UnsafeCell;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use wasmtime::{
+    AsContext, AsContextMut, ExternType, Linker, Memory, MemoryType, Module, SharedMemory, Store,
+    StoreContext, UpdateDeadline,
+};
+
+use crate::capabilities::Capability;
+use crate::memory_limit::PAGE_SIZE;
+use crate::permissions::FirstUse;
+use crate::runtime::{self, StoreState};
+
+/// Export each thread starts in, as `wasi_thread_start(tid, start_arg)`
+pub const THREAD_START_EXPORT: &str = "wasi_thread_start";
+
+/// Most threads a guest may run at once, besides its main thread
+pub const MAX_THREADS: usize = 64;
+
+/// Highest thread id the wasi-threads ABI allows
+const MAX_TID: i32 = 0x1FFF_FFFF;
+
+/// Whether `module` imports or exports a shared memory
+pub fn uses_shared_memory(module: &Module) -> bool {
+    let imports = module.imports().map(|import| import.ty());
+    let exports = module.exports().map(|export| export.ty());
+    imports
+        .chain(exports)
+        .any(|ty| matches!(ty, ExternType::Memory(memory) if memory.is_shared()))
+}
+
+/// Create the shared memory `module` imports, if any, and define it in
+/// `linker`, capped at `memory_limit` bytes
+pub fn link_shared_memory(
+    store: &Store<StoreState>,
+    linker: &mut Linker<StoreState>,
+    module: &Module,
+    memory_limit: Option<usize>,
+) -> Result<Option<SharedMemory>> {
+    let Some((import, ty)) = module.imports().find_map(|import| match import.ty() {
+        ExternType::Memory(ty) if ty.is_shared() => Some((import, ty)),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+    if ty.is_64() {
+        bail!("64-bit shared memories are not supported");
+    }
+    // Shared memories always declare a maximum
+    let mut maximum = ty.maximum().unwrap_or(ty.minimum());
+    if let Some(limit) = memory_limit {
+        maximum = maximum.min((limit / PAGE_SIZE) as u64).max(ty.minimum());
+    }
+    let ty = MemoryType::shared(ty.minimum() as u32, maximum as u32);
+    let memory = SharedMemory::new(store.engine(), ty).context("Failed to create shared memory")?;
+    linker
+        .define(store, import.module(), import.name(), memory.clone())
+        .context("Failed to link shared memory")?;
+    debug!(
+        "Linked shared memory {}::{} of up to {} pages",
+        import.module(),
+        import.name(),
+        maximum
+    );
+    Ok(Some(memory))
+}
+
+/// A guest's linear memory, owned by its store or shared between threads
+#[derive(Clone)]
+pub enum GuestMemory {
+    Owned(Memory),
+    Shared(SharedMemory),
+}
+
+impl GuestMemory {
+    /// The `len` bytes at `offset`, or `None` if they are out of bounds
+    ///
+    /// Owned memories are borrowed. Other threads of the guest may write a
+    /// shared memory at any time, so its bytes are copied out instead, which
+    /// at worst tears what is read, as it would for the guest itself.
+    pub fn read<'a, T: 'a>(
+        &self,
+        store: impl Into<StoreContext<'a, T>>,
+        offset: usize,
+        len: usize,
+    ) -> Option<Cow<'a, [u8]>> {
+        let end = offset.checked_add(len)?;
+        match self {
+            Self::Owned(memory) => memory.data(store).get(offset..end).map(Cow::Borrowed),
+            Self::Shared(memory) => {
+                let cells = memory.data().get(offset..end)?;
+                let mut bytes = vec![0; len];
+                // SAFETY: the cells are valid for `len` bytes while `memory`
+                // is alive, and are only accessed through raw pointers
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        UnsafeCell::raw_get(cells.as_ptr()),
+                        bytes.as_mut_ptr(),
+                        len,
+                    );
+                }
+                Some(Cow::Owned(bytes))
+            }
+        }
+    }
+
+    /// Size of the memory in bytes
+    pub fn data_size(&self, store: impl AsContext) -> usize {
+        match self {
+            Self::Owned(memory) => memory.data_size(store),
+            Self::Shared(memory) => memory.data_size(),
+        }
+    }
+
+    /// Grow the memory by `pages`, returning its previous size in pages
+    pub fn grow(&self, store: impl AsContextMut, pages: u64) -> Result<u64> {
+        match self {
+            Self::Owned(memory) => memory.grow(store, pages),
+            Self::Shared(memory) => memory.grow(pages),
+        }
+    }
+
+    /// Copy `bytes` to `offset`, failing if they do not fit
+    pub fn write(&self, store: impl AsContextMut, offset: usize, bytes: &[u8]) -> Result<()> {
+        match self {
+            Self::Owned(memory) => memory
+                .write(store, offset, bytes)
+                .context("Write out of bounds of guest memory"),
+            Self::Shared(memory) => {
+                let Some(cells) = offset
+                    .checked_add(bytes.len())
+                    .and_then(|end| memory.data().get(offset..end))
+                else {
+                    bail!("Write out of bounds of guest memory");
+                };
+                // SAFETY: as for `read`; the cells allow writes through a
+                // shared reference
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        bytes.as_ptr(),
+                        UnsafeCell::raw_get(cells.as_ptr()),
+                        bytes.len(),
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<Memory> for GuestMemory {
+    fn from(memory: Memory) -> Self {
+        Self::Owned(memory)
+    }
+}
+
+/// Creates the store state of a new thread
+pub type StateFactory = Box<dyn Fn(Arc<WasiThreads>) -> StoreState + Send + Sync>;
+
+/// The threads a guest started, and what starting another takes
+pub struct WasiThreads {
+    module: Module,
+    /// Linker holding no store's functions, gated for each thread's store
+    linker: Linker<StoreState>,
+    new_state: StateFactory,
+    first_use: Vec<FirstUse>,
+    declared: Option<BTreeSet<Capability>>,
+    next_tid: AtomicI32,
+    running: AtomicUsize,
+    /// Set when the app stops, to end its threads
+    stopped: AtomicBool,
+    /// Why a thread trapped, until the runtime reports it
+    failure: Mutex<Option<Error>>,
+}
+
+impl WasiThreads {
+    /// Threads instantiating `module` with `linker`, denying the imports
+    /// whose capability is not `declared` and asking before the first use
+    /// of those needing a `first_use` permission, like the main thread
+    pub fn new(
+        module: Module,
+        linker: Linker<StoreState>,
+        new_state: StateFactory,
+        first_use: Vec<FirstUse>,
+        declared: Option<BTreeSet<Capability>>,
+    ) -> Self {
+        Self {
+            module,
+            linker,
+            new_state,
+            first_use,
+            declared,
+            next_tid: AtomicI32::new(1),
+            running: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            failure: Mutex::new(None),
+        }
+    }
+
+    /// Start a thread calling `wasi_thread_start(tid, start_arg)`, returning
+    /// its id, or a negative value if it could not be started
+    pub fn spawn(self: &Arc<Self>, start_arg: i32) -> i32 {
+        if self.stopped.load(Ordering::SeqCst) {
+            return -1;
+        }
+        if self.running.fetch_add(1, Ordering::SeqCst) >= MAX_THREADS {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            warn!(
+                "thread-spawn: the guest already runs {} threads",
+                MAX_THREADS
+            );
+            return -1;
+        }
+        let tid = self.next_tid.fetch_add(1, Ordering::Relaxed);
+        if tid > MAX_TID {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            warn!("thread-spawn: out of thread ids");
+            return -1;
+        }
+
+        let threads = self.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("wasi thread {}", tid))
+            .spawn(move || {
+                if let Err(e) = threads.run(tid, start_arg) {
+                    if !threads.stopped.load(Ordering::SeqCst) {
+                        threads.fail(e.context(format!("Guest thread {} crashed", tid)));
+                    }
+                }
+                threads.running.fetch_sub(1, Ordering::SeqCst);
+            });
+        match spawned {
+            Ok(_) => {
+                debug!("Started guest thread {}", tid);
+                tid
+            }
+            Err(e) => {
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                warn!("thread-spawn: failed to start a thread: {}", e);
+                -1
+            }
+        }
+    }
+
+    /// Instantiate the module in a new store and run thread `tid` to its end
+    fn run(self: &Arc<Self>, tid: i32, start_arg: i32) -> Result<()> {
+        let mut store = Store::new(self.module.engine(), (self.new_state)(self.clone()));
+        // Threads run outside the frame budget, until the app stops
+        let threads = self.clone();
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| {
+            if threads.stopped.load(Ordering::SeqCst) {
+                bail!("Guest thread stopped");
+            }
+            Ok(UpdateDeadline::Continue(1))
+        });
+
+        let mut linker = self.linker.clone();
+        runtime::gate_first_use_imports(&mut store, &mut linker, &self.module, &self.first_use)?;
+        if let Some(declared) = &self.declared {
+            runtime::deny_undeclared_imports(&mut linker, &self.module, declared)?;
+        }
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .context("Failed to instantiate guest thread")?;
+        let start = instance
+            .get_typed_func::<(i32, i32), ()>(&mut store, THREAD_START_EXPORT)
+            .with_context(|| format!("Guest must export '{}'", THREAD_START_EXPORT))?;
+        start.call(&mut store, (tid, start_arg))
+    }
+
+    fn fail(&self, error: Error) {
+        if let Ok(mut failure) = self.failure.lock() {
+            failure.get_or_insert(error);
+        }
+    }
+
+    /// Why a thread crashed, if one did since the last call
+    pub fn take_failure(&self) -> Option<Error> {
+        self.failure.lock().ok()?.take()
+    }
+
+    /// Threads still running
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// End every thread at its next epoch check; threads blocked waiting on
+    /// memory are left until the host exits
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.module.engine().increment_epoch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_interface::HostInterface;
+    use crate::module_cache;
+    use crate::runtime::WasmRuntime;
+    use crate::wasi_policy::WasiPolicy;
+    use std::time::{Duration, Instant};
+
+    /// Spawns a thread on each update, which stores its start argument
+    const GUEST: &str = r#"(module
+        (import "env" "memory" (memory 1 1 shared))
+        (import "wasi" "thread-spawn" (func $spawn (param i32) (result i32)))
+        (export "memory" (memory 0))
+        (func (export "wasi_thread_start") (param i32 i32)
+            (i32.atomic.store (i32.const 0) (local.get 1)))
+        (func (export "update") (param f64)
+            (i32.atomic.store (i32.const 4) (call $spawn (i32.const 7)))))"#;
+
+    fn runtime(enable_threads: bool) -> Result<WasmRuntime> {
+        let mut host = HostInterface::new();
+        host.set_threads_enabled(enable_threads);
+        WasmRuntime::new(
+            GUEST.as_bytes(),
+            None,
+            host,
+            &[],
+            None,
+            &WasiPolicy::default(),
+            false,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_threads_write_shared_memory() {
+        module_cache::disable();
+        assert!(runtime(false).is_err());
+
+        let mut runtime = runtime(true).unwrap();
+        runtime.call_update(0.0).unwrap();
+        assert_eq!(runtime.memory_data()[4..8], 1i32.to_le_bytes());
+        let start = Instant::now();
+        while runtime.memory_data()[0] != 7 {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "thread never ran"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! WebAssembly Proposals
+//!
+//! Guests run with the SIMD, bulk memory, multi-value and reference types
+//! proposals enabled, as in browsers. `--disable-feature` turns one off for
+//! every app, and the `disabled_features` manifest entry for a package's own
+//! module, to check that a build runs where the proposal is missing or to
+//! compare a scalar path with a SIMD one. Reference types build on bulk
+//! memory, so disabling bulk memory disables both. A module using a disabled
+//! proposal fails to compile with an error naming it, found by validating the
+//! module again without each disabled proposal in turn.
+
+use anyhow::Error;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use wasmparser::{Validator, WasmFeatures};
+use wasmtime::Config;
+
+/// A WebAssembly proposal that can be disabled
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Proposal {
+    Simd,
+    BulkMemory,
+    MultiValue,
+    ReferenceTypes,
+}
+
+impl Proposal {
+    /// Name on the command line and in manifests
+    pub fn name(self) -> &'static str {
+        match self {
+            Proposal::Simd => "simd",
+            Proposal::BulkMemory => "bulk-memory",
+            Proposal::MultiValue => "multi-value",
+            Proposal::ReferenceTypes => "reference-types",
+        }
+    }
+
+    /// Validator features left out with the proposal, its own and those
+    /// building on it
+    fn validator_features(self) -> WasmFeatures {
+        match self {
+            Proposal::Simd => WasmFeatures::SIMD | WasmFeatures::RELAXED_SIMD,
+            Proposal::BulkMemory => WasmFeatures::BULK_MEMORY,
+            Proposal::MultiValue => WasmFeatures::MULTI_VALUE,
+            Proposal::ReferenceTypes => {
+                WasmFeatures::REFERENCE_TYPES | WasmFeatures::FUNCTION_REFERENCES | WasmFeatures::GC
+            }
+        }
+    }
+}
+
+/// Proposals disabled for a guest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisabledFeatures(BTreeSet<Proposal>);
+
+impl DisabledFeatures {
+    pub fn new(proposals: impl IntoIterator<Item = Proposal>) -> Self {
+        let mut proposals: BTreeSet<_> = proposals.into_iter().collect();
+        if proposals.contains(&Proposal::BulkMemory) {
+            proposals.insert(Proposal::ReferenceTypes);
+        }
+        Self(proposals)
+    }
+
+    /// These proposals and `more`, e.g. those a manifest disables
+    pub fn with(&self, more: &[Proposal]) -> Self {
+        Self::new(self.0.iter().chain(more).copied())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Turn the proposals off in `config`
+    pub fn configure(&self, config: &mut Config) {
+        for proposal in &self.0 {
+            match proposal {
+                Proposal::Simd => {
+                    config.wasm_simd(false).wasm_relaxed_simd(false);
+                }
+                Proposal::BulkMemory => {
+                    config.wasm_bulk_memory(false);
+                }
+                Proposal::MultiValue => {
+                    config.wasm_multi_value(false);
+                }
+                Proposal::ReferenceTypes => {
+                    config.wasm_reference_types(false);
+                }
+            }
+        }
+    }
+
+    /// The disabled proposals `wasm_bytes` uses
+    ///
+    /// Modules that are invalid whatever the proposals use none of them.
+    pub fn used_by(&self, wasm_bytes: &[u8]) -> Vec<Proposal> {
+        let validates = |features: WasmFeatures| {
+            Validator::new_with_features(features)
+                .validate_all(wasm_bytes)
+                .is_ok()
+        };
+        if self.is_empty() || !validates(WasmFeatures::default()) {
+            return Vec::new();
+        }
+        self.0
+            .iter()
+            .copied()
+            .filter(|proposal| {
+                !validates(WasmFeatures::default().difference(proposal.validator_features()))
+            })
+            .collect()
+    }
+
+    /// `error`, from compiling `wasm_bytes`, naming the disabled proposals
+    /// the module uses
+    pub fn explain(&self, wasm_bytes: &[u8], error: Error) -> Error {
+        let used = self.used_by(wasm_bytes);
+        if used.is_empty() {
+            return error;
+        }
+        let names: Vec<_> = used.iter().map(|proposal| proposal.name()).collect();
+        error.context(format!(
+            "The app uses WebAssembly proposals disabled on this host: {}",
+            names.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Engine, Module};
+
+    /// Uses SIMD and bulk memory
+    const GUEST: &str = r#"(module
+        (memory 1)
+        (func (export "update") (param f64)
+            (v128.store (i32.const 0) (v128.const i32x4 1 2 3 4))
+            (memory.fill (i32.const 16) (i32.const 0) (i32.const 16))))"#;
+
+    #[test]
+    fn test_names_disabled_proposals_in_use() {
+        let wasm = wat::parse_str(GUEST).unwrap();
+        let compile = |disabled: &DisabledFeatures| {
+            let mut config = Config::new();
+            disabled.configure(&mut config);
+            let engine = Engine::new(&config).unwrap();
+            Module::new(&engine, &wasm).map_err(|e| disabled.explain(&wasm, e))
+        };
+
+        assert!(compile(&DisabledFeatures::default()).is_ok());
+        let disabled = DisabledFeatures::new([Proposal::MultiValue]);
+        assert!(compile(&disabled).is_ok());
+
+        let disabled = DisabledFeatures::new([Proposal::Simd, Proposal::MultiValue]);
+        let error = compile(&disabled).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The app uses WebAssembly proposals disabled on this host: simd"
+        );
+
+        // Reference types are disabled along with bulk memory, but unused
+        let disabled = disabled.with(&[Proposal::BulkMemory]);
+        assert_eq!(
+            disabled.used_by(&wasm),
+            [Proposal::Simd, Proposal::BulkMemory]
+        );
+        assert!(compile(&disabled).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Guest Watchdog
+//!
+//! Guests are compiled with wasmtime's epoch interruption, and a background
+//! thread advances the engine's epoch every few milliseconds. Before each
+//! call into the guest (`update`, an event callback...), the runtime sets the
+//! store's deadline `--frame-budget-ms` ahead, so a guest stuck in a loop
+//! traps with an interrupt instead of freezing the host. The trap surfaces as
+//! a guest error, which the crash handling of `--restart-on-crash` recovers.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use wasmtime::{Engine, Trap};
+
+/// How long one call into the guest may run by default
+pub const DEFAULT_FRAME_BUDGET: Duration = Duration::from_millis(1000);
+
+/// Interval between epoch increments, the precision of the budget
+const TICK: Duration = Duration::from_millis(5);
+
+/// Background thread advancing an engine's epoch
+pub struct Watchdog {
+    budget: Duration,
+    stop: Arc<AtomicBool>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start ticking the epoch of `engine`, which must have epoch interruption enabled
+    pub fn start(engine: Engine, budget: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let ticker = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("wapps-watchdog".into())
+                .spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        thread::sleep(TICK);
+                        engine.increment_epoch();
+                    }
+                })
+                .ok()
+        };
+        Self {
+            budget,
+            stop,
+            ticker,
+        }
+    }
+
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// Epoch ticks a guest call may run for
+    pub fn deadline_ticks(&self) -> u64 {
+        budget_ticks(self.budget)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+    }
+}
+
+/// Whole ticks covering `budget`, at least one
+fn budget_ticks(budget: Duration) -> u64 {
+    (budget.as_nanos().div_ceil(TICK.as_nanos()) as u64).max(1)
+}
+
+/// Whether `error` is a guest call interrupted by the watchdog
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Trap>() == Some(&Trap::Interrupt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Config, Instance, Module, Store};
+
+    #[test]
+    fn test_runaway_guests_are_interrupted() {
+        assert_eq!(budget_ticks(Duration::from_millis(12)), 3);
+        assert_eq!(budget_ticks(Duration::ZERO), 1);
+
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).unwrap();
+        let module = Module::new(
+            &engine,
+            r#"(module (func (export "update") (param f64) (loop (br 0))))"#,
+        )
+        .unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let update = instance
+            .get_typed_func::<f64, ()>(&mut store, "update")
+            .unwrap();
+
+        let watchdog = Watchdog::start(engine, Duration::from_millis(20));
+        store.set_epoch_deadline(watchdog.deadline_ticks());
+        let error = update.call(&mut store, 0.0).unwrap_err();
+        assert!(is_timeout(&error));
+    }
+}
@@ -0,0 +1,116 @@
+//! Window Identity
+//!
+//! Task switchers and taskbars group windows by application, which would
+//! otherwise be the `wapps` executable for every package. Before SDL creates
+//! its first window, the host derives an id like `wapps.space-invaders` from
+//! the package name and hands it to the platform: as the WM_CLASS on X11, the
+//! app id on Wayland and the AppUserModelID on Windows, so each WAPP shows up
+//! and groups as its own application. The window title is the app's localized
+//! name. The id belongs to the process, so apps run side by side in one host
+//! keep the host's identity.
+
+use log::debug;
+use std::path::Path;
+
+use crate::deeplink;
+use crate::loader;
+use crate::permissions;
+
+/// Identity of the host itself
+pub const HOST_APP_ID: &str = "wapps";
+
+/// Longest package part of an id; AppUserModelIDs are limited to 128 characters
+const MAX_SLUG_LEN: usize = 64;
+
+/// Id of the package named `name`, or of the file at `path` when unnamed
+pub fn app_id(name: &str, path: &Path) -> String {
+    let mut slug = String::new();
+    for c in permissions::package_name(name, path).chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_SLUG_LEN {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        HOST_APP_ID.to_string()
+    } else {
+        format!("{}.{}", HOST_APP_ID, slug)
+    }
+}
+
+/// Identify the process as the package named on the command line, which may be
+/// a `wapps://` URL; must run before SDL initializes
+///
+/// Packages that cannot be read keep the host's identity, and report their
+/// error when the app loads.
+pub fn identify_as_package(arg: &Path) {
+    let metadata = deeplink::resolve_argument(arg)
+        .and_then(|(path, _)| loader::load_wapp(&path).map(|(_, metadata)| (path, metadata)));
+    match metadata {
+        Ok((path, metadata)) => {
+            let id = app_id(&metadata.name, &path);
+            debug!("Identifying as {}", id);
+            // Names the app to audio servers and screensaver inhibition too
+            sdl2::hint::set(
+                "SDL_APP_NAME",
+                &permissions::package_name(&metadata.name, &path),
+            );
+            set_platform_id(&id);
+        }
+        Err(e) => debug!("Keeping the host's window identity: {:#}", e),
+    }
+}
+
+/// Set the X11 WM_CLASS and Wayland app id, unless the user chose their own
+#[cfg(all(unix, not(target_os = "macos")))]
+fn set_platform_id(id: &str) {
+    // Both are read by SDL when its video subsystem initializes
+    for variable in ["SDL_VIDEO_X11_WMCLASS", "SDL_VIDEO_WAYLAND_WMCLASS"] {
+        if std::env::var_os(variable).is_none() {
+            std::env::set_var(variable, id);
+        }
+    }
+}
+
+/// Set the AppUserModelID the taskbar groups windows by
+#[cfg(target_os = "windows")]
+fn set_platform_id(id: &str) {
+    #[link(name = "shell32")]
+    extern "system" {
+        fn SetCurrentProcessExplicitAppUserModelID(app_id: *const u16) -> i32;
+    }
+
+    let wide: Vec<u16> = id.encode_utf16().chain(Some(0)).collect();
+    // SAFETY: `wide` is a NUL-terminated UTF-16 string that outlives the call
+    let result = unsafe { SetCurrentProcessExplicitAppUserModelID(wide.as_ptr()) };
+    if result < 0 {
+        log::warn!(
+            "Could not set the AppUserModelID (HRESULT {:#010x})",
+            result
+        );
+    }
+}
+
+/// The dock identifies apps by their bundle, which packages don't have
+#[cfg(not(any(all(unix, not(target_os = "macos")), target_os = "windows")))]
+fn set_platform_id(_id: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_derived_from_package_names() {
+        let path = Path::new("games/pong.wapp");
+        assert_eq!(app_id("Space Invaders!", path), "wapps.space-invaders");
+        assert_eq!(app_id("", path), "wapps.pong");
+        assert_eq!(app_id(" Pong: Deluxe ", path), "wapps.pong-deluxe");
+        assert_eq!(app_id("日本", path), HOST_APP_ID);
+        assert_eq!(app_id(&"a".repeat(200), path).len(), 6 + MAX_SLUG_LEN);
+    }
+}
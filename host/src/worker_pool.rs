@@ -0,0 +1,114 @@
+//! Worker Pool
+//!
+//! A fixed-size pool of threads used to run guest updates in parallel when
+//! several apps are running. Each job owns the runtime it operates on for the
+//! duration of the call, so stores are never shared between threads. A job
+//! that panics is caught, so its worker keeps serving the queue.
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Fixed-size thread pool executing boxed jobs
+pub struct WorkerPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Spawn a pool with `size` worker threads (at least one)
+    pub fn new(size: usize) -> Result<Self> {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|index| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("wapps-worker-{}", index))
+                    .spawn(move || loop {
+                        // Hold the lock only while waiting for the next job
+                        let job = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => break,
+                        };
+                        let Ok(job) = job else {
+                            break;
+                        };
+                        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                            warn!("A job panicked on worker thread {}", index);
+                        }
+                    })
+                    .context("Failed to spawn worker thread")
+            })
+            .collect::<Result<_>>()?;
+
+        debug!("Worker pool started with {} threads", size);
+
+        Ok(Self {
+            sender: Some(sender),
+            workers,
+        })
+    }
+
+    /// Number of threads that default to one per available core
+    pub fn default_size() -> usize {
+        thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    }
+
+    /// Queue a job for execution on the next idle worker
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(sender) = &self.sender {
+            // Workers only exit once the sender is dropped, so this cannot fail
+            let _ = sender.send(Box::new(job));
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // Closing the channel makes every worker's recv() fail and exit
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_runs_every_job() {
+        let pool = WorkerPool::new(4).unwrap();
+        let (tx, rx) = mpsc::channel();
+        for i in 0..16 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        drop(tx);
+        let mut results: Vec<i32> = rx.iter().collect();
+        results.sort();
+        assert_eq!(results, (0..16).collect::<Vec<_>>());
+    }
+    #[test]
+    fn test_workers_survive_panicking_jobs() {
+        let pool = WorkerPool::new(1).unwrap();
+        pool.execute(|| panic!("job failed"));
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(1).unwrap());
+        assert_eq!(rx.recv(), Ok(1));
+    }
+}
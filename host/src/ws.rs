@@ -0,0 +1,317 @@
+//! Sandboxed WebSockets
+//!
+//! Realtime apps (multiplayer games, live dashboards) open WebSockets with
+//! `wapps::ws_connect` to the hosts their manifest lists under
+//! `"allowed_hosts"`, like `wapps::http_fetch`, and send messages with
+//! `wapps::ws_send`. Each connection runs on its own thread, which queues the
+//! messages it receives; the app takes them at the end of each frame and
+//! delivers them through the guest's `on_ws_message(handle, ptr, len)`
+//! callback. Guests never see a socket, and the imports belong to the
+//! `network` capability and permission.
+
+use log::{debug, warn};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::http::Uri;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::http::host_allowed;
+
+/// Connections an app may have open at once
+pub const MAX_CONNECTIONS: usize = 8;
+
+/// Largest message sent or received, in bytes
+pub const MAX_MESSAGE: usize = 1024 * 1024;
+
+/// Received messages kept per connection until the app takes them; the
+/// oldest are dropped past this
+const MAX_QUEUED: usize = 256;
+
+/// How long a connection's thread waits for a message before checking for
+/// messages to send
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Errors returned by `wapps::ws_connect` instead of a handle; 0 means the
+/// network permission was denied
+pub const WS_INVALID: i32 = -1;
+pub const WS_HOST_DENIED: i32 = -2;
+pub const WS_TOO_MANY: i32 = -3;
+
+/// States returned by `wapps::ws_status`
+pub const WS_CONNECTING: i32 = 0;
+pub const WS_OPEN: i32 = 1;
+/// Returned by `wapps::ws_status` and `wapps::ws_send` for unknown handles
+pub const WS_NOT_FOUND: i32 = -1;
+/// Returned by `wapps::ws_status` and `wapps::ws_send` once the connection
+/// failed or was closed by the server
+pub const WS_CLOSED: i32 = -2;
+
+/// State of a connection, shared with its thread
+#[derive(Debug, Default)]
+struct Shared {
+    open: bool,
+    closed: bool,
+    received: VecDeque<Vec<u8>>,
+}
+
+/// One open connection
+#[derive(Debug)]
+struct Connection {
+    shared: Arc<Mutex<Shared>>,
+    /// Messages to send; dropping it makes the thread close the socket
+    outgoing: Sender<Vec<u8>>,
+}
+
+/// One app's WebSocket connections
+#[derive(Debug, Default)]
+pub struct WsClient {
+    /// Hosts connections may reach, from the manifest; `*.` entries match
+    /// subdomains
+    allowed_hosts: Vec<String>,
+    /// Connections by handle, ordered so messages are delivered in a stable
+    /// order
+    connections: BTreeMap<i32, Connection>,
+    last_handle: i32,
+}
+
+impl WsClient {
+    /// Allow connections to `hosts`, replacing the previous list
+    pub fn set_allowed_hosts(&mut self, hosts: Vec<String>) {
+        self.allowed_hosts = hosts
+            .into_iter()
+            .map(|host| host.to_ascii_lowercase())
+            .collect();
+    }
+
+    /// Open a connection to a `ws://` or `wss://` URL, returning its handle
+    /// or a `WS_*` error
+    pub fn connect(&mut self, url: &str) -> i32 {
+        let Some((uri, host)) = parse_url(url) else {
+            return WS_INVALID;
+        };
+        if !host_allowed(&self.allowed_hosts, &host) {
+            warn!(
+                "ws_connect: {} is not in the manifest's allowed_hosts",
+                host
+            );
+            return WS_HOST_DENIED;
+        }
+        if self.connections.len() >= MAX_CONNECTIONS {
+            return WS_TOO_MANY;
+        }
+
+        self.last_handle = self.last_handle.checked_add(1).unwrap_or(1);
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (outgoing, receiver) = mpsc::channel();
+        let connection = Connection {
+            shared: shared.clone(),
+            outgoing,
+        };
+        self.connections.insert(self.last_handle, connection);
+        thread::spawn(move || run(uri, &shared, &receiver));
+        self.last_handle
+    }
+
+    /// Queue `message` on the connection `handle`, sent as text if it is
+    /// UTF-8 and as binary otherwise; returns 0 or a `WS_*` error
+    ///
+    /// Messages sent while the connection opens go out once it is open.
+    pub fn send(&self, handle: i32, message: Vec<u8>) -> i32 {
+        let Some(connection) = self.connections.get(&handle) else {
+            return WS_NOT_FOUND;
+        };
+        if message.len() > MAX_MESSAGE {
+            return WS_INVALID;
+        }
+        match connection.outgoing.send(message) {
+            Ok(()) => 0,
+            Err(_) => WS_CLOSED,
+        }
+    }
+
+    /// State of the connection `handle`, as a `WS_*` state or error
+    pub fn status(&self, handle: i32) -> i32 {
+        let Some(connection) = self.connections.get(&handle) else {
+            return WS_NOT_FOUND;
+        };
+        let shared = connection.shared.lock().unwrap_or_else(|e| e.into_inner());
+        match (shared.closed, shared.open) {
+            (true, _) => WS_CLOSED,
+            (false, true) => WS_OPEN,
+            (false, false) => WS_CONNECTING,
+        }
+    }
+
+    /// Close the connection `handle` and drop its undelivered messages
+    pub fn close(&mut self, handle: i32) {
+        self.connections.remove(&handle);
+    }
+
+    /// Take the messages received since the last call, as (handle, message)
+    pub fn take_messages(&mut self) -> Vec<(i32, Vec<u8>)> {
+        let mut messages = Vec::new();
+        for (handle, connection) in &self.connections {
+            let mut shared = connection.shared.lock().unwrap_or_else(|e| e.into_inner());
+            messages.extend(shared.received.drain(..).map(|message| (*handle, message)));
+        }
+        messages
+    }
+}
+
+/// Body of a connection's thread: connect, then alternate between sending
+/// queued messages and waiting for received ones until either side closes
+fn run(uri: Uri, shared: &Mutex<Shared>, outgoing: &Receiver<Vec<u8>>) {
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE),
+        max_frame_size: Some(MAX_MESSAGE),
+        ..WebSocketConfig::default()
+    };
+    let url = uri.to_string();
+    // Redirects could lead outside the allowlist
+    let result = tungstenite::client::connect_with_config(uri, Some(config), 0)
+        .map_err(|e| e.to_string())
+        .and_then(|(socket, _)| {
+            set_read_timeout(&socket, Some(POLL_INTERVAL)).map_err(|e| e.to_string())?;
+            Ok(socket)
+        });
+    let mut socket = match result {
+        Ok(socket) => socket,
+        Err(e) => {
+            debug!("ws_connect: {} failed: {}", url, e);
+            shared.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+            return;
+        }
+    };
+    shared.lock().unwrap_or_else(|e| e.into_inner()).open = true;
+
+    if let Err(e) = exchange(&mut socket, shared, outgoing) {
+        debug!("ws: {} closed: {}", url, e);
+    }
+    shared.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+}
+
+/// Parse a ws or wss `url` as tungstenite does, into the URI to connect to
+/// and its lowercase host, without a port; `None` for other schemes and URLs
+/// with credentials
+///
+/// The host is the one the connection goes to. URLs with backslashes or
+/// control characters are rejected, as they do not always name the host they
+/// appear to.
+fn parse_url(url: &str) -> Option<(Uri, String)> {
+    if url.contains('\\') || url.chars().any(char::is_control) {
+        return None;
+    }
+    let uri: Uri = url.parse().ok()?;
+    if !matches!(uri.scheme_str(), Some("ws" | "wss")) {
+        return None;
+    }
+    let authority = uri.authority()?;
+    if authority.as_str().contains('@') {
+        return None;
+    }
+    // IPv6 literals keep their brackets
+    let host = authority.host();
+    let host = host
+        .strip_prefix('[')
+        .and_then(|literal| literal.strip_suffix(']'))
+        .unwrap_or(host)
+        .to_ascii_lowercase();
+    if host.is_empty() {
+        return None;
+    }
+    Some((uri, host))
+}
+
+/// Exchange messages until the server closes the connection or the app drops
+/// it; the error is boxed, being much larger than the `Ok` case
+fn exchange(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    shared: &Mutex<Shared>,
+    outgoing: &Receiver<Vec<u8>>,
+) -> Result<(), Box<tungstenite::Error>> {
+    loop {
+        loop {
+            let message = match outgoing.try_recv() {
+                Ok(message) => message,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    socket.close(None)?;
+                    return Ok(socket.flush()?);
+                }
+            };
+            let message = match String::from_utf8(message) {
+                Ok(text) => Message::Text(text),
+                Err(e) => Message::Binary(e.into_bytes()),
+            };
+            socket.send(message)?;
+        }
+
+        let message = match socket.read() {
+            Ok(Message::Text(text)) => text.into_bytes(),
+            Ok(Message::Binary(data)) => data,
+            // Pings are answered by the next write or flush
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                socket.flush()?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut shared = shared.lock().unwrap_or_else(|e| e.into_inner());
+        if shared.received.len() == MAX_QUEUED {
+            shared.received.pop_front();
+        }
+        shared.received.push_back(message);
+    }
+}
+
+/// Bound how long reads on the connection's TCP stream block
+fn set_read_timeout(
+    socket: &WebSocket<MaybeTlsStream<TcpStream>>,
+    timeout: Option<Duration>,
+) -> std::io::Result<()> {
+    match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(timeout),
+        MaybeTlsStream::Rustls(stream) => stream.get_ref().set_read_timeout(timeout),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_are_checked_against_the_allowlist() {
+        let mut client = WsClient::default();
+        client.set_allowed_hosts(vec!["*.example.com".to_string()]);
+        assert_eq!(client.connect("wss://example.org/live"), WS_HOST_DENIED);
+        assert_eq!(client.connect("https://live.example.com/"), WS_INVALID);
+        assert_eq!(client.status(1), WS_NOT_FOUND);
+        assert_eq!(client.send(1, b"hello".to_vec()), WS_NOT_FOUND);
+        assert!(client.take_messages().is_empty());
+
+        let host = |url| parse_url(url).map(|(_, host)| host);
+        assert_eq!(
+            host("wss://Live.Example.com:8443/x").as_deref(),
+            Some("live.example.com")
+        );
+        assert_eq!(host("ws://[::1]:80/").as_deref(), Some("::1"));
+        for url in [
+            "ws://evil.com\\x.example.com/",
+            "ws://x.example.com@evil.com/",
+            "ws://evil.com\tx.example.com/",
+        ] {
+            assert_eq!(client.connect(url), WS_INVALID, "{}", url);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 // Main entry point
-import { WappRuntime } from './runtime.js';
+import { WappRuntime, MOD_SHIFT, MOD_CTRL, MOD_ALT, MOD_SUPER } from './runtime.js';
 
 const canvas = document.getElementById('wapp-canvas');
 const runtime = new WappRuntime(canvas);
@@ -18,7 +18,11 @@ async function loadFile(file) {
     
     try {
         const buffer = await file.arrayBuffer();
-        await runtime.load(new Uint8Array(buffer));
+        const metadata = await runtime.load(new Uint8Array(buffer));
+        document.title = metadata.name || file.name;
+        if (devicePixelRatio !== 1) {
+            runtime.handleScaleChange(devicePixelRatio);
+        }
         runtime.start();
     } catch (e) {
         console.error("Failed to load WAPP:", e);
@@ -38,50 +42,141 @@ document.addEventListener('dragover', (e) => {
     e.preventDefault();
 });
 
+// Packages replace the running app; other files go to it if it takes them
 document.addEventListener('drop', (e) => {
     e.preventDefault();
-    if (e.dataTransfer.files.length > 0) {
-        loadFile(e.dataTransfer.files[0]);
+    const file = e.dataTransfer.files[0];
+    if (!file) return;
+    if (!file.name.endsWith('.wapp') && runtime.acceptsDroppedFiles()) {
+        runtime.handleFileDropped(file);
+    } else {
+        loadFile(file);
     }
 });
 
 // Input Handling
+// Frame pixel under the pointer, like the native host: beyond the edges when
+// outside the canvas
+function canvasPosition(e) {
+    return [
+        Math.floor(e.offsetX * (canvas.width / canvas.clientWidth)),
+        Math.floor(e.offsetY * (canvas.height / canvas.clientHeight)),
+    ];
+}
+
 canvas.addEventListener('mousedown', (e) => {
-    const x = e.offsetX * (canvas.width / canvas.clientWidth);
-    const y = e.offsetY * (canvas.height / canvas.clientHeight);
+    runtime.setEventTime(e.timeStamp);
+    if (runtime.relativeMouse && document.pointerLockElement !== canvas) {
+        canvas.requestPointerLock();
+    }
     // Button: 0->1 (Left), 1->2 (Middle), 2->3 (Right)
-    runtime.handleMouseDown(x, y, e.button + 1);
+    runtime.handleMouseDown(...canvasPosition(e), e.button + 1);
 });
 
 canvas.addEventListener('mouseup', (e) => {
-    const x = e.offsetX * (canvas.width / canvas.clientWidth);
-    const y = e.offsetY * (canvas.height / canvas.clientHeight);
-    runtime.handleMouseUp(x, y, e.button + 1);
+    runtime.setEventTime(e.timeStamp);
+    runtime.handleMouseUp(...canvasPosition(e), e.button + 1);
 });
 
 canvas.addEventListener('mousemove', (e) => {
-    const x = e.offsetX * (canvas.width / canvas.clientWidth);
-    const y = e.offsetY * (canvas.height / canvas.clientHeight);
-    runtime.handleMouseMove(x, y);
+    runtime.setEventTime(e.timeStamp);
+    runtime.handleMouseMove(...canvasPosition(e), e.movementX, e.movementY);
+});
+
+// Touch positions stay fractional; pens and mice send their own events
+for (const [type, phase] of [['pointerdown', 'down'], ['pointermove', 'move'], ['pointerup', 'up'], ['pointercancel', 'up']]) {
+    canvas.addEventListener(type, (e) => {
+        if (e.pointerType !== 'touch') return;
+        runtime.setEventTime(e.timeStamp);
+        const x = e.offsetX * (canvas.width / canvas.clientWidth);
+        const y = e.offsetY * (canvas.height / canvas.clientHeight);
+        runtime.handleTouch(phase, e.pointerId, x, y, e.pressure);
+    });
+}
+
+canvas.addEventListener('wheel', (e) => {
+    e.preventDefault();
+    runtime.setEventTime(e.timeStamp);
+    // Notches, positive away from the user like SDL's; lines and pages count as one
+    const notch = e.deltaMode === WheelEvent.DOM_DELTA_PIXEL ? 100 : 1;
+    runtime.handleScroll(e.deltaX / notch, -e.deltaY / notch);
+}, { passive: false });
+
+new ResizeObserver(([entry]) => {
+    const { width, height } = entry.contentRect;
+    runtime.handleResize(Math.round(width), Math.round(height));
+}).observe(canvas);
+
+// Report pixel density changes, e.g. when the window moves to another display
+function watchScale() {
+    matchMedia(`(resolution: ${devicePixelRatio}dppx)`).addEventListener('change', () => {
+        runtime.handleScaleChange(devicePixelRatio);
+        watchScale();
+    }, { once: true });
+}
+watchScale();
+
+window.addEventListener('focus', () => runtime.handleFocus(true));
+window.addEventListener('blur', () => runtime.handleFocus(false));
+document.addEventListener('visibilitychange', () => {
+    runtime.handleVisibility(document.visibilityState === 'visible');
+});
+window.addEventListener('pagehide', () => runtime.shutdown());
+window.addEventListener('beforeunload', (e) => {
+    if (!runtime.handleCloseRequest()) {
+        e.preventDefault();
+    }
 });
 
-// Key Mapping (Partial USB HID)
+// Key Mapping: KeyboardEvent.code to USB HID usage, the scancodes guests see
 const KEY_MAP = {
-    "Space": 44,
-    "KeyR": 21,
-    "KeyC": 6,
-    // Add more as needed
-    "ArrowUp": 82, "ArrowDown": 81, "ArrowLeft": 80, "ArrowRight": 79,
-    "Enter": 40, "Escape": 41, "Backspace": 42, "Tab": 43
+    "Enter": 40, "Escape": 41, "Backspace": 42, "Tab": 43, "Space": 44,
+    "Minus": 45, "Equal": 46, "BracketLeft": 47, "BracketRight": 48, "Backslash": 49,
+    "Semicolon": 51, "Quote": 52, "Backquote": 53, "Comma": 54, "Period": 55, "Slash": 56,
+    "CapsLock": 57, "PrintScreen": 70, "ScrollLock": 71, "Pause": 72,
+    "Insert": 73, "Home": 74, "PageUp": 75, "Delete": 76, "End": 77, "PageDown": 78,
+    "ArrowRight": 79, "ArrowLeft": 80, "ArrowDown": 81, "ArrowUp": 82,
+    "NumLock": 83, "NumpadDivide": 84, "NumpadMultiply": 85, "NumpadSubtract": 86,
+    "NumpadAdd": 87, "NumpadEnter": 88, "NumpadDecimal": 99, "IntlBackslash": 100,
+    "ContextMenu": 101,
+    "ControlLeft": 224, "ShiftLeft": 225, "AltLeft": 226, "MetaLeft": 227,
+    "ControlRight": 228, "ShiftRight": 229, "AltRight": 230, "MetaRight": 231,
 };
+// KeyA..KeyZ: 4..29
+for (let i = 0; i < 26; i++) {
+    KEY_MAP["Key" + String.fromCharCode(65 + i)] = 4 + i;
+}
+// Digit1..Digit9, Digit0: 30..39
+for (let i = 1; i <= 10; i++) {
+    KEY_MAP["Digit" + (i % 10)] = 29 + i;
+}
+// F1..F12: 58..69
+for (let i = 1; i <= 12; i++) {
+    KEY_MAP["F" + i] = 57 + i;
+}
+// Numpad1..Numpad9, Numpad0: 89..98
+for (let i = 1; i <= 10; i++) {
+    KEY_MAP["Numpad" + (i % 10)] = 88 + i;
+}
+
+function modifiers(e) {
+    return (e.shiftKey ? MOD_SHIFT : 0) | (e.ctrlKey ? MOD_CTRL : 0)
+        | (e.altKey ? MOD_ALT : 0) | (e.metaKey ? MOD_SUPER : 0);
+}
 
 window.addEventListener('keydown', (e) => {
+    runtime.setEventTime(e.timeStamp);
     if (KEY_MAP[e.code]) {
-        runtime.handleKeyDown(KEY_MAP[e.code]);
+        runtime.handleKeyDown(KEY_MAP[e.code], modifiers(e), e.repeat);
+    }
+    // Printable characters, unless they are shortcuts
+    if (e.key.length === 1 && !e.ctrlKey && !e.metaKey) {
+        runtime.handleTextInput(e.key);
     }
 });
 
 window.addEventListener('keyup', (e) => {
+    runtime.setEventTime(e.timeStamp);
     if (KEY_MAP[e.code]) {
         runtime.handleKeyUp(KEY_MAP[e.code]);
     }
@@ -1,4 +1,89 @@
 import { WASI, File, OpenFile, ConsoleStdout } from 'https://esm.sh/@bjorn3/browser_wasi_shim@0.4.2';
+// Packages are unpacked by the wapps-web crate, built with
+// `wasm-pack build web --target web --out-dir ../host/web/pkg`
+import initWappsWeb, { unpack } from './pkg/wapps_web.js';
+
+const wappsWebReady = initWappsWeb();
+
+// Statuses returned by the imports, as defined by the native host
+const STATUS_OK = 0;
+const STATUS_INVALID = -1;
+const NOT_FOUND = -1;
+const LAUNCH_DENIED = -1;
+const BUS_DENIED = -1;
+const FULLSCREEN_DENIED = -2;
+const SNAPSHOT_DENIED = -1;
+const STORAGE_QUOTA_EXCEEDED = -2;
+const STORAGE_IO_ERROR = -3;
+const HTTP_PENDING = 0;
+const HTTP_HOST_DENIED = -2;
+const HTTP_TOO_MANY = -3;
+const HTTP_FAILED = -2;
+const WS_INVALID = -1;
+const WS_HOST_DENIED = -2;
+const WS_TOO_MANY = -3;
+const WS_CONNECTING = 0;
+const WS_OPEN = 1;
+const WS_CLOSED = -2;
+const FILE_DIALOG_OK = 1;
+const FILE_DIALOG_INVALID = -3;
+const FILE_SAVED = 0;
+const FILE_CANCELLED = -1;
+const TIMER_OK = 0;
+const TIMER_INVALID = -1;
+const TIMER_TOO_MANY = -2;
+const TIMER_NOT_FOUND = -3;
+
+// Statuses of the wapps3 imports, as defined by the wapps-abi crate; older
+// revisions report every failure as STATUS_INVALID
+const OUT_OF_BOUNDS = -1;
+const INVALID_DIMENSIONS = -2;
+const PERMISSION_DENIED = -3;
+const UNSUPPORTED = -4;
+const legacyStatus = (status) => Math.max(status, STATUS_INVALID);
+
+// Timers an app may have scheduled via wapps::set_timer
+const MAX_TIMERS = 64;
+
+// Bytes of values an app may keep in storage, as on the native host
+const STORAGE_QUOTA = 1024 * 1024;
+
+// Largest side of a wapps::update_overlay HUD, as on the native host
+const MAX_OVERLAY_SIDE = 8192;
+
+// Pixel formats of wapps::update_frame_ex, indexed by their raw value:
+// RGBA32, RGB24, BGRA32, RGB565, Gray8, Indexed8
+const BYTES_PER_PIXEL = [4, 3, 4, 2, 1, 1];
+
+// CSS cursors of wapps::set_cursor shapes, indexed by their raw value
+const CURSORS = ['default', 'text', 'crosshair', 'pointer', 'move', 'ew-resize', 'ns-resize', 'not-allowed', 'wait'];
+
+// Limits of wapps::http_fetch: open requests, and bytes of request and response bodies
+const HTTP_MAX_REQUESTS = 16;
+const HTTP_MAX_BODY = 8 * 1024 * 1024;
+
+// Limits of wapps::ws_connect: open sockets, and bytes of each message
+const WS_MAX_CONNECTIONS = 8;
+const WS_MAX_MESSAGE = 1024 * 1024;
+
+// Largest file passed to on_file_opened or saved for the guest, in bytes
+const MAX_FILE_SIZE = 64 * 1024 * 1024;
+
+// Longest rumble of wapps::gamepad_rumble, in milliseconds
+const MAX_RUMBLE_MS = 10000;
+
+// Status codes of wapps::audio_capture_start, and samples per capture callback
+const AUDIO_CAPTURE_OK = 1;
+const AUDIO_CAPTURE_CHUNK = 4096;
+
+// Console methods of wapps::log levels, from 1 (error) to 5 (trace)
+const LOG_METHODS = ['error', 'warn', 'info', 'debug', 'debug'];
+
+// Modifier bits passed to on_key_down
+const MOD_SHIFT = 1;
+const MOD_CTRL = 2;
+const MOD_ALT = 4;
+const MOD_SUPER = 8;
 
 export class WappRuntime {
     constructor(canvas) {
@@ -7,45 +92,66 @@ export class WappRuntime {
         this.wasi = null;
         this.instance = null;
         this.memory = null;
+        this.metadata = {};
+        this.config = new Map();
+        // Package assets readable via wapps::asset_read, by name
+        this.assets = new Map();
         this.width = 0;
         this.height = 0;
         this.frameBufferPtr = 0;
+        this.format = 0;
         this.pixelsView = null;
+        // Frames in other formats than RGBA32, expanded to RGBA
+        this.converted = null;
+        this.palette = new Uint8Array(256 * 4);
+        // Size last passed to init or on_resize, for wapps::get_window_size
+        this.viewport = [0, 0];
+        // Frames rendered so far, for wapps::get_frame_count
+        this.frameCount = 0;
+        // HUD set via wapps::update_overlay, drawn over each frame
+        this.hud = null;
+        this.clearColor = '#000';
+        this.heldKeys = new Set();
+        this.cursorHidden = false;
+        this.cursorShape = 0;
+        // Pointer lock needs a user gesture, so main.js requests it on clicks while this is set
+        this.relativeMouse = false;
+        this.eventTime = 0;
+        this.audio = null;
+        this.audioEnd = 0;
+        // Microphone recording started via wapps::audio_capture_start
+        this.capture = null;
+        this.running = false;
+        // Requests started via wapps::http_fetch, by handle
+        this.httpRequests = new Map();
+        this.httpLastHandle = 0;
+        // WebSockets opened via wapps::ws_connect, by handle
+        this.webSockets = new Map();
+        this.wsLastHandle = 0;
+        // Timers scheduled via wapps::set_timer, by id
+        this.timers = new Map();
     }
 
     async load(bytes){
-        // Parse WAPP Header
-        // Struct: Magic(4) | Version(4) | JSON_Len(4) | JSON(N) | WASM(...)
-        const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
-        
-        // 1. Magic: WAPP (0x57415050)
-        const magic = view.getUint32(0, false); // Big Endian check for string
-        if (magic !== 0x57415050) {
-            throw new Error("Invalid magic header: Not a WAPP file");
-        }
-
-        // 2. Version: u32 LE
-        const version = view.getUint32(4, true);
-        if (version !== 1) {
-            throw new Error(`Unsupported WAPP version: ${version}`);
-        }
-
-        // 3. JSON Length: u32 LE
-        const jsonLen = view.getUint32(8, true);
-        
-        // 4. JSON Metadata
-        const jsonBytes = bytes.subarray(12, 12 + jsonLen);
-        const decoder = new TextDecoder('utf-8');
+        await wappsWebReady;
+        // Throws with the reason the package is invalid
+        const wapp = unpack(bytes);
         let metadata = {};
         try {
-            const jsonString = decoder.decode(jsonBytes);
-            metadata = JSON.parse(jsonString);
+            metadata = JSON.parse(wapp.metadata);
         } catch (e) {
+            wapp.free();
             throw new Error("Failed to parse WAPP metadata: " + e.message);
         }
+        this.metadata = metadata;
+        this.config = new Map([
+            ...Object.entries(metadata.config ?? {}),
+            ...new URLSearchParams(location.search),
+        ]);
 
-        // 5. WASM Payload
-        const wasmBytes = bytes.subarray(12 + jsonLen);
+        const wasmBytes = wapp.module;
+        this.assets = new Map(wapp.assetNames().map((name) => [name, wapp.asset(name)]));
+        wapp.free();
 
         const args = [];
         const env = [];
@@ -57,22 +163,37 @@ export class WappRuntime {
 
         this.wasi = new WASI(args, env, fds);
 
-        const wappsImports = {
-            wapps: {
-                update_frame: (width, height, ptr) => {
-                    this.frameBufferPtr = ptr;
-                    this.width = width;
-                    this.height = height;
-                }
-            }
-        };
-
-        const wasm = await WebAssembly.instantiate(wasmBytes, {
+        const module = await WebAssembly.compile(wasmBytes);
+        const wappsImports = this.createImports();
+        const imports = {
             "wasi_snapshot_preview1": this.wasi.wasiImport,
-            ...wappsImports
-        });
+            wapps: wappsImports,
+            // Revision 2 only changes update_frame to return a status
+            wapps2: {
+                ...wappsImports,
+                update_frame: (width, height, ptr) => legacyStatus(this.presentFrame(width, height, ptr, 0)),
+            },
+            // Revision 3 returns why drawing and fullscreen calls fail
+            wapps3: {
+                ...wappsImports,
+                update_frame: (width, height, ptr) => this.presentFrame(width, height, ptr, 0),
+                update_frame_ex: (width, height, ptr, format) => this.presentFrame(width, height, ptr, format),
+                update_overlay: (width, height, ptr) => this.updateOverlay(width, height, ptr),
+                set_palette: (ptr, count) => this.setPalette(ptr, count),
+                clear_canvas: (width, height, rgba) => this.clearCanvas(width, height, rgba),
+                set_fullscreen: (mode) => {
+                    const status = wappsImports.set_fullscreen(mode);
+                    if (status === FULLSCREEN_DENIED) return PERMISSION_DENIED;
+                    return status === STATUS_OK ? STATUS_OK : UNSUPPORTED;
+                },
+            },
+        };
+        stubMissingImports(module, imports);
 
-        this.instance = wasm.instance;
+        const instance = await WebAssembly.instantiate(module, imports);
+        this.shutdown();
+        this.timers.clear();
+        this.instance = instance;
         this.memory = this.instance.exports.memory;
         
         // Initialize WASI (runs _start if present)
@@ -81,19 +202,428 @@ export class WappRuntime {
         } else {
              this.wasi.initialize(this.instance); // If reactor mode supported by shim
         }
+        this.viewport = [this.canvas.clientWidth, this.canvas.clientHeight];
+        this.instance.exports.init?.(...this.viewport);
 
         return metadata;
     }
 
+    // The `wapps` imports the browser can provide; the others are stubbed
+    createImports() {
+        return {
+            update_frame: (width, height, ptr) => {
+                this.presentFrame(width, height, ptr, 0);
+            },
+            update_frame_ex: (width, height, ptr, format) =>
+                legacyStatus(this.presentFrame(width, height, ptr, format)),
+            update_overlay: (width, height, ptr) => legacyStatus(this.updateOverlay(width, height, ptr)),
+            set_palette: (ptr, count) => legacyStatus(this.setPalette(ptr, count)),
+            set_clear_color: (rgba) => {
+                this.clearColor = cssColor(rgba);
+                this.canvas.style.backgroundColor = this.clearColor;
+            },
+            clear_canvas: (width, height, rgba) => {
+                this.clearCanvas(width, height, rgba);
+            },
+            set_fullscreen: (mode) => {
+                // Browsers only allow fullscreen from a user gesture, which
+                // guest code never runs in
+                if (mode === 0 && document.fullscreenElement) {
+                    document.exitFullscreen();
+                    return STATUS_OK;
+                }
+                return mode === 0 ? STATUS_OK : FULLSCREEN_DENIED;
+            },
+            set_window_title: (ptr, len) => {
+                if (!this.inBounds(ptr, len)) return STATUS_INVALID;
+                document.title = this.readString(ptr, len);
+                return STATUS_OK;
+            },
+            // The page is the window, so the canvas takes the size instead
+            set_window_size: (width, height) => {
+                if (width === 0 && height === 0) {
+                    this.canvas.style.width = this.canvas.style.height = '';
+                    return STATUS_OK;
+                }
+                if (width <= 0 || height <= 0 || width > 16384 || height > 16384) return STATUS_INVALID;
+                this.canvas.style.width = `${width}px`;
+                this.canvas.style.height = `${height}px`;
+                return STATUS_OK;
+            },
+            get_window_size: (wPtr, hPtr) => {
+                if (!this.inBounds(wPtr, 4) || !this.inBounds(hPtr, 4)) return STATUS_INVALID;
+                const view = new DataView(this.memory.buffer);
+                view.setInt32(wPtr, this.viewport[0], true);
+                view.setInt32(hPtr, this.viewport[1], true);
+                return STATUS_OK;
+            },
+            // Browsers do not expose the display's refresh rate
+            get_display_refresh_rate: () => 0,
+            get_frame_count: () => BigInt(this.frameCount),
+            set_cursor_visible: (visible) => {
+                this.cursorHidden = visible === 0;
+                this.updateCursor();
+            },
+            set_cursor: (shape) => {
+                if (!CURSORS[shape]) return STATUS_INVALID;
+                this.cursorShape = shape;
+                this.updateCursor();
+                return STATUS_OK;
+            },
+            set_relative_mouse: (enabled) => {
+                this.relativeMouse = enabled !== 0;
+                if (this.relativeMouse) {
+                    this.canvas.requestPointerLock()?.catch?.(() => {});
+                } else if (document.pointerLockElement === this.canvas) {
+                    document.exitPointerLock();
+                }
+            },
+            push_audio: (ptr, frames, channels, sampleRate) => this.pushAudio(ptr, frames, channels, sampleRate),
+            get_audio_queued_frames: () => {
+                if (!this.audio) return 0;
+                const queued = Math.max(0, this.audioEnd - this.audio.currentTime);
+                return Math.floor(queued * this.audio.sampleRate);
+            },
+            launch: () => LAUNCH_DENIED,
+            // A page runs a single app, so there is no one to message
+            bus_subscribe: () => BUS_DENIED,
+            bus_send: () => BUS_DENIED,
+            get_string: (keyPtr, keyLen, bufPtr, bufCap) => {
+                const value = (this.metadata.strings ?? {})[this.readString(keyPtr, keyLen)];
+                return value === undefined ? NOT_FOUND : this.writeBytes(bufPtr, bufCap, encoder.encode(value));
+            },
+            // The page's query string stands in for --guest-arg
+            config_get: (keyPtr, keyLen, outPtr, cap) => {
+                const value = this.config.get(this.readString(keyPtr, keyLen));
+                return value === undefined ? NOT_FOUND : this.writeBytes(outPtr, cap, encoder.encode(value));
+            },
+            asset_size: (namePtr, nameLen) => this.assets.get(this.readString(namePtr, nameLen))?.byteLength ?? NOT_FOUND,
+            asset_read: (namePtr, nameLen, bufPtr, bufCap) => {
+                const data = this.assets.get(this.readString(namePtr, nameLen));
+                return data === undefined ? NOT_FOUND : this.writeBytes(bufPtr, bufCap, data);
+            },
+            // Seconds since the page loaded at which the current event happened
+            event_time: () => this.eventTime,
+            set_timer: (id, seconds, repeating) => this.setTimer(id, seconds, repeating !== 0),
+            cancel_timer: (id) => this.timers.delete(id) ? TIMER_OK : TIMER_NOT_FOUND,
+            now_micros: () => BigInt(Math.floor(performance.now() * 1000)),
+            // Browsers already stop animation frames for hidden tabs
+            request_redraw: () => {},
+            request_update_in: () => {},
+            query_key_state: (scancode) => this.heldKeys.has(scancode) ? 1 : 0,
+            app_name: (bufPtr, bufCap) => this.writeBytes(bufPtr, bufCap, encoder.encode(this.metadata.name ?? '')),
+            app_version: (bufPtr, bufCap) => this.writeBytes(bufPtr, bufCap, encoder.encode(this.metadata.version ?? '')),
+            get_locale: (outPtr, cap) => this.writeBytes(outPtr, cap, encoder.encode(navigator.language ?? '')),
+            get_prefers_dark: () => (matchMedia('(prefers-color-scheme: dark)').matches ? 1 : 0),
+            storage_get: (keyPtr, keyLen, outPtr, outCap) => {
+                const value = localStorage.getItem(this.storageKey(this.readString(keyPtr, keyLen)));
+                return value === null ? NOT_FOUND : this.writeBytes(outPtr, outCap, base64Decode(value));
+            },
+            storage_set: (keyPtr, keyLen, valuePtr, valueLen) => {
+                if (keyLen <= 0 || keyLen > 256 || !this.inBounds(valuePtr, valueLen)) {
+                    return STATUS_INVALID;
+                }
+                const key = this.readString(keyPtr, keyLen);
+                if (key.startsWith('wapps:')) {
+                    return STATUS_INVALID;
+                }
+                if (this.storageUsed(key) + valueLen > STORAGE_QUOTA) {
+                    return STORAGE_QUOTA_EXCEEDED;
+                }
+                try {
+                    localStorage.setItem(this.storageKey(key), base64Encode(this.bytes(valuePtr, valueLen)));
+                } catch (e) {
+                    console.warn("storage_set: " + e.message);
+                    return STORAGE_IO_ERROR;
+                }
+                return STATUS_OK;
+            },
+            report_allocations: () => {},
+            http_fetch: (methodPtr, methodLen, urlPtr, urlLen, bodyPtr, bodyLen) =>
+                this.httpFetch(this.readString(methodPtr, methodLen), this.readString(urlPtr, urlLen), bodyPtr, bodyLen),
+            http_poll: (handle) => this.httpRequests.get(handle)?.status ?? NOT_FOUND,
+            http_read: (handle, bufPtr, bufCap) => {
+                const body = this.httpRequests.get(handle)?.body;
+                return body ? this.writeBytes(bufPtr, bufCap, body) : NOT_FOUND;
+            },
+            http_close: (handle) => {
+                this.httpRequests.delete(handle);
+            },
+            ws_connect: (urlPtr, urlLen) => this.wsConnect(this.readString(urlPtr, urlLen)),
+            ws_send: (handle, ptr, len) => {
+                const socket = this.webSockets.get(handle);
+                if (!socket) return NOT_FOUND;
+                if (!this.inBounds(ptr, len) || len > WS_MAX_MESSAGE) return WS_INVALID;
+                if (socket.closed) return WS_CLOSED;
+                const bytes = this.bytes(ptr, len).slice();
+                let message;
+                try {
+                    message = new TextDecoder('utf-8', { fatal: true }).decode(bytes);
+                } catch {
+                    message = bytes;
+                }
+                if (socket.ws.readyState === WebSocket.CONNECTING) {
+                    socket.pending.push(message);
+                } else {
+                    socket.ws.send(message);
+                }
+                return STATUS_OK;
+            },
+            ws_status: (handle) => {
+                const socket = this.webSockets.get(handle);
+                if (!socket) return NOT_FOUND;
+                if (socket.closed) return WS_CLOSED;
+                return socket.ws.readyState === WebSocket.OPEN ? WS_OPEN : WS_CONNECTING;
+            },
+            ws_close: (handle) => {
+                this.webSockets.get(handle)?.ws.close();
+                this.webSockets.delete(handle);
+            },
+            log: (level, ptr, len) => {
+                const line = `[${this.metadata.name ?? 'app'}] ${this.readString(ptr, len)}`;
+                console[LOG_METHODS[Math.min(Math.max(level, 1), 5) - 1]](line);
+            },
+            request_snapshot: () => SNAPSHOT_DENIED,
+            request_open_file: (filterPtr, filterLen) => {
+                if (!this.inBounds(filterPtr, filterLen)) return FILE_DIALOG_INVALID;
+                this.openFile(this.readString(filterPtr, filterLen));
+                return FILE_DIALOG_OK;
+            },
+            request_save_file: (namePtr, nameLen, dataPtr, dataLen) => {
+                if (!this.inBounds(namePtr, nameLen) || !this.inBounds(dataPtr, dataLen) || dataLen > MAX_FILE_SIZE) {
+                    return FILE_DIALOG_INVALID;
+                }
+                const name = this.readString(namePtr, nameLen);
+                const url = URL.createObjectURL(new Blob([this.bytes(dataPtr, dataLen).slice()]));
+                const link = Object.assign(document.createElement('a'), { href: url, download: name });
+                link.click();
+                setTimeout(() => URL.revokeObjectURL(url));
+                // Downloads leave the choice of folder to the browser
+                setTimeout(() => this.instance?.exports.on_file_saved?.(FILE_SAVED));
+                return FILE_DIALOG_OK;
+            },
+            request_restore: () => SNAPSHOT_DENIED,
+            gamepad_rumble: (pad, low, high, durationMs) => {
+                const gamepad = navigator.getGamepads?.().filter(Boolean)[pad];
+                const strength = (value) => Math.min(Math.max(value, 0), 0xffff) / 0xffff;
+                gamepad?.vibrationActuator?.playEffect('dual-rumble', {
+                    duration: Math.min(Math.max(durationMs, 0), MAX_RUMBLE_MS),
+                    strongMagnitude: strength(low),
+                    weakMagnitude: strength(high),
+                }).catch(() => {});
+            },
+            // Browsers cannot set controller LEDs
+            gamepad_set_led: () => {},
+            audio_capture_start: (sampleRate, channels) => this.audioCaptureStart(sampleRate, channels),
+            audio_capture_read: (ptr, maxFrames) => this.audioCaptureRead(ptr, maxFrames),
+            audio_capture_stop: () => this.audioCaptureStop(),
+        };
+    }
+
+    // Show a frame from guest memory, returning a wapps3 status
+    presentFrame(width, height, ptr, format) {
+        const bytesPerPixel = BYTES_PER_PIXEL[format];
+        if (!bytesPerPixel) {
+            console.warn(`Unknown pixel format ${format}`);
+            return UNSUPPORTED;
+        }
+        if (width <= 0 || height <= 0) {
+            console.warn(`Invalid ${width}x${height} frame`);
+            return INVALID_DIMENSIONS;
+        }
+        if (!this.inBounds(ptr, width * height * bytesPerPixel)) {
+            console.warn(`${width}x${height} frame out of bounds`);
+            return OUT_OF_BOUNDS;
+        }
+        this.frameBufferPtr = ptr;
+        this.width = width;
+        this.height = height;
+        this.format = format;
+        return STATUS_OK;
+    }
+
+    // Keep a copy of a HUD from guest memory, or remove it when 0x0,
+    // returning a wapps3 status
+    updateOverlay(width, height, ptr) {
+        if (width === 0 && height === 0) {
+            this.hud = null;
+            return STATUS_OK;
+        }
+        if (width <= 0 || height <= 0 || width > MAX_OVERLAY_SIDE || height > MAX_OVERLAY_SIDE) {
+            return INVALID_DIMENSIONS;
+        }
+        if (!this.inBounds(ptr, width * height * 4)) return OUT_OF_BOUNDS;
+        if (!this.hud || this.hud.width !== width || this.hud.height !== height) {
+            this.hud = document.createElement('canvas');
+            this.hud.width = width;
+            this.hud.height = height;
+        }
+        const pixels = new Uint8ClampedArray(this.bytes(ptr, width * height * 4));
+        this.hud.getContext('2d').putImageData(new ImageData(pixels, width, height), 0, 0);
+        return STATUS_OK;
+    }
+
+    // Set the colors of indexed frames, returning a wapps3 status
+    setPalette(ptr, count) {
+        if (count < 0 || count > 256) return INVALID_DIMENSIONS;
+        if (!this.inBounds(ptr, count * 4)) return OUT_OF_BOUNDS;
+        this.palette.set(this.bytes(ptr, count * 4));
+        return STATUS_OK;
+    }
+
+    // Present a frame filled with one color, returning a wapps3 status
+    clearCanvas(width, height, rgba) {
+        if (width <= 0 || height <= 0) return INVALID_DIMENSIONS;
+        this.resizeCanvas(width, height);
+        this.ctx.fillStyle = cssColor(rgba);
+        this.ctx.fillRect(0, 0, width, height);
+        this.frameBufferPtr = 0;
+        return STATUS_OK;
+    }
+
+    pushAudio(ptr, frames, channels, sampleRate) {
+        if ((channels !== 1 && channels !== 2) || sampleRate < 8000 || sampleRate > 192000
+            || frames < 0 || !this.inBounds(ptr, frames * channels * 4)) {
+            return STATUS_INVALID;
+        }
+        if (frames === 0) return STATUS_OK;
+        if (!this.audio) {
+            this.audio = new AudioContext();
+        }
+        const samples = new Float32Array(this.memory.buffer.slice(ptr, ptr + frames * channels * 4));
+        const buffer = this.audio.createBuffer(channels, frames, sampleRate);
+        for (let channel = 0; channel < channels; channel++) {
+            const data = buffer.getChannelData(channel);
+            for (let i = 0; i < frames; i++) {
+                data[i] = samples[i * channels + channel];
+            }
+        }
+        // Queue the samples right after the ones already pushed
+        const source = this.audio.createBufferSource();
+        source.buffer = buffer;
+        source.connect(this.audio.destination);
+        const start = Math.max(this.audioEnd, this.audio.currentTime);
+        source.start(start);
+        this.audioEnd = start + buffer.duration;
+        return STATUS_OK;
+    }
+
+    // Record the microphone once the user allows it in the browser's prompt,
+    // keeping the last second of interleaved samples
+    audioCaptureStart(sampleRate, channels) {
+        if ((channels !== 1 && channels !== 2) || sampleRate < 8000 || sampleRate > 192000) {
+            return STATUS_INVALID;
+        }
+        this.audioCaptureStop();
+        const capture = { channels, samples: [], max: sampleRate * channels, stop: null };
+        this.capture = capture;
+        navigator.mediaDevices.getUserMedia({ audio: true }).then((stream) => {
+            if (this.capture !== capture) {
+                stream.getTracks().forEach((track) => track.stop());
+                return;
+            }
+            const context = new AudioContext({ sampleRate });
+            const source = context.createMediaStreamSource(stream);
+            const processor = context.createScriptProcessor(AUDIO_CAPTURE_CHUNK, channels, 1);
+            processor.onaudioprocess = (event) => {
+                const input = event.inputBuffer;
+                const data = [];
+                for (let channel = 0; channel < channels; channel++) {
+                    data.push(input.getChannelData(Math.min(channel, input.numberOfChannels - 1)));
+                }
+                for (let i = 0; i < input.length; i++) {
+                    for (const channelData of data) capture.samples.push(channelData[i]);
+                }
+                const excess = capture.samples.length - capture.max;
+                if (excess > 0) capture.samples.splice(0, excess + (excess % channels));
+            };
+            source.connect(processor);
+            // Processors only run while connected to the output; this one outputs silence
+            processor.connect(context.destination);
+            capture.stop = () => {
+                stream.getTracks().forEach((track) => track.stop());
+                context.close();
+            };
+        }).catch((e) => console.warn(`audio_capture_start: ${e.message}`));
+        return AUDIO_CAPTURE_OK;
+    }
+
+    // Move up to maxFrames captured frames into guest memory
+    audioCaptureRead(ptr, maxFrames) {
+        if (!this.capture || maxFrames <= 0) return 0;
+        const { channels, samples } = this.capture;
+        const frames = Math.min(maxFrames, Math.floor(samples.length / channels));
+        if (!this.inBounds(ptr, frames * channels * 4)) return STATUS_INVALID;
+        const view = new DataView(this.memory.buffer);
+        samples.splice(0, frames * channels).forEach((sample, i) => {
+            view.setFloat32(ptr + i * 4, sample, true);
+        });
+        return frames;
+    }
+
+    audioCaptureStop() {
+        this.capture?.stop?.();
+        this.capture = null;
+    }
+
+    // Browsers keep audio suspended until the page gets a user gesture
+    resumeAudio() {
+        if (this.audio?.state === 'suspended') {
+            this.audio.resume();
+        }
+    }
+
+    setTimer(id, seconds, repeating) {
+        if (!Number.isFinite(seconds) || seconds < 0 || (repeating && seconds === 0)) {
+            return TIMER_INVALID;
+        }
+        if (this.timers.size >= MAX_TIMERS && !this.timers.has(id)) {
+            return TIMER_TOO_MANY;
+        }
+        this.timers.set(id, { remaining: seconds, period: repeating ? seconds : null });
+        return TIMER_OK;
+    }
+
+    // Advance timers by `dt` and call on_timer for those that fired, earliest
+    // first; repeating timers fire at most once per frame, as on the native host
+    fireTimers(dt) {
+        const fired = [];
+        for (const [id, timer] of this.timers) {
+            timer.remaining -= dt;
+            if (timer.remaining > 0) continue;
+            fired.push([timer.remaining, id]);
+            if (timer.period === null) {
+                this.timers.delete(id);
+            } else {
+                timer.remaining = timer.period - (-timer.remaining % timer.period);
+            }
+        }
+        fired.sort((a, b) => a[0] - b[0] || a[1] - b[1]);
+        for (const [, id] of fired) {
+            this.instance?.exports.on_timer?.(id);
+        }
+    }
+
+    // Let the running guest clean up; it is not called again afterwards
+    shutdown() {
+        this.instance?.exports.shutdown?.();
+        this.instance = null;
+        this.hud = null;
+        this.audioCaptureStop();
+    }
+
     start() {
+        if (this.running) return;
+        this.running = true;
         let lastTime = performance.now();
         const loop = (time) => {
             const dt = (time - lastTime) / 1000;
             lastTime = time;
 
             if (this.instance) {
+                this.fireTimers(dt);
                 // Call WAPP update
-                this.instance.exports.update(dt);
+                this.instance?.exports.update?.(dt);
                 this.render();
             }
             requestAnimationFrame(loop);
@@ -104,29 +634,269 @@ export class WappRuntime {
     render() {
         if (!this.frameBufferPtr || !this.width || !this.height) return;
 
-        const size = this.width * this.height * 4;
+        let pixels;
+        if (this.format) {
+            pixels = this.convertFrame();
+        } else {
+            const size = this.width * this.height * 4;
 
-        // Cache view to avoid allocation if memory hasn't grown/moved
-        if (!this.pixelsView || 
-            this.pixelsView.buffer !== this.memory.buffer || 
-            this.pixelsView.byteOffset !== this.frameBufferPtr ||
-            this.pixelsView.byteLength !== size) {
-            
-            this.pixelsView = new Uint8ClampedArray(this.memory.buffer, this.frameBufferPtr, size);
-        }
-        
-        // We might need to scale the canvas or just put image data
-        if (this.canvas.width !== this.width || this.canvas.height !== this.height) {
-            this.canvas.width = this.width;
-            this.canvas.height = this.height;
+            // Cache view to avoid allocation if memory hasn't grown/moved
+            if (!this.pixelsView || 
+                this.pixelsView.buffer !== this.memory.buffer || 
+                this.pixelsView.byteOffset !== this.frameBufferPtr ||
+                this.pixelsView.byteLength !== size) {
+                
+                this.pixelsView = new Uint8ClampedArray(this.memory.buffer, this.frameBufferPtr, size);
+            }
+            pixels = this.pixelsView;
         }
 
-        const imageData = new ImageData(this.pixelsView, this.width, this.height);
+        this.resizeCanvas(this.width, this.height);
+        const imageData = new ImageData(pixels, this.width, this.height);
         this.ctx.putImageData(imageData, 0, 0);
+        // The canvas has the frame's size, so the HUD is scaled down to it
+        // rather than kept at the window's resolution
+        if (this.hud) {
+            this.ctx.drawImage(this.hud, 0, 0, this.width, this.height);
+        }
+        this.frameCount++;
+    }
+
+    // Expand the current frame to RGBA, like the native host's PixelFormat::to_rgba
+    convertFrame() {
+        const count = this.width * this.height;
+        if (!this.converted || this.converted.length !== count * 4) {
+            this.converted = new Uint8ClampedArray(count * 4);
+        }
+        const src = this.bytes(this.frameBufferPtr, count * BYTES_PER_PIXEL[this.format]);
+        const out = this.converted;
+        for (let i = 0; i < count; i++) {
+            let r, g, b, a = 255;
+            switch (this.format) {
+                case 1: // RGB24
+                    [r, g, b] = [src[i * 3], src[i * 3 + 1], src[i * 3 + 2]];
+                    break;
+                case 2: // BGRA32
+                    [b, g, r, a] = [src[i * 4], src[i * 4 + 1], src[i * 4 + 2], src[i * 4 + 3]];
+                    break;
+                case 3: { // RGB565, little-endian
+                    const value = src[i * 2] | (src[i * 2 + 1] << 8);
+                    const [r5, g6, b5] = [value >> 11, (value >> 5) & 0x3f, value & 0x1f];
+                    [r, g, b] = [(r5 << 3) | (r5 >> 2), (g6 << 2) | (g6 >> 4), (b5 << 3) | (b5 >> 2)];
+                    break;
+                }
+                case 4: // Gray8
+                    r = g = b = src[i];
+                    break;
+                case 5: // Indexed8
+                    [r, g, b, a] = this.palette.subarray(src[i] * 4, src[i] * 4 + 4);
+                    break;
+            }
+            out[i * 4] = r;
+            out[i * 4 + 1] = g;
+            out[i * 4 + 2] = b;
+            out[i * 4 + 3] = a;
+        }
+        return out;
+    }
+
+    updateCursor() {
+        this.canvas.style.cursor = this.cursorHidden ? 'none' : CURSORS[this.cursorShape];
+    }
+
+    resizeCanvas(width, height) {
+        if (this.canvas.width !== width || this.canvas.height !== height) {
+            this.canvas.width = width;
+            this.canvas.height = height;
+        }
+    }
+
+    // Guest memory access
+
+    inBounds(ptr, len) {
+        return ptr >= 0 && len >= 0 && (ptr >>> 0) + len <= this.memory.buffer.byteLength;
+    }
+
+    bytes(ptr, len) {
+        return new Uint8Array(this.memory.buffer, ptr >>> 0, len);
+    }
+
+    readString(ptr, len) {
+        return this.inBounds(ptr, len) ? new TextDecoder().decode(this.bytes(ptr, len)) : '';
+    }
+
+    // Write up to `cap` bytes of `value`, returning its full length
+    writeBytes(ptr, cap, value) {
+        const len = Math.min(value.length, Math.max(cap, 0));
+        if (!this.inBounds(ptr, len)) {
+            return STATUS_INVALID;
+        }
+        this.bytes(ptr, len).set(value.subarray(0, len));
+        return value.length;
+    }
+
+    // Copy `text`, or raw bytes, into memory from the guest's wapps_alloc, or
+    // null without one
+    copyToGuest(text) {
+        const { wapps_alloc } = this.instance.exports;
+        if (!wapps_alloc) return null;
+        const bytes = typeof text === 'string' ? encoder.encode(text) : text;
+        const ptr = wapps_alloc(bytes.length);
+        // 0 means the guest is out of memory
+        if (ptr === 0 || !this.inBounds(ptr, bytes.length)) return null;
+        this.bytes(ptr, bytes.length).set(bytes);
+        return [ptr, bytes.length];
+    }
+
+    // Start a request to a host in the manifest's allowed_hosts, returning its
+    // handle or an error; redirects are not followed
+    httpFetch(method, url, bodyPtr, bodyLen) {
+        if (!/^[A-Z]+$/.test(method) || !this.inBounds(bodyPtr, bodyLen) || bodyLen > HTTP_MAX_BODY) {
+            return STATUS_INVALID;
+        }
+        let host;
+        try {
+            const parsed = new URL(url);
+            if ((parsed.protocol !== 'http:' && parsed.protocol !== 'https:') || parsed.username) {
+                return STATUS_INVALID;
+            }
+            host = parsed.hostname.replace(/^\[|\]$/g, '');
+        } catch {
+            return STATUS_INVALID;
+        }
+        if (!hostAllowed(this.metadata.allowed_hosts ?? [], host)) {
+            console.warn(`http_fetch: ${host} is not in the manifest's allowed_hosts`);
+            return HTTP_HOST_DENIED;
+        }
+        if (this.httpRequests.size >= HTTP_MAX_REQUESTS) {
+            return HTTP_TOO_MANY;
+        }
+
+        const handle = ++this.httpLastHandle;
+        const request = { status: HTTP_PENDING, body: null };
+        this.httpRequests.set(handle, request);
+        const body = bodyLen > 0 ? this.bytes(bodyPtr, bodyLen).slice() : undefined;
+        fetch(url, { method, body, redirect: 'manual' })
+            .then(async (response) => {
+                // Redirects come back opaque, without a status
+                if (response.type === 'opaqueredirect') throw new Error('redirects are not followed');
+                const data = new Uint8Array(await response.arrayBuffer());
+                if (data.length > HTTP_MAX_BODY) throw new Error(`response over ${HTTP_MAX_BODY} bytes`);
+                request.body = data;
+                request.status = response.status;
+            })
+            .catch((e) => {
+                console.debug(`http_fetch: ${method} ${url} failed: ${e.message}`);
+                request.status = HTTP_FAILED;
+            });
+        return handle;
+    }
+
+    // Let the user pick a file with one of the comma-separated extensions of
+    // `filter`, and pass its contents to on_file_opened; browsers only show
+    // the picker while handling a click or key press
+    openFile(filter) {
+        const extensions = filter.split(',').map((e) => e.trim().replace(/^\./, '')).filter(Boolean);
+        const input = Object.assign(document.createElement('input'), {
+            type: 'file',
+            accept: extensions.map((e) => `.${e}`).join(','),
+        });
+        const deliver = (data) => {
+            const { on_file_opened, wapps_free } = this.instance?.exports ?? {};
+            if (!on_file_opened) return;
+            const copied = data && this.copyToGuest(data);
+            if (!copied) {
+                on_file_opened(0, FILE_CANCELLED);
+                return;
+            }
+            on_file_opened(...copied);
+            wapps_free?.(...copied);
+        };
+        input.addEventListener('cancel', () => deliver(null));
+        input.addEventListener('change', async () => {
+            const file = input.files[0];
+            if (!file || file.size > MAX_FILE_SIZE) {
+                deliver(null);
+                return;
+            }
+            deliver(new Uint8Array(await file.arrayBuffer()));
+        });
+        input.click();
+    }
+
+    // Open a WebSocket to a host in the manifest's allowed_hosts, returning its
+    // handle or an error; messages go to on_ws_message as they arrive
+    wsConnect(url) {
+        let host;
+        try {
+            const parsed = new URL(url);
+            if ((parsed.protocol !== 'ws:' && parsed.protocol !== 'wss:') || parsed.username) {
+                return WS_INVALID;
+            }
+            host = parsed.hostname.replace(/^\[|\]$/g, '');
+        } catch {
+            return WS_INVALID;
+        }
+        if (!hostAllowed(this.metadata.allowed_hosts ?? [], host)) {
+            console.warn(`ws_connect: ${host} is not in the manifest's allowed_hosts`);
+            return WS_HOST_DENIED;
+        }
+        if (this.webSockets.size >= WS_MAX_CONNECTIONS) {
+            return WS_TOO_MANY;
+        }
+
+        const handle = ++this.wsLastHandle;
+        const ws = new WebSocket(url);
+        ws.binaryType = 'arraybuffer';
+        const socket = { ws, pending: [], closed: false };
+        this.webSockets.set(handle, socket);
+        ws.onopen = () => {
+            for (const message of socket.pending) ws.send(message);
+            socket.pending = [];
+        };
+        ws.onmessage = (event) => {
+            const data = typeof event.data === 'string' ? encoder.encode(event.data) : new Uint8Array(event.data);
+            const { on_ws_message, wapps_free } = this.instance?.exports ?? {};
+            if (!on_ws_message || data.length > WS_MAX_MESSAGE || !this.webSockets.has(handle)) return;
+            const copied = this.copyToGuest(data);
+            if (!copied) return;
+            on_ws_message(handle, ...copied);
+            wapps_free?.(...copied);
+        };
+        ws.onclose = () => {
+            socket.closed = true;
+        };
+        return handle;
+    }
+
+    // Storage lives in localStorage, one entry per key, namespaced by app name
+
+    storageKey(key) {
+        return `wapps:${this.metadata.name ?? ''}:${key}`;
+    }
+
+    // Bytes stored by the app, not counting the value under `except`
+    storageUsed(except) {
+        const prefix = this.storageKey('');
+        let used = 0;
+        for (let i = 0; i < localStorage.length; i++) {
+            const key = localStorage.key(i);
+            if (key.startsWith(prefix) && key !== this.storageKey(except)) {
+                used += Math.floor(localStorage.getItem(key).length * 3 / 4);
+            }
+        }
+        return used;
     }
 
     // Input Handling
+
+    // Stamp the events that follow with a DOM event's timeStamp, in milliseconds
+    setEventTime(timeStamp) {
+        this.eventTime = timeStamp / 1000;
+    }
+
     handleMouseDown(x, y, button) {
+        this.resumeAudio();
         if (this.instance?.exports.on_pointer_down) {
             this.instance.exports.on_pointer_down(x, y, button);
         }
@@ -138,21 +908,154 @@ export class WappRuntime {
         }
     }
 
-    handleMouseMove(x, y) {
+    // Guests with the older on_pointer_move(x, y) ignore the motion
+    handleMouseMove(x, y, xrel = 0, yrel = 0) {
         if (this.instance?.exports.on_pointer_move) {
-            this.instance.exports.on_pointer_move(x, y);
+            this.instance.exports.on_pointer_move(x, y, xrel, yrel);
+        }
+    }
+
+    // Touches go to on_touch_down, on_touch_move or on_touch_up; browsers
+    // also send mouse events for taps, like SDL does
+    handleTouch(phase, id, x, y, pressure) {
+        if (phase === 'down') this.resumeAudio();
+        this.instance?.exports[`on_touch_${phase}`]?.(BigInt(id), x, y, pressure);
+    }
+
+    handleScroll(dx, dy) {
+        const exports = this.instance?.exports;
+        if (exports?.on_scroll_precise) {
+            exports.on_scroll_precise(dx, dy);
+        } else if (exports?.on_scroll && (Math.trunc(dx) || Math.trunc(dy))) {
+            exports.on_scroll(Math.trunc(dx), Math.trunc(dy));
         }
     }
 
-    handleKeyDown(code) {
+    // Guests with the older on_key_down(scancode) ignore the extra arguments
+    handleKeyDown(code, modifiers = 0, repeat = false) {
+        this.resumeAudio();
+        this.heldKeys.add(code);
         if (this.instance?.exports.on_key_down) {
-            this.instance.exports.on_key_down(code);
+            this.instance.exports.on_key_down(code, modifiers, repeat ? 1 : 0);
         }
     }
 
     handleKeyUp(code) {
+        this.heldKeys.delete(code);
         if (this.instance?.exports.on_key_up) {
             this.instance.exports.on_key_up(code);
         }
     }
+
+    handleTextInput(text) {
+        const { on_text_input, wapps_free } = this.instance?.exports ?? {};
+        if (!on_text_input) return;
+        const copied = this.copyToGuest(text);
+        if (!copied) return;
+        on_text_input(...copied);
+        wapps_free?.(...copied);
+    }
+
+    // Whether the guest takes files dropped on the canvas
+    acceptsDroppedFiles() {
+        return Boolean(this.instance?.exports.on_file_dropped);
+    }
+
+    async handleFileDropped(file) {
+        if (file.size > MAX_FILE_SIZE) {
+            console.warn(`Dropped file ${file.name} is larger than ${MAX_FILE_SIZE} bytes`);
+            return;
+        }
+        const data = new Uint8Array(await file.arrayBuffer());
+        const { on_file_dropped, wapps_free } = this.instance?.exports ?? {};
+        if (!on_file_dropped) return;
+        const name = this.copyToGuest(file.name);
+        if (!name) return;
+        const copied = this.copyToGuest(data);
+        if (!copied) {
+            wapps_free?.(...name);
+            return;
+        }
+        on_file_dropped(...name, ...copied);
+        wapps_free?.(...copied);
+        wapps_free?.(...name);
+    }
+
+    handleResize(width, height) {
+        this.viewport = [width, height];
+        if (this.instance?.exports.on_resize) {
+            this.instance.exports.on_resize(width, height);
+        }
+    }
+
+    handleScaleChange(scale) {
+        this.instance?.exports.on_scale_changed?.(scale);
+    }
+
+    handleFocus(focused) {
+        this.instance?.exports[focused ? 'on_focus' : 'on_blur']?.();
+    }
+
+    handleVisibility(visible) {
+        this.instance?.exports.on_visibility?.(visible ? 1 : 0);
+    }
+
+    // Whether the guest lets the page close; browsers only let it ask the user
+    handleCloseRequest() {
+        return !this.instance?.exports.on_close_request?.();
+    }
+}
+
+const encoder = new TextEncoder();
+
+// Provide every import the module needs that `imports` lacks, so packages
+// using host features the browser can't offer still run; the stubs are
+// listed when the package loads, and each warns again on its first call and
+// returns 0
+function stubMissingImports(module, imports) {
+    const stubbed = [];
+    for (const { module: name, name: field, kind } of WebAssembly.Module.imports(module)) {
+        if (kind !== 'function' || imports[name]?.[field]) continue;
+        imports[name] ??= {};
+        stubbed.push(`${name}::${field}`);
+        let warned = false;
+        imports[name][field] = () => {
+            if (!warned) {
+                console.warn(`${name}::${field} is not supported in the browser`);
+                warned = true;
+            }
+            return 0;
+        };
+    }
+    if (stubbed.length) {
+        console.warn(`Stubbing imports the browser does not provide: ${stubbed.join(', ')}`);
+    }
+}
+
+// Whether `host` is in `allowed`, exactly or as a subdomain of a `*.` entry
+function hostAllowed(allowed, host) {
+    host = host.toLowerCase();
+    return allowed.some((entry) => {
+        entry = entry.toLowerCase();
+        return entry.startsWith('*.') ? host.endsWith(entry.slice(1)) && host.length > entry.length - 1 : host === entry;
+    });
+}
+
+// CSS color of a 0xRRGGBBAA value
+function cssColor(rgba) {
+    const value = rgba >>> 0;
+    const [r, g, b, a] = [value >>> 24, (value >> 16) & 0xff, (value >> 8) & 0xff, value & 0xff];
+    return `rgba(${r}, ${g}, ${b}, ${a / 255})`;
+}
+
+function base64Encode(bytes) {
+    let binary = '';
+    for (const byte of bytes) binary += String.fromCharCode(byte);
+    return btoa(binary);
 }
+
+function base64Decode(text) {
+    return Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+}
+
+export { MOD_SHIFT, MOD_CTRL, MOD_ALT, MOD_SUPER };
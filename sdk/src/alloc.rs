@@ -0,0 +1,94 @@
+//! Allocation Statistics
+//!
+//! The host only sees how large linear memory grew, which never shrinks. An
+//! app that installs [`CountingAllocator`] as its global allocator can report
+//! the bytes it actually has in use:
+//!
+//! ```no_run
+//! #[global_allocator]
+//! static ALLOCATOR: wapps_sdk::CountingAllocator = wapps_sdk::CountingAllocator;
+//!
+//! // At the end of `App::update`:
+//! wapps_sdk::CountingAllocator::report();
+//! ```
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::host;
+
+/// Bytes allocated through [`CountingAllocator`] and not yet freed
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether [`CountingAllocator`] has allocated anything
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The system allocator, counting the bytes in use
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    /// Bytes allocated and not yet freed, or `None` if this is not the global allocator
+    pub fn bytes_in_use() -> Option<usize> {
+        ACTIVE
+            .load(Ordering::Relaxed)
+            .then(|| IN_USE.load(Ordering::Relaxed))
+    }
+
+    /// Send [`bytes_in_use`](Self::bytes_in_use) to the host, e.g. once per `update`
+    pub fn report() {
+        if let Some(bytes) = Self::bytes_in_use() {
+            host::report_allocations(bytes);
+        }
+    }
+
+    fn allocated(ptr: *mut u8, size: usize) -> *mut u8 {
+        if !ptr.is_null() {
+            IN_USE.fetch_add(size, Ordering::Relaxed);
+            ACTIVE.store(true, Ordering::Relaxed);
+        }
+        ptr
+    }
+}
+
+// SAFETY: every call is forwarded to the system allocator unchanged
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::allocated(System.alloc(layout), layout.size())
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::allocated(System.alloc_zeroed(layout), layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = Self::allocated(System.realloc(ptr, layout, new_size), new_size);
+        if !new_ptr.is_null() {
+            IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_bytes_in_use() {
+        let allocator = CountingAllocator;
+        let layout = Layout::array::<u8>(100).unwrap();
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            assert_eq!(CountingAllocator::bytes_in_use(), Some(100));
+            let ptr = allocator.realloc(ptr, layout, 300);
+            assert_eq!(CountingAllocator::bytes_in_use(), Some(300));
+            allocator.dealloc(ptr, Layout::array::<u8>(300).unwrap());
+        }
+        assert_eq!(CountingAllocator::bytes_in_use(), Some(0));
+    }
+}
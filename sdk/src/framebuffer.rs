@@ -0,0 +1,157 @@
+//! Framebuffer
+//!
+//! An RGBA pixel buffer owned by the guest and presented to the host with
+//! `wapps::update_frame`, or as one of several layers with `wapps::update_layer`.
+//! A framebuffer returned by `App::shared_framebuffer` is read by the host in
+//! place when presented.
+
+use crate::host;
+
+/// An RGBA color
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const TRANSPARENT: Color = Color::rgba(0, 0, 0, 0);
+
+    /// An opaque color
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 255 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+}
+
+/// A `width` x `height` RGBA pixel buffer, row-major
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    /// Create a framebuffer cleared to transparent black
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Raw RGBA bytes
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Raw RGBA bytes, for drawing code that writes rows directly
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+
+    /// Resize, clearing the contents
+    pub fn resize(&mut self, width: u32, height: u32) {
+        *self = Self::new(width, height);
+    }
+
+    /// Fill the whole buffer with `color`
+    pub fn clear(&mut self, color: Color) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+    }
+
+    /// Color at (`x`, `y`), or `None` outside the buffer
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Color> {
+        let i = self.index(x, y)?;
+        let p = &self.pixels[i..i + 4];
+        Some(Color::rgba(p[0], p[1], p[2], p[3]))
+    }
+
+    /// Set the pixel at (`x`, `y`); coordinates outside the buffer are ignored
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i..i + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+        }
+    }
+
+    /// Fill a rectangle, clipped to the buffer
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        let x0 = x.clamp(0, self.width as i32);
+        let y0 = y.clamp(0, self.height as i32);
+        let x1 = x.saturating_add_unsigned(width).clamp(0, self.width as i32);
+        let y1 = y
+            .saturating_add_unsigned(height)
+            .clamp(0, self.height as i32);
+        for py in y0..y1 {
+            for px in x0..x1 {
+                self.set_pixel(px, py, color);
+            }
+        }
+    }
+
+    /// Send the buffer to the host for display
+    ///
+    /// The buffer always holds its width times its height in pixels, so the
+    /// host only rejects empty buffers, which have nothing to show.
+    pub fn present(&self) {
+        let _ = host::update_frame(self.width as i32, self.height as i32, &self.pixels);
+    }
+
+    /// Send the buffer to the host as layer `id`, drawn over lower layers at
+    /// `opacity`; an empty buffer removes the layer
+    pub fn present_layer(&self, id: i32, opacity: f32) {
+        let _ = host::update_layer(
+            id,
+            self.width as i32,
+            self.height as i32,
+            &self.pixels,
+            opacity,
+        );
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let (x, y) = (u32::try_from(x).ok()?, u32::try_from(y).ok()?);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y as usize * self.width as usize + x as usize) * 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drawing_is_clipped() {
+        let mut fb = Framebuffer::new(4, 3);
+        fb.clear(Color::BLACK);
+        fb.fill_rect(-2, 1, 4, 10, Color::WHITE);
+        fb.set_pixel(4, 0, Color::WHITE);
+
+        assert_eq!(fb.pixel(0, 0), Some(Color::BLACK));
+        assert_eq!(fb.pixel(1, 2), Some(Color::WHITE));
+        assert_eq!(fb.pixel(2, 1), Some(Color::BLACK));
+        assert_eq!(fb.pixel(-1, 0), None);
+        assert_eq!(fb.pixels().len(), 4 * 3 * 4);
+    }
+}
@@ -0,0 +1,1582 @@
+//! Host Imports
+//!
+//! Safe wrappers around the `wapps` import module. Strings cross the boundary
+//! as UTF-8 (pointer, length) pairs; functions that return strings write into a
+//! guest buffer and report the full length, so the wrappers grow the buffer
+//! and retry when it was too small. Stored values are returned the same way.
+//! Drawing and fullscreen calls are imported from the `wapps3` revision of
+//! the module, which reports why the host rejected them as a [`HostError`].
+
+use crate::Color;
+
+pub use wapps_abi::Error as HostError;
+
+/// Raw `wapps` imports
+#[cfg(target_arch = "wasm32")]
+mod ffi {
+    /// Imports returning a `wapps_abi` status
+    #[link(wasm_import_module = "wapps3")]
+    extern "C" {
+        pub fn update_frame(width: i32, height: i32, pixels_ptr: *const u8) -> i32;
+        pub fn update_frame_ex(width: i32, height: i32, pixels_ptr: *const u8, format: i32) -> i32;
+        pub fn update_layer(
+            id: i32,
+            width: i32,
+            height: i32,
+            pixels_ptr: *const u8,
+            opacity: f32,
+        ) -> i32;
+        pub fn update_overlay(width: i32, height: i32, pixels_ptr: *const u8) -> i32;
+        pub fn set_palette(entries_ptr: *const u8, count: i32) -> i32;
+        pub fn create_image(pixels_ptr: *const u8, width: i32, height: i32) -> i32;
+        pub fn draw_image(id: i32, x: f32, y: f32, scale: f32, rotation: f32) -> i32;
+        pub fn clear_canvas(width: i32, height: i32, rgba: i32) -> i32;
+        pub fn draw_text(x: i32, y: i32, ptr: *const u8, len: i32, rgba: i32, size: i32) -> i32;
+        pub fn measure_text(
+            ptr: *const u8,
+            len: i32,
+            size: i32,
+            out_w_ptr: *mut i32,
+            out_h_ptr: *mut i32,
+        ) -> i32;
+        pub fn clear(rgba: i32) -> i32;
+        pub fn fill_rect(x: i32, y: i32, width: i32, height: i32, rgba: i32) -> i32;
+        pub fn blit(src_ptr: *const u8, width: i32, height: i32, x: i32, y: i32) -> i32;
+        pub fn set_fullscreen(mode: i32) -> i32;
+    }
+
+    #[link(wasm_import_module = "wapps")]
+    extern "C" {
+        pub fn set_aspect_ratio(width: i32, height: i32);
+        pub fn set_min_size(width: i32, height: i32);
+        pub fn set_clear_color(rgba: i32);
+        pub fn destroy_image(id: i32);
+        pub fn set_window_title(ptr: *const u8, len: i32) -> i32;
+        pub fn set_window_size(width: i32, height: i32, resizable: i32) -> i32;
+        pub fn get_window_size(out_w_ptr: *mut i32, out_h_ptr: *mut i32) -> i32;
+        pub fn get_display_refresh_rate() -> i32;
+        pub fn get_frame_count() -> i64;
+        pub fn set_cursor_visible(visible: i32);
+        pub fn set_cursor(shape: i32) -> i32;
+        pub fn set_relative_mouse(enabled: i32);
+        pub fn set_scaling_mode(mode: i32) -> i32;
+        pub fn set_display_adjustment(brightness: f32, contrast: f32, gamma: f32) -> i32;
+        pub fn push_audio(
+            samples_ptr: *const f32,
+            frames: i32,
+            channels: i32,
+            sample_rate: i32,
+        ) -> i32;
+        pub fn get_audio_queued_frames() -> i32;
+        pub fn launch(ptr: *const u8, len: i32) -> i32;
+        pub fn bus_subscribe(topic_ptr: *const u8, topic_len: i32) -> i32;
+        pub fn bus_send(
+            topic_ptr: *const u8,
+            topic_len: i32,
+            data_ptr: *const u8,
+            data_len: i32,
+        ) -> i32;
+        pub fn get_string(key_ptr: *const u8, key_len: i32, buf_ptr: *mut u8, buf_cap: i32) -> i32;
+        pub fn config_get(key_ptr: *const u8, key_len: i32, out_ptr: *mut u8, cap: i32) -> i32;
+        pub fn asset_size(name_ptr: *const u8, name_len: i32) -> i32;
+        pub fn asset_read(
+            name_ptr: *const u8,
+            name_len: i32,
+            buf_ptr: *mut u8,
+            buf_cap: i32,
+        ) -> i32;
+        pub fn event_time() -> f64;
+        pub fn request_frame_rate(fps: f64);
+        pub fn now_micros() -> i64;
+        pub fn set_timer(id: i32, seconds: f64, repeating: i32) -> i32;
+        pub fn cancel_timer(id: i32) -> i32;
+        pub fn request_redraw();
+        pub fn request_update_in(ms: i32);
+        pub fn query_key_state(scancode: i32) -> i32;
+        pub fn app_name(buf_ptr: *mut u8, buf_cap: i32) -> i32;
+        pub fn app_version(buf_ptr: *mut u8, buf_cap: i32) -> i32;
+        pub fn get_locale(out_ptr: *mut u8, cap: i32) -> i32;
+        pub fn get_prefers_dark() -> i32;
+        pub fn storage_get(key_ptr: *const u8, key_len: i32, out_ptr: *mut u8, out_cap: i32)
+            -> i32;
+        pub fn storage_set(
+            key_ptr: *const u8,
+            key_len: i32,
+            value_ptr: *const u8,
+            value_len: i32,
+        ) -> i32;
+        pub fn score_submit(value: i64, name_ptr: *const u8, name_len: i32) -> i32;
+        pub fn score_list(buf_ptr: *mut u8, buf_cap: i32) -> i32;
+        pub fn report_allocations(bytes_in_use: i64);
+        pub fn log(level: i32, ptr: *const u8, len: i32);
+        pub fn http_fetch(
+            method_ptr: *const u8,
+            method_len: i32,
+            url_ptr: *const u8,
+            url_len: i32,
+            body_ptr: *const u8,
+            body_len: i32,
+        ) -> i32;
+        pub fn http_poll(handle: i32) -> i32;
+        pub fn http_read(handle: i32, buf_ptr: *mut u8, buf_cap: i32) -> i32;
+        pub fn http_close(handle: i32);
+        pub fn ws_connect(url_ptr: *const u8, url_len: i32) -> i32;
+        pub fn ws_send(handle: i32, ptr: *const u8, len: i32) -> i32;
+        pub fn ws_status(handle: i32) -> i32;
+        pub fn ws_close(handle: i32);
+        pub fn request_snapshot() -> i32;
+        pub fn request_restore() -> i32;
+        pub fn request_open_file(filter_ptr: *const u8, filter_len: i32) -> i32;
+        pub fn request_save_file(
+            name_ptr: *const u8,
+            name_len: i32,
+            data_ptr: *const u8,
+            data_len: i32,
+        ) -> i32;
+        pub fn gamepad_rumble(pad: i32, low: i32, high: i32, duration_ms: i32);
+        pub fn gamepad_set_led(pad: i32, r: i32, g: i32, b: i32);
+        pub fn audio_capture_start(sample_rate: i32, channels: i32) -> i32;
+        pub fn audio_capture_read(out_ptr: *mut f32, max_frames: i32) -> i32;
+        pub fn audio_capture_stop();
+    }
+}
+
+/// Stand-ins for native builds, so guest code can be unit tested off the host
+#[cfg(not(target_arch = "wasm32"))]
+mod ffi {
+    pub unsafe fn update_frame(_width: i32, _height: i32, _pixels_ptr: *const u8) -> i32 {
+        0
+    }
+
+    pub unsafe fn update_frame_ex(_w: i32, _h: i32, _pixels: *const u8, _format: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn update_layer(
+        _id: i32,
+        _w: i32,
+        _h: i32,
+        _pixels: *const u8,
+        _opacity: f32,
+    ) -> i32 {
+        0
+    }
+
+    pub unsafe fn update_overlay(_width: i32, _height: i32, _pixels_ptr: *const u8) -> i32 {
+        0
+    }
+
+    pub unsafe fn set_aspect_ratio(_width: i32, _height: i32) {}
+
+    pub unsafe fn set_min_size(_width: i32, _height: i32) {}
+
+    pub unsafe fn set_clear_color(_rgba: i32) {}
+
+    pub unsafe fn set_palette(_entries_ptr: *const u8, _count: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn create_image(_pixels_ptr: *const u8, _width: i32, _height: i32) -> i32 {
+        1
+    }
+
+    pub unsafe fn destroy_image(_id: i32) {}
+
+    pub unsafe fn draw_image(_id: i32, _x: f32, _y: f32, _scale: f32, _rotation: f32) -> i32 {
+        0
+    }
+
+    pub unsafe fn clear_canvas(_width: i32, _height: i32, _rgba: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn draw_text(
+        _x: i32,
+        _y: i32,
+        _ptr: *const u8,
+        _len: i32,
+        _rgba: i32,
+        _size: i32,
+    ) -> i32 {
+        0
+    }
+
+    pub unsafe fn measure_text(
+        _ptr: *const u8,
+        _len: i32,
+        _size: i32,
+        _out_w_ptr: *mut i32,
+        _out_h_ptr: *mut i32,
+    ) -> i32 {
+        0
+    }
+
+    pub unsafe fn clear(_rgba: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn fill_rect(_x: i32, _y: i32, _width: i32, _height: i32, _rgba: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn blit(_src_ptr: *const u8, _width: i32, _height: i32, _x: i32, _y: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn set_fullscreen(_mode: i32) -> i32 {
+        wapps_abi::PERMISSION_DENIED
+    }
+
+    pub unsafe fn set_window_title(_ptr: *const u8, _len: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn set_window_size(_width: i32, _height: i32, _resizable: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn get_window_size(_out_w_ptr: *mut i32, _out_h_ptr: *mut i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn get_display_refresh_rate() -> i32 {
+        0
+    }
+
+    pub unsafe fn get_frame_count() -> i64 {
+        0
+    }
+
+    pub unsafe fn set_cursor_visible(_visible: i32) {}
+
+    pub unsafe fn set_cursor(_shape: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn set_relative_mouse(_enabled: i32) {}
+
+    pub unsafe fn set_scaling_mode(_mode: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn set_display_adjustment(_brightness: f32, _contrast: f32, _gamma: f32) -> i32 {
+        0
+    }
+
+    pub unsafe fn push_audio(_samples: *const f32, _frames: i32, _ch: i32, _rate: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn get_audio_queued_frames() -> i32 {
+        0
+    }
+
+    pub unsafe fn launch(_ptr: *const u8, _len: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn bus_subscribe(_topic_ptr: *const u8, _topic_len: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn bus_send(_topic: *const u8, _topic_len: i32, _data: *const u8, _len: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn get_string(_key_ptr: *const u8, _key_len: i32, _buf: *mut u8, _cap: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn config_get(_key_ptr: *const u8, _key_len: i32, _out: *mut u8, _cap: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn asset_size(_name_ptr: *const u8, _name_len: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn asset_read(
+        _name_ptr: *const u8,
+        _name_len: i32,
+        _buf: *mut u8,
+        _cap: i32,
+    ) -> i32 {
+        -1
+    }
+
+    pub unsafe fn event_time() -> f64 {
+        0.0
+    }
+
+    pub unsafe fn request_frame_rate(_fps: f64) {}
+
+    pub unsafe fn now_micros() -> i64 {
+        0
+    }
+
+    pub unsafe fn set_timer(_id: i32, _seconds: f64, _repeating: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn cancel_timer(_id: i32) -> i32 {
+        -3
+    }
+
+    pub unsafe fn request_redraw() {}
+
+    pub unsafe fn request_update_in(_ms: i32) {}
+
+    pub unsafe fn query_key_state(_scancode: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn app_name(_buf_ptr: *mut u8, _buf_cap: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn app_version(_buf_ptr: *mut u8, _buf_cap: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn get_locale(_out_ptr: *mut u8, _cap: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn get_prefers_dark() -> i32 {
+        0
+    }
+
+    pub unsafe fn storage_get(_key: *const u8, _key_len: i32, _out: *mut u8, _cap: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn storage_set(_key: *const u8, _key_len: i32, _val: *const u8, _len: i32) -> i32 {
+        -3
+    }
+
+    pub unsafe fn score_submit(_value: i64, _name_ptr: *const u8, _name_len: i32) -> i32 {
+        -3
+    }
+
+    pub unsafe fn score_list(_buf_ptr: *mut u8, _buf_cap: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn report_allocations(_bytes_in_use: i64) {}
+
+    pub unsafe fn log(_level: i32, _ptr: *const u8, _len: i32) {}
+
+    pub unsafe fn http_fetch(
+        _method_ptr: *const u8,
+        _method_len: i32,
+        _url_ptr: *const u8,
+        _url_len: i32,
+        _body_ptr: *const u8,
+        _body_len: i32,
+    ) -> i32 {
+        0
+    }
+
+    pub unsafe fn http_poll(_handle: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn http_read(_handle: i32, _buf_ptr: *mut u8, _buf_cap: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn http_close(_handle: i32) {}
+
+    pub unsafe fn ws_connect(_url_ptr: *const u8, _url_len: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn ws_send(_handle: i32, _ptr: *const u8, _len: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn ws_status(_handle: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn ws_close(_handle: i32) {}
+
+    pub unsafe fn request_snapshot() -> i32 {
+        -1
+    }
+
+    pub unsafe fn request_restore() -> i32 {
+        -1
+    }
+
+    pub unsafe fn request_open_file(_filter_ptr: *const u8, _filter_len: i32) -> i32 {
+        -1
+    }
+
+    pub unsafe fn request_save_file(
+        _name_ptr: *const u8,
+        _name_len: i32,
+        _data_ptr: *const u8,
+        _data_len: i32,
+    ) -> i32 {
+        -1
+    }
+
+    pub unsafe fn gamepad_rumble(_pad: i32, _low: i32, _high: i32, _duration_ms: i32) {}
+
+    pub unsafe fn gamepad_set_led(_pad: i32, _r: i32, _g: i32, _b: i32) {}
+
+    pub unsafe fn audio_capture_start(_sample_rate: i32, _channels: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn audio_capture_read(_out_ptr: *mut f32, _max_frames: i32) -> i32 {
+        0
+    }
+
+    pub unsafe fn audio_capture_stop() {}
+}
+
+/// The host rejected pushed audio: unsupported channel count or sample rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedAudioFormat;
+
+/// Why the host refused to record the microphone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureError {
+    /// The user denied the `microphone` permission, or the manifest does not
+    /// declare the `microphone` capability
+    Denied,
+    /// The channel count or sample rate is not supported
+    UnsupportedFormat,
+}
+
+/// Why the host refused to launch a package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchError {
+    /// The host was not started with `--allow-launch`
+    Denied,
+    /// The target was empty or malformed
+    Invalid,
+}
+
+/// Why the host refused a message bus call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusError {
+    /// The user denied the `messaging` permission, or the host runs a single
+    /// app (e.g. in a browser)
+    Denied,
+    /// The topic was empty, longer than 64 bytes or held control characters,
+    /// or the message was over 64 KiB
+    Invalid,
+    /// Over 64 messages this frame, or 64 subscribed topics
+    Full,
+}
+
+/// Layout of the pixels passed to [`update_frame_ex`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 4 bytes per pixel: red, green, blue, alpha
+    Rgba32 = 0,
+    /// 3 bytes per pixel: red, green, blue
+    Rgb24 = 1,
+    /// 4 bytes per pixel: blue, green, red, alpha
+    Bgra32 = 2,
+    /// 2 bytes per pixel, little-endian: 5 bits red, 6 green, 5 blue
+    Rgb565 = 3,
+    /// 1 byte per pixel: luminance
+    Gray8 = 4,
+    /// 1 byte per pixel: index into the palette set with [`set_palette`]
+    Indexed8 = 5,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba32 | PixelFormat::Bgra32 => 4,
+            PixelFormat::Rgb24 => 3,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Gray8 | PixelFormat::Indexed8 => 1,
+        }
+    }
+}
+
+/// An image uploaded with [`create_image`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageId(i32);
+
+/// How the host scales frames into the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalingMode {
+    /// Fill the window, distorting the frame if its aspect ratio differs
+    Stretch,
+    /// Largest size with the frame's aspect ratio, letterboxed
+    Fit,
+    /// Largest whole multiple of the frame size, letterboxed, for crisp pixel art
+    Integer,
+}
+
+/// A display adjustment outside the accepted ranges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidDisplayAdjustment;
+
+/// A window side was over 16384 pixels, or only one side was 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidWindowSize;
+
+/// Mouse cursor shapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Arrow = 0,
+    /// Text insertion bar
+    IBeam = 1,
+    Crosshair = 2,
+    /// Pointing hand, over links and buttons
+    Hand = 3,
+    /// Four-way arrow, for dragging things around
+    Move = 4,
+    ResizeHorizontal = 5,
+    ResizeVertical = 6,
+    NotAllowed = 7,
+    Wait = 8,
+}
+
+/// Why the host refused to store a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageError {
+    /// The key was empty, longer than 256 bytes, reserved (starting with
+    /// `wapps:`), or the value out of bounds
+    Invalid,
+    /// The app's stored keys and values would exceed 1 MiB
+    QuotaExceeded,
+    /// The host could not write the storage file
+    Io,
+}
+
+/// Severity of a line passed to [`log`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+/// An HTTP request started with [`http_fetch`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HttpRequest(i32);
+
+/// Why an HTTP request was refused or failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpError {
+    /// The user denied the network permission, or the manifest does not
+    /// declare the `network` capability
+    Denied,
+    /// The method or URL was malformed, or the body over 8 MiB
+    Invalid,
+    /// The URL's host is not in the manifest's `allowed_hosts`
+    HostNotAllowed,
+    /// 16 requests are already open
+    TooManyRequests,
+    /// No response: connection error, timeout or body over 8 MiB
+    Failed,
+}
+
+/// A WebSocket opened with [`ws_connect`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WebSocket(pub(crate) i32);
+
+/// State of a WebSocket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsStatus {
+    Connecting,
+    Open,
+    /// The connection failed, or the server closed it
+    Closed,
+}
+
+/// Why the host refused to schedule a timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// The delay was negative or not finite, or zero for a repeating timer
+    Invalid,
+    /// 64 timers are already scheduled
+    TooManyTimers,
+}
+
+/// Why a WebSocket was refused or a message not sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsError {
+    /// The user denied the network permission, or the manifest does not
+    /// declare the `network` capability
+    Denied,
+    /// The URL was malformed, the message over 1 MiB, or the socket closed
+    /// with [`ws_close`]
+    Invalid,
+    /// The URL's host is not in the manifest's `allowed_hosts`
+    HostNotAllowed,
+    /// 8 WebSockets are already open
+    TooManyConnections,
+    /// The connection failed, or the server closed it
+    Closed,
+}
+
+/// Why the host refused a save state request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Save states are off: the host is recording or replaying a session, or
+    /// runs in safe mode
+    Unavailable,
+    /// No state was saved to restore
+    NotFound,
+}
+
+/// Why the host refused to show a file dialog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileDialogError {
+    /// The manifest does not declare the `files` capability
+    Denied,
+    /// The host has no dialogs: it runs as a kiosk, in safe mode or for a
+    /// recorded session, or was built without them
+    Unavailable,
+    /// Another dialog was requested during the same frame
+    Busy,
+    /// The filter or name was not UTF-8, or the data over 64 MiB
+    Invalid,
+}
+
+/// How a save dialog requested with [`request_save_file`] ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSaved {
+    Saved,
+    /// The user closed the dialog without choosing a file
+    Cancelled,
+    /// The chosen file could not be written
+    Failed,
+}
+
+/// Why the host refused to record a score
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreError {
+    /// The name was longer than 32 bytes or the leaderboard would exceed the storage quota
+    Invalid,
+    /// The host could not write the storage file
+    Io,
+}
+
+/// A leaderboard entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Score {
+    pub value: i64,
+    pub name: String,
+    /// Whether the host signed this entry, so it was not edited outside the app
+    pub verified: bool,
+}
+
+/// Present `width` x `height` RGBA pixels
+///
+/// Prefer [`Framebuffer::present`](crate::Framebuffer::present), which keeps
+/// the dimensions and buffer length consistent.
+pub fn update_frame(width: i32, height: i32, pixels: &[u8]) -> Result<(), HostError> {
+    let len = width.max(0) as usize * height.max(0) as usize * 4;
+    assert!(pixels.len() >= len, "pixel buffer smaller than frame");
+    // SAFETY: the host reads `len` bytes, which the assertion keeps in bounds
+    wapps_abi::check(unsafe { ffi::update_frame(width, height, pixels.as_ptr()) }).map(drop)
+}
+
+/// Present `width` x `height` pixels laid out as `format`
+///
+/// Smaller formats such as [`PixelFormat::Rgb565`] let guests keep a smaller
+/// framebuffer; the host expands them to RGBA.
+pub fn update_frame_ex(
+    width: i32,
+    height: i32,
+    format: PixelFormat,
+    pixels: &[u8],
+) -> Result<(), HostError> {
+    let len = width.max(0) as usize * height.max(0) as usize * format.bytes_per_pixel();
+    assert!(pixels.len() >= len, "pixel buffer smaller than frame");
+    // SAFETY: the host reads `len` bytes, which the assertion keeps in bounds
+    let status = unsafe { ffi::update_frame_ex(width, height, pixels.as_ptr(), format as i32) };
+    wapps_abi::check(status).map(drop)
+}
+
+/// Set the colors of [`PixelFormat::Indexed8`] frames; indices past the end are black
+///
+/// The indexed frame on screen is recolored immediately, so palette cycling
+/// effects only need to call this, not present the frame again. Palettes of
+/// more than 256 colors are [`HostError::InvalidDimensions`].
+pub fn set_palette(colors: &[Color]) -> Result<(), HostError> {
+    let entries: Vec<u8> = colors.iter().flat_map(|c| [c.r, c.g, c.b, c.a]).collect();
+    // SAFETY: the host reads `colors.len()` entries of 4 bytes
+    let status = unsafe { ffi::set_palette(entries.as_ptr(), colors.len() as i32) };
+    wapps_abi::check(status).map(drop)
+}
+
+/// Upload `width` x `height` RGBA pixels once, to draw them every frame with [`draw_image`]
+///
+/// Images past the host's limits (4096 images or 64 MiB of pixels) are
+/// [`HostError::OutOfBounds`], as are draws of destroyed images and more
+/// than 65536 draws or 64 MiB of blits per frame.
+pub fn create_image(width: u32, height: u32, pixels: &[u8]) -> Result<ImageId, HostError> {
+    let len = width as usize * height as usize * 4;
+    assert!(pixels.len() >= len, "pixel buffer smaller than image");
+    // SAFETY: the host reads `len` bytes, which the assertion keeps in bounds
+    let id = unsafe { ffi::create_image(pixels.as_ptr(), width as i32, height as i32) };
+    wapps_abi::check(id).map(ImageId)
+}
+
+/// Free an image that will not be drawn again
+pub fn destroy_image(id: ImageId) {
+    // SAFETY: plain integer
+    unsafe { ffi::destroy_image(id.0) }
+}
+
+/// Draw image `id` over the next frame, centered on (`x`, `y`) in frame
+/// pixels, scaled by `scale` and turned clockwise by `rotation` radians
+///
+/// Images are drawn in call order over the frame and its layers, once: draw
+/// them again for every frame. Games drawing only images can start each
+/// frame with [`clear_canvas`] instead of presenting a framebuffer.
+pub fn draw_image(id: ImageId, x: f32, y: f32, scale: f32, rotation: f32) -> Result<(), HostError> {
+    // SAFETY: plain numbers
+    wapps_abi::check(unsafe { ffi::draw_image(id.0, x, y, scale, rotation) }).map(drop)
+}
+
+/// Present a `width` x `height` frame filled with `color`, for images to be drawn on
+pub fn clear_canvas(width: u32, height: u32, color: Color) -> Result<(), HostError> {
+    let rgba = u32::from_be_bytes([color.r, color.g, color.b, color.a]);
+    // SAFETY: plain integers
+    wapps_abi::check(unsafe { ffi::clear_canvas(width as i32, height as i32, rgba as i32) })
+        .map(drop)
+}
+
+/// Draw `text` over the next frame with its top-left corner at (`x`, `y`),
+/// in the host's bitmap font, `size` pixels high
+///
+/// The font has 3x5 glyphs in upper case, scaled by whole multiples, so sizes
+/// are rounded down to a multiple of 5; `\n` starts a new line. Text is drawn
+/// once, in call order with [`draw_image`]. Text over 4096 bytes is
+/// [`HostError::OutOfBounds`].
+pub fn draw_text(x: i32, y: i32, text: &str, color: Color, size: u32) -> Result<(), HostError> {
+    let rgba = u32::from_be_bytes([color.r, color.g, color.b, color.a]);
+    // SAFETY: the host reads `text.len()` bytes from `text`
+    let status = unsafe {
+        ffi::draw_text(
+            x,
+            y,
+            text.as_ptr(),
+            text.len() as i32,
+            rgba as i32,
+            size as i32,
+        )
+    };
+    wapps_abi::check(status).map(drop)
+}
+
+/// Width and height in pixels of `text` drawn with [`draw_text`] at `size`
+pub fn measure_text(text: &str, size: u32) -> (u32, u32) {
+    let (mut width, mut height) = (0, 0);
+    // SAFETY: the host reads `text.len()` bytes from `text` and writes one i32
+    // to each pointer
+    unsafe {
+        ffi::measure_text(
+            text.as_ptr(),
+            text.len() as i32,
+            size as i32,
+            &mut width,
+            &mut height,
+        )
+    };
+    (width.max(0) as u32, height.max(0) as u32)
+}
+
+/// Fill the next frame with `color`, replacing what it holds
+///
+/// Like [`fill_rect`] and [`blit`], this draws on the frame the host holds,
+/// in call order with [`draw_image`], so apps need not present a framebuffer
+/// every frame: start one with [`clear_canvas`] and draw on it.
+pub fn clear(color: Color) -> Result<(), HostError> {
+    let rgba = u32::from_be_bytes([color.r, color.g, color.b, color.a]);
+    // SAFETY: plain integer
+    wapps_abi::check(unsafe { ffi::clear(rgba as i32) }).map(drop)
+}
+
+/// Blend a `width` x `height` rectangle of `color` over the next frame, with
+/// its top-left corner at (`x`, `y`)
+pub fn fill_rect(x: i32, y: i32, width: u32, height: u32, color: Color) -> Result<(), HostError> {
+    let rgba = u32::from_be_bytes([color.r, color.g, color.b, color.a]);
+    // SAFETY: plain integers
+    let status = unsafe { ffi::fill_rect(x, y, width as i32, height as i32, rgba as i32) };
+    wapps_abi::check(status).map(drop)
+}
+
+/// Blend `width` x `height` RGBA `pixels` over the next frame, with their
+/// top-left corner at (`x`, `y`)
+///
+/// The pixels are copied; frequently drawn ones are cheaper uploaded once
+/// with [`create_image`].
+pub fn blit(pixels: &[u8], width: u32, height: u32, x: i32, y: i32) -> Result<(), HostError> {
+    let len = width as usize * height as usize * 4;
+    assert!(pixels.len() >= len, "pixel buffer smaller than image");
+    // SAFETY: the host reads `len` bytes, which the assertion keeps in bounds
+    let status = unsafe { ffi::blit(pixels.as_ptr(), width as i32, height as i32, x, y) };
+    wapps_abi::check(status).map(drop)
+}
+
+/// Set layer `id` to `width` x `height` RGBA pixels drawn at `opacity`
+///
+/// The host composites layers in increasing id order over layer 0, which is
+/// what [`update_frame`] sets. Layers persist until replaced or removed, so
+/// static content only needs to be submitted once. Prefer
+/// [`Framebuffer::present_layer`](crate::Framebuffer::present_layer).
+pub fn update_layer(
+    id: i32,
+    width: i32,
+    height: i32,
+    pixels: &[u8],
+    opacity: f32,
+) -> Result<(), HostError> {
+    let len = width.max(0) as usize * height.max(0) as usize * 4;
+    assert!(pixels.len() >= len, "pixel buffer smaller than layer");
+    // SAFETY: the host reads `len` bytes, which the assertion keeps in bounds
+    let status = unsafe { ffi::update_layer(id, width, height, pixels.as_ptr(), opacity) };
+    wapps_abi::check(status).map(drop)
+}
+
+/// Stop drawing layer `id`
+pub fn remove_layer(id: i32) {
+    // SAFETY: an empty layer reads no pixels, and removing one cannot fail
+    unsafe { ffi::update_layer(id, 0, 0, std::ptr::null(), 0.0) };
+}
+
+/// Draw `width` x `height` RGBA pixels over the whole window, blended by
+/// their alpha, e.g. a HUD at the window's resolution over a low-resolution
+/// frame
+///
+/// The HUD is stretched over the window; for one HUD pixel per screen pixel,
+/// size it to the window size times the scale reported by
+/// [`App::on_scale_changed`](crate::App::on_scale_changed). It stays until
+/// replaced or removed with [`remove_overlay`]. Sides over 8192 pixels are
+/// invalid.
+pub fn update_overlay(width: i32, height: i32, pixels: &[u8]) -> Result<(), HostError> {
+    let len = width.max(0) as usize * height.max(0) as usize * 4;
+    assert!(pixels.len() >= len, "pixel buffer smaller than overlay");
+    // SAFETY: the host reads `len` bytes, which the assertion keeps in bounds
+    wapps_abi::check(unsafe { ffi::update_overlay(width, height, pixels.as_ptr()) }).map(drop)
+}
+
+/// Stop drawing the HUD set with [`update_overlay`]
+pub fn remove_overlay() {
+    // SAFETY: an empty overlay reads no pixels
+    unsafe { ffi::update_overlay(0, 0, std::ptr::null()) };
+}
+
+/// Require a `width`:`height` aspect ratio, e.g. 16:9, or clear it with 0:0
+///
+/// The host letterboxes the frame to keep it; pointer positions and sizes
+/// passed to the callbacks are then relative to the letterboxed area.
+pub fn set_aspect_ratio(width: u32, height: u32) {
+    // SAFETY: plain integers
+    unsafe { ffi::set_aspect_ratio(width as i32, height as i32) }
+}
+
+/// Prevent the window from shrinking below `width` x `height`, or clear with 0x0
+pub fn set_min_size(width: u32, height: u32) {
+    // SAFETY: plain integers
+    unsafe { ffi::set_min_size(width as i32, height as i32) }
+}
+
+/// Fill the window around the frame, including the letterbox bars, with
+/// `color` instead of the host's default (usually black)
+pub fn set_clear_color(color: Color) {
+    let rgba = u32::from_be_bytes([color.r, color.g, color.b, color.a]);
+    // SAFETY: plain integer
+    unsafe { ffi::set_clear_color(rgba as i32) }
+}
+
+/// Change how frames are scaled into the window
+///
+/// Pointer positions keep mapping to frame pixels the same way in every mode:
+/// as if the frame filled the size reported to `on_resize`.
+pub fn set_scaling_mode(mode: ScalingMode) {
+    let mode = match mode {
+        ScalingMode::Stretch => 0,
+        ScalingMode::Fit => 1,
+        ScalingMode::Integer => 2,
+    };
+    // SAFETY: plain integer
+    unsafe { ffi::set_scaling_mode(mode) };
+}
+
+/// Adjust how the host presents frames: `brightness` is added to every
+/// channel (-1 to 1, default 0), `contrast` stretches channels away from
+/// mid-grey (0 to 4, default 1) and `gamma` above 1 lightens dark tones (0.1
+/// to 4, default 1)
+///
+/// Frames are drawn as before; the viewer's own adjustment applies on top.
+pub fn set_display_adjustment(
+    brightness: f32,
+    contrast: f32,
+    gamma: f32,
+) -> Result<(), InvalidDisplayAdjustment> {
+    // SAFETY: plain floats
+    match unsafe { ffi::set_display_adjustment(brightness, contrast, gamma) } {
+        0 => Ok(()),
+        _ => Err(InvalidDisplayAdjustment),
+    }
+}
+
+/// Enter borderless fullscreen, or return to a window
+///
+/// The change applies at the end of the frame, followed by `on_resize`
+/// with the new size. Hosts keeping their fullscreen state, e.g. kiosks,
+/// return [`HostError::PermissionDenied`].
+pub fn set_fullscreen(fullscreen: bool) -> Result<(), HostError> {
+    // SAFETY: plain integer
+    wapps_abi::check(unsafe { ffi::set_fullscreen(fullscreen as i32) }).map(drop)
+}
+
+/// Show `title` in the title bar instead of the package name
+pub fn set_window_title(title: &str) {
+    // SAFETY: the host only reads `title`
+    unsafe { ffi::set_window_title(title.as_ptr(), title.len() as i32) };
+}
+
+/// Resize the window to `width` x `height` and let the user resize it if
+/// `resizable`, or pass 0x0 to size it to the frame again
+///
+/// By default the window follows the size of the frames. Once sized
+/// here it keeps its size and the frame is scaled into it; the change
+/// applies at the end of the frame, followed by `on_resize`.
+pub fn set_window_size(width: u32, height: u32, resizable: bool) -> Result<(), InvalidWindowSize> {
+    let (width, height) = (
+        i32::try_from(width).map_err(|_| InvalidWindowSize)?,
+        i32::try_from(height).map_err(|_| InvalidWindowSize)?,
+    );
+    // SAFETY: plain integers
+    match unsafe { ffi::set_window_size(width, height, resizable as i32) } {
+        0 => Ok(()),
+        _ => Err(InvalidWindowSize),
+    }
+}
+
+/// Size of the viewport last passed to `init` or `on_resize`
+///
+/// Lets a guest size its framebuffer from the start instead of waiting for a
+/// resize that may never come.
+pub fn window_size() -> (u32, u32) {
+    let (mut width, mut height) = (0, 0);
+    // SAFETY: the host writes one i32 to each pointer
+    unsafe { ffi::get_window_size(&mut width, &mut height) };
+    (width.max(0) as u32, height.max(0) as u32)
+}
+
+/// Refresh rate of the window's display in Hz, if the host knows it
+pub fn display_refresh_rate() -> Option<u32> {
+    // SAFETY: no arguments
+    let hz = unsafe { ffi::get_display_refresh_rate() };
+    (hz > 0).then_some(hz as u32)
+}
+
+/// Number of frames the host has presented so far
+pub fn frame_count() -> u64 {
+    // SAFETY: no arguments
+    unsafe { ffi::get_frame_count() as u64 }
+}
+
+/// Show or hide the mouse cursor over the window
+pub fn set_cursor_visible(visible: bool) {
+    // SAFETY: plain integer
+    unsafe { ffi::set_cursor_visible(visible as i32) }
+}
+
+/// Change the shape of the mouse cursor over the window
+pub fn set_cursor(shape: CursorShape) {
+    // SAFETY: plain integer
+    unsafe { ffi::set_cursor(shape as i32) };
+}
+
+/// Capture the mouse, as first-person games do, or release it
+///
+/// While captured the cursor is hidden and held in the window, so only the
+/// motion passed to [`App::on_pointer_motion`](crate::App::on_pointer_motion)
+/// is meaningful. The host releases the mouse while the window is unfocused
+/// and captures it again when focus returns.
+pub fn set_relative_mouse(enabled: bool) {
+    // SAFETY: plain integer
+    unsafe { ffi::set_relative_mouse(enabled as i32) }
+}
+
+/// Queue interleaved samples in `-1.0..=1.0` for playback
+///
+/// `channels` is 1 (mono) or 2 (stereo, left first). The host buffers up to
+/// two seconds of audio; use [`audio_queued_frames`] to mix just ahead of
+/// playback.
+pub fn push_audio(
+    samples: &[f32],
+    channels: u8,
+    sample_rate: u32,
+) -> Result<(), UnsupportedAudioFormat> {
+    let frames = samples.len() / channels.max(1) as usize;
+    // SAFETY: the host reads `frames * channels` samples, all within `samples`
+    let status = unsafe {
+        ffi::push_audio(
+            samples.as_ptr(),
+            frames as i32,
+            channels as i32,
+            sample_rate as i32,
+        )
+    };
+    match status {
+        0 => Ok(()),
+        _ => Err(UnsupportedAudioFormat),
+    }
+}
+
+/// Frames pushed with [`push_audio`] that have not been played yet
+pub fn audio_queued_frames() -> u32 {
+    // SAFETY: no arguments
+    unsafe { ffi::get_audio_queued_frames() }.max(0) as u32
+}
+
+/// Start recording the microphone as `channels` interleaved channels at
+/// `sample_rate`
+///
+/// The user is asked for the `microphone` permission the first time. Read
+/// the samples with [`audio_capture_read`]; the host keeps the last second.
+pub fn audio_capture_start(sample_rate: u32, channels: u8) -> Result<(), CaptureError> {
+    // SAFETY: plain integers
+    match unsafe { ffi::audio_capture_start(sample_rate as i32, channels as i32) } {
+        1 => Ok(()),
+        0 => Err(CaptureError::Denied),
+        _ => Err(CaptureError::UnsupportedFormat),
+    }
+}
+
+/// Move the oldest recorded frames into `out`, returning how many were read
+///
+/// `channels` is the count passed to [`audio_capture_start`]; only whole
+/// frames that fit in `out` are read.
+pub fn audio_capture_read(out: &mut [f32], channels: u8) -> usize {
+    let max_frames = (out.len() / channels.max(1) as usize).min(i32::MAX as usize);
+    // SAFETY: the host writes at most `max_frames * channels` samples, all
+    // within `out`
+    let frames = unsafe { ffi::audio_capture_read(out.as_mut_ptr(), max_frames as i32) };
+    frames.max(0) as usize
+}
+
+/// Stop recording, dropping the samples not read yet
+pub fn audio_capture_stop() {
+    // SAFETY: no arguments
+    unsafe { ffi::audio_capture_stop() }
+}
+
+/// Ask the host to launch another package after this frame
+pub fn launch(target: &str) -> Result<(), LaunchError> {
+    // SAFETY: the host only reads `target`
+    match unsafe { ffi::launch(target.as_ptr(), target.len() as i32) } {
+        0 => Ok(()),
+        -1 => Err(LaunchError::Denied),
+        _ => Err(LaunchError::Invalid),
+    }
+}
+
+/// Receive the messages other running apps send under `topic` through
+/// [`App::on_bus_message`](crate::App::on_bus_message)
+pub fn bus_subscribe(topic: &str) -> Result<(), BusError> {
+    // SAFETY: the host only reads `topic`
+    bus_status(unsafe { ffi::bus_subscribe(topic.as_ptr(), topic.len() as i32) })
+}
+
+/// Send `message` to the other running apps subscribed to `topic`; they
+/// receive it before their next update
+pub fn bus_send(topic: &str, message: &[u8]) -> Result<(), BusError> {
+    // SAFETY: the host only reads `topic` and `message`
+    bus_status(unsafe {
+        ffi::bus_send(
+            topic.as_ptr(),
+            topic.len() as i32,
+            message.as_ptr(),
+            message.len() as i32,
+        )
+    })
+}
+
+fn bus_status(status: i32) -> Result<(), BusError> {
+    match status {
+        0 => Ok(()),
+        -1 => Err(BusError::Denied),
+        -3 => Err(BusError::Full),
+        _ => Err(BusError::Invalid),
+    }
+}
+
+/// Localized package string for `key`, or `None` if the package has none
+pub fn string(key: &str) -> Option<String> {
+    // SAFETY: the host reads `key` and writes at most `cap` bytes into `buf`
+    read_string(|buf, cap| unsafe { ffi::get_string(key.as_ptr(), key.len() as i32, buf, cap) })
+}
+
+/// Startup setting `key`, from the package's `config` or the host's
+/// `--guest-arg key=value`, or `None` if it is unset
+pub fn config(key: &str) -> Option<String> {
+    // SAFETY: the host reads `key` and writes at most `cap` bytes into `buf`
+    read_string(|buf, cap| unsafe { ffi::config_get(key.as_ptr(), key.len() as i32, buf, cap) })
+}
+
+/// Size in bytes of the asset bundled in the package as `name`, or `None` if
+/// the package has none
+pub fn asset_size(name: &str) -> Option<usize> {
+    // SAFETY: the host only reads `name`
+    usize::try_from(unsafe { ffi::asset_size(name.as_ptr(), name.len() as i32) }).ok()
+}
+
+/// Contents of the asset bundled in the package as `name`, or `None` if the
+/// package has none
+///
+/// Assets are kept out of the module, so large images, fonts and level data
+/// cost no memory until they are read.
+pub fn asset(name: &str) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; asset_size(name)?];
+    // SAFETY: the host reads `name` and writes at most `buf.len()` bytes into `buf`
+    let len = unsafe {
+        ffi::asset_read(
+            name.as_ptr(),
+            name.len() as i32,
+            buf.as_mut_ptr(),
+            buf.len() as i32,
+        )
+    };
+    (usize::try_from(len).ok()? == buf.len()).then_some(buf)
+}
+
+/// Time the event being handled happened, in seconds since the host started
+///
+/// Outside event callbacks, returns the time of the last event delivered.
+pub fn event_time() -> f64 {
+    // SAFETY: no arguments
+    unsafe { ffi::event_time() }
+}
+
+/// Ask to be updated `fps` times a second, or every frame again with 0
+///
+/// The host skips frames in between and passes the time of all those
+/// skipped to the next `update`, clamped after stalls, so simulations can
+/// step at their own cadence while the host paces the window. Hosts started
+/// with `--fixed-dt` keep their constant timestep instead.
+pub fn request_frame_rate(fps: f64) {
+    // SAFETY: plain float
+    unsafe { ffi::request_frame_rate(fps) }
+}
+
+/// Microseconds since the app started, from a clock that never goes back
+///
+/// Under `--deterministic` the clock advances by each update's `dt`, and
+/// replayed sessions return the recorded readings.
+pub fn now_micros() -> i64 {
+    // SAFETY: no arguments
+    unsafe { ffi::now_micros() }
+}
+
+/// Call [`App::on_timer`](crate::App::on_timer) with `id` in `seconds`, and
+/// every `seconds` after that if `repeating`, replacing any timer with that id
+///
+/// Timers run on the frame loop: they fire before the `update` of the first
+/// frame past their time, at most once per frame.
+pub fn set_timer(id: i32, seconds: f64, repeating: bool) -> Result<(), TimerError> {
+    // SAFETY: plain values
+    match unsafe { ffi::set_timer(id, seconds, repeating as i32) } {
+        0 => Ok(()),
+        -2 => Err(TimerError::TooManyTimers),
+        _ => Err(TimerError::Invalid),
+    }
+}
+
+/// Cancel the timer `id`, returning whether it was scheduled
+pub fn cancel_timer(id: i32) -> bool {
+    // SAFETY: plain integer
+    unsafe { ffi::cancel_timer(id) == 0 }
+}
+
+/// Ask for another frame
+///
+/// Hosts started with `--power-save` only call `update` when the app gets
+/// input, a timer fires or it called this during its previous frame, so
+/// animating apps call it every frame until they come to rest. Other hosts
+/// update every frame anyway.
+pub fn request_redraw() {
+    // SAFETY: no arguments
+    unsafe { ffi::request_redraw() }
+}
+
+/// Ask for an update within `ms` milliseconds of the current frame
+///
+/// Like [`request_redraw`], for apps that have nothing to do until then
+/// under `--power-save`, such as one stepping a simulation ten times a
+/// second; the earliest request made during a frame wins.
+pub fn request_update_in(ms: u32) {
+    // SAFETY: plain integer
+    unsafe { ffi::request_update_in(ms.min(i32::MAX as u32) as i32) }
+}
+
+/// Whether the key with USB HID `scancode` is held down in this app's window
+pub fn key_held(scancode: i32) -> bool {
+    // SAFETY: plain integer
+    unsafe { ffi::query_key_state(scancode) != 0 }
+}
+
+/// Name of the running package
+pub fn app_name() -> String {
+    // SAFETY: the host writes at most `cap` bytes into `buf`
+    read_string(|buf, cap| unsafe { ffi::app_name(buf, cap) }).unwrap_or_default()
+}
+
+/// Version of the running package
+pub fn app_version() -> String {
+    // SAFETY: the host writes at most `cap` bytes into `buf`
+    read_string(|buf, cap| unsafe { ffi::app_version(buf, cap) }).unwrap_or_default()
+}
+
+/// The user's locale as a BCP 47 tag such as `"pt-BR"`, empty if unknown
+///
+/// Package strings read with [`string`] are already translated; use this
+/// for formatting numbers and dates or choosing assets.
+pub fn locale() -> String {
+    // SAFETY: the host writes at most `cap` bytes into `buf`
+    read_string(|buf, cap| unsafe { ffi::get_locale(buf, cap) }).unwrap_or_default()
+}
+
+/// Whether the user prefers a dark theme
+pub fn prefers_dark() -> bool {
+    // SAFETY: no arguments
+    unsafe { ffi::get_prefers_dark() != 0 }
+}
+
+/// Value this app stored under `key`, or `None` if it has none
+///
+/// Storage persists across runs of the app.
+pub fn storage_get(key: &str) -> Option<Vec<u8>> {
+    // SAFETY: the host reads `key` and writes at most `cap` bytes into `buf`
+    read_bytes(|buf, cap| unsafe { ffi::storage_get(key.as_ptr(), key.len() as i32, buf, cap) })
+}
+
+/// Persistently store `value` under `key`, replacing any previous value
+pub fn storage_set(key: &str, value: &[u8]) -> Result<(), StorageError> {
+    // SAFETY: the host only reads `key` and `value`
+    let status = unsafe {
+        ffi::storage_set(
+            key.as_ptr(),
+            key.len() as i32,
+            value.as_ptr(),
+            value.len() as i32,
+        )
+    };
+    match status {
+        0 => Ok(()),
+        -2 => Err(StorageError::QuotaExceeded),
+        -3 => Err(StorageError::Io),
+        _ => Err(StorageError::Invalid),
+    }
+}
+
+/// Add a score to this app's leaderboard, which keeps the best 100 entries
+///
+/// Returns the entry's rank from 0 (the best), or `None` if it did not make
+/// the leaderboard. Equal scores rank below the ones submitted first.
+pub fn submit_score(value: i64, name: &str) -> Result<Option<usize>, ScoreError> {
+    // SAFETY: the host only reads `name`
+    let status = unsafe { ffi::score_submit(value, name.as_ptr(), name.len() as i32) };
+    match status {
+        rank if rank >= 0 => Ok(Some(rank as usize)),
+        -1 => Ok(None),
+        -3 => Err(ScoreError::Io),
+        _ => Err(ScoreError::Invalid),
+    }
+}
+
+/// This app's leaderboard, best first
+pub fn scores() -> Vec<Score> {
+    // SAFETY: the host writes at most `cap` bytes into `buf`
+    read_bytes(|buf, cap| unsafe { ffi::score_list(buf, cap) })
+        .map(|data| decode_scores(&data))
+        .unwrap_or_default()
+}
+
+/// Tell the host how many bytes the app's allocator has in use, to show next
+/// to the size of linear memory in `--show-usage` and `--stats`
+///
+/// See [`CountingAllocator`](crate::CountingAllocator) to count them.
+pub fn report_allocations(bytes_in_use: usize) {
+    // SAFETY: plain integer
+    unsafe { ffi::report_allocations(bytes_in_use as i64) }
+}
+
+/// Log a line through the host's logger, under the app's name, where
+/// `--verbose` and `RUST_LOG` filter it, unlike `println!` output
+pub fn log(level: LogLevel, message: &str) {
+    // SAFETY: ptr and len describe a valid string
+    unsafe { ffi::log(level as i32, message.as_ptr(), message.len() as i32) }
+}
+
+/// Start a `method` request to `url`, sending `body` unless empty
+///
+/// Only `http://` and `https://` URLs on the hosts the manifest lists under
+/// `allowed_hosts` are allowed, and redirects are not followed. Poll the
+/// request with [`http_poll`] and release it with [`http_close`].
+pub fn http_fetch(method: &str, url: &str, body: &[u8]) -> Result<HttpRequest, HttpError> {
+    // SAFETY: the host only reads the three buffers
+    let handle = unsafe {
+        ffi::http_fetch(
+            method.as_ptr(),
+            method.len() as i32,
+            url.as_ptr(),
+            url.len() as i32,
+            body.as_ptr(),
+            body.len() as i32,
+        )
+    };
+    match handle {
+        1.. => Ok(HttpRequest(handle)),
+        0 => Err(HttpError::Denied),
+        -2 => Err(HttpError::HostNotAllowed),
+        -3 => Err(HttpError::TooManyRequests),
+        _ => Err(HttpError::Invalid),
+    }
+}
+
+/// HTTP status code of `request` once it completed, `None` while it runs
+pub fn http_poll(request: HttpRequest) -> Option<Result<u16, HttpError>> {
+    // SAFETY: plain integer
+    match unsafe { ffi::http_poll(request.0) } {
+        0 => None,
+        status @ 1.. => Some(Ok(status as u16)),
+        -1 => Some(Err(HttpError::Invalid)),
+        _ => Some(Err(HttpError::Failed)),
+    }
+}
+
+/// Response body of `request`, once [`http_poll`] returned its status
+pub fn http_read(request: HttpRequest) -> Option<Vec<u8>> {
+    // SAFETY: the host writes at most `cap` bytes into `buf`
+    read_bytes(|buf, cap| unsafe { ffi::http_read(request.0, buf, cap) })
+}
+
+/// Release `request`, running or not
+pub fn http_close(request: HttpRequest) {
+    // SAFETY: plain integer
+    unsafe { ffi::http_close(request.0) }
+}
+
+/// Open a WebSocket to a `ws://` or `wss://` URL
+///
+/// Only hosts the manifest lists under `allowed_hosts` are allowed. Messages
+/// arrive through [`App::on_ws_message`](crate::App::on_ws_message); close
+/// the socket with [`ws_close`].
+pub fn ws_connect(url: &str) -> Result<WebSocket, WsError> {
+    // SAFETY: ptr and len describe a valid string
+    let handle = unsafe { ffi::ws_connect(url.as_ptr(), url.len() as i32) };
+    match handle {
+        1.. => Ok(WebSocket(handle)),
+        0 => Err(WsError::Denied),
+        -2 => Err(WsError::HostNotAllowed),
+        -3 => Err(WsError::TooManyConnections),
+        _ => Err(WsError::Invalid),
+    }
+}
+
+/// Send `message` on `socket`, as a text message if it is UTF-8 and a binary
+/// one otherwise; messages sent while it connects go out once it is open
+pub fn ws_send(socket: WebSocket, message: &[u8]) -> Result<(), WsError> {
+    // SAFETY: the host only reads the message
+    match unsafe { ffi::ws_send(socket.0, message.as_ptr(), message.len() as i32) } {
+        0 => Ok(()),
+        -2 => Err(WsError::Closed),
+        _ => Err(WsError::Invalid),
+    }
+}
+
+/// State of `socket`, `None` once closed with [`ws_close`]
+pub fn ws_status(socket: WebSocket) -> Option<WsStatus> {
+    // SAFETY: plain integer
+    match unsafe { ffi::ws_status(socket.0) } {
+        0 => Some(WsStatus::Connecting),
+        1 => Some(WsStatus::Open),
+        -2 => Some(WsStatus::Closed),
+        _ => None,
+    }
+}
+
+/// Close `socket`, dropping the messages not delivered yet
+pub fn ws_close(socket: WebSocket) {
+    // SAFETY: plain integer
+    unsafe { ffi::ws_close(socket.0) }
+}
+
+/// Save the app's state (its memory and frame) once the current callback
+/// returns, as when the user presses F2
+pub fn request_snapshot() -> Result<(), SnapshotError> {
+    // SAFETY: no arguments
+    match unsafe { ffi::request_snapshot() } {
+        0 => Ok(()),
+        _ => Err(SnapshotError::Unavailable),
+    }
+}
+
+/// Restore the state saved by [`request_snapshot`] or F2 once the current
+/// callback returns, as when the user presses F3
+pub fn request_restore() -> Result<(), SnapshotError> {
+    // SAFETY: no arguments
+    match unsafe { ffi::request_restore() } {
+        0 => Ok(()),
+        -2 => Err(SnapshotError::NotFound),
+        _ => Err(SnapshotError::Unavailable),
+    }
+}
+
+/// Ask the host to let the user pick a file to open, with one of the
+/// comma-separated extensions of `filter` (e.g. `"png,jpg"`) unless empty
+///
+/// Once the dialog closes, [`App::on_file_opened`](crate::App::on_file_opened)
+/// receives the file's contents, or `None` if the user cancelled.
+pub fn request_open_file(filter: &str) -> Result<(), FileDialogError> {
+    // SAFETY: ptr and len describe a valid string
+    file_dialog_status(unsafe { ffi::request_open_file(filter.as_ptr(), filter.len() as i32) })
+}
+
+/// Ask the host to let the user pick where to save `data`, suggesting the
+/// file name `name`
+///
+/// Once the dialog closes, [`App::on_file_saved`](crate::App::on_file_saved)
+/// reports whether the file was written.
+pub fn request_save_file(name: &str, data: &[u8]) -> Result<(), FileDialogError> {
+    // SAFETY: the host only reads the two buffers
+    let status = unsafe {
+        ffi::request_save_file(
+            name.as_ptr(),
+            name.len() as i32,
+            data.as_ptr(),
+            data.len() as i32,
+        )
+    };
+    file_dialog_status(status)
+}
+
+/// Run the rumble motors of the `pad`th connected controller for
+/// `duration_ms` milliseconds, up to 10 seconds; zero strengths stop them
+///
+/// `low` drives the low frequency motor and `high` the high frequency one,
+/// from 0 to `u16::MAX`. Needs the `gamepad` capability; controllers without
+/// motors ignore it.
+pub fn gamepad_rumble(pad: u32, low: u16, high: u16, duration_ms: u32) {
+    // SAFETY: plain integers
+    unsafe {
+        ffi::gamepad_rumble(
+            pad.min(i32::MAX as u32) as i32,
+            low as i32,
+            high as i32,
+            duration_ms.min(i32::MAX as u32) as i32,
+        )
+    }
+}
+
+/// Set the LED color of the `pad`th connected controller
+///
+/// Needs the `gamepad` capability; controllers without an LED ignore it.
+pub fn gamepad_set_led(pad: u32, r: u8, g: u8, b: u8) {
+    // SAFETY: plain integers
+    unsafe {
+        ffi::gamepad_set_led(
+            pad.min(i32::MAX as u32) as i32,
+            r as i32,
+            g as i32,
+            b as i32,
+        )
+    }
+}
+
+/// Result of `wapps::request_open_file` and `wapps::request_save_file`
+fn file_dialog_status(status: i32) -> Result<(), FileDialogError> {
+    match status {
+        1 => Ok(()),
+        0 => Err(FileDialogError::Denied),
+        -2 => Err(FileDialogError::Busy),
+        -3 => Err(FileDialogError::Invalid),
+        _ => Err(FileDialogError::Unavailable),
+    }
+}
+
+/// Decode `wapps::score_list` entries: the value (i64 LE), a flags byte
+/// (bit 0: verified), the name length (u8) and the name
+fn decode_scores(mut data: &[u8]) -> Vec<Score> {
+    let mut scores = Vec::new();
+    while data.len() >= 10 {
+        let value = i64::from_le_bytes(data[..8].try_into().unwrap());
+        let verified = data[8] & 1 != 0;
+        let Some(name) = data.get(10..10 + data[9] as usize) else {
+            break;
+        };
+        scores.push(Score {
+            value,
+            name: String::from_utf8_lossy(name).into_owned(),
+            verified,
+        });
+        data = &data[10 + name.len()..];
+    }
+    scores
+}
+
+/// [`read_bytes`] for UTF-8 strings
+fn read_string(read: impl FnMut(*mut u8, i32) -> i32) -> Option<String> {
+    String::from_utf8(read_bytes(read)?).ok()
+}
+
+/// Call a host function that fills a buffer and returns the full length, or a
+/// negative value on error, growing the buffer until the value fits
+fn read_bytes(mut read: impl FnMut(*mut u8, i32) -> i32) -> Option<Vec<u8>> {
+    let mut buf = vec![0u8; 64];
+    loop {
+        let len = usize::try_from(read(buf.as_mut_ptr(), buf.len() as i32)).ok()?;
+        if len <= buf.len() {
+            buf.truncate(len);
+            return Some(buf);
+        }
+        buf.resize(len, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_string_grows_buffer() {
+        let value = "a".repeat(100);
+        let mut calls = 0;
+        let result = read_string(|buf, cap| {
+            calls += 1;
+            let len = value.len().min(cap as usize);
+            unsafe { std::ptr::copy_nonoverlapping(value.as_ptr(), buf, len) };
+            value.len() as i32
+        });
+        assert_eq!(result, Some(value.clone()));
+        assert_eq!(calls, 2);
+        assert_eq!(read_string(|_, _| -1), None);
+    }
+
+    #[test]
+    fn test_decode_scores() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&42i64.to_le_bytes());
+        data.extend_from_slice(&[1, 3]);
+        data.extend_from_slice(b"ann");
+        data.extend_from_slice(&(-7i64).to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        let scores = decode_scores(&data);
+        assert_eq!(scores.len(), 2);
+        assert_eq!((scores[0].value, scores[0].name.as_str()), (42, "ann"));
+        assert!(scores[0].verified && !scores[1].verified);
+        assert_eq!(scores[1].value, -7);
+    }
+}
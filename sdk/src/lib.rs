@@ -0,0 +1,662 @@
+//! WAPP Guest SDK
+//!
+//! Safe Rust API for writing WAPP guest applications. Implement [`App`] for
+//! your state, draw into a [`Framebuffer`] and present it, and let [`app!`]
+//! generate the exports the host calls:
+//!
+//! ```no_run
+//! use wapps_sdk::{App, Color, Framebuffer, PointerButton};
+//!
+//! struct Paint {
+//!     canvas: Framebuffer,
+//!     drawing: bool,
+//! }
+//!
+//! impl Default for Paint {
+//!     fn default() -> Self {
+//!         Self {
+//!             canvas: Framebuffer::new(320, 240),
+//!             drawing: false,
+//!         }
+//!     }
+//! }
+//!
+//! impl App for Paint {
+//!     fn update(&mut self, _dt: f64) {
+//!         self.canvas.present();
+//!     }
+//!
+//!     fn on_pointer_down(&mut self, x: i32, y: i32, button: PointerButton) {
+//!         self.drawing = button == PointerButton::Left;
+//!         self.on_pointer_move(x, y);
+//!     }
+//!
+//!     fn on_pointer_move(&mut self, x: i32, y: i32) {
+//!         if self.drawing {
+//!             self.canvas.set_pixel(x, y, Color::WHITE);
+//!         }
+//!     }
+//!
+//!     fn on_pointer_up(&mut self, _x: i32, _y: i32, _button: PointerButton) {
+//!         self.drawing = false;
+//!     }
+//! }
+//!
+//! wapps_sdk::app!(Paint);
+//! ```
+//!
+//! Build the crate as a `cdylib` for `wasm32-wasip1`, or let `cargo wapp
+//! build` (from the `cargo-wapp` crate) build, optimize and pack it into a
+//! `.wapp` in one go. On other targets the host imports are no-ops, so guest
+//! logic can be unit tested natively.
+
+mod alloc;
+mod framebuffer;
+pub mod host;
+
+pub use alloc::CountingAllocator;
+pub use framebuffer::{Color, Framebuffer};
+
+/// Pointer button reported by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerButton {
+    Left,
+    Middle,
+    Right,
+    Other,
+}
+
+impl PointerButton {
+    /// Convert the button number passed to the pointer exports
+    pub fn from_raw(button: i32) -> Self {
+        match button {
+            1 => PointerButton::Left,
+            2 => PointerButton::Middle,
+            3 => PointerButton::Right,
+            _ => PointerButton::Other,
+        }
+    }
+}
+
+/// Modifier keys held during a key press
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers(i32);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const SHIFT: Modifiers = Modifiers(1);
+    pub const CTRL: Modifiers = Modifiers(2);
+    pub const ALT: Modifiers = Modifiers(4);
+    /// The Windows, Command or Super key
+    pub const SUPER: Modifiers = Modifiers(8);
+
+    /// Convert the bitmask passed to `on_key_down`
+    pub fn from_raw(bits: i32) -> Self {
+        Modifiers(bits & 0xf)
+    }
+
+    /// Whether every modifier in `other` is held
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, other: Modifiers) -> Modifiers {
+        Modifiers(self.0 | other.0)
+    }
+}
+
+/// A WAPP guest application
+///
+/// Only `update` is required; the other callbacks ignore their events by
+/// default. Use [`host::event_time`] inside a callback to get the time its
+/// event happened.
+pub trait App {
+    /// Advance by `dt` seconds and present a frame
+    fn update(&mut self, dt: f64);
+
+    /// Called once, before any event or `update`, with the window size
+    ///
+    /// Hosts without a window, such as `wapps --headless`, pass 0 x 0 and
+    /// leave the frame size to the app.
+    fn init(&mut self, _width: u32, _height: u32) {}
+
+    /// Called last, when the host exits or closes the window
+    ///
+    /// Crashed apps are not called; save anything that must survive a crash
+    /// as it changes.
+    fn shutdown(&mut self) {}
+
+    /// The window was resized to `width` x `height`
+    fn on_resize(&mut self, _width: i32, _height: i32) {}
+
+    /// The window now has `scale` pixels per unit of its size
+    ///
+    /// Sizes passed to `on_resize` are in these units; present frames
+    /// `scale` times larger to stay sharp on HiDPI displays. Apps start at a
+    /// scale of 1 and are only told when it differs.
+    fn on_scale_changed(&mut self, _scale: f32) {}
+
+    /// The pointer moved to (`x`, `y`), in pixels of the presented frame
+    ///
+    /// Positions outside the frame, over letterbox bars or while dragging
+    /// off the window, lie beyond its edges rather than being clamped.
+    fn on_pointer_move(&mut self, _x: i32, _y: i32) {}
+
+    /// The pointer moved by (`xrel`, `yrel`) window pixels, right after
+    /// `on_pointer_move`
+    ///
+    /// With [`host::set_relative_mouse`] on, the position stays put and
+    /// only this motion is meaningful.
+    fn on_pointer_motion(&mut self, _xrel: i32, _yrel: i32) {}
+
+    /// A pointer button was pressed at (`x`, `y`)
+    fn on_pointer_down(&mut self, _x: i32, _y: i32, _button: PointerButton) {}
+
+    /// A pointer button was released at (`x`, `y`)
+    fn on_pointer_up(&mut self, _x: i32, _y: i32, _button: PointerButton) {}
+
+    /// Finger `id` touched the screen at (`x`, `y`) frame pixels, with a
+    /// `pressure` from 0 to 1
+    ///
+    /// `id` stays the same until the finger lifts, telling apart fingers
+    /// touching at once. Hosts also report touches as left button pointer
+    /// events.
+    fn on_touch_down(&mut self, _id: i64, _x: f32, _y: f32, _pressure: f32) {}
+
+    /// Finger `id` moved to (`x`, `y`)
+    fn on_touch_move(&mut self, _id: i64, _x: f32, _y: f32, _pressure: f32) {}
+
+    /// Finger `id` lifted from the screen at (`x`, `y`)
+    fn on_touch_up(&mut self, _id: i64, _x: f32, _y: f32, _pressure: f32) {}
+
+    /// The wheel or trackpad scrolled by (`dx`, `dy`) notches
+    ///
+    /// Positive `dy` scrolls up (away from the user) and positive `dx` right.
+    /// Trackpads report fractions of a notch.
+    fn on_scroll(&mut self, _dx: f32, _dy: f32) {}
+
+    /// A key was pressed, identified by its USB HID scancode, while
+    /// `modifiers` were held
+    ///
+    /// Holding a key down presses it again periodically with `repeat` set.
+    /// Use [`host::key_held`] to poll keys instead.
+    fn on_key_down(&mut self, _scancode: i32, _modifiers: Modifiers, _repeat: bool) {}
+
+    /// A key was released, identified by its USB HID scancode
+    fn on_key_up(&mut self, _scancode: i32) {}
+
+    /// Text was typed, or committed by an input method
+    ///
+    /// Use this rather than the key callbacks for text entry: it follows the
+    /// keyboard layout and produces any Unicode text.
+    fn on_text_input(&mut self, _text: &str) {}
+
+    /// An input method is composing `text`, not yet committed, with the
+    /// cursor `cursor` characters in; an empty `text` ends the composition
+    fn on_text_editing(&mut self, _text: &str, _cursor: usize) {}
+
+    /// The frame numbered `frame_index` was shown on screen at `present_time_micros`
+    ///
+    /// Times are microseconds since the host started, the same origin as
+    /// [`host::event_time`].
+    fn on_present(&mut self, _frame_index: u64, _present_time_micros: u64) {}
+
+    /// No input was received for the host's `--attract-after` period
+    ///
+    /// Kiosk apps can start an attract mode here and leave it on the next input.
+    fn on_idle(&mut self) {}
+
+    /// The window gained keyboard focus
+    fn on_focus(&mut self) {}
+
+    /// The window lost keyboard focus
+    ///
+    /// Hosts started with `--pause-on-blur` stop calling `update` until the
+    /// window is focused again.
+    fn on_blur(&mut self) {}
+
+    /// The window was minimized or hidden, or shown again when `visible`
+    fn on_visibility(&mut self, _visible: bool) {}
+
+    /// The user asked to close the window; return `false` to keep it open
+    ///
+    /// Save state here before allowing it. Hosts also ask when quitting, and
+    /// close crashed or unresponsive apps without asking.
+    fn on_close_request(&mut self) -> bool {
+        true
+    }
+
+    /// The events that follow come from netplay player `index`: 0 for the
+    /// host that was started with `--netplay host`, 1 for the one that joined
+    fn on_player(&mut self, _index: u32) {}
+
+    /// The host keeps dropping frames: some at `level` 1, many at level 2
+    ///
+    /// Adaptive apps can reduce their simulation size or resolution here. Level
+    /// 0 follows once the host keeps up again.
+    fn on_performance_warning(&mut self, _level: u32) {}
+
+    /// Updates took longer than a frame, so the host now calls `update` every
+    /// 2^`level` frames with their combined `dt`
+    ///
+    /// Apps can lighten their updates here to get back to every frame (level
+    /// 0), which the host returns to once updates fit again.
+    fn on_perf_hint(&mut self, _level: u32) {}
+
+    /// Memory grew to `current_pages` of the `limit_pages` the host allows
+    /// (pages are 64 KiB)
+    ///
+    /// Growing past the limit fails, so free caches here while allocations
+    /// still succeed.
+    fn on_memory_pressure(&mut self, _current_pages: u32, _limit_pages: u32) {}
+
+    /// A message arrived on `socket`, opened with [`host::ws_connect`]
+    ///
+    /// Text messages come as their UTF-8 bytes. The host needs `wapps_alloc`,
+    /// which [`app!`] exports, to pass them.
+    fn on_ws_message(&mut self, _socket: host::WebSocket, _message: &[u8]) {}
+
+    /// The dialog requested with [`host::request_open_file`] closed, with the
+    /// contents of the file the user chose, or `None` if they cancelled
+    fn on_file_opened(&mut self, _data: Option<&[u8]>) {}
+
+    /// The dialog requested with [`host::request_save_file`] closed
+    fn on_file_saved(&mut self, _result: host::FileSaved) {}
+
+    /// The user dropped the file `name` (without its directory) on the window
+    fn on_file_dropped(&mut self, _name: &str, _data: &[u8]) {}
+
+    /// Another running app sent `message` under a `topic` subscribed to with
+    /// [`host::bus_subscribe`]
+    fn on_bus_message(&mut self, _topic: &str, _message: &[u8]) {}
+
+    /// The timer `id` scheduled with [`host::set_timer`] fired
+    fn on_timer(&mut self, _id: i32) {}
+
+    /// The host replaced the running module with a new build (`wapps --watch`)
+    ///
+    /// With `--watch-keep-memory` this app keeps the state of the previous
+    /// build, which only works while the layout of that state is unchanged;
+    /// refresh anything derived from code here, such as cached tables.
+    fn on_reload(&mut self) {}
+
+    /// The framebuffer this app presents every frame, if it always uses the same one
+    ///
+    /// The host asks once, at startup, and then reads frames presented from it
+    /// in place instead of copying them. Resizing the framebuffer moves its
+    /// pixels, after which frames are copied again.
+    fn shared_framebuffer(&mut self) -> Option<&Framebuffer> {
+        None
+    }
+
+    /// Textual description of the current screen for assistive technology
+    fn describe(&self) -> String {
+        String::new()
+    }
+}
+
+/// Generate the guest exports for an [`App`]
+///
+/// `app!(MyApp)` creates the app with `Default::default()` before the first
+/// callback; `app!(MyApp, MyApp::new(42))` uses the given expression instead.
+/// Use it once per crate.
+#[macro_export]
+macro_rules! app {
+    ($app:ty) => {
+        $crate::app!($app, <$app as ::core::default::Default>::default());
+    };
+    ($app:ty, $init:expr) => {
+        const _: () = {
+            ::std::thread_local! {
+                static APP: ::core::cell::RefCell<::core::option::Option<$app>> =
+                    const { ::core::cell::RefCell::new(::core::option::Option::None) };
+            }
+
+            fn with_app<R>(f: impl ::core::ops::FnOnce(&mut $app) -> R) -> R {
+                APP.with(|app| {
+                    let mut app = app.borrow_mut();
+                    f(app.get_or_insert_with(|| $init))
+                })
+            }
+
+            #[no_mangle]
+            pub extern "C" fn update(dt: f64) {
+                with_app(|app| $crate::App::update(app, dt))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn init(width: i32, height: i32) {
+                let (width, height) = (width.max(0) as u32, height.max(0) as u32);
+                with_app(|app| $crate::App::init(app, width, height))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn shutdown() {
+                with_app(|app| $crate::App::shutdown(app))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_resize(width: i32, height: i32) {
+                with_app(|app| $crate::App::on_resize(app, width, height))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_scale_changed(scale: f32) {
+                with_app(|app| $crate::App::on_scale_changed(app, scale))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_pointer_move(x: i32, y: i32, xrel: i32, yrel: i32) {
+                with_app(|app| {
+                    $crate::App::on_pointer_move(app, x, y);
+                    $crate::App::on_pointer_motion(app, xrel, yrel);
+                })
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_pointer_down(x: i32, y: i32, button: i32) {
+                let button = $crate::PointerButton::from_raw(button);
+                with_app(|app| $crate::App::on_pointer_down(app, x, y, button))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_pointer_up(x: i32, y: i32, button: i32) {
+                let button = $crate::PointerButton::from_raw(button);
+                with_app(|app| $crate::App::on_pointer_up(app, x, y, button))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_touch_down(id: i64, x: f32, y: f32, pressure: f32) {
+                with_app(|app| $crate::App::on_touch_down(app, id, x, y, pressure))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_touch_move(id: i64, x: f32, y: f32, pressure: f32) {
+                with_app(|app| $crate::App::on_touch_move(app, id, x, y, pressure))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_touch_up(id: i64, x: f32, y: f32, pressure: f32) {
+                with_app(|app| $crate::App::on_touch_up(app, id, x, y, pressure))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_scroll_precise(dx: f32, dy: f32) {
+                with_app(|app| $crate::App::on_scroll(app, dx, dy))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_key_down(scancode: i32, modifiers: i32, repeat: i32) {
+                let modifiers = $crate::Modifiers::from_raw(modifiers);
+                with_app(|app| $crate::App::on_key_down(app, scancode, modifiers, repeat != 0))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_key_up(scancode: i32) {
+                with_app(|app| $crate::App::on_key_up(app, scancode))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_text_input(ptr: *const u8, len: i32) {
+                // SAFETY: the host passes text it wrote into a `wapps_alloc` allocation
+                let text = unsafe { $crate::__private::str_from_raw(ptr, len) };
+                with_app(|app| $crate::App::on_text_input(app, text))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_text_editing(ptr: *const u8, len: i32, cursor: i32) {
+                // SAFETY: the host passes text it wrote into a `wapps_alloc` allocation
+                let text = unsafe { $crate::__private::str_from_raw(ptr, len) };
+                let cursor = cursor.max(0) as usize;
+                with_app(|app| $crate::App::on_text_editing(app, text, cursor))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn wapps_alloc(len: i32) -> *mut u8 {
+                $crate::__private::alloc(len)
+            }
+
+            #[no_mangle]
+            pub extern "C" fn wapps_free(ptr: *mut u8, len: i32) {
+                // SAFETY: the host only frees what `wapps_alloc` returned, once
+                unsafe { $crate::__private::free(ptr, len) }
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_present(frame_index: i64, present_time_micros: i64) {
+                with_app(|app| {
+                    $crate::App::on_present(app, frame_index as u64, present_time_micros as u64)
+                })
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_idle() {
+                with_app(|app| $crate::App::on_idle(app))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_focus() {
+                with_app(|app| $crate::App::on_focus(app))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_blur() {
+                with_app(|app| $crate::App::on_blur(app))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_visibility(visible: i32) {
+                with_app(|app| $crate::App::on_visibility(app, visible != 0))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_close_request() -> i32 {
+                with_app(|app| !$crate::App::on_close_request(app) as i32)
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_player(index: i32) {
+                with_app(|app| $crate::App::on_player(app, index as u32))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_performance_warning(level: i32) {
+                let level = level.max(0) as u32;
+                with_app(|app| $crate::App::on_performance_warning(app, level))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_perf_hint(level: i32) {
+                let level = level.max(0) as u32;
+                with_app(|app| $crate::App::on_perf_hint(app, level))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_memory_pressure(current_pages: i32, limit_pages: i32) {
+                let (current, limit) = (current_pages.max(0) as u32, limit_pages.max(0) as u32);
+                with_app(|app| $crate::App::on_memory_pressure(app, current, limit))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_ws_message(handle: i32, ptr: *const u8, len: i32) {
+                // SAFETY: the host passes a message it wrote into a `wapps_alloc` allocation
+                let message = unsafe { $crate::__private::bytes_from_raw(ptr, len) };
+                let socket = $crate::__private::web_socket(handle);
+                with_app(|app| $crate::App::on_ws_message(app, socket, message))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_file_opened(ptr: *const u8, len: i32) {
+                // SAFETY: the host passes a file it wrote into a `wapps_alloc` allocation
+                let data =
+                    (len >= 0).then(|| unsafe { $crate::__private::bytes_from_raw(ptr, len) });
+                with_app(|app| $crate::App::on_file_opened(app, data))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_file_saved(status: i32) {
+                let result = $crate::__private::file_saved(status);
+                with_app(|app| $crate::App::on_file_saved(app, result))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_file_dropped(
+                name_ptr: *const u8,
+                name_len: i32,
+                data_ptr: *const u8,
+                data_len: i32,
+            ) {
+                // SAFETY: the host passes a name and file it wrote into `wapps_alloc` allocations
+                let name = unsafe { $crate::__private::str_from_raw(name_ptr, name_len) };
+                let data = unsafe { $crate::__private::bytes_from_raw(data_ptr, data_len) };
+                with_app(|app| $crate::App::on_file_dropped(app, name, data))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_bus_message(
+                topic_ptr: *const u8,
+                topic_len: i32,
+                data_ptr: *const u8,
+                data_len: i32,
+            ) {
+                // SAFETY: the host passes a topic and message it wrote into `wapps_alloc` allocations
+                let topic = unsafe { $crate::__private::str_from_raw(topic_ptr, topic_len) };
+                let data = unsafe { $crate::__private::bytes_from_raw(data_ptr, data_len) };
+                with_app(|app| $crate::App::on_bus_message(app, topic, data))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_timer(id: i32) {
+                with_app(|app| $crate::App::on_timer(app, id))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_reload() {
+                with_app(|app| $crate::App::on_reload(app))
+            }
+
+            #[no_mangle]
+            pub extern "C" fn get_framebuffer() -> *const u8 {
+                with_app(|app| {
+                    $crate::App::shared_framebuffer(app)
+                        .map_or(::core::ptr::null(), |fb| fb.pixels().as_ptr())
+                })
+            }
+
+            #[no_mangle]
+            pub extern "C" fn on_describe(buf: *mut u8, cap: i32) -> i32 {
+                let description = with_app(|app| $crate::App::describe(app));
+                // SAFETY: the host passes a buffer of `cap` writable bytes
+                unsafe { $crate::__private::write_description(&description, buf, cap) }
+            }
+        };
+    };
+}
+
+/// Support code for [`app!`], not part of the public API
+#[doc(hidden)]
+pub mod __private {
+    /// Copy as much of `description` as fits into the host buffer, returning its full length
+    ///
+    /// # Safety
+    ///
+    /// `buf` must be valid for writes of `cap` bytes.
+    pub unsafe fn write_description(description: &str, buf: *mut u8, cap: i32) -> i32 {
+        let len = description.len().min(cap.max(0) as usize);
+        std::ptr::copy_nonoverlapping(description.as_ptr(), buf, len);
+        description.len() as i32
+    }
+
+    fn layout(len: i32) -> std::alloc::Layout {
+        // Zero-sized allocations are not allowed, so always reserve a byte
+        std::alloc::Layout::array::<u8>(len.max(1) as usize).expect("allocation too large")
+    }
+
+    /// Allocate `len` bytes for the host to write into, or return null when
+    /// out of memory so the host drops the data instead of the app aborting
+    pub fn alloc(len: i32) -> *mut u8 {
+        // SAFETY: the layout is never zero-sized
+        unsafe { std::alloc::alloc(layout(len)) }
+    }
+
+    /// Release memory returned by [`alloc`]
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `alloc(len)` and not have been freed.
+    pub unsafe fn free(ptr: *mut u8, len: i32) {
+        std::alloc::dealloc(ptr, layout(len));
+    }
+
+    /// Borrow `len` bytes of UTF-8 written by the host, replacing invalid text with ""
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` bytes for the returned lifetime.
+    pub unsafe fn str_from_raw<'a>(ptr: *const u8, len: i32) -> &'a str {
+        std::str::from_utf8(bytes_from_raw(ptr, len)).unwrap_or_default()
+    }
+
+    /// Borrow `len` bytes written by the host
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads of `len` bytes for the returned lifetime.
+    pub unsafe fn bytes_from_raw<'a>(ptr: *const u8, len: i32) -> &'a [u8] {
+        std::slice::from_raw_parts(ptr, len.max(0) as usize)
+    }
+
+    /// How a save dialog ended, from its `on_file_saved` status
+    pub fn file_saved(status: i32) -> crate::host::FileSaved {
+        match status {
+            0 => crate::host::FileSaved::Saved,
+            -1 => crate::host::FileSaved::Cancelled,
+            _ => crate::host::FileSaved::Failed,
+        }
+    }
+
+    /// The WebSocket a host handle refers to
+    pub fn web_socket(handle: i32) -> crate::host::WebSocket {
+        crate::host::WebSocket(handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_description_truncates() {
+        let mut buf = [0u8; 4];
+        let len =
+            unsafe { __private::write_description("paused", buf.as_mut_ptr(), buf.len() as i32) };
+        assert_eq!(len, 6);
+        assert_eq!(&buf, b"paus");
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let held = Modifiers::from_raw(1 | 2 | 16);
+        assert_eq!(held, Modifiers::SHIFT | Modifiers::CTRL);
+        assert!(held.contains(Modifiers::CTRL));
+        assert!(!held.contains(Modifiers::CTRL | Modifiers::ALT));
+        assert!(Modifiers::NONE.contains(Modifiers::NONE));
+    }
+
+    #[test]
+    fn test_host_allocations_round_trip() {
+        let text = "héllo";
+        let ptr = __private::alloc(text.len() as i32);
+        unsafe {
+            std::ptr::copy_nonoverlapping(text.as_ptr(), ptr, text.len());
+            assert_eq!(__private::str_from_raw(ptr, text.len() as i32), text);
+            __private::free(ptr, text.len() as i32);
+        }
+        let empty = __private::alloc(0);
+        unsafe { __private::free(empty, 0) };
+    }
+}
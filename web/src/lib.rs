@@ -0,0 +1,249 @@
+//! WAPP Browser Host
+//!
+//! The Rust side of the browser host in `host/web`: `runtime.js` hands the
+//! bytes of a package to [`unpack`], then instantiates the module it returns
+//! with `WebAssembly.instantiate`, provides the `wapps` imports and blits the
+//! frames to a `<canvas>`. Unpacking follows the native host's loader:
+//! version 1 packages hold the raw module after their JSON header, version 2
+//! packages a sequence of sections, each inflated to no more than the size its
+//! header records. zstd sections need the native host; signatures are not
+//! checked here.
+//!
+//! Build into the web host with
+//! `wasm-pack build web --target web --out-dir ../host/web/pkg`.
+
+use wasm_bindgen::prelude::*;
+
+/// Magic bytes for WAPP format
+const WAPP_MAGIC: &[u8; 4] = b"WAPP";
+
+/// Original format version: JSON header followed by the raw module
+const WAPP_VERSION: u32 = 1;
+
+/// Format version with compressed sections
+const WAPP_SECTIONED_VERSION: u32 = 2;
+
+/// Size of a section header: kind, codec, name length, stored and raw lengths
+const SECTION_HEADER_SIZE: usize = 1 + 1 + 2 + 4 + 4;
+
+/// Section kinds read by the browser; icons, signatures and precompiled
+/// modules are skipped
+const SECTION_MODULE: u8 = 0;
+const SECTION_ASSET: u8 = 2;
+
+/// Section codecs, as in the native host's `codec` module
+const CODEC_NONE: u8 = 0;
+const CODEC_ZSTD: u8 = 1;
+const CODEC_DEFLATE: u8 = 2;
+
+/// A package's assets by name
+type Assets = Vec<(String, Vec<u8>)>;
+
+/// An unpacked package
+#[wasm_bindgen]
+pub struct Package {
+    version: u32,
+    metadata: String,
+    module: Vec<u8>,
+    assets: Assets,
+}
+
+#[wasm_bindgen]
+impl Package {
+    /// Format version of the package file
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The JSON header, for `JSON.parse`
+    #[wasm_bindgen(getter)]
+    pub fn metadata(&self) -> String {
+        self.metadata.clone()
+    }
+
+    /// The WebAssembly module
+    #[wasm_bindgen(getter)]
+    pub fn module(&self) -> Vec<u8> {
+        self.module.clone()
+    }
+
+    /// Names of the bundled assets
+    #[wasm_bindgen(js_name = assetNames)]
+    pub fn asset_names(&self) -> Vec<String> {
+        self.assets.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Contents of the asset `name`, readable by the guest via `wapps::asset_read`
+    pub fn asset(&self, name: &str) -> Option<Vec<u8>> {
+        self.assets
+            .iter()
+            .find(|(asset, _)| asset == name)
+            .map(|(_, data)| data.clone())
+    }
+}
+
+/// Unpack the package file `bytes`, throwing an `Error` if it is invalid
+#[wasm_bindgen]
+pub fn unpack(bytes: &[u8]) -> Result<Package, JsError> {
+    parse_package(bytes).map_err(|e| JsError::new(&e))
+}
+
+fn parse_package(data: &[u8]) -> Result<Package, String> {
+    if data.len() < 12 || &data[..4] != WAPP_MAGIC {
+        return Err("Invalid magic header: Not a WAPP file".to_string());
+    }
+    let version = read_u32(data, 4);
+    if version != WAPP_VERSION && version != WAPP_SECTIONED_VERSION {
+        return Err(format!("Unsupported WAPP version: {}", version));
+    }
+    let header_end = 12 + read_u32(data, 8) as usize;
+    let metadata = data
+        .get(12..header_end)
+        .ok_or("Invalid WAPP file: incomplete header")?;
+    let metadata = std::str::from_utf8(metadata)
+        .map_err(|_| "Invalid WAPP file: metadata is not UTF-8")?
+        .to_string();
+
+    let payload = &data[header_end..];
+    let (module, assets) = if version == WAPP_VERSION {
+        (payload.to_vec(), Vec::new())
+    } else {
+        parse_sections(payload)?
+    };
+    if module.len() >= 4 && &module[..4] != b"\0asm" {
+        return Err("Invalid WASM module: incorrect magic number".to_string());
+    }
+    Ok(Package {
+        version,
+        metadata,
+        module,
+        assets,
+    })
+}
+
+/// The module and the assets by name of a version 2 package's sections
+fn parse_sections(mut data: &[u8]) -> Result<(Vec<u8>, Assets), String> {
+    let mut module = None;
+    let mut assets = Vec::new();
+    while !data.is_empty() {
+        if data.len() < SECTION_HEADER_SIZE {
+            return Err("Invalid WAPP file: truncated section header".to_string());
+        }
+        let (kind, codec) = (data[0], data[1]);
+        let name_len = u16::from_le_bytes([data[2], data[3]]) as usize;
+        let stored_len = read_u32(data, 4) as usize;
+        let raw_len = read_u32(data, 8) as usize;
+        let body_end = SECTION_HEADER_SIZE + name_len + stored_len;
+        if data.len() < body_end {
+            return Err("Invalid WAPP file: section extends past the end of the file".to_string());
+        }
+        let name = std::str::from_utf8(&data[SECTION_HEADER_SIZE..SECTION_HEADER_SIZE + name_len])
+            .map_err(|_| "Invalid WAPP file: section name is not UTF-8")?
+            .to_string();
+        let stored = &data[SECTION_HEADER_SIZE + name_len..body_end];
+        data = &data[body_end..];
+
+        match kind {
+            SECTION_MODULE if module.is_some() => {
+                return Err("Invalid WAPP file: more than one module section".to_string());
+            }
+            SECTION_MODULE => module = Some(decode_section(codec, stored, raw_len)?),
+            SECTION_ASSET => {
+                let contents = decode_section(codec, stored, raw_len)
+                    .map_err(|e| format!("Invalid asset {:?}: {}", name, e))?;
+                assets.push((name, contents));
+            }
+            _ => {}
+        }
+    }
+    let module = module.ok_or("Invalid WAPP file: no module section")?;
+    Ok((module, assets))
+}
+
+/// Decompress a section stored with `codec`, which expands to `raw_len` bytes
+fn decode_section(codec: u8, stored: &[u8], raw_len: usize) -> Result<Vec<u8>, String> {
+    let raw = match codec {
+        CODEC_NONE => stored.to_vec(),
+        CODEC_DEFLATE => miniz_oxide::inflate::decompress_to_vec_with_limit(stored, raw_len)
+            .map_err(|e| format!("Failed to decompress deflate section: {}", e))?,
+        CODEC_ZSTD => {
+            return Err(
+                "zstd sections need the native host; repack with --codec deflate".to_string(),
+            )
+        }
+        _ => return Err(format!("Unsupported compression codec id {}", codec)),
+    };
+    if raw.len() != raw_len {
+        return Err(format!(
+            "Decompressed section is {} bytes, expected {}",
+            raw.len(),
+            raw_len
+        ));
+    }
+    Ok(raw)
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().expect("4-byte slice"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn package(version: u32, payload: &[u8]) -> Vec<u8> {
+        let metadata = br#"{"name":"Demo"}"#;
+        let mut data = WAPP_MAGIC.to_vec();
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        data.extend_from_slice(metadata);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn section(kind: u8, codec: u8, name: &str, stored: &[u8], raw_len: usize) -> Vec<u8> {
+        let mut data = vec![kind, codec];
+        data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(raw_len as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(stored);
+        data
+    }
+
+    #[test]
+    fn test_unpacks_both_versions() {
+        let v1 = parse_package(&package(WAPP_VERSION, MODULE)).unwrap();
+        assert_eq!(v1.metadata, r#"{"name":"Demo"}"#);
+        assert_eq!(v1.module, MODULE);
+
+        let hello = miniz_oxide::deflate::compress_to_vec(b"hello hello hello", 6);
+        let mut sections = section(SECTION_MODULE, CODEC_NONE, "", MODULE, MODULE.len());
+        sections.extend(section(1, CODEC_NONE, "", b"png", 3));
+        sections.extend(section(SECTION_ASSET, CODEC_DEFLATE, "a.txt", &hello, 17));
+        let v2 = parse_package(&package(WAPP_SECTIONED_VERSION, &sections)).unwrap();
+        assert_eq!(v2.version(), 2);
+        assert_eq!(v2.module, MODULE);
+        assert_eq!(v2.asset_names(), ["a.txt"]);
+        assert_eq!(v2.asset("a.txt").unwrap(), b"hello hello hello");
+
+        assert!(parse_package(b"WAPX\x01\0\0\0\0\0\0\0").is_err());
+        assert!(parse_package(&package(3, MODULE)).is_err());
+        let no_module = section(SECTION_ASSET, CODEC_NONE, "a", b"a", 1);
+        assert!(parse_package(&package(WAPP_SECTIONED_VERSION, &no_module)).is_err());
+    }
+
+    #[test]
+    fn test_stops_inflating_past_the_recorded_size() {
+        let zeros = miniz_oxide::deflate::compress_to_vec(&[0; 1 << 20], 6);
+        let mut sections = section(SECTION_MODULE, CODEC_NONE, "", MODULE, MODULE.len());
+        sections.extend(section(SECTION_ASSET, CODEC_DEFLATE, "bomb", &zeros, 1000));
+        assert!(parse_package(&package(WAPP_SECTIONED_VERSION, &sections)).is_err());
+
+        let zstd = section(SECTION_MODULE, CODEC_ZSTD, "", MODULE, MODULE.len());
+        assert!(parse_package(&package(WAPP_SECTIONED_VERSION, &zstd)).is_err());
+    }
+}